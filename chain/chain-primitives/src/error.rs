@@ -7,7 +7,7 @@ use near_primitives::time::Utc;
 use tracing::error;
 
 use near_primitives::block::BlockValidityError;
-use near_primitives::challenge::{ChunkProofs, ChunkState};
+use near_primitives::challenge::{ChunkProofs, ChunkStateV2};
 use near_primitives::errors::{EpochError, StorageError};
 use near_primitives::serialize::to_base;
 use near_primitives::shard_layout::ShardLayoutError;
@@ -140,7 +140,10 @@ pub enum ErrorKind {
     InvalidChunkProofs(Box<ChunkProofs>),
     /// Invalid chunk state.
     #[error("Invalid Chunk State")]
-    InvalidChunkState(Box<ChunkState>),
+    InvalidChunkState(Box<ChunkStateV2>),
+    /// Chunk state witness doesn't match the chunk or the blocks it was recorded for.
+    #[error("Invalid Chunk State Witness")]
+    InvalidChunkStateWitness,
     /// Invalid chunk mask
     #[error("Invalid Chunk Mask")]
     InvalidChunkMask,
@@ -286,6 +289,7 @@ impl Error {
             | ErrorKind::InvalidChunk
             | ErrorKind::InvalidChunkProofs(_)
             | ErrorKind::InvalidChunkState(_)
+            | ErrorKind::InvalidChunkStateWitness
             | ErrorKind::InvalidChunkMask
             | ErrorKind::InvalidStateRoot
             | ErrorKind::InvalidTxRoot
@@ -364,6 +368,12 @@ impl From<ShardLayoutError> for Error {
             ShardLayoutError::InvalidShardIdError { shard_id } => {
                 ErrorKind::InvalidShardId(shard_id)
             }
+            ShardLayoutError::InvalidSplitError { shard_id, boundary_account } => ErrorKind::Other(
+                format!("Shard {} can't be split at {}", shard_id, boundary_account),
+            ),
+            ShardLayoutError::InvalidMergeError { shard_id } => {
+                ErrorKind::Other(format!("Shard {} can't be merged with the next shard", shard_id))
+            }
         }
         .into()
     }
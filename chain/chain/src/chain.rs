@@ -14,18 +14,19 @@ use tracing::{debug, error, info, warn};
 use near_chain_primitives::error::{BlockKnownError, Error, ErrorKind, LogTransientStorageError};
 use near_primitives::block::{genesis_chunks, Tip};
 use near_primitives::challenge::{
-    BlockDoubleSign, Challenge, ChallengeBody, ChallengesResult, ChunkProofs, ChunkState,
+    BlockDoubleSign, Challenge, ChallengeBody, ChallengesResult, ChunkProofs, ChunkStateV2,
     MaybeEncodedShardChunk, SlashedValidator,
 };
 use near_primitives::checked_feature;
+use near_primitives::epoch_manager::ShardLoad;
 use near_primitives::hash::{hash, CryptoHash};
 use near_primitives::merkle::{
     combine_hash, merklize, verify_path, Direction, MerklePath, MerklePathItem,
 };
 use near_primitives::receipt::Receipt;
 use near_primitives::sharding::{
-    ChunkHash, ChunkHashHeight, ReceiptList, ReceiptProof, ShardChunk, ShardChunkHeader, ShardInfo,
-    ShardProof, StateSyncInfo,
+    ChunkHash, ChunkHashHeight, ChunkStateWitness, ChunkStateWitnessInner, ReceiptList,
+    ReceiptProof, ShardChunk, ShardChunkHeader, ShardChunkV2, ShardInfo, ShardProof, StateSyncInfo,
 };
 use near_primitives::syncing::{
     get_num_state_parts, ReceiptProofResponse, RootProof, ShardStateSyncResponseHeader,
@@ -40,11 +41,13 @@ use near_primitives::types::{
 use near_primitives::unwrap_or_return;
 use near_primitives::utils::MaybeValidated;
 use near_primitives::views::{
-    ExecutionOutcomeWithIdView, ExecutionStatusView, FinalExecutionOutcomeView,
-    FinalExecutionOutcomeWithReceiptView, FinalExecutionStatus, LightClientBlockView,
-    SignedTransactionView,
+    BlockProductionView, ChunkProductionView, ExecutionOutcomeWithIdView, ExecutionStatusView,
+    FinalExecutionOutcomeView, FinalExecutionOutcomeWithReceiptView, FinalExecutionStatus,
+    LightClientBlockView, ProductionStatusView, SignedTransactionView, ValidatorPerformanceView,
+};
+use near_store::{
+    ColState, ColStateHeaders, ColStateParts, PartialStorage, ShardTries, StoreUpdate,
 };
-use near_store::{ColState, ColStateHeaders, ColStateParts, ShardTries, StoreUpdate};
 
 use near_primitives::state_record::StateRecord;
 
@@ -55,10 +58,11 @@ use crate::store::{ChainStore, ChainStoreAccess, ChainStoreUpdate, GCMode, Saved
 use crate::types::{
     AcceptedBlock, ApplySplitStateResult, ApplySplitStateResultOrStateChanges,
     ApplyTransactionResult, Block, BlockEconomicsConfig, BlockHeader, BlockHeaderInfo, BlockStatus,
-    ChainGenesis, Provenance, RuntimeAdapter,
+    ChainGenesis, ChunkStateWitnessResult, Provenance, RuntimeAdapter, ValidatorInfoIdentifier,
 };
 use crate::validate::{
     validate_challenge, validate_chunk_proofs, validate_chunk_with_chunk_extra,
+    validate_chunk_with_chunk_extra_and_receipts_root, validate_incoming_receipts,
     validate_transactions_order,
 };
 use crate::{byzantine_assert, create_light_client_block_view, Doomslug};
@@ -1019,6 +1023,136 @@ impl Chain {
         unwrap_or_return!(chain_update.commit());
     }
 
+    /// Creates the state witness of the chunk of given shard included in the block, by applying
+    /// the chunk again and recording the trie nodes it reads. The chunk must be new in the block
+    /// and the state of the shard must be tracked.
+    pub fn create_chunk_state_witness(
+        &mut self,
+        block: &Block,
+        shard_id: ShardId,
+    ) -> Result<ChunkStateWitnessInner, Error> {
+        let chunk_header = block
+            .chunks()
+            .get(shard_id as usize)
+            .ok_or_else(|| Error::from(ErrorKind::InvalidShardId(shard_id)))?
+            .clone();
+        if chunk_header.height_included() != block.header().height() {
+            return Err(ErrorKind::Other(format!(
+                "create_chunk_state_witness failed: no new chunk for shard {}",
+                shard_id
+            ))
+            .into());
+        }
+        let prev_block_headers = get_chunk_state_witness_headers(
+            &mut self.store,
+            &*self.runtime_adapter,
+            block.header(),
+            shard_id,
+        )?;
+        let prev_chunk_height_included = prev_block_headers[prev_block_headers.len() - 1].height();
+        let incoming_receipts = ChainStoreUpdate::new(&mut self.store)
+            .get_incoming_receipts_for_shard(shard_id, *block.hash(), prev_chunk_height_included)?;
+        let root_proofs = get_receipts_root_proofs(&mut self.store, shard_id, &incoming_receipts)?;
+        let receipts = collect_receipts_from_response(&incoming_receipts);
+        let chunk = self.store.get_chunk_clone_from_header(&chunk_header)?;
+        let is_first_block_with_chunk_of_version = check_if_block_is_first_with_chunk_of_version(
+            &mut self.store,
+            self.runtime_adapter.as_ref(),
+            block.header().prev_hash(),
+            shard_id,
+        )?;
+        let apply_result = self.runtime_adapter.apply_transactions_with_optional_storage_proof(
+            shard_id,
+            &chunk_header.prev_state_root(),
+            chunk_header.height_included(),
+            block.header().raw_timestamp(),
+            &chunk_header.prev_block_hash(),
+            block.hash(),
+            &receipts,
+            chunk.transactions(),
+            chunk_header.validator_proposals(),
+            prev_block_headers[0].gas_price(),
+            chunk_header.gas_limit(),
+            block.header().challenges_result(),
+            *block.header().random_value(),
+            true,
+            true,
+            is_first_block_with_chunk_of_version,
+            None,
+        )?;
+        let partial_state = match apply_result.proof {
+            Some(proof) => proof.nodes,
+            None => {
+                return Err(ErrorKind::Other(
+                    "create_chunk_state_witness failed: no storage proof was recorded".into(),
+                )
+                .into())
+            }
+        };
+        Ok(ChunkStateWitnessInner {
+            block_hash: *block.hash(),
+            shard_id,
+            chunk_hash: chunk_header.chunk_hash(),
+            transactions: chunk.transactions().to_vec(),
+            receipts: chunk.receipts().to_vec(),
+            partial_state,
+            incoming_receipts,
+            root_proofs,
+        })
+    }
+
+    /// Checks that the state witness was signed by the producer of its chunk and matches the
+    /// chunk, and keeps it until the block with the next chunk of the shard is processed, see
+    /// `ChainUpdate::validate_chunk_with_state_witness`.
+    pub fn process_chunk_state_witness(&mut self, witness: ChunkStateWitness) -> Result<(), Error> {
+        let block = self.get_block(&witness.inner.block_hash)?.clone();
+        let shard_id = witness.inner.shard_id;
+        let chunk_header = match block.chunks().get(shard_id as usize) {
+            Some(chunk_header)
+                if chunk_header.chunk_hash() == witness.inner.chunk_hash
+                    && chunk_header.height_included() == block.header().height() =>
+            {
+                chunk_header.clone()
+            }
+            _ => return Err(ErrorKind::InvalidChunkStateWitness.into()),
+        };
+        let epoch_id = block.header().epoch_id();
+        let chunk_producer = self.runtime_adapter.get_chunk_producer(
+            epoch_id,
+            chunk_header.height_created(),
+            shard_id,
+        )?;
+        if !self.runtime_adapter.verify_validator_signature(
+            epoch_id,
+            block.hash(),
+            &chunk_producer,
+            &ChunkStateWitness::get_data_for_sig(&witness.inner.hash()),
+            &witness.signature,
+        )? {
+            return Err(ErrorKind::InvalidChunkStateWitness.into());
+        }
+        let chunk = ShardChunk::V2(ShardChunkV2 {
+            chunk_hash: chunk_header.chunk_hash(),
+            header: chunk_header,
+            transactions: witness.inner.transactions.clone(),
+            receipts: witness.inner.receipts.clone(),
+        });
+        if !validate_chunk_proofs(&chunk, &*self.runtime_adapter)? {
+            return Err(ErrorKind::InvalidChunkStateWitness.into());
+        }
+        self.store.save_chunk_state_witness(witness);
+        Ok(())
+    }
+
+    /// Applies the chunk a state witness was recorded for without the state of its shard, see
+    /// `ChainUpdate::apply_chunk_state_witness`.
+    pub fn apply_chunk_state_witness(
+        &mut self,
+        witness: &ChunkStateWitnessInner,
+    ) -> Result<ChunkStateWitnessResult, Error> {
+        self.chain_update().apply_chunk_state_witness(witness)
+    }
+
     /// Processes headers and adds them to store for syncing.
     pub fn sync_block_headers(
         &mut self,
@@ -1798,42 +1932,8 @@ impl Chain {
             .get_incoming_receipts_for_shard(shard_id, sync_hash, prev_chunk_height_included)?;
 
         // Collecting proofs for incoming receipts.
-        let mut root_proofs = vec![];
-        for receipt_response in incoming_receipts_proofs.iter() {
-            let ReceiptProofResponse(block_hash, receipt_proofs) = receipt_response;
-            let block_header = self.get_block_header(block_hash)?.clone();
-            let block = self.get_block(block_hash)?;
-            let (block_receipts_root, block_receipts_proofs) = merklize(
-                &block
-                    .chunks()
-                    .iter()
-                    .map(|chunk| chunk.outgoing_receipts_root())
-                    .collect::<Vec<CryptoHash>>(),
-            );
-
-            let mut root_proofs_cur = vec![];
-            assert_eq!(receipt_proofs.len(), block_header.chunks_included() as usize);
-            for receipt_proof in receipt_proofs {
-                let ReceiptProof(receipts, shard_proof) = receipt_proof;
-                let ShardProof { from_shard_id, to_shard_id: _, proof } = shard_proof;
-                let receipts_hash = hash(&ReceiptList(shard_id, receipts).try_to_vec()?);
-                let from_shard_id = *from_shard_id as usize;
-
-                let root_proof = block.chunks()[from_shard_id].outgoing_receipts_root();
-                root_proofs_cur
-                    .push(RootProof(root_proof, block_receipts_proofs[from_shard_id].clone()));
-
-                // Make sure we send something reasonable.
-                assert_eq!(block_header.chunk_receipts_root(), &block_receipts_root);
-                assert!(verify_path(root_proof, proof, &receipts_hash));
-                assert!(verify_path(
-                    block_receipts_root,
-                    &block_receipts_proofs[from_shard_id],
-                    &root_proof,
-                ));
-            }
-            root_proofs.push(root_proofs_cur);
-        }
+        let root_proofs =
+            get_receipts_root_proofs(&mut self.store, shard_id, &incoming_receipts_proofs)?;
 
         let state_root_node = self.runtime_adapter.get_state_root_node(
             shard_id,
@@ -2246,6 +2346,129 @@ impl Chain {
         chain_update.commit()
     }
 
+    /// Schedules building the states of shards of the next epoch which are merged from several
+    /// shards of this epoch, if `block_hash` is the last block of the epoch. Unlike split states,
+    /// which are updated along the epoch, merged states are built from the final states of their
+    /// parent shards, so that the delayed receipts of the parents are queued one after the other.
+    /// The first block of the next epoch is kept as an orphan until they are built, see
+    /// `build_merged_states_postprocessing`. The state of a merged shard can only be built once
+    /// the states of all its parents are caught up. Returns whether building was scheduled.
+    pub fn build_merged_states_preprocessing(
+        &mut self,
+        me: &Option<AccountId>,
+        block_hash: &CryptoHash,
+        state_merge_scheduler: &dyn Fn(StateMergeRequest),
+    ) -> Result<bool, Error> {
+        if !self.runtime_adapter.is_next_block_epoch_start(block_hash)? {
+            return Ok(false);
+        }
+        let epoch_id = self.get_block_header(block_hash)?.epoch_id().clone();
+        let shard_layout = self.runtime_adapter.get_shard_layout(&epoch_id)?;
+        let next_shard_layout =
+            self.runtime_adapter.get_shard_layout_from_prev_block(block_hash)?;
+        if shard_layout == next_shard_layout {
+            return Ok(false);
+        }
+
+        let mut merged_shards = vec![];
+        'shards: for shard_id in 0..next_shard_layout.num_shards() {
+            if !next_shard_layout.is_merged_shard(shard_id)
+                || !self.runtime_adapter.cares_about_shard(me.as_ref(), block_hash, shard_id, true)
+            {
+                continue;
+            }
+            let shard_uid = ShardUId::from_shard_id_and_layout(shard_id, &next_shard_layout);
+            if self.get_chunk_extra(block_hash, &shard_uid).is_ok() {
+                continue;
+            }
+            let mut parent_states = vec![];
+            for parent_shard_id in next_shard_layout.get_parent_shard_ids(shard_id)? {
+                let parent_shard_uid =
+                    ShardUId::from_shard_id_and_layout(parent_shard_id, &shard_layout);
+                match self.get_chunk_extra(block_hash, &parent_shard_uid) {
+                    Ok(chunk_extra) => {
+                        parent_states.push((parent_shard_uid, *chunk_extra.state_root()))
+                    }
+                    // The state of the parent shard is not caught up yet.
+                    Err(e) if matches!(e.kind(), ErrorKind::DBNotFoundErr(_)) => continue 'shards,
+                    Err(e) => return Err(e),
+                }
+            }
+            merged_shards.push((shard_uid, parent_states));
+        }
+        if merged_shards.is_empty() {
+            return Ok(false);
+        }
+
+        debug!(target: "chain", "Scheduling building merged states for {:?} at {:?}", merged_shards, block_hash);
+        state_merge_scheduler(StateMergeRequest {
+            runtime: Arc::clone(&self.runtime_adapter),
+            block_hash: *block_hash,
+            merged_shards,
+        });
+        Ok(true)
+    }
+
+    /// Saves the chunk extras of the merged shards built for the last block of an epoch, and
+    /// processes the orphans which were waiting for them.
+    pub fn build_merged_states_postprocessing(
+        &mut self,
+        me: &Option<AccountId>,
+        block_hash: &CryptoHash,
+        state_roots: Result<Vec<(ShardUId, StateRoot)>, Error>,
+        block_accepted: &mut dyn FnMut(AcceptedBlock),
+        block_misses_chunks: &mut dyn FnMut(BlockMissingChunks),
+        orphan_misses_chunks: &mut dyn FnMut(OrphanMissingChunks),
+        on_challenge: &mut dyn FnMut(ChallengeBody),
+    ) -> Result<(), Error> {
+        let epoch_id = self.get_block_header(block_hash)?.epoch_id().clone();
+        let shard_layout = self.runtime_adapter.get_shard_layout(&epoch_id)?;
+        let next_shard_layout =
+            self.runtime_adapter.get_shard_layout_from_prev_block(block_hash)?;
+        let mut chain_update = self.chain_update();
+        for (shard_uid, state_root) in state_roots? {
+            let mut parent_chunk_extras = vec![];
+            for parent_shard_id in next_shard_layout.get_parent_shard_ids(shard_uid.shard_id())? {
+                let parent_shard_uid =
+                    ShardUId::from_shard_id_and_layout(parent_shard_id, &shard_layout);
+                parent_chunk_extras.push(
+                    chain_update
+                        .chain_store_update
+                        .get_chunk_extra(block_hash, &parent_shard_uid)?
+                        .clone(),
+                );
+            }
+            // The merged shard takes the last chunk of its first parent as its previous chunk, see
+            // `get_prev_chunk_headers`, hence its outcome root. Validator proposals, gas and
+            // balance burnt of the last chunks of all parents add up, and so do their gas limits,
+            // so that the gas price accounts for the gas used by all of them.
+            let chunk_extra = ChunkExtra::new(
+                &state_root,
+                *parent_chunk_extras[0].outcome_root(),
+                parent_chunk_extras
+                    .iter()
+                    .flat_map(|chunk_extra| chunk_extra.validator_proposals())
+                    .collect(),
+                parent_chunk_extras.iter().map(|chunk_extra| chunk_extra.gas_used()).sum(),
+                parent_chunk_extras.iter().map(|chunk_extra| chunk_extra.gas_limit()).sum(),
+                parent_chunk_extras.iter().map(|chunk_extra| chunk_extra.balance_burnt()).sum(),
+            );
+            chain_update.chain_store_update.save_chunk_extra(block_hash, &shard_uid, chunk_extra);
+            debug!(target: "chain", "Built merged state for shard {:?} at {:?}: {:?}", shard_uid, block_hash, state_root);
+        }
+        chain_update.commit()?;
+
+        self.check_orphans(
+            me,
+            *block_hash,
+            block_accepted,
+            block_misses_chunks,
+            orphan_misses_chunks,
+            on_challenge,
+        );
+        Ok(())
+    }
+
     pub fn clear_downloaded_parts(
         &mut self,
         shard_id: ShardId,
@@ -2836,6 +3059,163 @@ impl Chain {
         self.store.get_previous_header(header)
     }
 
+    /// Lists the blocks and chunks `account_id` was expected to produce in epoch `epoch_id`
+    /// starting at `epoch_start_height`, up to the last block of the epoch on the canonical chain.
+    /// The start height must come from the epoch manager: the canonical chain may have no block
+    /// at it.
+    pub fn get_validator_performance(
+        &mut self,
+        epoch_id: &EpochId,
+        epoch_start_height: BlockHeight,
+        account_id: &AccountId,
+    ) -> Result<ValidatorPerformanceView, Error> {
+        let num_shards = self.runtime_adapter.num_shards(epoch_id)?;
+        // Missing blocks and chunks of a garbage collected epoch would be reported as not produced.
+        if epoch_start_height < self.tail()? {
+            return Err(ErrorKind::Other(format!(
+                "Blocks of epoch {:?} starting at height {} were garbage collected",
+                epoch_id, epoch_start_height
+            ))
+            .into());
+        }
+        let epoch_last_height = self.get_epoch_last_height(epoch_id, epoch_start_height)?;
+
+        let mut last_block_height = epoch_start_height;
+        let mut expected_blocks = vec![];
+        let mut expected_chunks = vec![];
+        for height in epoch_start_height..=epoch_last_height {
+            let header = match self.get_header_by_height(height) {
+                Ok(header) => Some(header.clone()),
+                Err(err) => match err.kind() {
+                    ErrorKind::DBNotFoundErr(_) => None,
+                    _ => return Err(err),
+                },
+            };
+            if let Some(header) = &header {
+                if header.epoch_id() != epoch_id {
+                    break;
+                }
+                last_block_height = height;
+            }
+
+            if &self.runtime_adapter.get_block_producer(epoch_id, height)? == account_id {
+                let status = match &header {
+                    Some(_) => ProductionStatusView::Produced,
+                    None => self.get_block_production_status(epoch_id, height)?,
+                };
+                expected_blocks.push(BlockProductionView { height, status });
+            }
+
+            // Like in the epoch manager, chunks are only expected in blocks that exist.
+            let header = match header {
+                Some(header) => header,
+                None => continue,
+            };
+            for shard_id in 0..num_shards {
+                if &self.runtime_adapter.get_chunk_producer(epoch_id, height, shard_id)?
+                    != account_id
+                {
+                    continue;
+                }
+                let status = if header.chunk_mask().get(shard_id as usize) == Some(&true) {
+                    ProductionStatusView::Produced
+                } else {
+                    self.get_chunk_production_status(&header, shard_id)
+                };
+                expected_chunks.push(ChunkProductionView { height, shard_id, status });
+            }
+        }
+        // Heights skipped before the first block of the next epoch belong to the next epoch.
+        expected_blocks.retain(|block| block.height <= last_block_height);
+
+        Ok(ValidatorPerformanceView {
+            account_id: account_id.clone(),
+            epoch_id: epoch_id.clone(),
+            epoch_start_height,
+            last_block_height,
+            expected_blocks,
+            expected_chunks,
+        })
+    }
+
+    /// Height of the last block of `epoch_id`, or of the head if the epoch hasn't ended yet. It is
+    /// the block before the first block of the next epoch, whose height is known to the epoch
+    /// manager.
+    fn get_epoch_last_height(
+        &mut self,
+        epoch_id: &EpochId,
+        epoch_start_height: BlockHeight,
+    ) -> Result<BlockHeight, Error> {
+        let head = self.head()?;
+        if &head.epoch_id == epoch_id {
+            return Ok(head.height);
+        }
+        let epoch_first_header = self.get_header_by_height(epoch_start_height)?;
+        if epoch_first_header.epoch_id() != epoch_id {
+            return Err(ErrorKind::EpochOutOfBounds(epoch_id.clone()).into());
+        }
+        let next_epoch_id = epoch_first_header.next_epoch_id().clone();
+        let next_epoch_start_height = if next_epoch_id == head.epoch_id {
+            self.runtime_adapter.get_epoch_start_height(&head.last_block_hash)?
+        } else {
+            self.runtime_adapter
+                .get_validator_info(ValidatorInfoIdentifier::EpochId(next_epoch_id))?
+                .epoch_start_height
+        };
+        let next_epoch_first_header = self.get_header_by_height(next_epoch_start_height)?;
+        let prev_hash = *next_epoch_first_header.prev_hash();
+        Ok(self.get_block_header(&prev_hash)?.height())
+    }
+
+    /// Status of a block at `height` of `epoch_id` that is not on the canonical chain. It is late
+    /// if it was built on the canonical chain, i.e. the next canonical block skipped it.
+    fn get_block_production_status(
+        &mut self,
+        epoch_id: &EpochId,
+        height: BlockHeight,
+    ) -> Result<ProductionStatusView, Error> {
+        let block_hashes = match self.store.get_all_block_hashes_by_height(height) {
+            Ok(blocks) => blocks.get(epoch_id).cloned().unwrap_or_default(),
+            Err(err) => match err.kind() {
+                ErrorKind::DBNotFoundErr(_) => Default::default(),
+                _ => return Err(err),
+            },
+        };
+        if block_hashes.is_empty() {
+            return Ok(ProductionStatusView::NotProduced);
+        }
+        for block_hash in block_hashes {
+            let prev_hash = *self.get_block_header(&block_hash)?.prev_hash();
+            let prev_header = self.get_block_header(&prev_hash)?.clone();
+            if self.is_on_current_chain(&prev_header).is_ok() {
+                return Ok(ProductionStatusView::Late);
+            }
+        }
+        Ok(ProductionStatusView::NotIncluded)
+    }
+
+    /// Status of a chunk for `shard_id` at the height of the canonical block `header` that the
+    /// block does not include. It is late if it was built on the same block as `header`.
+    fn get_chunk_production_status(
+        &mut self,
+        header: &BlockHeader,
+        shard_id: ShardId,
+    ) -> ProductionStatusView {
+        let chunk_hash =
+            match self.store.get_any_chunk_hash_by_height_shard(header.height(), shard_id) {
+                Ok(chunk_hash) => chunk_hash.clone(),
+                Err(_) => return ProductionStatusView::NotProduced,
+            };
+        match self.store.get_partial_chunk(&chunk_hash) {
+            Ok(partial_chunk)
+                if partial_chunk.cloned_header().prev_block_hash() == *header.prev_hash() =>
+            {
+                ProductionStatusView::Late
+            }
+            _ => ProductionStatusView::NotIncluded,
+        }
+    }
+
     /// Returns hash of the first available block after genesis.
     pub fn get_earliest_block_hash(&mut self) -> Result<Option<CryptoHash>, Error> {
         self.store.get_earliest_block_hash()
@@ -3395,83 +3775,327 @@ impl<'a> ChainUpdate<'a> {
         }
 
         for (shard_id, mut receipt_proofs) in receipt_proofs_by_shard_id {
-            let mut slice = [0u8; 32];
-            slice.copy_from_slice(block.hash().as_ref());
-            let mut rng: StdRng = SeedableRng::from_seed(slice);
-            receipt_proofs.shuffle(&mut rng);
+            shuffle_receipt_proofs(&mut receipt_proofs, block.hash());
             self.chain_store_update.save_incoming_receipt(block.hash(), shard_id, receipt_proofs);
         }
 
         Ok(())
     }
 
+    /// Creates a challenge for the chunk in `block` whose post-state doesn't match the result of
+    /// applying the previous chunk, which must be included in `prev_block`.
     pub fn create_chunk_state_challenge(
         &mut self,
         prev_block: &Block,
         block: &Block,
         chunk_header: &ShardChunkHeader,
-    ) -> Result<ChunkState, Error> {
+    ) -> Result<ChunkStateV2, Error> {
         let chunk_shard_id = chunk_header.shard_id();
         let prev_chunk_header = &prev_block.chunks()[chunk_shard_id as usize];
+        if prev_chunk_header.height_included() != prev_block.header().height()
+            || prev_block.header().prev_hash() == &CryptoHash::default()
+        {
+            return Err(ErrorKind::Other(
+                "create_chunk_state_challenge failed: previous chunk wasn't applied in previous block"
+                    .into(),
+            )
+            .into());
+        }
         let prev_merkle_proofs = Block::compute_chunk_headers_root(prev_block.chunks().iter()).1;
         let merkle_proofs = Block::compute_chunk_headers_root(block.chunks().iter()).1;
         let prev_chunk = self
             .chain_store_update
             .get_chain_store()
-            .get_chunk_clone_from_header(&prev_block.chunks()[chunk_shard_id as usize].clone())
-            .unwrap();
+            .get_chunk_clone_from_header(prev_chunk_header)?;
+
+        // Receipts applied with the previous chunk were sent since the chunk before it.
+        let prev_prev_block_header =
+            self.chain_store_update.get_block_header(prev_block.header().prev_hash())?.clone();
+        let prev_prev_chunk_height_included =
+            self.chain_store_update.get_block(prev_prev_block_header.hash())?.chunks()
+                [chunk_shard_id as usize]
+                .height_included();
+        let mut prev_chunk_block_headers = vec![];
+        let mut header = prev_prev_block_header.clone();
+        loop {
+            prev_chunk_block_headers.push(header.try_to_vec()?);
+            if header.height() <= prev_prev_chunk_height_included {
+                break;
+            }
+            header = self.chain_store_update.get_block_header(header.prev_hash())?.clone();
+        }
+        if self.runtime_adapter.get_shard_layout(header.epoch_id())?
+            != self.runtime_adapter.get_shard_layout(block.header().epoch_id())?
+        {
+            return Err(ErrorKind::Other(
+                "create_chunk_state_challenge failed: shard layout changed since previous chunk"
+                    .into(),
+            )
+            .into());
+        }
         let receipt_proof_response: Vec<ReceiptProofResponse> =
             self.chain_store_update.get_incoming_receipts_for_shard(
                 chunk_shard_id,
                 *prev_block.hash(),
-                prev_chunk_header.height_included(),
+                prev_prev_chunk_height_included,
             )?;
+        let root_proofs = get_receipts_root_proofs(
+            &mut self.chain_store_update,
+            chunk_shard_id,
+            &receipt_proof_response,
+        )?;
         let receipts = collect_receipts_from_response(&receipt_proof_response);
 
-        let challenges_result = self.verify_challenges(
-            block.challenges(),
-            block.header().epoch_id(),
-            block.header().prev_hash(),
-            Some(block.hash()),
-        )?;
         let prev_chunk_inner = prev_chunk.cloned_header().take_inner();
         let is_first_block_with_chunk_of_version = check_if_block_is_first_with_chunk_of_version(
             &mut self.chain_store_update,
             self.runtime_adapter.as_ref(),
-            prev_block.hash(),
+            prev_block.header().prev_hash(),
             chunk_shard_id,
         )?;
-        let apply_result = self
+        let apply_result = self.runtime_adapter.apply_transactions_with_optional_storage_proof(
+            chunk_shard_id,
+            prev_chunk_inner.prev_state_root(),
+            prev_chunk.height_included(),
+            prev_block.header().raw_timestamp(),
+            prev_chunk_inner.prev_block_hash(),
+            prev_block.hash(),
+            &receipts,
+            prev_chunk.transactions(),
+            prev_chunk_inner.validator_proposals(),
+            prev_prev_block_header.gas_price(),
+            prev_chunk_inner.gas_limit(),
+            prev_block.header().challenges_result(),
+            *prev_block.header().random_value(),
+            true,
+            true,
+            is_first_block_with_chunk_of_version,
+            None,
+        )?;
+        let partial_state = match apply_result.proof {
+            Some(proof) => proof.nodes,
+            None => {
+                return Err(ErrorKind::Other(
+                    "create_chunk_state_challenge failed: no storage proof was recorded".into(),
+                )
+                .into())
+            }
+        };
+        Ok(ChunkStateV2 {
+            prev_block_header: prev_block.header().try_to_vec()?,
+            block_header: block.header().try_to_vec()?,
+            prev_merkle_proof: prev_merkle_proofs[chunk_shard_id as usize].clone(),
+            merkle_proof: merkle_proofs[chunk_shard_id as usize].clone(),
+            prev_chunk,
+            chunk_header: chunk_header.clone(),
+            partial_state,
+            prev_chunk_incoming_receipts: receipt_proof_response,
+            prev_chunk_root_proofs: root_proofs,
+            prev_chunk_block_headers,
+        })
+    }
+
+    /// Applies the chunk a state witness was recorded for without the state of its shard, using
+    /// only the witness and the blocks in the store. Returns the results the next chunk of the
+    /// shard has to commit to, see `validate_chunk_with_chunk_extra_and_receipts_root`.
+    pub fn apply_chunk_state_witness(
+        &mut self,
+        witness: &ChunkStateWitnessInner,
+    ) -> Result<ChunkStateWitnessResult, Error> {
+        let block = self.chain_store_update.get_block(&witness.block_hash)?.clone();
+        let shard_id = witness.shard_id;
+        let chunk_header = match block.chunks().get(shard_id as usize) {
+            Some(chunk_header)
+                if chunk_header.chunk_hash() == witness.chunk_hash
+                    && chunk_header.height_included() == block.header().height() =>
+            {
+                chunk_header.clone()
+            }
+            _ => return Err(ErrorKind::InvalidChunkStateWitness.into()),
+        };
+        if merklize(&witness.transactions).0 != chunk_header.tx_root() {
+            return Err(ErrorKind::InvalidChunkStateWitness.into());
+        }
+        if !validate_transactions_order(&witness.transactions) {
+            return Err(ErrorKind::InvalidTransactions.into());
+        }
+
+        let prev_block_headers = get_chunk_state_witness_headers(
+            &mut self.chain_store_update,
+            &*self.runtime_adapter,
+            block.header(),
+            shard_id,
+        )?;
+        let receipts = validate_incoming_receipts(
+            &witness.incoming_receipts,
+            &witness.root_proofs,
+            shard_id,
+            block.header(),
+            &prev_block_headers,
+        )?
+        .ok_or_else(|| Error::from(ErrorKind::InvalidChunkStateWitness))?;
+
+        let protocol_version =
+            self.runtime_adapter.get_epoch_protocol_version(block.header().epoch_id())?;
+        if checked_feature!("stable", AccessKeyNonceRange, protocol_version) {
+            let transaction_validity_period = self.transaction_validity_period;
+            for transaction in witness.transactions.iter() {
+                self.chain_store_update
+                    .get_chain_store()
+                    .check_transaction_validity_period(
+                        &prev_block_headers[0],
+                        &transaction.transaction.block_hash,
+                        transaction_validity_period,
+                    )
+                    .map_err(|_| Error::from(ErrorKind::InvalidTransactions))?;
+            }
+        }
+
+        let is_first_block_with_chunk_of_version = check_if_block_is_first_with_chunk_of_version(
+            &mut self.chain_store_update,
+            self.runtime_adapter.as_ref(),
+            block.header().prev_hash(),
+            shard_id,
+        )?;
+        let partial_storage = PartialStorage { nodes: witness.partial_state.clone() };
+        let result = self
             .runtime_adapter
-            .apply_transactions_with_optional_storage_proof(
-                chunk_shard_id,
-                prev_chunk_inner.prev_state_root(),
-                prev_chunk.height_included(),
-                prev_block.header().raw_timestamp(),
-                prev_chunk_inner.prev_block_hash(),
-                prev_block.hash(),
+            .check_state_transition(
+                partial_storage,
+                shard_id,
+                &chunk_header.prev_state_root(),
+                chunk_header.height_included(),
+                block.header().raw_timestamp(),
+                &chunk_header.prev_block_hash(),
+                block.hash(),
                 &receipts,
-                prev_chunk.transactions(),
-                prev_chunk_inner.validator_proposals(),
-                prev_block.header().gas_price(),
-                prev_chunk_inner.gas_limit(),
-                &challenges_result,
+                &witness.transactions,
+                chunk_header.validator_proposals(),
+                prev_block_headers[0].gas_price(),
+                chunk_header.gas_limit(),
+                block.header().challenges_result(),
                 *block.header().random_value(),
                 true,
-                true,
                 is_first_block_with_chunk_of_version,
-                None,
             )
-            .unwrap();
-        let partial_state = apply_result.proof.unwrap().nodes;
-        Ok(ChunkState {
+            .map_err(|_| Error::from(ErrorKind::InvalidChunkStateWitness))?;
+        metrics::CHUNK_STATE_WITNESSES_APPLIED_TOTAL.inc();
+        let outcome_root = ApplyTransactionResult::compute_outcomes_proof(&result.outcomes).0;
+        let outgoing_receipts_root = {
+            let shard_layout =
+                self.runtime_adapter.get_shard_layout_from_prev_block(block.hash())?;
+            merklize(&Chain::build_receipts_hashes(&result.outgoing_receipts, &shard_layout)).0
+        };
+        Ok(ChunkStateWitnessResult {
+            chunk_extra: ChunkExtra::new(
+                &result.new_root,
+                outcome_root,
+                result.validator_proposals,
+                result.total_gas_burnt,
+                chunk_header.gas_limit(),
+                result.total_balance_burnt,
+            ),
+            outgoing_receipts_root,
+        })
+    }
+
+    /// Validates the new chunk of a shard whose state is not tracked against the state witness of
+    /// the previous chunk of the shard, if it was received. If the chunk doesn't commit to the
+    /// results of applying the previous chunk from its witness, the block is rejected with a chunk
+    /// state challenge built from the witness. Invalid witnesses are skipped, they are the fault
+    /// of the previous chunk producer, not of this block.
+    fn validate_chunk_with_state_witness(
+        &mut self,
+        prev_block: &Block,
+        block: &Block,
+        chunk_header: &ShardChunkHeader,
+    ) -> Result<(), Error> {
+        let shard_id = chunk_header.shard_id();
+        let witness = match self
+            .chain_store_update
+            .get_chain_store()
+            .get_chunk_state_witness(prev_block.hash(), shard_id)
+        {
+            Some(witness) => witness.inner.clone(),
+            None => return Ok(()),
+        };
+        let result = match self.apply_chunk_state_witness(&witness) {
+            Ok(result) => result,
+            Err(err) => {
+                debug!(target: "chain", "Can't apply state witness of chunk {:?}: {}", witness.chunk_hash, err);
+                return Ok(());
+            }
+        };
+        match validate_chunk_with_chunk_extra_and_receipts_root(
+            &result.chunk_extra,
+            &result.outgoing_receipts_root,
+            chunk_header,
+        ) {
+            Ok(()) => {
+                metrics::CHUNK_STATE_WITNESS_VALIDATIONS_TOTAL.with_label_values(&["valid"]).inc();
+                Ok(())
+            }
+            Err(err) => {
+                metrics::CHUNK_STATE_WITNESS_VALIDATIONS_TOTAL
+                    .with_label_values(&["invalid"])
+                    .inc();
+                warn!(target: "chain", "Chunk {:?} of shard {} in block {} doesn't match the state witness of the previous chunk: {}", chunk_header.chunk_hash(), shard_id, block.hash(), err);
+                match self.create_chunk_state_challenge_from_witness(
+                    prev_block,
+                    block,
+                    chunk_header,
+                    witness,
+                ) {
+                    Ok(chunk_state) => {
+                        Err(ErrorKind::InvalidChunkState(Box::new(chunk_state)).into())
+                    }
+                    Err(challenge_err) => {
+                        debug!(target: "chain", "Can't create chunk state challenge: {}", challenge_err);
+                        Err(err)
+                    }
+                }
+            }
+        }
+    }
+
+    /// Same as `create_chunk_state_challenge`, but takes the previous chunk, the partial state and
+    /// the incoming receipts from the state witness of the previous chunk instead of the state.
+    fn create_chunk_state_challenge_from_witness(
+        &mut self,
+        prev_block: &Block,
+        block: &Block,
+        chunk_header: &ShardChunkHeader,
+        witness: ChunkStateWitnessInner,
+    ) -> Result<ChunkStateV2, Error> {
+        let shard_id = chunk_header.shard_id();
+        let prev_chunk_header = prev_block.chunks()[shard_id as usize].clone();
+        let prev_merkle_proofs = Block::compute_chunk_headers_root(prev_block.chunks().iter()).1;
+        let merkle_proofs = Block::compute_chunk_headers_root(block.chunks().iter()).1;
+        let prev_chunk_block_headers = get_chunk_state_witness_headers(
+            &mut self.chain_store_update,
+            &*self.runtime_adapter,
+            prev_block.header(),
+            shard_id,
+        )?
+        .iter()
+        .map(|header| header.try_to_vec())
+        .collect::<Result<Vec<_>, _>>()?;
+        Ok(ChunkStateV2 {
             prev_block_header: prev_block.header().try_to_vec()?,
             block_header: block.header().try_to_vec()?,
-            prev_merkle_proof: prev_merkle_proofs[chunk_shard_id as usize].clone(),
-            merkle_proof: merkle_proofs[chunk_shard_id as usize].clone(),
-            prev_chunk,
+            prev_merkle_proof: prev_merkle_proofs[shard_id as usize].clone(),
+            merkle_proof: merkle_proofs[shard_id as usize].clone(),
+            prev_chunk: ShardChunk::V2(ShardChunkV2 {
+                chunk_hash: prev_chunk_header.chunk_hash(),
+                header: prev_chunk_header,
+                transactions: witness.transactions,
+                receipts: witness.receipts,
+            }),
             chunk_header: chunk_header.clone(),
-            partial_state,
+            partial_state: witness.partial_state,
+            prev_chunk_incoming_receipts: witness.incoming_receipts,
+            prev_chunk_root_proofs: witness.root_proofs,
+            prev_chunk_block_headers,
         })
     }
 
@@ -3541,6 +4165,9 @@ impl<'a> ChainUpdate<'a> {
         let prev_hash = block.header().prev_hash();
         let will_shard_layout_change =
             self.runtime_adapter.will_shard_layout_change_next_epoch(prev_hash)?;
+        let shard_layout = self.runtime_adapter.get_shard_layout(block.header().epoch_id())?;
+        let next_shard_layout =
+            self.runtime_adapter.get_shard_layout(block.header().next_epoch_id())?;
         let prev_chunk_headers = Chain::get_prev_chunk_headers(&*self.runtime_adapter, prev_block)?;
         for (shard_id, (chunk_header, prev_chunk_header)) in
             (block.chunks().iter().zip(prev_chunk_headers.iter())).enumerate()
@@ -3568,7 +4195,12 @@ impl<'a> ChainUpdate<'a> {
                     !cares_about_shard_this_epoch && cares_about_shard_next_epoch
                 }
             };
-            let need_to_split_states = will_shard_layout_change && cares_about_shard_next_epoch;
+            // States of shards merged in the next epoch are built once the last block of this
+            // epoch is processed, see `Chain::build_merged_states_preprocessing`, so changes of their parent
+            // shards are not applied to them along the epoch.
+            let will_split_shard =
+                will_shard_layout_change && !next_shard_layout.merges_parent_shard(shard_id);
+            let need_to_split_states = will_split_shard && cares_about_shard_next_epoch;
             // We can only split states when states are ready, i.e., mode != ApplyChunksMode::NotCaughtUp
             // 1) if should_apply_transactions == true && split_state_roots.is_some(),
             //     that means split states are ready.
@@ -3636,15 +4268,27 @@ impl<'a> ChainUpdate<'a> {
                             Ok(chunk_state) => {
                                 Error::from(ErrorKind::InvalidChunkState(Box::new(chunk_state)))
                             }
-                            Err(err) => err,
+                            Err(err) => {
+                                debug!(target: "chain", "Can't create chunk state challenge: {}", err);
+                                e
+                            }
                         }
                     })?;
                     let receipt_proof_response: Vec<ReceiptProofResponse> =
-                        self.chain_store_update.get_incoming_receipts_for_shard(
-                            shard_id,
-                            *block.hash(),
-                            prev_chunk_height_included,
-                        )?;
+                        if shard_layout.is_merged_shard(shard_id) {
+                            self.chain_store_update.get_incoming_receipts_for_merged_shard(
+                                &*self.runtime_adapter,
+                                shard_id,
+                                *block.hash(),
+                                prev_chunk_height_included,
+                            )?
+                        } else {
+                            self.chain_store_update.get_incoming_receipts_for_shard(
+                                shard_id,
+                                *block.hash(),
+                                prev_chunk_height_included,
+                            )?
+                        };
                     let receipts = collect_receipts_from_response(&receipt_proof_response);
                     let chunk = self
                         .chain_store_update
@@ -3727,18 +4371,17 @@ impl<'a> ChainUpdate<'a> {
                             None,
                         ) {
                             Ok(apply_result) => {
-                                let apply_split_result_or_state_changes =
-                                    if will_shard_layout_change {
-                                        Some(Self::apply_split_state_changes(
-                                            &*runtime_adapter,
-                                            &block_hash,
-                                            &prev_block_hash,
-                                            &apply_result,
-                                            split_state_roots,
-                                        )?)
-                                    } else {
-                                        None
-                                    };
+                                let apply_split_result_or_state_changes = if will_split_shard {
+                                    Some(Self::apply_split_state_changes(
+                                        &*runtime_adapter,
+                                        &block_hash,
+                                        &prev_block_hash,
+                                        &apply_result,
+                                        split_state_roots,
+                                    )?)
+                                } else {
+                                    None
+                                };
                                 Ok(ApplyChunkResult::SameHeight(SameHeightResult {
                                     gas_limit,
                                     shard_uid,
@@ -3791,18 +4434,17 @@ impl<'a> ChainUpdate<'a> {
                             None,
                         ) {
                             Ok(apply_result) => {
-                                let apply_split_result_or_state_changes =
-                                    if will_shard_layout_change {
-                                        Some(Self::apply_split_state_changes(
-                                            &*runtime_adapter,
-                                            &block_hash,
-                                            &prev_block_hash,
-                                            &apply_result,
-                                            split_state_roots,
-                                        )?)
-                                    } else {
-                                        None
-                                    };
+                                let apply_split_result_or_state_changes = if will_split_shard {
+                                    Some(Self::apply_split_state_changes(
+                                        &*runtime_adapter,
+                                        &block_hash,
+                                        &prev_block_hash,
+                                        &apply_result,
+                                        split_state_roots,
+                                    )?)
+                                } else {
+                                    None
+                                };
                                 Ok(ApplyChunkResult::DifferentHeight(DifferentHeightResult {
                                     shard_uid,
                                     apply_result,
@@ -3838,6 +4480,11 @@ impl<'a> ChainUpdate<'a> {
                         )?,
                     }))
                 }));
+            } else if is_new_chunk
+                && !cares_about_shard_this_epoch
+                && mode != ApplyChunksMode::CatchingUp
+            {
+                self.validate_chunk_with_state_witness(prev_block, block, chunk_header)?;
             }
         }
 
@@ -4057,6 +4704,47 @@ impl<'a> ChainUpdate<'a> {
         Ok(())
     }
 
+    /// Saves the loads of the shards in the block, used to split shards by load. They are derived
+    /// from the chunk headers of the block and, for the first block of an epoch, from the state of
+    /// the shards as of the previous block, so that all nodes record the same loads.
+    fn save_shard_loads(
+        &mut self,
+        block: &Block,
+        prev_hash: &CryptoHash,
+        prev_epoch_id: &EpochId,
+    ) -> Result<(), Error> {
+        let epoch_id = block.header().epoch_id();
+        if !self.runtime_adapter.is_resharding_enabled(epoch_id)? {
+            return Ok(());
+        }
+        // The previous block is caught up for the first block of an epoch to be processed, so the
+        // states of the shards of the new epoch are known even if shards were split.
+        let shard_layout = self.runtime_adapter.get_shard_layout(epoch_id)?;
+        let is_epoch_start = epoch_id != prev_epoch_id;
+        for (shard_id, chunk_header) in block.chunks().iter().enumerate() {
+            let shard_id = shard_id as ShardId;
+            let gas_used = if chunk_header.height_included() == block.header().height() {
+                Some(chunk_header.gas_used())
+            } else {
+                None
+            };
+            let state = if is_epoch_start {
+                let shard_uid = ShardUId::from_shard_id_and_layout(shard_id, &shard_layout);
+                let state_root =
+                    *self.chain_store_update.get_chunk_extra(prev_hash, &shard_uid)?.state_root();
+                Some(self.runtime_adapter.get_shard_state_load(&shard_uid, &state_root)?)
+            } else {
+                None
+            };
+            self.chain_store_update.save_shard_load(
+                block.hash(),
+                shard_id,
+                &ShardLoad { gas_used, state },
+            )?;
+        }
+        Ok(())
+    }
+
     /// Runs the block processing, including validation and finding a place for the new block in the chain.
     /// Returns new head if chain head updated, as well as a boolean indicating if we need to start
     ///    fetching state for the next epoch.
@@ -4142,6 +4830,11 @@ impl<'a> ChainUpdate<'a> {
                     // at all yet, needs to be orphaned
                     return Err(ErrorKind::Orphan.into());
                 }
+                if !self.merged_states_are_built(me, &prev_hash)? {
+                    // Same if the states of shards merged in this epoch are still being built, see
+                    // `Chain::build_merged_states_preprocessing`.
+                    return Err(ErrorKind::Orphan.into());
+                }
 
                 // For the first block of the epoch we never apply state for the next epoch, so it's
                 // always caught up.
@@ -4255,6 +4948,8 @@ impl<'a> ChainUpdate<'a> {
             self.chain_store_update.get_block_header(last_final_block)?.height()
         };
 
+        self.save_shard_loads(block, &prev_hash, &prev_epoch_id)?;
+
         let epoch_manager_update = self
             .runtime_adapter
             .add_validator_proposals(BlockHeaderInfo::new(block.header(), last_finalized_height))?;
@@ -4304,6 +4999,35 @@ impl<'a> ChainUpdate<'a> {
         )
     }
 
+    /// Whether the states of the shards of the epoch after `prev_hash` which are merged from
+    /// several shards, and which `me` cares about, are built.
+    fn merged_states_are_built(
+        &mut self,
+        me: &Option<AccountId>,
+        prev_hash: &CryptoHash,
+    ) -> Result<bool, Error> {
+        let epoch_id = self.chain_store_update.get_block_header(prev_hash)?.epoch_id().clone();
+        let shard_layout = self.runtime_adapter.get_shard_layout(&epoch_id)?;
+        let next_shard_layout = self.runtime_adapter.get_shard_layout_from_prev_block(prev_hash)?;
+        if shard_layout == next_shard_layout {
+            return Ok(true);
+        }
+        for shard_id in 0..next_shard_layout.num_shards() {
+            if !next_shard_layout.is_merged_shard(shard_id)
+                || !self.runtime_adapter.cares_about_shard(me.as_ref(), prev_hash, shard_id, true)
+            {
+                continue;
+            }
+            let shard_uid = ShardUId::from_shard_id_and_layout(shard_id, &next_shard_layout);
+            match self.chain_store_update.get_chunk_extra(prev_hash, &shard_uid) {
+                Ok(_) => {}
+                Err(e) if matches!(e.kind(), ErrorKind::DBNotFoundErr(_)) => return Ok(false),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(true)
+    }
+
     fn prev_block_is_caught_up(
         &self,
         prev_prev_hash: &CryptoHash,
@@ -4844,6 +5568,131 @@ pub fn do_apply_chunks(
     work.into_par_iter().map(|task| task()).collect::<Vec<_>>()
 }
 
+/// Builds the states of merged shards requested in `request`, returning their state roots.
+pub fn do_build_merged_states(
+    request: &StateMergeRequest,
+) -> Result<Vec<(ShardUId, StateRoot)>, Error> {
+    request
+        .merged_shards
+        .iter()
+        .map(|(shard_uid, parent_states)| {
+            let state_root =
+                request.runtime.build_state_for_merged_shard(parent_states, *shard_uid)?;
+            Ok((*shard_uid, state_root))
+        })
+        .collect()
+}
+
+/// Shuffles incoming receipt proofs of a block in the order in which they are applied.
+pub fn shuffle_receipt_proofs(receipt_proofs: &mut Vec<ReceiptProof>, block_hash: &CryptoHash) {
+    let mut slice = [0u8; 32];
+    slice.copy_from_slice(block_hash.as_ref());
+    let mut rng: StdRng = SeedableRng::from_seed(slice);
+    receipt_proofs.shuffle(&mut rng);
+}
+
+/// Collects proofs that the outgoing receipts roots of the chunks which sent given incoming
+/// receipts are included in the corresponding blocks. Fails if the receipts don't match the
+/// blocks, so that no invalid proofs are sent.
+/// Returns the headers of the blocks before the block with given header, down to and including
+/// the block with the previous chunk of the shard. Receipts applied with the chunk of the shard
+/// included in the block were sent from these blocks.
+fn get_chunk_state_witness_headers(
+    chain_store: &mut dyn ChainStoreAccess,
+    runtime_adapter: &dyn RuntimeAdapter,
+    block_header: &BlockHeader,
+    shard_id: ShardId,
+) -> Result<Vec<BlockHeader>, Error> {
+    // Receipts of merged shards are collected from their parent shards, and receipt proofs
+    // are checked against shard ids of the block, so the shard layout can't change either.
+    let shard_layout = runtime_adapter.get_shard_layout(block_header.epoch_id())?;
+    if shard_layout.is_merged_shard(shard_id) {
+        return Err(ErrorKind::Other(format!(
+            "chunk state witnesses are not supported for merged shard {}",
+            shard_id
+        ))
+        .into());
+    }
+    let mut headers = vec![];
+    let mut hash = *block_header.prev_hash();
+    loop {
+        let header = chain_store.get_block_header(&hash)?.clone();
+        if header.epoch_id() != block_header.epoch_id()
+            && runtime_adapter.get_shard_layout(header.epoch_id())? != shard_layout
+        {
+            return Err(ErrorKind::Other(
+                "chunk state witnesses are not supported across shard layout changes".into(),
+            )
+            .into());
+        }
+        let has_chunk = header.chunk_mask()[shard_id as usize];
+        hash = *header.prev_hash();
+        headers.push(header);
+        if has_chunk {
+            return Ok(headers);
+        }
+    }
+}
+
+fn get_receipts_root_proofs(
+    chain_store: &mut dyn ChainStoreAccess,
+    shard_id: ShardId,
+    incoming_receipts_proofs: &[ReceiptProofResponse],
+) -> Result<Vec<Vec<RootProof>>, Error> {
+    let invalid_receipts = |block_hash: &CryptoHash, reason: &str| -> Error {
+        ErrorKind::Other(format!(
+            "Incoming receipts of shard {} from block {} are invalid: {}",
+            shard_id, block_hash, reason
+        ))
+        .into()
+    };
+    let mut root_proofs = vec![];
+    for receipt_response in incoming_receipts_proofs.iter() {
+        let ReceiptProofResponse(block_hash, receipt_proofs) = receipt_response;
+        let block_header = chain_store.get_block_header(block_hash)?.clone();
+        let block = chain_store.get_block(block_hash)?;
+        let (block_receipts_root, block_receipts_proofs) = merklize(
+            &block
+                .chunks()
+                .iter()
+                .map(|chunk| chunk.outgoing_receipts_root())
+                .collect::<Vec<CryptoHash>>(),
+        );
+        if block_header.chunk_receipts_root() != &block_receipts_root {
+            return Err(invalid_receipts(block_hash, "receipts root doesn't match the chunks"));
+        }
+        if receipt_proofs.len() != block_header.chunks_included() as usize {
+            return Err(invalid_receipts(block_hash, "not one receipt proof per included chunk"));
+        }
+
+        let mut root_proofs_cur = vec![];
+        for receipt_proof in receipt_proofs {
+            let ReceiptProof(receipts, shard_proof) = receipt_proof;
+            let ShardProof { from_shard_id, to_shard_id: _, proof } = shard_proof;
+            let receipts_hash = hash(&ReceiptList(shard_id, receipts).try_to_vec()?);
+            let from_shard_id = *from_shard_id as usize;
+
+            let (root_proof, block_receipts_proof) =
+                match (block.chunks().get(from_shard_id), block_receipts_proofs.get(from_shard_id))
+                {
+                    (Some(chunk), Some(block_receipts_proof)) => {
+                        (chunk.outgoing_receipts_root(), block_receipts_proof.clone())
+                    }
+                    _ => return Err(invalid_receipts(block_hash, "unknown sender shard")),
+                };
+            // Make sure we send something reasonable.
+            if !verify_path(root_proof, proof, &receipts_hash)
+                || !verify_path(block_receipts_root, &block_receipts_proof, &root_proof)
+            {
+                return Err(invalid_receipts(block_hash, "receipts proof doesn't verify"));
+            }
+            root_proofs_cur.push(RootProof(root_proof, block_receipts_proof));
+        }
+        root_proofs.push(root_proofs_cur);
+    }
+    Ok(root_proofs)
+}
+
 pub fn collect_receipts<'a, T>(receipt_proofs: T) -> Vec<Receipt>
 where
     T: IntoIterator<Item = &'a ReceiptProof>,
@@ -4905,6 +5754,22 @@ pub struct StateSplitRequest {
     pub next_epoch_shard_layout: ShardLayout,
 }
 
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct StateMergeRequest {
+    pub runtime: Arc<dyn RuntimeAdapter>,
+    pub block_hash: CryptoHash,
+    /// Shards to build the states of, along with the states of their parent shards.
+    pub merged_shards: Vec<(ShardUId, Vec<(ShardUId, StateRoot)>)>,
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct StateMergeResponse {
+    pub block_hash: CryptoHash,
+    pub state_roots: Result<Vec<(ShardUId, StateRoot)>, Error>,
+}
+
 #[derive(Message)]
 #[rtype(result = "()")]
 pub struct StateSplitResponse {
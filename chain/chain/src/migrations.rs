@@ -5,7 +5,7 @@ use near_primitives::hash::CryptoHash;
 use near_primitives::types::ShardId;
 
 /// Check that epoch of block with given prev_block_hash is the first one with current protocol version.
-fn is_first_epoch_with_protocol_version(
+pub(crate) fn is_first_epoch_with_protocol_version(
     runtime_adapter: &dyn RuntimeAdapter,
     prev_block_hash: &CryptoHash,
 ) -> Result<bool, Error> {
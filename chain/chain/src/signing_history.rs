@@ -0,0 +1,264 @@
+//! Signing history of validators.
+//!
+//! For every validator account the node keeps the highest block, approval and per shard chunk
+//! it has signed. The history is consulted before anything is signed, so that the node never
+//! hands out two conflicting signatures for the same height, nor signs anything below what it has
+//! already signed, even across restarts.
+//!
+//! The history can be exported to and imported from JSON, so that operators moving a validator
+//! to another machine can carry it over.
+use std::collections::BTreeMap;
+use std::io;
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use serde::{Deserialize, Serialize};
+
+use near_primitives::hash::CryptoHash;
+use near_primitives::types::{AccountId, BlockHeight, ShardId};
+use near_store::{ColSigningHistory, Store};
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum SigningHistoryError {
+    #[error("{kind} at height {height} is below the highest signed height {highest}")]
+    BelowHighestHeight { kind: &'static str, height: BlockHeight, highest: BlockHeight },
+    #[error("{kind} at height {height} differs from the one already signed at this height")]
+    ConflictingData { kind: &'static str, height: BlockHeight },
+}
+
+/// Height and hash of signed data.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SignedData {
+    pub height: BlockHeight,
+    pub hash: CryptoHash,
+}
+
+#[derive(
+    BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq,
+)]
+pub struct SigningHistory {
+    /// Highest block signed, identified by the block hash.
+    pub last_block: Option<SignedData>,
+    /// Highest approval signed, by its target height and the hash of the signed data.
+    pub last_approval: Option<SignedData>,
+    /// Highest chunk signed for each shard, by its height and chunk hash.
+    #[serde(default)]
+    pub last_chunks: BTreeMap<ShardId, SignedData>,
+}
+
+impl SigningHistory {
+    /// Checks, before producing it, that a block at `height` may be signed.
+    pub fn check_block_height(&self, height: BlockHeight) -> Result<(), SigningHistoryError> {
+        Self::check_height(self.last_block.as_ref(), "Block", height)
+    }
+
+    /// Checks, before producing it, that a chunk at `height` may be signed.
+    pub fn check_chunk_height(
+        &self,
+        shard_id: ShardId,
+        height: BlockHeight,
+    ) -> Result<(), SigningHistoryError> {
+        Self::check_height(self.last_chunks.get(&shard_id), "Chunk", height)
+    }
+
+    /// Records a signed block, unless it conflicts with the history. Returns whether the history
+    /// changed.
+    pub fn record_block(
+        &mut self,
+        height: BlockHeight,
+        hash: CryptoHash,
+    ) -> Result<bool, SigningHistoryError> {
+        Self::record(&mut self.last_block, "Block", height, hash)
+    }
+
+    pub fn record_chunk(
+        &mut self,
+        shard_id: ShardId,
+        height: BlockHeight,
+        hash: CryptoHash,
+    ) -> Result<bool, SigningHistoryError> {
+        let mut last = self.last_chunks.get(&shard_id).cloned();
+        let changed = Self::record(&mut last, "Chunk", height, hash)?;
+        if let Some(last) = last {
+            self.last_chunks.insert(shard_id, last);
+        }
+        Ok(changed)
+    }
+
+    pub fn record_approval(
+        &mut self,
+        target_height: BlockHeight,
+        hash: CryptoHash,
+    ) -> Result<bool, SigningHistoryError> {
+        Self::record(&mut self.last_approval, "Approval", target_height, hash)
+    }
+
+    /// Merges an imported history into this one, keeping the highest entries of both.
+    pub fn merge(&mut self, other: SigningHistory) {
+        Self::merge_entry(&mut self.last_block, other.last_block);
+        Self::merge_entry(&mut self.last_approval, other.last_approval);
+        for (shard_id, data) in other.last_chunks {
+            let mut last = self.last_chunks.remove(&shard_id);
+            Self::merge_entry(&mut last, Some(data));
+            self.last_chunks.insert(shard_id, last.unwrap());
+        }
+    }
+
+    fn check_height(
+        last: Option<&SignedData>,
+        kind: &'static str,
+        height: BlockHeight,
+    ) -> Result<(), SigningHistoryError> {
+        match last {
+            Some(last) if height < last.height => {
+                Err(SigningHistoryError::BelowHighestHeight { kind, height, highest: last.height })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Signing the same data again is allowed, e.g. when an approval is resent.
+    fn record(
+        last: &mut Option<SignedData>,
+        kind: &'static str,
+        height: BlockHeight,
+        hash: CryptoHash,
+    ) -> Result<bool, SigningHistoryError> {
+        Self::check_height(last.as_ref(), kind, height)?;
+        match last {
+            Some(last) if last.height == height => {
+                if last.hash == hash {
+                    Ok(false)
+                } else {
+                    Err(SigningHistoryError::ConflictingData { kind, height })
+                }
+            }
+            _ => {
+                *last = Some(SignedData { height, hash });
+                Ok(true)
+            }
+        }
+    }
+
+    fn merge_entry(last: &mut Option<SignedData>, other: Option<SignedData>) {
+        if let Some(other) = other {
+            if last.as_ref().map_or(true, |last| last.height < other.height) {
+                *last = Some(other);
+            }
+        }
+    }
+}
+
+/// Entry of the export format, i.e. the signing history of one validator.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SigningHistoryRecord {
+    pub account_id: AccountId,
+    pub history: SigningHistory,
+}
+
+pub fn get_signing_history(store: &Store, account_id: &AccountId) -> io::Result<SigningHistory> {
+    Ok(store.get_ser(ColSigningHistory, account_id.as_ref().as_bytes())?.unwrap_or_default())
+}
+
+pub fn save_signing_history(
+    store: &Store,
+    account_id: &AccountId,
+    history: &SigningHistory,
+) -> io::Result<()> {
+    let mut store_update = store.store_update();
+    store_update.set_ser(ColSigningHistory, account_id.as_ref().as_bytes(), history)?;
+    store_update.commit()
+}
+
+/// Returns the signing history of all validators the node has signed for.
+pub fn export_signing_history(store: &Store) -> io::Result<Vec<SigningHistoryRecord>> {
+    store
+        .iter(ColSigningHistory)
+        .map(|(key, value)| {
+            Ok(SigningHistoryRecord {
+                account_id: String::from_utf8(key.to_vec())
+                    .ok()
+                    .and_then(|account_id| account_id.parse().ok())
+                    .ok_or_else(|| {
+                        io::Error::new(io::ErrorKind::InvalidData, "invalid account id")
+                    })?,
+                history: SigningHistory::try_from_slice(&value)?,
+            })
+        })
+        .collect()
+}
+
+/// Merges exported signing history into the one kept by the node.
+pub fn import_signing_history(store: &Store, records: Vec<SigningHistoryRecord>) -> io::Result<()> {
+    for record in records {
+        let mut history = get_signing_history(store, &record.account_id)?;
+        history.merge(record.history);
+        save_signing_history(store, &record.account_id, &history)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use near_primitives::hash::hash;
+    use near_store::test_utils::create_test_store;
+
+    use super::*;
+
+    #[test]
+    fn test_signing_history() {
+        let mut history = SigningHistory::default();
+        assert!(history.record_block(10, hash(&[1])).unwrap());
+        // Same block again is fine, a different one at the same or lower height is not.
+        assert!(!history.record_block(10, hash(&[1])).unwrap());
+        assert_eq!(
+            history.record_block(10, hash(&[2])),
+            Err(SigningHistoryError::ConflictingData { kind: "Block", height: 10 })
+        );
+        assert_eq!(
+            history.check_block_height(9),
+            Err(SigningHistoryError::BelowHighestHeight { kind: "Block", height: 9, highest: 10 })
+        );
+        assert!(history.check_block_height(10).is_ok());
+
+        // Chunks are tracked per shard.
+        history.record_chunk(0, 5, hash(&[3])).unwrap();
+        assert!(history.check_chunk_height(0, 4).is_err());
+        assert!(history.check_chunk_height(1, 4).is_ok());
+        assert!(history.record_chunk(0, 5, hash(&[4])).is_err());
+
+        history.record_approval(11, hash(&[5])).unwrap();
+        assert!(history.record_approval(11, hash(&[6])).is_err());
+        assert!(history.record_approval(12, hash(&[6])).unwrap());
+    }
+
+    #[test]
+    fn test_export_import_signing_history() {
+        let account_id: AccountId = "test0".parse().unwrap();
+        let store = create_test_store();
+        let mut history = SigningHistory::default();
+        history.record_block(10, hash(&[1])).unwrap();
+        history.record_chunk(1, 10, hash(&[2])).unwrap();
+        save_signing_history(&store, &account_id, &history).unwrap();
+
+        let exported = export_signing_history(&store).unwrap();
+        let exported: Vec<SigningHistoryRecord> =
+            serde_json::from_str(&serde_json::to_string(&exported).unwrap()).unwrap();
+        assert_eq!(
+            exported,
+            vec![SigningHistoryRecord { account_id: account_id.clone(), history }]
+        );
+
+        // The new machine has signed approvals already, the imported history is merged in.
+        let new_store = create_test_store();
+        let mut new_history = SigningHistory::default();
+        new_history.record_block(5, hash(&[3])).unwrap();
+        new_history.record_approval(6, hash(&[4])).unwrap();
+        save_signing_history(&new_store, &account_id, &new_history).unwrap();
+        import_signing_history(&new_store, exported).unwrap();
+
+        let merged = get_signing_history(&new_store, &account_id).unwrap();
+        assert_eq!(merged.last_block, Some(SignedData { height: 10, hash: hash(&[1]) }));
+        assert_eq!(merged.last_approval, Some(SignedData { height: 6, hash: hash(&[4]) }));
+        assert_eq!(merged.last_chunks.get(&1), Some(&SignedData { height: 10, hash: hash(&[2]) }));
+    }
+}
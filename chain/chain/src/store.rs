@@ -8,14 +8,17 @@ use near_primitives::time::Utc;
 
 use near_chain_primitives::error::{Error, ErrorKind};
 use near_primitives::block::{Approval, Tip};
+use near_primitives::epoch_manager::ShardLoad;
 use near_primitives::errors::InvalidTxError;
 use near_primitives::hash::CryptoHash;
 use near_primitives::merkle::{MerklePath, PartialMerkleTree};
 use near_primitives::receipt::Receipt;
-use near_primitives::shard_layout::{account_id_to_shard_id, get_block_shard_uid, ShardUId};
+use near_primitives::shard_layout::{
+    account_id_to_shard_id, get_block_shard_uid, ShardLayout, ShardUId,
+};
 use near_primitives::sharding::{
-    ChunkHash, EncodedShardChunk, PartialEncodedChunk, ReceiptProof, ShardChunk, ShardChunkHeader,
-    StateSyncInfo,
+    ChunkHash, ChunkStateWitness, EncodedShardChunk, PartialEncodedChunk, ReceiptProof, ShardChunk,
+    ShardChunkHeader, StateSyncInfo,
 };
 use near_primitives::syncing::{
     get_num_state_parts, ReceiptProofResponse, ShardStateSyncResponseHeader, StateHeaderKey,
@@ -40,7 +43,7 @@ use near_store::{
     ColChunkPerHeightShard, ColChunks, ColEpochLightClientBlocks, ColGCCount,
     ColHeaderHashesByHeight, ColIncomingReceipts, ColInvalidChunks, ColNextBlockHashes,
     ColOutcomeIds, ColOutgoingReceipts, ColPartialChunks, ColProcessedBlockHeights,
-    ColReceiptIdToShardId, ColReceipts, ColState, ColStateChanges, ColStateDlInfos,
+    ColReceiptIdToShardId, ColReceipts, ColShardLoad, ColState, ColStateChanges, ColStateDlInfos,
     ColStateHeaders, ColStateParts, ColTransactionResult, ColTransactions, ColTrieChanges, DBCol,
     KeyForStateChanges, ShardTries, Store, StoreUpdate, TrieChanges, WrappedTrieChanges,
     CHUNK_TAIL_KEY, FINAL_HEAD_KEY, FORK_TAIL_KEY, HEADER_HEAD_KEY, HEAD_KEY,
@@ -57,6 +60,10 @@ const CACHE_SIZE: usize = 100;
 #[cfg(not(feature = "no_cache"))]
 const CHUNK_CACHE_SIZE: usize = 1024;
 
+/// Number of chunk state witnesses kept in memory. They are only needed until the block with the
+/// next chunk of their shard is processed.
+const CHUNK_STATE_WITNESS_CACHE_SIZE: usize = 32;
+
 #[cfg(feature = "no_cache")]
 const CACHE_SIZE: usize = 1;
 #[cfg(feature = "no_cache")]
@@ -345,6 +352,9 @@ pub struct ChainStore {
     block_ordinal_to_hash: LruCache<Vec<u8>, CryptoHash>,
     /// Processed block heights.
     processed_block_heights: LruCache<Vec<u8>, ()>,
+    /// State witnesses of chunks of shards that are not tracked, by the hash of the block that
+    /// includes the chunk and the shard id. They are not persisted.
+    chunk_state_witnesses: LruCache<(CryptoHash, ShardId), ChunkStateWitness>,
 }
 
 pub fn option_to_not_found<T>(res: io::Result<Option<T>>, field_name: &str) -> Result<T, Error> {
@@ -386,6 +396,7 @@ impl ChainStore {
             block_merkle_tree: LruCache::new(CACHE_SIZE),
             block_ordinal_to_hash: LruCache::new(CACHE_SIZE),
             processed_block_heights: LruCache::new(CACHE_SIZE),
+            chunk_state_witnesses: LruCache::new(CHUNK_STATE_WITNESS_CACHE_SIZE),
         }
     }
 
@@ -455,6 +466,15 @@ impl ChainStore {
                 let receipts_shard_layout =
                     runtime_adapter.get_shard_layout(block_header.epoch_id())?;
 
+                if shard_layout != receipts_shard_layout && shard_layout.is_merged_shard(shard_id) {
+                    return self.get_outgoing_receipts_for_merged_shard(
+                        runtime_adapter,
+                        prev_block_hash,
+                        shard_id,
+                        &shard_layout,
+                    );
+                }
+
                 // get the shard from which the outgoing receipt were generated
                 let receipts_shard_id = if shard_layout != receipts_shard_layout {
                     shard_layout.get_parent_shard_id(shard_id)?
@@ -480,6 +500,51 @@ impl ChainStore {
         }
     }
 
+    /// Outgoing receipts of the last chunks of the parents of a merged shard, in the order of the
+    /// parent shards. Unlike for split shards, receipts are not filtered, since all receipts of
+    /// the parent shards are sent by the merged shard.
+    fn get_outgoing_receipts_for_merged_shard(
+        &mut self,
+        runtime_adapter: &dyn RuntimeAdapter,
+        prev_block_hash: CryptoHash,
+        shard_id: ShardId,
+        shard_layout: &ShardLayout,
+    ) -> Result<Vec<Receipt>, Error> {
+        // find the last block before the shard layout changed
+        let mut last_block_hash = prev_block_hash;
+        loop {
+            let block_header = self.get_block_header(&last_block_hash)?;
+            if runtime_adapter.get_shard_layout(block_header.epoch_id())? != *shard_layout {
+                break;
+            }
+            last_block_hash = *block_header.prev_hash();
+        }
+        let heights_included: Vec<_> = self
+            .get_block(&last_block_hash)?
+            .chunks()
+            .iter()
+            .map(|chunk_header| chunk_header.height_included())
+            .collect();
+
+        let mut receipts = vec![];
+        for parent_shard_id in shard_layout.get_parent_shard_ids(shard_id)? {
+            let mut receipts_block_hash = last_block_hash;
+            loop {
+                let block_header = self.get_block_header(&receipts_block_hash)?;
+                if block_header.height() == heights_included[parent_shard_id as usize] {
+                    break;
+                }
+                receipts_block_hash = *block_header.prev_hash();
+            }
+            if let Ok(parent_receipts) =
+                self.get_outgoing_receipts(&receipts_block_hash, parent_shard_id)
+            {
+                receipts.extend(parent_receipts.iter().cloned());
+            }
+        }
+        Ok(receipts)
+    }
+
     /// For a given transaction, it expires if the block that the chunk points to is more than `validity_period`
     /// ahead of the block that has `base_block_hash`.
     pub fn check_transaction_validity_period(
@@ -629,6 +694,22 @@ impl ChainStore {
         store_update.commit().map_err(|err| err.into())
     }
 
+    /// Keeps the state witness of a chunk until the block with the next chunk of its shard is
+    /// processed.
+    pub fn save_chunk_state_witness(&mut self, witness: ChunkStateWitness) {
+        self.chunk_state_witnesses.put((witness.inner.block_hash, witness.inner.shard_id), witness);
+    }
+
+    /// Returns the state witness of the chunk of given shard included in given block, if it was
+    /// received.
+    pub fn get_chunk_state_witness(
+        &self,
+        block_hash: &CryptoHash,
+        shard_id: ShardId,
+    ) -> Option<&ChunkStateWitness> {
+        self.chunk_state_witnesses.peek(&(*block_hash, shard_id))
+    }
+
     /// Retrieve the kinds of state changes occurred in a given block.
     ///
     /// We store different types of data, so we prefer to only expose minimal information about the
@@ -1225,6 +1306,58 @@ impl<'a> ChainStoreUpdate<'a> {
         Ok(ret)
     }
 
+    /// Like `get_incoming_receipts_for_shard`, for a shard merged from several parent shards.
+    /// Until the merged shard has a chunk, its previous chunk is the last chunk of its first
+    /// parent, see `Chain::get_prev_chunk_headers`. Receipts sent to the merged shard since the
+    /// shard layout changed are then returned along with receipts sent to each parent shard since
+    /// its own last chunk.
+    pub fn get_incoming_receipts_for_merged_shard(
+        &mut self,
+        runtime_adapter: &dyn RuntimeAdapter,
+        shard_id: ShardId,
+        mut block_hash: CryptoHash,
+        last_chunk_height_included: BlockHeight,
+    ) -> Result<Vec<ReceiptProofResponse>, Error> {
+        let shard_layout =
+            runtime_adapter.get_shard_layout(self.get_block_header(&block_hash)?.epoch_id())?;
+        let mut ret = vec![];
+
+        loop {
+            let header = self.get_block_header(&block_hash)?.clone();
+
+            if header.height() == last_chunk_height_included {
+                return Ok(ret);
+            }
+            if runtime_adapter.get_shard_layout(header.epoch_id())? != shard_layout {
+                break;
+            }
+
+            if let Ok(receipt_proofs) = self.get_incoming_receipts(&block_hash, shard_id) {
+                ret.push(ReceiptProofResponse(block_hash, receipt_proofs.clone()));
+            } else {
+                ret.push(ReceiptProofResponse(block_hash, vec![]));
+            }
+
+            block_hash = *header.prev_hash();
+        }
+
+        // `block_hash` is the last block before the shard layout changed
+        let heights_included: Vec<_> = self
+            .get_block(&block_hash)?
+            .chunks()
+            .iter()
+            .map(|chunk_header| chunk_header.height_included())
+            .collect();
+        for parent_shard_id in shard_layout.get_parent_shard_ids(shard_id)? {
+            ret.extend(self.get_incoming_receipts_for_shard(
+                parent_shard_id,
+                block_hash,
+                heights_included[parent_shard_id as usize],
+            )?);
+        }
+        Ok(ret)
+    }
+
     /// WARNING
     ///
     /// Usually ChainStoreUpdate has some uncommitted changes
@@ -1803,6 +1936,19 @@ impl<'a> ChainStoreUpdate<'a> {
             .insert((*hash, shard_id), outgoing_receipts);
     }
 
+    /// Save the load of shard `shard_id` in block `hash`.
+    pub fn save_shard_load(
+        &mut self,
+        hash: &CryptoHash,
+        shard_id: ShardId,
+        shard_load: &ShardLoad,
+    ) -> Result<(), Error> {
+        let mut store_update = self.store().store_update();
+        store_update.set_ser(ColShardLoad, &get_block_shard_id(hash, shard_id), shard_load)?;
+        self.merge(store_update);
+        Ok(())
+    }
+
     pub fn save_receipt_id_to_shard_id(
         &mut self,
         runtime_adapter: &dyn RuntimeAdapter,
@@ -2124,6 +2270,7 @@ impl<'a> ChainStoreUpdate<'a> {
             self.gc_outgoing_receipts(&block_hash, shard_id);
             self.gc_col(ColIncomingReceipts, &block_shard_id);
             self.gc_col(ColChunkPerHeightShard, &block_shard_id);
+            self.gc_col(ColShardLoad, &block_shard_id);
 
             // For incoming State Parts it's done in chain.clear_downloaded_parts()
             // The following code is mostly for outgoing State Parts.
@@ -2422,6 +2569,9 @@ impl<'a> ChainStoreUpdate<'a> {
             DBCol::ColHeaderHashesByHeight => {
                 store_update.delete(col, key);
             }
+            DBCol::ColShardLoad => {
+                store_update.delete(col, key);
+            }
             DBCol::ColDbVersion
             | DBCol::ColBlockMisc
             | DBCol::ColGCCount
@@ -2441,6 +2591,8 @@ impl<'a> ChainStoreUpdate<'a> {
             | DBCol::_ColLastBlockWithNewChunk
             | DBCol::_ColTransactionRefCount
             | DBCol::ColStateChangesForSplitStates
+            | DBCol::ColEpochShardConfig
+            | DBCol::ColSigningHistory
             | DBCol::ColCachedContractCode => {
                 unreachable!();
             }
@@ -53,7 +53,7 @@ use crate::types::{
 };
 use crate::Doomslug;
 use crate::{BlockHeader, DoomslugThresholdMode, RuntimeAdapter};
-use near_primitives::epoch_manager::ShardConfig;
+use near_primitives::epoch_manager::{ShardConfig, ShardStateLoad};
 use near_primitives::time::Clock;
 
 #[derive(BorshSerialize, BorshDeserialize, Hash, PartialEq, Eq, Ord, PartialOrd, Clone, Debug)]
@@ -483,6 +483,18 @@ impl RuntimeAdapter for KeyValueRuntime {
         panic!("get_shard_config not implemented for KeyValueRuntime");
     }
 
+    fn is_resharding_enabled(&self, _epoch_id: &EpochId) -> Result<bool, Error> {
+        Ok(false)
+    }
+
+    fn get_shard_state_load(
+        &self,
+        _shard_uid: &ShardUId,
+        _state_root: &StateRoot,
+    ) -> Result<ShardStateLoad, Error> {
+        Ok(ShardStateLoad::default())
+    }
+
     fn get_prev_shard_ids(
         &self,
         _prev_hash: &CryptoHash,
@@ -1205,6 +1217,14 @@ impl RuntimeAdapter for KeyValueRuntime {
         Ok(HashMap::new())
     }
 
+    fn build_state_for_merged_shard(
+        &self,
+        _parent_states: &[(ShardUId, StateRoot)],
+        _shard_uid: ShardUId,
+    ) -> Result<StateRoot, Error> {
+        Ok(StateRoot::default())
+    }
+
     fn get_protocol_upgrade_block_height(
         &self,
         _block_hash: CryptoHash,
@@ -21,6 +21,7 @@ use near_primitives::merkle::{merklize, MerklePath};
 use near_primitives::receipt::Receipt;
 use near_primitives::sharding::{ChunkHash, ShardChunkHeader};
 use near_primitives::transaction::{ExecutionOutcomeWithId, SignedTransaction};
+use near_primitives::types::chunk_extra::ChunkExtra;
 use near_primitives::types::validator_stake::{ValidatorStake, ValidatorStakeIter};
 use near_primitives::types::{
     AccountId, ApprovalStake, Balance, BlockHeight, BlockHeightDelta, EpochHeight, EpochId, Gas,
@@ -34,7 +35,7 @@ use near_primitives::views::{EpochValidatorInfo, QueryRequest, QueryResponse};
 use near_store::{PartialStorage, ShardTries, Store, StoreUpdate, Trie, WrappedTrieChanges};
 
 use crate::DoomslugThresholdMode;
-use near_primitives::epoch_manager::ShardConfig;
+use near_primitives::epoch_manager::{ShardConfig, ShardStateLoad};
 use near_primitives::shard_layout::{ShardLayout, ShardUId};
 use near_primitives::state_record::StateRecord;
 
@@ -119,6 +120,14 @@ impl ApplyTransactionResult {
     }
 }
 
+/// Results of applying a chunk from its state witness, which the next chunk of the shard has to
+/// commit to.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChunkStateWitnessResult {
+    pub chunk_extra: ChunkExtra,
+    pub outgoing_receipts_root: CryptoHash,
+}
+
 /// Compressed information about block.
 /// Useful for epoch manager.
 #[derive(Default, Clone, Debug)]
@@ -460,6 +469,17 @@ pub trait RuntimeAdapter: Send + Sync {
 
     fn get_shard_config(&self, epoch_id: &EpochId) -> Result<ShardConfig, Error>;
 
+    /// Whether shards are split by load in the given epoch, see `ShardLoad`.
+    fn is_resharding_enabled(&self, epoch_id: &EpochId) -> Result<bool, Error>;
+
+    /// Size and median account of the given state of the shard, recorded once per epoch to split
+    /// shards by load.
+    fn get_shard_state_load(
+        &self,
+        shard_uid: &ShardUId,
+        state_root: &StateRoot,
+    ) -> Result<ShardStateLoad, Error>;
+
     fn get_prev_shard_ids(
         &self,
         prev_hash: &CryptoHash,
@@ -641,6 +661,9 @@ pub trait RuntimeAdapter: Send + Sync {
         states_to_patch: Option<Vec<StateRecord>>,
     ) -> Result<ApplyTransactionResult, Error>;
 
+    /// Same as `apply_transactions`, but reads the state only from `partial_storage`, as recorded
+    /// by `apply_transactions_with_optional_storage_proof`. Used to check chunk state challenges
+    /// and to apply chunks from their state witnesses.
     fn check_state_transition(
         &self,
         partial_storage: PartialStorage,
@@ -715,6 +738,14 @@ pub trait RuntimeAdapter: Send + Sync {
         next_epoch_shard_layout: &ShardLayout,
     ) -> Result<HashMap<ShardUId, StateRoot>, Error>;
 
+    /// Builds the state of `shard_uid`, which is merged from several shards, from the states of
+    /// its parent shards given in the order of the shard layout. Returns the merged state root.
+    fn build_state_for_merged_shard(
+        &self,
+        parent_states: &[(ShardUId, StateRoot)],
+        shard_uid: ShardUId,
+    ) -> Result<StateRoot, Error>;
+
     /// Should be executed after accepting all the parts to set up a new state.
     fn apply_state_part(
         &self,
@@ -825,7 +856,8 @@ mod tests {
             KeyType::ED25519,
             "other2",
         );
-        let approvals = vec![Some(Approval::new(*b1.hash(), 1, 2, &other_signer).signature)];
+        let approvals =
+            vec![Some(Approval::new(*b1.hash(), 1, 2, &other_signer).unwrap().signature)];
         let b2 = Block::empty_with_approvals(
             &b1,
             2,
@@ -1,24 +1,28 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
-use borsh::BorshDeserialize;
+use borsh::{BorshDeserialize, BorshSerialize};
 
 use near_crypto::PublicKey;
 use near_primitives::block::{Block, BlockHeader};
 use near_primitives::challenge::{
-    BlockDoubleSign, Challenge, ChallengeBody, ChallengesResult, ChunkProofs, ChunkState,
-    MaybeEncodedShardChunk,
+    BlockDoubleSign, Challenge, ChallengeBody, ChunkProofs, ChunkStateV2, MaybeEncodedShardChunk,
 };
-use near_primitives::hash::CryptoHash;
-use near_primitives::merkle::merklize;
+use near_primitives::checked_feature;
+use near_primitives::hash::{hash, CryptoHash};
+use near_primitives::merkle::{merklize, verify_path};
+use near_primitives::receipt::Receipt;
 use near_primitives::sharding::{
-    ShardChunk, ShardChunkHeader, ShardChunkHeaderV1, ShardChunkHeaderV2, ShardChunkHeaderV3,
+    ReceiptList, ReceiptProof, ShardChunk, ShardChunkHeader, ShardChunkHeaderV1,
+    ShardChunkHeaderV2, ShardChunkHeaderV3, ShardProof,
 };
+use near_primitives::syncing::{ReceiptProofResponse, RootProof};
 use near_primitives::transaction::SignedTransaction;
 use near_primitives::types::chunk_extra::ChunkExtra;
-use near_primitives::types::validator_stake::ValidatorStakeIter;
-use near_primitives::types::{AccountId, BlockHeight, EpochId, Nonce};
+use near_primitives::types::{AccountId, BlockHeight, EpochId, Nonce, ShardId};
 use near_store::PartialStorage;
 
+use crate::chain::{collect_receipts, shuffle_receipt_proofs};
+use crate::migrations::is_first_epoch_with_protocol_version;
 use crate::types::ApplyTransactionResult;
 use crate::{byzantine_assert, Chain};
 use crate::{ChainStore, Error, ErrorKind, RuntimeAdapter};
@@ -129,6 +133,31 @@ pub fn validate_chunk_with_chunk_extra(
     prev_chunk_extra: &ChunkExtra,
     prev_chunk_height_included: BlockHeight,
     chunk_header: &ShardChunkHeader,
+) -> Result<(), Error> {
+    let outgoing_receipts = chain_store.get_outgoing_receipts_for_shard(
+        runtime_adapter,
+        *prev_block_hash,
+        chunk_header.shard_id(),
+        prev_chunk_height_included,
+    )?;
+    let outgoing_receipts_hashes = {
+        let shard_layout = runtime_adapter.get_shard_layout_from_prev_block(prev_block_hash)?;
+        Chain::build_receipts_hashes(&outgoing_receipts, &shard_layout)
+    };
+    let (outgoing_receipts_root, _) = merklize(&outgoing_receipts_hashes);
+    validate_chunk_with_chunk_extra_and_receipts_root(
+        prev_chunk_extra,
+        &outgoing_receipts_root,
+        chunk_header,
+    )
+}
+
+/// Validates that the given chunk header matches the results of applying the previous chunk,
+/// given as its chunk extra and the root of its outgoing receipts.
+pub fn validate_chunk_with_chunk_extra_and_receipts_root(
+    prev_chunk_extra: &ChunkExtra,
+    outgoing_receipts_root: &CryptoHash,
+    chunk_header: &ShardChunkHeader,
 ) -> Result<(), Error> {
     if *prev_chunk_extra.state_root() != chunk_header.prev_state_root() {
         return Err(ErrorKind::InvalidStateRoot.into());
@@ -158,19 +187,7 @@ pub fn validate_chunk_with_chunk_extra(
         return Err(ErrorKind::InvalidBalanceBurnt.into());
     }
 
-    let outgoing_receipts = chain_store.get_outgoing_receipts_for_shard(
-        runtime_adapter,
-        *prev_block_hash,
-        chunk_header.shard_id(),
-        prev_chunk_height_included,
-    )?;
-    let outgoing_receipts_hashes = {
-        let shard_layout = runtime_adapter.get_shard_layout_from_prev_block(prev_block_hash)?;
-        Chain::build_receipts_hashes(&outgoing_receipts, &shard_layout)
-    };
-    let (outgoing_receipts_root, _) = merklize(&outgoing_receipts_hashes);
-
-    if outgoing_receipts_root != chunk_header.outgoing_receipts_root() {
+    if *outgoing_receipts_root != chunk_header.outgoing_receipts_root() {
         return Err(ErrorKind::InvalidReceiptsProof.into());
     }
 
@@ -306,9 +323,78 @@ fn validate_chunk_proofs_challenge(
     return Err(ErrorKind::MaliciousChallenge.into());
 }
 
+/// Checks that `incoming_receipts` are exactly the receipts applied with the chunk of `shard_id`
+/// included in the block with `block_header` and returns them in the order in which they were
+/// applied. `prev_block_headers` are the headers of the blocks before it, down to and including
+/// the block with the previous chunk of the shard. Returns `None` if the receipts or their proofs
+/// don't match the headers.
+pub(crate) fn validate_incoming_receipts(
+    incoming_receipts: &[ReceiptProofResponse],
+    root_proofs: &[Vec<RootProof>],
+    shard_id: ShardId,
+    block_header: &BlockHeader,
+    prev_block_headers: &[BlockHeader],
+) -> Result<Option<Vec<Receipt>>, Error> {
+    if incoming_receipts.is_empty()
+        || incoming_receipts.len() != root_proofs.len()
+        || incoming_receipts.len() != prev_block_headers.len()
+    {
+        return Ok(None);
+    }
+    let has_chunk = |header: &BlockHeader| {
+        header.chunk_mask().get(shard_id as usize).copied().unwrap_or_default()
+    };
+
+    let mut receipts = vec![];
+    let mut header = block_header;
+    for (i, receipt_response) in incoming_receipts.iter().enumerate() {
+        let ReceiptProofResponse(block_hash, receipt_proofs) = receipt_response;
+        // Blocks must be continuous and only the last header may have the previous chunk, so
+        // that no block with receipts for the chunk is left out.
+        let prev_header = &prev_block_headers[i];
+        let is_last = i + 1 == prev_block_headers.len();
+        if block_hash != header.hash()
+            || prev_header.hash() != header.prev_hash()
+            || has_chunk(prev_header) != is_last
+        {
+            return Ok(None);
+        }
+
+        let root_proofs = &root_proofs[i];
+        if receipt_proofs.len() != root_proofs.len()
+            || receipt_proofs.len() != header.chunks_included() as usize
+        {
+            return Ok(None);
+        }
+        // Same as for state sync, distinct proofs for each included chunk mean that no receipts
+        // were hidden.
+        let mut visited_shard_ids = HashSet::new();
+        for (receipt_proof, RootProof(root, block_proof)) in receipt_proofs.iter().zip(root_proofs)
+        {
+            let ReceiptProof(shard_receipts, ShardProof { from_shard_id, proof, .. }) =
+                receipt_proof;
+            let receipts_hash = hash(&ReceiptList(shard_id, shard_receipts).try_to_vec()?);
+            if !visited_shard_ids.insert(*from_shard_id)
+                || !verify_path(*root, proof, &receipts_hash)
+                || !verify_path(*header.chunk_receipts_root(), block_proof, root)
+            {
+                return Ok(None);
+            }
+        }
+
+        // Receipts of a block are applied in the order in which they were saved.
+        let mut receipt_proofs = receipt_proofs.clone();
+        receipt_proofs.sort_by_key(|ReceiptProof(_, shard_proof)| shard_proof.from_shard_id);
+        shuffle_receipt_proofs(&mut receipt_proofs, block_hash);
+        receipts.extend(collect_receipts(&receipt_proofs));
+        header = prev_header;
+    }
+    Ok(Some(receipts))
+}
+
 fn validate_chunk_state_challenge(
     runtime_adapter: &dyn RuntimeAdapter,
-    chunk_state: &ChunkState,
+    chunk_state: &ChunkStateV2,
 ) -> Result<(CryptoHash, Vec<AccountId>), Error> {
     let prev_block_header = BlockHeader::try_from_slice(&chunk_state.prev_block_header)?;
     let block_header = BlockHeader::try_from_slice(&chunk_state.block_header)?;
@@ -321,7 +407,9 @@ fn validate_chunk_state_challenge(
         &prev_chunk_header,
         prev_block_header.chunk_headers_root(),
         &chunk_state.prev_merkle_proof,
-    ) {
+    ) || prev_chunk_header.height_included() != prev_block_header.height()
+        || !validate_chunk_proofs(&chunk_state.prev_chunk, runtime_adapter)?
+    {
         return Err(ErrorKind::MaliciousChallenge.into());
     }
 
@@ -332,31 +420,58 @@ fn validate_chunk_state_challenge(
         &chunk_state.chunk_header,
         block_header.chunk_headers_root(),
         &chunk_state.merkle_proof,
-    ) {
+    ) || block_header.prev_hash() != prev_block_header.hash()
+        || chunk_state.chunk_header.shard_id() != prev_chunk_header.shard_id()
+    {
         return Err(ErrorKind::MaliciousChallenge.into());
     }
 
+    // Validate receipts applied with the previous chunk.
+    let shard_id = prev_chunk_header.shard_id();
+    let block_headers = chunk_state
+        .prev_chunk_block_headers
+        .iter()
+        .map(|header| BlockHeader::try_from_slice(header))
+        .collect::<Result<Vec<_>, _>>()?;
+    let receipts = validate_incoming_receipts(
+        &chunk_state.prev_chunk_incoming_receipts,
+        &chunk_state.prev_chunk_root_proofs,
+        shard_id,
+        &prev_block_header,
+        &block_headers,
+    )?
+    .ok_or_else(|| Error::from(ErrorKind::MaliciousChallenge))?;
+    let prev_prev_block_header = &block_headers[0];
+    let prev_prev_chunk_block_header = &block_headers[block_headers.len() - 1];
+    if runtime_adapter.get_shard_layout(prev_prev_chunk_block_header.epoch_id())?
+        != runtime_adapter.get_shard_layout(block_header.epoch_id())?
+    {
+        return Err(ErrorKind::MaliciousChallenge.into());
+    }
+    let is_first_block_with_chunk_of_version =
+        is_first_epoch_with_protocol_version(runtime_adapter, prev_block_header.prev_hash())?
+            && prev_prev_chunk_block_header.epoch_id() != prev_block_header.epoch_id();
+
     // Apply state transition and check that the result state and other data doesn't match.
     let partial_storage = PartialStorage { nodes: chunk_state.partial_state.clone() };
     let result = runtime_adapter
         .check_state_transition(
             partial_storage,
-            prev_chunk_header.shard_id(),
+            shard_id,
             &prev_chunk_header.prev_state_root(),
-            block_header.height(),
-            block_header.raw_timestamp(),
-            block_header.prev_hash(),
-            block_header.hash(),
-            chunk_state.prev_chunk.receipts(),
+            prev_block_header.height(),
+            prev_block_header.raw_timestamp(),
+            &prev_chunk_header.prev_block_hash(),
+            prev_block_header.hash(),
+            &receipts,
             chunk_state.prev_chunk.transactions(),
-            ValidatorStakeIter::empty(),
-            prev_block_header.gas_price(),
+            prev_chunk_header.validator_proposals(),
+            prev_prev_block_header.gas_price(),
             prev_chunk_header.gas_limit(),
-            &ChallengesResult::default(),
-            *block_header.random_value(),
-            // TODO: set it properly when challenges are enabled
+            prev_block_header.challenges_result(),
+            *prev_block_header.random_value(),
             true,
-            false,
+            is_first_block_with_chunk_of_version,
         )
         .map_err(|_| Error::from(ErrorKind::MaliciousChallenge))?;
     let outcome_root = ApplyTransactionResult::compute_outcomes_proof(&result.outcomes).0;
@@ -367,10 +482,18 @@ fn validate_chunk_state_challenge(
             .iter()
             .zip(chunk_state.chunk_header.validator_proposals())
             .all(|(x, y)| x == &y);
+    let outgoing_receipts_root = {
+        let shard_layout =
+            runtime_adapter.get_shard_layout_from_prev_block(block_header.prev_hash())?;
+        merklize(&Chain::build_receipts_hashes(&result.outgoing_receipts, &shard_layout)).0
+    };
     if result.new_root != chunk_state.chunk_header.prev_state_root()
         || outcome_root != chunk_state.chunk_header.outcome_root()
         || !proposals_match
         || result.total_gas_burnt != chunk_state.chunk_header.gas_used()
+        || result.total_balance_burnt != chunk_state.chunk_header.balance_burnt()
+        || outgoing_receipts_root != chunk_state.chunk_header.outgoing_receipts_root()
+        || prev_chunk_header.gas_limit() != chunk_state.chunk_header.gas_limit()
     {
         Ok((*block_header.hash(), vec![chunk_producer]))
     } else {
@@ -404,7 +527,18 @@ pub fn validate_challenge(
         ChallengeBody::ChunkProofs(chunk_proofs) => {
             validate_chunk_proofs_challenge(runtime_adapter, chunk_proofs)
         }
-        ChallengeBody::ChunkState(chunk_state) => {
+        // Without the receipts applied with the previous chunk, the state transition can't be
+        // re-executed.
+        ChallengeBody::ChunkState(_) => Err(ErrorKind::MaliciousChallenge.into()),
+        ChallengeBody::ChunkStateV2(chunk_state) => {
+            let protocol_version = runtime_adapter.get_epoch_protocol_version(epoch_id)?;
+            if !checked_feature!(
+                "protocol_feature_chunk_state_challenges",
+                ChunkStateChallenges,
+                protocol_version
+            ) {
+                return Err(ErrorKind::InvalidChallenge.into());
+            }
             validate_chunk_state_challenge(runtime_adapter, chunk_state)
         }
     }
@@ -16,6 +16,26 @@ pub enum Error {
     IOError(std::io::Error),
 }
 
+impl Error {
+    /// Whether the error is caused by invalid data sent by a peer.
+    pub fn is_bad_data(&self) -> bool {
+        match self {
+            Error::InvalidPartMessage
+            | Error::InvalidChunkPartId
+            | Error::InvalidChunkShardId
+            | Error::InvalidMerkleProof
+            | Error::InvalidChunkSignature
+            | Error::InvalidChunkHeader
+            | Error::InvalidChunk => true,
+            Error::ChainError(err) => err.is_bad_data(),
+            Error::DuplicateChunkHeight
+            | Error::UnknownChunk
+            | Error::KnownPart
+            | Error::IOError(_) => false,
+        }
+    }
+}
+
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
         write!(f, "{:?}", self)
@@ -22,7 +22,7 @@ use near_primitives::views::{
     BlockView, ChunkView, EpochValidatorInfo, ExecutionOutcomeWithIdView,
     FinalExecutionOutcomeViewEnum, GasPriceView, LightClientBlockLiteView, LightClientBlockView,
     QueryRequest, QueryResponse, ReceiptView, StateChangesKindsView, StateChangesRequestView,
-    StateChangesView,
+    StateChangesView, ValidatorPerformanceView,
 };
 pub use near_primitives::views::{StatusResponse, StatusSyncInfo};
 
@@ -36,6 +36,17 @@ pub enum Error {
     Other(String),
 }
 
+impl Error {
+    /// Whether the error is caused by invalid data sent by a peer.
+    pub fn is_bad_data(&self) -> bool {
+        match self {
+            Error::Chain(err) => err.is_bad_data(),
+            Error::Chunk(err) => err.is_bad_data(),
+            Error::BlockProducer(_) | Error::ChunkProducer(_) | Error::Other(_) => false,
+        }
+    }
+}
+
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -445,6 +456,14 @@ impl Message for GetNetworkInfo {
     type Result = Result<NetworkInfoResponse, String>;
 }
 
+/// Loads the pending validator key from the configured file, see
+/// `ClientConfig::pending_validator_key_path`. Returns the public key of the pending key if any.
+pub struct LoadPendingValidatorKey {}
+
+impl Message for LoadPendingValidatorKey {
+    type Result = Result<Option<near_crypto::PublicKey>, String>;
+}
+
 pub struct GetGasPrice {
     pub block_id: MaybeBlockId,
 }
@@ -571,6 +590,16 @@ impl Message for GetValidatorOrdered {
     type Result = Result<Vec<ValidatorStakeView>, GetValidatorInfoError>;
 }
 
+/// Blocks and chunks a validator was expected to produce in an epoch.
+pub struct GetValidatorPerformance {
+    pub epoch_reference: EpochReference,
+    pub account_id: AccountId,
+}
+
+impl Message for GetValidatorPerformance {
+    type Result = Result<ValidatorPerformanceView, GetValidatorInfoError>;
+}
+
 pub struct GetStateChanges {
     pub block_hash: CryptoHash,
     pub state_changes_request: StateChangesRequestView,
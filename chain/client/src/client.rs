@@ -10,7 +10,10 @@ use tracing::{debug, error, info, warn};
 
 use near_chain::chain::{
     ApplyStatePartsRequest, BlockCatchUpRequest, BlockMissingChunks, BlocksCatchUpState,
-    OrphanMissingChunks, StateSplitRequest, TX_ROUTING_HEIGHT_HORIZON,
+    OrphanMissingChunks, StateMergeRequest, StateSplitRequest, TX_ROUTING_HEIGHT_HORIZON,
+};
+use near_chain::signing_history::{
+    get_signing_history, save_signing_history, SigningHistory, SigningHistoryError,
 };
 use near_chain::test_utils::format_hash;
 use near_chain::types::{AcceptedBlock, LatestKnown};
@@ -20,26 +23,34 @@ use near_chain::{
 };
 use near_chain_configs::ClientConfig;
 use near_chunks::{ProcessPartialEncodedChunkResult, ShardsManager};
+use near_crypto::PublicKey;
 use near_network::types::{
     FullPeerInfo, NetworkClientResponses, NetworkRequests, PeerManagerAdapter,
 };
 use near_primitives::block::{Approval, ApprovalInner, ApprovalMessage, Block, BlockHeader, Tip};
 use near_primitives::challenge::{Challenge, ChallengeBody};
-use near_primitives::hash::CryptoHash;
+use near_primitives::checked_feature;
+use near_primitives::hash::{hash, CryptoHash};
 use near_primitives::merkle::{merklize, MerklePath};
 use near_primitives::receipt::Receipt;
+use near_primitives::shard_layout::ShardUId;
+#[cfg(feature = "protocol_feature_chunk_state_witness")]
+use near_primitives::sharding::ChunkStateWitness;
 use near_primitives::sharding::{
     EncodedShardChunk, PartialEncodedChunk, PartialEncodedChunkV2, ReedSolomonWrapper,
     ShardChunkHeader, ShardInfo,
 };
 use near_primitives::transaction::SignedTransaction;
 use near_primitives::types::chunk_extra::ChunkExtra;
-use near_primitives::types::{AccountId, ApprovalStake, BlockHeight, EpochId, NumBlocks, ShardId};
+use near_primitives::types::{
+    AccountId, ApprovalStake, BlockHeight, EpochId, NumBlocks, ShardId, StateRoot,
+};
 use near_primitives::unwrap_or_return;
 use near_primitives::utils::{to_timestamp, MaybeValidated};
 use near_primitives::validator_signer::ValidatorSigner;
 
 use crate::chunks_delay_tracker::ChunksDelayTracker;
+use crate::fisherman;
 use crate::sync::{BlockSync, EpochSync, HeaderSync, StateSync, StateSyncResult};
 use crate::{metrics, SyncStatus};
 use near_client_primitives::types::{Error, ShardSyncDownload, ShardSyncStatus};
@@ -77,6 +88,9 @@ pub struct Client {
     network_adapter: Arc<dyn PeerManagerAdapter>,
     /// Signer for block producer (if present).
     pub validator_signer: Option<Arc<dyn ValidatorSigner>>,
+    /// New key of the same validator which replaces `validator_signer` once it becomes the
+    /// validator's key in the epoch info.
+    pub pending_validator_signer: Option<Arc<dyn ValidatorSigner>>,
     /// Approvals for which we do not have the block yet
     pub pending_approvals:
         lru::LruCache<ApprovalInner, HashMap<AccountId, (Approval, ApprovalType)>>,
@@ -84,6 +98,8 @@ pub struct Client {
     /// storing the current status of the state sync and blocks catch up
     pub catchup_state_syncs:
         HashMap<CryptoHash, (StateSync, HashMap<u64, ShardSyncDownload>, BlocksCatchUpState)>,
+    /// Last blocks of epochs for which the states of shards merged in the next epoch are being built
+    pub merged_states_in_progress: HashSet<CryptoHash>,
     /// Keeps track of information needed to perform the initial Epoch Sync
     pub epoch_sync: EpochSync,
     /// Keeps track of syncing headers.
@@ -180,8 +196,10 @@ impl Client {
             shards_mgr,
             network_adapter,
             validator_signer,
+            pending_validator_signer: None,
             pending_approvals: lru::LruCache::new(num_block_producer_seats),
             catchup_state_syncs: HashMap::new(),
+            merged_states_in_progress: HashSet::new(),
             epoch_sync,
             header_sync,
             block_sync,
@@ -334,11 +352,6 @@ impl Client {
     pub fn produce_block(&mut self, next_height: BlockHeight) -> Result<Option<Block>, Error> {
         let known_height = self.chain.mut_store().get_latest_known()?.height;
 
-        let validator_signer = self
-            .validator_signer
-            .as_ref()
-            .ok_or_else(|| Error::BlockProducer("Called without block producer info.".to_string()))?
-            .clone();
         let head = self.chain.head()?;
         assert_eq!(
             head.epoch_id,
@@ -348,6 +361,10 @@ impl Client {
         // Check that we are were called at the block that we are producer for.
         let epoch_id =
             self.runtime_adapter.get_epoch_id_from_prev_block(&head.last_block_hash).unwrap();
+        let validator_signer =
+            self.validator_signer_for_epoch(&epoch_id, &head.last_block_hash).ok_or_else(|| {
+                Error::BlockProducer("Called without block producer info.".to_string())
+            })?;
         let next_block_proposer =
             self.runtime_adapter.get_block_producer(&epoch_id, next_height)?;
 
@@ -400,6 +417,11 @@ impl Client {
             return Ok(None);
         }
 
+        self.update_signing_history(validator_signer.validator_id(), |history| {
+            history.check_block_height(next_height).map(|()| false)
+        })
+        .map_err(Error::BlockProducer)?;
+
         let mut approvals_map = self.doomslug.remove_witness(&prev_hash, prev_height, next_height);
 
         // At this point, the previous epoch hash must be available
@@ -486,14 +508,27 @@ impl Client {
                 None
             };
 
-        // Get all the current challenges.
-        // TODO(2445): Enable challenges when they are working correctly.
-        // let challenges = self.challenges.drain().map(|(_, challenge)| challenge).collect();
         let this_epoch_protocol_version =
             self.runtime_adapter.get_epoch_protocol_version(&epoch_id)?;
         let next_epoch_protocol_version =
             self.runtime_adapter.get_epoch_protocol_version(&next_epoch_id)?;
 
+        // Get all the current challenges. They are removed once a block with them is accepted.
+        // TODO(2445): Enable other challenges when they are working correctly.
+        let challenges = if checked_feature!(
+            "protocol_feature_chunk_state_challenges",
+            ChunkStateChallenges,
+            this_epoch_protocol_version
+        ) {
+            self.challenges
+                .values()
+                .filter(|challenge| matches!(challenge.body, ChallengeBody::ChunkStateV2(_)))
+                .cloned()
+                .collect()
+        } else {
+            vec![]
+        };
+
         let block = Block::produce(
             this_epoch_protocol_version,
             next_epoch_protocol_version,
@@ -510,11 +545,17 @@ impl Client {
             max_gas_price,
             minted_amount,
             prev_block_extra.challenges_result,
-            vec![],
+            challenges,
             &*validator_signer,
             next_bp_hash,
             block_merkle_root,
-        );
+        )
+        .map_err(|err| Error::BlockProducer(err.to_string()))?;
+
+        self.update_signing_history(validator_signer.validator_id(), |history| {
+            history.record_block(next_height, *block.hash())
+        })
+        .map_err(Error::BlockProducer)?;
 
         // Update latest known even before returning block out, to prevent race conditions.
         self.chain.mut_store().save_latest_known(LatestKnown {
@@ -535,11 +576,10 @@ impl Client {
         next_height: BlockHeight,
         shard_id: ShardId,
     ) -> Result<Option<(EncodedShardChunk, Vec<MerklePath>, Vec<Receipt>)>, Error> {
-        let validator_signer = self
-            .validator_signer
-            .as_ref()
-            .ok_or_else(|| Error::ChunkProducer("Called without block producer info.".to_string()))?
-            .clone();
+        let validator_signer =
+            self.validator_signer_for_epoch(epoch_id, &prev_block_hash).ok_or_else(|| {
+                Error::ChunkProducer("Called without block producer info.".to_string())
+            })?;
 
         let chunk_proposer =
             self.runtime_adapter.get_chunk_producer(epoch_id, next_height, shard_id).unwrap();
@@ -568,6 +608,11 @@ impl Client {
             validator_signer.validator_id()
         );
 
+        self.update_signing_history(validator_signer.validator_id(), |history| {
+            history.check_chunk_height(shard_id, next_height).map(|()| false)
+        })
+        .map_err(Error::ChunkProducer)?;
+
         let shard_uid = self.runtime_adapter.shard_id_to_uid(shard_id, epoch_id)?;
         let chunk_extra = self
             .chain
@@ -622,6 +667,11 @@ impl Client {
             protocol_version,
         )?;
 
+        self.update_signing_history(validator_signer.validator_id(), |history| {
+            history.record_chunk(shard_id, next_height, encoded_chunk.chunk_hash().0)
+        })
+        .map_err(Error::ChunkProducer)?;
+
         debug!(
             target: "client",
             "Produced chunk at height {} for shard {} with {} txs and {} receipts, I'm {}, chunk_hash: {}",
@@ -687,7 +737,16 @@ impl Client {
     pub fn send_challenges(&mut self, challenges: Vec<ChallengeBody>) {
         if let Some(validator_signer) = &self.validator_signer {
             for body in challenges {
-                let challenge = Challenge::produce(body, &**validator_signer);
+                let challenge = match Challenge::produce(body, &**validator_signer) {
+                    Ok(challenge) => challenge,
+                    Err(err) => {
+                        error!(target: "client", "Failed to sign challenge: {}", err);
+                        continue;
+                    }
+                };
+                if self.config.fisherman {
+                    fisherman::record_challenge(&challenge);
+                }
                 self.challenges.insert(challenge.hash, challenge.clone());
                 self.network_adapter.do_send(PeerManagerMessageRequest::NetworkRequests(
                     NetworkRequests::Challenge(challenge),
@@ -750,27 +809,14 @@ impl Client {
         self.send_challenges(challenges);
 
         // Send out challenge if the block was found to be invalid.
-        if let Some(validator_signer) = self.validator_signer.as_ref() {
-            match &result {
-                Err(e) => match e.kind() {
-                    near_chain::ErrorKind::InvalidChunkProofs(chunk_proofs) => {
-                        self.network_adapter.do_send(PeerManagerMessageRequest::NetworkRequests(
-                            NetworkRequests::Challenge(Challenge::produce(
-                                ChallengeBody::ChunkProofs(*chunk_proofs),
-                                &**validator_signer,
-                            )),
-                        ));
-                    }
-                    near_chain::ErrorKind::InvalidChunkState(chunk_state) => {
-                        self.network_adapter.do_send(PeerManagerMessageRequest::NetworkRequests(
-                            NetworkRequests::Challenge(Challenge::produce(
-                                ChallengeBody::ChunkState(*chunk_state),
-                                &**validator_signer,
-                            )),
-                        ));
-                    }
-                    _ => {}
-                },
+        if let Err(e) = &result {
+            match e.kind() {
+                near_chain::ErrorKind::InvalidChunkProofs(chunk_proofs) => {
+                    self.send_challenges(vec![ChallengeBody::ChunkProofs(*chunk_proofs)]);
+                }
+                near_chain::ErrorKind::InvalidChunkState(chunk_state) => {
+                    self.send_challenges(vec![ChallengeBody::ChunkStateV2(*chunk_state)]);
+                }
                 _ => {}
             }
         }
@@ -939,11 +985,98 @@ impl Client {
                 tip.height,
                 last_final_height,
             );
+            // Approvals are sent for the blocks built on top of the tip, so sign them with the
+            // key the validator has in the epoch of those blocks.
+            if self.pending_validator_signer.is_some() {
+                let next_epoch_id =
+                    self.runtime_adapter.get_epoch_id_from_prev_block(&tip.last_block_hash)?;
+                self.doomslug.set_signer(
+                    self.validator_signer_for_epoch(&next_epoch_id, &tip.last_block_hash),
+                );
+            }
         }
 
         Ok(())
     }
 
+    /// Sets a new key for the validator this node is running. The current key keeps being used
+    /// until the new one becomes the validator's key in the epoch info, i.e. until the staking
+    /// transaction with the new key takes effect, at which point the node switches to it.
+    pub fn set_pending_validator_signer(
+        &mut self,
+        signer: Arc<dyn ValidatorSigner>,
+    ) -> Result<(), Error> {
+        let validator_signer = self.validator_signer.as_ref().ok_or_else(|| {
+            Error::Other("Can't set a pending validator key on a non-validator node".to_string())
+        })?;
+        if signer.validator_id() != validator_signer.validator_id() {
+            return Err(Error::Other(format!(
+                "Pending validator key is for {}, but the node is running validator {}",
+                signer.validator_id(),
+                validator_signer.validator_id()
+            )));
+        }
+        if signer.public_key() == validator_signer.public_key() {
+            self.pending_validator_signer = None;
+            return Ok(());
+        }
+        info!(target: "client", "Set pending validator key {} for {}", signer.public_key(), signer.validator_id());
+        self.pending_validator_signer = Some(signer);
+        self.maybe_switch_to_pending_validator_signer();
+        Ok(())
+    }
+
+    /// Returns the signer that matches the validator key in the given epoch: the pending key if
+    /// it has already taken effect there, the current key otherwise.
+    pub fn validator_signer_for_epoch(
+        &self,
+        epoch_id: &EpochId,
+        last_known_block_hash: &CryptoHash,
+    ) -> Option<Arc<dyn ValidatorSigner>> {
+        if let Some(pending_validator_signer) = self.pending_validator_signer.as_ref() {
+            if self.validator_key_for_epoch(epoch_id, last_known_block_hash).as_ref()
+                == Some(&pending_validator_signer.public_key())
+            {
+                return Some(pending_validator_signer.clone());
+            }
+        }
+        self.validator_signer.clone()
+    }
+
+    fn validator_key_for_epoch(
+        &self,
+        epoch_id: &EpochId,
+        last_known_block_hash: &CryptoHash,
+    ) -> Option<PublicKey> {
+        let account_id = self.validator_signer.as_ref()?.validator_id();
+        self.runtime_adapter
+            .get_validator_by_account_id(epoch_id, last_known_block_hash, account_id)
+            .ok()
+            .map(|(validator_stake, _)| validator_stake.take_public_key())
+    }
+
+    /// Replaces the validator key with the pending one once the head is in an epoch where the
+    /// pending key is the validator's key.
+    fn maybe_switch_to_pending_validator_signer(&mut self) {
+        let pending_public_key = match self.pending_validator_signer.as_ref() {
+            Some(signer) => signer.public_key(),
+            None => return,
+        };
+        let head = match self.chain.head() {
+            Ok(head) => head,
+            Err(_) => return,
+        };
+        if self.validator_key_for_epoch(&head.epoch_id, &head.last_block_hash)
+            != Some(pending_public_key)
+        {
+            return;
+        }
+        let signer = self.pending_validator_signer.take();
+        info!(target: "client", "Switching to validator key {} at height {}", signer.as_ref().unwrap().public_key(), head.height);
+        self.validator_signer = signer;
+        self.doomslug.set_signer(self.validator_signer.clone());
+    }
+
     #[cfg(feature = "sandbox")]
     pub fn sandbox_update_tip(&mut self, height: BlockHeight) -> Result<(), Error> {
         let tip = self.chain.head()?;
@@ -965,6 +1098,14 @@ impl Client {
         parent_hash: &CryptoHash,
         approval: Approval,
     ) -> Result<(), Error> {
+        // Approvals are signed by doomslug, check them against the signing history before they
+        // leave the node.
+        let data = Approval::get_data_for_sig(&approval.inner, approval.target_height);
+        self.update_signing_history(&approval.account_id, |history| {
+            history.record_approval(approval.target_height, hash(&data))
+        })
+        .map_err(Error::Other)?;
+
         let next_epoch_id = self.runtime_adapter.get_epoch_id_from_prev_block(parent_hash)?;
         let next_block_producer =
             self.runtime_adapter.get_block_producer(&next_epoch_id, approval.target_height)?;
@@ -981,6 +1122,24 @@ impl Client {
         Ok(())
     }
 
+    /// Checks and updates the signing history of the validator with `update`, which returns
+    /// whether the history changed. Does nothing if the signing history is disabled.
+    fn update_signing_history(
+        &self,
+        account_id: &AccountId,
+        update: impl FnOnce(&mut SigningHistory) -> Result<bool, SigningHistoryError>,
+    ) -> Result<(), String> {
+        if !self.config.signing_history {
+            return Ok(());
+        }
+        let store = self.chain.store().owned_store();
+        let mut history = get_signing_history(store, account_id).map_err(|err| err.to_string())?;
+        if update(&mut history).map_err(|err| err.to_string())? {
+            save_signing_history(store, account_id, &history).map_err(|err| err.to_string())?;
+        }
+        Ok(())
+    }
+
     /// Gets called when block got accepted.
     /// Send updates over network, update tx pool and notify ourselves if it's time to produce next block.
     /// Blocks are passed in no particular order.
@@ -1035,6 +1194,7 @@ impl Client {
         }
 
         if status.is_new_head() {
+            self.maybe_switch_to_pending_validator_signer();
             self.shards_mgr.update_largest_seen_height(block.header().height());
             let last_final_block = block.header().last_final_block();
             let last_finalized_height = if last_final_block == &CryptoHash::default() {
@@ -1065,6 +1225,13 @@ impl Client {
             }
         }
 
+        #[cfg(feature = "protocol_feature_chunk_state_witness")]
+        {
+            if self.config.produce_chunk_state_witnesses {
+                self.send_chunk_state_witnesses(&block);
+            }
+        }
+
         if let Some(validator_signer) = self.validator_signer.clone() {
             // Reconcile the txpool against the new block *after* we have broadcast it too our peers.
             // This may be slow and we do not want to delay block propagation.
@@ -1633,15 +1800,28 @@ impl Client {
         Ok(false)
     }
 
-    /// Walks through all the ongoing state syncs for future epochs and processes them
+    /// Walks through all the ongoing state syncs for future epochs and processes them, and
+    /// schedules building the states of shards merged in the next epoch once the head is the last
+    /// block of the epoch
     pub fn run_catchup(
         &mut self,
         highest_height_peers: &Vec<FullPeerInfo>,
         state_parts_task_scheduler: &dyn Fn(ApplyStatePartsRequest),
         block_catch_up_task_scheduler: &dyn Fn(BlockCatchUpRequest),
         state_split_scheduler: &dyn Fn(StateSplitRequest),
+        state_merge_scheduler: &dyn Fn(StateMergeRequest),
     ) -> Result<Vec<AcceptedBlock>, Error> {
         let me = &self.validator_signer.as_ref().map(|x| x.validator_id().clone());
+        let head_hash = self.chain.head()?.last_block_hash;
+        if !self.merged_states_in_progress.contains(&head_hash)
+            && self.chain.build_merged_states_preprocessing(
+                me,
+                &head_hash,
+                state_merge_scheduler,
+            )?
+        {
+            self.merged_states_in_progress.insert(head_hash);
+        }
         for (sync_hash, state_sync_info) in self.chain.store().iterate_state_sync_infos() {
             assert_eq!(sync_hash, state_sync_info.epoch_tail_hash);
             let network_adapter1 = self.network_adapter.clone();
@@ -1754,30 +1934,157 @@ impl Client {
         Ok(vec![])
     }
 
+    /// Saves the states of merged shards built for the last block of an epoch, see `run_catchup`,
+    /// and processes the blocks of the next epoch which were waiting for them.
+    pub fn finish_merged_states(
+        &mut self,
+        block_hash: CryptoHash,
+        state_roots: Result<Vec<(ShardUId, StateRoot)>, near_chain::Error>,
+    ) -> Result<Vec<AcceptedBlock>, Error> {
+        self.merged_states_in_progress.remove(&block_hash);
+        let me = &self.validator_signer.as_ref().map(|x| x.validator_id().clone());
+        let mut accepted_blocks = vec![];
+        let mut blocks_missing_chunks = vec![];
+        let mut orphans_missing_chunks = vec![];
+        let mut challenges = vec![];
+        self.chain.build_merged_states_postprocessing(
+            me,
+            &block_hash,
+            state_roots,
+            &mut |accepted_block| accepted_blocks.push(accepted_block),
+            &mut |missing_chunks| blocks_missing_chunks.push(missing_chunks),
+            &mut |orphan_missing_chunks| orphans_missing_chunks.push(orphan_missing_chunks),
+            &mut |challenge| challenges.push(challenge),
+        )?;
+        self.send_challenges(challenges);
+        self.request_missing_chunks(blocks_missing_chunks, orphans_missing_chunks);
+        Ok(accepted_blocks)
+    }
+
     /// When accepting challenge, we verify that it's valid given signature with current validators.
-    pub fn process_challenge(&mut self, _challenge: Challenge) -> Result<(), Error> {
-        // TODO(2445): Enable challenges when they are working correctly.
-        //        if self.challenges.contains_key(&challenge.hash) {
-        //            return Ok(());
-        //        }
-        //        debug!(target: "client", "Received challenge: {:?}", challenge);
-        //        let head = self.chain.head()?;
-        //        if self.runtime_adapter.verify_validator_or_fisherman_signature(
-        //            &head.epoch_id,
-        //            &head.prev_block_hash,
-        //            &challenge.account_id,
-        //            challenge.hash.as_ref(),
-        //            &challenge.signature,
-        //        )? {
-        //            // If challenge is not double sign, we should process it right away to invalidate the chain.
-        //            match challenge.body {
-        //                ChallengeBody::BlockDoubleSign(_) => {}
-        //                _ => {
-        //                    self.chain.process_challenge(&challenge);
-        //                }
-        //            }
-        //            self.challenges.insert(challenge.hash, challenge);
-        //        }
+    pub fn process_challenge(&mut self, challenge: Challenge) -> Result<(), Error> {
+        if self.challenges.contains_key(&challenge.hash) {
+            return Ok(());
+        }
+        let head = self.chain.head()?;
+        let protocol_version = self.runtime_adapter.get_epoch_protocol_version(&head.epoch_id)?;
+        // TODO(2445): Enable other challenges when they are working correctly.
+        if !checked_feature!(
+            "protocol_feature_chunk_state_challenges",
+            ChunkStateChallenges,
+            protocol_version
+        ) || !matches!(challenge.body, ChallengeBody::ChunkStateV2(_))
+        {
+            return Ok(());
+        }
+        debug!(target: "client", "Received challenge: {:?}", challenge);
+        if self.runtime_adapter.verify_validator_or_fisherman_signature(
+            &head.epoch_id,
+            &head.prev_block_hash,
+            &challenge.account_id,
+            challenge.hash.as_ref(),
+            &challenge.signature,
+        )? {
+            // Process the challenge right away to invalidate the chain.
+            self.chain.process_challenge(&challenge);
+            self.challenges.insert(challenge.hash, challenge);
+        }
+        Ok(())
+    }
+
+    /// Sends state witnesses of the chunks produced by this node that are included in the block to
+    /// the block producers of the next block, which validate the next chunks of the shards.
+    #[cfg(feature = "protocol_feature_chunk_state_witness")]
+    fn send_chunk_state_witnesses(&mut self, block: &Block) {
+        let validator_signer = match self.validator_signer.as_ref() {
+            Some(signer) => signer.clone(),
+            None => return,
+        };
+        let me = validator_signer.validator_id();
+        let epoch_id = block.header().epoch_id();
+        let protocol_version =
+            unwrap_or_return!(self.runtime_adapter.get_epoch_protocol_version(epoch_id));
+        if !checked_feature!(
+            "protocol_feature_chunk_state_witness",
+            ChunkStateWitness,
+            protocol_version
+        ) {
+            return;
+        }
+        let next_epoch_id =
+            unwrap_or_return!(self.runtime_adapter.get_epoch_id_from_prev_block(block.hash()));
+        let validators: HashSet<AccountId> = unwrap_or_return!(self
+            .runtime_adapter
+            .get_epoch_block_producers_ordered(&next_epoch_id, block.hash()))
+        .into_iter()
+        .filter(|(validator_stake, is_slashed)| !is_slashed && validator_stake.account_id() != me)
+        .map(|(validator_stake, _)| validator_stake.take_account_id())
+        .collect();
+        for (shard_id, chunk_header) in block.chunks().iter().enumerate() {
+            let shard_id = shard_id as ShardId;
+            if chunk_header.height_included() != block.header().height()
+                || !self.runtime_adapter.cares_about_shard(
+                    Some(me),
+                    block.header().prev_hash(),
+                    shard_id,
+                    true,
+                )
+                || self
+                    .runtime_adapter
+                    .get_chunk_producer(epoch_id, chunk_header.height_created(), shard_id)
+                    .map_or(true, |chunk_producer| &chunk_producer != me)
+            {
+                continue;
+            }
+            let witness = match self.chain.create_chunk_state_witness(block, shard_id) {
+                Ok(inner) => match ChunkStateWitness::new(inner, validator_signer.as_ref()) {
+                    Ok(witness) => witness,
+                    Err(err) => {
+                        error!(target: "client", "Failed to sign state witness of chunk {:?}: {}", chunk_header.chunk_hash(), err);
+                        continue;
+                    }
+                },
+                Err(err) => {
+                    debug!(target: "client", "Can't create state witness of chunk {:?}: {}", chunk_header.chunk_hash(), err);
+                    continue;
+                }
+            };
+            for account_id in validators.iter() {
+                self.network_adapter.do_send(PeerManagerMessageRequest::NetworkRequests(
+                    NetworkRequests::ChunkStateWitness {
+                        account_id: account_id.clone(),
+                        witness: Box::new(witness.clone()),
+                    },
+                ));
+            }
+        }
+    }
+
+    /// Checks and keeps the state witness of a chunk if its shard is not tracked, so that the
+    /// next chunk of the shard is validated against it when its block is processed.
+    #[cfg(feature = "protocol_feature_chunk_state_witness")]
+    pub fn process_chunk_state_witness(&mut self, witness: ChunkStateWitness) -> Result<(), Error> {
+        if !self.config.validate_chunk_state_witnesses {
+            return Ok(());
+        }
+        let block_header = self.chain.get_block_header(&witness.inner.block_hash)?.clone();
+        let protocol_version =
+            self.runtime_adapter.get_epoch_protocol_version(block_header.epoch_id())?;
+        let me = self.validator_signer.as_ref().map(|signer| signer.validator_id().clone());
+        if !checked_feature!(
+            "protocol_feature_chunk_state_witness",
+            ChunkStateWitness,
+            protocol_version
+        ) || self.runtime_adapter.cares_about_shard(
+            me.as_ref(),
+            block_header.prev_hash(),
+            witness.inner.shard_id,
+            true,
+        ) {
+            return Ok(());
+        }
+        debug!(target: "client", "Received state witness of chunk {:?}", witness.inner.chunk_hash);
+        self.chain.process_chunk_state_witness(witness)?;
         Ok(())
     }
 
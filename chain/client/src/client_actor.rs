@@ -10,8 +10,9 @@ use actix_rt::ArbiterHandle;
 use borsh::BorshSerialize;
 use chrono::DateTime;
 use near_chain::chain::{
-    do_apply_chunks, ApplyStatePartsRequest, ApplyStatePartsResponse, BlockCatchUpRequest,
-    BlockCatchUpResponse, StateSplitRequest, StateSplitResponse,
+    do_apply_chunks, do_build_merged_states, ApplyStatePartsRequest, ApplyStatePartsResponse,
+    BlockCatchUpRequest, BlockCatchUpResponse, StateMergeRequest, StateMergeResponse,
+    StateSplitRequest, StateSplitResponse,
 };
 use near_chain::test_utils::format_hash;
 use near_chain::types::{AcceptedBlock, ValidatorInfoIdentifier};
@@ -21,14 +22,15 @@ use near_chain::{
 };
 use near_chain_configs::ClientConfig;
 use near_client_primitives::types::{
-    Error, GetNetworkInfo, NetworkInfoResponse, ShardSyncDownload, ShardSyncStatus, Status,
-    StatusError, StatusSyncInfo, SyncStatus,
+    Error, GetNetworkInfo, LoadPendingValidatorKey, NetworkInfoResponse, ShardSyncDownload,
+    ShardSyncStatus, Status, StatusError, StatusSyncInfo, SyncStatus,
 };
+use near_crypto::PublicKey;
 use near_network::types::{
     NetworkClientMessages, NetworkClientResponses, NetworkInfo, NetworkRequests,
     PeerManagerAdapter, PeerManagerMessageRequest,
 };
-use near_network_primitives::types::ReasonForBan;
+use near_network_primitives::types::{PeerEvidence, ReasonForBan};
 use near_performance_metrics;
 use near_performance_metrics_macros::{perf, perf_with_debug};
 use near_primitives::block_header::ApprovalType;
@@ -40,14 +42,14 @@ use near_primitives::time::{Clock, Utc};
 use near_primitives::types::BlockHeight;
 use near_primitives::unwrap_or_return;
 use near_primitives::utils::{from_timestamp, MaybeValidated};
-use near_primitives::validator_signer::ValidatorSigner;
+use near_primitives::validator_signer::{InMemoryValidatorSigner, ValidatorSigner};
 use near_primitives::version::PROTOCOL_VERSION;
 use near_primitives::views::ValidatorInfo;
 use near_store::db::DBCol::ColStateParts;
 use near_telemetry::TelemetryActor;
 use rand::seq::SliceRandom;
 use rand::{thread_rng, Rng};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use std::thread;
 use std::time::{Duration, Instant};
@@ -86,6 +88,7 @@ pub struct ClientActor {
     state_parts_task_scheduler: Box<dyn Fn(ApplyStatePartsRequest)>,
     block_catch_up_scheduler: Box<dyn Fn(BlockCatchUpRequest)>,
     state_split_scheduler: Box<dyn Fn(StateSplitRequest)>,
+    state_merge_scheduler: Box<dyn Fn(StateMergeRequest)>,
     state_parts_client_arbiter: Arbiter,
 
     #[cfg(feature = "sandbox")]
@@ -166,6 +169,8 @@ impl ClientActor {
                 sent_bytes_per_sec: 0,
                 known_producers: vec![],
                 peer_counter: 0,
+                peer_scores: HashMap::new(),
+                private_peers: HashSet::new(),
             },
             last_validator_announce_time: None,
             info_helper,
@@ -181,6 +186,9 @@ impl ClientActor {
                 sync_jobs_actor_addr.clone(),
             ),
             state_split_scheduler: create_sync_job_scheduler::<StateSplitRequest>(
+                sync_jobs_actor_addr.clone(),
+            ),
+            state_merge_scheduler: create_sync_job_scheduler::<StateMergeRequest>(
                 sync_jobs_actor_addr,
             ),
             state_parts_client_arbiter: state_parts_arbiter,
@@ -223,6 +231,11 @@ impl Actor for ClientActor {
             self.block_production_started = true;
         }
 
+        // Pick up the key of an unfinished validator key rotation.
+        if let Err(err) = self.load_pending_validator_key() {
+            error!(target: "client", "Failed to load pending validator key: {}", err);
+        }
+
         // Start triggers
         self.schedule_triggers(ctx);
 
@@ -555,19 +568,27 @@ impl Handler<NetworkClientMessages> for ClientActor {
                 NetworkClientResponses::NoResponse
             }
             NetworkClientMessages::PartialEncodedChunkResponse(response) => {
-                if let Ok(accepted_blocks) =
-                    self.client.process_partial_encoded_chunk_response(response)
-                {
-                    self.process_accepted_blocks(accepted_blocks);
+                match self.client.process_partial_encoded_chunk_response(response) {
+                    Ok(accepted_blocks) => self.process_accepted_blocks(accepted_blocks),
+                    Err(err) if err.is_bad_data() => {
+                        warn!(target: "client", "Received invalid chunk response: {}", err);
+                        return NetworkClientResponses::InvalidMessage;
+                    }
+                    Err(_) => {}
                 }
                 NetworkClientResponses::NoResponse
             }
             NetworkClientMessages::PartialEncodedChunk(partial_encoded_chunk) => {
-                if let Ok(accepted_blocks) = self
+                match self
                     .client
                     .process_partial_encoded_chunk(MaybeValidated::from(partial_encoded_chunk))
                 {
-                    self.process_accepted_blocks(accepted_blocks);
+                    Ok(accepted_blocks) => self.process_accepted_blocks(accepted_blocks),
+                    Err(err) if err.is_bad_data() => {
+                        warn!(target: "client", "Received invalid chunk: {}", err);
+                        return NetworkClientResponses::InvalidMessage;
+                    }
+                    Err(_) => {}
                 }
                 NetworkClientResponses::NoResponse
             }
@@ -576,6 +597,10 @@ impl Handler<NetworkClientMessages> for ClientActor {
                     Ok(accepted_blocks) => self.process_accepted_blocks(accepted_blocks),
                     // Unknown chunk is normal if we get parts before the header
                     Err(Error::Chunk(near_chunks::Error::UnknownChunk)) => (),
+                    Err(err) if err.is_bad_data() => {
+                        warn!(target: "client", "Received invalid forwarded chunk: {}", err);
+                        return NetworkClientResponses::InvalidMessage;
+                    }
                     Err(err) => {
                         error!(target: "client", "Error processing forwarded chunk: {}", err)
                     }
@@ -591,6 +616,16 @@ impl Handler<NetworkClientMessages> for ClientActor {
                 }
                 NetworkClientResponses::NoResponse
             }
+            #[cfg(feature = "protocol_feature_chunk_state_witness")]
+            NetworkClientMessages::ChunkStateWitness(witness) => {
+                match self.client.process_chunk_state_witness(*witness) {
+                    Ok(_) => {}
+                    Err(err) => {
+                        error!(target: "client", "Error processing chunk state witness: {}", err);
+                    }
+                }
+                NetworkClientResponses::NoResponse
+            }
             NetworkClientMessages::NetworkInfo(network_info) => {
                 self.network_info = network_info;
                 NetworkClientResponses::NoResponse
@@ -686,6 +721,16 @@ impl Handler<Status> for ClientActor {
     }
 }
 
+impl Handler<LoadPendingValidatorKey> for ClientActor {
+    type Result = Result<Option<PublicKey>, String>;
+
+    #[perf]
+    fn handle(&mut self, _msg: LoadPendingValidatorKey, _ctx: &mut Context<Self>) -> Self::Result {
+        let _d = delay_detector::DelayDetector::new(|| "client load pending validator key".into());
+        self.load_pending_validator_key()
+    }
+}
+
 impl Handler<GetNetworkInfo> for ClientActor {
     type Result = Result<NetworkInfoResponse, String>;
 
@@ -694,15 +739,26 @@ impl Handler<GetNetworkInfo> for ClientActor {
         let _d = delay_detector::DelayDetector::new(|| "client get network info".into());
         self.check_triggers(ctx);
 
+        // Addresses of private peers, such as validators behind this sentry node, aren't exposed.
+        let private_peers = &self.network_info.private_peers;
         Ok(NetworkInfoResponse {
             connected_peers: (self.network_info.connected_peers.iter())
-                .map(|fpi| fpi.peer_info.clone())
+                .map(|fpi| {
+                    let mut peer_info = fpi.peer_info.clone();
+                    if private_peers.contains(&peer_info.id) {
+                        peer_info.addr = None;
+                    }
+                    peer_info
+                })
                 .collect(),
             num_connected_peers: self.network_info.num_connected_peers,
             peer_max_count: self.network_info.peer_max_count,
             sent_bytes_per_sec: self.network_info.sent_bytes_per_sec,
             received_bytes_per_sec: self.network_info.received_bytes_per_sec,
-            known_producers: self.network_info.known_producers.clone(),
+            known_producers: (self.network_info.known_producers.iter())
+                .filter(|producer| !private_peers.contains(&producer.peer_id))
+                .cloned()
+                .collect(),
         })
     }
 }
@@ -746,11 +802,11 @@ impl ClientActor {
             debug!(target: "client", "Sending announce account for {}", validator_signer.validator_id());
             self.last_validator_announce_time = Some(now);
 
-            let signature = validator_signer.sign_account_announce(
+            let signature = unwrap_or_return!(validator_signer.sign_account_announce(
                 validator_signer.validator_id(),
                 &self.node_id,
                 &next_epoch_id,
-            );
+            ));
             self.network_adapter.do_send(PeerManagerMessageRequest::NetworkRequests(
                 NetworkRequests::AnnounceAccount(AnnounceAccount {
                     account_id: validator_signer.validator_id().clone(),
@@ -1074,6 +1130,9 @@ impl ClientActor {
             Ok(_) => {}
             Err(ref err) if err.is_bad_data() => {
                 warn!(target: "client", "receive bad block: {}", err);
+                self.network_adapter.do_send(PeerManagerMessageRequest::NetworkRequests(
+                    NetworkRequests::ReportPeer { peer_id, evidence: PeerEvidence::InvalidMessage },
+                ));
             }
             Err(ref err) if err.is_error() => {
                 if let near_chain::ErrorKind::DBNotFoundErr(msg) = err.kind() {
@@ -1253,6 +1312,7 @@ impl ClientActor {
             &self.state_parts_task_scheduler,
             &self.block_catch_up_scheduler,
             &self.state_split_scheduler,
+            &self.state_merge_scheduler,
         ) {
             Ok(accepted_blocks) => {
                 self.process_accepted_blocks(accepted_blocks);
@@ -1338,7 +1398,8 @@ impl ClientActor {
                 &mut self.client.sync_status,
                 &mut self.client.chain,
                 highest_height,
-                &self.network_info.highest_height_peers
+                &self.network_info.highest_height_peers,
+                &self.network_info.peer_scores
             ));
             // Only body / state sync if header height is close to the latest.
             let header_head = unwrap_or_run_later!(self.client.chain.header_head());
@@ -1354,7 +1415,8 @@ impl ClientActor {
                         &mut self.client.sync_status,
                         &mut self.client.chain,
                         highest_height,
-                        &self.network_info.highest_height_peers
+                        &self.network_info.highest_height_peers,
+                        &self.network_info.peer_scores
                     ))
                 }
                 _ => false,
@@ -1461,6 +1523,21 @@ impl ClientActor {
         });
     }
 
+    /// Loads the new validator key from `pending_validator_key_path`, if the file exists, so that
+    /// the client switches to it once it takes effect. Returns the pending public key.
+    fn load_pending_validator_key(&mut self) -> Result<Option<PublicKey>, String> {
+        let path = match self.client.config.pending_validator_key_path.as_ref() {
+            Some(path) if path.exists() => path,
+            _ => return Ok(self.client.pending_validator_signer.as_ref().map(|x| x.public_key())),
+        };
+        let signer = InMemoryValidatorSigner::try_from_file(path)
+            .map_err(|err| format!("Failed to read {}: {}", path.display(), err))?;
+        self.client
+            .set_pending_validator_signer(Arc::new(signer))
+            .map_err(|err| err.to_string())?;
+        Ok(self.client.pending_validator_signer.as_ref().map(|x| x.public_key()))
+    }
+
     /// Periodically log summary.
     fn log_summary(&self, ctx: &mut Context<Self>) {
         near_performance_metrics::actix::run_later(
@@ -1504,6 +1581,7 @@ impl ClientActor {
                     .get_validator_info(epoch_identifier)
                     .map(get_validator_epoch_stats)
                     .unwrap_or_default();
+                act.info_helper.set_validator_signer(act.client.validator_signer.clone());
                 act.info_helper.info(
                     act.client.chain.store().get_genesis_height(),
                     &head,
@@ -1662,6 +1740,31 @@ impl Handler<StateSplitResponse> for ClientActor {
     }
 }
 
+impl Handler<StateMergeRequest> for SyncJobsActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: StateMergeRequest, _: &mut Self::Context) -> Self::Result {
+        let state_roots = do_build_merged_states(&msg);
+
+        self.client_addr.do_send(StateMergeResponse { block_hash: msg.block_hash, state_roots });
+    }
+}
+
+impl Handler<StateMergeResponse> for ClientActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: StateMergeResponse, _: &mut Self::Context) -> Self::Result {
+        match self.client.finish_merged_states(msg.block_hash, msg.state_roots) {
+            Ok(accepted_blocks) => {
+                self.process_accepted_blocks(accepted_blocks);
+            }
+            Err(err) => {
+                error!(target: "client", "Error occurred while building merged states at {}: {:?}", msg.block_hash, err);
+            }
+        }
+    }
+}
+
 /// Returns random seed sampled from the current thread
 pub fn random_seed_from_thread() -> RngSeed {
     let mut rng_seed: RngSeed = [0; 32];
@@ -0,0 +1,27 @@
+//! Fisherman mode lets a node which doesn't validate watch tracked shards for misbehavior.
+//! Chunks of tracked shards are verified while their blocks are processed, i.e. their proofs
+//! and, once the next chunk of the shard is included, their state transition; every challenge
+//! the node submits for them is counted in metrics and logged with the `fisherman` target.
+
+use borsh::BorshDeserialize;
+use tracing::info;
+
+use near_primitives::block::BlockHeader;
+use near_primitives::challenge::{Challenge, ChallengeBody};
+
+use crate::metrics;
+
+/// Records a challenge submitted by the fisherman.
+pub(crate) fn record_challenge(challenge: &Challenge) {
+    let (kind, block_hash) = match &challenge.body {
+        ChallengeBody::BlockDoubleSign(block_double_sign) => {
+            ("block_double_sign", &block_double_sign.left_block_header)
+        }
+        ChallengeBody::ChunkProofs(chunk_proofs) => ("chunk_proofs", &chunk_proofs.block_header),
+        ChallengeBody::ChunkState(chunk_state) => ("chunk_state", &chunk_state.block_header),
+        ChallengeBody::ChunkStateV2(chunk_state) => ("chunk_state", &chunk_state.block_header),
+    };
+    let block_hash = BlockHeader::try_from_slice(block_hash).map(|header| *header.hash());
+    metrics::FISHERMAN_CHALLENGES_TOTAL.with_label_values(&[kind]).inc();
+    info!(target: "fisherman", "Detected {} in block {:?}, submitting challenge {}", kind, block_hash, challenge.hash);
+}
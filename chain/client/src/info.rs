@@ -7,7 +7,8 @@ use near_primitives::block::Tip;
 use near_primitives::network::PeerId;
 use near_primitives::serialize::to_base;
 use near_primitives::telemetry::{
-    TelemetryAgentInfo, TelemetryChainInfo, TelemetryInfo, TelemetrySystemInfo,
+    TelemetryAgentInfo, TelemetryChainInfo, TelemetryInfo, TelemetrySyncInfo, TelemetrySystemInfo,
+    TelemetryValidatorInfo,
 };
 use near_primitives::time::{Clock, Instant};
 use near_primitives::types::{AccountId, BlockHeight, EpochHeight, Gas, NumBlocks, ShardId};
@@ -19,7 +20,7 @@ use std::cmp::min;
 use std::fmt::Write;
 use std::sync::Arc;
 use sysinfo::{get_current_pid, set_open_files_limit, Pid, ProcessExt, System, SystemExt};
-use tracing::info;
+use tracing::{info, warn};
 
 const TERAGAS: f64 = 1_000_000_000_000_f64;
 
@@ -73,6 +74,11 @@ impl InfoHelper {
         }
     }
 
+    /// Updates the key telemetry is signed with after the validator key was rotated.
+    pub fn set_validator_signer(&mut self, validator_signer: Option<Arc<dyn ValidatorSigner>>) {
+        self.validator_signer = validator_signer;
+    }
+
     pub fn chunk_processed(&mut self, shard_id: ShardId, gas_used: Gas) {
         metrics::TGAS_USAGE_HIST
             .with_label_values(&[&format!("{}", shard_id)])
@@ -163,7 +169,7 @@ impl InfoHelper {
         );
 
         let (cpu_usage, memory_usage) = proc_info.unwrap_or_default();
-        let is_validator = validator_info.map(|v| v.is_validator).unwrap_or_default();
+        let is_validator = validator_info.as_ref().map(|v| v.is_validator).unwrap_or_default();
         (metrics::IS_VALIDATOR.set(is_validator as i64));
         (metrics::RECEIVED_BYTES_PER_SECOND.set(network_info.received_bytes_per_sec as i64));
         (metrics::SENT_BYTES_PER_SECOND.set(network_info.sent_bytes_per_sec as i64));
@@ -222,14 +228,24 @@ impl InfoHelper {
                 latest_block_height: head.height,
                 num_peers: network_info.num_connected_peers,
             },
+            validator: validator_info
+                .map(|v| TelemetryValidatorInfo { num_validators: v.num_validators, epoch_height }),
+            sync: match sync_status {
+                SyncStatus::HeaderSync { current_height, highest_height }
+                | SyncStatus::BodySync { current_height, highest_height } => TelemetrySyncInfo {
+                    current_height: Some(*current_height),
+                    highest_height: Some(*highest_height),
+                },
+                _ => TelemetrySyncInfo::default(),
+            },
         };
         // Sign telemetry if there is a signer present.
-        let content = if let Some(vs) = self.validator_signer.as_ref() {
+        let validator_signature = self.validator_signer.as_ref().and_then(|vs| {
             vs.sign_telemetry(&info)
-        } else {
-            serde_json::to_value(&info).expect("Telemetry must serialize to json")
-        };
-        telemetry(&self.telemetry_actor, content);
+                .map_err(|err| warn!(target: "stats", "Failed to sign telemetry: {}", err))
+                .ok()
+        });
+        telemetry(&self.telemetry_actor, info, validator_signature);
     }
 }
 
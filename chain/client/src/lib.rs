@@ -3,8 +3,9 @@ pub use near_client_primitives::types::{
     GetChunk, GetExecutionOutcome, GetExecutionOutcomeResponse, GetExecutionOutcomesForBlock,
     GetGasPrice, GetNetworkInfo, GetNextLightClientBlock, GetProtocolConfig, GetReceipt,
     GetStateChanges, GetStateChangesInBlock, GetStateChangesWithCauseInBlock,
-    GetStateChangesWithCauseInBlockForTrackedShards, GetValidatorInfo, GetValidatorOrdered, Query,
-    QueryError, Status, StatusResponse, SyncStatus, TxStatus, TxStatusError,
+    GetStateChangesWithCauseInBlockForTrackedShards, GetValidatorInfo, GetValidatorOrdered,
+    GetValidatorPerformance, LoadPendingValidatorKey, Query, QueryError, Status, StatusResponse,
+    SyncStatus, TxStatus, TxStatusError,
 };
 
 pub use crate::client::Client;
@@ -16,8 +17,10 @@ pub use crate::view_client::{start_view_client, ViewClientActor};
 mod chunks_delay_tracker;
 mod client;
 mod client_actor;
+mod fisherman;
 mod info;
 mod metrics;
+pub mod network_simulator;
 pub mod sync;
 pub mod test_utils;
 #[cfg(test)]
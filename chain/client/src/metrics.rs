@@ -148,3 +148,11 @@ pub static CHUNK_SKIPPED_TOTAL: Lazy<IntCounterVec> = Lazy::new(|| {
     )
     .unwrap()
 });
+pub static FISHERMAN_CHALLENGES_TOTAL: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_fisherman_challenges_total",
+        "Number of challenges submitted in fisherman mode",
+        &["kind"],
+    )
+    .unwrap()
+});
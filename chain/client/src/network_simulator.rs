@@ -0,0 +1,479 @@
+//! Deterministic in-process network between the clients of a `TestEnv`.
+//!
+//! Every client of `TestEnv` sends its messages to a `MockPeerManagerAdapter`. The simulator
+//! drains those queues, decides for every message when it reaches which nodes, and delivers it by
+//! calling into the receiving `Client` directly, the way `ClientActor` and `ViewClientActor` would.
+//! Time is virtual: it only advances when the simulator delivers the next message, so there are
+//! no sleeps, and all randomness (latency jitter, which reorders messages, and loss) comes from a
+//! single seeded generator. Runs with the same seed deliver the same messages in the same order.
+//! While the simulator exists, `Clock::utc()` and `Clock::instant()` return the virtual time of
+//! the current thread, starting from the genesis time, so the blocks produced are identical too
+//! as long as the genesis time of the `TestEnv` is fixed.
+//!
+//! NOTES:
+//! - Only messages exchanged by clients are simulated: blocks, approvals, block and header
+//!   requests, and chunk parts. Other requests are dropped and counted in `SimulatorStats`.
+//! - Timers of the clients aren't driven, tests produce blocks explicitly with `produce_block`.
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use tracing::debug;
+
+use near_chain::Provenance;
+use near_crypto::{KeyType, SecretKey};
+use near_network::types::{NetworkRequests, PeerManagerMessageRequest};
+use near_primitives::block::{Block, BlockHeader};
+use near_primitives::block_header::ApprovalType;
+use near_primitives::hash::{hash, CryptoHash};
+use near_primitives::network::PeerId;
+use near_primitives::sharding::PartialEncodedChunk;
+use near_primitives::time::MockClockGuard;
+use near_primitives::types::{AccountId, BlockHeight};
+use near_primitives::utils::MaybeValidated;
+
+use crate::sync::MAX_BLOCK_HEADERS;
+use crate::test_utils::TestEnv;
+use crate::Client;
+
+/// Behavior of a link between two nodes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LinkConfig {
+    /// Minimal time it takes a message to arrive.
+    pub latency: Duration,
+    /// Maximal random delay added to `latency`. Messages sent over links with jitter may arrive
+    /// in a different order than they were sent.
+    pub jitter: Duration,
+    /// Probability for a message to be lost, between 0 and 1.
+    pub loss: f64,
+}
+
+impl Default for LinkConfig {
+    fn default() -> Self {
+        Self { latency: Duration::from_millis(10), jitter: Duration::from_millis(0), loss: 0.0 }
+    }
+}
+
+/// Counts of messages handled by the simulator.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SimulatorStats {
+    /// Messages scheduled for delivery, once per receiver.
+    pub sent: u64,
+    /// Messages delivered to their receiver.
+    pub delivered: u64,
+    /// Messages lost on a link or dropped by a partition.
+    pub lost: u64,
+    /// Requests the simulator doesn't handle.
+    pub unsupported: u64,
+}
+
+/// Message on its way to a node.
+#[derive(Debug)]
+enum Message {
+    Request(NetworkRequests),
+    /// Response to a `BlockRequest`.
+    RequestedBlock(Block),
+    /// Response to a `BlockHeadersRequest`.
+    Headers(Vec<BlockHeader>),
+}
+
+#[derive(Debug)]
+struct Envelope {
+    from: usize,
+    to: usize,
+    message: Message,
+}
+
+pub struct NetworkSimulator {
+    pub env: TestEnv,
+    now: Duration,
+    /// Mocks `Clock` with `start_utc` and `start_instant` advanced by `now`.
+    clock: MockClockGuard,
+    start_utc: DateTime<Utc>,
+    start_instant: Instant,
+    rng: StdRng,
+    default_link: LinkConfig,
+    links: HashMap<(usize, usize), LinkConfig>,
+    /// Group of every node. Messages are only delivered between nodes in the same group.
+    groups: Vec<usize>,
+    /// Messages in flight, by arrival time and order of sending.
+    queue: BTreeMap<(Duration, u64), Envelope>,
+    next_seq: u64,
+    /// Nodes waiting for responses to their chunk requests.
+    route_back: HashMap<CryptoHash, usize>,
+    account_ids: Vec<AccountId>,
+    peer_ids: Vec<PeerId>,
+    stats: SimulatorStats,
+}
+
+impl NetworkSimulator {
+    pub fn new(env: TestEnv, seed: u64) -> Self {
+        let num_nodes = env.clients.len();
+        let account_ids: Vec<AccountId> =
+            (0..num_nodes).map(|i| env.get_client_id(i).clone()).collect();
+        let peer_ids = account_ids
+            .iter()
+            .map(|account_id| {
+                PeerId::new(
+                    SecretKey::from_seed(KeyType::ED25519, account_id.as_ref()).public_key(),
+                )
+            })
+            .collect();
+        let clock = MockClockGuard::default();
+        let start_utc = env.clients[0].chain.genesis().timestamp();
+        let start_instant = Instant::now();
+        clock.set_utc(start_utc);
+        clock.set_instant(start_instant);
+        Self {
+            env,
+            now: Duration::from_millis(0),
+            clock,
+            start_utc,
+            start_instant,
+            rng: StdRng::seed_from_u64(seed),
+            default_link: LinkConfig::default(),
+            links: HashMap::new(),
+            groups: vec![0; num_nodes],
+            queue: BTreeMap::new(),
+            next_seq: 0,
+            route_back: HashMap::new(),
+            account_ids,
+            peer_ids,
+            stats: SimulatorStats::default(),
+        }
+    }
+
+    /// Virtual time elapsed since the simulator was created.
+    pub fn now(&self) -> Duration {
+        self.now
+    }
+
+    /// Virtual wall clock time, as returned by `Clock::utc()`.
+    pub fn utc(&self) -> DateTime<Utc> {
+        self.start_utc + chrono::Duration::from_std(self.now).unwrap()
+    }
+
+    fn set_now(&mut self, now: Duration) {
+        self.now = now;
+        self.clock.set_utc(self.utc());
+        self.clock.set_instant(self.start_instant + now);
+    }
+
+    pub fn stats(&self) -> &SimulatorStats {
+        &self.stats
+    }
+
+    pub fn peer_id(&self, node: usize) -> &PeerId {
+        &self.peer_ids[node]
+    }
+
+    /// Sets the behavior of links without one of their own.
+    pub fn set_default_link(&mut self, link: LinkConfig) {
+        self.default_link = link;
+    }
+
+    /// Sets the behavior of the link from `from` to `to`, in this direction only.
+    pub fn set_link(&mut self, from: usize, to: usize, link: LinkConfig) {
+        self.links.insert((from, to), link);
+    }
+
+    /// Splits the nodes into `groups` which can't reach each other. Nodes not listed form a
+    /// group of their own. Messages already in flight between groups are lost.
+    pub fn partition(&mut self, groups: &[&[usize]]) {
+        self.groups = vec![0; self.groups.len()];
+        for (group, nodes) in groups.iter().enumerate() {
+            for &node in nodes.iter() {
+                self.groups[node] = group + 1;
+            }
+        }
+    }
+
+    /// Reconnects all nodes.
+    pub fn heal(&mut self) {
+        self.groups = vec![0; self.groups.len()];
+    }
+
+    /// Produces the block at `height` by whichever node is its producer, and sends it out.
+    /// Returns the index of the producer, if any node produced the block.
+    pub fn produce_block(&mut self, height: BlockHeight) -> Option<usize> {
+        for node in 0..self.env.clients.len() {
+            let block = match self.env.clients[node].produce_block(height) {
+                Ok(Some(block)) => block,
+                Ok(None) => continue,
+                Err(err) => {
+                    debug!(target: "simulator", "node {} failed to produce block at {}: {}", node, height, err);
+                    continue;
+                }
+            };
+            self.broadcast(node, NetworkRequests::Block { block: block.clone() });
+            self.env.process_block(node, block, Provenance::PRODUCED);
+            self.collect();
+            return Some(node);
+        }
+        None
+    }
+
+    /// Delivers the next message in flight and advances the time to its arrival.
+    /// Returns false if there are no messages left.
+    pub fn step(&mut self) -> bool {
+        self.collect();
+        let key = match self.queue.keys().next() {
+            Some(key) => *key,
+            None => return false,
+        };
+        let envelope = self.queue.remove(&key).unwrap();
+        self.set_now(key.0);
+        if self.groups[envelope.from] != self.groups[envelope.to] {
+            self.stats.lost += 1;
+        } else {
+            self.stats.delivered += 1;
+            self.deliver(envelope);
+        }
+        self.collect();
+        true
+    }
+
+    /// Delivers all messages arriving within `duration`, and advances the time by it.
+    pub fn run_for(&mut self, duration: Duration) {
+        let until = self.now + duration;
+        self.collect();
+        while self.queue.keys().next().map_or(false, |(time, _)| *time <= until) {
+            self.step();
+        }
+        self.set_now(until);
+    }
+
+    /// Delivers messages until none are left in flight.
+    pub fn run_until_idle(&mut self) {
+        while self.step() {}
+    }
+
+    /// Takes the messages sent by all nodes, in the order of the nodes.
+    fn collect(&mut self) {
+        for node in 0..self.env.network_adapters.len() {
+            while let Some(request) = self.env.network_adapters[node].pop() {
+                match request {
+                    PeerManagerMessageRequest::NetworkRequests(request) => {
+                        self.route(node, request)
+                    }
+                    _ => self.stats.unsupported += 1,
+                }
+            }
+        }
+    }
+
+    /// Schedules a request of `from` to the nodes it is meant for.
+    fn route(&mut self, from: usize, request: NetworkRequests) {
+        match &request {
+            NetworkRequests::Block { .. } => self.broadcast(from, request),
+            NetworkRequests::Approval { approval_message } => {
+                let to = self.node_of_account(&approval_message.target);
+                self.send_to(from, to, Message::Request(request));
+            }
+            NetworkRequests::BlockRequest { peer_id, .. }
+            | NetworkRequests::BlockHeadersRequest { peer_id, .. } => {
+                let to = self.peer_ids.iter().position(|id| id == peer_id);
+                self.send_to(from, to, Message::Request(request));
+            }
+            NetworkRequests::PartialEncodedChunkRequest { target, .. } => {
+                let to = match &target.account_id {
+                    Some(account_id) => self.node_of_account(account_id),
+                    None => self.random_peer(from),
+                };
+                self.send_to(from, to, Message::Request(request));
+            }
+            NetworkRequests::PartialEncodedChunkResponse { route_back, .. } => {
+                let to = self.route_back.remove(route_back);
+                self.send_to(from, to, Message::Request(request));
+            }
+            NetworkRequests::PartialEncodedChunkMessage { account_id, .. }
+            | NetworkRequests::PartialEncodedChunkForward { account_id, .. } => {
+                let to = self.node_of_account(account_id);
+                self.send_to(from, to, Message::Request(request));
+            }
+            _ => {
+                debug!(target: "simulator", "dropping unsupported request {}", request.as_ref());
+                self.stats.unsupported += 1;
+            }
+        }
+    }
+
+    fn broadcast(&mut self, from: usize, request: NetworkRequests) {
+        for to in 0..self.peer_ids.len() {
+            if to != from {
+                self.send_to(from, Some(to), Message::Request(request.clone()));
+            }
+        }
+    }
+
+    fn send_to(&mut self, from: usize, to: Option<usize>, message: Message) {
+        let to = match to {
+            Some(to) if to != from => to,
+            _ => {
+                self.stats.unsupported += 1;
+                return;
+            }
+        };
+        self.stats.sent += 1;
+        let link = self.links.get(&(from, to)).unwrap_or(&self.default_link).clone();
+        if link.loss > 0.0 && self.rng.gen::<f64>() < link.loss {
+            self.stats.lost += 1;
+            return;
+        }
+        let jitter = match link.jitter.as_micros() as u64 {
+            0 => 0,
+            jitter => self.rng.gen_range(0, jitter + 1),
+        };
+        let arrival = self.now + link.latency + Duration::from_micros(jitter);
+        self.queue.insert((arrival, self.next_seq), Envelope { from, to, message });
+        self.next_seq += 1;
+    }
+
+    fn node_of_account(&self, account_id: &AccountId) -> Option<usize> {
+        self.account_ids.iter().position(|id| id == account_id)
+    }
+
+    fn random_peer(&mut self, node: usize) -> Option<usize> {
+        let num_nodes = self.peer_ids.len();
+        if num_nodes < 2 {
+            return None;
+        }
+        let other = self.rng.gen_range(0, num_nodes - 1);
+        Some(if other >= node { other + 1 } else { other })
+    }
+
+    fn deliver(&mut self, envelope: Envelope) {
+        let Envelope { from, to, message } = envelope;
+        match message {
+            Message::RequestedBlock(block) => self.receive_block(from, to, block, Provenance::SYNC),
+            Message::Headers(headers) => {
+                if let Err(err) = self.env.clients[to].sync_block_headers(headers) {
+                    debug!(target: "simulator", "node {} refused headers: {}", to, err);
+                }
+            }
+            Message::Request(request) => self.receive_request(from, to, request),
+        }
+    }
+
+    fn receive_request(&mut self, from: usize, to: usize, request: NetworkRequests) {
+        let client = &mut self.env.clients[to];
+        let accepted_blocks = match request {
+            NetworkRequests::Block { block } => {
+                return self.receive_block(from, to, block, Provenance::NONE);
+            }
+            NetworkRequests::Approval { approval_message } => {
+                let peer_id = self.peer_ids[from].clone();
+                client.collect_block_approval(
+                    &approval_message.approval,
+                    ApprovalType::PeerApproval(peer_id),
+                );
+                return;
+            }
+            NetworkRequests::BlockRequest { hash, .. } => {
+                if let Ok(block) = client.chain.get_block(&hash) {
+                    let block = block.clone();
+                    self.send_to(to, Some(from), Message::RequestedBlock(block));
+                }
+                return;
+            }
+            NetworkRequests::BlockHeadersRequest { hashes, .. } => {
+                let headers = retrieve_headers(client, &hashes);
+                self.send_to(to, Some(from), Message::Headers(headers));
+                return;
+            }
+            NetworkRequests::PartialEncodedChunkRequest { request, .. } => {
+                let route_back = hash(&self.next_seq.to_le_bytes());
+                self.next_seq += 1;
+                self.route_back.insert(route_back, from);
+                client.shards_mgr.process_partial_encoded_chunk_request(
+                    request,
+                    route_back,
+                    client.chain.mut_store(),
+                );
+                return;
+            }
+            NetworkRequests::PartialEncodedChunkResponse { response, .. } => {
+                client.process_partial_encoded_chunk_response(response)
+            }
+            NetworkRequests::PartialEncodedChunkMessage { partial_encoded_chunk, .. } => client
+                .process_partial_encoded_chunk(MaybeValidated::from(PartialEncodedChunk::from(
+                    partial_encoded_chunk,
+                ))),
+            NetworkRequests::PartialEncodedChunkForward { forward, .. } => {
+                client.process_partial_encoded_chunk_forward(forward)
+            }
+            _ => return,
+        };
+        match accepted_blocks {
+            Ok(accepted_blocks) => {
+                for block in accepted_blocks {
+                    client.on_block_accepted(block.hash, block.status, block.provenance);
+                }
+            }
+            Err(err) => debug!(target: "simulator", "node {} failed to process chunk: {}", to, err),
+        }
+    }
+
+    /// Processes a block the way `ClientActor` does: valid blocks are rebroadcast, and the
+    /// parents of orphans are requested from the sender.
+    fn receive_block(&mut self, from: usize, to: usize, block: Block, provenance: Provenance) {
+        let client = &mut self.env.clients[to];
+        let prev_hash = *block.header().prev_hash();
+        let (accepted_blocks, result) =
+            client.process_block(MaybeValidated::from(block.clone()), provenance);
+        for accepted_block in accepted_blocks {
+            client.on_block_accepted(
+                accepted_block.hash,
+                accepted_block.status,
+                accepted_block.provenance,
+            );
+        }
+        match result {
+            Ok(_) => {
+                if provenance == Provenance::NONE {
+                    client.rebroadcast_block(&block);
+                }
+            }
+            Err(err) => match err.kind() {
+                near_chain::ErrorKind::Orphan => {
+                    if !client.chain.is_orphan(&prev_hash)
+                        && !client.chain.block_exists(&prev_hash).unwrap_or(true)
+                    {
+                        let request = NetworkRequests::BlockRequest {
+                            hash: prev_hash,
+                            peer_id: self.peer_ids[from].clone(),
+                        };
+                        self.send_to(to, Some(from), Message::Request(request));
+                    }
+                }
+                _ => {
+                    debug!(target: "simulator", "node {} refused block {}: {}", to, block.hash(), err)
+                }
+            },
+        }
+    }
+}
+
+/// Headers following the last of `hashes` known to `client`, as served by `ViewClientActor`.
+fn retrieve_headers(client: &mut Client, hashes: &[CryptoHash]) -> Vec<BlockHeader> {
+    let header = match client.chain.find_common_header(hashes) {
+        Some(header) => header,
+        None => return vec![],
+    };
+    let max_height = match client.chain.header_head() {
+        Ok(header_head) => header_head.height,
+        Err(_) => return vec![],
+    };
+    let mut headers = vec![];
+    for height in header.height() + 1..=max_height {
+        if let Ok(header) = client.chain.get_header_by_height(height) {
+            headers.push(header.clone());
+            if headers.len() >= MAX_BLOCK_HEADERS as usize {
+                break;
+            }
+        }
+    }
+    headers
+}
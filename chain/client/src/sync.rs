@@ -9,7 +9,7 @@ use std::time::Duration as TimeDuration;
 use ansi_term::Color::{Purple, Yellow};
 use chrono::{DateTime, Duration};
 use futures::{future, FutureExt};
-use rand::seq::{IteratorRandom, SliceRandom};
+use rand::seq::SliceRandom;
 use rand::{thread_rng, Rng};
 use tracing::{debug, error, info, warn};
 
@@ -31,7 +31,7 @@ use near_client_primitives::types::{
     DownloadStatus, ShardSyncDownload, ShardSyncStatus, SyncStatus,
 };
 use near_network::types::PeerManagerMessageRequest;
-use near_network_primitives::types::AccountOrPeerIdOrHash;
+use near_network_primitives::types::{AccountOrPeerIdOrHash, PeerEvidence, PeerReputation};
 use near_primitives::shard_layout::ShardUId;
 
 /// Maximum number of block headers send over the network.
@@ -53,6 +53,21 @@ pub const MAX_PENDING_PART: u64 = MAX_STATE_PART_REQUEST * 10000;
 
 pub const NS_PER_SECOND: u128 = 1_000_000_000;
 
+/// Chooses a random peer, preferring peers with better reputation.
+fn choose_peer<'a>(
+    peers: impl Iterator<Item = &'a FullPeerInfo>,
+    peer_scores: &HashMap<PeerId, f64>,
+) -> Option<&'a FullPeerInfo> {
+    let peers: Vec<&FullPeerInfo> = peers.collect();
+    peers
+        .choose_weighted(&mut thread_rng(), |peer| {
+            let score = peer_scores.get(&peer.peer_info.id).copied().unwrap_or(0.0);
+            PeerReputation::selection_weight(score)
+        })
+        .ok()
+        .copied()
+}
+
 /// Helper to keep track of the Epoch Sync
 // TODO #3488
 #[allow(dead_code)]
@@ -167,6 +182,7 @@ impl HeaderSync {
         chain: &mut Chain,
         highest_height: BlockHeight,
         highest_height_peers: &Vec<FullPeerInfo>,
+        peer_scores: &HashMap<PeerId, f64>,
     ) -> Result<(), near_chain::Error> {
         let header_head = chain.header_head()?;
         if !self.header_sync_due(sync_status, &header_head, highest_height) {
@@ -191,7 +207,7 @@ impl HeaderSync {
             *sync_status =
                 SyncStatus::HeaderSync { current_height: header_head.height, highest_height };
             self.syncing_peer = None;
-            if let Some(peer) = highest_height_peers.choose(&mut thread_rng()).cloned() {
+            if let Some(peer) = choose_peer(highest_height_peers.iter(), peer_scores).cloned() {
                 if peer.chain_info.height > header_head.height {
                     self.syncing_peer = self.request_headers(chain, peer);
                 }
@@ -247,6 +263,14 @@ impl HeaderSync {
                 if self.stalling_ts.is_none() {
                     self.stalling_ts = Some(now);
                 }
+                if let Some(ref peer) = self.syncing_peer {
+                    self.network_adapter.do_send(PeerManagerMessageRequest::NetworkRequests(
+                        NetworkRequests::ReportPeer {
+                            peer_id: peer.peer_info.id.clone(),
+                            evidence: PeerEvidence::Timeout,
+                        },
+                    ));
+                }
             } else {
                 self.stalling_ts = None;
             }
@@ -426,9 +450,10 @@ impl BlockSync {
         chain: &mut Chain,
         highest_height: BlockHeight,
         highest_height_peers: &[FullPeerInfo],
+        peer_scores: &HashMap<PeerId, f64>,
     ) -> Result<bool, near_chain::Error> {
         if self.block_sync_due(chain)? {
-            if self.block_sync(chain, highest_height_peers)? {
+            if self.block_sync(chain, highest_height_peers, peer_scores)? {
                 debug!(target: "sync", "Sync: transition to State Sync.");
                 return Ok(true);
             }
@@ -464,11 +489,13 @@ impl BlockSync {
     }
 
     /// Returns true if state download is required (last known block is too far).
-    /// Otherwise request recent blocks from peers round robin.
+    /// Otherwise request recent blocks from peers at random, preferring peers with better
+    /// reputation.
     pub fn block_sync(
         &mut self,
         chain: &mut Chain,
         highest_height_peers: &[FullPeerInfo],
+        peer_scores: &HashMap<PeerId, f64>,
     ) -> Result<bool, near_chain::Error> {
         if self.check_state_needed(chain)? {
             return Ok(true);
@@ -554,10 +581,9 @@ impl BlockSync {
             let peer = if request_from_archival {
                 let archival_peer_iter =
                     highest_height_peers.iter().filter(|p| p.chain_info.archival);
-                archival_peer_iter.choose(&mut rand::thread_rng())
+                choose_peer(archival_peer_iter, peer_scores)
             } else {
-                let peer_iter = highest_height_peers.iter();
-                peer_iter.choose(&mut rand::thread_rng())
+                choose_peer(highest_height_peers.iter(), peer_scores)
             };
 
             if let Some(peer) = peer {
@@ -713,9 +739,16 @@ impl StateSync {
             error!("cannot sync to the first epoch after sharding upgrade");
             panic!("cannot sync to the first epoch after sharding upgrade. Please wait for the next epoch or find peers that are more up to date");
         }
-        let split_states = runtime_adapter.will_shard_layout_change_next_epoch(&prev_hash)?;
+        let will_shard_layout_change =
+            runtime_adapter.will_shard_layout_change_next_epoch(&prev_hash)?;
+        let next_epoch_shard_layout = runtime_adapter
+            .get_shard_layout(chain.get_block_header(&sync_hash)?.next_epoch_id())?;
 
         for shard_id in tracking_shards {
+            // States of shards merged in the next epoch are built at the end of this epoch rather
+            // than split from the downloaded states.
+            let split_states =
+                will_shard_layout_change && !next_epoch_shard_layout.merges_parent_shard(shard_id);
             let mut download_timeout = false;
             let mut need_shard = false;
             let shard_sync_download = new_shard_sync.entry(shard_id).or_insert_with(|| {
@@ -1468,6 +1501,7 @@ mod test {
                             current_height,
                             &signer,
                         )
+                        .unwrap()
                         .signature
                     })
                 })
@@ -1501,7 +1535,8 @@ mod test {
                 &*signers[3],
                 *last_block.header().next_bp_hash(),
                 block_merkle_tree.root(),
-            );
+            )
+            .unwrap();
             block_merkle_tree.insert(*block.hash());
 
             all_blocks.push(block);
@@ -1616,8 +1651,9 @@ mod test {
 
         // fetch three blocks at a time
         for i in 0..3 {
-            let is_state_sync =
-                block_sync.block_sync(&mut env.clients[1].chain, &peer_infos).unwrap();
+            let is_state_sync = block_sync
+                .block_sync(&mut env.clients[1].chain, &peer_infos, &HashMap::new())
+                .unwrap();
             assert!(!is_state_sync);
 
             let expected_blocks: Vec<_> =
@@ -1634,7 +1670,8 @@ mod test {
 
         // Now test when the node receives the block out of order
         // fetch the next three blocks
-        let is_state_sync = block_sync.block_sync(&mut env.clients[1].chain, &peer_infos).unwrap();
+        let is_state_sync =
+            block_sync.block_sync(&mut env.clients[1].chain, &peer_infos, &HashMap::new()).unwrap();
         assert!(!is_state_sync);
         check_hashes_from_network_adapter(
             network_adapter.clone(),
@@ -1646,7 +1683,8 @@ mod test {
             Provenance::NONE,
         );
         // the next block sync should not request block[4*MAX_BLOCK_REQUESTS-1] again
-        let is_state_sync = block_sync.block_sync(&mut env.clients[1].chain, &peer_infos).unwrap();
+        let is_state_sync =
+            block_sync.block_sync(&mut env.clients[1].chain, &peer_infos, &HashMap::new()).unwrap();
         assert!(!is_state_sync);
         check_hashes_from_network_adapter(
             network_adapter.clone(),
@@ -1659,7 +1697,7 @@ mod test {
         for i in 3 * MAX_BLOCK_REQUESTS..5 * MAX_BLOCK_REQUESTS {
             env.process_block(1, blocks[i].clone(), Provenance::NONE);
         }
-        block_sync.block_sync(&mut env.clients[1].chain, &peer_infos).unwrap();
+        block_sync.block_sync(&mut env.clients[1].chain, &peer_infos, &HashMap::new()).unwrap();
         let requested_block_hashes = collect_hashes_from_network_adapter(network_adapter);
         assert!(requested_block_hashes.is_empty(), "{:?}", requested_block_hashes);
     }
@@ -1681,7 +1719,8 @@ mod test {
         let block_headers = blocks.iter().map(|b| b.header().clone()).collect::<Vec<_>>();
         let peer_infos = create_peer_infos(2);
         env.clients[1].chain.sync_block_headers(block_headers, &mut |_| unreachable!()).unwrap();
-        let is_state_sync = block_sync.block_sync(&mut env.clients[1].chain, &peer_infos).unwrap();
+        let is_state_sync =
+            block_sync.block_sync(&mut env.clients[1].chain, &peer_infos, &HashMap::new()).unwrap();
         assert!(!is_state_sync);
         let requested_block_hashes = collect_hashes_from_network_adapter(network_adapter.clone());
         // We don't have archival peers, and thus cannot request any blocks
@@ -1691,7 +1730,8 @@ mod test {
         for peer in peer_infos.iter_mut() {
             peer.chain_info.archival = true;
         }
-        let is_state_sync = block_sync.block_sync(&mut env.clients[1].chain, &peer_infos).unwrap();
+        let is_state_sync =
+            block_sync.block_sync(&mut env.clients[1].chain, &peer_infos, &HashMap::new()).unwrap();
         assert!(!is_state_sync);
         let requested_block_hashes = collect_hashes_from_network_adapter(network_adapter);
         assert_eq!(
@@ -50,7 +50,10 @@ use near_telemetry::TelemetryActor;
 #[cfg(feature = "test_features")]
 use crate::AdversarialControls;
 use crate::{start_view_client, Client, ClientActor, SyncStatus, ViewClientActor};
-use near_chain::chain::{do_apply_chunks, BlockCatchUpRequest, StateSplitRequest};
+use near_chain::chain::{
+    do_apply_chunks, do_build_merged_states, BlockCatchUpRequest, StateMergeRequest,
+    StateSplitRequest,
+};
 use near_chain::types::AcceptedBlock;
 use near_client_primitives::types::Error;
 use near_network::types::{NetworkInfo, PeerManagerMessageRequest, PeerManagerMessageResponse};
@@ -590,6 +593,8 @@ pub fn setup_mock_all_validators(
                             received_bytes_per_sec: 0,
                             known_producers: vec![],
                             peer_counter: 0,
+                            peer_scores: HashMap::new(),
+                            private_peers: HashSet::new(),
                         };
                         client_addr.do_send(NetworkClientMessages::NetworkInfo(info));
                     }
@@ -979,6 +984,7 @@ pub fn setup_mock_all_validators(
                         | NetworkRequests::PingTo(_, _)
                         | NetworkRequests::FetchPingPongInfo
                         | NetworkRequests::BanPeer { .. }
+                        | NetworkRequests::ReportPeer { .. }
                         | NetworkRequests::TxStatus(_, _, _)
                         | NetworkRequests::Query { .. }
                         | NetworkRequests::Challenge(_)
@@ -987,6 +993,8 @@ pub fn setup_mock_all_validators(
                         | NetworkRequests::ReceiptOutComeRequest(_, _) => {}
                         #[cfg(feature = "protocol_feature_routing_exchange_algorithm")]
                         | NetworkRequests::IbfMessage { .. } => {}
+                        #[cfg(feature = "protocol_feature_chunk_state_witness")]
+                        NetworkRequests::ChunkStateWitness { .. } => {}
                     };
                 }
                 Box::new(Some(resp))
@@ -1667,7 +1675,8 @@ pub fn create_chunk(
         &*client.validator_signer.as_ref().unwrap().clone(),
         *last_block.header().next_bp_hash(),
         block_merkle_tree.root(),
-    );
+    )
+    .unwrap();
     (chunk, merkle_paths, receipts, block)
 }
 
@@ -1687,9 +1696,21 @@ pub fn run_catchup(
     let state_split = move |msg: StateSplitRequest| {
         state_split_inside_messages.write().unwrap().push(msg);
     };
+    let state_merge_messages = Arc::new(RwLock::new(vec![]));
+    let state_merge_inside_messages = state_merge_messages.clone();
+    let state_merge = move |msg: StateMergeRequest| {
+        state_merge_inside_messages.write().unwrap().push(msg);
+    };
     let rt = client.runtime_adapter.clone();
-    while !client.chain.store().iterate_state_sync_infos().is_empty() {
-        let call = client.run_catchup(highest_height_peers, &f, &block_catch_up, &state_split)?;
+    // Runs at least once, so that merged states are built even if there is no state to catch up.
+    loop {
+        let call = client.run_catchup(
+            highest_height_peers,
+            &f,
+            &block_catch_up,
+            &state_split,
+            &state_merge,
+        )?;
         for msg in block_messages.write().unwrap().drain(..) {
             let results = do_apply_chunks(msg.work);
             if let Some((_, _, blocks_catch_up_state)) =
@@ -1718,6 +1739,13 @@ pub fn run_catchup(
             }
         }
         result.extend(call);
+        for msg in state_merge_messages.write().unwrap().drain(..) {
+            let state_roots = do_build_merged_states(&msg);
+            result.extend(client.finish_merged_states(msg.block_hash, state_roots)?);
+        }
+        if client.chain.store().iterate_state_sync_infos().is_empty() {
+            break;
+        }
     }
     Ok(result)
 }
@@ -52,12 +52,12 @@ use near_primitives::views::{
     BlockView, ChunkView, EpochValidatorInfo, ExecutionOutcomeWithIdView,
     FinalExecutionOutcomeView, FinalExecutionOutcomeViewEnum, FinalExecutionStatus, GasPriceView,
     LightClientBlockView, QueryRequest, QueryResponse, ReceiptView, StateChangesKindsView,
-    StateChangesView,
+    StateChangesView, ValidatorPerformanceView,
 };
 
 use crate::{
     sync, GetChunk, GetExecutionOutcomeResponse, GetNextLightClientBlock, GetStateChanges,
-    GetStateChangesInBlock, GetValidatorInfo, GetValidatorOrdered,
+    GetStateChangesInBlock, GetValidatorInfo, GetValidatorOrdered, GetValidatorPerformance,
 };
 
 /// Max number of queries that we keep.
@@ -732,6 +732,41 @@ impl Handler<GetValidatorOrdered> for ViewClientActor {
             })?)
     }
 }
+impl Handler<GetValidatorPerformance> for ViewClientActor {
+    type Result = Result<ValidatorPerformanceView, GetValidatorInfoError>;
+
+    #[perf]
+    fn handle(&mut self, msg: GetValidatorPerformance, _: &mut Self::Context) -> Self::Result {
+        let head = self.chain.head()?;
+        let (epoch_id, epoch_start_height) = match msg.epoch_reference {
+            EpochReference::EpochId(id) if id != head.epoch_id => {
+                // The first block of the epoch may not be at the start height, so the height
+                // comes from the epoch manager rather than from a block.
+                let epoch_start_height = self
+                    .runtime_adapter
+                    .get_validator_info(ValidatorInfoIdentifier::EpochId(id.clone()))?
+                    .epoch_start_height;
+                (id, epoch_start_height)
+            }
+            epoch_reference => {
+                let block_hash = match epoch_reference {
+                    EpochReference::BlockId(BlockId::Hash(hash)) => hash,
+                    EpochReference::BlockId(BlockId::Height(height)) => {
+                        *self.chain.get_header_by_height(height)?.hash()
+                    }
+                    EpochReference::EpochId(_) | EpochReference::Latest => head.last_block_hash,
+                };
+                let epoch_id = self.chain.get_block_header(&block_hash)?.epoch_id().clone();
+                (epoch_id, self.runtime_adapter.get_epoch_start_height(&block_hash)?)
+            }
+        };
+        if epoch_start_height < self.chain.tail()? {
+            return Err(GetValidatorInfoError::ValidatorInfoUnavailable);
+        }
+        Ok(self.chain.get_validator_performance(&epoch_id, epoch_start_height, &msg.account_id)?)
+    }
+}
+
 /// Returns a list of change kinds per account in a store for a given block.
 impl Handler<GetStateChangesInBlock> for ViewClientActor {
     type Result = Result<StateChangesKindsView, GetStateChangesError>;
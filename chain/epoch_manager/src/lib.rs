@@ -7,7 +7,7 @@ use tracing::{debug, warn};
 use near_primitives::epoch_manager::block_info::BlockInfo;
 use near_primitives::epoch_manager::epoch_info::{EpochInfo, EpochSummary};
 use near_primitives::epoch_manager::{
-    AllEpochConfig, EpochConfig, ShardConfig, SlashState, AGGREGATOR_KEY,
+    AllEpochConfig, EpochConfig, EpochShardConfig, ShardConfig, SlashState, AGGREGATOR_KEY,
 };
 use near_primitives::errors::EpochError;
 use near_primitives::hash::CryptoHash;
@@ -28,15 +28,18 @@ use crate::types::EpochInfoAggregator;
 pub use crate::types::RngSeed;
 
 pub use crate::reward_calculator::NUM_SECONDS_IN_A_YEAR;
+pub use crate::simulation::{EpochSimulation, ProposalOverride};
 use near_chain::types::{BlockHeaderInfo, ValidatorInfoIdentifier};
 use near_chain_configs::GenesisConfig;
 use near_primitives::shard_layout::ShardLayout;
 use near_store::db::DBCol::ColEpochValidatorInfo;
 
 mod proposals;
+mod resharding;
 mod reward_calculator;
 #[cfg(feature = "protocol_feature_chunk_only_producers")]
 mod shard_assignment;
+mod simulation;
 pub mod test_utils;
 mod tests;
 mod types;
@@ -62,6 +65,8 @@ pub struct EpochManager {
     blocks_info: lru::LruCache<CryptoHash, BlockInfo>,
     /// Cache of epoch id to epoch start height
     epoch_id_to_start: lru::LruCache<EpochId, BlockHeight>,
+    /// Cache of epoch configs, with the shard configs chosen by dynamic resharding applied.
+    epoch_configs: lru::LruCache<EpochId, EpochConfig>,
     /// Epoch validators ordered by `block_producer_settlement`.
     epoch_validators_ordered: lru::LruCache<EpochId, Vec<(ValidatorStake, bool)>>,
     /// Unique validators ordered by `block_producer_settlement`.
@@ -106,6 +111,7 @@ impl EpochManager {
             epochs_info: lru::LruCache::new(EPOCH_CACHE_SIZE),
             blocks_info: lru::LruCache::new(BLOCK_CACHE_SIZE),
             epoch_id_to_start: lru::LruCache::new(EPOCH_CACHE_SIZE),
+            epoch_configs: lru::LruCache::new(EPOCH_CACHE_SIZE),
             epoch_validators_ordered: lru::LruCache::new(EPOCH_CACHE_SIZE),
             epoch_validators_ordered_unique: lru::LruCache::new(EPOCH_CACHE_SIZE),
             epoch_info_aggregator: None,
@@ -266,6 +272,22 @@ impl EpochManager {
         &mut self,
         last_block_info: &BlockInfo,
         last_block_hash: &CryptoHash,
+    ) -> Result<EpochSummary, EpochError> {
+        let aggregator = self.get_and_update_epoch_info_aggregator(
+            last_block_info.epoch_id(),
+            last_block_hash,
+            false,
+        )?;
+        self.summarize_epoch(last_block_info, last_block_hash, aggregator)
+    }
+
+    /// Computes the summary of the epoch ending with the given block from the information
+    /// aggregated over the epoch.
+    fn summarize_epoch(
+        &mut self,
+        last_block_info: &BlockInfo,
+        last_block_hash: &CryptoHash,
+        aggregator: EpochInfoAggregator,
     ) -> Result<EpochSummary, EpochError> {
         let epoch_info = self.get_epoch_info(last_block_info.epoch_id())?.clone();
         let next_epoch_id = self.get_next_epoch_id(last_block_hash)?;
@@ -276,11 +298,7 @@ impl EpochManager {
             all_proposals,
             version_tracker,
             ..
-        } = self.get_and_update_epoch_info_aggregator(
-            last_block_info.epoch_id(),
-            last_block_hash,
-            false,
-        )?;
+        } = aggregator;
         let mut proposals = vec![];
         let mut validator_kickout = HashMap::new();
 
@@ -377,62 +395,46 @@ impl EpochManager {
         rng_seed: RngSeed,
     ) -> Result<(), EpochError> {
         let epoch_summary = self.collect_blocks_info(block_info, last_block_hash)?;
-        let epoch_info = self.get_epoch_info(block_info.epoch_id())?;
-        let epoch_protocol_version = epoch_info.protocol_version();
-        let validator_stake =
-            epoch_info.validators_iter().map(|r| r.account_and_stake()).collect::<HashMap<_, _>>();
         let next_epoch_id = self.get_next_epoch_id_from_info(block_info)?;
         let next_epoch_info = self.get_epoch_info(&next_epoch_id)?.clone();
         self.save_epoch_validator_info(store_update, block_info.epoch_id(), &epoch_summary)?;
+        let next_version = epoch_summary.next_version;
+        let mut epoch_shard_config =
+            self.choose_next_next_epoch_shard_config(block_info, next_version)?;
+        let next_next_epoch_config =
+            self.epoch_config_with_shard_config(next_version, epoch_shard_config.as_ref());
 
-        let EpochSummary {
-            all_proposals,
-            validator_kickout,
-            validator_block_chunk_stats,
-            next_version,
-            ..
-        } = epoch_summary;
-
-        let (validator_reward, minted_amount) = {
+        let epoch_duration = {
             let last_epoch_last_block_hash =
                 *self.get_block_info(block_info.epoch_first_block())?.prev_hash();
             let last_block_in_last_epoch = self.get_block_info(&last_epoch_last_block_hash)?;
             assert!(block_info.timestamp_nanosec() > last_block_in_last_epoch.timestamp_nanosec());
-            let epoch_duration =
-                block_info.timestamp_nanosec() - last_block_in_last_epoch.timestamp_nanosec();
-            self.reward_calculator.calculate_reward(
-                validator_block_chunk_stats,
-                &validator_stake,
-                *block_info.total_supply(),
-                epoch_protocol_version,
-                self.genesis_protocol_version,
-                epoch_duration,
-            )
+            block_info.timestamp_nanosec() - last_block_in_last_epoch.timestamp_nanosec()
         };
-        let next_next_epoch_config = self.config.for_protocol_version(next_version);
-        let next_next_epoch_info = match proposals_to_epoch_info(
-            next_next_epoch_config,
-            rng_seed,
+        let (next_next_epoch_info, next_next_epoch_config) = match self.select_next_next_epoch_info(
+            block_info,
             &next_epoch_info,
-            all_proposals,
-            validator_kickout,
-            validator_reward,
-            minted_amount,
-            next_version,
-            epoch_protocol_version,
+            &next_next_epoch_config,
+            epoch_summary,
+            epoch_duration,
+            rng_seed,
         ) {
-            Ok(next_next_epoch_info) => next_next_epoch_info,
+            Ok(next_next_epoch_info) => (next_next_epoch_info, next_next_epoch_config),
             Err(EpochError::ThresholdError { stake_sum, num_seats }) => {
                 warn!(target: "epoch_manager", "Not enough stake for required number of seats (all validators tried to unstake?): amount = {} for {}", stake_sum, num_seats);
-                let mut epoch_info = next_epoch_info.clone();
-                *epoch_info.epoch_height_mut() += 1;
-                epoch_info
+                self.reuse_next_epoch_info(
+                    &next_epoch_id,
+                    &next_epoch_info,
+                    &mut epoch_shard_config,
+                )?
             }
             Err(EpochError::NotEnoughValidators { num_validators, num_shards }) => {
                 warn!(target: "epoch_manager", "Not enough validators for required number of shards (all validators tried to unstake?): num_validators={} num_shards={}", num_validators, num_shards);
-                let mut epoch_info = next_epoch_info.clone();
-                *epoch_info.epoch_height_mut() += 1;
-                epoch_info
+                self.reuse_next_epoch_info(
+                    &next_epoch_id,
+                    &next_epoch_info,
+                    &mut epoch_shard_config,
+                )?
             }
             Err(err) => return Err(err),
         };
@@ -441,13 +443,79 @@ impl EpochManager {
                next_next_epoch_info.epoch_height(),
                &next_next_epoch_id,
                next_next_epoch_info.protocol_version(),
-               self.config.for_protocol_version(next_next_epoch_info.protocol_version()).shard_layout);
+               next_next_epoch_config.shard_layout);
         // This epoch info is computed for the epoch after next (T+2),
         // where epoch_id of it is the hash of last block in this epoch (T).
         self.save_epoch_info(store_update, &next_next_epoch_id, next_next_epoch_info)?;
+        if let Some(epoch_shard_config) = epoch_shard_config {
+            self.save_epoch_shard_config(store_update, &next_next_epoch_id, &epoch_shard_config)?;
+        }
+        self.epoch_configs.put(next_next_epoch_id, next_next_epoch_config);
         Ok(())
     }
 
+    /// Epoch info and config of epoch (T + 2) when validators can't be selected for it: the
+    /// validators of epoch (T + 1) are kept along with its shard config.
+    fn reuse_next_epoch_info(
+        &mut self,
+        next_epoch_id: &EpochId,
+        next_epoch_info: &EpochInfo,
+        epoch_shard_config: &mut Option<EpochShardConfig>,
+    ) -> Result<(EpochInfo, EpochConfig), EpochError> {
+        let mut epoch_info = next_epoch_info.clone();
+        *epoch_info.epoch_height_mut() += 1;
+        let config = self.get_epoch_config(next_epoch_id)?.clone();
+        if let Some(epoch_shard_config) = epoch_shard_config {
+            epoch_shard_config.shard_config = config.clone().into();
+        }
+        Ok((epoch_info, config))
+    }
+
+    /// Rewards validators of epoch (T), which ends with the given block, and selects validators
+    /// for epoch (T + 2) based on the summary of epoch (T).
+    fn select_next_next_epoch_info(
+        &mut self,
+        block_info: &BlockInfo,
+        next_epoch_info: &EpochInfo,
+        next_next_epoch_config: &EpochConfig,
+        epoch_summary: EpochSummary,
+        epoch_duration: u64,
+        rng_seed: RngSeed,
+    ) -> Result<EpochInfo, EpochError> {
+        let epoch_info = self.get_epoch_info(block_info.epoch_id())?;
+        let epoch_protocol_version = epoch_info.protocol_version();
+        let validator_stake =
+            epoch_info.validators_iter().map(|r| r.account_and_stake()).collect::<HashMap<_, _>>();
+
+        let EpochSummary {
+            all_proposals,
+            validator_kickout,
+            validator_block_chunk_stats,
+            next_version,
+            ..
+        } = epoch_summary;
+
+        let (validator_reward, minted_amount) = self.reward_calculator.calculate_reward(
+            validator_block_chunk_stats,
+            &validator_stake,
+            *block_info.total_supply(),
+            epoch_protocol_version,
+            self.genesis_protocol_version,
+            epoch_duration,
+        );
+        proposals_to_epoch_info(
+            next_next_epoch_config,
+            rng_seed,
+            next_epoch_info,
+            all_proposals,
+            validator_kickout,
+            validator_reward,
+            minted_amount,
+            next_version,
+            epoch_protocol_version,
+        )
+    }
+
     pub fn record_block_info(
         &mut self,
         mut block_info: BlockInfo,
@@ -1255,19 +1323,22 @@ impl EpochManager {
     }
 
     pub fn get_shard_config(&mut self, epoch_id: &EpochId) -> Result<ShardConfig, EpochError> {
-        let protocol_version = self.get_epoch_info(epoch_id)?.protocol_version();
-        Ok(self.config.for_protocol_version(protocol_version).clone().into())
+        Ok(self.get_epoch_config(epoch_id)?.clone().into())
     }
 
     pub fn get_epoch_config(&mut self, epoch_id: &EpochId) -> Result<&EpochConfig, EpochError> {
-        let protocol_version = self.get_epoch_info(epoch_id)?.protocol_version();
-        Ok(self.config.for_protocol_version(protocol_version))
+        if self.epoch_configs.get(epoch_id).is_none() {
+            let protocol_version = self.get_epoch_info(epoch_id)?.protocol_version();
+            let epoch_shard_config = self.get_epoch_shard_config(epoch_id)?;
+            let config =
+                self.epoch_config_with_shard_config(protocol_version, epoch_shard_config.as_ref());
+            self.epoch_configs.put(epoch_id.clone(), config);
+        }
+        self.epoch_configs.get(epoch_id).ok_or(EpochError::EpochOutOfBounds(epoch_id.clone()))
     }
 
     pub fn get_shard_layout(&mut self, epoch_id: &EpochId) -> Result<&ShardLayout, EpochError> {
-        let protocol_version = self.get_epoch_info(epoch_id)?.protocol_version();
-        let shard_layout = &self.config.for_protocol_version(protocol_version).shard_layout;
-        Ok(shard_layout)
+        Ok(&self.get_epoch_config(epoch_id)?.shard_layout)
     }
 
     pub fn will_shard_layout_change(
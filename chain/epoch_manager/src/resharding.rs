@@ -0,0 +1,298 @@
+//! Dynamic resharding: shards are split in two and merged by observed load.
+//!
+//! While dynamic resharding is enabled, the chain records the load of each shard for every block
+//! (see `ShardLoad`), only from data in consensus: the gas used reported by the chunk headers of
+//! the block and, for the first block of an epoch, the size and median account of the state of
+//! the shard as of the end of the previous epoch, computed once from the state root of the
+//! canonical chain. At the end of each epoch the loads of the epoch are summarized per shard, and
+//! a shard whose average gas usage per chunk or state size is above the limits of the
+//! `ReshardingConfig` for `num_overloaded_epochs_to_split` epochs in a row is split at its median
+//! account, starting from the epoch after next. Otherwise two adjacent shards whose loads are both
+//! below the minimums of the config for `num_underloaded_epochs_to_merge` epochs in a row are
+//! merged. At most one shard is split or merged at a time, and no shard is split or merged while a
+//! previous change of the layout hasn't taken effect yet.
+//!
+//! The shard config chosen for each epoch is stored in `ColEpochShardConfig` and takes precedence
+//! over the config of the protocol version of the epoch.
+//!
+//! Computing the state load of a shard requires its state, so nodes must track all shards while
+//! dynamic resharding is enabled. A node missing the load of a block fails to finalize the epoch
+//! instead of choosing a shard layout other nodes may not agree on.
+
+use tracing::debug;
+
+use near_primitives::epoch_manager::block_info::BlockInfo;
+use near_primitives::epoch_manager::{
+    EpochConfig, EpochShardConfig, ReshardingConfig, ShardConfig, ShardLoad, ShardStateLoad,
+};
+use near_primitives::errors::EpochError;
+use near_primitives::types::{EpochId, Gas, ShardId};
+use near_primitives::utils::get_block_shard_id;
+use near_primitives::version::ProtocolVersion;
+use near_store::{ColEpochShardConfig, ColShardLoad, StoreUpdate};
+
+use crate::EpochManager;
+
+/// Load of a shard over an epoch.
+#[derive(Debug, Default)]
+struct EpochShardLoad {
+    gas_used: Gas,
+    num_chunks: u64,
+    /// State as of the start of the epoch.
+    state: Option<ShardStateLoad>,
+}
+
+impl EpochShardLoad {
+    fn avg_gas_used(&self) -> Gas {
+        self.gas_used / self.num_chunks.max(1)
+    }
+}
+
+impl EpochManager {
+    /// Whether loads of shards are recorded in the given epoch.
+    pub fn is_resharding_enabled(&mut self, epoch_id: &EpochId) -> Result<bool, EpochError> {
+        let protocol_version = self.get_epoch_info(epoch_id)?.protocol_version();
+        Ok(self.config.resharding_config(protocol_version).is_some())
+    }
+
+    /// Shard config chosen for the given epoch by dynamic resharding, if any.
+    pub fn get_epoch_shard_config(
+        &mut self,
+        epoch_id: &EpochId,
+    ) -> Result<Option<EpochShardConfig>, EpochError> {
+        self.store.get_ser(ColEpochShardConfig, epoch_id.as_ref()).map_err(EpochError::from)
+    }
+
+    pub(crate) fn save_epoch_shard_config(
+        &mut self,
+        store_update: &mut StoreUpdate,
+        epoch_id: &EpochId,
+        epoch_shard_config: &EpochShardConfig,
+    ) -> Result<(), EpochError> {
+        store_update
+            .set_ser(ColEpochShardConfig, epoch_id.as_ref(), epoch_shard_config)
+            .map_err(EpochError::from)
+    }
+
+    /// Epoch config of the given protocol version, with the shard config chosen by dynamic
+    /// resharding if any.
+    pub(crate) fn epoch_config_with_shard_config(
+        &self,
+        protocol_version: ProtocolVersion,
+        epoch_shard_config: Option<&EpochShardConfig>,
+    ) -> EpochConfig {
+        let config = self.config.for_protocol_version(protocol_version).clone();
+        match epoch_shard_config {
+            Some(epoch_shard_config) => {
+                config.with_shard_config(epoch_shard_config.shard_config.clone())
+            }
+            None => config,
+        }
+    }
+
+    /// Chooses the shard config of epoch (T + 2) at the end of epoch (T), which ends with the
+    /// given block. Returns `None` if shards are not split by load in epoch (T + 2).
+    pub(crate) fn choose_next_next_epoch_shard_config(
+        &mut self,
+        block_info: &BlockInfo,
+        next_version: ProtocolVersion,
+    ) -> Result<Option<EpochShardConfig>, EpochError> {
+        let resharding_config = match self.config.resharding_config(next_version) {
+            Some(resharding_config) => resharding_config.clone(),
+            None => return Ok(None),
+        };
+        let epoch_id = block_info.epoch_id().clone();
+        let next_epoch_id = self.get_next_epoch_id_from_info(block_info)?;
+        let shard_layout = self.get_shard_layout(&epoch_id)?.clone();
+        let num_shards = shard_layout.num_shards();
+
+        // Shard config of epoch (T + 1) and the tracking as of the end of epoch (T - 1).
+        let prev_epoch_shard_config = self.get_epoch_shard_config(&next_epoch_id)?;
+        let mut epoch_shard_config = match prev_epoch_shard_config {
+            Some(epoch_shard_config) => epoch_shard_config,
+            None => {
+                let config = self.config.for_protocol_version(next_version).clone();
+                EpochShardConfig {
+                    shard_config: ShardConfig::from(config),
+                    num_overloaded_epochs: vec![],
+                    num_underloaded_epochs: vec![],
+                }
+            }
+        };
+        if epoch_shard_config.num_overloaded_epochs.len() != num_shards as usize
+            || epoch_shard_config.num_underloaded_epochs.len() != num_shards as usize
+        {
+            epoch_shard_config.num_overloaded_epochs = vec![0; num_shards as usize];
+            epoch_shard_config.num_underloaded_epochs = vec![0; num_shards as usize];
+        }
+
+        // Loads are only recorded for epochs in which dynamic resharding is enabled.
+        let loads = if self.is_resharding_enabled(&epoch_id)? {
+            self.summarize_shard_loads(block_info, num_shards)?
+        } else {
+            (0..num_shards).map(|_| EpochShardLoad::default()).collect()
+        };
+        for (shard_id, load) in loads.iter().enumerate() {
+            let avg_gas_used = load.avg_gas_used();
+            let state_size = load.state.as_ref().map_or(0, |state| state.state_size);
+            let is_overloaded = avg_gas_used > resharding_config.max_avg_gas_used_per_chunk
+                || state_size > resharding_config.max_state_size;
+            // A shard whose state is unknown is never merged.
+            let is_underloaded = !is_overloaded
+                && load.state.is_some()
+                && avg_gas_used < resharding_config.min_avg_gas_used_per_chunk
+                && state_size < resharding_config.min_state_size;
+            let num_overloaded_epochs = &mut epoch_shard_config.num_overloaded_epochs[shard_id];
+            *num_overloaded_epochs = if is_overloaded { *num_overloaded_epochs + 1 } else { 0 };
+            let num_underloaded_epochs = &mut epoch_shard_config.num_underloaded_epochs[shard_id];
+            *num_underloaded_epochs = if is_underloaded { *num_underloaded_epochs + 1 } else { 0 };
+        }
+
+        // Loads are tracked by shards of epoch (T), so shards can only be split or merged if the
+        // layout doesn't change in epoch (T + 1).
+        if epoch_shard_config.shard_config.shard_layout != shard_layout {
+            return Ok(Some(epoch_shard_config));
+        }
+        if num_shards < resharding_config.max_num_shards
+            && self.split_overloaded_shard(&resharding_config, &loads, &mut epoch_shard_config)
+        {
+            debug!(target: "epoch_manager", "Splitting a shard in epoch after {:?}", next_epoch_id);
+        } else if num_shards > resharding_config.min_num_shards
+            && self.merge_underloaded_shards(&resharding_config, &loads, &mut epoch_shard_config)
+        {
+            debug!(target: "epoch_manager", "Merging shards in epoch after {:?}", next_epoch_id);
+        }
+        Ok(Some(epoch_shard_config))
+    }
+
+    /// Splits the most overloaded shard of the config in two at its median account, if any shard
+    /// has been overloaded for long enough. Returns whether a shard was split.
+    fn split_overloaded_shard(
+        &self,
+        resharding_config: &ReshardingConfig,
+        loads: &[EpochShardLoad],
+        epoch_shard_config: &mut EpochShardConfig,
+    ) -> bool {
+        let num_overloaded_epochs = &epoch_shard_config.num_overloaded_epochs;
+        let candidate = (0..loads.len())
+            .filter(|&index| {
+                num_overloaded_epochs[index] >= resharding_config.num_overloaded_epochs_to_split
+            })
+            .max_by_key(|&index| {
+                (num_overloaded_epochs[index], loads[index].state.as_ref().map(|s| s.state_size))
+            });
+        let index = match candidate {
+            Some(index) => index,
+            None => return false,
+        };
+        let shard_id = index as ShardId;
+        let median_account_id = match loads[index]
+            .state
+            .as_ref()
+            .and_then(|state| state.median_account_id.clone())
+        {
+            Some(median_account_id) => median_account_id,
+            None => {
+                debug!(target: "epoch_manager", "Shard {} is overloaded but has no median account", shard_id);
+                return false;
+            }
+        };
+        let shard_config = &mut epoch_shard_config.shard_config;
+        match shard_config.shard_layout.split_shard(shard_id, median_account_id.clone()) {
+            Ok(new_shard_layout) => {
+                debug!(target: "epoch_manager", "Splitting shard {} at {}", shard_id, median_account_id);
+                let seats = &mut shard_config.num_block_producer_seats_per_shard;
+                seats.insert(index + 1, seats[index]);
+                let seats = &mut shard_config.avg_hidden_validator_seats_per_shard;
+                seats.insert(index + 1, seats[index]);
+                shard_config.shard_layout = new_shard_layout;
+                epoch_shard_config.num_overloaded_epochs[index] = 0;
+                true
+            }
+            Err(err) => {
+                debug!(target: "epoch_manager", "Shard {} is overloaded but can't be split: {:?}", shard_id, err);
+                false
+            }
+        }
+    }
+
+    /// Merges the two adjacent shards of the config with the smallest state which have both been
+    /// underloaded for long enough, if their combined load is still under the limits. Returns
+    /// whether shards were merged.
+    fn merge_underloaded_shards(
+        &self,
+        resharding_config: &ReshardingConfig,
+        loads: &[EpochShardLoad],
+        epoch_shard_config: &mut EpochShardConfig,
+    ) -> bool {
+        let num_underloaded_epochs = &epoch_shard_config.num_underloaded_epochs;
+        let state_size = |index: usize| loads[index].state.as_ref().map_or(0, |s| s.state_size);
+        let candidate = (0..loads.len().saturating_sub(1))
+            .filter(|&index| {
+                num_underloaded_epochs[index] >= resharding_config.num_underloaded_epochs_to_merge
+                    && num_underloaded_epochs[index + 1]
+                        >= resharding_config.num_underloaded_epochs_to_merge
+                    && loads[index].avg_gas_used() + loads[index + 1].avg_gas_used()
+                        <= resharding_config.max_avg_gas_used_per_chunk
+                    && state_size(index) + state_size(index + 1) <= resharding_config.max_state_size
+            })
+            .filter(|&index| {
+                epoch_shard_config.shard_config.shard_layout.merge_shards(index as ShardId).is_ok()
+            })
+            .min_by_key(|&index| state_size(index) + state_size(index + 1));
+        let index = match candidate {
+            Some(index) => index,
+            None => return false,
+        };
+        let shard_config = &mut epoch_shard_config.shard_config;
+        debug!(target: "epoch_manager", "Merging shards {} and {}", index, index + 1);
+        shard_config.shard_layout = match shard_config.shard_layout.merge_shards(index as ShardId) {
+            Ok(new_shard_layout) => new_shard_layout,
+            Err(_) => return false,
+        };
+        shard_config.num_block_producer_seats_per_shard.remove(index + 1);
+        shard_config.avg_hidden_validator_seats_per_shard.remove(index + 1);
+        epoch_shard_config.num_overloaded_epochs.remove(index + 1);
+        epoch_shard_config.num_underloaded_epochs.remove(index + 1);
+        epoch_shard_config.num_overloaded_epochs[index] = 0;
+        epoch_shard_config.num_underloaded_epochs[index] = 0;
+        true
+    }
+
+    /// Summarizes loads recorded for the blocks of the epoch which ends with the given block.
+    /// The loads of the last block are saved along with the block, once the epoch is finalized,
+    /// so they are left out.
+    fn summarize_shard_loads(
+        &mut self,
+        last_block_info: &BlockInfo,
+        num_shards: u64,
+    ) -> Result<Vec<EpochShardLoad>, EpochError> {
+        let mut loads: Vec<EpochShardLoad> =
+            (0..num_shards).map(|_| EpochShardLoad::default()).collect();
+        let epoch_first_block = *last_block_info.epoch_first_block();
+        let mut block_hash = *last_block_info.hash();
+        while block_hash != epoch_first_block {
+            block_hash = *self.get_block_info(&block_hash)?.prev_hash();
+            for shard_id in 0..num_shards {
+                let shard_load: ShardLoad = self
+                    .store
+                    .get_ser(ColShardLoad, &get_block_shard_id(&block_hash, shard_id))?
+                    .ok_or_else(|| {
+                        EpochError::ShardingError(format!(
+                            "Missing load of shard {} in block {}",
+                            shard_id, block_hash
+                        ))
+                    })?;
+                let load = &mut loads[shard_id as usize];
+                if let Some(gas_used) = shard_load.gas_used {
+                    load.gas_used = load.gas_used.saturating_add(gas_used);
+                    load.num_chunks += 1;
+                }
+                if shard_load.state.is_some() {
+                    load.state = shard_load.state;
+                }
+            }
+        }
+        Ok(loads)
+    }
+}
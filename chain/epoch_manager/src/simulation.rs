@@ -0,0 +1,146 @@
+//! Simulation of validator selection for "what if" questions, e.g. what would happen to a pool if
+//! it restaked a different amount.
+//!
+//! The simulation assumes the current epoch ends with a given block and runs the same summary,
+//! reward calculation and validator selection as `EpochManager::finalize_epoch`, on top of the
+//! proposals made so far with hypothetical proposals applied. Nothing is written to the store.
+
+use near_crypto::PublicKey;
+use near_primitives::epoch_manager::block_info::BlockInfo;
+use near_primitives::epoch_manager::epoch_info::EpochInfo;
+use near_primitives::errors::EpochError;
+use near_primitives::hash::CryptoHash;
+use near_primitives::types::validator_stake::ValidatorStake;
+use near_primitives::types::{AccountId, Balance, BlockHeight, EpochId};
+
+use crate::{EpochManager, RngSeed};
+
+/// Hypothetical proposal applied on top of the proposals made in the epoch so far. Replaces the
+/// latest proposal of the account, if any.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProposalOverride {
+    pub account_id: AccountId,
+    /// Total stake of the proposal, zero to unstake.
+    pub stake: Balance,
+    /// Key to stake with. If not given, the latest proposal or the current stake of the account is
+    /// reused with the new amount, otherwise a block producer proposal is made.
+    pub public_key: Option<PublicKey>,
+}
+
+/// Outcome of [`EpochManager::simulate_epoch_end`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EpochSimulation {
+    /// Epoch which is assumed to end.
+    pub epoch_id: EpochId,
+    /// Height of the block the epoch is assumed to end with.
+    pub last_block_height: BlockHeight,
+    /// Proposals the selection was run on, with the overrides applied.
+    pub proposals: Vec<ValidatorStake>,
+    /// Info of the epoch after next, as selected at the end of the epoch. Contains the selected
+    /// validators and their seats, seat price, kickouts and rewards for the epoch which ends.
+    pub epoch_info: EpochInfo,
+}
+
+impl EpochManager {
+    /// Simulates the end of the epoch of `last_block_hash` as if it was the last block of the
+    /// epoch, with `overrides` applied to the proposals made in the epoch.
+    ///
+    /// Block and chunk production stats are taken as aggregated up to `last_block_hash`. Rewards
+    /// are computed for the duration of a full epoch, extrapolated from the time it took to produce
+    /// the blocks so far.
+    pub fn simulate_epoch_end(
+        &mut self,
+        last_block_hash: &CryptoHash,
+        overrides: Vec<ProposalOverride>,
+        rng_seed: RngSeed,
+    ) -> Result<EpochSimulation, EpochError> {
+        let block_info = self.get_block_info(last_block_hash)?.clone();
+        let epoch_id = block_info.epoch_id().clone();
+        let next_epoch_id = self.get_next_epoch_id_from_info(&block_info)?;
+        let next_epoch_info = self.get_epoch_info(&next_epoch_id)?.clone();
+
+        let mut aggregator =
+            self.get_and_update_epoch_info_aggregator(&epoch_id, last_block_hash, true)?;
+        for ProposalOverride { account_id, stake, public_key } in overrides {
+            let current = match aggregator.all_proposals.get(&account_id) {
+                Some(proposal) => Some(proposal.clone()),
+                None => self.current_stake(&epoch_id, &next_epoch_id, &account_id)?,
+            };
+            let proposal = match (current, public_key) {
+                (Some(mut proposal), None) => {
+                    *proposal.stake_mut() = stake;
+                    proposal
+                }
+                (None, None) => {
+                    return Err(EpochError::NotAValidator(account_id, next_epoch_id));
+                }
+                (_, Some(public_key)) => ValidatorStake::new(
+                    account_id.clone(),
+                    public_key,
+                    stake,
+                    #[cfg(feature = "protocol_feature_chunk_only_producers")]
+                    false,
+                ),
+            };
+            aggregator.all_proposals.insert(account_id, proposal);
+        }
+
+        let epoch_summary = self.summarize_epoch(&block_info, last_block_hash, aggregator)?;
+        let proposals = epoch_summary.all_proposals.clone();
+        let epoch_duration = self.expected_epoch_duration(&block_info)?;
+        let next_version = epoch_summary.next_version;
+        let epoch_shard_config =
+            self.choose_next_next_epoch_shard_config(&block_info, next_version)?;
+        let next_next_epoch_config =
+            self.epoch_config_with_shard_config(next_version, epoch_shard_config.as_ref());
+        let epoch_info = self.select_next_next_epoch_info(
+            &block_info,
+            &next_epoch_info,
+            &next_next_epoch_config,
+            epoch_summary,
+            epoch_duration,
+            rng_seed,
+        )?;
+        Ok(EpochSimulation {
+            epoch_id,
+            last_block_height: *block_info.height(),
+            proposals,
+            epoch_info,
+        })
+    }
+
+    /// Stake of the account as of the next epoch, falling back to the current one.
+    fn current_stake(
+        &mut self,
+        epoch_id: &EpochId,
+        next_epoch_id: &EpochId,
+        account_id: &AccountId,
+    ) -> Result<Option<ValidatorStake>, EpochError> {
+        for epoch_id in [next_epoch_id, epoch_id] {
+            let epoch_info = self.get_epoch_info(epoch_id)?;
+            if let Some(stake) = epoch_info
+                .get_validator_by_account(account_id)
+                .or_else(|| epoch_info.get_fisherman_by_account(account_id))
+            {
+                return Ok(Some(stake));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Duration of the epoch of the given block in nanoseconds, assuming the rest of the epoch is
+    /// produced at the same pace.
+    fn expected_epoch_duration(&mut self, block_info: &BlockInfo) -> Result<u64, EpochError> {
+        let protocol_version = self.get_epoch_info(block_info.epoch_id())?.protocol_version();
+        let epoch_length = self.config.for_protocol_version(protocol_version).epoch_length;
+        let last_epoch_last_block_hash =
+            *self.get_block_info(block_info.epoch_first_block())?.prev_hash();
+        let last_block_in_last_epoch = self.get_block_info(&last_epoch_last_block_hash)?;
+        let elapsed = block_info
+            .timestamp_nanosec()
+            .saturating_sub(last_block_in_last_epoch.timestamp_nanosec());
+        let num_blocks =
+            block_info.height().saturating_sub(*last_block_in_last_epoch.height()).max(1);
+        Ok((elapsed as u128 * epoch_length as u128 / num_blocks as u128) as u64)
+    }
+}
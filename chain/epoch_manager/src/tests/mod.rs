@@ -1 +1,5 @@
 mod random_epochs;
+#[cfg(all(test, feature = "protocol_feature_dynamic_resharding", feature = "nightly_protocol"))]
+mod resharding;
+#[cfg(test)]
+mod simulation;
@@ -0,0 +1,159 @@
+use near_primitives::epoch_manager::{ReshardingConfig, ShardConfig, ShardLoad, ShardStateLoad};
+use near_primitives::errors::EpochError;
+use near_primitives::hash::CryptoHash;
+use near_primitives::shard_layout::ShardLayout;
+use near_primitives::types::AccountId;
+use near_primitives::utils::get_block_shard_id;
+use near_primitives::version::PROTOCOL_VERSION;
+use near_store::test_utils::create_test_store;
+use near_store::ColShardLoad;
+
+use crate::test_utils::{default_reward_calculator, epoch_config, hash_range, record_block, stake};
+use crate::EpochManager;
+
+#[test]
+fn test_split_overloaded_shard() {
+    let store = create_test_store();
+    let resharding_config = ReshardingConfig {
+        max_avg_gas_used_per_chunk: 100,
+        max_state_size: u64::MAX,
+        num_overloaded_epochs_to_split: 2,
+        max_num_shards: 2,
+        ..ReshardingConfig::default()
+    };
+    let config = epoch_config(5, 1, 2, 0, 0, 0, 0, None).with_resharding_config(resharding_config);
+    let validators = vec![
+        stake("test1".parse().unwrap(), 1_000_000),
+        stake("test2".parse().unwrap(), 1_000_000),
+    ];
+    let mut epoch_manager = EpochManager::new(
+        store.clone(),
+        config.clone(),
+        PROTOCOL_VERSION,
+        default_reward_calculator(),
+        validators.clone(),
+    )
+    .unwrap();
+    let median_account_id: AccountId = "test5".parse().unwrap();
+    let overloaded_shard_load = ShardLoad {
+        gas_used: Some(1000),
+        state: Some(ShardStateLoad {
+            state_size: 0,
+            median_account_id: Some(median_account_id.clone()),
+        }),
+    };
+    let shard_load = ShardLoad { gas_used: Some(10), state: None };
+
+    let h = hash_range(40);
+    record_block(&mut epoch_manager, CryptoHash::default(), h[0], 0, vec![]);
+    let mut num_shards = vec![];
+    for i in 1..h.len() {
+        // Only the first shard is overloaded.
+        let mut store_update = store.store_update();
+        store_update
+            .set_ser(ColShardLoad, &get_block_shard_id(&h[i], 0), &overloaded_shard_load)
+            .unwrap();
+        store_update.set_ser(ColShardLoad, &get_block_shard_id(&h[i], 1), &shard_load).unwrap();
+        store_update.commit().unwrap();
+        record_block(&mut epoch_manager, h[i - 1], h[i], i as u64, vec![]);
+        let epoch_id = epoch_manager.get_epoch_id(&h[i]).unwrap();
+        num_shards.push(epoch_manager.get_shard_layout(&epoch_id).unwrap().num_shards());
+    }
+    // The shard is split once it has been overloaded for two epochs, two epochs later, and is
+    // not split further.
+    assert_eq!(num_shards[..15], [1; 15]);
+    assert_eq!(num_shards.last(), Some(&2));
+    assert!(num_shards.windows(2).all(|w| w[0] <= w[1]));
+
+    let epoch_id = epoch_manager.get_epoch_id(h.last().unwrap()).unwrap();
+    let expected_layout = ShardLayout::v0(1, 0).split_shard(0, median_account_id).unwrap();
+    let epoch_config = epoch_manager.get_epoch_config(&epoch_id).unwrap();
+    assert_eq!(epoch_config.shard_layout, expected_layout);
+    assert_eq!(epoch_config.num_block_producer_seats_per_shard, vec![2, 2]);
+    assert_eq!(
+        epoch_manager.get_epoch_info(&epoch_id).unwrap().chunk_producers_settlement().len(),
+        2
+    );
+
+    // An epoch can't be finalized without the loads of its blocks.
+    let mut store_update = store.store_update();
+    store_update.delete(ColShardLoad, &get_block_shard_id(&h[h.len() - 2], 0));
+    store_update.commit().unwrap();
+    let last_block_info = epoch_manager.get_block_info(h.last().unwrap()).unwrap().clone();
+    assert!(matches!(
+        epoch_manager.choose_next_next_epoch_shard_config(&last_block_info, PROTOCOL_VERSION),
+        Err(EpochError::ShardingError(_))
+    ));
+
+    // The layout is persisted along with the epoch.
+    let mut epoch_manager =
+        EpochManager::new(store, config, PROTOCOL_VERSION, default_reward_calculator(), validators)
+            .unwrap();
+    assert_eq!(epoch_manager.get_shard_layout(&epoch_id).unwrap(), &expected_layout);
+}
+
+#[test]
+fn test_merge_underloaded_shards() {
+    let store = create_test_store();
+    let resharding_config = ReshardingConfig {
+        min_avg_gas_used_per_chunk: 100,
+        min_state_size: 100,
+        num_underloaded_epochs_to_merge: 2,
+        min_num_shards: 1,
+        ..ReshardingConfig::default()
+    };
+    let shard_layout = ShardLayout::v1(vec![], vec!["test5".parse().unwrap()], None, 1);
+    let shard_config = ShardConfig {
+        num_block_producer_seats_per_shard: vec![2, 2],
+        avg_hidden_validator_seats_per_shard: vec![0, 0],
+        shard_layout: shard_layout.clone(),
+    };
+    let config = epoch_config(5, 2, 2, 0, 0, 0, 0, Some(shard_config))
+        .with_resharding_config(resharding_config);
+    let validators = vec![
+        stake("test1".parse().unwrap(), 1_000_000),
+        stake("test2".parse().unwrap(), 1_000_000),
+    ];
+    let mut epoch_manager = EpochManager::new(
+        store.clone(),
+        config,
+        PROTOCOL_VERSION,
+        default_reward_calculator(),
+        validators,
+    )
+    .unwrap();
+    let underloaded_shard_load = ShardLoad {
+        gas_used: Some(10),
+        state: Some(ShardStateLoad { state_size: 10, median_account_id: None }),
+    };
+
+    let h = hash_range(40);
+    record_block(&mut epoch_manager, CryptoHash::default(), h[0], 0, vec![]);
+    let mut num_shards = vec![];
+    for i in 1..h.len() {
+        let mut store_update = store.store_update();
+        for shard_id in 0..2 {
+            store_update
+                .set_ser(
+                    ColShardLoad,
+                    &get_block_shard_id(&h[i], shard_id),
+                    &underloaded_shard_load,
+                )
+                .unwrap();
+        }
+        store_update.commit().unwrap();
+        record_block(&mut epoch_manager, h[i - 1], h[i], i as u64, vec![]);
+        let epoch_id = epoch_manager.get_epoch_id(&h[i]).unwrap();
+        num_shards.push(epoch_manager.get_shard_layout(&epoch_id).unwrap().num_shards());
+    }
+    // Both shards are merged once they have been underloaded for two epochs, two epochs later.
+    assert_eq!(num_shards[..15], [2; 15]);
+    assert_eq!(num_shards.last(), Some(&1));
+    assert!(num_shards.windows(2).all(|w| w[0] >= w[1]));
+
+    let epoch_id = epoch_manager.get_epoch_id(h.last().unwrap()).unwrap();
+    let epoch_config = epoch_manager.get_epoch_config(&epoch_id).unwrap();
+    assert_eq!(epoch_config.shard_layout, shard_layout.merge_shards(0).unwrap());
+    assert_eq!(epoch_config.num_block_producer_seats_per_shard, vec![2]);
+    assert_eq!(epoch_config.avg_hidden_validator_seats_per_shard, vec![0]);
+}
@@ -0,0 +1,94 @@
+use std::collections::HashMap;
+
+use near_crypto::{KeyType, SecretKey};
+use near_primitives::epoch_manager::epoch_info::EpochInfo;
+use near_primitives::errors::EpochError;
+use near_primitives::hash::CryptoHash;
+use near_primitives::types::{AccountId, EpochId, ValidatorKickoutReason};
+
+use crate::test_utils::{hash_range, record_block, setup_default_epoch_manager, stake};
+use crate::ProposalOverride;
+
+fn validator_stakes(epoch_info: &EpochInfo) -> Vec<(String, u128)> {
+    let mut stakes: Vec<_> =
+        epoch_info.validators_iter().map(|v| (v.account_id().to_string(), v.stake())).collect();
+    stakes.sort();
+    stakes
+}
+
+#[test]
+fn test_simulation_matches_epoch_finalization() {
+    let amount_staked = 1_000_000;
+    let validators =
+        vec![("test1".parse().unwrap(), amount_staked), ("test2".parse().unwrap(), amount_staked)];
+    let mut epoch_manager = setup_default_epoch_manager(validators, 2, 1, 3, 0, 90, 60);
+    let h = hash_range(3);
+    record_block(&mut epoch_manager, CryptoHash::default(), h[0], 0, vec![]);
+    record_block(
+        &mut epoch_manager,
+        h[0],
+        h[1],
+        1,
+        vec![stake("test1".parse().unwrap(), 0), stake("test3".parse().unwrap(), amount_staked)],
+    );
+    // `h[2]` is the last block of the epoch.
+    record_block(&mut epoch_manager, h[1], h[2], 2, vec![]);
+
+    let simulation = epoch_manager.simulate_epoch_end(&h[2], vec![], [0; 32]).unwrap();
+    assert_eq!(simulation.epoch_id, epoch_manager.get_epoch_id(&h[2]).unwrap());
+    assert_eq!(simulation.last_block_height, 2);
+    assert_eq!(&simulation.epoch_info, epoch_manager.get_epoch_info(&EpochId(h[2])).unwrap());
+}
+
+#[test]
+fn test_simulation_with_overrides() {
+    let amount_staked = 1_000_000;
+    let validators =
+        vec![("test1".parse().unwrap(), amount_staked), ("test2".parse().unwrap(), amount_staked)];
+    let mut epoch_manager = setup_default_epoch_manager(validators, 2, 1, 3, 0, 90, 60);
+    let h = hash_range(3);
+    record_block(&mut epoch_manager, CryptoHash::default(), h[0], 0, vec![]);
+    record_block(&mut epoch_manager, h[0], h[1], 1, vec![]);
+
+    let test3: AccountId = "test3".parse().unwrap();
+    let overrides = vec![
+        ProposalOverride { account_id: "test1".parse().unwrap(), stake: 0, public_key: None },
+        ProposalOverride {
+            account_id: "test2".parse().unwrap(),
+            stake: 2 * amount_staked,
+            public_key: None,
+        },
+        ProposalOverride {
+            account_id: test3.clone(),
+            stake: amount_staked,
+            public_key: Some(SecretKey::from_seed(KeyType::ED25519, "test3").public_key()),
+        },
+    ];
+    let simulation = epoch_manager.simulate_epoch_end(&h[1], overrides, [0; 32]).unwrap();
+    assert_eq!(simulation.proposals.len(), 3);
+    assert_eq!(
+        validator_stakes(&simulation.epoch_info),
+        vec![("test2".to_string(), 2 * amount_staked), ("test3".to_string(), amount_staked)]
+    );
+    assert_eq!(
+        simulation.epoch_info.validator_kickout(),
+        &HashMap::from([("test1".parse().unwrap(), ValidatorKickoutReason::Unstaked)])
+    );
+
+    // An account which doesn't stake can't propose without a key.
+    let overrides =
+        vec![ProposalOverride { account_id: test3.clone(), stake: 1, public_key: None }];
+    assert_eq!(
+        epoch_manager.simulate_epoch_end(&h[1], overrides, [0; 32]),
+        Err(EpochError::NotAValidator(test3, epoch_manager.get_next_epoch_id(&h[1]).unwrap()))
+    );
+
+    // The simulation doesn't change what is actually selected.
+    record_block(&mut epoch_manager, h[1], h[2], 2, vec![]);
+    let epoch_info = epoch_manager.get_epoch_info(&EpochId(h[2])).unwrap();
+    assert_eq!(
+        validator_stakes(epoch_info),
+        vec![("test1".to_string(), amount_staked), ("test2".to_string(), amount_staked)]
+    );
+    assert!(epoch_info.validator_kickout().is_empty());
+}
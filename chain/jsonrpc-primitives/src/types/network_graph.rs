@@ -0,0 +1,65 @@
+use near_network_primitives::types::{Edge, EdgeState};
+use near_primitives::network::PeerId;
+use near_primitives::types::AccountId;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RpcNetworkGraphEdge {
+    pub peer0: PeerId,
+    pub peer1: PeerId,
+    pub nonce: u64,
+    /// Whether the edge was removed, i.e. the connection between the peers was closed.
+    pub removed: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RpcNetworkGraphResponse {
+    /// Edges of the network graph known to the node.
+    pub edges: Vec<RpcNetworkGraphEdge>,
+    /// Peers announced by accounts.
+    pub account_peers: BTreeMap<AccountId, PeerId>,
+    /// Directly connected peers on the shortest paths to each reachable peer.
+    pub next_hops: BTreeMap<PeerId, Vec<PeerId>>,
+}
+
+#[derive(thiserror::Error, Debug, Serialize, Deserialize)]
+#[serde(tag = "name", content = "info", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum RpcNetworkGraphError {
+    #[error("Internal error: {error_message}")]
+    InternalError { error_message: String },
+}
+
+impl From<&Edge> for RpcNetworkGraphEdge {
+    fn from(edge: &Edge) -> Self {
+        let (peer0, peer1) = edge.key().clone();
+        Self { peer0, peer1, nonce: edge.nonce(), removed: edge.edge_type() == EdgeState::Removed }
+    }
+}
+
+impl From<actix::MailboxError> for RpcNetworkGraphError {
+    fn from(error: actix::MailboxError) -> Self {
+        Self::InternalError { error_message: error.to_string() }
+    }
+}
+
+impl From<String> for RpcNetworkGraphError {
+    fn from(error_message: String) -> Self {
+        Self::InternalError { error_message }
+    }
+}
+
+impl From<RpcNetworkGraphError> for crate::errors::RpcError {
+    fn from(error: RpcNetworkGraphError) -> Self {
+        let error_data = match serde_json::to_value(error) {
+            Ok(value) => value,
+            Err(err) => {
+                return Self::new_internal_error(
+                    None,
+                    format!("Failed to serialize RpcNetworkGraphError: {:?}", err),
+                )
+            }
+        };
+        Self::new_internal_or_handler_error(Some(error_data.clone()), error_data)
+    }
+}
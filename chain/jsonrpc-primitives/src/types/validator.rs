@@ -32,6 +32,19 @@ pub struct RpcValidatorResponse {
     pub validator_info: near_primitives::views::EpochValidatorInfo,
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RpcValidatorPerformanceRequest {
+    #[serde(flatten)]
+    pub epoch_reference: near_primitives::types::EpochReference,
+    pub account_id: near_primitives::types::AccountId,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RpcValidatorPerformanceResponse {
+    #[serde(flatten)]
+    pub validator_performance: near_primitives::views::ValidatorPerformanceView,
+}
+
 impl From<near_client_primitives::types::GetValidatorInfoError> for RpcValidatorError {
     fn from(error: near_client_primitives::types::GetValidatorInfoError) -> Self {
         match error {
@@ -85,6 +98,12 @@ impl RpcValidatorsOrderedRequest {
     }
 }
 
+impl RpcValidatorPerformanceRequest {
+    pub fn parse(value: Option<Value>) -> Result<Self, crate::errors::RpcParseError> {
+        Ok(crate::utils::parse_params::<RpcValidatorPerformanceRequest>(value)?)
+    }
+}
+
 impl From<RpcValidatorError> for crate::errors::RpcError {
     fn from(error: RpcValidatorError) -> Self {
         let error_data = match &error {
@@ -108,3 +127,43 @@ impl From<RpcValidatorError> for crate::errors::RpcError {
         Self::new_internal_or_handler_error(error_data, error_data_value)
     }
 }
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RpcPendingValidatorKeyResponse {
+    /// Key the validator switches to once it takes effect, `None` if no rotation is pending.
+    pub public_key: Option<near_crypto::PublicKey>,
+}
+
+#[derive(thiserror::Error, Debug, Serialize, Deserialize)]
+#[serde(tag = "name", content = "info", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum RpcPendingValidatorKeyError {
+    #[error("Failed to load pending validator key: {error_message}")]
+    InternalError { error_message: String },
+}
+
+impl From<actix::MailboxError> for RpcPendingValidatorKeyError {
+    fn from(error: actix::MailboxError) -> Self {
+        Self::InternalError { error_message: error.to_string() }
+    }
+}
+
+impl From<String> for RpcPendingValidatorKeyError {
+    fn from(error_message: String) -> Self {
+        Self::InternalError { error_message }
+    }
+}
+
+impl From<RpcPendingValidatorKeyError> for crate::errors::RpcError {
+    fn from(error: RpcPendingValidatorKeyError) -> Self {
+        let error_data = match serde_json::to_value(error) {
+            Ok(value) => value,
+            Err(err) => {
+                return Self::new_internal_error(
+                    None,
+                    format!("Failed to serialize RpcPendingValidatorKeyError: {:?}", err),
+                )
+            }
+        };
+        Self::new_internal_or_handler_error(Some(error_data.clone()), error_data)
+    }
+}
@@ -243,6 +243,15 @@ impl JsonRpcClient {
         call_method(&self.client, &self.server_addr, "EXPERIMENTAL_validators_ordered", request)
     }
 
+    #[allow(non_snake_case)]
+    pub fn EXPERIMENTAL_validator_performance(
+        &self,
+        request: near_jsonrpc_primitives::types::validator::RpcValidatorPerformanceRequest,
+    ) -> RpcRequest<near_jsonrpc_primitives::types::validator::RpcValidatorPerformanceResponse>
+    {
+        call_method(&self.client, &self.server_addr, "EXPERIMENTAL_validator_performance", request)
+    }
+
     #[allow(non_snake_case)]
     pub fn EXPERIMENTAL_receipt(
         &self,
@@ -251,6 +260,13 @@ impl JsonRpcClient {
         call_method(&self.client, &self.server_addr, "EXPERIMENTAL_receipt", request)
     }
 
+    #[allow(non_snake_case)]
+    pub fn EXPERIMENTAL_load_pending_validator_key(
+        &self,
+    ) -> RpcRequest<near_jsonrpc_primitives::types::validator::RpcPendingValidatorKeyResponse> {
+        call_method(&self.client, &self.server_addr, "EXPERIMENTAL_load_pending_validator_key", ())
+    }
+
     #[allow(non_snake_case)]
     pub fn EXPERIMENTAL_protocol_config(
         &self,
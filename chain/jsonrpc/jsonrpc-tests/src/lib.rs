@@ -9,8 +9,8 @@ use near_client::ViewClientActor;
 use near_jsonrpc::{start_http, RpcConfig};
 use near_jsonrpc_primitives::message::{from_slice, Message};
 use near_network::test_utils::open_port;
-#[cfg(feature = "test_features")]
 use near_network::test_utils::test_features::make_peer_manager_routing_table_addr_pair;
+use near_network::{PeerManagerActor, RoutingTableActor};
 use near_primitives::types::NumBlocks;
 
 pub static TEST_GENESIS_CONFIG: Lazy<GenesisConfig> = Lazy::new(|| {
@@ -31,6 +31,26 @@ pub fn start_all_with_validity_period_and_no_epoch_sync(
     transaction_validity_period: NumBlocks,
     enable_doomslug: bool,
 ) -> (Addr<ViewClientActor>, String) {
+    let (view_client_addr, _, _, addr) =
+        start_all_with_rpc_config(node_type, transaction_validity_period, enable_doomslug, false);
+    (view_client_addr, addr)
+}
+
+/// Starts the node with debug methods enabled, and returns the addresses of its network actors.
+pub fn start_all_with_debug_rpc(
+    node_type: NodeType,
+) -> (Addr<PeerManagerActor>, Addr<RoutingTableActor>, String) {
+    let (_, peer_manager_addr, routing_table_addr, addr) =
+        start_all_with_rpc_config(node_type, 100, false, true);
+    (peer_manager_addr, routing_table_addr, addr)
+}
+
+fn start_all_with_rpc_config(
+    node_type: NodeType,
+    transaction_validity_period: NumBlocks,
+    enable_doomslug: bool,
+    enable_debug_rpc: bool,
+) -> (Addr<ViewClientActor>, Addr<PeerManagerActor>, Addr<RoutingTableActor>, String) {
     let (client_addr, view_client_addr) = setup_no_network_with_validity_period_and_no_epoch_sync(
         vec!["test1".parse().unwrap(), "test2".parse().unwrap()],
         if let NodeType::Validator = node_type {
@@ -45,20 +65,17 @@ pub fn start_all_with_validity_period_and_no_epoch_sync(
 
     let addr = format!("127.0.0.1:{}", open_port());
 
-    #[cfg(feature = "test_features")]
     let (peer_manager_addr, routing_table_addr) = make_peer_manager_routing_table_addr_pair();
 
     start_http(
-        RpcConfig::new(&addr),
+        RpcConfig { enable_debug_rpc, ..RpcConfig::new(&addr) },
         TEST_GENESIS_CONFIG.clone(),
         client_addr.clone(),
         view_client_addr.clone(),
-        #[cfg(feature = "test_features")]
-        peer_manager_addr,
-        #[cfg(feature = "test_features")]
-        routing_table_addr,
+        peer_manager_addr.clone(),
+        routing_table_addr.clone(),
     );
-    (view_client_addr, addr)
+    (view_client_addr, peer_manager_addr, routing_table_addr, addr)
 }
 
 #[macro_export]
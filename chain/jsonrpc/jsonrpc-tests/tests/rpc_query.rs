@@ -5,15 +5,24 @@ use futures::{future, FutureExt};
 use serde_json::json;
 
 use near_actix_test_utils::run_actix;
-use near_crypto::{KeyType, PublicKey, Signature};
+use near_crypto::{KeyType, PublicKey, SecretKey, Signature};
 use near_jsonrpc::client::{new_client, ChunkId};
+use near_jsonrpc_primitives::types::network_graph::RpcNetworkGraphResponse;
 use near_jsonrpc_primitives::types::query::QueryResponseKind;
-use near_jsonrpc_primitives::types::validator::RpcValidatorsOrderedRequest;
+use near_jsonrpc_primitives::types::validator::{
+    RpcValidatorPerformanceRequest, RpcValidatorsOrderedRequest,
+};
 use near_logger_utils::init_test_logger;
 use near_network::test_utils::WaitOrTimeoutActor;
+use near_network::types::{NetworkRequests, PeerManagerMessageRequest};
+use near_network::RoutingTableMessages;
+use near_network_primitives::types::Edge;
 use near_primitives::account::{AccessKey, AccessKeyPermission};
 use near_primitives::hash::CryptoHash;
-use near_primitives::types::{BlockId, BlockReference, EpochId, ShardId, SyncCheckpoint};
+use near_primitives::network::{AnnounceAccount, PeerId};
+use near_primitives::types::{
+    BlockId, BlockReference, EpochId, EpochReference, ShardId, SyncCheckpoint,
+};
 use near_primitives::views::QueryRequest;
 
 use near_jsonrpc_tests::{self as test_utils, test_with_client};
@@ -466,6 +475,24 @@ fn test_validators_ordered() {
     });
 }
 
+#[test]
+fn test_validator_performance() {
+    test_with_client!(test_utils::NodeType::Validator, client, async move {
+        let performance = client
+            .EXPERIMENTAL_validator_performance(RpcValidatorPerformanceRequest {
+                epoch_reference: EpochReference::Latest,
+                account_id: "test1".parse().unwrap(),
+            })
+            .await
+            .unwrap()
+            .validator_performance;
+        // The statuses themselves are checked against a real epoch manager in
+        // `integration-tests`, this only checks that the request is served.
+        assert_eq!(performance.account_id, "test1".parse().unwrap());
+        assert!(performance.epoch_start_height <= performance.last_block_height);
+    });
+}
+
 /// Retrieve genesis config via JSON RPC.
 /// WARNING: Be mindful about changing genesis structure as it is part of the public protocol!
 #[test]
@@ -554,6 +581,123 @@ fn test_invalid_methods() {
     });
 }
 
+/// Debug methods are only available if enabled in the config.
+#[test]
+fn test_debug_methods_disabled() {
+    test_with_client!(test_utils::NodeType::NonValidator, client, async move {
+        for method in ["network_graph", "EXPERIMENTAL_load_pending_validator_key"] {
+            let json = serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": "dontcare",
+                "method": method,
+                "params": serde_json::json!([]),
+            });
+            let response = &mut client
+                .client
+                .post(&client.server_addr)
+                .insert_header(("Content-Type", "application/json"))
+                .send_json(&json)
+                .await
+                .unwrap();
+
+            let response =
+                serde_json::from_value::<serde_json::Value>(response.json().await.unwrap())
+                    .unwrap();
+            assert_eq!(response["error"]["code"], serde_json::json!(-32601), "{}", method);
+        }
+    });
+}
+
+/// The network graph reports the edges known to the routing table, the announced accounts and the
+/// next hops computed from the edges.
+#[test]
+fn test_network_graph() {
+    init_test_logger();
+
+    run_actix(async {
+        let (peer_manager_addr, routing_table_addr, addr) =
+            test_utils::start_all_with_debug_rpc(test_utils::NodeType::NonValidator);
+        // The node has the network key of `test2`.
+        let me = PeerId::new(SecretKey::from_seed(KeyType::ED25519, "test2").public_key());
+        let peer1 = PeerId::new(SecretKey::from_seed(KeyType::ED25519, "peer1").public_key());
+        let peer2 = PeerId::new(SecretKey::from_seed(KeyType::ED25519, "peer2").public_key());
+        let edges = vec![
+            Edge::new(me.clone(), peer1.clone(), 1, Signature::default(), Signature::default()),
+            Edge::new(peer1.clone(), peer2.clone(), 3, Signature::default(), Signature::default()),
+        ];
+        routing_table_addr.do_send(RoutingTableMessages::AddVerifiedEdges { edges });
+        peer_manager_addr.do_send(PeerManagerMessageRequest::NetworkRequests(
+            NetworkRequests::AnnounceAccount(AnnounceAccount {
+                account_id: "test1".parse().unwrap(),
+                peer_id: peer2.clone(),
+                epoch_id: EpochId::default(),
+                signature: Signature::default(),
+            }),
+        ));
+
+        let client = new_client(&format!("http://{}", addr));
+        // The peer manager takes the next hops from the routing table periodically.
+        WaitOrTimeoutActor::new(
+            Box::new(move |_| {
+                let (me, peer1, peer2) = (me.clone(), peer1.clone(), peer2.clone());
+                actix::spawn(
+                    test_utils::call_method::<RpcNetworkGraphResponse>(
+                        &client.client,
+                        &client.server_addr,
+                        "network_graph",
+                        json!([]),
+                    )
+                    .then(move |res| {
+                        let graph = res.unwrap();
+                        if graph.next_hops.is_empty() {
+                            return future::ready(());
+                        }
+                        let mut edges: Vec<_> = graph
+                            .edges
+                            .iter()
+                            .map(|edge| {
+                                (edge.peer0.clone(), edge.peer1.clone(), edge.nonce, edge.removed)
+                            })
+                            .collect();
+                        edges.sort();
+                        let mut expected_edges = vec![
+                            (
+                                std::cmp::min(&me, &peer1).clone(),
+                                std::cmp::max(&me, &peer1).clone(),
+                                1,
+                                false,
+                            ),
+                            (
+                                std::cmp::min(&peer1, &peer2).clone(),
+                                std::cmp::max(&peer1, &peer2).clone(),
+                                3,
+                                false,
+                            ),
+                        ];
+                        expected_edges.sort();
+                        assert_eq!(edges, expected_edges);
+                        assert_eq!(
+                            graph.account_peers,
+                            vec![("test1".parse().unwrap(), peer2.clone())].into_iter().collect()
+                        );
+                        assert_eq!(
+                            graph.next_hops,
+                            vec![(peer1.clone(), vec![peer1.clone()]), (peer2, vec![peer1])]
+                                .into_iter()
+                                .collect()
+                        );
+                        System::current().stop();
+                        future::ready(())
+                    }),
+                );
+            }),
+            100,
+            10000,
+        )
+        .start();
+    });
+}
+
 #[test]
 fn test_get_chunk_with_object_in_params() {
     test_with_client!(test_utils::NodeType::NonValidator, client, async move {
@@ -17,8 +17,8 @@ use near_chain_configs::GenesisConfig;
 use near_client::{
     ClientActor, GetBlock, GetBlockProof, GetChunk, GetExecutionOutcome, GetGasPrice,
     GetNetworkInfo, GetNextLightClientBlock, GetProtocolConfig, GetReceipt, GetStateChanges,
-    GetStateChangesInBlock, GetValidatorInfo, GetValidatorOrdered, Query, Status, TxStatus,
-    TxStatusError, ViewClientActor,
+    GetStateChangesInBlock, GetValidatorInfo, GetValidatorOrdered, GetValidatorPerformance,
+    LoadPendingValidatorKey, Query, Status, TxStatus, TxStatusError, ViewClientActor,
 };
 pub use near_jsonrpc_client as client;
 use near_jsonrpc_primitives::errors::RpcError;
@@ -70,6 +70,10 @@ pub struct RpcConfig {
     pub polling_config: RpcPollingConfig,
     #[serde(default)]
     pub limits_config: RpcLimitsConfig,
+    // Enables debug and node operator methods, such as `network_graph` and
+    // `EXPERIMENTAL_load_pending_validator_key`. These shouldn't be exposed publicly.
+    #[serde(default)]
+    pub enable_debug_rpc: bool,
 }
 
 impl Default for RpcConfig {
@@ -80,6 +84,7 @@ impl Default for RpcConfig {
             cors_allowed_origins: vec!["*".to_owned()],
             polling_config: Default::default(),
             limits_config: Default::default(),
+            enable_debug_rpc: false,
         }
     }
 }
@@ -199,10 +204,9 @@ struct JsonRpcHandler {
     view_client_addr: Addr<ViewClientActor>,
     polling_config: RpcPollingConfig,
     genesis_config: GenesisConfig,
-    #[cfg(feature = "test_features")]
     peer_manager_addr: Addr<near_network::PeerManagerActor>,
-    #[cfg(feature = "test_features")]
     routing_table_addr: Addr<near_network::RoutingTableActor>,
+    enable_debug_rpc: bool,
 }
 
 impl JsonRpcHandler {
@@ -432,6 +436,11 @@ impl JsonRpcHandler {
                 serde_json::to_value(next_light_client_block)
                     .map_err(|err| RpcError::serialization_error(err.to_string()))
             }
+            "network_graph" if self.enable_debug_rpc => {
+                let network_graph_response = self.network_graph().await?;
+                serde_json::to_value(network_graph_response)
+                    .map_err(|err| RpcError::serialization_error(err.to_string()))
+            }
             "network_info" => {
                 let network_info_response = self.network_info().await?;
                 serde_json::to_value(network_info_response)
@@ -515,6 +524,11 @@ impl JsonRpcHandler {
                 serde_json::to_value(rpc_light_client_execution_proof_response)
                     .map_err(|err| RpcError::serialization_error(err.to_string()))
             }
+            "EXPERIMENTAL_load_pending_validator_key" if self.enable_debug_rpc => {
+                let pending_validator_key = self.load_pending_validator_key().await?;
+                serde_json::to_value(pending_validator_key)
+                    .map_err(|err| RpcError::serialization_error(err.to_string()))
+            }
             "EXPERIMENTAL_protocol_config" => {
                 let rpc_protocol_config_request =
                     near_jsonrpc_primitives::types::config::RpcProtocolConfigRequest::parse(
@@ -540,6 +554,16 @@ impl JsonRpcHandler {
                 serde_json::to_value(rpc_transaction_response)
                     .map_err(|err| RpcError::serialization_error(err.to_string()))
             }
+            "EXPERIMENTAL_validator_performance" => {
+                let rpc_validator_performance_request =
+                    near_jsonrpc_primitives::types::validator::RpcValidatorPerformanceRequest::parse(
+                        request.params,
+                    )?;
+                let validator_performance =
+                    self.validator_performance(rpc_validator_performance_request).await?;
+                serde_json::to_value(validator_performance)
+                    .map_err(|err| RpcError::serialization_error(err.to_string()))
+            }
             "EXPERIMENTAL_validators_ordered" => {
                 let rpc_validators_ordered_request =
                     near_jsonrpc_primitives::types::validator::RpcValidatorsOrderedRequest::parse(
@@ -1063,6 +1087,44 @@ impl JsonRpcHandler {
         Ok(self.client_addr.send(GetNetworkInfo {}).await??.into())
     }
 
+    async fn network_graph(
+        &self,
+    ) -> Result<
+        near_jsonrpc_primitives::types::network_graph::RpcNetworkGraphResponse,
+        near_jsonrpc_primitives::types::network_graph::RpcNetworkGraphError,
+    > {
+        let edges = match self
+            .routing_table_addr
+            .send(near_network::RoutingTableMessages::RequestRoutingTable)
+            .await?
+        {
+            near_network::RoutingTableMessagesResponse::RequestRoutingTableResponse {
+                edges_info,
+            } => edges_info.iter().map(Into::into).collect(),
+            _ => return Err("unexpected response from routing table".to_string().into()),
+        };
+        let routing_table_info = match self
+            .peer_manager_addr
+            .send(near_network::types::PeerManagerMessageRequest::NetworkRequests(
+                near_network::types::NetworkRequests::FetchRoutingTable,
+            ))
+            .await?
+            .as_network_response()
+        {
+            near_network::types::NetworkResponses::RoutingTableInfo(info) => info,
+            _ => return Err("unexpected response from peer manager".to_string().into()),
+        };
+        Ok(near_jsonrpc_primitives::types::network_graph::RpcNetworkGraphResponse {
+            edges,
+            account_peers: routing_table_info.account_peers.into_iter().collect(),
+            next_hops: routing_table_info
+                .peer_forwarding
+                .iter()
+                .map(|(peer_id, next_hops)| (peer_id.clone(), next_hops.clone()))
+                .collect(),
+        })
+    }
+
     async fn gas_price(
         &self,
         request_data: near_jsonrpc_primitives::types::gas_price::RpcGasPriceRequest,
@@ -1103,6 +1165,38 @@ impl JsonRpcHandler {
             request;
         Ok(self.view_client_addr.send(GetValidatorOrdered { block_id }).await??.into())
     }
+
+    /// Returns the blocks and chunks the validator was expected to produce in the epoch, and
+    /// whether it produced them.
+    async fn validator_performance(
+        &self,
+        request_data: near_jsonrpc_primitives::types::validator::RpcValidatorPerformanceRequest,
+    ) -> Result<
+        near_jsonrpc_primitives::types::validator::RpcValidatorPerformanceResponse,
+        near_jsonrpc_primitives::types::validator::RpcValidatorError,
+    > {
+        let validator_performance = self
+            .view_client_addr
+            .send(GetValidatorPerformance {
+                epoch_reference: request_data.epoch_reference,
+                account_id: request_data.account_id,
+            })
+            .await??;
+        Ok(near_jsonrpc_primitives::types::validator::RpcValidatorPerformanceResponse {
+            validator_performance,
+        })
+    }
+
+    /// Makes the node (re)load its pending validator key file to start a key rotation.
+    async fn load_pending_validator_key(
+        &self,
+    ) -> Result<
+        near_jsonrpc_primitives::types::validator::RpcPendingValidatorKeyResponse,
+        near_jsonrpc_primitives::types::validator::RpcPendingValidatorKeyError,
+    > {
+        let public_key = self.client_addr.send(LoadPendingValidatorKey {}).await??;
+        Ok(near_jsonrpc_primitives::types::validator::RpcPendingValidatorKeyResponse { public_key })
+    }
 }
 
 #[cfg(feature = "sandbox")]
@@ -1376,11 +1470,17 @@ pub fn start_http(
     genesis_config: GenesisConfig,
     client_addr: Addr<ClientActor>,
     view_client_addr: Addr<ViewClientActor>,
-    #[cfg(feature = "test_features")] peer_manager_addr: Addr<near_network::PeerManagerActor>,
-    #[cfg(feature = "test_features")] routing_table_addr: Addr<near_network::RoutingTableActor>,
+    peer_manager_addr: Addr<near_network::PeerManagerActor>,
+    routing_table_addr: Addr<near_network::RoutingTableActor>,
 ) -> Vec<(&'static str, actix_web::dev::Server)> {
-    let RpcConfig { addr, prometheus_addr, cors_allowed_origins, polling_config, limits_config } =
-        config;
+    let RpcConfig {
+        addr,
+        prometheus_addr,
+        cors_allowed_origins,
+        polling_config,
+        limits_config,
+        enable_debug_rpc,
+    } = config;
     let prometheus_addr = prometheus_addr.filter(|it| it != &addr);
     let cors_allowed_origins_clone = cors_allowed_origins.clone();
     info!(target:"network", "Starting http server at {}", addr);
@@ -1393,10 +1493,9 @@ pub fn start_http(
                 view_client_addr: view_client_addr.clone(),
                 polling_config,
                 genesis_config: genesis_config.clone(),
-                #[cfg(feature = "test_features")]
                 peer_manager_addr: peer_manager_addr.clone(),
-                #[cfg(feature = "test_features")]
                 routing_table_addr: routing_table_addr.clone(),
+                enable_debug_rpc,
             })
             .app_data(web::JsonConfig::default().limit(limits_config.json_payload_max_size))
             .wrap(middleware::Logger::default())
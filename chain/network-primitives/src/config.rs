@@ -1,6 +1,7 @@
 use crate::network_protocol::PeerInfo;
 use crate::types::ROUTED_MESSAGE_TTL;
 use near_crypto::{KeyType, PublicKey, SecretKey};
+use near_primitives::network::PeerId;
 use near_primitives::types::AccountId;
 use std::collections::{HashMap, HashSet};
 use std::net::{AddrParseError, IpAddr, SocketAddr};
@@ -66,6 +67,71 @@ pub struct NetworkConfig {
     pub outbound_disabled: bool,
     /// Not clear old data, set `true` for archive nodes.
     pub archive: bool,
+    /// Offer an encrypted session on outbound connections, reconnecting in plaintext if the other
+    /// peer doesn't answer the offer. Encrypted inbound connections are always accepted. Peers
+    /// which established an encrypted session once are never connected with in plaintext again.
+    pub encrypted_transport: bool,
+    /// Refuse plaintext connections, with peers which don't support encrypted sessions as well.
+    /// Requires `encrypted_transport`.
+    pub require_encrypted_transport: bool,
+    /// Messages of at least this size are compressed, for peers which accept compressed messages.
+    /// `None` disables compression of sent messages, received ones are always accepted.
+    pub compression_threshold: Option<usize>,
+    /// Limits of messages received from and sent to each peer, by `PeerMessage` or
+    /// `RoutedMessageBody` variant name. Messages over the limits are queued until the limits let
+    /// them through, peers repeatedly filling the queues are banned. Consensus messages, like
+    /// blocks, approvals and chunks, are never limited.
+    pub message_rate_limits: HashMap<String, RateLimit>,
+    /// Transport used for connections with peers.
+    pub transport: Transport,
+    /// Address advertised to peers for them to connect. If not set, it is discovered through
+    /// `port_mapping` or from the addresses peers see connections of this node coming from.
+    pub external_address: Option<SocketAddr>,
+    /// Mapping of the listening port on the local gateway.
+    pub port_mapping: PortMapping,
+    /// If not empty, the only peers this node connects to. Connections with all other peers are
+    /// refused, whatever the number of peers. Used by validators only reachable through their
+    /// sentry nodes, which relay their messages.
+    pub trusted_peers: Vec<PeerInfo>,
+    /// Peers whose address is never sent to other peers, such as validators behind this sentry
+    /// node.
+    pub private_peers: HashSet<PeerId>,
+}
+
+/// Transport of connections with peers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Transport {
+    /// Single TCP stream per peer, see `NetworkConfig::encrypted_transport`.
+    Tcp,
+    /// QUIC connection per peer, with one stream per message class. The node listens for QUIC on
+    /// the UDP port with the same number as its TCP port, and keeps accepting TCP connections.
+    /// Outbound connections fall back to TCP if the peer doesn't answer over QUIC, and peers which
+    /// told in their handshake that they don't accept QUIC are connected to over TCP.
+    Quic,
+}
+
+/// Mapping of the listening port on the local gateway, for nodes behind NAT.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PortMapping {
+    Disabled,
+    /// Gateway is discovered on the local network with UPnP.
+    Upnp,
+    /// NAT-PMP gateway at the given address, usually the default route of the local network.
+    NatPmp {
+        gateway: IpAddr,
+    },
+}
+
+/// Token bucket limits of a message type on a single connection.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RateLimit {
+    /// Sustained number of messages per second.
+    pub messages_per_sec: f64,
+    /// Number of messages allowed at once.
+    pub messages_burst: u32,
+    /// Sustained number of bytes per second, allowing bursts of one second worth.
+    /// `None` doesn't limit the size of messages.
+    pub bytes_per_sec: Option<u64>,
 }
 
 impl NetworkConfig {
@@ -101,6 +167,15 @@ impl NetworkConfig {
             blacklist: HashMap::new(),
             outbound_disabled: false,
             archive: false,
+            encrypted_transport: true,
+            require_encrypted_transport: false,
+            compression_threshold: None,
+            message_rate_limits: HashMap::new(),
+            transport: Transport::Tcp,
+            external_address: None,
+            port_mapping: PortMapping::Disabled,
+            trusted_peers: vec![],
+            private_peers: HashSet::new(),
         }
     }
 
@@ -120,6 +195,10 @@ impl NetworkConfig {
             );
         }
 
+        if self.require_encrypted_transport && !self.encrypted_transport {
+            anyhow::bail!("require_encrypted_transport is set, but encrypted_transport is not.");
+        }
+
         if self.outbound_disabled {
             anyhow::bail!("Outbound connections are disabled.");
         }
@@ -239,5 +318,11 @@ mod test {
         nc.peer_recent_time_window = UPDATE_INTERVAL_LAST_TIME_RECEIVED_MESSAGE;
         let res = nc.verify();
         assert!(res.is_err(), "{:?}", res);
+
+        let mut nc = NetworkConfig::from_seed("123", 213);
+        nc.encrypted_transport = false;
+        nc.require_encrypted_transport = true;
+        let res = nc.verify();
+        assert!(res.is_err(), "{:?}", res);
     }
 }
@@ -9,6 +9,8 @@ use near_primitives::block::{Approval, GenesisId};
 use near_primitives::hash::CryptoHash;
 use near_primitives::merkle::combine_hash;
 use near_primitives::network::PeerId;
+#[cfg(feature = "protocol_feature_chunk_state_witness")]
+use near_primitives::sharding::ChunkStateWitness;
 use near_primitives::sharding::{
     ChunkHash, PartialEncodedChunk, PartialEncodedChunkPart, PartialEncodedChunkV1,
     PartialEncodedChunkWithArcReceipts, ReceiptProof, ShardChunkHeader,
@@ -205,6 +207,8 @@ pub enum RoutedMessageBody {
     VersionedPartialEncodedChunk(PartialEncodedChunk),
     VersionedStateResponse(StateResponseInfo),
     PartialEncodedChunkForward(PartialEncodedChunkForwardMsg),
+    #[cfg(feature = "protocol_feature_chunk_state_witness")]
+    ChunkStateWitness(Box<ChunkStateWitness>),
 }
 
 impl From<PartialEncodedChunkWithArcReceipts> for RoutedMessageBody {
@@ -277,6 +281,12 @@ impl Debug for RoutedMessageBody {
             ),
             RoutedMessageBody::Ping(_) => write!(f, "Ping"),
             RoutedMessageBody::Pong(_) => write!(f, "Pong"),
+            #[cfg(feature = "protocol_feature_chunk_state_witness")]
+            RoutedMessageBody::ChunkStateWitness(witness) => write!(
+                f,
+                "ChunkStateWitness({:?}, {})",
+                witness.inner.chunk_hash, witness.inner.shard_id
+            ),
             RoutedMessageBody::Unused => write!(f, "Unused"),
         }
     }
@@ -35,7 +35,9 @@ pub use crate::network_protocol::{
     RoutedMessageBody, StateResponseInfo, StateResponseInfoV1, StateResponseInfoV2,
 };
 
-pub use crate::config::{blacklist_from_iter, BlockedPorts, NetworkConfig};
+pub use crate::config::{
+    blacklist_from_iter, BlockedPorts, NetworkConfig, PortMapping, RateLimit, Transport,
+};
 
 pub use crate::network_protocol::edge::{Edge, EdgeState, PartialEdgeInfo, SimpleEdge};
 
@@ -150,6 +152,96 @@ impl KnownPeerStatus {
     }
 }
 
+/// Score gained for every useful response.
+const USEFUL_RESPONSE_SCORE: f64 = 1.0;
+/// Useful responses counted in the score, so that a long history of them doesn't hide misbehaviour.
+const MAX_SCORED_USEFUL_RESPONSES: u32 = 100;
+/// Score lost for every request which timed out.
+const TIMEOUT_PENALTY: f64 = 5.0;
+/// Score lost for every invalid message.
+const INVALID_MESSAGE_PENALTY: f64 = 50.0;
+/// Round trip time costing one point of score.
+const LATENCY_PENALTY_MS: f64 = 100.0;
+/// Weight of a new latency sample in the moving average.
+const LATENCY_SAMPLE_WEIGHT: u64 = 8;
+/// Score difference which doubles the chance of a peer to be chosen.
+const SELECTION_WEIGHT_DOUBLING_SCORE: f64 = 10.0;
+
+/// Evidence about the behaviour of a peer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeerEvidence {
+    /// Sent a block, header or other message which failed validation.
+    InvalidMessage,
+    /// Didn't answer a request in time.
+    Timeout,
+    /// Answered a request with data we needed.
+    UsefulResponse,
+    /// Round trip time of a request.
+    Latency(Duration),
+}
+
+#[cfg(feature = "deepsize_feature")]
+impl deepsize::DeepSizeOf for PeerEvidence {
+    fn deep_size_of_children(&self, _context: &mut deepsize::Context) -> usize {
+        0
+    }
+}
+
+/// Reputation of a peer, accumulated from the evidence about its behaviour.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct PeerReputation {
+    pub invalid_messages: u32,
+    pub timeouts: u32,
+    pub useful_responses: u32,
+    /// Moving average of the round trip time, in milliseconds.
+    pub latency_ms: Option<u64>,
+}
+
+impl PeerReputation {
+    pub fn record(&mut self, evidence: PeerEvidence) {
+        match evidence {
+            PeerEvidence::InvalidMessage => {
+                self.invalid_messages = self.invalid_messages.saturating_add(1)
+            }
+            PeerEvidence::Timeout => self.timeouts = self.timeouts.saturating_add(1),
+            PeerEvidence::UsefulResponse => {
+                self.useful_responses = self.useful_responses.saturating_add(1)
+            }
+            PeerEvidence::Latency(latency) => {
+                let sample = latency.as_millis() as u64;
+                self.latency_ms = Some(match self.latency_ms {
+                    Some(average) => {
+                        (average * (LATENCY_SAMPLE_WEIGHT - 1) + sample) / LATENCY_SAMPLE_WEIGHT
+                    }
+                    None => sample,
+                });
+            }
+        }
+    }
+
+    /// Halves the evidence, so that peers recover from past misbehaviour, and don't live on past
+    /// merits forever.
+    pub fn decay(&mut self) {
+        self.invalid_messages /= 2;
+        self.timeouts /= 2;
+        self.useful_responses /= 2;
+    }
+
+    /// Score of the peer, zero for peers we know nothing about. The higher the better.
+    pub fn score(&self) -> f64 {
+        self.useful_responses.min(MAX_SCORED_USEFUL_RESPONSES) as f64 * USEFUL_RESPONSE_SCORE
+            - self.timeouts as f64 * TIMEOUT_PENALTY
+            - self.invalid_messages as f64 * INVALID_MESSAGE_PENALTY
+            - self.latency_ms.map_or(0.0, |latency| latency as f64 / LATENCY_PENALTY_MS)
+    }
+
+    /// Weight of a peer with the given score when choosing peers at random. Peers with better
+    /// scores are preferred, without starving the others.
+    pub fn selection_weight(score: f64) -> f64 {
+        (score / SELECTION_WEIGHT_DOUBLING_SCORE).clamp(-32.0, 32.0).exp2()
+    }
+}
+
 /// not part of protocol, probably doesn't need `borsh`
 /// Information node stores about known peers.
 #[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
@@ -159,6 +251,7 @@ pub struct KnownPeerState {
     /// Unused
     first_seen: u64,
     pub last_seen: u64,
+    pub reputation: PeerReputation,
 }
 
 impl KnownPeerState {
@@ -168,6 +261,7 @@ impl KnownPeerState {
             status: KnownPeerStatus::Unknown,
             first_seen: to_timestamp(Clock::utc()),
             last_seen: to_timestamp(Clock::utc()),
+            reputation: PeerReputation::default(),
         }
     }
 
@@ -225,6 +319,7 @@ pub enum ReasonForBan {
     EpochSyncNoResponse = 11,
     EpochSyncInvalidResponse = 12,
     EpochSyncInvalidFinalizationResponse = 13,
+    RateLimitExceeded = 14,
 }
 
 /// Banning signal sent from Peer instance to PeerManager
@@ -9,6 +9,7 @@ use near_network_primitives::types::{
 };
 use near_primitives::block::{Block, BlockHeader, GenesisId};
 use near_primitives::challenge::Challenge;
+use near_primitives::checked_feature;
 use near_primitives::hash::CryptoHash;
 use near_primitives::network::{AnnounceAccount, PeerId};
 use near_primitives::syncing::{EpochSyncFinalizationResponse, EpochSyncResponse};
@@ -16,12 +17,12 @@ use near_primitives::transaction::SignedTransaction;
 use near_primitives::types::{EpochId, ProtocolVersion};
 use near_primitives::version::{PEER_MIN_ALLOWED_PROTOCOL_VERSION, PROTOCOL_VERSION};
 use std::fmt::Formatter;
+use std::net::SocketAddr;
 use std::{fmt, io};
 
 const ERROR_UNEXPECTED_LENGTH_OF_INPUT: &str = "Unexpected length of input";
 
-#[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
-#[derive(BorshSerialize, PartialEq, Eq, Clone, Debug)]
+#[derive(PartialEq, Eq, Clone, Debug)]
 /// Structure representing handshake between peers.
 /// This replaces deprecated handshake `HandshakeV2`.
 pub struct Handshake {
@@ -39,6 +40,27 @@ pub struct Handshake {
     pub(crate) sender_chain_info: PeerChainInfoV2,
     /// Represents new `edge`. Contains only `none` and `Signature` from the sender.
     pub(crate) partial_edge_info: PartialEdgeInfo,
+    /// Whether the sender accepts compressed messages, see `peer::codec`. Only sent with protocol
+    /// versions that support it, see `Handshake::has_compression`.
+    pub(crate) accepts_compression: bool,
+    /// Whether the sender accepts QUIC connections, on the UDP port with the number of its
+    /// listening port. Only sent with protocol versions that support it, see
+    /// `Handshake::has_quic`.
+    pub(crate) accepts_quic: bool,
+    /// Address the connection comes from or goes to, as seen by the sender, which lets the
+    /// receiver learn its public address. Only sent with protocol versions that support it,
+    /// see `Handshake::has_observed_addr`.
+    pub(crate) target_observed_addr: Option<SocketAddr>,
+}
+
+#[cfg(feature = "deepsize_feature")]
+impl deepsize::DeepSizeOf for Handshake {
+    fn deep_size_of_children(&self, context: &mut deepsize::Context) -> usize {
+        self.sender_peer_id.deep_size_of_children(context)
+            + self.target_peer_id.deep_size_of_children(context)
+            + self.sender_chain_info.deep_size_of_children(context)
+            + self.partial_edge_info.deep_size_of_children(context)
+    }
 }
 
 /// Struct describing the layout for Handshake.
@@ -69,6 +91,8 @@ impl Handshake {
         listen_port: Option<u16>,
         chain_info: PeerChainInfoV2,
         partial_edge_info: PartialEdgeInfo,
+        target_observed_addr: Option<SocketAddr>,
+        accepts_quic: bool,
     ) -> Self {
         Handshake {
             protocol_version: version,
@@ -78,7 +102,52 @@ impl Handshake {
             sender_listen_port: listen_port,
             sender_chain_info: chain_info,
             partial_edge_info,
+            // Compressed messages are always accepted from peers which support them.
+            accepts_compression: Self::has_compression(version),
+            accepts_quic,
+            target_observed_addr,
+        }
+    }
+
+    /// Whether handshakes of `protocol_version` end with `accepts_compression`.
+    fn has_compression(protocol_version: ProtocolVersion) -> bool {
+        checked_feature!(
+            "protocol_feature_peer_message_compression",
+            PeerMessageCompression,
+            protocol_version
+        )
+    }
+
+    /// Whether handshakes of `protocol_version` end with `accepts_quic`.
+    fn has_quic(protocol_version: ProtocolVersion) -> bool {
+        checked_feature!("protocol_feature_quic_transport", QuicTransport, protocol_version)
+    }
+
+    /// Whether handshakes of `protocol_version` end with `target_observed_addr`.
+    fn has_observed_addr(protocol_version: ProtocolVersion) -> bool {
+        checked_feature!("protocol_feature_observed_address", ObservedAddress, protocol_version)
+    }
+}
+
+impl BorshSerialize for Handshake {
+    fn serialize<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.protocol_version.serialize(writer)?;
+        self.oldest_supported_version.serialize(writer)?;
+        self.sender_peer_id.serialize(writer)?;
+        self.target_peer_id.serialize(writer)?;
+        self.sender_listen_port.serialize(writer)?;
+        self.sender_chain_info.serialize(writer)?;
+        self.partial_edge_info.serialize(writer)?;
+        if Self::has_compression(self.protocol_version) {
+            self.accepts_compression.serialize(writer)?;
+        }
+        if Self::has_quic(self.protocol_version) {
+            self.accepts_quic.serialize(writer)?;
         }
+        if Self::has_observed_addr(self.protocol_version) {
+            self.target_observed_addr.serialize(writer)?;
+        }
+        Ok(())
     }
 }
 
@@ -98,7 +167,18 @@ impl BorshDeserialize for Handshake {
 
         if PEER_MIN_ALLOWED_PROTOCOL_VERSION <= version && version <= PROTOCOL_VERSION {
             // If we support this version, then try to deserialize with custom deserializer
-            <HandshakeAutoDes as BorshDeserialize>::deserialize(buf).map(Into::into)
+            let mut handshake: Handshake =
+                <HandshakeAutoDes as BorshDeserialize>::deserialize(buf)?.into();
+            if Self::has_compression(version) {
+                handshake.accepts_compression = BorshDeserialize::deserialize(buf)?;
+            }
+            if Self::has_quic(version) {
+                handshake.accepts_quic = BorshDeserialize::deserialize(buf)?;
+            }
+            if Self::has_observed_addr(version) {
+                handshake.target_observed_addr = BorshDeserialize::deserialize(buf)?;
+            }
+            Ok(handshake)
         } else {
             Err(std::io::Error::new(
                 std::io::ErrorKind::InvalidData,
@@ -121,6 +201,9 @@ impl From<HandshakeAutoDes> for Handshake {
             sender_listen_port: handshake.sender_listen_port,
             sender_chain_info: handshake.sender_chain_info,
             partial_edge_info: handshake.partial_edge_info,
+            accepts_compression: false,
+            accepts_quic: false,
+            target_observed_addr: None,
         }
     }
 }
@@ -281,6 +364,10 @@ impl PeerMessage {
             | PeerMessage::EpochSyncFinalizationResponse(_)
             | PeerMessage::EpochSyncResponse(_)
             | PeerMessage::Transaction(_) => true,
+            #[cfg(feature = "protocol_feature_chunk_state_witness")]
+            PeerMessage::Routed(r) if matches!(r.body, RoutedMessageBody::ChunkStateWitness(_)) => {
+                true
+            }
             PeerMessage::Routed(r) => matches!(
                 r.body,
                 RoutedMessageBody::BlockApproval(_)
@@ -1,27 +1,161 @@
 /// The purpose of this crate is to encode/decode messages on the network layer.
 /// Each message contains:
 ///     - 4 bytes - length of the message as u32
-///     - the message itself, which is encoded with `borsh`
+///     - the message itself, which is encoded with `borsh`, optionally compressed, and encrypted if
+///       the connection established an encrypted session (see `noise`)
+///
+/// Compression is negotiated per connection in the `Handshake`. Once the other side tells that it
+/// accepts compressed messages, messages above the configured threshold are compressed with zstd
+/// and sent prefixed with `COMPRESSED_MESSAGE_TAG`. Compressed messages are dropped until then.
 ///
 /// NOTES:
 ///     - Code has an extra logic to ban peers if they sent messages that are too large.
+///     - Compressed messages are at most `MAX_DECOMPRESSED_MESSAGE_SIZE_BYTES` once decompressed,
+///       larger messages are sent uncompressed.
+///     - Decompressed bytes are reported to the `ThrottleController` of the connection, so that
+///       peers are throttled by the size of the messages they make this node process.
+use crate::peer::noise::{CipherState, TAG_LEN};
 use crate::stats::metrics;
 use bytes::{Buf, BufMut, BytesMut};
 use bytesize::{GIB, MIB};
 use near_network_primitives::types::ReasonForBan;
 use near_performance_metrics::framed_write::EncoderCallBack;
+use near_rate_limiter::ThrottleController;
 use std::io::{Error, ErrorKind};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use tokio_util::codec::{Decoder, Encoder};
-use tracing::error;
+use tracing::{debug, error};
 
 /// Maximum size of network message in encoded format.
 /// We encode length as `u32`, and therefore maximum size can't be larger than `u32::MAX`.
 const NETWORK_MESSAGE_MAX_SIZE_BYTES: usize = 512 * MIB as usize;
 /// Maximum capacity of write buffer in bytes.
 const MAX_WRITE_BUFFER_CAPACITY_BYTES: usize = GIB as usize;
+/// Maximum size of a compressed message once decompressed, well below
+/// `NETWORK_MESSAGE_MAX_SIZE_BYTES` so that a small frame can't make this node allocate a lot of
+/// memory.
+const MAX_DECOMPRESSED_MESSAGE_SIZE_BYTES: usize = 64 * MIB as usize;
+/// First byte of a compressed message.
+const COMPRESSED_MESSAGE_TAG: u8 = 0xfe;
+
+#[cfg(feature = "protocol_feature_peer_message_compression")]
+fn zstd_compress(data: &[u8]) -> Result<Vec<u8>, Error> {
+    zstd::bulk::compress(data, zstd::DEFAULT_COMPRESSION_LEVEL)
+}
+
+/// Reads at most `limit` decompressed bytes.
+#[cfg(feature = "protocol_feature_peer_message_compression")]
+fn zstd_decompress(data: &[u8], limit: u64) -> Result<Vec<u8>, Error> {
+    use std::io::Read;
+    let mut decompressed = vec![];
+    zstd::Decoder::new(data)?.take(limit).read_to_end(&mut decompressed)?;
+    Ok(decompressed)
+}
+
+/// Without compression support handshakes never tell that compression is accepted, so nothing is
+/// compressed, and compressed messages are dropped before they get here.
+#[cfg(not(feature = "protocol_feature_peer_message_compression"))]
+fn zstd_compress(_data: &[u8]) -> Result<Vec<u8>, Error> {
+    Err(Error::new(ErrorKind::Unsupported, "compression isn't supported"))
+}
+
+#[cfg(not(feature = "protocol_feature_peer_message_compression"))]
+fn zstd_decompress(_data: &[u8], _limit: u64) -> Result<Vec<u8>, Error> {
+    Err(Error::new(ErrorKind::Unsupported, "compression isn't supported"))
+}
 
 #[derive(Default)]
-pub(crate) struct Codec {}
+pub(crate) struct Codec {
+    /// Encrypts or decrypts messages of an encrypted session.
+    cipher: Option<CipherState>,
+    /// Messages of at least this size are compressed, if the other side accepts it.
+    compression_threshold: Option<usize>,
+    /// Set by the `PeerActor` once the `Handshake` of the other side tells that it accepts
+    /// compressed messages. Shared by the codecs of both directions.
+    peer_accepts_compression: Arc<AtomicBool>,
+    /// Throttle controller of the connection, which decompressed bytes are reported to.
+    throttle_controller: Option<ThrottleController>,
+}
+
+impl Codec {
+    /// Codecs of both directions of a connection, which compress messages once
+    /// `peer_accepts_compression` is set.
+    pub(crate) fn new_pair(
+        read_cipher: Option<CipherState>,
+        write_cipher: Option<CipherState>,
+        compression_threshold: Option<usize>,
+        peer_accepts_compression: Arc<AtomicBool>,
+        throttle_controller: ThrottleController,
+    ) -> (Codec, Codec) {
+        (
+            Codec {
+                cipher: read_cipher,
+                compression_threshold,
+                peer_accepts_compression: peer_accepts_compression.clone(),
+                throttle_controller: Some(throttle_controller),
+            },
+            Codec {
+                cipher: write_cipher,
+                compression_threshold,
+                peer_accepts_compression,
+                throttle_controller: None,
+            },
+        )
+    }
+
+    fn compress(&self, item: Vec<u8>) -> Vec<u8> {
+        match self.compression_threshold {
+            Some(threshold)
+                if item.len() >= threshold
+                    && item.len() <= MAX_DECOMPRESSED_MESSAGE_SIZE_BYTES
+                    && self.peer_accepts_compression.load(Ordering::Relaxed) =>
+            {
+                match zstd_compress(&item) {
+                    Ok(compressed) if compressed.len() + 1 < item.len() => {
+                        let mut data = Vec::with_capacity(compressed.len() + 1);
+                        data.push(COMPRESSED_MESSAGE_TAG);
+                        data.extend_from_slice(&compressed);
+                        metrics::PEER_COMPRESSION_RAW_BYTES_SENT.inc_by(item.len() as u64);
+                        metrics::PEER_COMPRESSION_COMPRESSED_BYTES_SENT.inc_by(data.len() as u64);
+                        data
+                    }
+                    _ => item,
+                }
+            }
+            _ => item,
+        }
+    }
+
+    fn decompress(&mut self, data: &[u8]) -> Result<Vec<u8>, ReasonForBan> {
+        // Reading one byte more than allowed tells whether the message is too large.
+        let decompressed =
+            zstd_decompress(&data[1..], MAX_DECOMPRESSED_MESSAGE_SIZE_BYTES as u64 + 1)
+                .map_err(|_| ReasonForBan::Abusive)?;
+        if decompressed.len() > MAX_DECOMPRESSED_MESSAGE_SIZE_BYTES {
+            return Err(ReasonForBan::Abusive);
+        }
+        // The compressed bytes were already reported when they were read from the stream.
+        if let Some(throttle_controller) = self.throttle_controller.as_mut() {
+            throttle_controller
+                .report_bandwidth_used(decompressed.len().saturating_sub(data.len()));
+        }
+        metrics::PEER_COMPRESSION_COMPRESSED_BYTES_RECEIVED.inc_by(data.len() as u64);
+        metrics::PEER_COMPRESSION_RAW_BYTES_RECEIVED.inc_by(decompressed.len() as u64);
+        Ok(decompressed)
+    }
+
+    fn put_frame(&mut self, item: &[u8], buf: &mut BytesMut) {
+        let item = match self.cipher.as_mut() {
+            Some(cipher) => cipher.encrypt(&[], item),
+            None => item.to_vec(),
+        };
+        // First four bytes is the length of the buffer.
+        buf.reserve(item.len() + 4);
+        buf.put_u32_le(item.len() as u32);
+        buf.put(&item[..]);
+    }
+}
 
 impl EncoderCallBack for Codec {
     #[allow(unused)]
@@ -38,20 +172,20 @@ impl Encoder<Vec<u8>> for Codec {
     type Error = Error;
 
     fn encode(&mut self, item: Vec<u8>, buf: &mut BytesMut) -> Result<(), Error> {
-        if item.len() > NETWORK_MESSAGE_MAX_SIZE_BYTES {
+        let item = self.compress(item);
+        // The message is encrypted only once it is known to be sent, otherwise the nonces of both
+        // sides get out of sync.
+        let len = item.len() + if self.cipher.is_some() { TAG_LEN } else { 0 };
+        if len > NETWORK_MESSAGE_MAX_SIZE_BYTES {
             Err(Error::new(ErrorKind::InvalidInput, "Input is too long"))
         } else {
             #[cfg(feature = "performance_stats")]
             {
                 let stat = near_performance_metrics::stats_enabled::get_thread_stats_logger();
-                stat.lock().unwrap().log_add_write_buffer(
-                    item.len() + 4,
-                    buf.len(),
-                    buf.capacity(),
-                );
+                stat.lock().unwrap().log_add_write_buffer(len + 4, buf.len(), buf.capacity());
             }
             if buf.capacity() >= MAX_WRITE_BUFFER_CAPACITY_BYTES
-                && item.len() + 4 + buf.len() > buf.capacity()
+                && len + 4 + buf.len() > buf.capacity()
             {
                 #[cfg(feature = "performance_stats")]
                 let tid = near_rust_allocator_proxy::get_tid();
@@ -59,15 +193,12 @@ impl Encoder<Vec<u8>> for Codec {
                 let tid = 0;
                 error!(target: "network", "{} throwing away message, because buffer is full item.len(): {} buf.capacity: {}", 
                     tid,
-                    item.len(), buf.capacity());
+                    len, buf.capacity());
 
                 metrics::DROPPED_MESSAGES_COUNT.inc_by(1);
                 return Err(Error::new(ErrorKind::Other, "Buf max capacity exceeded"));
             }
-            // First four bytes is the length of the buffer.
-            buf.reserve(item.len() + 4);
-            buf.put_u32_le(item.len() as u32);
-            buf.put(&item[..]);
+            self.put_frame(&item, buf);
             Ok(())
         }
     }
@@ -78,28 +209,43 @@ impl Decoder for Codec {
     type Error = Error;
 
     fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
-        let len_buf = match buf.get(..4).and_then(|s| <[u8; 4]>::try_from(s).ok()) {
-            // not enough bytes to start decoding
-            None => return Ok(None),
-            Some(res) => res,
-        };
+        loop {
+            let len_buf = match buf.get(..4).and_then(|s| <[u8; 4]>::try_from(s).ok()) {
+                // not enough bytes to start decoding
+                None => return Ok(None),
+                Some(res) => res,
+            };
 
-        let len = u32::from_le_bytes(len_buf) as usize;
-        if len > NETWORK_MESSAGE_MAX_SIZE_BYTES {
-            // If this point is reached, abusive peer is banned.
-            return Ok(Some(Err(ReasonForBan::Abusive)));
-        }
+            let len = u32::from_le_bytes(len_buf) as usize;
+            if len > NETWORK_MESSAGE_MAX_SIZE_BYTES {
+                // If this point is reached, abusive peer is banned.
+                return Ok(Some(Err(ReasonForBan::Abusive)));
+            }
 
-        if let Some(data_buf) = buf.get(4..4 + len) {
-            let res = Some(Ok(data_buf.to_vec()));
+            let data = match buf.get(4..4 + len) {
+                // Failing to decrypt closes the connection, as the message may have been
+                // modified on the way.
+                Some(data_buf) => match self.cipher.as_mut() {
+                    Some(cipher) => cipher.decrypt(&[], data_buf)?,
+                    None => data_buf.to_vec(),
+                },
+                // not enough bytes, keep waiting
+                None => return Ok(None),
+            };
             buf.advance(4 + len);
             if buf.is_empty() && buf.capacity() > 0 {
                 *buf = BytesMut::new();
             }
-            Ok(res)
-        } else {
-            // not enough bytes, keep waiting
-            Ok(None)
+            if data.first() != Some(&COMPRESSED_MESSAGE_TAG) {
+                return Ok(Some(Ok(data)));
+            }
+            if self.peer_accepts_compression.load(Ordering::Relaxed) {
+                return Ok(Some(self.decompress(&data)));
+            }
+            // Before its `Handshake` is received, the other side can't know that compressed
+            // messages are accepted. Like other messages before the handshake, they are dropped,
+            // and aren't decompressed.
+            debug!(target: "network", "Dropping compressed message received before the handshake");
         }
     }
 }
@@ -107,6 +253,7 @@ impl Decoder for Codec {
 #[cfg(test)]
 mod test {
     use crate::peer::codec::{Codec, NETWORK_MESSAGE_MAX_SIZE_BYTES};
+    use crate::peer::noise::CipherState;
     use crate::types::{Handshake, PeerMessage, RoutingTableUpdate};
     use borsh::{BorshDeserialize, BorshSerialize};
     use bytes::{BufMut, BytesMut};
@@ -120,8 +267,27 @@ mod test {
     use near_primitives::network::{AnnounceAccount, PeerId};
     use near_primitives::types::EpochId;
     use near_primitives::version::{PEER_MIN_ALLOWED_PROTOCOL_VERSION, PROTOCOL_VERSION};
+    use near_rate_limiter::ThrottleController;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
     use tokio_util::codec::{Decoder, Encoder};
 
+    fn new_pair(
+        read_cipher: Option<CipherState>,
+        write_cipher: Option<CipherState>,
+        compression_threshold: Option<usize>,
+    ) -> (Codec, Codec, Arc<AtomicBool>) {
+        let peer_accepts_compression = Arc::new(AtomicBool::new(false));
+        let (read, write) = Codec::new_pair(
+            read_cipher,
+            write_cipher,
+            compression_threshold,
+            peer_accepts_compression.clone(),
+            ThrottleController::new(usize::MAX, usize::MAX),
+        );
+        (read, write, peer_accepts_compression)
+    }
+
     fn test_codec(msg: PeerMessage) {
         let mut codec = Codec::default();
         let mut buffer = BytesMut::new();
@@ -174,6 +340,34 @@ mod test {
                 archival: false,
             },
             partial_edge_info: PartialEdgeInfo::default(),
+            accepts_compression: false,
+            accepts_quic: false,
+            target_observed_addr: None,
+        };
+        let msg = PeerMessage::Handshake(fake_handshake);
+        test_codec(msg);
+    }
+
+    #[test]
+    #[cfg(feature = "protocol_feature_observed_address")]
+    fn test_peer_message_handshake_observed_addr() {
+        let peer_info = PeerInfo::random();
+        let fake_handshake = Handshake {
+            protocol_version: PROTOCOL_VERSION,
+            oldest_supported_version: PEER_MIN_ALLOWED_PROTOCOL_VERSION,
+            sender_peer_id: peer_info.id.clone(),
+            target_peer_id: peer_info.id,
+            sender_listen_port: Some(24567),
+            sender_chain_info: PeerChainInfoV2 {
+                genesis_id: Default::default(),
+                height: 0,
+                tracked_shards: vec![],
+                archival: false,
+            },
+            partial_edge_info: PartialEdgeInfo::default(),
+            accepts_compression: false,
+            accepts_quic: false,
+            target_observed_addr: Some("1.2.3.4:5678".parse().unwrap()),
         };
         let msg = PeerMessage::Handshake(fake_handshake);
         test_codec(msg);
@@ -227,6 +421,84 @@ mod test {
         test_codec(msg);
     }
 
+    #[test]
+    fn test_encrypted_codec() {
+        let msg = PeerMessage::PeersResponse(vec![PeerInfo::random()]);
+        let (_, mut encoder, _) = new_pair(None, Some(CipherState::new(&[1; 32])), None);
+        let (mut decoder, _, _) = new_pair(Some(CipherState::new(&[1; 32])), None, None);
+        let mut buffer = BytesMut::new();
+        for _ in 0..2 {
+            let data = msg.try_to_vec().unwrap();
+            encoder.encode(data.clone(), &mut buffer).unwrap();
+            assert!(!buffer.windows(data.len()).any(|window| window == &data[..]));
+            let decoded = decoder.decode(&mut buffer).unwrap().unwrap().unwrap();
+            assert_eq!(PeerMessage::try_from_slice(&decoded).unwrap(), msg);
+        }
+
+        // A modified message isn't decoded.
+        encoder.encode(msg.try_to_vec().unwrap(), &mut buffer).unwrap();
+        buffer[4] ^= 1;
+        assert!(decoder.decode(&mut buffer).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "protocol_feature_peer_message_compression")]
+    fn test_compressed_codec() {
+        use crate::peer::codec::{COMPRESSED_MESSAGE_TAG, MAX_DECOMPRESSED_MESSAGE_SIZE_BYTES};
+        use bytesize::MIB;
+        use std::sync::atomic::Ordering;
+
+        let small = PeerMessage::PeersResponse(vec![PeerInfo::random()]).try_to_vec().unwrap();
+        let large = PeerMessage::PeersResponse(vec![PeerInfo::random(); 100]).try_to_vec().unwrap();
+        // Codecs of both sides, each side reads what the other writes.
+        let (mut read1, mut write1, accepts1) = new_pair(None, None, Some(1000));
+        let (mut read2, mut write2, accepts2) = new_pair(None, None, Some(1000));
+        let mut buffer1 = BytesMut::new();
+        let mut buffer2 = BytesMut::new();
+
+        // Nothing is compressed before the handshake of the other side is received.
+        write1.encode(large.clone(), &mut buffer1).unwrap();
+        assert!(buffer1.len() > large.len());
+        assert_eq!(read2.decode(&mut buffer1).unwrap().unwrap().unwrap(), large);
+
+        // Compressed messages received before the handshake are dropped.
+        accepts1.store(true, Ordering::Relaxed);
+        write1.encode(large.clone(), &mut buffer1).unwrap();
+        write1.encode(small.clone(), &mut buffer1).unwrap();
+        assert_eq!(read2.decode(&mut buffer1).unwrap().unwrap().unwrap(), small);
+        assert!(buffer1.is_empty());
+
+        accepts2.store(true, Ordering::Relaxed);
+        write2.encode(large.clone(), &mut buffer2).unwrap();
+        assert!(buffer2.len() < large.len());
+        assert_eq!(read1.decode(&mut buffer2).unwrap().unwrap().unwrap(), large);
+
+        // Messages below the threshold aren't compressed.
+        write1.encode(small.clone(), &mut buffer1).unwrap();
+        assert_eq!(buffer1.len(), small.len() + 4);
+        assert_eq!(read2.decode(&mut buffer1).unwrap().unwrap().unwrap(), small);
+        write1.encode(large.clone(), &mut buffer1).unwrap();
+        assert!(buffer1.len() < large.len());
+        assert_eq!(read2.decode(&mut buffer1).unwrap().unwrap().unwrap(), large);
+
+        // Invalid compressed data gets the peer banned.
+        let mut buffer = BytesMut::new();
+        buffer.put_u32_le(3);
+        buffer.put(&[COMPRESSED_MESSAGE_TAG, 1, 2][..]);
+        assert_eq!(read2.decode(&mut buffer).unwrap(), Some(Err(ReasonForBan::Abusive)));
+
+        // So does data which decompresses to more than allowed, even if it's small.
+        let mut data = vec![COMPRESSED_MESSAGE_TAG];
+        data.extend(
+            zstd::bulk::compress(&vec![0u8; MAX_DECOMPRESSED_MESSAGE_SIZE_BYTES + 1], 0).unwrap(),
+        );
+        assert!(data.len() < MIB as usize);
+        let mut buffer = BytesMut::new();
+        buffer.put_u32_le(data.len() as u32);
+        buffer.put(&data[..]);
+        assert_eq!(read2.decode(&mut buffer).unwrap(), Some(Err(ReasonForBan::Abusive)));
+    }
+
     #[test]
     fn test_account_id_bytes() {
         use near_primitives::types::AccountId;
@@ -1,5 +1,8 @@
 pub(crate) mod codec;
+pub(crate) mod noise;
 pub(crate) mod peer_actor;
+pub(crate) mod quic;
+pub(crate) mod rate_limiter;
 mod tracker;
 mod transfer_stats;
 mod utils;
@@ -0,0 +1,506 @@
+/// Encrypted and authenticated transport between peers.
+///
+/// Before the `Handshake` is exchanged, the node opening a connection offers a
+/// `Noise_XX_25519_ChaChaPoly_BLAKE2s` handshake (see https://noiseprotocol.org/noise.html).
+/// Every node has a static X25519 key for the lifetime of the process, which it signs with its
+/// node key. The signature is sent as the handshake payload, so at the end of the handshake both
+/// sides know the `PeerId` of the other side, and all following frames are encrypted.
+///
+/// Negotiation, so that old and new nodes interoperate:
+///     - The initiator sends its first handshake message in a regular frame starting with
+///       `NOISE_PREAMBLE`. The first byte of the preamble is not a valid `PeerMessage` variant,
+///       so an old node logs the frame as invalid data and keeps waiting for the `Handshake`.
+///     - A responder that understands the preamble answers with a frame starting with the same
+///       preamble. Without an answer within `fallback_timeout`, the initiator closes the
+///       connection and opens a plaintext one. It doesn't continue in plaintext on the same
+///       connection, where a slow responder would take the `Handshake` for the last handshake
+///       message.
+///     - The responder looks at the first bytes of every inbound connection, and accepts both
+///       encrypted and plaintext connections.
+///
+/// NOTES:
+///     - Transport frames keep the length prefix of `Codec` in plaintext, and are not limited to
+///       the 65535 bytes of the Noise specification.
+///     - A middlebox dropping the answer to the offer makes the connection fall back to plaintext.
+///       This is refused for peers which established an encrypted session with this node before,
+///       and for all peers with `require_encrypted_transport`.
+use borsh::{BorshDeserialize, BorshSerialize};
+use chacha20poly1305::aead::{Aead, NewAead, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use curve25519_dalek::constants::X25519_BASEPOINT;
+use curve25519_dalek::montgomery::MontgomeryPoint;
+use curve25519_dalek::scalar::Scalar;
+use near_crypto::{SecretKey, Signature};
+use near_primitives::network::PeerId;
+use rand::RngCore;
+use std::io::{Error, ErrorKind};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+const PROTOCOL_NAME: &[u8] = b"Noise_XX_25519_ChaChaPoly_BLAKE2s";
+/// Prefix of the first frame of both sides, also used as the prologue of the handshake.
+pub(crate) const NOISE_PREAMBLE: [u8; 8] = [0xff, b'N', b'O', b'I', b'S', b'E', 0, 1];
+/// Prefix of the data signed by the node key to authenticate the static key.
+const STATIC_KEY_SIGNATURE_PREFIX: &[u8] = b"near-network-noise-static-key:";
+/// Handshake messages are small, larger frames are rejected before reading them.
+const MAX_HANDSHAKE_MESSAGE_SIZE: usize = 1024;
+const DH_LEN: usize = 32;
+const HASH_LEN: usize = 32;
+const BLOCK_LEN: usize = 64;
+pub(crate) const TAG_LEN: usize = 16;
+
+type Hash = [u8; HASH_LEN];
+
+fn invalid_data(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+fn blake2s(parts: &[&[u8]]) -> Hash {
+    use blake2::{Blake2s, Digest};
+    let mut hasher = Blake2s::new();
+    for part in parts {
+        hasher.update(part);
+    }
+    hasher.finalize().into()
+}
+
+fn hmac(key: &Hash, parts: &[&[u8]]) -> Hash {
+    let mut ipad = [0x36u8; BLOCK_LEN];
+    let mut opad = [0x5cu8; BLOCK_LEN];
+    for (i, byte) in key.iter().enumerate() {
+        ipad[i] ^= byte;
+        opad[i] ^= byte;
+    }
+    let mut inner_parts: Vec<&[u8]> = vec![&ipad[..]];
+    inner_parts.extend_from_slice(parts);
+    let inner = blake2s(&inner_parts);
+    blake2s(&[&opad[..], &inner[..]])
+}
+
+fn hkdf(chaining_key: &Hash, input_key_material: &[u8]) -> (Hash, Hash) {
+    let temp_key = hmac(chaining_key, &[input_key_material]);
+    let output1 = hmac(&temp_key, &[&[1u8][..]]);
+    let output2 = hmac(&temp_key, &[&output1[..], &[2u8][..]]);
+    (output1, output2)
+}
+
+/// X25519 key pair.
+#[derive(Clone)]
+struct KeyPair {
+    secret: Scalar,
+    public: [u8; DH_LEN],
+}
+
+impl KeyPair {
+    fn from_random() -> Self {
+        let mut bytes = [0u8; 32];
+        rand::rngs::OsRng.fill_bytes(&mut bytes);
+        bytes[0] &= 248;
+        bytes[31] &= 127;
+        bytes[31] |= 64;
+        let secret = Scalar::from_bits(bytes);
+        KeyPair { secret, public: (X25519_BASEPOINT * secret).to_bytes() }
+    }
+
+    fn dh(&self, public: &[u8; DH_LEN]) -> Result<[u8; DH_LEN], Error> {
+        let shared = (MontgomeryPoint(*public) * self.secret).to_bytes();
+        // Low order points result in a shared secret known to everybody.
+        if shared == [0u8; DH_LEN] {
+            return Err(invalid_data("Invalid public key in noise handshake"));
+        }
+        Ok(shared)
+    }
+}
+
+/// Proof that the static key of the handshake belongs to the node with this `PeerId`.
+#[derive(BorshSerialize, BorshDeserialize)]
+struct StaticKeyIdentity {
+    peer_id: PeerId,
+    signature: Signature,
+}
+
+impl StaticKeyIdentity {
+    fn data_to_sign(static_key: &[u8; DH_LEN]) -> Vec<u8> {
+        [STATIC_KEY_SIGNATURE_PREFIX, &static_key[..]].concat()
+    }
+
+    fn verify(&self, static_key: &[u8; DH_LEN]) -> bool {
+        self.signature.verify(&Self::data_to_sign(static_key), self.peer_id.public_key())
+    }
+}
+
+/// Static key of this node, together with its signature by the node key.
+pub(crate) struct NoiseKeys {
+    static_key: KeyPair,
+    identity: Vec<u8>,
+}
+
+impl NoiseKeys {
+    pub(crate) fn new(secret_key: &SecretKey) -> Self {
+        let static_key = KeyPair::from_random();
+        let identity = StaticKeyIdentity {
+            peer_id: PeerId::new(secret_key.public_key()),
+            signature: secret_key.sign(&StaticKeyIdentity::data_to_sign(&static_key.public)),
+        };
+        NoiseKeys { static_key, identity: identity.try_to_vec().unwrap() }
+    }
+}
+
+/// Encrypts or decrypts one direction of the connection.
+pub(crate) struct CipherState {
+    cipher: ChaCha20Poly1305,
+    nonce: u64,
+}
+
+impl CipherState {
+    pub(crate) fn new(key: &Hash) -> Self {
+        CipherState { cipher: ChaCha20Poly1305::new(Key::from_slice(key)), nonce: 0 }
+    }
+
+    fn next_nonce(&mut self) -> Nonce {
+        let mut nonce = [0u8; 12];
+        nonce[4..].copy_from_slice(&self.nonce.to_le_bytes());
+        self.nonce += 1;
+        *Nonce::from_slice(&nonce)
+    }
+
+    pub(crate) fn encrypt(&mut self, ad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+        let nonce = self.next_nonce();
+        self.cipher
+            .encrypt(&nonce, Payload { msg: plaintext, aad: ad })
+            .expect("encryption doesn't fail for messages below 256 GiB")
+    }
+
+    pub(crate) fn decrypt(&mut self, ad: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, Error> {
+        let nonce = self.next_nonce();
+        self.cipher
+            .decrypt(&nonce, Payload { msg: ciphertext, aad: ad })
+            .map_err(|_| invalid_data("Failed to decrypt message"))
+    }
+}
+
+struct SymmetricState {
+    chaining_key: Hash,
+    hash: Hash,
+    cipher: Option<CipherState>,
+}
+
+impl SymmetricState {
+    fn new() -> Self {
+        // The protocol name is longer than `HASH_LEN`, so it is hashed.
+        let hash = blake2s(&[PROTOCOL_NAME]);
+        SymmetricState { chaining_key: hash, hash, cipher: None }
+    }
+
+    fn mix_hash(&mut self, data: &[u8]) {
+        self.hash = blake2s(&[&self.hash[..], data]);
+    }
+
+    fn mix_key(&mut self, input_key_material: &[u8]) {
+        let (chaining_key, key) = hkdf(&self.chaining_key, input_key_material);
+        self.chaining_key = chaining_key;
+        self.cipher = Some(CipherState::new(&key));
+    }
+
+    fn encrypt_and_hash(&mut self, plaintext: &[u8]) -> Vec<u8> {
+        let ciphertext = match self.cipher.as_mut() {
+            Some(cipher) => cipher.encrypt(&self.hash, plaintext),
+            None => plaintext.to_vec(),
+        };
+        self.mix_hash(&ciphertext);
+        ciphertext
+    }
+
+    fn decrypt_and_hash(&mut self, ciphertext: &[u8]) -> Result<Vec<u8>, Error> {
+        let plaintext = match self.cipher.as_mut() {
+            Some(cipher) => cipher.decrypt(&self.hash, ciphertext)?,
+            None => ciphertext.to_vec(),
+        };
+        self.mix_hash(ciphertext);
+        Ok(plaintext)
+    }
+
+    fn split(&self) -> (CipherState, CipherState) {
+        let (key1, key2) = hkdf(&self.chaining_key, &[]);
+        (CipherState::new(&key1), CipherState::new(&key2))
+    }
+}
+
+/// Both directions of an established encrypted session.
+pub(crate) struct NoiseSession {
+    /// Peer which signed the static key of the other side.
+    pub(crate) remote_peer_id: PeerId,
+    pub(crate) send: CipherState,
+    pub(crate) recv: CipherState,
+}
+
+/// State of the `XX` handshake:
+///     -> e
+///     <- e, ee, s, es
+///     -> s, se
+struct HandshakeState<'a> {
+    keys: &'a NoiseKeys,
+    symmetric: SymmetricState,
+    ephemeral: KeyPair,
+    remote_ephemeral: [u8; DH_LEN],
+    remote_peer_id: Option<PeerId>,
+}
+
+impl<'a> HandshakeState<'a> {
+    fn new(keys: &'a NoiseKeys) -> Self {
+        let mut symmetric = SymmetricState::new();
+        symmetric.mix_hash(&NOISE_PREAMBLE);
+        HandshakeState {
+            keys,
+            symmetric,
+            ephemeral: KeyPair::from_random(),
+            remote_ephemeral: [0u8; DH_LEN],
+            remote_peer_id: None,
+        }
+    }
+
+    fn write_ephemeral(&mut self, message: &mut Vec<u8>) {
+        message.extend_from_slice(&self.ephemeral.public);
+        self.symmetric.mix_hash(&self.ephemeral.public);
+    }
+
+    fn read_ephemeral<'m>(&mut self, message: &'m [u8]) -> Result<&'m [u8], Error> {
+        if message.len() < DH_LEN {
+            return Err(invalid_data("Noise handshake message is too short"));
+        }
+        let (ephemeral, rest) = message.split_at(DH_LEN);
+        self.remote_ephemeral.copy_from_slice(ephemeral);
+        self.symmetric.mix_hash(ephemeral);
+        Ok(rest)
+    }
+
+    /// Writes the static key and its signature, which are encrypted at this point.
+    fn write_static_and_identity(&mut self, message: &mut Vec<u8>, dh: [u8; DH_LEN]) {
+        let static_key = self.symmetric.encrypt_and_hash(&self.keys.static_key.public);
+        message.extend_from_slice(&static_key);
+        self.symmetric.mix_key(&dh);
+        let identity = self.symmetric.encrypt_and_hash(&self.keys.identity);
+        message.extend_from_slice(&identity);
+    }
+
+    /// Reads the static key of the other side, and checks that it is signed by its node key.
+    /// `dh` is called with the static key of the other side.
+    fn read_static_and_identity(
+        &mut self,
+        message: &[u8],
+        dh: impl FnOnce(&Self, &[u8; DH_LEN]) -> Result<[u8; DH_LEN], Error>,
+    ) -> Result<(), Error> {
+        if message.len() < DH_LEN + TAG_LEN {
+            return Err(invalid_data("Noise handshake message is too short"));
+        }
+        let (static_key, identity) = message.split_at(DH_LEN + TAG_LEN);
+        let mut remote_static = [0u8; DH_LEN];
+        remote_static.copy_from_slice(&self.symmetric.decrypt_and_hash(static_key)?);
+        let dh = dh(self, &remote_static)?;
+        self.symmetric.mix_key(&dh);
+        let identity =
+            StaticKeyIdentity::try_from_slice(&self.symmetric.decrypt_and_hash(identity)?)?;
+        if !identity.verify(&remote_static) {
+            return Err(invalid_data("Invalid signature of the noise static key"));
+        }
+        self.remote_peer_id = Some(identity.peer_id);
+        Ok(())
+    }
+
+    /// Initiator: `-> e`
+    fn write_message_a(&mut self) -> Vec<u8> {
+        let mut message = NOISE_PREAMBLE.to_vec();
+        self.write_ephemeral(&mut message);
+        // Empty payload, which isn't encrypted yet.
+        let payload = self.symmetric.encrypt_and_hash(&[]);
+        message.extend_from_slice(&payload);
+        message
+    }
+
+    /// Responder: `-> e`
+    fn read_message_a(&mut self, message: &[u8]) -> Result<(), Error> {
+        let payload = self.read_ephemeral(message)?;
+        self.symmetric.decrypt_and_hash(payload)?;
+        Ok(())
+    }
+
+    /// Responder: `<- e, ee, s, es`
+    fn write_message_b(&mut self) -> Result<Vec<u8>, Error> {
+        let mut message = NOISE_PREAMBLE.to_vec();
+        self.write_ephemeral(&mut message);
+        let ee = self.ephemeral.dh(&self.remote_ephemeral)?;
+        self.symmetric.mix_key(&ee);
+        let es = self.keys.static_key.dh(&self.remote_ephemeral)?;
+        self.write_static_and_identity(&mut message, es);
+        Ok(message)
+    }
+
+    /// Initiator: `<- e, ee, s, es`
+    fn read_message_b(&mut self, message: &[u8]) -> Result<(), Error> {
+        let rest = self.read_ephemeral(message)?;
+        let ee = self.ephemeral.dh(&self.remote_ephemeral)?;
+        self.symmetric.mix_key(&ee);
+        self.read_static_and_identity(rest, |state, remote_static| {
+            state.ephemeral.dh(remote_static)
+        })
+    }
+
+    /// Initiator: `-> s, se`
+    fn write_message_c(&mut self) -> Result<Vec<u8>, Error> {
+        let mut message = vec![];
+        let se = self.keys.static_key.dh(&self.remote_ephemeral)?;
+        self.write_static_and_identity(&mut message, se);
+        Ok(message)
+    }
+
+    /// Responder: `-> s, se`
+    fn read_message_c(&mut self, message: &[u8]) -> Result<(), Error> {
+        self.read_static_and_identity(message, |state, remote_static| {
+            state.ephemeral.dh(remote_static)
+        })
+    }
+
+    fn into_session(self, initiator: bool) -> NoiseSession {
+        let (initiator_to_responder, responder_to_initiator) = self.symmetric.split();
+        let (send, recv) = if initiator {
+            (initiator_to_responder, responder_to_initiator)
+        } else {
+            (responder_to_initiator, initiator_to_responder)
+        };
+        NoiseSession { remote_peer_id: self.remote_peer_id.unwrap(), send, recv }
+    }
+}
+
+async fn write_frame<S: AsyncWrite + Unpin>(stream: &mut S, data: &[u8]) -> Result<(), Error> {
+    let mut frame = Vec::with_capacity(4 + data.len());
+    frame.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    frame.extend_from_slice(data);
+    stream.write_all(&frame).await?;
+    stream.flush().await
+}
+
+async fn read_frame<S: AsyncRead + Unpin>(stream: &mut S) -> Result<Vec<u8>, Error> {
+    let len = stream.read_u32_le().await? as usize;
+    if len > MAX_HANDSHAKE_MESSAGE_SIZE {
+        return Err(invalid_data("Noise handshake message is too long"));
+    }
+    let mut data = vec![0u8; len];
+    stream.read_exact(&mut data).await?;
+    Ok(data)
+}
+
+/// Offers an encrypted session on an outbound connection. Returns `None` if the other side
+/// doesn't answer within `fallback_timeout`, in which case the connection must be closed.
+pub(crate) async fn initiate<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+    keys: &NoiseKeys,
+    fallback_timeout: Duration,
+) -> Result<Option<NoiseSession>, Error> {
+    let mut state = HandshakeState::new(keys);
+    write_frame(stream, &state.write_message_a()).await?;
+    let message = match tokio::time::timeout(fallback_timeout, read_frame(stream)).await {
+        Ok(message) => message?,
+        Err(_) => return Ok(None),
+    };
+    let message = message
+        .strip_prefix(&NOISE_PREAMBLE[..])
+        .ok_or_else(|| invalid_data("Unexpected answer to noise handshake"))?;
+    state.read_message_b(message)?;
+    write_frame(stream, &state.write_message_c()?).await?;
+    Ok(Some(state.into_session(true)))
+}
+
+/// Completes the encrypted session on an inbound connection, if the other side offers it.
+/// Otherwise returns the bytes already read from the stream, which are the beginning of the first
+/// plaintext frame.
+pub(crate) async fn accept<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+    keys: &NoiseKeys,
+) -> Result<(Vec<u8>, Option<NoiseSession>), Error> {
+    let mut prefix = vec![0u8; 4 + NOISE_PREAMBLE.len()];
+    stream.read_exact(&mut prefix).await?;
+    if prefix[4..] != NOISE_PREAMBLE {
+        return Ok((prefix, None));
+    }
+    let len = u32::from_le_bytes([prefix[0], prefix[1], prefix[2], prefix[3]]) as usize;
+    if !(NOISE_PREAMBLE.len()..=MAX_HANDSHAKE_MESSAGE_SIZE).contains(&len) {
+        return Err(invalid_data("Invalid noise handshake message length"));
+    }
+    let mut message = vec![0u8; len - NOISE_PREAMBLE.len()];
+    stream.read_exact(&mut message).await?;
+
+    let mut state = HandshakeState::new(keys);
+    state.read_message_a(&message)?;
+    write_frame(stream, &state.write_message_b()?).await?;
+    state.read_message_c(&read_frame(stream).await?)?;
+    Ok((vec![], Some(state.into_session(false))))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::peer::noise::{HandshakeState, NoiseKeys, NoiseSession, NOISE_PREAMBLE, TAG_LEN};
+    use near_crypto::{KeyType, SecretKey};
+    use near_primitives::network::PeerId;
+
+    fn handshake(
+        initiator_keys: &NoiseKeys,
+        responder_keys: &NoiseKeys,
+        tamper: impl Fn(&mut Vec<u8>),
+    ) -> Result<(NoiseSession, NoiseSession), std::io::Error> {
+        let mut initiator = HandshakeState::new(initiator_keys);
+        let mut responder = HandshakeState::new(responder_keys);
+        let mut message = initiator.write_message_a();
+        tamper(&mut message);
+        responder.read_message_a(&message[NOISE_PREAMBLE.len()..])?;
+        let mut message = responder.write_message_b()?;
+        tamper(&mut message);
+        initiator.read_message_b(&message[NOISE_PREAMBLE.len()..])?;
+        let mut message = initiator.write_message_c()?;
+        tamper(&mut message);
+        responder.read_message_c(&message)?;
+        Ok((initiator.into_session(true), responder.into_session(false)))
+    }
+
+    #[test]
+    fn test_noise_handshake() {
+        let initiator_key = SecretKey::from_seed(KeyType::ED25519, "initiator");
+        let responder_key = SecretKey::from_seed(KeyType::ED25519, "responder");
+        let (mut initiator, mut responder) =
+            handshake(&NoiseKeys::new(&initiator_key), &NoiseKeys::new(&responder_key), |_| {})
+                .unwrap();
+        assert_eq!(initiator.remote_peer_id, PeerId::new(responder_key.public_key()));
+        assert_eq!(responder.remote_peer_id, PeerId::new(initiator_key.public_key()));
+
+        for msg in [&b"first"[..], &b""[..], &[7u8; 1000][..]] {
+            let ciphertext = initiator.send.encrypt(&[], msg);
+            assert_eq!(ciphertext.len(), msg.len() + TAG_LEN);
+            assert_eq!(responder.recv.decrypt(&[], &ciphertext).unwrap(), msg);
+            let ciphertext = responder.send.encrypt(&[], msg);
+            assert_eq!(initiator.recv.decrypt(&[], &ciphertext).unwrap(), msg);
+        }
+
+        // Modified or replayed frames are rejected.
+        let mut ciphertext = initiator.send.encrypt(&[], b"message");
+        ciphertext[0] ^= 1;
+        assert!(responder.recv.decrypt(&[], &ciphertext).is_err());
+        let ciphertext = initiator.send.encrypt(&[], b"message");
+        assert!(responder.recv.decrypt(&[], &ciphertext).is_ok());
+        assert!(responder.recv.decrypt(&[], &ciphertext).is_err());
+    }
+
+    #[test]
+    fn test_noise_handshake_tampered() {
+        let initiator_keys = NoiseKeys::new(&SecretKey::from_seed(KeyType::ED25519, "initiator"));
+        let responder_keys = NoiseKeys::new(&SecretKey::from_seed(KeyType::ED25519, "responder"));
+        assert!(handshake(&initiator_keys, &responder_keys, |message| {
+            let last = message.len() - 1;
+            message[last] ^= 1;
+        })
+        .is_err());
+
+        // The static key is signed by a different node key.
+        let mut forged_keys = NoiseKeys::new(&SecretKey::from_seed(KeyType::ED25519, "forger"));
+        forged_keys.identity = initiator_keys.identity.clone();
+        assert!(handshake(&forged_keys, &responder_keys, |_| {}).is_err());
+    }
+}
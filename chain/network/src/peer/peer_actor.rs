@@ -1,4 +1,6 @@
 use crate::peer::codec::Codec;
+use crate::peer::quic::MessageClass;
+use crate::peer::rate_limiter::{MessageRateLimiter, RateLimitStatus};
 use crate::peer::tracker::Tracker;
 use crate::peer::utils;
 use crate::private_actix::{
@@ -19,9 +21,9 @@ use borsh::{BorshDeserialize, BorshSerialize};
 use lru::LruCache;
 use near_crypto::Signature;
 use near_network_primitives::types::{
-    Ban, NetworkViewClientMessages, NetworkViewClientResponses, PeerChainInfoV2, PeerIdOrHash,
-    PeerInfo, PeerManagerRequest, PeerStatsResult, PeerType, QueryPeerStats, ReasonForBan,
-    RoutedMessage, RoutedMessageBody, RoutedMessageFrom, StateResponseInfo,
+    Ban, NetworkViewClientMessages, NetworkViewClientResponses, PeerChainInfoV2, PeerEvidence,
+    PeerIdOrHash, PeerInfo, PeerManagerRequest, PeerStatsResult, PeerType, QueryPeerStats,
+    ReasonForBan, RoutedMessage, RoutedMessageBody, RoutedMessageFrom, StateResponseInfo,
     UPDATE_INTERVAL_LAST_TIME_RECEIVED_MESSAGE,
 };
 use near_network_primitives::types::{Edge, PartialEdgeInfo};
@@ -37,17 +39,18 @@ use near_primitives::utils::DisplayOption;
 use near_primitives::version::{
     ProtocolVersion, PEER_MIN_ALLOWED_PROTOCOL_VERSION, PROTOCOL_VERSION,
 };
-use near_rate_limiter::{ActixMessageWrapper, ThrottleController};
+use near_rate_limiter::{ActixMessageWrapper, ThrottleController, ThrottleToken};
 use std::cmp::max;
 use std::fmt::Debug;
 use std::io;
 use std::net::SocketAddr;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tracing::{debug, error, info, trace, warn};
 
-type WriteHalf = tokio::io::WriteHalf<tokio::net::TcpStream>;
+/// Write side of a TCP connection, or of a QUIC stream.
+pub(crate) type WriteHalf = Box<dyn tokio::io::AsyncWrite + Unpin + Send>;
 
 /// Maximum number of messages per minute from single peer.
 // TODO(#5453): current limit is way to high due to us sending lots of messages during sync.
@@ -61,6 +64,8 @@ const MAX_TRANSACTIONS_PER_BLOCK_MESSAGE: usize = 1000;
 const ROUTED_MESSAGE_CACHE_SIZE: usize = 1000;
 /// Duplicated messages will be dropped if routed through the same peer multiple times.
 const DROP_DUPLICATED_MESSAGES_PERIOD: Duration = Duration::from_millis(50);
+/// How often messages queued by the rate limits are checked for being let through.
+const RATE_LIMITED_MESSAGES_INTERVAL: Duration = Duration::from_millis(100);
 
 pub(crate) struct PeerActor {
     /// This node's id and address (either listening or socket address).
@@ -75,8 +80,9 @@ pub(crate) struct PeerActor {
     peer_status: PeerStatus,
     /// Protocol version to communicate with this peer.
     protocol_version: ProtocolVersion,
-    /// Framed wrapper to send messages through the TCP connection.
-    framed: FramedWrite<Vec<u8>, WriteHalf, Codec, Codec>,
+    /// Framed wrappers to send messages through the connection: a single one over TCP, and one
+    /// per `MessageClass` over QUIC.
+    framed: Vec<FramedWrite<Vec<u8>, WriteHalf, Codec, Codec>>,
     /// Handshake timeout.
     handshake_timeout: Duration,
     /// Peer manager recipient to break the dependency loop.
@@ -106,6 +112,18 @@ pub(crate) struct PeerActor {
     routed_message_cache: LruCache<(PeerId, PeerIdOrHash, Signature), Instant>,
     /// A helper data structure for limiting reading
     throttle_controller: ThrottleController,
+    /// Peer id authenticated by the encrypted session, if the connection established one.
+    session_peer_id: Option<PeerId>,
+    /// Set once the `Handshake` of the peer tells that it accepts compressed messages, shared
+    /// with the codecs of the connection.
+    peer_accepts_compression: Arc<AtomicBool>,
+    /// Limits of received and sent messages by message type. Messages to send are queued with
+    /// the index of the stream they are sent over.
+    message_rate_limiter: MessageRateLimiter<Vec<u8>, (Vec<u8>, usize)>,
+    /// When headers were last requested from the peer, to measure its latency.
+    headers_requested_at: Option<Instant>,
+    /// Whether this node accepts QUIC connections, told to the peer in the handshake.
+    accepts_quic: bool,
 }
 
 impl Debug for PeerActor {
@@ -121,7 +139,7 @@ impl PeerActor {
         peer_addr: SocketAddr,
         peer_info: Option<PeerInfo>,
         peer_type: PeerType,
-        framed: FramedWrite<Vec<u8>, WriteHalf, Codec, Codec>,
+        framed: Vec<FramedWrite<Vec<u8>, WriteHalf, Codec, Codec>>,
         handshake_timeout: Duration,
         peer_manager_addr: Addr<PeerManagerActor>,
         client_addr: Recipient<NetworkClientMessages>,
@@ -131,6 +149,10 @@ impl PeerActor {
         txns_since_last_block: Arc<AtomicUsize>,
         peer_counter: Arc<AtomicUsize>,
         throttle_controller: ThrottleController,
+        session_peer_id: Option<PeerId>,
+        peer_accepts_compression: Arc<AtomicBool>,
+        message_rate_limiter: MessageRateLimiter<Vec<u8>, (Vec<u8>, usize)>,
+        accepts_quic: bool,
     ) -> Self {
         PeerActor {
             my_node_info,
@@ -154,6 +176,11 @@ impl PeerActor {
             peer_counter,
             routed_message_cache: LruCache::new(ROUTED_MESSAGE_CACHE_SIZE),
             throttle_controller,
+            session_peer_id,
+            peer_accepts_compression,
+            message_rate_limiter,
+            headers_requested_at: None,
+            accepts_quic,
         }
     }
 
@@ -163,30 +190,78 @@ impl PeerActor {
         match msg {
             PeerMessage::Block(b) if self.tracker.has_received(b.hash()) => return,
             PeerMessage::BlockRequest(h) => self.tracker.push_request(*h),
+            PeerMessage::BlockHeadersRequest(_) => {
+                self.headers_requested_at = Some(Clock::instant())
+            }
             _ => (),
         };
 
         match msg.try_to_vec() {
             Ok(bytes) => {
-                self.tracker.increment_sent(bytes.len() as u64);
                 let bytes_len = bytes.len();
-                if !self.framed.write(bytes) {
-                    #[cfg(feature = "performance_stats")]
-                    let tid = near_rust_allocator_proxy::get_tid();
-                    #[cfg(not(feature = "performance_stats"))]
-                    let tid = 0;
-                    error!(
-                        "{} Failed to send message {} of size {}",
-                        tid,
-                        strum::AsStaticRef::as_static(msg),
-                        bytes_len,
-                    )
+                let stream = match self.framed.len() {
+                    1 => 0,
+                    _ => MessageClass::of(msg) as usize,
+                };
+                match self.message_rate_limiter.on_sent(
+                    msg.msg_variant(),
+                    (bytes, stream),
+                    bytes_len,
+                    Clock::instant(),
+                ) {
+                    RateLimitStatus::Allowed((bytes, stream)) => {
+                        self.write_message(msg.msg_variant(), bytes, stream)
+                    }
+                    RateLimitStatus::Queued => {
+                        debug!(target: "network", "Queueing message {} to {} over the rate limit", msg.msg_variant(), self.peer_addr);
+                    }
+                    RateLimitStatus::Dropped | RateLimitStatus::Ban => {
+                        debug!(target: "network", "Dropping message {} to {} over the rate limit", msg.msg_variant(), self.peer_addr);
+                        self.network_metrics.inc(
+                            NetworkMetrics::peer_message_rate_limited_tx(msg.msg_variant())
+                                .as_ref(),
+                        );
+                    }
                 }
             }
             Err(err) => error!(target: "network", "Error converting message to bytes: {}", err),
         };
     }
 
+    /// Writes a serialized message to the given stream of the connection.
+    fn write_message(&mut self, msg_variant: &str, bytes: Vec<u8>, stream: usize) {
+        let bytes_len = bytes.len();
+        self.tracker.increment_sent(bytes_len as u64);
+        if !self.framed[stream].write(bytes) {
+            #[cfg(feature = "performance_stats")]
+            let tid = near_rust_allocator_proxy::get_tid();
+            #[cfg(not(feature = "performance_stats"))]
+            let tid = 0;
+            error!("{} Failed to send message {} of size {}", tid, msg_variant, bytes_len)
+        }
+    }
+
+    /// Processes and sends the queued messages which the rate limits now let through, then
+    /// checks again after `RATE_LIMITED_MESSAGES_INTERVAL`.
+    fn process_rate_limited_messages(&mut self, ctx: &mut Context<PeerActor>) {
+        let now = Clock::instant();
+        while let Some((bytes, stream)) = self.message_rate_limiter.pop_sent(now) {
+            let msg_variant = utils::msg_variant(&bytes).unwrap_or_default();
+            self.write_message(msg_variant, bytes, stream);
+        }
+        while let Some(msg) = self.message_rate_limiter.pop_received(now) {
+            self.process_received_message(ctx, msg);
+        }
+
+        near_performance_metrics::actix::run_later(
+            ctx,
+            RATE_LIMITED_MESSAGES_INTERVAL,
+            move |act, ctx| {
+                act.process_rate_limited_messages(ctx);
+            },
+        );
+    }
+
     fn fetch_client_chain_info(&self, ctx: &mut Context<PeerActor>) {
         ctx.wait(
             self.view_client_addr
@@ -230,6 +305,8 @@ impl PeerActor {
                             act.my_node_info.addr_port(),
                             PeerChainInfoV2 { genesis_id, height, tracked_shards, archival },
                             act.partial_edge_info.as_ref().unwrap().clone(),
+                            Some(act.peer_addr),
+                            act.accepts_quic,
                         )),
                         _ => {
                             error!(target: "network", "Trying to talk with peer with no supported version: {}", act.protocol_version);
@@ -266,17 +343,24 @@ impl PeerActor {
         self.peer_info.as_ref().as_ref().map(|peer_info| &peer_info.id)
     }
 
-    fn receive_message(&mut self, ctx: &mut Context<PeerActor>, msg: PeerMessage) {
+    /// Passes a message to the client or the view client. `msg_len` is the size of the message
+    /// once decompressed, which is throttled on until the message is processed.
+    fn receive_message(&mut self, ctx: &mut Context<PeerActor>, msg: PeerMessage, msg_len: usize) {
         if msg.is_view_client_message() {
-            self.receive_view_client_message(ctx, msg);
+            self.receive_view_client_message(ctx, msg, msg_len);
         } else if msg.is_client_message() {
-            self.receive_client_message(ctx, msg);
+            self.receive_client_message(ctx, msg, msg_len);
         } else {
             debug_assert!(false, "expected (view) client message, got: {}", msg.msg_variant());
         }
     }
 
-    fn receive_view_client_message(&self, ctx: &mut Context<PeerActor>, msg: PeerMessage) {
+    fn receive_view_client_message(
+        &self,
+        ctx: &mut Context<PeerActor>,
+        msg: PeerMessage,
+        msg_len: usize,
+    ) {
         let mut msg_hash = None;
         let view_client_message = match msg {
             PeerMessage::Routed(message) => {
@@ -322,10 +406,12 @@ impl PeerActor {
             }
         };
 
+        let throttle_token = ThrottleToken::new(Some(self.throttle_controller.clone()), msg_len);
         self.view_client_addr
             .send(view_client_message)
             .into_actor(self)
             .then(move |res, act, _ctx| {
+                drop(throttle_token);
                 // Ban peer if client thinks received data is bad.
                 match res {
                     Ok(NetworkViewClientResponses::TxStatus(tx_result)) => {
@@ -382,11 +468,32 @@ impl PeerActor {
             .spawn(ctx);
     }
 
+    /// Reports evidence about the behaviour of the peer, to be recorded in its reputation.
+    fn report_peer(&self, peer_id: PeerId, evidence: PeerEvidence) {
+        self.peer_manager_addr.do_send(ActixMessageWrapper::new_without_size(
+            PeerManagerMessageRequest::NetworkRequests(NetworkRequests::ReportPeer {
+                peer_id,
+                evidence,
+            }),
+            Some(self.throttle_controller.clone()),
+        ));
+    }
+
     /// Process non handshake/peer related messages.
-    fn receive_client_message(&mut self, ctx: &mut Context<PeerActor>, msg: PeerMessage) {
+    fn receive_client_message(
+        &mut self,
+        ctx: &mut Context<PeerActor>,
+        msg: PeerMessage,
+        msg_len: usize,
+    ) {
         metrics::PEER_CLIENT_MESSAGE_RECEIVED_TOTAL.inc();
         let peer_id =
             if let Some(peer_id) = self.other_peer_id() { peer_id.clone() } else { return };
+        // Routed messages are signed by their author, the peer only forwarded them.
+        let author = match &msg {
+            PeerMessage::Routed(routed_message) => routed_message.author.clone(),
+            _ => peer_id.clone(),
+        };
 
         // Wrap peer message into what client expects.
         let network_client_msg = match msg {
@@ -395,7 +502,11 @@ impl PeerActor {
                 let block_hash = *block.hash();
                 self.tracker.push_received(block_hash);
                 self.chain_info.height = max(self.chain_info.height, block.header().height());
-                NetworkClientMessages::Block(block, peer_id, self.tracker.has_request(&block_hash))
+                let was_requested = self.tracker.has_request(&block_hash);
+                if was_requested {
+                    self.report_peer(peer_id.clone(), PeerEvidence::UsefulResponse);
+                }
+                NetworkClientMessages::Block(block, peer_id, was_requested)
             }
             PeerMessage::Transaction(transaction) => {
                 metrics::PEER_TRANSACTION_RECEIVED_TOTAL.inc();
@@ -406,6 +517,15 @@ impl PeerActor {
                 }
             }
             PeerMessage::BlockHeaders(headers) => {
+                if let Some(requested_at) = self.headers_requested_at.take() {
+                    self.report_peer(
+                        peer_id.clone(),
+                        PeerEvidence::Latency(requested_at.elapsed()),
+                    );
+                    if !headers.is_empty() {
+                        self.report_peer(peer_id.clone(), PeerEvidence::UsefulResponse);
+                    }
+                }
                 NetworkClientMessages::BlockHeaders(headers, peer_id)
             }
             // All Routed messages received at this point are for us.
@@ -447,6 +567,10 @@ impl PeerActor {
                     RoutedMessageBody::PartialEncodedChunkForward(forward) => {
                         NetworkClientMessages::PartialEncodedChunkForward(forward)
                     }
+                    #[cfg(feature = "protocol_feature_chunk_state_witness")]
+                    RoutedMessageBody::ChunkStateWitness(witness) => {
+                        NetworkClientMessages::ChunkStateWitness(witness)
+                    }
                     RoutedMessageBody::Ping(_)
                     | RoutedMessageBody::Pong(_)
                     | RoutedMessageBody::TxStatusRequest(_, _)
@@ -493,10 +617,12 @@ impl PeerActor {
             }
         };
 
+        let throttle_token = ThrottleToken::new(Some(self.throttle_controller.clone()), msg_len);
         self.client_addr
             .send(network_client_msg)
             .into_actor(self)
             .then(move |res, act, ctx| {
+                drop(throttle_token);
                 // Ban peer if client thinks received data is bad.
                 match res {
                     Ok(NetworkClientResponses::InvalidTx(err)) => {
@@ -506,6 +632,9 @@ impl PeerActor {
                     Ok(NetworkClientResponses::Ban { ban_reason }) => {
                         act.ban_peer(ctx, ban_reason);
                     }
+                    Ok(NetworkClientResponses::InvalidMessage) => {
+                        act.report_peer(author, PeerEvidence::InvalidMessage);
+                    }
                     Err(err) => {
                         error!(
                             target: "network",
@@ -580,84 +709,12 @@ impl PeerActor {
             info!(target: "network", "Received invalid data {:?} from {}: {}", logging::pretty_vec(msg), self.peer_info, err);
         }
     }
-}
-
-impl Actor for PeerActor {
-    type Context = Context<PeerActor>;
-
-    fn started(&mut self, ctx: &mut Self::Context) {
-        metrics::PEER_CONNECTIONS_TOTAL.inc();
-        // Fetch genesis hash from the client.
-        self.fetch_client_chain_info(ctx);
-
-        debug!(target: "network", "{:?}: Peer {:?} {:?} started", self.my_node_info.id, self.peer_addr, self.peer_type);
-        // Set Handshake timeout for stopping actor if peer is not ready after given period of time.
-
-        near_performance_metrics::actix::run_later(ctx, self.handshake_timeout, move |act, ctx| {
-            if act.peer_status != PeerStatus::Ready {
-                info!(target: "network", "Handshake timeout expired for {}", act.peer_info);
-                ctx.stop();
-            }
-        });
-
-        // If outbound peer, initiate handshake.
-        if self.peer_type == PeerType::Outbound {
-            self.send_handshake(ctx);
-        }
-    }
-
-    fn stopping(&mut self, _: &mut Self::Context) -> Running {
-        self.peer_counter.fetch_sub(1, Ordering::SeqCst);
-        metrics::PEER_CONNECTIONS_TOTAL.dec();
-        debug!(target: "network", "{:?}: Peer {} disconnected. {:?}", self.my_node_info.id, self.peer_info, self.peer_status);
-        if let Some(peer_info) = self.peer_info.as_ref() {
-            if let PeerStatus::Banned(ban_reason) = self.peer_status {
-                self.peer_manager_addr.do_send(PeerManagerMessageRequest::Ban(Ban {
-                    peer_id: peer_info.id.clone(),
-                    ban_reason,
-                }));
-            } else {
-                self.peer_manager_addr.do_send(PeerManagerMessageRequest::Unregister(Unregister {
-                    peer_id: peer_info.id.clone(),
-                    peer_type: self.peer_type,
-                    // If the PeerActor is no longer in the Connecting state this means
-                    // that the connection was consolidated at some point in the past.
-                    // Only if the connection was consolidated try to remove this peer from the
-                    // peer store. This avoids a situation in which both peers are connecting to
-                    // each other, and after resolving the tie, a peer tries to remove the other
-                    // peer from the active connection if it was added in the parallel connection.
-                    remove_from_peer_store: self.peer_status != PeerStatus::Connecting,
-                }))
-            }
-        }
-        Running::Stop
-    }
-
-    fn stopped(&mut self, _ctx: &mut Self::Context) {
-        Arbiter::current().stop();
-    }
-}
-
-impl WriteHandler<io::Error> for PeerActor {}
-
-impl StreamHandler<Result<Vec<u8>, ReasonForBan>> for PeerActor {
-    #[perf]
-    fn handle(&mut self, msg: Result<Vec<u8>, ReasonForBan>, ctx: &mut Self::Context) {
-        let msg = match msg {
-            Ok(msg) => msg,
-            Err(ban_reason) => {
-                self.ban_peer(ctx, ban_reason);
-                return;
-            }
-        };
-        // TODO(#5155) We should change our code to track size of messages received from Peer
-        // as long as it travels to PeerManager, etc.
 
-        self.update_stats_on_receiving_message(msg.len());
-
-        if self.should_we_drop_msg_without_decoding(&msg) {
-            return;
-        }
+    /// Decodes and processes a message received from the peer, once let through by the rate
+    /// limits.
+    fn process_received_message(&mut self, ctx: &mut Context<PeerActor>, msg: Vec<u8>) {
+        // Messages are throttled on their decompressed size while the client processes them.
+        let msg_len = msg.len();
         let peer_msg = match PeerMessage::try_from_slice(&msg) {
             Ok(peer_msg) => peer_msg,
             Err(err) => {
@@ -777,6 +834,16 @@ impl StreamHandler<Result<Vec<u8>, ReasonForBan>> for PeerActor {
                     // Connection will be closed by a handshake timeout
                 }
 
+                if self
+                    .session_peer_id
+                    .as_ref()
+                    .map_or(false, |peer_id| peer_id != &handshake.sender_peer_id)
+                {
+                    warn!(target: "network", "Received handshake from {} over a session authenticated by {:?}. Disconnecting peer", handshake.sender_peer_id, self.session_peer_id);
+                    ctx.stop();
+                    return;
+                }
+
                 // Verify signature of the new edge in handshake.
                 if !Edge::partial_verify(
                     self.my_node_id(),
@@ -798,6 +865,10 @@ impl StreamHandler<Result<Vec<u8>, ReasonForBan>> for PeerActor {
                     return;
                 }
 
+                if handshake.accepts_compression {
+                    self.peer_accepts_compression.store(true, Ordering::Relaxed);
+                }
+
                 let peer_info = PeerInfo {
                     id: handshake.sender_peer_id.clone(),
                     addr: handshake
@@ -816,6 +887,13 @@ impl StreamHandler<Result<Vec<u8>, ReasonForBan>> for PeerActor {
                         other_edge_info: handshake.partial_edge_info.clone(),
                         peer_protocol_version: self.protocol_version,
                         throttle_controller: self.throttle_controller.clone(),
+                        observed_addr: handshake.target_observed_addr,
+                        encrypted: self.session_peer_id.is_some(),
+                        // A peer which accepted this connection over QUIC accepts QUIC, even if
+                        // its protocol version doesn't tell it in the handshake.
+                        #[cfg(feature = "protocol_feature_quic_transport")]
+                        accepts_quic: handshake.accepts_quic
+                            || (self.peer_type == PeerType::Outbound && self.framed.len() > 1),
                     }), Some(self.throttle_controller.clone())))
                     .into_actor(self)
                     .then(move |res, act, ctx| {
@@ -988,7 +1066,11 @@ impl StreamHandler<Result<Vec<u8>, ReasonForBan>> for PeerActor {
                         .then(move |res, act, ctx| {
                             if res.map(|f| f.into_inner().as_routed_message_from()).unwrap_or(false)
                             {
-                                act.receive_message(ctx, PeerMessage::Routed(routed_message));
+                                act.receive_message(
+                                    ctx,
+                                    PeerMessage::Routed(routed_message),
+                                    msg_len,
+                                );
                             }
                             actix::fut::ready(())
                         })
@@ -996,7 +1078,7 @@ impl StreamHandler<Result<Vec<u8>, ReasonForBan>> for PeerActor {
                 }
             }
             (PeerStatus::Ready, msg) => {
-                self.receive_message(ctx, msg);
+                self.receive_message(ctx, msg, msg_len);
             }
             (_, msg) => {
                 warn!(target: "network", "Received {} while {:?} from {:?} connection.", msg, self.peer_status, self.peer_type);
@@ -1005,6 +1087,112 @@ impl StreamHandler<Result<Vec<u8>, ReasonForBan>> for PeerActor {
     }
 }
 
+impl Actor for PeerActor {
+    type Context = Context<PeerActor>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        metrics::PEER_CONNECTIONS_TOTAL.inc();
+        // Fetch genesis hash from the client.
+        self.fetch_client_chain_info(ctx);
+
+        debug!(target: "network", "{:?}: Peer {:?} {:?} started", self.my_node_info.id, self.peer_addr, self.peer_type);
+        // Set Handshake timeout for stopping actor if peer is not ready after given period of time.
+
+        near_performance_metrics::actix::run_later(ctx, self.handshake_timeout, move |act, ctx| {
+            if act.peer_status != PeerStatus::Ready {
+                info!(target: "network", "Handshake timeout expired for {}", act.peer_info);
+                ctx.stop();
+            }
+        });
+
+        // If outbound peer, initiate handshake.
+        if self.peer_type == PeerType::Outbound {
+            self.send_handshake(ctx);
+        }
+
+        self.process_rate_limited_messages(ctx);
+    }
+
+    fn stopping(&mut self, _: &mut Self::Context) -> Running {
+        self.peer_counter.fetch_sub(1, Ordering::SeqCst);
+        metrics::PEER_CONNECTIONS_TOTAL.dec();
+        debug!(target: "network", "{:?}: Peer {} disconnected. {:?}", self.my_node_info.id, self.peer_info, self.peer_status);
+        if let Some(peer_info) = self.peer_info.as_ref() {
+            if let PeerStatus::Banned(ban_reason) = self.peer_status {
+                self.peer_manager_addr.do_send(PeerManagerMessageRequest::Ban(Ban {
+                    peer_id: peer_info.id.clone(),
+                    ban_reason,
+                }));
+            } else {
+                self.peer_manager_addr.do_send(PeerManagerMessageRequest::Unregister(Unregister {
+                    peer_id: peer_info.id.clone(),
+                    peer_type: self.peer_type,
+                    // If the PeerActor is no longer in the Connecting state this means
+                    // that the connection was consolidated at some point in the past.
+                    // Only if the connection was consolidated try to remove this peer from the
+                    // peer store. This avoids a situation in which both peers are connecting to
+                    // each other, and after resolving the tie, a peer tries to remove the other
+                    // peer from the active connection if it was added in the parallel connection.
+                    remove_from_peer_store: self.peer_status != PeerStatus::Connecting,
+                }))
+            }
+        }
+        Running::Stop
+    }
+
+    fn stopped(&mut self, _ctx: &mut Self::Context) {
+        Arbiter::current().stop();
+    }
+}
+
+impl WriteHandler<io::Error> for PeerActor {}
+
+impl StreamHandler<Result<Vec<u8>, ReasonForBan>> for PeerActor {
+    #[perf]
+    fn handle(&mut self, msg: Result<Vec<u8>, ReasonForBan>, ctx: &mut Self::Context) {
+        let msg = match msg {
+            Ok(msg) => msg,
+            Err(ban_reason) => {
+                self.ban_peer(ctx, ban_reason);
+                return;
+            }
+        };
+        // TODO(#5155) We should change our code to track size of messages received from Peer
+        // as long as it travels to PeerManager, etc.
+        let msg_len = msg.len();
+        self.update_stats_on_receiving_message(msg_len);
+
+        if self.should_we_drop_msg_without_decoding(&msg) {
+            return;
+        }
+        let msg_variant = utils::msg_variant(&msg).unwrap_or_default();
+        let msg = match self.message_rate_limiter.on_received(
+            msg_variant,
+            msg,
+            msg_len,
+            Clock::instant(),
+        ) {
+            RateLimitStatus::Allowed(msg) => msg,
+            RateLimitStatus::Queued => {
+                debug!(target: "network", "Queueing message {} from {} over the rate limit", msg_variant, self.peer_addr);
+                return;
+            }
+            RateLimitStatus::Dropped => {
+                debug!(target: "network", "Dropping message {} from {} over the rate limit", msg_variant, self.peer_addr);
+                self.network_metrics
+                    .inc(NetworkMetrics::peer_message_rate_limited_rx(msg_variant).as_ref());
+                return;
+            }
+            RateLimitStatus::Ban => {
+                warn!(target: "network", "Peer {} keeps exceeding the rate limit of {} messages", self.peer_addr, msg_variant);
+                self.ban_peer(ctx, ReasonForBan::RateLimitExceeded);
+                return;
+            }
+        };
+        self.process_received_message(ctx, msg);
+    }
+}
+
 impl Handler<SendMessage> for PeerActor {
     type Result = ();
 
@@ -0,0 +1,102 @@
+/// QUIC transport of connections with peers.
+///
+/// Over a single TCP stream a large message, such as a state part, holds back every message sent
+/// after it, including approvals. Over QUIC each side instead opens one unidirectional stream per
+/// `MessageClass`, so that only messages of the same class wait for each other. Every stream starts
+/// with the byte of its class, followed by frames in the same format as over TCP.
+///
+/// Connections are encrypted with mutual TLS, each side using a self-signed certificate which the
+/// TLS handshake only proves it holds the key of. Each side then binds its certificate to its
+/// `PeerId`: it signs the certificate with its node key, and sends the signature at the beginning of
+/// its consensus stream. The other side checks it against the certificate of the connection, which
+/// then is an encrypted session authenticated by the `PeerId`, like a Noise session over TCP.
+///
+/// Nodes using QUIC listen for it on the UDP port with the same number as their TCP port, so the
+/// address advertised in `PeerInfo` stands for both transports. They tell in their `Handshake`
+/// that they accept QUIC connections.
+///
+/// NOTES:
+/// - Messages of different classes may arrive out of order. Messages received before the
+///   handshake completes are ignored as usual, and requests are retried by their senders.
+/// - The transport itself is only built with `protocol_feature_quic_transport`. Otherwise the
+///   single TCP stream of a connection carries every `MessageClass`.
+use crate::types::PeerMessage;
+use near_network_primitives::types::RoutedMessageBody;
+
+#[cfg(feature = "protocol_feature_quic_transport")]
+mod transport;
+
+#[cfg(feature = "protocol_feature_quic_transport")]
+pub(crate) use transport::{endpoint, QuicEndpoint, QuicStreams};
+
+/// Class of messages, sent over its own stream.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum MessageClass {
+    /// Handshake, blocks, approvals and everything not in the other classes.
+    Consensus = 0,
+    /// Chunk parts, their requests and forwards.
+    Chunks = 1,
+    /// Header, epoch and state sync.
+    Sync = 2,
+}
+
+impl MessageClass {
+    #[cfg(feature = "protocol_feature_quic_transport")]
+    pub(crate) const ALL: [MessageClass; 3] =
+        [MessageClass::Consensus, MessageClass::Chunks, MessageClass::Sync];
+
+    pub(crate) fn of(msg: &PeerMessage) -> Self {
+        match msg {
+            PeerMessage::BlockHeadersRequest(_)
+            | PeerMessage::BlockHeaders(_)
+            | PeerMessage::EpochSyncRequest(_)
+            | PeerMessage::EpochSyncResponse(_)
+            | PeerMessage::EpochSyncFinalizationRequest(_)
+            | PeerMessage::EpochSyncFinalizationResponse(_) => MessageClass::Sync,
+            PeerMessage::Routed(msg) => match &msg.body {
+                RoutedMessageBody::StateRequestHeader(..)
+                | RoutedMessageBody::StateRequestPart(..)
+                | RoutedMessageBody::StateResponse(_)
+                | RoutedMessageBody::VersionedStateResponse(_) => MessageClass::Sync,
+                RoutedMessageBody::PartialEncodedChunkRequest(_)
+                | RoutedMessageBody::PartialEncodedChunkResponse(_)
+                | RoutedMessageBody::PartialEncodedChunk(_)
+                | RoutedMessageBody::VersionedPartialEncodedChunk(_)
+                | RoutedMessageBody::PartialEncodedChunkForward(_) => MessageClass::Chunks,
+                #[cfg(feature = "protocol_feature_chunk_state_witness")]
+                RoutedMessageBody::ChunkStateWitness(_) => MessageClass::Chunks,
+                _ => MessageClass::Consensus,
+            },
+            _ => MessageClass::Consensus,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MessageClass;
+    use crate::types::PeerMessage;
+    use near_crypto::{KeyType, SecretKey};
+    use near_network_primitives::types::{
+        AccountOrPeerIdOrHash, Ping, RawRoutedMessage, RoutedMessageBody,
+    };
+    use near_primitives::hash::CryptoHash;
+    use near_primitives::network::PeerId;
+
+    fn routed(body: RoutedMessageBody) -> PeerMessage {
+        let key = SecretKey::from_seed(KeyType::ED25519, "test");
+        let peer_id = PeerId::new(key.public_key());
+        let msg = RawRoutedMessage { target: AccountOrPeerIdOrHash::PeerId(peer_id.clone()), body };
+        PeerMessage::Routed(msg.sign(peer_id, &key, 1))
+    }
+
+    #[test]
+    fn test_message_class() {
+        assert_eq!(MessageClass::of(&PeerMessage::PeersRequest), MessageClass::Consensus);
+        assert_eq!(MessageClass::of(&PeerMessage::BlockHeaders(vec![])), MessageClass::Sync);
+        let state_request = RoutedMessageBody::StateRequestPart(0, CryptoHash::default(), 1);
+        assert_eq!(MessageClass::of(&routed(state_request)), MessageClass::Sync);
+        let ping = RoutedMessageBody::Ping(Ping { nonce: 0, source: PeerId::random() });
+        assert_eq!(MessageClass::of(&routed(ping)), MessageClass::Consensus);
+    }
+}
@@ -0,0 +1,300 @@
+/// Endpoints and authenticated connections of the QUIC transport, see the parent module.
+use super::MessageClass;
+use borsh::{BorshDeserialize, BorshSerialize};
+use futures::StreamExt;
+use near_crypto::{SecretKey, Signature};
+use near_primitives::network::PeerId;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+/// Application protocol negotiated in the TLS handshake.
+const ALPN: &[u8] = b"near";
+/// Server name of all nodes, certificates aren't checked against it.
+const SERVER_NAME: &str = "near";
+/// Prefix of the data signed by the node key to bind the certificate to the `PeerId`.
+const CERTIFICATE_SIGNATURE_PREFIX: &[u8] = b"near-quic-certificate";
+/// Certificate identities are small, larger ones are rejected before reading them.
+const MAX_CERTIFICATE_IDENTITY_SIZE: usize = 1024;
+/// Interval of keep alive packets, so that quiet connections aren't closed for being idle.
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Streams of a QUIC connection, indexed by `MessageClass`.
+pub(crate) struct QuicStreams {
+    pub(crate) remote_addr: SocketAddr,
+    /// `PeerId` the certificate of the other side is bound to.
+    pub(crate) remote_peer_id: PeerId,
+    pub(crate) send: Vec<quinn::SendStream>,
+    pub(crate) recv: Vec<quinn::RecvStream>,
+}
+
+/// Proof that the certificate of a connection belongs to the node with this `PeerId`.
+#[derive(BorshSerialize, BorshDeserialize)]
+struct CertificateIdentity {
+    peer_id: PeerId,
+    signature: Signature,
+}
+
+impl CertificateIdentity {
+    fn data_to_sign(certificate: &rustls::Certificate) -> Vec<u8> {
+        [CERTIFICATE_SIGNATURE_PREFIX, &certificate.0].concat()
+    }
+
+    fn verify(&self, certificate: &rustls::Certificate) -> bool {
+        self.signature.verify(&Self::data_to_sign(certificate), self.peer_id.public_key())
+    }
+}
+
+/// Accepts any certificate in the TLS handshake, which are bound to the `PeerId` of the other
+/// side by its `CertificateIdentity` instead.
+struct AcceptAnyCertificate;
+
+impl rustls::client::ServerCertVerifier for AcceptAnyCertificate {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &rustls::Certificate,
+        _intermediates: &[rustls::Certificate],
+        _server_name: &rustls::ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<rustls::client::ServerCertVerified, rustls::Error> {
+        Ok(rustls::client::ServerCertVerified::assertion())
+    }
+}
+
+impl rustls::server::ClientCertVerifier for AcceptAnyCertificate {
+    fn client_auth_root_subjects(&self) -> Option<rustls::DistinguishedNames> {
+        Some(rustls::DistinguishedNames::new())
+    }
+
+    fn verify_client_cert(
+        &self,
+        _end_entity: &rustls::Certificate,
+        _intermediates: &[rustls::Certificate],
+        _now: SystemTime,
+    ) -> Result<rustls::server::ClientCertVerified, rustls::Error> {
+        Ok(rustls::server::ClientCertVerified::assertion())
+    }
+}
+
+/// Self-signed certificate of this node, with its key and its signature by the node key.
+struct NodeCertificate {
+    certificate: rustls::Certificate,
+    key: rustls::PrivateKey,
+    identity: CertificateIdentity,
+}
+
+impl NodeCertificate {
+    fn new(secret_key: &SecretKey) -> io::Result<Self> {
+        let cert = rcgen::generate_simple_self_signed(vec![SERVER_NAME.to_string()])
+            .map_err(other_error)?;
+        let certificate = rustls::Certificate(cert.serialize_der().map_err(other_error)?);
+        let identity = CertificateIdentity {
+            peer_id: PeerId::new(secret_key.public_key()),
+            signature: secret_key.sign(&CertificateIdentity::data_to_sign(&certificate)),
+        };
+        Ok(Self {
+            certificate,
+            key: rustls::PrivateKey(cert.serialize_private_key_der()),
+            identity,
+        })
+    }
+}
+
+/// QUIC endpoint of this node.
+pub(crate) struct QuicEndpoint {
+    pub(crate) endpoint: quinn::Endpoint,
+    /// Serialized `CertificateIdentity` of this node, sent over every connection.
+    identity: Arc<Vec<u8>>,
+}
+
+impl QuicEndpoint {
+    pub(crate) fn connect(&self, addr: SocketAddr) -> io::Result<quinn::Connecting> {
+        self.endpoint.connect(addr, SERVER_NAME).map_err(other_error)
+    }
+
+    /// Opens a stream for each message class, accepts the streams of the peer and authenticates
+    /// it.
+    pub(crate) fn open_streams(
+        &self,
+        connection: quinn::NewConnection,
+    ) -> impl std::future::Future<Output = io::Result<QuicStreams>> {
+        open_streams(connection, self.identity.clone())
+    }
+}
+
+fn other_error<E: Into<Box<dyn std::error::Error + Send + Sync>>>(err: E) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, err)
+}
+
+fn transport_config() -> Arc<quinn::TransportConfig> {
+    let mut config = quinn::TransportConfig::default();
+    config.keep_alive_interval(Some(KEEP_ALIVE_INTERVAL));
+    Arc::new(config)
+}
+
+fn server_config(certificate: &NodeCertificate) -> io::Result<quinn::ServerConfig> {
+    let mut crypto = rustls::ServerConfig::builder()
+        .with_safe_defaults()
+        .with_client_cert_verifier(Arc::new(AcceptAnyCertificate))
+        .with_single_cert(vec![certificate.certificate.clone()], certificate.key.clone())
+        .map_err(other_error)?;
+    crypto.alpn_protocols = vec![ALPN.to_vec()];
+    let mut config = quinn::ServerConfig::with_crypto(Arc::new(crypto));
+    config.transport = transport_config();
+    Ok(config)
+}
+
+fn client_config(certificate: &NodeCertificate) -> io::Result<quinn::ClientConfig> {
+    let mut crypto = rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(Arc::new(AcceptAnyCertificate))
+        .with_single_cert(vec![certificate.certificate.clone()], certificate.key.clone())
+        .map_err(other_error)?;
+    crypto.alpn_protocols = vec![ALPN.to_vec()];
+    let mut config = quinn::ClientConfig::new(Arc::new(crypto));
+    config.transport = transport_config();
+    Ok(config)
+}
+
+/// Creates the QUIC endpoint of this node, with a certificate signed by `secret_key`. It accepts
+/// connections if `addr` is given, and only makes outbound ones otherwise.
+pub(crate) fn endpoint(
+    addr: Option<SocketAddr>,
+    secret_key: &SecretKey,
+) -> io::Result<(QuicEndpoint, Option<quinn::Incoming>)> {
+    let certificate = NodeCertificate::new(secret_key)?;
+    let (mut endpoint, incoming) = match addr {
+        Some(addr) => {
+            let (endpoint, incoming) = quinn::Endpoint::server(server_config(&certificate)?, addr)?;
+            (endpoint, Some(incoming))
+        }
+        None => (quinn::Endpoint::client(SocketAddr::from(([0, 0, 0, 0], 0)))?, None),
+    };
+    endpoint.set_default_client_config(client_config(&certificate)?);
+    let identity = Arc::new(certificate.identity.try_to_vec()?);
+    Ok((QuicEndpoint { endpoint, identity }, incoming))
+}
+
+/// Reads the `CertificateIdentity` of the other side, and checks it against the certificate of
+/// the connection.
+async fn read_remote_peer_id(
+    connection: &quinn::Connection,
+    stream: &mut quinn::RecvStream,
+) -> io::Result<PeerId> {
+    let mut len = [0u8; 4];
+    stream.read_exact(&mut len).await.map_err(other_error)?;
+    let len = u32::from_le_bytes(len) as usize;
+    if len > MAX_CERTIFICATE_IDENTITY_SIZE {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "certificate identity is too long"));
+    }
+    let mut data = vec![0u8; len];
+    stream.read_exact(&mut data).await.map_err(other_error)?;
+    let identity = CertificateIdentity::try_from_slice(&data)?;
+    let certificate = connection
+        .peer_identity()
+        .and_then(|certificates| certificates.downcast::<Vec<rustls::Certificate>>().ok())
+        .and_then(|certificates| certificates.first().cloned())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "no peer certificate"))?;
+    if !identity.verify(&certificate) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "invalid signature of the peer certificate",
+        ));
+    }
+    Ok(identity.peer_id)
+}
+
+async fn open_streams(
+    connection: quinn::NewConnection,
+    identity: Arc<Vec<u8>>,
+) -> io::Result<QuicStreams> {
+    let quinn::NewConnection { connection, mut uni_streams, .. } = connection;
+    let mut send = Vec::with_capacity(MessageClass::ALL.len());
+    for class in MessageClass::ALL {
+        let mut stream = connection.open_uni().await.map_err(other_error)?;
+        stream.write_all(&[class as u8]).await?;
+        if class == MessageClass::Consensus {
+            stream.write_all(&(identity.len() as u32).to_le_bytes()).await?;
+            stream.write_all(&identity).await?;
+        }
+        send.push(stream);
+    }
+
+    let mut recv: Vec<Option<quinn::RecvStream>> = MessageClass::ALL.iter().map(|_| None).collect();
+    let mut remote_peer_id = None;
+    for _ in MessageClass::ALL {
+        let mut stream = uni_streams
+            .next()
+            .await
+            .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?
+            .map_err(other_error)?;
+        let mut class = [0u8];
+        stream.read_exact(&mut class).await.map_err(other_error)?;
+        let slot =
+            recv.get_mut(class[0] as usize).filter(|slot| slot.is_none()).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unexpected stream of class {}", class[0]),
+                )
+            })?;
+        if class[0] == MessageClass::Consensus as u8 {
+            remote_peer_id = Some(read_remote_peer_id(&connection, &mut stream).await?);
+        }
+        *slot = Some(stream);
+    }
+    Ok(QuicStreams {
+        remote_addr: connection.remote_address(),
+        // Every class has a stream, so the consensus one was received.
+        remote_peer_id: remote_peer_id.unwrap(),
+        send,
+        recv: recv.into_iter().flatten().collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{endpoint, NodeCertificate};
+    use crate::peer::quic::MessageClass;
+    use futures::StreamExt;
+    use near_crypto::{KeyType, SecretKey};
+    use near_primitives::network::PeerId;
+
+    #[test]
+    fn test_certificate_identity() {
+        let secret_key = SecretKey::from_seed(KeyType::ED25519, "test");
+        let certificate = NodeCertificate::new(&secret_key).unwrap();
+        assert_eq!(certificate.identity.peer_id, PeerId::new(secret_key.public_key()));
+        assert!(certificate.identity.verify(&certificate.certificate));
+        // The signature doesn't hold for another certificate.
+        let other = NodeCertificate::new(&secret_key).unwrap();
+        assert!(!certificate.identity.verify(&other.certificate));
+    }
+
+    #[test]
+    fn test_connection_authenticated() {
+        let server_key = SecretKey::from_seed(KeyType::ED25519, "server");
+        let client_key = SecretKey::from_seed(KeyType::ED25519, "client");
+        tokio::runtime::Runtime::new().unwrap().block_on(async {
+            let (server, incoming) =
+                endpoint(Some("127.0.0.1:0".parse().unwrap()), &server_key).unwrap();
+            let (client, _) = endpoint(None, &client_key).unwrap();
+            let addr = server.endpoint.local_addr().unwrap();
+
+            let accept = async {
+                let connection = incoming.unwrap().next().await.unwrap().await.unwrap();
+                server.open_streams(connection).await.unwrap()
+            };
+            let connect = async {
+                let connection = client.connect(addr).unwrap().await.unwrap();
+                client.open_streams(connection).await.unwrap()
+            };
+            let (server_streams, client_streams) = futures::join!(accept, connect);
+            assert_eq!(server_streams.remote_peer_id, PeerId::new(client_key.public_key()));
+            assert_eq!(client_streams.remote_peer_id, PeerId::new(server_key.public_key()));
+            assert_eq!(server_streams.recv.len(), MessageClass::ALL.len());
+        });
+    }
+}
@@ -0,0 +1,298 @@
+use near_network_primitives::types::RateLimit;
+use near_rate_limiter::TokenBucket;
+use std::collections::{HashMap, VecDeque};
+use std::time::Instant;
+
+/// Number of received messages a peer may send while the queue of their type is full, before
+/// being banned.
+const MAX_RATE_LIMIT_VIOLATIONS: f64 = 100.0;
+/// Rate at which the allowance above recovers, in messages per second.
+const RATE_LIMIT_VIOLATIONS_RECOVERY: f64 = 1.0;
+/// Number of messages of a single type which may wait for the limits, in each direction.
+const MAX_QUEUED_MESSAGES: usize = 100;
+/// Total size of the messages which may wait for the limits, in each direction.
+const MAX_QUEUED_BYTES: usize = 32 * 1024 * 1024;
+
+/// Messages which are never limited, as delaying them would slow down consensus.
+const CONSENSUS_MESSAGES: [&str; 8] = [
+    "Block",
+    "BlockApproval",
+    "PartialEncodedChunk",
+    "VersionedPartialEncodedChunk",
+    "PartialEncodedChunkRequest",
+    "PartialEncodedChunkResponse",
+    "PartialEncodedChunkForward",
+    "ChunkStateWitness",
+];
+
+/// Whether messages of type `msg_variant` are exempt from the limits.
+pub(crate) fn is_consensus_message(msg_variant: &str) -> bool {
+    CONSENSUS_MESSAGES.contains(&msg_variant)
+}
+
+/// Outcome of checking a message against the limits.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum RateLimitStatus<T> {
+    /// The message is within the limits and can be processed right away.
+    Allowed(T),
+    /// The message exceeds the limits, it is queued until they let it through.
+    Queued,
+    /// The message exceeds the limits and its queue is full, it is dropped.
+    Dropped,
+    /// The peer has filled the queues too often and should be banned.
+    Ban,
+}
+
+#[derive(Clone, Debug)]
+struct MessageBuckets<T> {
+    messages: TokenBucket,
+    bytes: Option<TokenBucket>,
+    /// Messages over the limits with their sizes, oldest first.
+    queue: VecDeque<(T, usize)>,
+}
+
+impl<T> MessageBuckets<T> {
+    fn new(limit: &RateLimit, now: Instant) -> Self {
+        Self {
+            messages: TokenBucket::new(limit.messages_burst as f64, limit.messages_per_sec, now),
+            bytes: limit.bytes_per_sec.map(|rate| TokenBucket::new(rate as f64, rate as f64, now)),
+            queue: VecDeque::new(),
+        }
+    }
+
+    /// Takes tokens for a message of `size` bytes, from both buckets or none of them.
+    fn try_take(&mut self, size: usize, now: Instant) -> bool {
+        if let Some(bytes) = &mut self.bytes {
+            if bytes.tokens(now) <= 0.0 {
+                return false;
+            }
+        }
+        if !self.messages.try_take(1.0, now) {
+            return false;
+        }
+        if let Some(bytes) = &mut self.bytes {
+            bytes.try_take(size as f64, now);
+        }
+        true
+    }
+}
+
+/// Limits of the messages of one direction, by message type.
+#[derive(Clone, Debug)]
+struct Limits<T> {
+    buckets: HashMap<String, MessageBuckets<T>>,
+    /// Total size of the queued messages.
+    queued_bytes: usize,
+}
+
+impl<T> Limits<T> {
+    fn new(limits: &HashMap<String, RateLimit>, now: Instant) -> Self {
+        let buckets = limits
+            .iter()
+            .filter(|(name, _)| !is_consensus_message(name))
+            .map(|(name, limit)| (name.clone(), MessageBuckets::new(limit, now)))
+            .collect();
+        Self { buckets, queued_bytes: 0 }
+    }
+
+    fn on_message(
+        &mut self,
+        msg_variant: &str,
+        msg: T,
+        size: usize,
+        now: Instant,
+    ) -> RateLimitStatus<T> {
+        let buckets = match self.buckets.get_mut(msg_variant) {
+            Some(buckets) => buckets,
+            None => return RateLimitStatus::Allowed(msg),
+        };
+        // Queued messages of the same type go first, to keep their order.
+        if buckets.queue.is_empty() && buckets.try_take(size, now) {
+            return RateLimitStatus::Allowed(msg);
+        }
+        if buckets.queue.len() >= MAX_QUEUED_MESSAGES || self.queued_bytes + size > MAX_QUEUED_BYTES
+        {
+            return RateLimitStatus::Dropped;
+        }
+        buckets.queue.push_back((msg, size));
+        self.queued_bytes += size;
+        RateLimitStatus::Queued
+    }
+
+    /// Takes a queued message the limits now let through, if any.
+    fn pop_ready(&mut self, now: Instant) -> Option<T> {
+        for buckets in self.buckets.values_mut() {
+            let size = match buckets.queue.front() {
+                Some((_, size)) => *size,
+                None => continue,
+            };
+            if buckets.try_take(size, now) {
+                self.queued_bytes -= size;
+                return buckets.queue.pop_front().map(|(msg, _)| msg);
+            }
+        }
+        None
+    }
+}
+
+/// Per message type limits of a single connection, for received and sent messages.
+/// Message types are named after `PeerMessage::msg_variant`.
+///
+/// Messages over the limits are queued and processed once the limits let them through, after the
+/// messages of other types. Consensus messages are never limited.
+#[derive(Clone, Debug)]
+pub(crate) struct MessageRateLimiter<R, S> {
+    received: Limits<R>,
+    sent: Limits<S>,
+    /// Allowance of received messages dropped for a full queue, the peer is banned once it runs
+    /// out.
+    violations: TokenBucket,
+}
+
+impl<R, S> MessageRateLimiter<R, S> {
+    pub(crate) fn new(limits: &HashMap<String, RateLimit>, now: Instant) -> Self {
+        Self {
+            received: Limits::new(limits, now),
+            sent: Limits::new(limits, now),
+            violations: TokenBucket::new(
+                MAX_RATE_LIMIT_VIOLATIONS,
+                RATE_LIMIT_VIOLATIONS_RECOVERY,
+                now,
+            ),
+        }
+    }
+
+    /// Checks a message of type `msg_variant` and `size` bytes received from the peer.
+    pub(crate) fn on_received(
+        &mut self,
+        msg_variant: &str,
+        msg: R,
+        size: usize,
+        now: Instant,
+    ) -> RateLimitStatus<R> {
+        match self.received.on_message(msg_variant, msg, size, now) {
+            RateLimitStatus::Dropped if !self.violations.try_take(1.0, now) => RateLimitStatus::Ban,
+            status => status,
+        }
+    }
+
+    /// Checks a message of type `msg_variant` and `size` bytes to be sent to the peer.
+    pub(crate) fn on_sent(
+        &mut self,
+        msg_variant: &str,
+        msg: S,
+        size: usize,
+        now: Instant,
+    ) -> RateLimitStatus<S> {
+        self.sent.on_message(msg_variant, msg, size, now)
+    }
+
+    /// Takes a queued received message the limits now let through, if any.
+    pub(crate) fn pop_received(&mut self, now: Instant) -> Option<R> {
+        self.received.pop_ready(now)
+    }
+
+    /// Takes a queued message to send the limits now let through, if any.
+    pub(crate) fn pop_sent(&mut self, now: Instant) -> Option<S> {
+        self.sent.pop_ready(now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        MessageRateLimiter, RateLimitStatus, MAX_QUEUED_MESSAGES, MAX_RATE_LIMIT_VIOLATIONS,
+    };
+    use near_network_primitives::types::RateLimit;
+    use std::collections::HashMap;
+    use std::time::{Duration, Instant};
+
+    fn limits() -> HashMap<String, RateLimit> {
+        let mut limits = HashMap::new();
+        limits.insert(
+            "StateRequestPart".to_string(),
+            RateLimit { messages_per_sec: 1.0, messages_burst: 2, bytes_per_sec: None },
+        );
+        limits.insert(
+            "BlockRequest".to_string(),
+            RateLimit { messages_per_sec: 100.0, messages_burst: 100, bytes_per_sec: Some(1000) },
+        );
+        limits.insert(
+            "Block".to_string(),
+            RateLimit { messages_per_sec: 1.0, messages_burst: 1, bytes_per_sec: None },
+        );
+        limits
+    }
+
+    #[test]
+    fn test_message_rate_limiter() {
+        let now = Instant::now();
+        let mut limiter = MessageRateLimiter::<u32, u32>::new(&limits(), now);
+        assert_eq!(
+            limiter.on_received("StateRequestPart", 1, 10, now),
+            RateLimitStatus::Allowed(1)
+        );
+        assert_eq!(
+            limiter.on_received("StateRequestPart", 2, 10, now),
+            RateLimitStatus::Allowed(2)
+        );
+        assert_eq!(limiter.on_received("StateRequestPart", 3, 10, now), RateLimitStatus::Queued);
+        assert_eq!(limiter.on_received("StateRequestPart", 4, 10, now), RateLimitStatus::Queued);
+        assert_eq!(limiter.pop_received(now), None);
+        // Other message types aren't limited, nor are consensus messages.
+        assert_eq!(limiter.on_received("PeersRequest", 5, 10, now), RateLimitStatus::Allowed(5));
+        for i in 0..10 {
+            assert_eq!(limiter.on_received("Block", i, 10, now), RateLimitStatus::Allowed(i));
+        }
+
+        // Queued messages are let through in order as the limits allow, and before new ones.
+        let later = now + Duration::from_secs(1);
+        assert_eq!(limiter.on_received("StateRequestPart", 6, 10, later), RateLimitStatus::Queued);
+        assert_eq!(limiter.pop_received(later), Some(3));
+        assert_eq!(limiter.pop_received(later), None);
+        let later = later + Duration::from_secs(2);
+        assert_eq!(limiter.pop_received(later), Some(4));
+        assert_eq!(limiter.pop_received(later), Some(6));
+        assert_eq!(limiter.pop_received(later), None);
+
+        // Sent messages are limited separately.
+        assert_eq!(limiter.on_sent("StateRequestPart", 1, 10, now), RateLimitStatus::Allowed(1));
+        assert_eq!(limiter.on_sent("StateRequestPart", 2, 10, now), RateLimitStatus::Allowed(2));
+        assert_eq!(limiter.on_sent("StateRequestPart", 3, 10, now), RateLimitStatus::Queued);
+        assert_eq!(limiter.pop_sent(now + Duration::from_secs(1)), Some(3));
+
+        // Bytes are limited too.
+        assert_eq!(limiter.on_sent("BlockRequest", 1, 1500, now), RateLimitStatus::Allowed(1));
+        assert_eq!(limiter.on_sent("BlockRequest", 2, 10, now), RateLimitStatus::Queued);
+    }
+
+    #[test]
+    fn test_message_rate_limiter_ban() {
+        let now = Instant::now();
+        let mut limiter = MessageRateLimiter::<(), ()>::new(&limits(), now);
+        for _ in 0..2 {
+            assert_eq!(
+                limiter.on_received("StateRequestPart", (), 10, now),
+                RateLimitStatus::Allowed(())
+            );
+        }
+        for _ in 0..MAX_QUEUED_MESSAGES {
+            assert_eq!(
+                limiter.on_received("StateRequestPart", (), 10, now),
+                RateLimitStatus::Queued
+            );
+        }
+        for _ in 0..MAX_RATE_LIMIT_VIOLATIONS as usize {
+            assert_eq!(
+                limiter.on_received("StateRequestPart", (), 10, now),
+                RateLimitStatus::Dropped
+            );
+        }
+        assert_eq!(limiter.on_received("StateRequestPart", (), 10, now), RateLimitStatus::Ban);
+        // Sent messages don't get anyone banned.
+        for _ in 0..2 + MAX_QUEUED_MESSAGES {
+            limiter.on_sent("StateRequestPart", (), 10, now);
+        }
+        assert_eq!(limiter.on_sent("StateRequestPart", (), 10, now), RateLimitStatus::Dropped);
+    }
+}
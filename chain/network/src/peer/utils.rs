@@ -1,5 +1,13 @@
+use crate::types::PeerMessage;
+use near_network_primitives::types::RoutedMessageBody;
+use strum::VariantNames;
 use tracing::error;
 
+/// Borsh variant index of `PeerMessage::Routed`.
+const ROUTED_MESSAGE_VARIANT: u8 = 13;
+/// Borsh variant index of `RoutedMessageBody::ForwardTx`.
+const FORWARD_TX_VARIANT: u8 = 1;
+
 /// Determines size of `PeerId` based on first byte of it's representation.
 /// Size of `PeerId` depends on type of `PublicMessage` it stores.
 /// `PublicKey::ED25519` -> `1 + 32 bytes`
@@ -14,17 +22,9 @@ fn peer_id_type_field_len(enum_var: u8) -> Option<usize> {
     }
 }
 
-/// Checks `bytes` represents `PeerMessage::Routed(RoutedMessage)`,
-/// and `RoutedMessage.body` has type of `RoutedMessageBody::ForwardTx`.
-///
-/// This is done to avoid expensive `borsh`-deserializing.
-pub(crate) fn is_forward_transaction(bytes: &[u8]) -> Option<bool> {
-    // PeerMessage::Routed variant == 13
-    let peer_message_variant = *bytes.get(0)?;
-    if peer_message_variant != 13 {
-        return Some(false);
-    }
-
+/// Returns the variant index of `RoutedMessage.body`, given that `bytes` represents
+/// `PeerMessage::Routed(RoutedMessage)`.
+fn routed_message_body_variant(bytes: &[u8]) -> Option<u8> {
     // target: PeerIdOrHash
     let author_variant_idx = {
         let target_field_len = {
@@ -70,14 +70,35 @@ pub(crate) fn is_forward_transaction(bytes: &[u8]) -> Option<bool> {
     // ttl: u8
     let message_body_idx = ttl_idx + 1;
 
-    // check if type is `RoutedMessageBody::ForwardTx`
-    let message_body_variant = *bytes.get(message_body_idx)?;
-    Some(message_body_variant == 1)
+    bytes.get(message_body_idx).copied()
+}
+
+/// Checks `bytes` represents `PeerMessage::Routed(RoutedMessage)`,
+/// and `RoutedMessage.body` has type of `RoutedMessageBody::ForwardTx`.
+///
+/// This is done to avoid expensive `borsh`-deserializing.
+pub(crate) fn is_forward_transaction(bytes: &[u8]) -> Option<bool> {
+    if *bytes.get(0)? != ROUTED_MESSAGE_VARIANT {
+        return Some(false);
+    }
+    Some(routed_message_body_variant(bytes)? == FORWARD_TX_VARIANT)
+}
+
+/// Returns the type of the message `bytes` represents, named like `PeerMessage::msg_variant`,
+/// without `borsh`-deserializing it.
+pub(crate) fn msg_variant(bytes: &[u8]) -> Option<&'static str> {
+    let peer_message_variant = *bytes.get(0)?;
+    if peer_message_variant == ROUTED_MESSAGE_VARIANT {
+        let body_variant = routed_message_body_variant(bytes)?;
+        RoutedMessageBody::VARIANTS.get(body_variant as usize).copied()
+    } else {
+        PeerMessage::VARIANTS.get(peer_message_variant as usize).copied()
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::peer::utils::is_forward_transaction;
+    use crate::peer::utils::{is_forward_transaction, msg_variant};
     use crate::types::PeerMessage;
     use borsh::BorshSerialize;
     use near_crypto::{KeyType, SecretKey};
@@ -171,6 +192,20 @@ mod tests {
             let msg = create_tx_forward(s);
             let bytes = msg.try_to_vec().unwrap();
             assert!(is_forward_transaction(&bytes).unwrap());
+            assert_eq!(msg_variant(&bytes), Some(msg.msg_variant()));
         })
     }
+
+    #[test]
+    fn test_msg_variant() {
+        for msg in [
+            PeerMessage::PeersRequest,
+            PeerMessage::BlockRequest(CryptoHash::default()),
+            PeerMessage::Disconnect,
+        ] {
+            assert_eq!(msg_variant(&msg.try_to_vec().unwrap()), Some(msg.msg_variant()));
+        }
+        assert_eq!(msg_variant(&[]), None);
+        assert_eq!(msg_variant(&[u8::MAX]), None);
+    }
 }
@@ -1,2 +1,3 @@
+pub(crate) mod nat;
 pub(crate) mod peer_manager_actor;
 pub(crate) mod peer_store;
@@ -0,0 +1,108 @@
+/// Discovery of the address this node is reachable at, for nodes behind NAT.
+///
+/// The address is learned in two ways:
+/// - Peers report in the handshake the address they see the connection at. Only peers this node
+///   dialed are trusted, as anyone can open inbound connections, and reports from the same subnet
+///   count once. Once enough subnets agree on the IP, the node dials the IP with the listening
+///   port to check it's reachable, and only then advertises it.
+/// - Optionally, the listening port is mapped on the local gateway with UPnP or NAT-PMP, which
+///   also tells the external IP of the gateway. Port mapping is only built with
+///   `protocol_feature_observed_address`.
+/// An external address set in the config takes precedence over both.
+use near_primitives::network::PeerId;
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::Duration;
+
+#[cfg(feature = "protocol_feature_observed_address")]
+mod port_mapping;
+
+#[cfg(feature = "protocol_feature_observed_address")]
+pub(crate) use port_mapping::{map_port, PORT_MAPPING_RENEW_INTERVAL};
+
+/// Number of subnets peers have to report the same IP from before it is advertised.
+const MIN_OBSERVED_ADDR_REPORTS: usize = 2;
+/// Timeout of dialing the observed address before advertising it.
+pub(crate) const DIAL_BACK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// IPs of this node reported by peers it dialed.
+#[derive(Default)]
+pub(crate) struct ObservedAddresses {
+    /// IP reported by every peer, with the subnet of the peer.
+    by_peer: HashMap<PeerId, (IpAddr, IpAddr)>,
+}
+
+impl ObservedAddresses {
+    /// Records that the peer at `peer_ip` sees this node at `ip`.
+    pub(crate) fn insert(&mut self, peer_id: PeerId, peer_ip: IpAddr, ip: IpAddr) {
+        self.by_peer.insert(peer_id, (ip, subnet(peer_ip)));
+    }
+
+    pub(crate) fn remove(&mut self, peer_id: &PeerId) {
+        self.by_peer.remove(peer_id);
+    }
+
+    /// IP reported from most subnets, if at least `MIN_OBSERVED_ADDR_REPORTS` of them agree on it.
+    pub(crate) fn best(&self) -> Option<IpAddr> {
+        let mut reports: HashMap<IpAddr, HashSet<IpAddr>> = HashMap::new();
+        for (ip, subnet) in self.by_peer.values() {
+            reports.entry(*ip).or_default().insert(*subnet);
+        }
+        reports
+            .into_iter()
+            .map(|(ip, subnets)| (ip, subnets.len()))
+            .filter(|(_, count)| *count >= MIN_OBSERVED_ADDR_REPORTS)
+            .max_by_key(|(ip, count)| (*count, *ip))
+            .map(|(ip, _)| ip)
+    }
+}
+
+/// The /24 network of IPv4 addresses and the /48 network of IPv6 addresses, which is usually
+/// assigned to a single operator.
+fn subnet(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, _] = ip.octets();
+            IpAddr::V4(Ipv4Addr::new(a, b, c, 0))
+        }
+        IpAddr::V6(ip) => {
+            let segments = ip.segments();
+            IpAddr::V6(Ipv6Addr::new(segments[0], segments[1], segments[2], 0, 0, 0, 0, 0))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ObservedAddresses;
+    use near_primitives::network::PeerId;
+    use std::net::IpAddr;
+
+    #[test]
+    fn test_observed_addresses() {
+        let ip1: IpAddr = "1.2.3.4".parse().unwrap();
+        let ip2: IpAddr = "5.6.7.8".parse().unwrap();
+        let peers: Vec<PeerId> = (0..4).map(|_| PeerId::random()).collect();
+        let peer_ips: Vec<IpAddr> = ["10.0.0.1", "10.0.0.2", "10.0.1.1", "10.0.2.1"]
+            .iter()
+            .map(|ip| ip.parse().unwrap())
+            .collect();
+        let mut observed = ObservedAddresses::default();
+
+        observed.insert(peers[0].clone(), peer_ips[0], ip1);
+        assert_eq!(observed.best(), None);
+        // Peers in the same subnet count once.
+        observed.insert(peers[1].clone(), peer_ips[1], ip1);
+        assert_eq!(observed.best(), None);
+        observed.insert(peers[2].clone(), peer_ips[2], ip1);
+        assert_eq!(observed.best(), Some(ip1));
+        observed.insert(peers[3].clone(), peer_ips[3], ip2);
+        assert_eq!(observed.best(), Some(ip1));
+
+        // Peers report again when they reconnect, and are forgotten when they disconnect.
+        observed.insert(peers[2].clone(), peer_ips[2], ip2);
+        assert_eq!(observed.best(), Some(ip2));
+        observed.remove(&peers[3]);
+        assert_eq!(observed.best(), None);
+    }
+}
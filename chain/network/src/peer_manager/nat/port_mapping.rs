@@ -0,0 +1,168 @@
+/// Mapping of the listening port on the local gateway with UPnP or NAT-PMP.
+use near_network_primitives::types::PortMapping;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket};
+use std::time::Duration;
+
+/// Lease of port mappings on the gateway.
+const PORT_MAPPING_LEASE: Duration = Duration::from_secs(60 * 60);
+/// Port mappings are renewed well before their lease expires.
+pub(crate) const PORT_MAPPING_RENEW_INTERVAL: Duration = Duration::from_secs(20 * 60);
+const PORT_MAPPING_DESCRIPTION: &str = "near";
+/// Port NAT-PMP gateways listen on.
+const NAT_PMP_PORT: u16 = 5351;
+/// Timeout of the first NAT-PMP request, doubled on every retry.
+const NAT_PMP_INITIAL_TIMEOUT: Duration = Duration::from_millis(250);
+const NAT_PMP_ATTEMPTS: usize = 4;
+const NAT_PMP_OP_EXTERNAL_ADDRESS: u8 = 0;
+const NAT_PMP_OP_MAP_UDP: u8 = 1;
+const NAT_PMP_OP_MAP_TCP: u8 = 2;
+
+fn other_error<E: Into<Box<dyn std::error::Error + Send + Sync>>>(err: E) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, err)
+}
+
+/// Maps `port` on the gateway for TCP, and for UDP as well if `udp` is set, and returns the
+/// external address. Blocks until the gateway answers.
+pub(crate) fn map_port(mapping: &PortMapping, port: u16, udp: bool) -> io::Result<SocketAddr> {
+    match mapping {
+        PortMapping::Disabled => Err(other_error("port mapping is disabled")),
+        PortMapping::Upnp => map_port_upnp(port, udp),
+        PortMapping::NatPmp { gateway } => {
+            map_port_nat_pmp(SocketAddr::new(*gateway, NAT_PMP_PORT), port, udp)
+        }
+    }
+}
+
+fn map_port_upnp(port: u16, udp: bool) -> io::Result<SocketAddr> {
+    let gateway = igd::search_gateway(Default::default()).map_err(other_error)?;
+    // The gateway needs the address of this node on the local network.
+    let socket = UdpSocket::bind(SocketAddr::from(([0, 0, 0, 0], 0)))?;
+    socket.connect(gateway.addr)?;
+    let local_ip = match socket.local_addr()?.ip() {
+        IpAddr::V4(ip) => ip,
+        IpAddr::V6(_) => return Err(other_error("UPnP gateway isn't reachable over IPv4")),
+    };
+    let local_addr = SocketAddrV4::new(local_ip, port);
+    let lease = PORT_MAPPING_LEASE.as_secs() as u32;
+    gateway
+        .add_port(igd::PortMappingProtocol::TCP, port, local_addr, lease, PORT_MAPPING_DESCRIPTION)
+        .map_err(other_error)?;
+    if udp {
+        gateway
+            .add_port(
+                igd::PortMappingProtocol::UDP,
+                port,
+                local_addr,
+                lease,
+                PORT_MAPPING_DESCRIPTION,
+            )
+            .map_err(other_error)?;
+    }
+    let ip = gateway.get_external_ip().map_err(other_error)?;
+    Ok(SocketAddr::new(IpAddr::V4(ip), port))
+}
+
+fn map_port_nat_pmp(gateway: SocketAddr, port: u16, udp: bool) -> io::Result<SocketAddr> {
+    let bind_addr: SocketAddr = match gateway {
+        SocketAddr::V4(_) => ([0, 0, 0, 0], 0).into(),
+        SocketAddr::V6(_) => ([0u16; 8], 0).into(),
+    };
+    let socket = UdpSocket::bind(bind_addr)?;
+    socket.connect(gateway)?;
+
+    let response = nat_pmp_request(&socket, &[0, NAT_PMP_OP_EXTERNAL_ADDRESS], 12)?;
+    let ip = Ipv4Addr::new(response[8], response[9], response[10], response[11]);
+    let external_port = nat_pmp_map(&socket, NAT_PMP_OP_MAP_TCP, port)?;
+    if udp {
+        let udp_port = nat_pmp_map(&socket, NAT_PMP_OP_MAP_UDP, port)?;
+        if udp_port != external_port {
+            return Err(other_error(format!(
+                "gateway mapped TCP port {} and UDP port {} for port {}",
+                external_port, udp_port, port
+            )));
+        }
+    }
+    Ok(SocketAddr::new(IpAddr::V4(ip), external_port))
+}
+
+/// Requests a mapping of `port` to the same external port, returns the port actually mapped.
+fn nat_pmp_map(socket: &UdpSocket, opcode: u8, port: u16) -> io::Result<u16> {
+    let mut request = [0u8; 12];
+    request[1] = opcode;
+    request[4..6].copy_from_slice(&port.to_be_bytes());
+    request[6..8].copy_from_slice(&port.to_be_bytes());
+    request[8..12].copy_from_slice(&(PORT_MAPPING_LEASE.as_secs() as u32).to_be_bytes());
+    let response = nat_pmp_request(socket, &request, 16)?;
+    Ok(u16::from_be_bytes([response[10], response[11]]))
+}
+
+/// Sends `request` until the gateway answers, and checks the header of the response.
+fn nat_pmp_request(socket: &UdpSocket, request: &[u8], response_len: usize) -> io::Result<Vec<u8>> {
+    let mut timeout = NAT_PMP_INITIAL_TIMEOUT;
+    let mut buf = [0u8; 16];
+    for _ in 0..NAT_PMP_ATTEMPTS {
+        socket.send(request)?;
+        socket.set_read_timeout(Some(timeout))?;
+        match socket.recv(&mut buf) {
+            Ok(len) => {
+                if len < response_len || buf[0] != 0 || buf[1] != request[1] + 128 {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "unexpected NAT-PMP response",
+                    ));
+                }
+                let result = u16::from_be_bytes([buf[2], buf[3]]);
+                if result != 0 {
+                    return Err(other_error(format!(
+                        "NAT-PMP request failed with code {}",
+                        result
+                    )));
+                }
+                return Ok(buf[..len].to_vec());
+            }
+            Err(err)
+                if err.kind() == io::ErrorKind::WouldBlock
+                    || err.kind() == io::ErrorKind::TimedOut =>
+            {
+                timeout *= 2;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+    Err(io::Error::new(io::ErrorKind::TimedOut, "NAT-PMP gateway didn't answer"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::map_port_nat_pmp;
+    use std::net::{SocketAddr, UdpSocket};
+
+    #[test]
+    fn test_map_port_nat_pmp() {
+        let gateway = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let gateway_addr = gateway.local_addr().unwrap();
+        let handle = std::thread::spawn(move || {
+            let mut buf = [0u8; 12];
+            for _ in 0..2 {
+                let (len, from) = gateway.recv_from(&mut buf).unwrap();
+                let mut response = vec![0, buf[1] + 128, 0, 0, 0, 0, 0, 1];
+                match buf[1] {
+                    0 => response.extend_from_slice(&[1, 2, 3, 4]),
+                    _ => {
+                        assert_eq!(len, 12);
+                        // Internal port, external port one above it, lifetime.
+                        let port = u16::from_be_bytes([buf[4], buf[5]]);
+                        response.extend_from_slice(&buf[4..6]);
+                        response.extend_from_slice(&(port + 1).to_be_bytes());
+                        response.extend_from_slice(&buf[8..12]);
+                    }
+                }
+                gateway.send_to(&response, from).unwrap();
+            }
+        });
+        let addr = map_port_nat_pmp(gateway_addr, 24567, false).unwrap();
+        assert_eq!(addr, "1.2.3.4:24568".parse::<SocketAddr>().unwrap());
+        handle.join().unwrap();
+    }
+}
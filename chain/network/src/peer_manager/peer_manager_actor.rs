@@ -1,5 +1,10 @@
 use crate::peer::codec::Codec;
-use crate::peer::peer_actor::PeerActor;
+use crate::peer::noise::{self, NoiseKeys, NoiseSession};
+use crate::peer::peer_actor::{PeerActor, WriteHalf};
+#[cfg(feature = "protocol_feature_quic_transport")]
+use crate::peer::quic::{self, QuicEndpoint, QuicStreams};
+use crate::peer::rate_limiter::{is_consensus_message, MessageRateLimiter};
+use crate::peer_manager::nat::{self, ObservedAddresses};
 use crate::peer_manager::peer_store::{PeerStore, TrustLevel};
 use crate::private_actix::{
     PeerRequestResult, PeersRequest, RegisterPeer, RegisterPeerResponse, SendMessage, StopMsg,
@@ -27,8 +32,8 @@ use near_network_primitives::types::{
     AccountOrPeerIdOrHash, Ban, BlockedPorts, Edge, InboundTcpConnect, KnownPeerStatus,
     KnownProducer, NetworkConfig, NetworkViewClientMessages, NetworkViewClientResponses,
     OutboundTcpConnect, PeerIdOrHash, PeerInfo, PeerManagerRequest, PeerType, Ping, Pong,
-    QueryPeerStats, RawRoutedMessage, ReasonForBan, RoutedMessage, RoutedMessageBody,
-    RoutedMessageFrom, StateResponseInfo,
+    PortMapping, QueryPeerStats, RawRoutedMessage, ReasonForBan, RoutedMessage, RoutedMessageBody,
+    RoutedMessageFrom, StateResponseInfo, Transport,
 };
 use near_network_primitives::types::{EdgeState, PartialEdgeInfo};
 use near_performance_metrics::framed_write::FramedWrite;
@@ -48,14 +53,22 @@ use rand::seq::IteratorRandom;
 use rand::thread_rng;
 use std::cmp::{max, min};
 use std::collections::{HashMap, HashSet};
-use std::net::SocketAddr;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
+use strum::VariantNames;
+use tokio::io::AsyncRead;
 use tokio::net::{TcpListener, TcpStream};
 use tokio_stream::StreamExt;
 use tracing::{debug, error, info, trace, warn};
 
+/// How long to wait for the answer to an encrypted session offer, before continuing the
+/// connection in plaintext.
+const NOISE_FALLBACK_TIMEOUT: Duration = Duration::from_secs(2);
+/// How long to wait for a peer to answer over QUIC, before connecting to it over TCP instead.
+#[cfg(feature = "protocol_feature_quic_transport")]
+const QUIC_FALLBACK_TIMEOUT: Duration = Duration::from_secs(2);
 /// How often to request peers from active peers.
 const REQUEST_PEERS_INTERVAL: Duration = Duration::from_millis(60_000);
 /// How much time to wait (in milliseconds) after we send update nonce request before disconnecting.
@@ -85,6 +98,10 @@ const WAIT_FOR_SYNC_DELAY: Duration = Duration::from_millis(1_000);
 const UPDATE_ROUTING_TABLE_INTERVAL: Duration = Duration::from_millis(1_000);
 /// How often to report bandwidth stats.
 const REPORT_BANDWIDTH_STATS_TRIGGER_INTERVAL: Duration = Duration::from_millis(60_000);
+/// How often the reputation of known peers decays.
+const REPUTATION_DECAY_INTERVAL: Duration = Duration::from_secs(60 * 60);
+/// How often reputations changed by evidence about peers are saved.
+const REPUTATION_FLUSH_INTERVAL: Duration = Duration::from_secs(60);
 
 /// Max number of messages we received from peer, and they are in progress, before we start throttling.
 /// Disabled for now (TODO PUT UNDER FEATURE FLAG)
@@ -97,6 +114,35 @@ const REPORT_BANDWIDTH_THRESHOLD_BYTES: usize = 10_000_000;
 /// If we received more than REPORT_BANDWIDTH_THRESHOLD_COUNT` of messages from given peer it's bandwidth stats will be reported.
 const REPORT_BANDWIDTH_THRESHOLD_COUNT: usize = 10_000;
 
+/// Connection with a peer, once the transport is negotiated.
+enum PeerConnection {
+    /// `read_prefix` are the bytes already read from the stream while negotiating the transport.
+    Tcp { stream: TcpStream, read_prefix: Vec<u8>, session: Option<NoiseSession> },
+    #[cfg(feature = "protocol_feature_quic_transport")]
+    Quic(QuicStreams),
+}
+
+/// Adds a stream of messages read from the peer to its actor.
+fn add_peer_stream<R: AsyncRead + Unpin + 'static>(
+    read: R,
+    codec: Codec,
+    rate_limiter: ThrottleController,
+    ctx: &mut Context<PeerActor>,
+) {
+    PeerActor::add_stream(
+        ThrottleFramedRead::new(read, codec, rate_limiter)
+            .take_while(|x| match x {
+                Ok(_) => true,
+                Err(e) => {
+                    warn!(target: "network", ?e, "Peer stream error");
+                    false
+                }
+            })
+            .map(Result::unwrap),
+        ctx,
+    );
+}
+
 /// Contains information relevant to a connected peer.
 struct ConnectedPeer {
     addr: Addr<PeerActor>,
@@ -188,6 +234,25 @@ pub struct PeerManagerActor {
     peer_counter: Arc<AtomicUsize>,
     /// Used for testing, for disabling features.
     adv_helper: AdvHelper,
+    /// Static key of encrypted sessions with other peers.
+    noise_keys: Arc<NoiseKeys>,
+    /// Peers which established an encrypted session with this node, so a connection with them
+    /// falling back to plaintext is a downgrade attempt.
+    encrypted_peers: HashSet<PeerId>,
+    /// QUIC endpoint, if the node uses the QUIC transport.
+    #[cfg(feature = "protocol_feature_quic_transport")]
+    quic_endpoint: Option<QuicEndpoint>,
+    /// Whether peers told in their handshake that they accept QUIC connections. Peers known not to
+    /// are connected to over TCP right away, instead of after `QUIC_FALLBACK_TIMEOUT`.
+    #[cfg(feature = "protocol_feature_quic_transport")]
+    quic_peers: HashMap<PeerId, bool>,
+    /// Addresses of this node reported by connected peers.
+    observed_addresses: ObservedAddresses,
+    /// Observed IP this node is dialing back, and whether it could connect. Dialing is retried
+    /// on the next report after a failure.
+    dialed_back_ip: Option<(IpAddr, bool)>,
+    /// External address of the listening port mapped on the gateway.
+    mapped_addr: Option<SocketAddr>,
 }
 
 impl Actor for PeerManagerActor {
@@ -221,6 +286,38 @@ impl Actor for PeerManagerActor {
             });
         }
 
+        #[cfg(not(feature = "protocol_feature_quic_transport"))]
+        if self.config.transport == Transport::Quic {
+            warn!(target: "network", "QUIC transport isn't supported by this build, using TCP");
+        }
+        #[cfg(feature = "protocol_feature_quic_transport")]
+        if self.config.transport == Transport::Quic {
+            match quic::endpoint(self.config.addr, &self.config.secret_key) {
+                Ok((endpoint, incoming)) => {
+                    if let Some(incoming) = incoming {
+                        ctx.add_stream(incoming);
+                    }
+                    self.quic_endpoint = Some(endpoint);
+                }
+                Err(e) => {
+                    panic!(
+                        "failed to start QUIC endpoint on server_addr={:?} e={:?}",
+                        self.config.addr, e
+                    );
+                }
+            }
+        }
+
+        // Map the listening port on the gateway, and keep renewing the mapping.
+        #[cfg(feature = "protocol_feature_observed_address")]
+        if self.config.port_mapping != PortMapping::Disabled {
+            self.map_port_trigger(ctx);
+        }
+        #[cfg(not(feature = "protocol_feature_observed_address"))]
+        if self.config.port_mapping != PortMapping::Disabled {
+            warn!(target: "network", "Port mapping isn't supported by this build");
+        }
+
         // Periodically push network information to client.
         self.push_network_info_trigger(ctx, self.config.push_info_period);
 
@@ -245,6 +342,14 @@ impl Actor for PeerManagerActor {
 
         // Periodically prints bandwidth stats for each peer.
         self.report_bandwidth_stats_trigger(ctx, REPORT_BANDWIDTH_STATS_TRIGGER_INTERVAL);
+
+        // Periodically decays the reputation of known peers.
+        near_performance_metrics::actix::run_later(ctx, REPUTATION_DECAY_INTERVAL, |act, ctx| {
+            act.decay_reputations_trigger(ctx, REPUTATION_DECAY_INTERVAL);
+        });
+
+        // Periodically saves the reputations changed by evidence about peers.
+        self.flush_reputations_trigger(ctx, REPUTATION_FLUSH_INTERVAL);
     }
 
     /// Try to gracefully disconnect from connected peers.
@@ -257,6 +362,10 @@ impl Actor for PeerManagerActor {
 
         self.routing_table_addr.do_send(StopMsg {});
 
+        if let Err(err) = self.peer_store.flush_reputations() {
+            error!(target: "network", ?err, "Failed to save peer data");
+        }
+
         Running::Stop
     }
 }
@@ -269,11 +378,23 @@ impl PeerManagerActor {
         view_client_addr: Recipient<NetworkViewClientMessages>,
         routing_table_addr: Addr<RoutingTableActor>,
     ) -> Result<Self, Box<dyn std::error::Error>> {
-        let peer_store = PeerStore::new(store.clone(), &config.boot_nodes)?;
-        debug!(target: "network", len = peer_store.len(), boot_nodes = config.boot_nodes.len(), "Found known peers");
+        // Trusted peers are connected to like boot nodes.
+        let boot_nodes = [config.boot_nodes.as_slice(), config.trusted_peers.as_slice()].concat();
+        let peer_store = PeerStore::new(store.clone(), &boot_nodes)?;
+        debug!(target: "network", len = peer_store.len(), boot_nodes = config.boot_nodes.len(), trusted_peers = config.trusted_peers.len(), "Found known peers");
         debug!(target: "network", blacklist = ?config.blacklist, "Blacklist");
+        for name in config.message_rate_limits.keys() {
+            if !PeerMessage::VARIANTS.contains(&name.as_str())
+                && !RoutedMessageBody::VARIANTS.contains(&name.as_str())
+            {
+                warn!(target: "network", "Rate limit configured for unknown message type {}", name);
+            } else if is_consensus_message(name) {
+                warn!(target: "network", "Rate limit configured for consensus message type {} is ignored", name);
+            }
+        }
 
         let my_peer_id: PeerId = PeerId::new(config.public_key.clone());
+        let noise_keys = Arc::new(NoiseKeys::new(&config.secret_key));
         let routing_table = RoutingTableView::new(store);
 
         let txns_since_last_block = Arc::new(AtomicUsize::new(0));
@@ -295,9 +416,105 @@ impl PeerManagerActor {
             txns_since_last_block,
             peer_counter: Arc::new(AtomicUsize::new(0)),
             adv_helper: AdvHelper::default(),
+            noise_keys,
+            encrypted_peers: HashSet::default(),
+            #[cfg(feature = "protocol_feature_quic_transport")]
+            quic_endpoint: None,
+            #[cfg(feature = "protocol_feature_quic_transport")]
+            quic_peers: HashMap::default(),
+            observed_addresses: ObservedAddresses::default(),
+            dialed_back_ip: None,
+            mapped_addr: None,
+        })
+    }
+
+    /// Address advertised to peers: the configured external address, else the address mapped on
+    /// the gateway, else the IP peers see this node at with the listening port, once this node
+    /// could dial it.
+    fn external_addr(&self) -> Option<SocketAddr> {
+        self.config.external_address.or(self.mapped_addr).or_else(|| {
+            let ip = self.observed_addresses.best()?;
+            if self.dialed_back_ip != Some((ip, true)) {
+                return None;
+            }
+            Some(SocketAddr::new(ip, self.config.addr?.port()))
         })
     }
 
+    /// Dials the listening port at the IP reported by peers, unless it was already dialed.
+    fn dial_back_observed_addr(&mut self, ctx: &mut Context<Self>) {
+        if self.config.external_address.is_some() || self.mapped_addr.is_some() {
+            return;
+        }
+        let (ip, port) = match (self.observed_addresses.best(), self.config.addr) {
+            (Some(ip), Some(addr)) => (ip, addr.port()),
+            _ => return,
+        };
+        if self.dialed_back_ip.map(|(dialed_ip, _)| dialed_ip) == Some(ip) {
+            return;
+        }
+        self.dialed_back_ip = Some((ip, false));
+        let addr = SocketAddr::new(ip, port);
+        tokio::time::timeout(nat::DIAL_BACK_TIMEOUT, TcpStream::connect(addr))
+            .into_actor(self)
+            .then(move |res, act, _ctx| {
+                if act.dialed_back_ip == Some((ip, false)) {
+                    act.dialed_back_ip = None;
+                    match res {
+                        Ok(Ok(_)) => {
+                            info!(target: "network", ?addr, "Advertising address observed by peers");
+                            act.dialed_back_ip = Some((ip, true));
+                        }
+                        Ok(Err(err)) => {
+                            warn!(target: "network", ?addr, ?err, "Address observed by peers isn't reachable");
+                        }
+                        Err(_) => {
+                            warn!(target: "network", ?addr, "Dialing address observed by peers timed out");
+                        }
+                    }
+                }
+                actix::fut::ready(())
+            })
+            .spawn(ctx);
+    }
+
+    /// Maps the listening port on the gateway, and renews the mapping before its lease expires.
+    #[cfg(feature = "protocol_feature_observed_address")]
+    fn map_port_trigger(&self, ctx: &mut Context<Self>) {
+        let port = match self.config.addr {
+            Some(addr) => addr.port(),
+            None => return,
+        };
+        let port_mapping = self.config.port_mapping.clone();
+        let udp = cfg!(feature = "protocol_feature_quic_transport")
+            && self.config.transport == Transport::Quic;
+        tokio::task::spawn_blocking(move || nat::map_port(&port_mapping, port, udp))
+            .into_actor(self)
+            .then(|res, act, ctx| {
+                match res {
+                    Ok(Ok(addr)) => {
+                        if act.mapped_addr != Some(addr) {
+                            info!(target: "network", ?addr, "Mapped listening port on the gateway");
+                        }
+                        act.mapped_addr = Some(addr);
+                    }
+                    Ok(Err(err)) => {
+                        warn!(target: "network", ?err, "Failed to map listening port on the gateway");
+                    }
+                    Err(err) => {
+                        error!(target: "network", ?err, "Port mapping task failed");
+                    }
+                }
+                near_performance_metrics::actix::run_later(
+                    ctx,
+                    nat::PORT_MAPPING_RENEW_INTERVAL,
+                    |act, ctx| act.map_port_trigger(ctx),
+                );
+                actix::fut::ready(())
+            })
+            .spawn(ctx);
+    }
+
     fn update_routing_table_and_prune_edges(
         &self,
         ctx: &mut Context<Self>,
@@ -418,6 +635,28 @@ impl PeerManagerActor {
         });
     }
 
+    /// Periodically decays the reputation of known peers, so that old evidence matters less.
+    fn decay_reputations_trigger(&mut self, ctx: &mut Context<Self>, every: Duration) {
+        if let Err(err) = self.peer_store.decay_reputations() {
+            error!(target: "network", ?err, "Failed to save peer data");
+        }
+
+        near_performance_metrics::actix::run_later(ctx, every, move |act, ctx| {
+            act.decay_reputations_trigger(ctx, every);
+        });
+    }
+
+    /// Periodically saves the reputations changed since the last flush, in a single update.
+    fn flush_reputations_trigger(&mut self, ctx: &mut Context<Self>, every: Duration) {
+        if let Err(err) = self.peer_store.flush_reputations() {
+            error!(target: "network", ?err, "Failed to save peer data");
+        }
+
+        near_performance_metrics::actix::run_later(ctx, every, move |act, ctx| {
+            act.flush_reputations_trigger(ctx, every);
+        });
+    }
+
     /// Receives list of edges that were verified, in a trigger every 20ms, and adds them to
     /// the routing table.
     fn broadcast_validated_edges_trigger(&mut self, ctx: &mut Context<Self>, interval: Duration) {
@@ -714,6 +953,7 @@ impl PeerManagerActor {
         // If the last edge we have with this peer represent a connection addition, create the edge
         // update that represents the connection removal.
         self.connected_peers.remove(peer_id);
+        self.observed_addresses.remove(peer_id);
 
         #[cfg(feature = "protocol_feature_routing_exchange_algorithm")]
         self.routing_table_addr.do_send(RoutingTableMessages::RemovePeer(peer_id.clone()));
@@ -782,12 +1022,119 @@ impl PeerManagerActor {
         }
     }
 
-    /// Connects peer with given TcpStream and optional information if it's outbound.
+    /// Establishes an encrypted session on a new connection, if both sides support it, and then
+    /// connects the peer. Outbound connections offer the session if `offer_encryption` is set,
+    /// and are opened again in plaintext if the other peer doesn't answer and it is allowed.
+    fn negotiate_transport(
+        &self,
+        ctx: &mut Context<Self>,
+        mut stream: TcpStream,
+        peer_type: PeerType,
+        peer_info: Option<PeerInfo>,
+        partial_edge_info: Option<PartialEdgeInfo>,
+        offer_encryption: bool,
+    ) {
+        let noise_keys = self.noise_keys.clone();
+        let handshake_timeout = self.config.handshake_timeout;
+        async move {
+            let result = tokio::time::timeout(handshake_timeout, async {
+                match peer_type {
+                    PeerType::Outbound if offer_encryption => {
+                        noise::initiate(&mut stream, &noise_keys, NOISE_FALLBACK_TIMEOUT)
+                            .await
+                            .map(|session| (vec![], session))
+                    }
+                    PeerType::Outbound => Ok((vec![], None)),
+                    PeerType::Inbound => noise::accept(&mut stream, &noise_keys).await,
+                }
+            })
+            .await;
+            (stream, result)
+        }
+        .into_actor(self)
+        .map(move |(stream, result), act, ctx| {
+            let err = match result {
+                // The other peer may still be completing the handshake, so the connection can't
+                // continue in plaintext.
+                Ok(Ok((_, None))) if offer_encryption => match &peer_info {
+                    Some(info) if info.addr.is_some() && act.is_plaintext_allowed(&info.id) => {
+                        debug!(target: "network", peer_info = ?info, "No answer to the offer of an encrypted session, reconnecting in plaintext");
+                        act.connect_tcp(ctx, info.clone(), info.addr.unwrap(), false);
+                        return;
+                    }
+                    _ => "No answer to the offer of an encrypted session".to_string(),
+                },
+                Ok(Ok((_, None)))
+                    if peer_type == PeerType::Inbound && act.config.require_encrypted_transport =>
+                {
+                    "Plaintext connections are refused".to_string()
+                }
+                Ok(Ok((read_prefix, session))) => {
+                    act.try_connect_peer(
+                        ctx.address(),
+                        PeerConnection::Tcp { stream, read_prefix, session },
+                        peer_type,
+                        peer_info,
+                        partial_edge_info,
+                    );
+                    return;
+                }
+                Ok(Err(err)) => err.to_string(),
+                Err(err) => err.to_string(),
+            };
+            info!(target: "network", ?peer_info, %err, "Failed to negotiate transport with");
+            if let Some(peer_info) = peer_info {
+                act.outgoing_peers.remove(&peer_info.id);
+            }
+        })
+        .spawn(ctx);
+    }
+
+    /// Opens the streams of a QUIC connection, and then connects the peer.
+    #[cfg(feature = "protocol_feature_quic_transport")]
+    fn negotiate_quic(
+        &self,
+        ctx: &mut Context<Self>,
+        connection: quinn::NewConnection,
+        peer_type: PeerType,
+        peer_info: Option<PeerInfo>,
+        partial_edge_info: Option<PartialEdgeInfo>,
+    ) {
+        let endpoint = match &self.quic_endpoint {
+            Some(endpoint) => endpoint,
+            None => return,
+        };
+        tokio::time::timeout(self.config.handshake_timeout, endpoint.open_streams(connection))
+            .into_actor(self)
+            .map(move |result, act, ctx| {
+                let err = match result {
+                    Ok(Ok(streams)) => {
+                        act.try_connect_peer(
+                            ctx.address(),
+                            PeerConnection::Quic(streams),
+                            peer_type,
+                            peer_info,
+                            partial_edge_info,
+                        );
+                        return;
+                    }
+                    Ok(Err(err)) => err.to_string(),
+                    Err(err) => err.to_string(),
+                };
+                info!(target: "network", ?peer_info, %err, "Failed to open QUIC streams with");
+                if let Some(peer_info) = peer_info {
+                    act.outgoing_peers.remove(&peer_info.id);
+                }
+            })
+            .spawn(ctx);
+    }
+
+    /// Connects peer with given connection and optional information if it's outbound.
     /// This might fail if the other peers drop listener at its endpoint while establishing connection.
     fn try_connect_peer(
         &self,
         recipient: Addr<Self>,
-        stream: TcpStream,
+        connection: PeerConnection,
         peer_type: PeerType,
         peer_info: Option<PeerInfo>,
         partial_edge_info: Option<PartialEdgeInfo>,
@@ -799,9 +1146,21 @@ impl PeerManagerActor {
         let client_addr = self.client_addr.clone();
         let view_client_addr = self.view_client_addr.clone();
 
+        let (local_addr, remote_addr) = match &connection {
+            PeerConnection::Tcp { stream, .. } => (stream.local_addr(), stream.peer_addr()),
+            #[cfg(feature = "protocol_feature_quic_transport")]
+            PeerConnection::Quic(streams) => (
+                self.quic_endpoint.as_ref().map_or_else(
+                    || Err(std::io::Error::from(std::io::ErrorKind::NotConnected)),
+                    |quic| quic.endpoint.local_addr(),
+                ),
+                Ok(streams.remote_addr),
+            ),
+        };
+
         let server_addr = match server_addr {
             Some(server_addr) => server_addr,
-            None => match stream.local_addr() {
+            None => match local_addr {
                 Ok(server_addr) => server_addr,
                 _ => {
                     warn!(target: "network", ?peer_info, "Failed establishing connection with");
@@ -810,7 +1169,7 @@ impl PeerManagerActor {
             },
         };
 
-        let remote_addr = match stream.peer_addr() {
+        let remote_addr = match remote_addr {
             Ok(remote_addr) => remote_addr,
             _ => {
                 warn!(target: "network", ?peer_info, "Failed establishing connection with");
@@ -818,8 +1177,17 @@ impl PeerManagerActor {
             }
         };
 
+        let advertised_addr = self.external_addr().unwrap_or(server_addr);
         let network_metrics = self.network_metrics.clone();
         let txns_since_last_block = Arc::clone(&self.txns_since_last_block);
+        let compression_threshold = self.config.compression_threshold;
+        // Inbound QUIC connections are only accepted by nodes listening for them.
+        #[cfg(feature = "protocol_feature_quic_transport")]
+        let accepts_quic = self.quic_endpoint.is_some() && self.config.addr.is_some();
+        #[cfg(not(feature = "protocol_feature_quic_transport"))]
+        let accepts_quic = false;
+        let message_rate_limiter =
+            MessageRateLimiter::new(&self.config.message_rate_limits, Clock::instant());
 
         // Start every peer actor on separate thread.
         let arbiter = Arbiter::new();
@@ -827,29 +1195,59 @@ impl PeerManagerActor {
         peer_counter.fetch_add(1, Ordering::SeqCst);
 
         PeerActor::start_in_arbiter(&arbiter.handle(), move |ctx| {
-            let (read, write) = tokio::io::split(stream);
-
             // TODO: check if peer is banned or known based on IP address and port.
             let rate_limiter = ThrottleController::new(MAX_MESSAGES_COUNT, MAX_MESSAGES_TOTAL_SIZE);
-            PeerActor::add_stream(
-                ThrottleFramedRead::new(read, Codec::default(), rate_limiter.clone())
-                    .take_while(|x| match x {
-                        Ok(_) => true,
-                        Err(e) => {
-                            warn!(target: "network", ?e, "Peer stream error");
-                            false
+            let peer_accepts_compression = Arc::new(AtomicBool::new(false));
+            let (framed, session_peer_id) = match connection {
+                PeerConnection::Tcp { stream, read_prefix, session } => {
+                    let (read, write) = tokio::io::split(stream);
+                    let read =
+                        tokio::io::AsyncReadExt::chain(std::io::Cursor::new(read_prefix), read);
+                    let (session_peer_id, read_cipher, write_cipher) = match session {
+                        Some(session) => {
+                            (Some(session.remote_peer_id), Some(session.recv), Some(session.send))
                         }
-                    })
-                    .map(Result::unwrap),
-                ctx,
-            );
+                        None => (None, None, None),
+                    };
+                    let (read_codec, write_codec) = Codec::new_pair(
+                        read_cipher,
+                        write_cipher,
+                        compression_threshold,
+                        peer_accepts_compression.clone(),
+                        rate_limiter.clone(),
+                    );
+                    add_peer_stream(read, read_codec, rate_limiter.clone(), ctx);
+                    let write: WriteHalf = Box::new(write);
+                    (
+                        vec![FramedWrite::new(write, write_codec, Codec::default(), ctx)],
+                        session_peer_id,
+                    )
+                }
+                #[cfg(feature = "protocol_feature_quic_transport")]
+                PeerConnection::Quic(QuicStreams { send, recv, remote_peer_id, .. }) => {
+                    let mut framed = Vec::with_capacity(send.len());
+                    for (read, write) in recv.into_iter().zip(send) {
+                        let (read_codec, write_codec) = Codec::new_pair(
+                            None,
+                            None,
+                            compression_threshold,
+                            peer_accepts_compression.clone(),
+                            rate_limiter.clone(),
+                        );
+                        add_peer_stream(read, read_codec, rate_limiter.clone(), ctx);
+                        let write: WriteHalf = Box::new(write);
+                        framed.push(FramedWrite::new(write, write_codec, Codec::default(), ctx));
+                    }
+                    (framed, Some(remote_peer_id))
+                }
+            };
 
             PeerActor::new(
-                PeerInfo { id: my_peer_id, addr: Some(server_addr), account_id },
+                PeerInfo { id: my_peer_id, addr: Some(advertised_addr), account_id },
                 remote_addr,
                 peer_info,
                 peer_type,
-                FramedWrite::new(write, Codec::default(), Codec::default(), ctx),
+                framed,
                 handshake_timeout,
                 recipient,
                 client_addr,
@@ -859,6 +1257,10 @@ impl PeerManagerActor {
                 txns_since_last_block,
                 peer_counter,
                 rate_limiter,
+                session_peer_id,
+                peer_accepts_compression,
+                message_rate_limiter,
+                accepts_quic,
             )
         });
     }
@@ -880,8 +1282,41 @@ impl PeerManagerActor {
             && !self.config.outbound_disabled
     }
 
+    /// Whether connections with the peer are allowed: all peers are, unless `trusted_peers` is set.
+    fn is_peer_allowed(&self, peer_id: &PeerId) -> bool {
+        self.config.trusted_peers.is_empty()
+            || self.config.trusted_peers.iter().any(|peer_info| &peer_info.id == peer_id)
+    }
+
+    /// Whether a connection with this peer may be in plaintext.
+    fn is_plaintext_allowed(&self, peer_id: &PeerId) -> bool {
+        !self.config.require_encrypted_transport && !self.encrypted_peers.contains(peer_id)
+    }
+
+    /// Number of connected private peers. Private peers, such as validators behind this sentry
+    /// node, don't count against `max_num_peers`.
+    fn num_connected_private_peers(&self) -> usize {
+        (self.connected_peers.keys())
+            .filter(|peer_id| self.config.private_peers.contains(peer_id))
+            .count()
+    }
+
+    /// Whether an inbound connection may be accepted before the handshake. The peer isn't known
+    /// yet, so slots are kept open for private peers which aren't connected, and connections
+    /// beyond `max_num_peers` are dropped after the handshake unless they are with private peers,
+    /// see `is_inbound_allowed_for`.
     fn is_inbound_allowed(&self) -> bool {
-        self.connected_peers.len() + self.outgoing_peers.len() < self.config.max_num_peers as usize
+        let reserved = self.config.private_peers.len() - self.num_connected_private_peers();
+        self.connected_peers.len() + self.outgoing_peers.len()
+            < self.config.max_num_peers as usize + reserved
+    }
+
+    /// Whether an inbound connection with `peer_id` may be registered after the handshake.
+    fn is_inbound_allowed_for(&self, peer_id: &PeerId) -> bool {
+        self.config.private_peers.contains(peer_id)
+            || self.connected_peers.len() + self.outgoing_peers.len()
+                - self.num_connected_private_peers()
+                < self.config.max_num_peers as usize
     }
 
     /// Returns single random peer with close to the highest height
@@ -1059,7 +1494,8 @@ impl PeerManagerActor {
 
     /// Select one peer and send signal to stop connection to it gracefully.
     /// Selection process:
-    ///     Create a safe set of peers, and among the remaining peers select one at random.
+    ///     Create a safe set of peers, and among the remaining peers select the one with the
+    ///     worst reputation, at random if several peers share it.
     ///     If the number of outbound connections is less or equal than minimum_outbound_connections,
     ///         add all outbound connections to the safe set.
     ///     While the length of the safe set is less than safe_set_size:
@@ -1102,6 +1538,13 @@ impl PeerManagerActor {
             }
         }
 
+        // Private peers, such as validators behind this sentry node, are always kept.
+        for peer_id in self.connected_peers.keys() {
+            if self.config.private_peers.contains(peer_id) {
+                safe_set.insert(peer_id);
+            }
+        }
+
         // Find all recent connections
         let mut recent_connections = (self.connected_peers.iter())
             .filter_map(|(peer_id, active)| {
@@ -1136,8 +1579,14 @@ impl PeerManagerActor {
             }
         });
 
-        if let Some(peer_id) = candidates.choose(&mut rand::thread_rng()) {
-            if let Some(connected_peer) = self.connected_peers.get(peer_id) {
+        let candidates: Vec<(&PeerId, f64)> = candidates
+            .map(|peer_id| (peer_id, self.peer_store.reputation_score(peer_id)))
+            .collect();
+        let worst_score = candidates.iter().map(|(_, score)| *score).fold(f64::INFINITY, f64::min);
+        let worst_candidates = candidates.iter().filter(|(_, score)| *score == worst_score);
+
+        if let Some((peer_id, _)) = worst_candidates.choose(&mut rand::thread_rng()) {
+            if let Some(connected_peer) = self.connected_peers.get(*peer_id) {
                 debug!(target: "network", ?peer_id, "Stop active connection");
                 connected_peer.addr.do_send(PeerManagerRequest::UnregisterPeer);
             }
@@ -1187,6 +1636,8 @@ impl PeerManagerActor {
                     || self.config.addr == peer_state.peer_info.addr
                     // Or to peers we are currently trying to connect to
                     || self.outgoing_peers.contains(&peer_state.peer_info.id)
+                    // Or to untrusted peers, if only trusted peers are allowed
+                    || !self.is_peer_allowed(&peer_state.peer_info.id)
             }) {
                 // Start monitor_peers_attempts from start after we discover the first healthy peer
                 if !self.started_connect_attempts {
@@ -1471,6 +1922,10 @@ impl PeerManagerActor {
                 })
                 .collect(),
             peer_counter: self.peer_counter.load(Ordering::SeqCst),
+            peer_scores: (self.connected_peers.keys())
+                .map(|peer_id| (peer_id.clone(), self.peer_store.reputation_score(peer_id)))
+                .collect(),
+            private_peers: self.config.private_peers.clone(),
         }
     }
 
@@ -1593,6 +2048,12 @@ impl PeerManagerActor {
                 self.try_ban_peer(&peer_id, ban_reason);
                 NetworkResponses::NoResponse
             }
+            NetworkRequests::ReportPeer { peer_id, evidence } => {
+                if let Err(err) = self.peer_store.record_evidence(&peer_id, evidence) {
+                    debug!(target: "network", ?err, "Failed to record peer evidence");
+                }
+                NetworkResponses::NoResponse
+            }
             NetworkRequests::AnnounceAccount(announce_account) => {
                 self.announce_account(announce_account);
                 NetworkResponses::NoResponse
@@ -1783,6 +2244,17 @@ impl PeerManagerActor {
                 );
                 NetworkResponses::NoResponse
             }
+            #[cfg(feature = "protocol_feature_chunk_state_witness")]
+            NetworkRequests::ChunkStateWitness { account_id, witness } => {
+                if self.send_message_to_account(
+                    &account_id,
+                    RoutedMessageBody::ChunkStateWitness(witness),
+                ) {
+                    NetworkResponses::NoResponse
+                } else {
+                    NetworkResponses::RouteNotFound
+                }
+            }
             NetworkRequests::RequestUpdateNonce(peer_id, edge_info) => {
                 if Edge::partial_verify(&self.my_peer_id, &peer_id, &edge_info) {
                     if let Some(cur_edge) = self.routing_table_view.get_local_edge(&peer_id) {
@@ -1914,7 +2386,7 @@ impl PeerManagerActor {
         let _d = delay_detector::DelayDetector::new(|| "inbound tcp connect".into());
 
         if self.is_inbound_allowed() {
-            self.try_connect_peer(ctx.address(), msg.stream, PeerType::Inbound, None, None);
+            self.negotiate_transport(ctx, msg.stream, PeerType::Inbound, None, None, false);
         } else {
             // TODO(1896): Gracefully drop inbound connection for other peer.
             debug!(target: "network", "Inbound connection dropped (network at max capacity).");
@@ -1935,47 +2407,107 @@ impl PeerManagerActor {
         let _d = delay_detector::DelayDetector::new(|| "outbound tcp connect".into());
         debug!(target: "network", to = ?msg.peer_info, "Trying to connect");
         if let Some(addr) = msg.peer_info.addr {
-            // The `connect` may take several minutes. This happens when the
-            // `SYN` packet for establishing a TCP connection gets silently
-            // dropped, in which case the default TCP timeout is applied. That's
-            // too long for us, so we shorten it to one second.
-            //
-            // Why exactly a second? It was hard-coded in a library we used
-            // before, so we keep it to preserve behavior. Removing the timeout
-            // completely was observed to break stuff for real on the testnet.
-            tokio::time::timeout(Duration::from_secs(1), TcpStream::connect(addr))
-                .into_actor(self)
-                .then(move |res, act, ctx| match res {
-                    Ok(res) => match res {
-                        Ok(stream) => {
-                            debug!(target: "network", peer_info = ?msg.peer_info, "Connecting");
-                            let edge_info = act.propose_edge(&msg.peer_info.id, None);
-
-                            act.try_connect_peer(
-                                ctx.address(),
-                                stream,
-                                PeerType::Outbound,
-                                Some(msg.peer_info),
-                                Some(edge_info),
-                            );
-                            actix::fut::ready(())
-                        }
-                        Err(err) => {
-                            info!(target: "network", ?addr, ?err, "Error connecting to");
-                            act.outgoing_peers.remove(&msg.peer_info.id);
-                            actix::fut::ready(())
-                        }
-                    },
+            #[cfg(feature = "protocol_feature_quic_transport")]
+            if let Some(endpoint) = &self.quic_endpoint {
+                if self.quic_peers.get(&msg.peer_info.id) != Some(&false) {
+                    self.connect_quic(ctx, endpoint, msg.peer_info, addr);
+                    return;
+                }
+            }
+            self.connect_tcp(ctx, msg.peer_info, addr, self.config.encrypted_transport);
+        } else {
+            warn!(target: "network", peer_info = ?msg.peer_info, "Trying to connect to peer with no public address");
+        }
+    }
+
+    fn connect_tcp(
+        &self,
+        ctx: &mut Context<Self>,
+        peer_info: PeerInfo,
+        addr: SocketAddr,
+        offer_encryption: bool,
+    ) {
+        // The `connect` may take several minutes. This happens when the
+        // `SYN` packet for establishing a TCP connection gets silently
+        // dropped, in which case the default TCP timeout is applied. That's
+        // too long for us, so we shorten it to one second.
+        //
+        // Why exactly a second? It was hard-coded in a library we used
+        // before, so we keep it to preserve behavior. Removing the timeout
+        // completely was observed to break stuff for real on the testnet.
+        tokio::time::timeout(Duration::from_secs(1), TcpStream::connect(addr))
+            .into_actor(self)
+            .then(move |res, act, ctx| match res {
+                Ok(res) => match res {
+                    Ok(stream) => {
+                        debug!(target: "network", ?peer_info, "Connecting");
+                        let edge_info = act.propose_edge(&peer_info.id, None);
+
+                        act.negotiate_transport(
+                            ctx,
+                            stream,
+                            PeerType::Outbound,
+                            Some(peer_info),
+                            Some(edge_info),
+                            offer_encryption,
+                        );
+                        actix::fut::ready(())
+                    }
                     Err(err) => {
                         info!(target: "network", ?addr, ?err, "Error connecting to");
-                        act.outgoing_peers.remove(&msg.peer_info.id);
+                        act.outgoing_peers.remove(&peer_info.id);
                         actix::fut::ready(())
                     }
-                })
-                .wait(ctx);
-        } else {
-            warn!(target: "network", peer_info = ?msg.peer_info, "Trying to connect to peer with no public address");
-        }
+                },
+                Err(err) => {
+                    info!(target: "network", ?addr, ?err, "Error connecting to");
+                    act.outgoing_peers.remove(&peer_info.id);
+                    actix::fut::ready(())
+                }
+            })
+            .wait(ctx);
+    }
+
+    /// Connects to the peer over QUIC, or over TCP if it doesn't answer.
+    #[cfg(feature = "protocol_feature_quic_transport")]
+    fn connect_quic(
+        &self,
+        ctx: &mut Context<Self>,
+        endpoint: &QuicEndpoint,
+        peer_info: PeerInfo,
+        addr: SocketAddr,
+    ) {
+        let connecting = match endpoint.connect(addr) {
+            Ok(connecting) => connecting,
+            Err(err) => {
+                debug!(target: "network", ?addr, %err, "Failed to connect over QUIC");
+                self.connect_tcp(ctx, peer_info, addr, self.config.encrypted_transport);
+                return;
+            }
+        };
+        tokio::time::timeout(QUIC_FALLBACK_TIMEOUT, connecting)
+            .into_actor(self)
+            .map(move |result, act, ctx| {
+                let err = match result {
+                    Ok(Ok(connection)) => {
+                        debug!(target: "network", ?peer_info, "Connecting over QUIC");
+                        let edge_info = act.propose_edge(&peer_info.id, None);
+                        act.negotiate_quic(
+                            ctx,
+                            connection,
+                            PeerType::Outbound,
+                            Some(peer_info),
+                            Some(edge_info),
+                        );
+                        return;
+                    }
+                    Ok(Err(err)) => err.to_string(),
+                    Err(err) => err.to_string(),
+                };
+                debug!(target: "network", ?addr, %err, "Failed to connect over QUIC, falling back to TCP");
+                act.connect_tcp(ctx, peer_info, addr, act.config.encrypted_transport);
+            })
+            .spawn(ctx);
     }
 
     #[perf]
@@ -1999,6 +2531,16 @@ impl PeerManagerActor {
             return RegisterPeerResponse::Reject;
         }
 
+        if !self.is_peer_allowed(&msg.peer_info.id) {
+            debug!(target: "network", id = ?msg.peer_info.id, "Dropping connection from untrusted peer");
+            return RegisterPeerResponse::Reject;
+        }
+
+        if !msg.encrypted && !self.is_plaintext_allowed(&msg.peer_info.id) {
+            debug!(target: "network", id = ?msg.peer_info.id, "Dropping plaintext connection from peer which must use an encrypted session");
+            return RegisterPeerResponse::Reject;
+        }
+
         // We already connected to this peer.
         if self.connected_peers.contains_key(&msg.peer_info.id) {
             debug!(target: "network", peer_info = ?self.my_peer_id, id = ?msg.peer_info.id, "Dropping handshake (Active Peer).");
@@ -2015,7 +2557,7 @@ impl PeerManagerActor {
             }
         }
 
-        if msg.peer_type == PeerType::Inbound && !self.is_inbound_allowed() {
+        if msg.peer_type == PeerType::Inbound && !self.is_inbound_allowed_for(&msg.peer_info.id) {
             // TODO(1896): Gracefully drop inbound connection for other peer.
             debug!(target: "network",
                 connected_peers = self.connected_peers.len(), outgoing_peers = self.outgoing_peers.len(),
@@ -2053,6 +2595,24 @@ impl PeerManagerActor {
 
         let edge_info_response = if require_response { Some(edge_info.clone()) } else { None };
 
+        // Anyone can open inbound connections, so only peers this node dialed are trusted.
+        if let (PeerType::Outbound, Some(observed_addr), Some(peer_addr)) =
+            (msg.peer_type, msg.observed_addr, msg.peer_info.addr)
+        {
+            self.observed_addresses.insert(
+                msg.peer_info.id.clone(),
+                peer_addr.ip(),
+                observed_addr.ip(),
+            );
+            self.dial_back_observed_addr(ctx);
+        }
+
+        if msg.encrypted && self.config.encrypted_transport {
+            self.encrypted_peers.insert(msg.peer_info.id.clone());
+        }
+        #[cfg(feature = "protocol_feature_quic_transport")]
+        self.quic_peers.insert(msg.peer_info.id.clone(), msg.accepts_quic);
+
         // TODO: double check that address is connectable and add account id.
         self.register_peer(
             FullPeerInfo {
@@ -2086,15 +2646,30 @@ impl PeerManagerActor {
     #[perf]
     fn handle_msg_peers_request(&self, _msg: PeersRequest) -> PeerRequestResult {
         let _d = delay_detector::DelayDetector::new(|| "peers request".into());
-        PeerRequestResult {
-            peers: self.peer_store.healthy_peers(self.config.max_send_peers as usize),
+        let mut peers = self.peer_store.healthy_peers(self.config.max_send_peers as usize);
+        peers.retain(|peer_info| !self.config.private_peers.contains(&peer_info.id));
+        // Let peers learn the address of this node as well, once it is known. Nodes only
+        // connecting to trusted peers keep it for themselves.
+        let external_addr = self.external_addr().filter(|_| self.config.trusted_peers.is_empty());
+        if let Some(addr) = external_addr {
+            peers.push(PeerInfo {
+                id: self.my_peer_id.clone(),
+                addr: Some(addr),
+                account_id: self.config.account_id.clone(),
+            });
         }
+        PeerRequestResult { peers }
     }
 
     fn handle_msg_peers_response(&mut self, msg: PeersResponse) {
         let _d = delay_detector::DelayDetector::new(|| "peers response".into());
         if let Err(err) = self.peer_store.add_indirect_peers(
-            msg.peers.into_iter().filter(|peer_info| peer_info.id != self.my_peer_id).collect(),
+            msg.peers
+                .into_iter()
+                .filter(|peer_info| {
+                    peer_info.id != self.my_peer_id && self.is_peer_allowed(&peer_info.id)
+                })
+                .collect(),
         ) {
             error!(target: "network", ?err, "Fail to update peer store");
         };
@@ -2142,7 +2717,9 @@ impl PeerManagerActor {
             }
             PeerManagerMessageRequest::InboundTcpConnect(msg) => {
                 if self.peer_counter.load(Ordering::SeqCst)
-                    < self.config.max_num_peers as usize + LIMIT_PENDING_PEERS
+                    < self.config.max_num_peers as usize
+                        + self.config.private_peers.len()
+                        + LIMIT_PENDING_PEERS
                 {
                     self.handle_msg_inbound_tcp_connect(msg, ctx);
                 }
@@ -2281,6 +2858,31 @@ impl PeerManagerActor {
     }
 }
 
+/// Inbound QUIC connections.
+#[cfg(feature = "protocol_feature_quic_transport")]
+impl StreamHandler<quinn::Connecting> for PeerManagerActor {
+    fn handle(&mut self, connecting: quinn::Connecting, ctx: &mut Self::Context) {
+        if !self.is_inbound_allowed() {
+            debug!(target: "network", "Inbound QUIC connection dropped (network at max capacity).");
+            return;
+        }
+        tokio::time::timeout(self.config.handshake_timeout, connecting)
+            .into_actor(self)
+            .map(|result, act, ctx| match result {
+                Ok(Ok(connection)) => {
+                    act.negotiate_quic(ctx, connection, PeerType::Inbound, None, None)
+                }
+                Ok(Err(err)) => debug!(target: "network", %err, "Inbound QUIC connection failed"),
+                Err(err) => debug!(target: "network", %err, "Inbound QUIC connection failed"),
+            })
+            .spawn(ctx);
+    }
+
+    fn finished(&mut self, _ctx: &mut Self::Context) {
+        warn!(target: "network", "QUIC endpoint stopped accepting connections");
+    }
+}
+
 impl Handler<ActixMessageWrapper<PeerManagerMessageRequest>> for PeerManagerActor {
     type Result = ActixMessageResponse<PeerManagerMessageResponse>;
 
@@ -1,15 +1,16 @@
 use borsh::{BorshDeserialize, BorshSerialize};
 use near_network_primitives::types::{
-    KnownPeerState, KnownPeerStatus, NetworkConfig, PeerInfo, ReasonForBan,
+    KnownPeerState, KnownPeerStatus, NetworkConfig, PeerEvidence, PeerInfo, PeerReputation,
+    ReasonForBan,
 };
 use near_primitives::network::PeerId;
 use near_primitives::time::Utc;
 use near_primitives::utils::to_timestamp;
 use near_store::{ColPeers, Store};
-use rand::seq::IteratorRandom;
+use rand::seq::{IteratorRandom, SliceRandom};
 use rand::thread_rng;
 use std::collections::hash_map::{Entry, Iter};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::net::SocketAddr;
 use std::ops::Not;
@@ -49,6 +50,8 @@ pub struct PeerStore {
     // It can happens that some peers don't have known address, so
     // they will not be present in this list, otherwise they will be present.
     addr_peers: HashMap<SocketAddr, VerifiedPeer>,
+    /// Peers whose reputation changed since it was last saved, see `Self::flush_reputations`.
+    dirty_reputations: HashSet<PeerId>,
 }
 
 impl PeerStore {
@@ -91,10 +94,12 @@ impl PeerStore {
             };
 
             if let Some(current_peer_state) = peer_states.get_mut(&peer_id) {
-                // This peer is a boot node and was already added so skip.
+                // This peer is a boot node and was already added so skip, but keep what we
+                // learned about it.
                 if peer_state.status.is_banned() {
                     current_peer_state.status = peer_state.status;
                 }
+                current_peer_state.reputation = peer_state.reputation;
                 continue;
             }
 
@@ -105,7 +110,7 @@ impl PeerStore {
                 }
             }
         }
-        Ok(PeerStore { store, peer_states, addr_peers })
+        Ok(PeerStore { store, peer_states, addr_peers, dirty_reputations: HashSet::default() })
     }
 
     pub(crate) fn len(&self) -> usize {
@@ -150,12 +155,58 @@ impl PeerStore {
         if let Some(peer_state) = self.peer_states.get_mut(peer_id) {
             peer_state.last_seen = to_timestamp(Utc::now());
             peer_state.status = KnownPeerStatus::Banned(ban_reason, to_timestamp(Utc::now()));
+            // Bans are for misbehaviour, which also stays in the reputation after the ban ends.
+            peer_state.reputation.record(PeerEvidence::InvalidMessage);
             Self::save_to_db(&self.store, peer_id.try_to_vec()?.as_slice(), peer_state)
         } else {
             Err(format!("Peer {} is missing in the peer store", peer_id).into())
         }
     }
 
+    /// Records evidence about the behaviour of a peer in its reputation. Evidence is recorded for
+    /// every response, so it's only saved on the next `Self::flush_reputations`.
+    pub(crate) fn record_evidence(
+        &mut self,
+        peer_id: &PeerId,
+        evidence: PeerEvidence,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(peer_state) = self.peer_states.get_mut(peer_id) {
+            peer_state.reputation.record(evidence);
+            self.dirty_reputations.insert(peer_id.clone());
+            Ok(())
+        } else {
+            Err(format!("Peer {} is missing in the peer store", peer_id).into())
+        }
+    }
+
+    /// Reputation score of a peer, zero for unknown peers.
+    pub(crate) fn reputation_score(&self, peer_id: &PeerId) -> f64 {
+        self.peer_states.get(peer_id).map_or(0.0, |peer_state| peer_state.reputation.score())
+    }
+
+    /// Saves the reputations changed since the last flush, in a single update.
+    pub(crate) fn flush_reputations(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let mut store_update = self.store.store_update();
+        for peer_id in self.dirty_reputations.drain() {
+            if let Some(peer_state) = self.peer_states.get(&peer_id) {
+                store_update.set_ser(ColPeers, &peer_id.try_to_vec()?, peer_state)?;
+            }
+        }
+        store_update.commit().map_err(|err| err.into())
+    }
+
+    /// Decays the reputation of all known peers, see `PeerReputation::decay`.
+    pub(crate) fn decay_reputations(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        for (peer_id, peer_state) in self.peer_states.iter_mut() {
+            if peer_state.reputation == PeerReputation::default() {
+                continue;
+            }
+            peer_state.reputation.decay();
+            self.dirty_reputations.insert(peer_id.clone());
+        }
+        self.flush_reputations()
+    }
+
     fn save_to_db(
         store: &Store,
         peer_id: &[u8],
@@ -192,21 +243,25 @@ impl PeerStore {
     }
 
     /// Return unconnected or peers with unknown status that we can try to connect to.
-    /// Peers with unknown addresses are filtered out.
+    /// Peers with unknown addresses are filtered out. Peers with better reputation are more
+    /// likely to be chosen.
     pub(crate) fn unconnected_peer(
         &self,
         ignore_fn: impl Fn(&KnownPeerState) -> bool,
     ) -> Option<PeerInfo> {
-        self.find_peers(
-            |p| {
+        let candidates: Vec<&KnownPeerState> = (self.peer_states.values())
+            .filter(|p| {
                 (p.status == KnownPeerStatus::NotConnected || p.status == KnownPeerStatus::Unknown)
                     && !ignore_fn(p)
                     && p.peer_info.addr.is_some()
-            },
-            1,
-        )
-        .get(0)
-        .cloned()
+            })
+            .collect();
+        candidates
+            .choose_weighted(&mut thread_rng(), |p| {
+                PeerReputation::selection_weight(p.reputation.score())
+            })
+            .ok()
+            .map(|p| p.peer_info.clone())
     }
 
     /// Return healthy known peers up to given amount.
@@ -379,6 +434,7 @@ mod test {
     use near_store::create_store;
     use near_store::test_utils::create_test_store;
     use std::net::{Ipv4Addr, SocketAddrV4};
+    use std::time::Duration;
 
     use super::*;
 
@@ -436,6 +492,85 @@ mod test {
         }
     }
 
+    #[test]
+    fn reputation_store() {
+        let tmp_dir = tempfile::Builder::new().prefix("_test_store_reputation").tempdir().unwrap();
+        let peer_info_good = gen_peer_info(0);
+        let peer_info_bad = gen_peer_info(1);
+        let boot_nodes = vec![peer_info_good.clone(), peer_info_bad.clone()];
+        {
+            let store = create_store(tmp_dir.path());
+            let mut peer_store = PeerStore::new(store, &boot_nodes).unwrap();
+            for _ in 0..10 {
+                peer_store
+                    .record_evidence(&peer_info_good.id, PeerEvidence::UsefulResponse)
+                    .unwrap();
+            }
+            peer_store
+                .record_evidence(
+                    &peer_info_good.id,
+                    PeerEvidence::Latency(Duration::from_millis(200)),
+                )
+                .unwrap();
+            peer_store.record_evidence(&peer_info_bad.id, PeerEvidence::Timeout).unwrap();
+            peer_store.record_evidence(&peer_info_bad.id, PeerEvidence::InvalidMessage).unwrap();
+            assert_eq!(peer_store.reputation_score(&peer_info_good.id), 8.0);
+            assert_eq!(peer_store.reputation_score(&peer_info_bad.id), -55.0);
+            assert_eq!(peer_store.reputation_score(&gen_peer_info(2).id), 0.0);
+        }
+        {
+            // Evidence isn't saved until the reputations are flushed.
+            let store_new = create_store(tmp_dir.path());
+            let mut peer_store_new = PeerStore::new(store_new, &boot_nodes).unwrap();
+            assert_eq!(peer_store_new.reputation_score(&peer_info_bad.id), 0.0);
+            peer_store_new.record_evidence(&peer_info_bad.id, PeerEvidence::Timeout).unwrap();
+            peer_store_new
+                .record_evidence(&peer_info_bad.id, PeerEvidence::InvalidMessage)
+                .unwrap();
+            for _ in 0..10 {
+                peer_store_new
+                    .record_evidence(&peer_info_good.id, PeerEvidence::UsefulResponse)
+                    .unwrap();
+            }
+            peer_store_new
+                .record_evidence(
+                    &peer_info_good.id,
+                    PeerEvidence::Latency(Duration::from_millis(200)),
+                )
+                .unwrap();
+            peer_store_new.flush_reputations().unwrap();
+        }
+        {
+            // Reputation is persisted, and decays.
+            let store_new = create_store(tmp_dir.path());
+            let mut peer_store_new = PeerStore::new(store_new, &boot_nodes).unwrap();
+            assert_eq!(peer_store_new.reputation_score(&peer_info_bad.id), -55.0);
+            peer_store_new.decay_reputations().unwrap();
+            assert_eq!(peer_store_new.reputation_score(&peer_info_good.id), 3.0);
+            assert_eq!(peer_store_new.reputation_score(&peer_info_bad.id), 0.0);
+        }
+    }
+
+    #[test]
+    fn unconnected_peer_prefers_reputation() {
+        let store = create_test_store();
+        let peer_info_good = gen_peer_info(0);
+        let peer_info_bad = gen_peer_info(1);
+        let boot_nodes = vec![peer_info_good.clone(), peer_info_bad.clone()];
+        let mut peer_store = PeerStore::new(store, &boot_nodes).unwrap();
+        for _ in 0..7 {
+            peer_store.record_evidence(&peer_info_bad.id, PeerEvidence::InvalidMessage).unwrap();
+        }
+        // The bad peer has a weight of 2^-32 against the good one.
+        for _ in 0..100 {
+            assert_eq!(peer_store.unconnected_peer(|_| false).unwrap().id, peer_info_good.id);
+        }
+        // Still chosen when there is no one else.
+        let bad_peer =
+            peer_store.unconnected_peer(|peer_state| peer_state.peer_info.id == peer_info_good.id);
+        assert_eq!(bad_peer.unwrap().id, peer_info_bad.id);
+    }
+
     fn check_exist(
         peer_store: &PeerStore,
         peer_id: &PeerId,
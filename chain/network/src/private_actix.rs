@@ -13,6 +13,7 @@ use near_rate_limiter::ThrottleController;
 use std::collections::HashMap;
 use std::fmt;
 use std::fmt::{Debug, Formatter};
+use std::net::SocketAddr;
 use std::sync::{Arc, Mutex};
 
 /// Actor message which asks `PeerManagerActor` to register peer.
@@ -35,6 +36,13 @@ pub struct RegisterPeer {
     pub(crate) peer_protocol_version: ProtocolVersion,
     /// A helper data structure for limiting reading, reporting bandwidth stats.
     pub(crate) throttle_controller: ThrottleController,
+    /// Address of this node as seen by the peer, if the peer reported it in the handshake.
+    pub(crate) observed_addr: Option<SocketAddr>,
+    /// Whether the connection is an encrypted session authenticated by the peer id.
+    pub(crate) encrypted: bool,
+    /// Whether the peer accepts QUIC connections.
+    #[cfg(feature = "protocol_feature_quic_transport")]
+    pub(crate) accepts_quic: bool,
 }
 
 /// Addr<PeerActor> doesn't implement `DeepSizeOf` waiting for `deepsize` > 0.2.0.
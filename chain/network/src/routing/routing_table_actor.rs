@@ -9,13 +9,13 @@ use actix::{
 };
 use near_network_primitives::types::{Edge, EdgeState};
 use near_performance_metrics_macros::perf;
-use near_primitives::borsh::BorshSerialize;
+use near_primitives::borsh::{BorshDeserialize, BorshSerialize};
 use near_primitives::network::PeerId;
 use near_primitives::utils::index_to_bytes;
 use near_rate_limiter::{ActixMessageResponse, ActixMessageWrapper, ThrottleToken};
 use near_store::db::DBCol::{ColComponentEdges, ColLastComponentNonce, ColPeerComponent};
 use near_store::{Store, StoreUpdate};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tracing::{debug, trace, warn};
@@ -763,3 +763,29 @@ impl Handler<ActixMessageWrapper<RoutingTableMessages>> for RoutingTableActor {
 pub fn start_routing_table_actor(peer_id: PeerId, store: Store) -> Addr<RoutingTableActor> {
     RoutingTableActor::new(peer_id, store).start()
 }
+
+/// Network components stored in the database, i.e. edges between peers which became unreachable.
+/// Returns the edges and the peers of each component, by component nonce.
+pub fn components_from_store(store: &Store) -> BTreeMap<u64, (Vec<Edge>, Vec<PeerId>)> {
+    let mut components: BTreeMap<u64, (Vec<Edge>, Vec<PeerId>)> = BTreeMap::new();
+    for (key, value) in store.iter(ColComponentEdges) {
+        let nonce = match key.as_ref().try_into() {
+            Ok(bytes) => u64::from_le_bytes(bytes),
+            Err(_) => {
+                warn!(target: "network", "Invalid component nonce in store: {:?}", key);
+                continue;
+            }
+        };
+        match Vec::<Edge>::try_from_slice(value.as_ref()) {
+            Ok(edges) => components.entry(nonce).or_default().0 = edges,
+            Err(err) => warn!(target: "network", "Invalid edges of component {}: {}", nonce, err),
+        }
+    }
+    for (key, value) in store.iter(ColPeerComponent) {
+        match (PeerId::try_from_slice(key.as_ref()), u64::try_from_slice(value.as_ref())) {
+            (Ok(peer_id), Ok(nonce)) => components.entry(nonce).or_default().1.push(peer_id),
+            _ => warn!(target: "network", "Invalid peer component in store: {:?}", key),
+        }
+    }
+    components
+}
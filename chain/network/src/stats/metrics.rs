@@ -89,6 +89,34 @@ pub static DROPPED_MESSAGES_COUNT: Lazy<IntCounter> = Lazy::new(|| {
     )
     .unwrap()
 });
+pub static PEER_COMPRESSION_RAW_BYTES_SENT: Lazy<IntCounter> = Lazy::new(|| {
+    try_create_int_counter(
+        "near_peer_compression_raw_bytes_sent",
+        "Size of messages sent compressed to peers, before compression",
+    )
+    .unwrap()
+});
+pub static PEER_COMPRESSION_COMPRESSED_BYTES_SENT: Lazy<IntCounter> = Lazy::new(|| {
+    try_create_int_counter(
+        "near_peer_compression_compressed_bytes_sent",
+        "Size of messages sent compressed to peers, after compression",
+    )
+    .unwrap()
+});
+pub static PEER_COMPRESSION_RAW_BYTES_RECEIVED: Lazy<IntCounter> = Lazy::new(|| {
+    try_create_int_counter(
+        "near_peer_compression_raw_bytes_received",
+        "Size of compressed messages received from peers, after decompression",
+    )
+    .unwrap()
+});
+pub static PEER_COMPRESSION_COMPRESSED_BYTES_RECEIVED: Lazy<IntCounter> = Lazy::new(|| {
+    try_create_int_counter(
+        "near_peer_compression_compressed_bytes_received",
+        "Size of compressed messages received from peers, before decompression",
+    )
+    .unwrap()
+});
 
 #[derive(Clone)]
 pub struct NetworkMetrics {
@@ -107,6 +135,8 @@ impl NetworkMetrics {
                         NetworkMetrics::peer_message_total_rx,
                         NetworkMetrics::peer_message_bytes_rx,
                         NetworkMetrics::peer_message_dropped,
+                        NetworkMetrics::peer_message_rate_limited_rx,
+                        NetworkMetrics::peer_message_rate_limited_tx,
                     ]
                     .map(|method| {
                         let counter_name = method(name);
@@ -130,6 +160,14 @@ impl NetworkMetrics {
         format!("near_{}_dropped", message_name.to_lowercase())
     }
 
+    pub fn peer_message_rate_limited_rx(message_name: &str) -> String {
+        format!("near_{}_rate_limited_rx", message_name.to_lowercase())
+    }
+
+    pub fn peer_message_rate_limited_tx(message_name: &str) -> String {
+        format!("near_{}_rate_limited_tx", message_name.to_lowercase())
+    }
+
     pub fn inc(&self, message_name: &str) {
         if let Some(counter) = self.peer_messages.get(message_name) {
             inc_counter_opt(counter.as_ref());
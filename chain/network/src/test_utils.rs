@@ -265,7 +265,6 @@ impl MockPeerManagerAdapter {
     }
 }
 
-#[cfg(feature = "test_features")]
 pub mod test_features {
     use crate::routing::routing_table_actor::{start_routing_table_actor, RoutingTableActor};
     use crate::test_utils::{convert_boot_nodes, open_port};
@@ -1,7 +1,7 @@
 use crate::routing::routing_table_actor::Prune;
 use crate::routing::routing_table_view::{DELETE_PEERS_AFTER_TIME, SAVE_PEERS_MAX_TIME};
 use crate::test_utils::random_peer_id;
-use crate::RoutingTableActor;
+use crate::{components_from_store, RoutingTableActor};
 use actix::System;
 use borsh::de::BorshDeserialize;
 use near_crypto::Signature;
@@ -309,3 +309,30 @@ fn overwrite_edge() {
 
     System::current().stop();
 }
+
+#[test]
+fn components_from_store() {
+    let _system = System::new();
+
+    let mut test = RoutingTableTest::new();
+    test.add_edge(0, 1, 2);
+    test.set_times(vec![(1, 2)]);
+    test.update_routing_table();
+    test.add_edge(0, 2, 2);
+    test.set_times(vec![(2, 2)]);
+    test.update_routing_table();
+
+    let components = components_from_store(&test.store);
+    assert_eq!(components.keys().cloned().collect::<Vec<_>>(), vec![0, 1]);
+    for (nonce, peer) in [(0, 1), (1, 2)] {
+        let me = test.get_peer(0).clone();
+        let peer_id = test.get_peer(peer).clone();
+        let (edges, peers) = components.get(&nonce).unwrap();
+        assert_eq!(edges.len(), 1);
+        assert_eq!(edges[0].key(), &Edge::make_key(me, peer_id.clone()));
+        assert_eq!(edges[0].edge_type(), EdgeState::Removed);
+        assert_eq!(peers, &vec![peer_id]);
+    }
+
+    System::current().stop();
+}
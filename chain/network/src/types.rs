@@ -13,21 +13,23 @@ use futures::future::BoxFuture;
 use near_network_primitives::types::{
     AccountIdOrPeerTrackingShard, AccountOrPeerIdOrHash, Ban, Edge, InboundTcpConnect,
     KnownProducer, OutboundTcpConnect, PartialEdgeInfo, PartialEncodedChunkForwardMsg,
-    PartialEncodedChunkRequestMsg, PartialEncodedChunkResponseMsg, PeerChainInfoV2, PeerInfo, Ping,
-    Pong, ReasonForBan, RoutedMessageBody, RoutedMessageFrom, StateResponseInfo,
+    PartialEncodedChunkRequestMsg, PartialEncodedChunkResponseMsg, PeerChainInfoV2, PeerEvidence,
+    PeerInfo, Ping, Pong, ReasonForBan, RoutedMessageBody, RoutedMessageFrom, StateResponseInfo,
 };
 use near_primitives::block::{Approval, ApprovalMessage, Block, BlockHeader};
 use near_primitives::challenge::Challenge;
 use near_primitives::errors::InvalidTxError;
 use near_primitives::hash::CryptoHash;
 use near_primitives::network::{AnnounceAccount, PeerId};
+#[cfg(feature = "protocol_feature_chunk_state_witness")]
+use near_primitives::sharding::ChunkStateWitness;
 use near_primitives::sharding::{PartialEncodedChunk, PartialEncodedChunkWithArcReceipts};
 use near_primitives::syncing::{EpochSyncFinalizationResponse, EpochSyncResponse};
 use near_primitives::time::Instant;
 use near_primitives::transaction::SignedTransaction;
 use near_primitives::types::{AccountId, BlockReference, EpochId, ShardId};
 use near_primitives::views::QueryRequest;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
 use strum::AsStaticStr;
 
@@ -253,6 +255,11 @@ pub enum NetworkRequests {
         peer_id: PeerId,
         ban_reason: ReasonForBan,
     },
+    /// Record evidence about the behaviour of given peer in its reputation.
+    ReportPeer {
+        peer_id: PeerId,
+        evidence: PeerEvidence,
+    },
     /// Announce account
     AnnounceAccount(AnnounceAccount),
 
@@ -291,9 +298,9 @@ pub enum NetworkRequests {
     /// Request for receipt execution outcome
     ReceiptOutComeRequest(AccountId, CryptoHash),
 
-    /// The following types of requests are used to trigger actions in the Peer Manager for testing.
-    /// (Unit tests) Fetch current routing table.
+    /// Fetch current routing table, used by unit tests and the `network_graph` debug RPC.
     FetchRoutingTable,
+    /// The following types of requests are used to trigger actions in the Peer Manager for testing.
     /// Data to sync routing table from active peer.
     SyncRoutingTable {
         peer_id: PeerId,
@@ -311,6 +318,13 @@ pub enum NetworkRequests {
     /// A challenge to invalidate a block.
     Challenge(Challenge),
 
+    /// Sending state witness of a chunk to a validator that may not track the shard.
+    #[cfg(feature = "protocol_feature_chunk_state_witness")]
+    ChunkStateWitness {
+        account_id: AccountId,
+        witness: Box<ChunkStateWitness>,
+    },
+
     // IbfMessage
     #[cfg(feature = "protocol_feature_routing_exchange_algorithm")]
     IbfMessage {
@@ -338,6 +352,10 @@ pub struct NetworkInfo {
     /// Accounts of known block and chunk producers from routing table.
     pub known_producers: Vec<KnownProducer>,
     pub peer_counter: usize,
+    /// Reputation scores of connected peers.
+    pub peer_scores: HashMap<PeerId, f64>,
+    /// Peers whose address is never exposed, see `NetworkConfig::private_peers`.
+    pub private_peers: HashSet<PeerId>,
 }
 
 #[derive(Debug, actix::MessageResponse)]
@@ -393,6 +411,9 @@ pub enum NetworkClientMessages {
 
     /// A challenge to invalidate the block.
     Challenge(Challenge),
+    /// State witness of a chunk.
+    #[cfg(feature = "protocol_feature_chunk_state_witness")]
+    ChunkStateWitness(Box<ChunkStateWitness>),
 
     NetworkInfo(NetworkInfo),
 }
@@ -422,6 +443,8 @@ pub enum NetworkClientResponses {
     DoesNotTrackShard,
     /// Ban peer for malicious behavior.
     Ban { ban_reason: ReasonForBan },
+    /// The message is invalid, which lowers the reputation of its author.
+    InvalidMessage,
 }
 
 /// Adapter to break dependency of sub-components on the network requests.
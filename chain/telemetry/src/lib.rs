@@ -1,40 +1,81 @@
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
 use std::time::Duration;
 
 use actix::{Actor, Addr, Context, Handler, Message};
 use awc::{Client, Connector};
 use futures::FutureExt;
+use near_crypto::{EmptySigner, Signature, Signer};
 use near_performance_metrics_macros::perf;
+use near_primitives::telemetry::{TelemetryInfo, TelemetryReport};
+use near_primitives::time::Clock;
 use serde::{Deserialize, Serialize};
-use tracing::info;
+use tracing::{error, info, warn};
 
 /// Timeout for establishing connection.
 const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
 
-#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+fn default_interval() -> Duration {
+    Duration::from_secs(10)
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct TelemetryConfig {
     pub endpoints: Vec<String>,
+    /// File reports are appended to, one JSON object per line.
+    #[serde(default)]
+    pub file: Option<PathBuf>,
+    /// Print reports to stdout, one JSON object per line.
+    #[serde(default)]
+    pub stdout: bool,
+    /// Reports are sent at most once per interval, with the latest telemetry collected.
+    #[serde(default = "default_interval")]
+    pub interval: Duration,
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        Self { endpoints: vec![], file: None, stdout: false, interval: default_interval() }
+    }
 }
 
 /// Event to send over telemetry.
 #[derive(Message, Debug)]
 #[rtype(result = "()")]
 pub struct TelemetryEvent {
-    content: serde_json::Value,
+    info: TelemetryInfo,
+    validator_signature: Option<Signature>,
 }
 
 pub struct TelemetryActor {
     config: TelemetryConfig,
     client: Client,
+    /// Reports are signed with the key of the node.
+    node_signer: Arc<dyn Signer>,
+    /// File and stdout sinks which are enabled, reports are written to them one per line.
+    sinks: Vec<Box<dyn Write>>,
+    /// Latest event, sent on the next tick.
+    pending: Option<TelemetryEvent>,
 }
 
 impl Default for TelemetryActor {
     fn default() -> Self {
-        Self::new(TelemetryConfig::default())
+        Self::new(TelemetryConfig::default(), Arc::new(EmptySigner {}))
     }
 }
 
 impl TelemetryActor {
-    pub fn new(config: TelemetryConfig) -> Self {
+    pub fn new(config: TelemetryConfig, node_signer: Arc<dyn Signer>) -> Self {
+        Self::with_stdout(config, node_signer, Box::new(io::stdout()))
+    }
+
+    fn with_stdout(
+        config: TelemetryConfig,
+        node_signer: Arc<dyn Signer>,
+        stdout: Box<dyn Write>,
+    ) -> Self {
         for endpoint in config.endpoints.iter() {
             if endpoint.is_empty() {
                 panic!(
@@ -43,30 +84,58 @@ impl TelemetryActor {
                 );
             }
         }
+        let mut sinks: Vec<Box<dyn Write>> = vec![];
+        if let Some(path) = config.file.as_ref() {
+            // The node runs fine without telemetry, so the file sink is disabled instead.
+            match OpenOptions::new().create(true).append(true).open(path) {
+                Ok(file) => sinks.push(Box::new(file)),
+                Err(err) => error!(
+                    target: "telemetry",
+                    "Failed to open telemetry file {}, not writing telemetry to it: {}",
+                    path.display(),
+                    err
+                ),
+            }
+        }
+        if config.stdout {
+            sinks.push(stdout);
+        }
 
         let client = Client::builder()
             .timeout(CONNECT_TIMEOUT)
             .connector(Connector::new().max_http_version(actix_web::http::Version::HTTP_11))
             .finish();
-        Self { config, client }
+        Self { config, client, node_signer, sinks, pending: None }
     }
-}
 
-impl Actor for TelemetryActor {
-    type Context = Context<Self>;
-}
+    fn send_report_trigger(&mut self, ctx: &mut Context<Self>) {
+        self.send_pending_report();
+        near_performance_metrics::actix::run_later(ctx, self.config.interval, move |act, ctx| {
+            act.send_report_trigger(ctx)
+        });
+    }
 
-impl Handler<TelemetryEvent> for TelemetryActor {
-    type Result = ();
+    /// Sends the latest event received since the last report, if any.
+    fn send_pending_report(&mut self) {
+        if let Some(event) = self.pending.take() {
+            self.send_report(event);
+        }
+    }
 
-    #[perf]
-    fn handle(&mut self, msg: TelemetryEvent, _ctx: &mut Context<Self>) {
+    fn send_report(&mut self, event: TelemetryEvent) {
+        let report = TelemetryReport::new(
+            event.info,
+            Clock::utc().timestamp_millis() as u64,
+            event.validator_signature,
+            self.node_signer.as_ref(),
+        );
+        let content = serde_json::to_value(&report).expect("Telemetry must serialize to JSON");
         for endpoint in self.config.endpoints.iter() {
             near_performance_metrics::actix::spawn("telemetry",
                                            self.client
                         .post(endpoint)
                         .insert_header(("Content-Type", "application/json"))
-                        .send_json(&msg.content)
+                        .send_json(&content)
                         .map(|response| {
                             if let Err(error) = response {
                                 info!(target: "telemetry", "Telemetry data could not be sent due to: {}", error);
@@ -74,10 +143,182 @@ impl Handler<TelemetryEvent> for TelemetryActor {
                         }),
                 );
         }
+        for sink in self.sinks.iter_mut() {
+            if let Err(err) = writeln!(sink, "{}", content).and_then(|()| sink.flush()) {
+                warn!(target: "telemetry", "Telemetry data could not be written due to: {}", err);
+            }
+        }
+    }
+}
+
+impl Actor for TelemetryActor {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.send_report_trigger(ctx);
     }
 }
 
-/// Send telemetry event to all the endpoints.
-pub fn telemetry(telemetry: &Addr<TelemetryActor>, content: serde_json::Value) {
-    telemetry.do_send(TelemetryEvent { content });
+impl Handler<TelemetryEvent> for TelemetryActor {
+    type Result = ();
+
+    #[perf]
+    fn handle(&mut self, msg: TelemetryEvent, _ctx: &mut Context<Self>) {
+        self.pending = Some(msg);
+    }
+}
+
+/// Send telemetry to all the sinks, signed with the validator key if there is one.
+pub fn telemetry(
+    telemetry: &Addr<TelemetryActor>,
+    info: TelemetryInfo,
+    validator_signature: Option<Signature>,
+) {
+    telemetry.do_send(TelemetryEvent { info, validator_signature });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TelemetryActor, TelemetryConfig, TelemetryEvent};
+    use actix::{Context, Handler};
+    use near_crypto::{InMemorySigner, KeyType};
+    use near_primitives::telemetry::{
+        TelemetryAgentInfo, TelemetryChainInfo, TelemetryInfo, TelemetryReport, TelemetrySyncInfo,
+        TelemetrySystemInfo,
+    };
+    use std::cell::RefCell;
+    use std::io::{self, Write};
+    use std::rc::Rc;
+    use std::sync::Arc;
+
+    /// Stdout of the actor under test.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn info(height: u64) -> TelemetryInfo {
+        TelemetryInfo {
+            agent: TelemetryAgentInfo {
+                name: "near-rs".to_string(),
+                version: "trunk".to_string(),
+                build: "test".to_string(),
+            },
+            system: TelemetrySystemInfo {
+                bandwidth_download: 0,
+                bandwidth_upload: 0,
+                cpu_usage: 0.0,
+                memory_usage: 0,
+            },
+            chain: TelemetryChainInfo {
+                node_id: "node".to_string(),
+                account_id: None,
+                is_validator: false,
+                status: "NoSync".to_string(),
+                latest_block_hash: "hash".to_string(),
+                latest_block_height: height,
+                num_peers: 0,
+            },
+            validator: None,
+            sync: TelemetrySyncInfo::default(),
+        }
+    }
+
+    fn actor(config: TelemetryConfig, stdout: SharedBuffer) -> TelemetryActor {
+        let signer = InMemorySigner::from_seed("node".parse().unwrap(), KeyType::ED25519, "node");
+        TelemetryActor::with_stdout(config, Arc::new(signer), Box::new(stdout))
+    }
+
+    fn send(actor: &mut TelemetryActor, height: u64) {
+        let event = TelemetryEvent { info: info(height), validator_signature: None };
+        actor.handle(event, &mut Context::new());
+    }
+
+    fn reports(data: &[u8]) -> Vec<TelemetryReport> {
+        std::str::from_utf8(data)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_file_sink() {
+        actix::System::new().block_on(async {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("telemetry.jsonl");
+            let config = TelemetryConfig { file: Some(path.clone()), ..Default::default() };
+            let stdout = SharedBuffer::default();
+            let mut actor = actor(config, stdout.clone());
+            for height in 1..=2 {
+                send(&mut actor, height);
+                actor.send_pending_report();
+            }
+            // Reports are appended to the file, and nothing is printed.
+            let reports = reports(&std::fs::read(&path).unwrap());
+            assert_eq!(reports.len(), 2);
+            assert_eq!(reports[1].info, info(2));
+            assert!(reports.iter().all(|report| report.verify()));
+            assert!(stdout.0.borrow().is_empty());
+        });
+    }
+
+    #[test]
+    fn test_file_sink_fails_to_open() {
+        actix::System::new().block_on(async {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("missing").join("telemetry.jsonl");
+            let config =
+                TelemetryConfig { file: Some(path.clone()), stdout: true, ..Default::default() };
+            let stdout = SharedBuffer::default();
+            let mut actor = actor(config, stdout.clone());
+            send(&mut actor, 1);
+            actor.send_pending_report();
+            // The file sink is disabled, the others keep working.
+            assert!(!path.exists());
+            assert_eq!(reports(&stdout.0.borrow()).len(), 1);
+        });
+    }
+
+    #[test]
+    fn test_stdout_sink() {
+        actix::System::new().block_on(async {
+            let config = TelemetryConfig { stdout: true, ..Default::default() };
+            let stdout = SharedBuffer::default();
+            let mut actor = actor(config, stdout.clone());
+            send(&mut actor, 1);
+            actor.send_pending_report();
+            let reports = reports(&stdout.0.borrow());
+            assert_eq!(reports.len(), 1);
+            assert_eq!(reports[0].info, info(1));
+            assert!(reports[0].verify());
+        });
+    }
+
+    #[test]
+    fn test_interval_batching() {
+        actix::System::new().block_on(async {
+            let config = TelemetryConfig { stdout: true, ..Default::default() };
+            let stdout = SharedBuffer::default();
+            let mut actor = actor(config, stdout.clone());
+            // Only the latest event received during an interval is reported.
+            for height in 1..=3 {
+                send(&mut actor, height);
+            }
+            actor.send_pending_report();
+            // Nothing is reported for intervals without events.
+            actor.send_pending_report();
+            let reports = reports(&stdout.0.borrow());
+            assert_eq!(reports.len(), 1);
+            assert_eq!(reports[0].info, info(3));
+        });
+    }
 }
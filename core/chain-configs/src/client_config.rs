@@ -1,5 +1,6 @@
 //! Chain Client Configuration
 use std::cmp::min;
+use std::path::PathBuf;
 use std::time::Duration;
 
 use serde::{Deserialize, Serialize};
@@ -101,6 +102,19 @@ pub struct ClientConfig {
     /// genesis file.  The value only affects the RPCs without influencing the
     /// protocol thus changing it per-node doesn’t affect the blockchain.
     pub max_gas_burnt_view: Option<Gas>,
+    /// Submit challenges for misbehavior detected in tracked shards and report them in metrics.
+    pub fisherman: bool,
+    /// Send state witnesses of produced chunks to the block producers.
+    pub produce_chunk_state_witnesses: bool,
+    /// Validate chunks of shards that are not tracked using state witnesses sent by their
+    /// chunk producers, and reject blocks with chunks that don't match them.
+    pub validate_chunk_state_witnesses: bool,
+    /// File with a new key for the validator. It is loaded at startup and on request, and
+    /// replaces the current validator key once the new key takes effect in the epoch info.
+    pub pending_validator_key_path: Option<PathBuf>,
+    /// Refuse to sign blocks, chunks and approvals conflicting with the signing history kept in
+    /// the database.
+    pub signing_history: bool,
 }
 
 impl ClientConfig {
@@ -159,6 +173,11 @@ impl ClientConfig {
             view_client_throttle_period: Duration::from_secs(1),
             trie_viewer_state_size_limit: None,
             max_gas_burnt_view: None,
+            fisherman: false,
+            produce_chunk_state_witnesses: false,
+            validate_chunk_state_witnesses: false,
+            pending_validator_key_path: None,
+            signing_history: false,
         }
     }
 }
@@ -19,6 +19,8 @@ use smart_default::SmartDefault;
 use tracing::{info, warn};
 
 use crate::genesis_validate::validate_genesis;
+#[cfg(feature = "protocol_feature_dynamic_resharding")]
+use near_primitives::epoch_manager::ReshardingConfig;
 use near_primitives::epoch_manager::{AllEpochConfig, EpochConfig, ShardConfig};
 use near_primitives::shard_layout::ShardLayout;
 use near_primitives::types::validator_stake::ValidatorStake;
@@ -105,6 +107,11 @@ pub struct GenesisConfig {
     pub avg_hidden_validator_seats_per_shard: Vec<NumSeats>,
     /// Enable dynamic re-sharding.
     pub dynamic_resharding: bool,
+    /// Limits on the load of shards above which shards are split, if `dynamic_resharding` is
+    /// enabled.
+    #[cfg(feature = "protocol_feature_dynamic_resharding")]
+    #[serde(default)]
+    pub resharding_config: ReshardingConfig,
     /// Threshold of stake that needs to indicate that they ready for upgrade.
     #[serde(default = "default_protocol_upgrade_stake_threshold")]
     #[default(Rational::new(8, 10))]
@@ -243,6 +250,12 @@ impl From<&GenesisConfig> for AllEpochConfig {
             None
         };
         let epoch_config = Self::new(initial_epoch_config.clone(), shard_config);
+        #[cfg(feature = "protocol_feature_dynamic_resharding")]
+        let epoch_config = if genesis_config.dynamic_resharding {
+            epoch_config.with_resharding_config(genesis_config.resharding_config.clone())
+        } else {
+            epoch_config
+        };
         assert_eq!(
             initial_epoch_config,
             epoch_config.for_protocol_version(genesis_config.protocol_version).clone()
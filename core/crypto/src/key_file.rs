@@ -34,4 +34,13 @@ impl KeyFile {
         file.read_to_string(&mut content).expect("Could not read from key file.");
         serde_json::from_str(&content).expect("Failed to deserialize KeyFile")
     }
+
+    /// Same as `from_file`, but returns an error instead of panicking if the file is missing or
+    /// malformed.
+    pub fn try_from_file(path: &Path) -> std::io::Result<Self> {
+        let mut file = File::open(path)?;
+        let mut content = String::new();
+        file.read_to_string(&mut content)?;
+        Ok(serde_json::from_str(&content)?)
+    }
 }
@@ -35,6 +35,9 @@ pub struct VMLimitConfig {
     /// [`StackLimiterVersion`].
     #[serde(default = "StackLimiterVersion::v0")]
     pub stack_limiter_version: StackLimiterVersion,
+    /// How `regular_op_cost` is charged, see [`GasMetering`].
+    #[serde(default = "GasMetering::instrumentation")]
+    pub gas_metering: GasMetering,
 
     /// The initial number of memory pages.
     /// NOTE: It's not a limiter itself, but it's a value we use for initial_memory_pages.
@@ -121,6 +124,39 @@ impl StackLimiterVersion {
     }
 }
 
+/// Way of charging `regular_op_cost` for the executed wasm code.
+///
+/// Both variants must produce exactly the same `gas_burnt` for any contract.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GasMetering {
+    /// Calls to the `gas` host function are injected into the contract code before it is
+    /// compiled. Used by all VMs.
+    Instrumentation,
+    /// The same calls are emitted by a compiler middleware while the contract is compiled,
+    /// instead of being injected into the wasm code. Only supported by Wasmer2, other VMs fall
+    /// back to `Instrumentation`.
+    Middleware,
+}
+
+impl GasMetering {
+    fn instrumentation() -> GasMetering {
+        GasMetering::Instrumentation
+    }
+}
+
+/// `Instrumentation` doesn't contribute to the hash at all, so that [`VMConfig::non_crypto_hash`],
+/// and with it the keys of the compiled contracts cache, stay the same as before the field was
+/// added to [`VMLimitConfig`] as long as the middleware isn't used.
+impl Hash for GasMetering {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self {
+            GasMetering::Instrumentation => {}
+            GasMetering::Middleware => 1u8.hash(state),
+        }
+    }
+}
+
 impl Serialize for StackLimiterVersion {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -180,6 +216,7 @@ impl VMLimitConfig {
             // For experimentation try `test_stack_overflow`.
             max_stack_height: 16 * 1024, // 16Kib of stack.
             stack_limiter_version: StackLimiterVersion::V1,
+            gas_metering: GasMetering::Instrumentation,
             initial_memory_pages: 2u32.pow(10), // 64Mib of memory.
             max_memory_pages: 2u32.pow(11),     // 128Mib of memory.
 
@@ -390,6 +427,18 @@ pub struct ExtCostsConfig {
     /// Cost for pairing check per byte
     #[cfg(feature = "protocol_feature_alt_bn128")]
     pub alt_bn128_pairing_check_byte: Gas,
+
+    // ##################
+    // # Ed25519 Verify #
+    // ##################
+    /// Cost of verifying an ed25519 signature
+    #[cfg(feature = "protocol_feature_ed25519_verify")]
+    #[serde(default = "ExtCostsConfig::default_ed25519_verify_base")]
+    pub ed25519_verify_base: Gas,
+    /// Cost of verifying an ed25519 signature per byte of the message
+    #[cfg(feature = "protocol_feature_ed25519_verify")]
+    #[serde(default = "ExtCostsConfig::default_ed25519_verify_byte")]
+    pub ed25519_verify_byte: Gas,
 }
 
 // We multiply the actual computed costs by the fixed factor to ensure we
@@ -466,6 +515,10 @@ impl ExtCostsConfig {
             alt_bn128_g1_sum_base: SAFETY_MULTIPLIER * 1058438125,
             #[cfg(feature = "protocol_feature_alt_bn128")]
             alt_bn128_g1_sum_byte: SAFETY_MULTIPLIER * 25406181,
+            #[cfg(feature = "protocol_feature_ed25519_verify")]
+            ed25519_verify_base: Self::default_ed25519_verify_base(),
+            #[cfg(feature = "protocol_feature_ed25519_verify")]
+            ed25519_verify_byte: Self::default_ed25519_verify_byte(),
         }
     }
 
@@ -537,8 +590,23 @@ impl ExtCostsConfig {
             alt_bn128_g1_sum_base: 0,
             #[cfg(feature = "protocol_feature_alt_bn128")]
             alt_bn128_g1_sum_byte: 0,
+            #[cfg(feature = "protocol_feature_ed25519_verify")]
+            ed25519_verify_base: 0,
+            #[cfg(feature = "protocol_feature_ed25519_verify")]
+            ed25519_verify_byte: 0,
         }
     }
+
+    /// Runtime configs predating `ed25519_verify` don't list its costs.
+    #[cfg(feature = "protocol_feature_ed25519_verify")]
+    fn default_ed25519_verify_base() -> Gas {
+        SAFETY_MULTIPLIER * 70_000_000_000
+    }
+
+    #[cfg(feature = "protocol_feature_ed25519_verify")]
+    fn default_ed25519_verify_byte() -> Gas {
+        SAFETY_MULTIPLIER * 3_000_000
+    }
 }
 
 /// Strongly-typed representation of the fees for counting.
@@ -611,6 +679,10 @@ pub enum ExtCosts {
     alt_bn128_g1_sum_base,
     #[cfg(feature = "protocol_feature_alt_bn128")]
     alt_bn128_g1_sum_byte,
+    #[cfg(feature = "protocol_feature_ed25519_verify")]
+    ed25519_verify_base,
+    #[cfg(feature = "protocol_feature_ed25519_verify")]
+    ed25519_verify_byte,
 
     // NOTE: this should be the last element of the enum.
     __count,
@@ -738,6 +810,10 @@ impl ExtCosts {
             alt_bn128_g1_sum_base => config.alt_bn128_g1_sum_base,
             #[cfg(feature = "protocol_feature_alt_bn128")]
             alt_bn128_g1_sum_byte => config.alt_bn128_g1_sum_byte,
+            #[cfg(feature = "protocol_feature_ed25519_verify")]
+            ed25519_verify_base => config.ed25519_verify_base,
+            #[cfg(feature = "protocol_feature_ed25519_verify")]
+            ed25519_verify_byte => config.ed25519_verify_byte,
 
             __count => unreachable!(),
         }
@@ -815,6 +891,27 @@ impl ExtCosts {
             "alt_bn128_g1_sum_base",
             #[cfg(feature = "protocol_feature_alt_bn128")]
             "alt_bn128_g1_sum_byte",
+            #[cfg(feature = "protocol_feature_ed25519_verify")]
+            "ed25519_verify_base",
+            #[cfg(feature = "protocol_feature_ed25519_verify")]
+            "ed25519_verify_byte",
         ][index]
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_instrumentation_gas_metering_keeps_config_hash() {
+        let mut hasher = DefaultHasher::new();
+        GasMetering::Instrumentation.hash(&mut hasher);
+        assert_eq!(hasher.finish(), DefaultHasher::new().finish());
+
+        let mut config = VMConfig::test();
+        let hash = config.non_crypto_hash();
+        config.limit_config.gas_metering = GasMetering::Middleware;
+        assert_ne!(config.non_crypto_hash(), hash);
+    }
+}
@@ -9,7 +9,17 @@ use crate::config::{ActionCosts, ExtCosts};
 pub struct DataArray(Box<[u64; Self::LEN]>);
 
 impl DataArray {
-    pub const LEN: usize = Cost::ALL.len();
+    /// Every cost has a fixed slot, whether the protocol feature it belongs to is enabled or not.
+    pub const LEN: usize = 72;
+    /// Slots up to the last cost of the enabled features, which are serialized. The profiles of
+    /// builds without the features thus keep their format.
+    const SERIALIZED_LEN: usize = if cfg!(feature = "protocol_feature_ed25519_verify") {
+        72
+    } else if cfg!(feature = "protocol_feature_alt_bn128") {
+        70
+    } else {
+        63
+    };
 }
 
 impl Index<usize> for DataArray {
@@ -38,7 +48,7 @@ impl BorshDeserialize for DataArray {
 
 impl BorshSerialize for DataArray {
     fn serialize<W: std::io::Write>(&self, writer: &mut W) -> Result<(), std::io::Error> {
-        let v: Vec<_> = self.0.as_ref().to_vec();
+        let v: Vec<_> = self.0[..Self::SERIALIZED_LEN].to_vec();
         BorshSerialize::serialize(&v, writer)
     }
 }
@@ -259,6 +269,10 @@ impl Cost {
         Cost::ExtCost { ext_cost_kind: ExtCosts::alt_bn128_g1_sum_base },
         #[cfg(feature = "protocol_feature_alt_bn128")]
         Cost::ExtCost { ext_cost_kind: ExtCosts::alt_bn128_g1_sum_byte },
+        #[cfg(feature = "protocol_feature_ed25519_verify")]
+        Cost::ExtCost { ext_cost_kind: ExtCosts::ed25519_verify_base },
+        #[cfg(feature = "protocol_feature_ed25519_verify")]
+        Cost::ExtCost { ext_cost_kind: ExtCosts::ed25519_verify_byte },
     ];
 
     pub fn index(self) -> usize {
@@ -341,6 +355,10 @@ impl Cost {
             Cost::ExtCost { ext_cost_kind: ExtCosts::alt_bn128_g1_sum_base } => 68,
             #[cfg(feature = "protocol_feature_alt_bn128")]
             Cost::ExtCost { ext_cost_kind: ExtCosts::alt_bn128_g1_sum_byte } => 69,
+            #[cfg(feature = "protocol_feature_ed25519_verify")]
+            Cost::ExtCost { ext_cost_kind: ExtCosts::ed25519_verify_base } => 70,
+            #[cfg(feature = "protocol_feature_ed25519_verify")]
+            Cost::ExtCost { ext_cost_kind: ExtCosts::ed25519_verify_byte } => 71,
             Cost::ExtCost { ext_cost_kind: ExtCosts::__count } => unreachable!(),
         }
     }
@@ -386,6 +404,15 @@ mod test {
         assert_eq!(res, u64::MAX);
     }
 
+    #[test]
+    fn test_cost_index_in_bounds() {
+        let mut indices: Vec<usize> = Cost::ALL.iter().map(|cost| cost.index()).collect();
+        assert!(indices.iter().all(|index| *index < DataArray::SERIALIZED_LEN));
+        indices.sort();
+        indices.dedup();
+        assert_eq!(indices.len(), Cost::ALL.len());
+    }
+
     #[test]
     fn test_merge() {
         let mut profile_data = ProfileData::new();
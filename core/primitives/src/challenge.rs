@@ -6,8 +6,9 @@ use near_crypto::Signature;
 use crate::hash::{hash, CryptoHash};
 use crate::merkle::MerklePath;
 use crate::sharding::{EncodedShardChunk, ShardChunk, ShardChunkHeader};
+use crate::syncing::{ReceiptProofResponse, RootProof};
 use crate::types::AccountId;
-use crate::validator_signer::ValidatorSigner;
+use crate::validator_signer::{ValidatorSigner, ValidatorSignerError};
 
 /// Serialized TrieNodeWithSize
 pub type StateItem = Vec<u8>;
@@ -73,6 +74,35 @@ pub struct ChunkState {
     pub partial_state: PartialState,
 }
 
+/// Same as `ChunkState`, along with the receipts applied together with the previous chunk, so
+/// that the state transition can be re-executed.
+#[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Eq, Clone, Debug)]
+pub struct ChunkStateV2 {
+    /// Encoded prev block header.
+    pub prev_block_header: Vec<u8>,
+    /// Encoded block header that contains invalid chunk.
+    pub block_header: Vec<u8>,
+    /// Merkle proof in inclusion of prev chunk.
+    pub prev_merkle_proof: MerklePath,
+    /// Previous chunk that contains transactions.
+    pub prev_chunk: ShardChunk,
+    /// Merkle proof of inclusion of this chunk.
+    pub merkle_proof: MerklePath,
+    /// Invalid chunk header.
+    pub chunk_header: ShardChunkHeader,
+    /// Partial state that was affected by transactions of given chunk.
+    pub partial_state: PartialState,
+    /// Receipts applied together with the previous chunk, one entry per block starting from the
+    /// previous block and down to the block with the chunk before the previous chunk, excluded.
+    pub prev_chunk_incoming_receipts: Vec<ReceiptProofResponse>,
+    /// Proofs that the receipts in `prev_chunk_incoming_receipts` are included in their blocks.
+    pub prev_chunk_root_proofs: Vec<Vec<RootProof>>,
+    /// Encoded headers of the blocks before the previous block, down to and including the block
+    /// with the chunk before the previous chunk.
+    pub prev_chunk_block_headers: Vec<Vec<u8>>,
+}
+
 #[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
 #[derive(BorshSerialize, BorshDeserialize, PartialEq, Eq, Clone, Debug)]
 // TODO(#1313): Use Box
@@ -81,6 +111,8 @@ pub enum ChallengeBody {
     BlockDoubleSign(BlockDoubleSign),
     ChunkProofs(ChunkProofs),
     ChunkState(ChunkState),
+    /// Only valid once `ProtocolFeature::ChunkStateChallenges` is enabled.
+    ChunkStateV2(ChunkStateV2),
 }
 
 #[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
@@ -100,9 +132,12 @@ impl Challenge {
         self.hash = hash(&self.body.try_to_vec().expect("Failed to serialize"));
     }
 
-    pub fn produce(body: ChallengeBody, signer: &dyn ValidatorSigner) -> Self {
-        let (hash, signature) = signer.sign_challenge(&body);
-        Self { body, account_id: signer.validator_id().clone(), signature, hash }
+    pub fn produce(
+        body: ChallengeBody,
+        signer: &dyn ValidatorSigner,
+    ) -> Result<Self, ValidatorSignerError> {
+        let (hash, signature) = signer.sign_challenge(&body)?;
+        Ok(Self { body, account_id: signer.validator_id().clone(), signature, hash })
     }
 }
 
@@ -7,8 +7,8 @@ use crate::checked_feature;
 use crate::shard_layout::ShardLayout;
 use crate::types::validator_stake::ValidatorStakeV1;
 use crate::types::{
-    AccountId, Balance, BlockHeightDelta, EpochHeight, EpochId, NumSeats, ProtocolVersion,
-    ValidatorId, ValidatorKickoutReason,
+    AccountId, Balance, BlockHeightDelta, EpochHeight, EpochId, Gas, NumSeats, NumShards,
+    ProtocolVersion, ValidatorId, ValidatorKickoutReason,
 };
 use crate::version::PROTOCOL_VERSION;
 use near_primitives_core::hash::CryptoHash;
@@ -54,7 +54,24 @@ pub struct EpochConfig {
     pub validator_selection_config: ValidatorSelectionConfig,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl EpochConfig {
+    /// Returns the config with the number of seats per shard and the shard layout replaced.
+    pub fn with_shard_config(self, shard_config: ShardConfig) -> Self {
+        let ShardConfig {
+            num_block_producer_seats_per_shard,
+            avg_hidden_validator_seats_per_shard,
+            shard_layout,
+        } = shard_config;
+        EpochConfig {
+            num_block_producer_seats_per_shard,
+            avg_hidden_validator_seats_per_shard,
+            shard_layout,
+            ..self
+        }
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ShardConfig {
     pub num_block_producer_seats_per_shard: Vec<NumSeats>,
     pub avg_hidden_validator_seats_per_shard: Vec<NumSeats>,
@@ -75,6 +92,9 @@ impl From<EpochConfig> for ShardConfig {
 pub struct AllEpochConfig {
     genesis_epoch_config: EpochConfig,
     simple_nightshade_epoch_config: EpochConfig,
+    /// Limits on the load of shards, if shards are split by load.
+    #[cfg(feature = "protocol_feature_dynamic_resharding")]
+    resharding_config: Option<ReshardingConfig>,
 }
 
 impl AllEpochConfig {
@@ -83,17 +103,41 @@ impl AllEpochConfig {
         simple_nightshade_shard_config: Option<ShardConfig>,
     ) -> Self {
         let mut config = genesis_epoch_config.clone();
-        if let Some(ShardConfig {
-            num_block_producer_seats_per_shard,
-            avg_hidden_validator_seats_per_shard,
-            shard_layout,
-        }) = simple_nightshade_shard_config
-        {
-            config.num_block_producer_seats_per_shard = num_block_producer_seats_per_shard;
-            config.avg_hidden_validator_seats_per_shard = avg_hidden_validator_seats_per_shard;
-            config.shard_layout = shard_layout;
+        if let Some(shard_config) = simple_nightshade_shard_config {
+            config = config.with_shard_config(shard_config);
+        }
+        Self {
+            genesis_epoch_config,
+            simple_nightshade_epoch_config: config,
+            #[cfg(feature = "protocol_feature_dynamic_resharding")]
+            resharding_config: None,
         }
-        Self { genesis_epoch_config, simple_nightshade_epoch_config: config }
+    }
+
+    /// Splits shards by load, with the given limits, once dynamic resharding is enabled.
+    #[cfg(feature = "protocol_feature_dynamic_resharding")]
+    pub fn with_resharding_config(mut self, resharding_config: ReshardingConfig) -> Self {
+        self.resharding_config = Some(resharding_config);
+        self
+    }
+
+    /// Limits on the load of shards in epochs of the given protocol version, if shards are split
+    /// by load in these epochs.
+    pub fn resharding_config(
+        &self,
+        protocol_version: ProtocolVersion,
+    ) -> Option<&ReshardingConfig> {
+        #[cfg(feature = "protocol_feature_dynamic_resharding")]
+        if checked_feature!(
+            "protocol_feature_dynamic_resharding",
+            DynamicResharding,
+            protocol_version
+        ) {
+            return self.resharding_config.as_ref();
+        }
+        #[cfg(not(feature = "protocol_feature_dynamic_resharding"))]
+        let _ = protocol_version;
+        None
     }
 
     pub fn for_protocol_version(&self, protocol_version: ProtocolVersion) -> &EpochConfig {
@@ -105,6 +149,80 @@ impl AllEpochConfig {
     }
 }
 
+/// Limits on the load of a single shard, used to split and merge shards by load. Load is measured
+/// over whole epochs: a shard whose load stays above the limits for
+/// `num_overloaded_epochs_to_split` epochs in a row is split in two at its median account, and two
+/// adjacent shards whose loads both stay below the minimums for `num_underloaded_epochs_to_merge`
+/// epochs in a row are merged.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, SmartDefault)]
+#[serde(default)]
+pub struct ReshardingConfig {
+    /// Average gas burnt per chunk of the shard over an epoch, above which the shard is overloaded.
+    #[default(500_000_000_000_000)]
+    pub max_avg_gas_used_per_chunk: Gas,
+    /// Size of the state of the shard at the end of an epoch, as accounted by trie memory usage,
+    /// above which the shard is overloaded.
+    #[default(50_000_000_000)]
+    pub max_state_size: u64,
+    /// Number of epochs in a row a shard must be overloaded for to be split.
+    #[default(3)]
+    pub num_overloaded_epochs_to_split: EpochHeight,
+    /// Shards are not split further once there are that many of them.
+    #[default(32)]
+    pub max_num_shards: NumShards,
+    /// Average gas burnt per chunk of the shard over an epoch, below which the shard is
+    /// underloaded, unless it is overloaded by state size.
+    #[default(50_000_000_000_000)]
+    pub min_avg_gas_used_per_chunk: Gas,
+    /// Size of the state of the shard, below which the shard is underloaded, unless it is
+    /// overloaded by gas.
+    #[default(5_000_000_000)]
+    pub min_state_size: u64,
+    /// Number of epochs in a row two adjacent shards must both be underloaded for to be merged.
+    #[default(3)]
+    pub num_underloaded_epochs_to_merge: EpochHeight,
+    /// Shards are not merged further once there are that many of them.
+    #[default(1)]
+    pub min_num_shards: NumShards,
+}
+
+/// Load of a shard recorded per block and shard to split shards by load. It is only derived from
+/// data in consensus, i.e. chunk headers and state roots of the canonical chain, so that all nodes
+/// agree on how shards are split.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct ShardLoad {
+    /// Gas used as reported by the chunk header of the shard included in the block. `None` if the
+    /// block has no new chunk for the shard.
+    pub gas_used: Option<Gas>,
+    /// State of the shard as of the end of the previous epoch. Only recorded for the first block
+    /// of an epoch.
+    pub state: Option<ShardStateLoad>,
+}
+
+/// Size of the state of a shard and the account it would be split at, computed once per epoch.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct ShardStateLoad {
+    /// Size of the state, as accounted by trie memory usage.
+    pub state_size: u64,
+    /// Account of the key in the middle of the state, by memory usage. `None` if the state has no
+    /// account keys.
+    pub median_account_id: Option<AccountId>,
+}
+
+/// Shard config of an epoch chosen by dynamic resharding, along with the per shard tracking it
+/// was chosen from.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct EpochShardConfig {
+    pub shard_config: ShardConfig,
+    /// Number of epochs in a row each shard has been overloaded for, as of the end of the epoch
+    /// in which this config was chosen, i.e. two epochs before the epoch it applies to. Indexed
+    /// by shard ids of the layout of that epoch.
+    pub num_overloaded_epochs: Vec<EpochHeight>,
+    /// Number of epochs in a row each shard has been underloaded for, indexed like
+    /// `num_overloaded_epochs`.
+    pub num_underloaded_epochs: Vec<EpochHeight>,
+}
+
 /// Additional configuration parameters for the new validator selection
 /// algorithm.  See <https://github.com/near/NEPs/pull/167> for details.
 #[derive(Debug, Clone, SmartDefault, PartialEq, Eq)]
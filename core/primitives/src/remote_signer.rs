@@ -0,0 +1,308 @@
+//! Protocol used by the node to sign with a validator key kept by a separate signer process.
+//!
+//! The node connects to the signer over a unix socket and sends `SignerRequest`s, each answered
+//! with one `SignerResponse`. Messages are borsh encoded and prefixed with their length as a
+//! little endian `u32`.
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use tracing::error;
+
+use near_crypto::{PublicKey, Signature};
+
+use crate::block::BlockHeader;
+use crate::block_header::ApprovalInner;
+use crate::challenge::ChallengeBody;
+use crate::hash::{hash, CryptoHash};
+use crate::network::PeerId;
+use crate::sharding::ShardChunkHeader;
+use crate::telemetry::TelemetryInfo;
+use crate::types::{AccountId, BlockHeight, EpochId};
+use crate::validator_signer::{ValidatorSigner, ValidatorSignerError};
+
+/// Version of the protocol, checked when the node connects to the signer.
+pub const REMOTE_SIGNER_PROTOCOL_VERSION: u32 = 2;
+
+/// Maximum size of a single message. Challenges carry chunk proofs, so they can be large.
+const MAX_MESSAGE_SIZE: usize = 64 * bytesize::MIB as usize;
+
+/// Requests carry the data to sign rather than its hash, so that the signer knows what it signs:
+/// it checks blocks, chunks and approvals against its slashing protection and prefixes or hashes
+/// everything else so that the signature can't be taken for one of a block, chunk or approval.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
+pub enum SignerRequest {
+    /// Returns the account id and public key of the validator key held by the signer.
+    GetValidatorKey {
+        protocol_version: u32,
+    },
+    SignBlockHeader {
+        prev_hash: CryptoHash,
+        inner_lite: Vec<u8>,
+        inner_rest: Vec<u8>,
+    },
+    /// Signs the hash of the chunk header, which the signer computes itself. The signature of
+    /// the header is ignored.
+    SignChunkHeader {
+        header: ShardChunkHeader,
+    },
+    SignApproval {
+        inner: ApprovalInner,
+        target_height: BlockHeight,
+    },
+    SignChallenge {
+        challenge_body: ChallengeBody,
+    },
+    SignAccountAnnounce {
+        account_id: AccountId,
+        peer_id: PeerId,
+        epoch_id: EpochId,
+    },
+    /// Signs serialized telemetry info, which must be a JSON object. The signed data is prefixed,
+    /// see `TelemetryInfo::get_data_for_sig`.
+    SignTelemetry {
+        content: String,
+    },
+    ComputeVrfWithProof {
+        data: Vec<u8>,
+    },
+    /// Signs state witness of a chunk with given hash. The signed data is prefixed, see
+    /// `ChunkStateWitness::get_data_for_sig`.
+    SignChunkStateWitness {
+        witness_hash: CryptoHash,
+    },
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
+pub enum SignerResponse {
+    ValidatorKey {
+        account_id: AccountId,
+        public_key: PublicKey,
+    },
+    Signature(Signature),
+    VrfWithProof {
+        value: near_crypto::vrf::Value,
+        proof: near_crypto::vrf::Proof,
+    },
+    /// The signer refused to sign, e.g. because it would be a double sign.
+    Refused(String),
+}
+
+/// Writes a length prefixed borsh message.
+pub fn write_message<T: BorshSerialize>(writer: &mut impl Write, message: &T) -> io::Result<()> {
+    let bytes = message.try_to_vec()?;
+    if bytes.len() > MAX_MESSAGE_SIZE {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "message is too large"));
+    }
+    writer.write_all(&(bytes.len() as u32).to_le_bytes())?;
+    writer.write_all(&bytes)?;
+    writer.flush()
+}
+
+/// Reads a length prefixed borsh message.
+pub fn read_message<T: BorshDeserialize>(reader: &mut impl Read) -> io::Result<T> {
+    let mut len = [0u8; 4];
+    reader.read_exact(&mut len)?;
+    let len = u32::from_le_bytes(len) as usize;
+    if len > MAX_MESSAGE_SIZE {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "message is too large"));
+    }
+    let mut bytes = vec![0u8; len];
+    reader.read_exact(&mut bytes)?;
+    T::try_from_slice(&bytes)
+}
+
+/// Validator signer that forwards everything to a signer process listening on a unix socket.
+///
+/// If the signer is unreachable or refuses to sign, e.g. because it would be a double sign, the
+/// error is returned to the caller, which then doesn't produce the block, chunk or approval.
+#[cfg(unix)]
+pub struct RemoteValidatorSigner {
+    account_id: AccountId,
+    public_key: PublicKey,
+    socket_path: PathBuf,
+    timeout: Duration,
+    stream: Mutex<Option<std::os::unix::net::UnixStream>>,
+}
+
+#[cfg(unix)]
+impl RemoteValidatorSigner {
+    /// Connects to the signer and fetches the validator key it holds.
+    pub fn connect(socket_path: &Path, timeout: Duration) -> io::Result<Self> {
+        let mut signer = Self {
+            account_id: "remote-signer".parse().unwrap(),
+            public_key: PublicKey::empty(near_crypto::KeyType::ED25519),
+            socket_path: socket_path.to_path_buf(),
+            timeout,
+            stream: Mutex::new(None),
+        };
+        match signer.request(&SignerRequest::GetValidatorKey {
+            protocol_version: REMOTE_SIGNER_PROTOCOL_VERSION,
+        })? {
+            SignerResponse::ValidatorKey { account_id, public_key } => {
+                signer.account_id = account_id;
+                signer.public_key = public_key;
+                Ok(signer)
+            }
+            response => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unexpected response from remote signer: {:?}", response),
+            )),
+        }
+    }
+
+    fn open_stream(&self) -> io::Result<std::os::unix::net::UnixStream> {
+        let stream = std::os::unix::net::UnixStream::connect(&self.socket_path)?;
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+        Ok(stream)
+    }
+
+    fn exchange(
+        stream: &mut std::os::unix::net::UnixStream,
+        request: &SignerRequest,
+    ) -> io::Result<SignerResponse> {
+        write_message(stream, request)?;
+        read_message(stream)
+    }
+
+    /// Sends the request over the current connection. The signer may have closed it in the
+    /// meantime, so on failure the request is retried once over a new connection. Retrying a
+    /// signing request is safe, the signer signs the same data again.
+    fn request(&self, request: &SignerRequest) -> io::Result<SignerResponse> {
+        let mut stream = self.stream.lock().expect("remote signer lock poisoned");
+        if let Some(current) = stream.as_mut() {
+            match Self::exchange(current, request) {
+                Ok(response) => return Ok(response),
+                Err(_) => *stream = None,
+            }
+        }
+        let mut new_stream = self.open_stream()?;
+        let response = Self::exchange(&mut new_stream, request)?;
+        *stream = Some(new_stream);
+        Ok(response)
+    }
+
+    /// Sends the request, turning any response other than the expected one into an error.
+    fn request_checked<T>(
+        &self,
+        request: SignerRequest,
+        expected: impl FnOnce(SignerResponse) -> Result<T, SignerResponse>,
+    ) -> Result<T, ValidatorSignerError> {
+        match self.request(&request) {
+            Ok(SignerResponse::Refused(reason)) => {
+                error!(target: "remote_signer", "Remote signer refused to sign {:?}: {}", request, reason);
+                Err(ValidatorSignerError::Refused(reason))
+            }
+            Ok(response) => expected(response).map_err(|response| {
+                error!(target: "remote_signer", "Unexpected response from remote signer: {:?}", response);
+                ValidatorSignerError::Unavailable(format!("Unexpected response {:?}", response))
+            }),
+            Err(err) => {
+                error!(target: "remote_signer", "Failed to reach remote signer: {}", err);
+                Err(ValidatorSignerError::Unavailable(err.to_string()))
+            }
+        }
+    }
+
+    fn request_signature(&self, request: SignerRequest) -> Result<Signature, ValidatorSignerError> {
+        self.request_checked(request, |response| match response {
+            SignerResponse::Signature(signature) => Ok(signature),
+            response => Err(response),
+        })
+    }
+}
+
+#[cfg(unix)]
+impl ValidatorSigner for RemoteValidatorSigner {
+    fn validator_id(&self) -> &AccountId {
+        &self.account_id
+    }
+
+    fn public_key(&self) -> PublicKey {
+        self.public_key.clone()
+    }
+
+    fn sign_telemetry(&self, info: &TelemetryInfo) -> Result<Signature, ValidatorSignerError> {
+        let content = serde_json::to_string(info).expect("Telemetry must serialize to JSON");
+        self.request_signature(SignerRequest::SignTelemetry { content })
+    }
+
+    fn sign_block_header_parts(
+        &self,
+        prev_hash: CryptoHash,
+        inner_lite: &[u8],
+        inner_rest: &[u8],
+    ) -> Result<(CryptoHash, Signature), ValidatorSignerError> {
+        let hash = BlockHeader::compute_hash(prev_hash, inner_lite, inner_rest);
+        let signature = self.request_signature(SignerRequest::SignBlockHeader {
+            prev_hash,
+            inner_lite: inner_lite.to_vec(),
+            inner_rest: inner_rest.to_vec(),
+        })?;
+        Ok((hash, signature))
+    }
+
+    fn sign_chunk_header(
+        &self,
+        header: &ShardChunkHeader,
+    ) -> Result<Signature, ValidatorSignerError> {
+        self.request_signature(SignerRequest::SignChunkHeader { header: header.clone() })
+    }
+
+    fn sign_chunk_state_witness(
+        &self,
+        witness_hash: &CryptoHash,
+    ) -> Result<Signature, ValidatorSignerError> {
+        self.request_signature(SignerRequest::SignChunkStateWitness { witness_hash: *witness_hash })
+    }
+
+    fn sign_approval(
+        &self,
+        inner: &ApprovalInner,
+        target_height: BlockHeight,
+    ) -> Result<Signature, ValidatorSignerError> {
+        self.request_signature(SignerRequest::SignApproval { inner: inner.clone(), target_height })
+    }
+
+    fn sign_challenge(
+        &self,
+        challenge_body: &ChallengeBody,
+    ) -> Result<(CryptoHash, Signature), ValidatorSignerError> {
+        let hash = hash(&challenge_body.try_to_vec().expect("Failed to serialize"));
+        let signature = self.request_signature(SignerRequest::SignChallenge {
+            challenge_body: challenge_body.clone(),
+        })?;
+        Ok((hash, signature))
+    }
+
+    fn sign_account_announce(
+        &self,
+        account_id: &AccountId,
+        peer_id: &PeerId,
+        epoch_id: &EpochId,
+    ) -> Result<Signature, ValidatorSignerError> {
+        self.request_signature(SignerRequest::SignAccountAnnounce {
+            account_id: account_id.clone(),
+            peer_id: peer_id.clone(),
+            epoch_id: epoch_id.clone(),
+        })
+    }
+
+    fn compute_vrf_with_proof(
+        &self,
+        data: &[u8],
+    ) -> Result<(near_crypto::vrf::Value, near_crypto::vrf::Proof), ValidatorSignerError> {
+        let request = SignerRequest::ComputeVrfWithProof { data: data.to_vec() };
+        self.request_checked(request, |response| match response {
+            SignerResponse::VrfWithProof { value, proof } => Ok((value, proof)),
+            response => Err(response),
+        })
+    }
+
+    fn write_to_file(&self, _path: &Path) -> std::io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Other, "Remote signer keys can't be written to a file"))
+    }
+}
@@ -55,6 +55,22 @@ impl RuntimeConfigStore {
             store.insert(42, Arc::new(config));
         }
 
+        #[cfg(feature = "protocol_feature_wasmer2_middleware_metering")]
+        {
+            let protocol_version =
+                crate::version::ProtocolFeature::Wasmer2MiddlewareMetering.protocol_version();
+            let mut config = store
+                .range((Bound::Unbounded, Bound::Included(protocol_version)))
+                .next_back()
+                .unwrap()
+                .1
+                .as_ref()
+                .clone();
+            config.wasm_config.limit_config.gas_metering =
+                near_primitives_core::config::GasMetering::Middleware;
+            store.insert(protocol_version, Arc::new(config));
+        }
+
         Self { store }
     }
 
@@ -151,6 +167,24 @@ If you add new config version, add a missing hash to the end of `expected_hashes
         }
     }
 
+    #[test]
+    #[cfg(feature = "protocol_feature_wasmer2_middleware_metering")]
+    fn test_wasmer2_middleware_metering() {
+        use crate::version::ProtocolFeature::Wasmer2MiddlewareMetering;
+        use near_primitives_core::config::GasMetering;
+
+        let store = RuntimeConfigStore::new(None);
+        let version = Wasmer2MiddlewareMetering.protocol_version();
+        let base_cfg = store.get_config(version - 1);
+        let new_cfg = store.get_config(version);
+        assert_eq!(base_cfg.wasm_config.limit_config.gas_metering, GasMetering::Instrumentation);
+        assert_eq!(new_cfg.wasm_config.limit_config.gas_metering, GasMetering::Middleware);
+        // Only the way gas is charged changes, not the costs.
+        let mut cfg = new_cfg.as_ref().clone();
+        cfg.wasm_config.limit_config.gas_metering = GasMetering::Instrumentation;
+        assert_eq!(&cfg, base_cfg.as_ref());
+    }
+
     #[test]
     fn test_lower_storage_cost() {
         let store = RuntimeConfigStore::new(None);
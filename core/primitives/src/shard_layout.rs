@@ -1,5 +1,6 @@
-use std::cmp::Ordering::Greater;
+use std::cmp::Ordering::{self, Greater};
 
+use borsh::{BorshDeserialize, BorshSerialize};
 use byteorder::{LittleEndian, ReadBytesExt};
 use serde::{Deserialize, Serialize};
 
@@ -34,9 +35,11 @@ use std::collections::HashMap;
 /// which shards from the previous shard layout split to which shards in the following shard layout.
 /// If shard A in shard layout 0 splits to shard B and C in shard layout 1,
 /// we call shard A the parent shard of shard B and C.
-/// Note that a shard can only have one parent shard. For example, the following case will be prohibited,
-/// a shard C in shard layout 1 contains accounts in both shard A and B in shard layout 0.
-/// Parent/split shard information can be accessed through these two functions.
+/// Shards can also be merged: if shards A and B in shard layout 0 merge to shard C in shard layout 1,
+/// both A and B are parent shards of C. A shard merged from several parents must be the only shard
+/// its parents map to, that is a shard can't be split and merged at the same time.
+/// Parent/split shard information can be accessed through these two functions, and
+/// `get_parent_shard_ids` returns all parents of merged shards.
 ///
 /// `account_id_to_shard_id`
 ///  Maps an account to the shard that it belongs to given a shard_layout
@@ -53,7 +56,7 @@ use std::collections::HashMap;
 
 pub type ShardVersion = u32;
 
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
 pub enum ShardLayout {
     V0(ShardLayoutV0),
     V1(ShardLayoutV1),
@@ -64,7 +67,7 @@ pub enum ShardLayout {
 /// to keep backward compatibility for some existing tests.
 /// `parent_shards` for `ShardLayoutV1` is always `None`, meaning it can only be the first shard layout
 /// a chain uses.
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
 pub struct ShardLayoutV0 {
     /// Map accounts evenly across all shards
     num_shards: NumShards,
@@ -78,7 +81,7 @@ pub struct ShardLayoutV0 {
 /// will be `[[0, 1, 2, 3]]`
 type ShardSplitMap = Vec<Vec<ShardId>>;
 
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
 pub struct ShardLayoutV1 {
     /// num_shards = fixed_shards.len() + boundary_accounts.len() + 1
     /// Each account and all sub-accounts map to the shard of position in this array.
@@ -89,7 +92,7 @@ pub struct ShardLayoutV1 {
     /// Useful for constructing states for the shards.
     /// None for the genesis shard layout
     shards_split_map: Option<ShardSplitMap>,
-    /// Maps shard in this shard layout to their parent shard
+    /// Maps shard in this shard layout to their parent shard, the first one for merged shards
     /// Since shard_ids always range from 0 to num_shards - 1, we use vec instead of a hashmap
     to_parent_shard_map: Option<Vec<ShardId>>,
     /// Version of the shard layout, this is useful for uniquely identify the shard layout
@@ -98,7 +101,18 @@ pub struct ShardLayoutV1 {
 
 #[derive(Debug)]
 pub enum ShardLayoutError {
-    InvalidShardIdError { shard_id: ShardId },
+    InvalidShardIdError {
+        shard_id: ShardId,
+    },
+    /// The shard can't be split at the boundary account, see `ShardLayout::split_shard`.
+    InvalidSplitError {
+        shard_id: ShardId,
+        boundary_account: AccountId,
+    },
+    /// The shard can't be merged with the next one, see `ShardLayout::merge_shards`.
+    InvalidMergeError {
+        shard_id: ShardId,
+    },
 }
 
 impl ShardLayout {
@@ -124,12 +138,23 @@ impl ShardLayout {
             let num_shards = (fixed_shards.len() + boundary_accounts.len() + 1) as NumShards;
             for (parent_shard_id, shard_ids) in shards_split_map.iter().enumerate() {
                 for &shard_id in shard_ids {
-                    let prev = to_parent_shard_map.insert(shard_id, parent_shard_id as ShardId);
-                    assert!(prev.is_none(), "no shard should appear in the map twice");
                     assert!(shard_id < num_shards, "shard id should be valid");
+                    // A shard appears several times in the map if it is merged from several
+                    // parents, in which case it must be the only shard of each of them.
+                    if let Some(&prev) = to_parent_shard_map.get(&shard_id) {
+                        assert!(
+                            shard_ids.len() == 1 && shards_split_map[prev].len() == 1,
+                            "shard {} is both split and merged",
+                            shard_id
+                        );
+                    } else {
+                        to_parent_shard_map.insert(shard_id, parent_shard_id);
+                    }
                 }
             }
-            Some((0..num_shards).map(|shard_id| to_parent_shard_map[&shard_id]).collect())
+            Some(
+                (0..num_shards).map(|shard_id| to_parent_shard_map[&shard_id] as ShardId).collect(),
+            )
         } else {
             None
         };
@@ -152,6 +177,89 @@ impl ShardLayout {
         )
     }
 
+    /// Returns the shard layout that follows this one, in which shard `shard_id` is split in two at
+    /// `boundary_account`: accounts of the shard lower than the boundary account stay in the first
+    /// child and the rest go to the second one. Shards after the split shard are shifted by one.
+    /// Only shards holding a range of accounts can be split, that is neither fixed shards nor
+    /// shards of a V0 layout with more than one shard, and the boundary account must be strictly
+    /// inside the range of the shard.
+    pub fn split_shard(
+        &self,
+        shard_id: ShardId,
+        boundary_account: AccountId,
+    ) -> Result<ShardLayout, ShardLayoutError> {
+        let (fixed_shards, boundary_accounts) = match self {
+            Self::V0(v0) if v0.num_shards == 1 => (&[][..], &[][..]),
+            Self::V0(_) => {
+                return Err(ShardLayoutError::InvalidSplitError { shard_id, boundary_account })
+            }
+            Self::V1(v1) => (&v1.fixed_shards[..], &v1.boundary_accounts[..]),
+        };
+        let num_fixed_shards = fixed_shards.len() as ShardId;
+        if shard_id < num_fixed_shards || shard_id >= self.num_shards() {
+            return Err(ShardLayoutError::InvalidSplitError { shard_id, boundary_account });
+        }
+        // The shard holds accounts in [boundary_accounts[index - 1], boundary_accounts[index]),
+        // without a lower bound for the first range and an upper bound for the last one.
+        let index = (shard_id - num_fixed_shards) as usize;
+        let above_lower = index == 0 || boundary_accounts[index - 1] < boundary_account;
+        let below_upper =
+            index == boundary_accounts.len() || boundary_account < boundary_accounts[index];
+        if !above_lower || !below_upper {
+            return Err(ShardLayoutError::InvalidSplitError { shard_id, boundary_account });
+        }
+
+        let mut new_boundary_accounts = boundary_accounts.to_vec();
+        new_boundary_accounts.insert(index, boundary_account);
+        let shards_split_map = (0..self.num_shards())
+            .map(|parent_shard_id| match parent_shard_id.cmp(&shard_id) {
+                Ordering::Less => vec![parent_shard_id],
+                Ordering::Equal => vec![shard_id, shard_id + 1],
+                Ordering::Greater => vec![parent_shard_id + 1],
+            })
+            .collect();
+        Ok(ShardLayout::v1(
+            fixed_shards.to_vec(),
+            new_boundary_accounts,
+            Some(shards_split_map),
+            self.version() + 1,
+        ))
+    }
+
+    /// Returns the shard layout that follows this one, in which shard `shard_id` is merged with the
+    /// next shard. Shards after the merged shards are shifted back by one. Only two adjacent shards
+    /// holding ranges of accounts can be merged, that is neither fixed shards nor shards of a V0
+    /// layout.
+    pub fn merge_shards(&self, shard_id: ShardId) -> Result<ShardLayout, ShardLayoutError> {
+        let (fixed_shards, boundary_accounts) = match self {
+            Self::V0(_) => return Err(ShardLayoutError::InvalidMergeError { shard_id }),
+            Self::V1(v1) => (&v1.fixed_shards[..], &v1.boundary_accounts[..]),
+        };
+        let num_fixed_shards = fixed_shards.len() as ShardId;
+        if shard_id < num_fixed_shards || shard_id + 1 >= self.num_shards() {
+            return Err(ShardLayoutError::InvalidMergeError { shard_id });
+        }
+        // The boundary account between the two shards is removed.
+        let index = (shard_id - num_fixed_shards) as usize;
+        let mut new_boundary_accounts = boundary_accounts.to_vec();
+        new_boundary_accounts.remove(index);
+        let shards_split_map = (0..self.num_shards())
+            .map(|parent_shard_id| {
+                if parent_shard_id <= shard_id {
+                    vec![parent_shard_id]
+                } else {
+                    vec![parent_shard_id - 1]
+                }
+            })
+            .collect();
+        Ok(ShardLayout::v1(
+            fixed_shards.to_vec(),
+            new_boundary_accounts,
+            Some(shards_split_map),
+            self.version() + 1,
+        ))
+    }
+
     /// Given a parent shard id, return the shard uids for the shards in the current shard layout that
     /// are split from this parent shard. If this shard layout has no parent shard layout, return None
     pub fn get_split_shard_uids(&self, parent_shard_id: ShardId) -> Option<Vec<ShardUId>> {
@@ -172,7 +280,8 @@ impl ShardLayout {
         }
     }
 
-    /// Return the parent shard id for a given shard in the shard layout
+    /// Return the parent shard id for a given shard in the shard layout, the first parent shard
+    /// for a shard merged from several shards
     /// Only calls this function for shard layout that has parent shard layouts
     /// Returns error if `shard_id` is an invalid shard id in the current layout
     /// Panics if `self` has no parent shard layout
@@ -192,6 +301,53 @@ impl ShardLayout {
         Ok(parent_shard_id)
     }
 
+    /// Return all parent shard ids for a given shard in the shard layout, in increasing order.
+    /// There is more than one parent only for shards merged from several shards.
+    /// Only calls this function for shard layout that has parent shard layouts
+    /// Returns error if `shard_id` is an invalid shard id in the current layout
+    /// Panics if `self` has no parent shard layout
+    pub fn get_parent_shard_ids(
+        &self,
+        shard_id: ShardId,
+    ) -> Result<Vec<ShardId>, ShardLayoutError> {
+        if shard_id >= self.num_shards() {
+            return Err(ShardLayoutError::InvalidShardIdError { shard_id });
+        }
+        match self {
+            Self::V0(_) => panic!("shard layout has no parent shard"),
+            Self::V1(v1) => match &v1.shards_split_map {
+                Some(shards_split_map) => Ok(shards_split_map
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, shard_ids)| shard_ids.contains(&shard_id))
+                    .map(|(parent_shard_id, _)| parent_shard_id as ShardId)
+                    .collect()),
+                None => panic!("shard_layout has no parent shard"),
+            },
+        }
+    }
+
+    /// Whether the given shard is merged from several shards of the last shard layout.
+    /// Returns false if this shard layout has no parent shard layout.
+    pub fn is_merged_shard(&self, shard_id: ShardId) -> bool {
+        match self {
+            Self::V0(_) => false,
+            Self::V1(v1) => v1.shards_split_map.as_ref().map_or(false, |shards_split_map| {
+                shards_split_map.iter().filter(|shard_ids| shard_ids.contains(&shard_id)).count()
+                    > 1
+            }),
+        }
+    }
+
+    /// Whether the given shard of the last shard layout is merged with other shards into a shard
+    /// of this shard layout. Returns false if this shard layout has no parent shard layout.
+    pub fn merges_parent_shard(&self, parent_shard_id: ShardId) -> bool {
+        match self.get_split_shard_ids(parent_shard_id).as_deref() {
+            Some(&[shard_id]) => self.is_merged_shard(shard_id),
+            _ => false,
+        }
+    }
+
     #[inline]
     pub fn version(&self) -> ShardVersion {
         match self {
@@ -332,7 +488,7 @@ pub fn get_block_shard_uid_rev(
 
 #[cfg(test)]
 mod tests {
-    use crate::shard_layout::{account_id_to_shard_id, ShardLayout, ShardUId};
+    use crate::shard_layout::{account_id_to_shard_id, ShardLayout, ShardLayoutError, ShardUId};
     use rand::distributions::Alphanumeric;
     use rand::rngs::StdRng;
     use rand::{Rng, SeedableRng};
@@ -398,4 +554,114 @@ mod tests {
         assert_eq!(account_id_to_shard_id(&"goo".parse().unwrap(), &shard_layout), 6);
         assert_eq!(account_id_to_shard_id(&"zoo".parse().unwrap(), &shard_layout), 7);
     }
+
+    #[test]
+    fn test_split_shard() {
+        let shard_layout = ShardLayout::v0_single_shard()
+            .split_shard(0, "foo".parse().unwrap())
+            .unwrap()
+            .split_shard(1, "paz".parse().unwrap())
+            .unwrap()
+            .split_shard(0, "abc".parse().unwrap())
+            .unwrap();
+        assert_eq!(
+            shard_layout,
+            ShardLayout::v1(
+                vec![],
+                vec!["abc", "foo", "paz"].into_iter().map(|s| s.parse().unwrap()).collect(),
+                Some(vec![vec![0, 1], vec![2], vec![3]]),
+                3,
+            )
+        );
+        for x in 0..4 {
+            let parent_shard_id = if x < 2 { 0 } else { x - 1 };
+            assert_eq!(shard_layout.get_parent_shard_id(x).unwrap(), parent_shard_id);
+        }
+
+        assert!(matches!(
+            shard_layout.split_shard(1, "abc".parse().unwrap()),
+            Err(ShardLayoutError::InvalidSplitError { shard_id: 1, .. })
+        ));
+        assert!(matches!(
+            shard_layout.split_shard(1, "goo".parse().unwrap()),
+            Err(ShardLayoutError::InvalidSplitError { shard_id: 1, .. })
+        ));
+        assert!(matches!(
+            shard_layout.split_shard(4, "zoo".parse().unwrap()),
+            Err(ShardLayoutError::InvalidSplitError { shard_id: 4, .. })
+        ));
+        assert!(matches!(
+            ShardLayout::v0(2, 0).split_shard(0, "foo".parse().unwrap()),
+            Err(ShardLayoutError::InvalidSplitError { shard_id: 0, .. })
+        ));
+        assert!(matches!(
+            ShardLayout::v1_test().split_shard(0, "foo".parse().unwrap()),
+            Err(ShardLayoutError::InvalidSplitError { shard_id: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn test_merge_shards() {
+        let shard_layout = ShardLayout::v1(
+            vec!["aurora".parse().unwrap()],
+            vec!["abc", "foo", "paz"].into_iter().map(|s| s.parse().unwrap()).collect(),
+            None,
+            1,
+        );
+        let merged_shard_layout = shard_layout.merge_shards(2).unwrap();
+        assert_eq!(
+            merged_shard_layout,
+            ShardLayout::v1(
+                vec!["aurora".parse().unwrap()],
+                vec!["abc", "paz"].into_iter().map(|s| s.parse().unwrap()).collect(),
+                Some(vec![vec![0], vec![1], vec![2], vec![2], vec![3]]),
+                2,
+            )
+        );
+        assert_eq!(merged_shard_layout.get_parent_shard_ids(2).unwrap(), vec![2, 3]);
+        assert_eq!(merged_shard_layout.get_parent_shard_id(2).unwrap(), 2);
+        assert_eq!(merged_shard_layout.get_parent_shard_ids(3).unwrap(), vec![4]);
+        assert!(merged_shard_layout.is_merged_shard(2));
+        assert!(!merged_shard_layout.is_merged_shard(3));
+        assert!(merged_shard_layout.merges_parent_shard(3));
+        assert!(!merged_shard_layout.merges_parent_shard(4));
+        assert!(!shard_layout.is_merged_shard(2));
+        assert!(!shard_layout.merges_parent_shard(2));
+        for account_id in ["bbb", "foo", "goo"] {
+            assert_eq!(
+                account_id_to_shard_id(&account_id.parse().unwrap(), &merged_shard_layout),
+                2
+            );
+        }
+        assert_eq!(account_id_to_shard_id(&"zoo".parse().unwrap(), &merged_shard_layout), 3);
+
+        // Merging reverts a split.
+        let split_shard_layout =
+            merged_shard_layout.split_shard(2, "foo".parse().unwrap()).unwrap();
+        assert_eq!(split_shard_layout.merge_shards(2).unwrap().num_shards(), 4);
+
+        assert!(matches!(
+            shard_layout.merge_shards(0),
+            Err(ShardLayoutError::InvalidMergeError { shard_id: 0 })
+        ));
+        assert!(matches!(
+            shard_layout.merge_shards(4),
+            Err(ShardLayoutError::InvalidMergeError { shard_id: 4 })
+        ));
+        assert!(matches!(
+            ShardLayout::v0(2, 0).merge_shards(0),
+            Err(ShardLayoutError::InvalidMergeError { shard_id: 0 })
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "is both split and merged")]
+    fn test_split_and_merge_shard() {
+        ShardLayout::v1(
+            vec![],
+            vec!["abc", "foo"].into_iter().map(|s| s.parse().unwrap()).collect(),
+            Some(vec![vec![0, 1], vec![1, 2]]),
+            1,
+        );
+    }
 }
@@ -4,13 +4,15 @@ use serde::{Deserialize, Serialize};
 
 use near_crypto::Signature;
 
+use crate::challenge::PartialState;
 use crate::hash::{hash, CryptoHash};
 use crate::merkle::{combine_hash, merklize, MerklePath};
 use crate::receipt::Receipt;
+use crate::syncing::{ReceiptProofResponse, RootProof};
 use crate::transaction::SignedTransaction;
 use crate::types::validator_stake::{ValidatorStake, ValidatorStakeIter, ValidatorStakeV1};
 use crate::types::{Balance, BlockHeight, Gas, MerkleHash, ShardId, StateRoot};
-use crate::validator_signer::ValidatorSigner;
+use crate::validator_signer::{ValidatorSigner, ValidatorSignerError};
 use crate::version::{
     ProtocolFeature, ProtocolVersion, ProtocolVersionRange, SHARD_CHUNK_HEADER_UPGRADE_VERSION,
 };
@@ -126,7 +128,7 @@ impl ShardChunkHeaderV2 {
         tx_root: CryptoHash,
         validator_proposals: Vec<ValidatorStakeV1>,
         signer: &dyn ValidatorSigner,
-    ) -> Self {
+    ) -> Result<Self, ValidatorSignerError> {
         let inner = ShardChunkHeaderInnerV1 {
             prev_block_hash,
             prev_state_root,
@@ -143,8 +145,9 @@ impl ShardChunkHeaderV2 {
             validator_proposals,
         };
         let hash = Self::compute_hash(&inner);
-        let signature = signer.sign_chunk_hash(&hash);
-        Self { inner, height_included: 0, signature, hash }
+        let mut header = Self { inner, height_included: 0, signature: Signature::default(), hash };
+        header.signature = signer.sign_chunk_header(&ShardChunkHeader::V2(header.clone()))?;
+        Ok(header)
     }
 }
 
@@ -191,7 +194,7 @@ impl ShardChunkHeaderV3 {
         tx_root: CryptoHash,
         validator_proposals: Vec<ValidatorStake>,
         signer: &dyn ValidatorSigner,
-    ) -> Self {
+    ) -> Result<Self, ValidatorSignerError> {
         let inner = ShardChunkHeaderInner::V2(ShardChunkHeaderInnerV2 {
             prev_block_hash,
             prev_state_root,
@@ -208,8 +211,9 @@ impl ShardChunkHeaderV3 {
             validator_proposals,
         });
         let hash = Self::compute_hash(&inner);
-        let signature = signer.sign_chunk_hash(&hash);
-        Self { inner, height_included: 0, signature, hash }
+        let mut header = Self { inner, height_included: 0, signature: Signature::default(), hash };
+        header.signature = signer.sign_chunk_header(&ShardChunkHeader::V3(header.clone()))?;
+        Ok(header)
     }
 }
 
@@ -443,7 +447,7 @@ impl ShardChunkHeaderV1 {
         tx_root: CryptoHash,
         validator_proposals: Vec<ValidatorStakeV1>,
         signer: &dyn ValidatorSigner,
-    ) -> Self {
+    ) -> Result<Self, ValidatorSignerError> {
         let inner = ShardChunkHeaderInnerV1 {
             prev_block_hash,
             prev_state_root,
@@ -460,8 +464,9 @@ impl ShardChunkHeaderV1 {
             validator_proposals,
         };
         let hash = Self::compute_hash(&inner);
-        let signature = signer.sign_chunk_hash(&hash);
-        Self { inner, height_included: 0, signature, hash }
+        let mut header = Self { inner, height_included: 0, signature: Signature::default(), hash };
+        header.signature = signer.sign_chunk_header(&ShardChunkHeader::V1(header.clone()))?;
+        Ok(header)
     }
 }
 
@@ -772,6 +777,61 @@ impl ShardChunk {
     }
 }
 
+/// Everything needed to apply a chunk without holding the state of its shard. Produced by the
+/// chunk producer after the block with the chunk is processed, so that nodes which don't track
+/// the shard can validate the post-state committed to by the next chunk.
+#[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Eq, PartialEq)]
+pub struct ChunkStateWitnessInner {
+    /// Hash of the block that includes the chunk.
+    pub block_hash: CryptoHash,
+    pub shard_id: ShardId,
+    pub chunk_hash: ChunkHash,
+    /// Transactions of the chunk, checked against the `tx_root` of its header.
+    pub transactions: Vec<SignedTransaction>,
+    /// Outgoing receipts of the chunk, checked against the `outgoing_receipts_root` of its header.
+    pub receipts: Vec<Receipt>,
+    /// Trie nodes read while applying the chunk.
+    pub partial_state: PartialState,
+    /// Receipts applied together with the chunk, one entry per block starting from the block
+    /// that includes the chunk and down to the block with the previous chunk of the shard, excluded.
+    pub incoming_receipts: Vec<ReceiptProofResponse>,
+    /// Proofs that the receipts in `incoming_receipts` are included in their blocks.
+    pub root_proofs: Vec<Vec<RootProof>>,
+}
+
+impl ChunkStateWitnessInner {
+    pub fn hash(&self) -> CryptoHash {
+        hash(&self.try_to_vec().expect("Failed to serialize"))
+    }
+}
+
+const CHUNK_STATE_WITNESS_SIGNATURE_PREFIX: &[u8] = b"chunk_state_witness";
+
+/// State witness signed by the producer of its chunk.
+#[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Eq, PartialEq)]
+pub struct ChunkStateWitness {
+    pub inner: ChunkStateWitnessInner,
+    pub signature: Signature,
+}
+
+impl ChunkStateWitness {
+    pub fn new(
+        inner: ChunkStateWitnessInner,
+        signer: &dyn ValidatorSigner,
+    ) -> Result<Self, ValidatorSignerError> {
+        let signature = signer.sign_chunk_state_witness(&inner.hash())?;
+        Ok(Self { inner, signature })
+    }
+
+    /// Data signed for the witness with given hash. It's prefixed, so that the signature can't
+    /// be taken for one of a chunk hash, block hash or approval.
+    pub fn get_data_for_sig(witness_hash: &CryptoHash) -> Vec<u8> {
+        [CHUNK_STATE_WITNESS_SIGNATURE_PREFIX, witness_hash.as_ref()].concat()
+    }
+}
+
 #[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
 #[derive(Default, BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
 pub struct EncodedShardChunkBody {
@@ -1013,7 +1073,7 @@ impl EncodedShardChunk {
                 tx_root,
                 validator_proposals,
                 signer,
-            );
+            )?;
             let chunk = EncodedShardChunkV1 { header, content };
             Ok((Self::V1(chunk), merkle_paths))
         } else if block_header_v3_version.is_none()
@@ -1036,7 +1096,7 @@ impl EncodedShardChunk {
                 tx_root,
                 validator_proposals,
                 signer,
-            );
+            )?;
             let chunk = EncodedShardChunkV2 { header: ShardChunkHeader::V2(header), content };
             Ok((Self::V2(chunk), merkle_paths))
         } else {
@@ -1055,7 +1115,7 @@ impl EncodedShardChunk {
                 tx_root,
                 validator_proposals,
                 signer,
-            );
+            )?;
             let chunk = EncodedShardChunkV2 { header: ShardChunkHeader::V3(header), content };
             Ok((Self::V2(chunk), merkle_paths))
         }
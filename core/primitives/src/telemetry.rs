@@ -1,19 +1,25 @@
 //! Types for telemetry reporting. Can be received by any telemetry dashboard to display
 //! node count and their status across the network.
+use near_crypto::{PublicKey, Signature, Signer};
 use serde::{Deserialize, Serialize};
 
-use crate::types::BlockHeight;
+use crate::types::{BlockHeight, EpochHeight};
 
 use crate::types::AccountId;
 
-#[derive(Serialize, Deserialize, Debug)]
+/// Version of the telemetry schema, bumped whenever fields are changed or removed.
+pub const TELEMETRY_SCHEMA_VERSION: u32 = 1;
+
+const TELEMETRY_SIGNATURE_PREFIX: &[u8] = b"telemetry";
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct TelemetryAgentInfo {
     pub name: String,
     pub version: String,
     pub build: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct TelemetrySystemInfo {
     pub bandwidth_download: u64,
     pub bandwidth_upload: u64,
@@ -21,7 +27,7 @@ pub struct TelemetrySystemInfo {
     pub memory_usage: u64,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct TelemetryChainInfo {
     pub node_id: String,
     pub account_id: Option<AccountId>,
@@ -32,9 +38,167 @@ pub struct TelemetryChainInfo {
     pub num_peers: usize,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TelemetryValidatorInfo {
+    pub num_validators: usize,
+    pub epoch_height: EpochHeight,
+}
+
+/// Progress of header and block sync, empty once the node is synced.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct TelemetrySyncInfo {
+    pub current_height: Option<BlockHeight>,
+    pub highest_height: Option<BlockHeight>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct TelemetryInfo {
     pub agent: TelemetryAgentInfo,
     pub system: TelemetrySystemInfo,
     pub chain: TelemetryChainInfo,
+    /// Only known once the node is synced.
+    pub validator: Option<TelemetryValidatorInfo>,
+    pub sync: TelemetrySyncInfo,
+}
+
+impl TelemetryInfo {
+    /// Data signed with the validator key for given JSON of the info. It's prefixed, so that the
+    /// signature can't be taken for one of a block, chunk or approval.
+    pub fn get_data_for_sig(content: &str) -> Vec<u8> {
+        [TELEMETRY_SIGNATURE_PREFIX, content.as_bytes()].concat()
+    }
+}
+
+/// Report sent to all telemetry sinks: `TelemetryInfo` with the version of the schema and the time
+/// it was collected, signed with the key of the node.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TelemetryReport {
+    pub version: u32,
+    /// Unix time in milliseconds.
+    pub timestamp: u64,
+    #[serde(flatten)]
+    pub info: TelemetryInfo,
+    /// Signature of `info` with the validator key, if the node has one. See
+    /// `TelemetryInfo::get_data_for_sig` for the signed data.
+    #[serde(rename = "signature", default, skip_serializing_if = "Option::is_none")]
+    pub validator_signature: Option<Signature>,
+    pub node_public_key: PublicKey,
+    /// Signature with the node key of the JSON of the report without this field.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub node_signature: Option<Signature>,
+}
+
+impl TelemetryReport {
+    pub fn new(
+        info: TelemetryInfo,
+        timestamp: u64,
+        validator_signature: Option<Signature>,
+        node_signer: &dyn Signer,
+    ) -> Self {
+        let mut report = Self {
+            version: TELEMETRY_SCHEMA_VERSION,
+            timestamp,
+            info,
+            validator_signature,
+            node_public_key: node_signer.public_key(),
+            node_signature: None,
+        };
+        report.node_signature = Some(node_signer.sign(&report.signed_content()));
+        report
+    }
+
+    /// Checks the signature of the node key.
+    pub fn verify(&self) -> bool {
+        self.node_signature.as_ref().map_or(false, |signature| {
+            signature.verify(&self.signed_content(), &self.node_public_key)
+        })
+    }
+
+    /// Checks the signature of the validator key.
+    pub fn verify_validator_signature(&self, validator_public_key: &PublicKey) -> bool {
+        self.validator_signature.as_ref().map_or(false, |signature| {
+            let content =
+                serde_json::to_string(&self.info).expect("Telemetry must serialize to JSON");
+            signature.verify(&TelemetryInfo::get_data_for_sig(&content), validator_public_key)
+        })
+    }
+
+    fn signed_content(&self) -> Vec<u8> {
+        let unsigned = Self { node_signature: None, ..self.clone() };
+        serde_json::to_vec(&unsigned).expect("Telemetry must serialize to JSON")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validator_signer::{InMemoryValidatorSigner, ValidatorSigner};
+    use near_crypto::{InMemorySigner, KeyType};
+
+    fn info() -> TelemetryInfo {
+        TelemetryInfo {
+            agent: TelemetryAgentInfo {
+                name: "near-rs".to_string(),
+                version: "trunk".to_string(),
+                build: "test".to_string(),
+            },
+            system: TelemetrySystemInfo {
+                bandwidth_download: 1,
+                bandwidth_upload: 2,
+                cpu_usage: 12.5,
+                memory_usage: 1024,
+            },
+            chain: TelemetryChainInfo {
+                node_id: "node".to_string(),
+                account_id: None,
+                is_validator: false,
+                status: "NoSync".to_string(),
+                latest_block_hash: "hash".to_string(),
+                latest_block_height: 10,
+                num_peers: 3,
+            },
+            validator: Some(TelemetryValidatorInfo { num_validators: 4, epoch_height: 2 }),
+            sync: TelemetrySyncInfo::default(),
+        }
+    }
+
+    #[test]
+    fn test_telemetry_report_signature() {
+        let signer = InMemorySigner::from_seed("node".parse().unwrap(), KeyType::ED25519, "node");
+        let report = TelemetryReport::new(info(), 1000, None, &signer);
+        assert!(report.verify());
+
+        // The signature survives a round trip through JSON, as written by the sinks.
+        let json = serde_json::to_string(&report).unwrap();
+        let parsed: TelemetryReport = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, report);
+        assert!(parsed.verify());
+
+        let mut tampered = parsed;
+        tampered.info.chain.latest_block_height += 1;
+        assert!(!tampered.verify());
+    }
+
+    #[test]
+    fn test_telemetry_validator_signature() {
+        let node_signer =
+            InMemorySigner::from_seed("node".parse().unwrap(), KeyType::ED25519, "node");
+        let validator_signer = InMemoryValidatorSigner::from_seed(
+            "validator".parse().unwrap(),
+            KeyType::ED25519,
+            "validator",
+        );
+        let info = info();
+        let signature = validator_signer.sign_telemetry(&info).unwrap();
+        let report = TelemetryReport::new(info, 1000, Some(signature), &node_signer);
+        assert!(report.verify_validator_signature(&validator_signer.public_key()));
+        assert!(!report.verify_validator_signature(&node_signer.public_key()));
+
+        // The signature is not one of the plain JSON, so it can't be reused for other data.
+        let content = serde_json::to_string(&report.info).unwrap();
+        let plain =
+            InMemorySigner::from_seed("validator".parse().unwrap(), KeyType::ED25519, "validator")
+                .sign(content.as_bytes());
+        assert_ne!(report.validator_signature, Some(plain));
+    }
 }
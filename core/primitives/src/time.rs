@@ -43,6 +43,10 @@ struct MockClockPerState {
     utc_call_count: u64,
     /// Number of times `Clock::instant()` method was called since we started mocking.
     instant_call_count: u64,
+    /// Timestamp returned by `Clock::utc()` once `utc_list` is empty.
+    utc: Option<DateTime<Utc>>,
+    /// Timestamp returned by `Clock::instant()` once `instant_list` is empty.
+    instant: Option<Instant>,
 }
 
 /// Stores the mocking state.
@@ -90,6 +94,30 @@ impl MockClockGuard {
         });
     }
 
+    /// Sets timestamp returned by `Self::utc()` once the queue is empty.
+    pub fn set_utc(&self, mock_date: DateTime<chrono::Utc>) {
+        MockClockPerThread::with(|clock| match &mut clock.mock {
+            Some(clock) => {
+                clock.utc = Some(mock_date);
+            }
+            None => {
+                panic!("Use MockClockGuard in your test");
+            }
+        });
+    }
+
+    /// Sets timestamp returned by `Self::instant()` once the queue is empty.
+    pub fn set_instant(&self, mock_date: Instant) {
+        MockClockPerThread::with(|clock| match &mut clock.mock {
+            Some(clock) => {
+                clock.instant = Some(mock_date);
+            }
+            None => {
+                panic!("Use MockClockGuard in your test");
+            }
+        });
+    }
+
     /// Returns number of calls  to `Self::utc` since `Self::mock()` was called.
     pub fn utc_call_count(&self) -> u64 {
         MockClockPerThread::with(|clock| match &mut clock.mock {
@@ -148,7 +176,7 @@ impl Clock {
         MockClockPerThread::with(|clock| match &mut clock.mock {
             Some(clock) => {
                 clock.instant_call_count += 1;
-                let x = clock.instant_list.pop_front();
+                let x = clock.instant_list.pop_front().or(clock.instant);
                 match x {
                     Some(t) => t,
                     None => {
@@ -166,7 +194,7 @@ impl Clock {
         MockClockPerThread::with(|clock| match &mut clock.mock {
             Some(clock) => {
                 clock.utc_call_count += 1;
-                let x = clock.utc_list.pop_front();
+                let x = clock.utc_list.pop_front().or(clock.utc);
                 match x {
                     Some(t) => t,
                     None => {
@@ -285,6 +313,22 @@ mod tests {
             }
         });
     }
+    #[test]
+    fn test_clock_set() {
+        let mock_clock_guard = MockClockGuard::default();
+        let utc_now = Utc::now();
+        let instant_now = Instant::now();
+        mock_clock_guard.set_utc(utc_now);
+        mock_clock_guard.set_instant(instant_now);
+        mock_clock_guard.add_instant(instant_now.add(Duration::from_secs(1)));
+
+        assert_eq!(Clock::utc(), utc_now);
+        assert_eq!(Clock::utc(), utc_now);
+        assert_eq!(Clock::instant(), instant_now.add(Duration::from_secs(1)));
+        assert_eq!(Clock::instant(), instant_now);
+        assert_eq!(mock_clock_guard.utc_call_count(), 2);
+        assert_eq!(mock_clock_guard.instant_call_count(), 2);
+    }
 }
 
 mod time {
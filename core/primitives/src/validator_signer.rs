@@ -1,18 +1,46 @@
+use std::fmt;
 use std::path::Path;
 use std::sync::Arc;
 
 use borsh::BorshSerialize;
 
-use near_crypto::{InMemorySigner, KeyType, PublicKey, Signature, Signer};
+use near_crypto::{InMemorySigner, KeyFile, KeyType, PublicKey, Signature, Signer};
 
 use crate::block::{Approval, ApprovalInner, BlockHeader};
 use crate::challenge::ChallengeBody;
 use crate::hash::{hash, CryptoHash};
 use crate::network::{AnnounceAccount, PeerId};
-use crate::sharding::ChunkHash;
+use crate::sharding::{ChunkStateWitness, ShardChunkHeader};
 use crate::telemetry::TelemetryInfo;
 use crate::types::{AccountId, BlockHeight, EpochId};
 
+/// Error returned when the validator key can't be used, e.g. because the remote signer holding it
+/// is unreachable or refuses to sign.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidatorSignerError {
+    /// The signer refused to sign, e.g. because it would be a double sign.
+    Refused(String),
+    /// The signer couldn't be reached or gave an unexpected answer.
+    Unavailable(String),
+}
+
+impl fmt::Display for ValidatorSignerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Refused(reason) => write!(f, "Signer refused to sign: {}", reason),
+            Self::Unavailable(reason) => write!(f, "Signer is unavailable: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for ValidatorSignerError {}
+
+impl From<ValidatorSignerError> for std::io::Error {
+    fn from(err: ValidatorSignerError) -> Self {
+        std::io::Error::new(std::io::ErrorKind::Other, err)
+    }
+}
+
 /// Validator signer that is used to sign blocks and approvals.
 pub trait ValidatorSigner: Sync + Send {
     /// Account id of the given validator.
@@ -21,8 +49,8 @@ pub trait ValidatorSigner: Sync + Send {
     /// Public key that identifies this validator.
     fn public_key(&self) -> PublicKey;
 
-    /// Serializes telemetry info to JSON and signs it, returning JSON with "signature" field.
-    fn sign_telemetry(&self, info: &TelemetryInfo) -> serde_json::Value;
+    /// Serializes telemetry info to JSON and signs it, see `TelemetryInfo::get_data_for_sig`.
+    fn sign_telemetry(&self, info: &TelemetryInfo) -> Result<Signature, ValidatorSignerError>;
 
     /// Signs given parts of the header.
     fn sign_block_header_parts(
@@ -30,16 +58,32 @@ pub trait ValidatorSigner: Sync + Send {
         prev_hash: CryptoHash,
         inner_lite: &[u8],
         inner_rest: &[u8],
-    ) -> (CryptoHash, Signature);
+    ) -> Result<(CryptoHash, Signature), ValidatorSignerError>;
 
-    /// Signs given inner of the chunk header.
-    fn sign_chunk_hash(&self, chunk_hash: &ChunkHash) -> Signature;
+    /// Signs the hash of given chunk header. The signature of the header is ignored.
+    fn sign_chunk_header(
+        &self,
+        header: &ShardChunkHeader,
+    ) -> Result<Signature, ValidatorSignerError>;
+
+    /// Signs state witness of a chunk with given hash.
+    fn sign_chunk_state_witness(
+        &self,
+        witness_hash: &CryptoHash,
+    ) -> Result<Signature, ValidatorSignerError>;
 
     /// Signs approval of given parent hash and reference hash.
-    fn sign_approval(&self, inner: &ApprovalInner, target_height: BlockHeight) -> Signature;
+    fn sign_approval(
+        &self,
+        inner: &ApprovalInner,
+        target_height: BlockHeight,
+    ) -> Result<Signature, ValidatorSignerError>;
 
     /// Signs challenge body.
-    fn sign_challenge(&self, challenge_body: &ChallengeBody) -> (CryptoHash, Signature);
+    fn sign_challenge(
+        &self,
+        challenge_body: &ChallengeBody,
+    ) -> Result<(CryptoHash, Signature), ValidatorSignerError>;
 
     /// Signs account announce.
     fn sign_account_announce(
@@ -47,12 +91,12 @@ pub trait ValidatorSigner: Sync + Send {
         account_id: &AccountId,
         peer_id: &PeerId,
         epoch_id: &EpochId,
-    ) -> Signature;
+    ) -> Result<Signature, ValidatorSignerError>;
 
     fn compute_vrf_with_proof(
         &self,
         data: &[u8],
-    ) -> (near_crypto::vrf::Value, near_crypto::vrf::Proof);
+    ) -> Result<(near_crypto::vrf::Value, near_crypto::vrf::Proof), ValidatorSignerError>;
 
     /// Used by test infrastructure, only implement if make sense for testing otherwise raise `unimplemented`.
     fn write_to_file(&self, path: &Path) -> std::io::Result<()>;
@@ -75,8 +119,8 @@ impl ValidatorSigner for EmptyValidatorSigner {
         PublicKey::empty(KeyType::ED25519)
     }
 
-    fn sign_telemetry(&self, _info: &TelemetryInfo) -> serde_json::Value {
-        serde_json::Value::default()
+    fn sign_telemetry(&self, _info: &TelemetryInfo) -> Result<Signature, ValidatorSignerError> {
+        Ok(Signature::default())
     }
 
     fn sign_block_header_parts(
@@ -84,22 +128,39 @@ impl ValidatorSigner for EmptyValidatorSigner {
         prev_hash: CryptoHash,
         inner_lite: &[u8],
         inner_rest: &[u8],
-    ) -> (CryptoHash, Signature) {
+    ) -> Result<(CryptoHash, Signature), ValidatorSignerError> {
         let hash = BlockHeader::compute_hash(prev_hash, inner_lite, inner_rest);
-        (hash, Signature::default())
+        Ok((hash, Signature::default()))
     }
 
-    fn sign_chunk_hash(&self, _chunk_hash: &ChunkHash) -> Signature {
-        Signature::default()
+    fn sign_chunk_header(
+        &self,
+        _header: &ShardChunkHeader,
+    ) -> Result<Signature, ValidatorSignerError> {
+        Ok(Signature::default())
     }
 
-    fn sign_approval(&self, _inner: &ApprovalInner, _target_height: BlockHeight) -> Signature {
-        Signature::default()
+    fn sign_chunk_state_witness(
+        &self,
+        _witness_hash: &CryptoHash,
+    ) -> Result<Signature, ValidatorSignerError> {
+        Ok(Signature::default())
     }
 
-    fn sign_challenge(&self, challenge_body: &ChallengeBody) -> (CryptoHash, Signature) {
+    fn sign_approval(
+        &self,
+        _inner: &ApprovalInner,
+        _target_height: BlockHeight,
+    ) -> Result<Signature, ValidatorSignerError> {
+        Ok(Signature::default())
+    }
+
+    fn sign_challenge(
+        &self,
+        challenge_body: &ChallengeBody,
+    ) -> Result<(CryptoHash, Signature), ValidatorSignerError> {
         let hash = hash(&challenge_body.try_to_vec().expect("Failed to serialize"));
-        (hash, Signature::default())
+        Ok((hash, Signature::default()))
     }
 
     fn sign_account_announce(
@@ -107,14 +168,14 @@ impl ValidatorSigner for EmptyValidatorSigner {
         _account_id: &AccountId,
         _peer_id: &PeerId,
         _epoch_id: &EpochId,
-    ) -> Signature {
-        Signature::default()
+    ) -> Result<Signature, ValidatorSignerError> {
+        Ok(Signature::default())
     }
 
     fn compute_vrf_with_proof(
         &self,
         _data: &[u8],
-    ) -> (near_crypto::vrf::Value, near_crypto::vrf::Proof) {
+    ) -> Result<(near_crypto::vrf::Value, near_crypto::vrf::Proof), ValidatorSignerError> {
         unimplemented!()
     }
 
@@ -149,6 +210,11 @@ impl InMemoryValidatorSigner {
         let signer = InMemorySigner::from_file(path);
         Self { account_id: signer.account_id.clone(), signer: Arc::new(signer) }
     }
+
+    pub fn try_from_file(path: &Path) -> std::io::Result<Self> {
+        let signer: InMemorySigner = KeyFile::try_from_file(path)?.into();
+        Ok(Self { account_id: signer.account_id.clone(), signer: Arc::new(signer) })
+    }
 }
 
 impl ValidatorSigner for InMemoryValidatorSigner {
@@ -160,11 +226,9 @@ impl ValidatorSigner for InMemoryValidatorSigner {
         self.signer.public_key()
     }
 
-    fn sign_telemetry(&self, info: &TelemetryInfo) -> serde_json::Value {
-        let mut value = serde_json::to_value(info).expect("Telemetry must serialize to JSON");
-        let content = serde_json::to_string(&value).expect("Telemetry must serialize to JSON");
-        value["signature"] = format!("{}", self.signer.sign(content.as_bytes())).into();
-        value
+    fn sign_telemetry(&self, info: &TelemetryInfo) -> Result<Signature, ValidatorSignerError> {
+        let content = serde_json::to_string(info).expect("Telemetry must serialize to JSON");
+        Ok(self.signer.sign(&TelemetryInfo::get_data_for_sig(&content)))
     }
 
     fn sign_block_header_parts(
@@ -172,23 +236,40 @@ impl ValidatorSigner for InMemoryValidatorSigner {
         prev_hash: CryptoHash,
         inner_lite: &[u8],
         inner_rest: &[u8],
-    ) -> (CryptoHash, Signature) {
+    ) -> Result<(CryptoHash, Signature), ValidatorSignerError> {
         let hash = BlockHeader::compute_hash(prev_hash, inner_lite, inner_rest);
-        (hash, self.signer.sign(hash.as_ref()))
+        Ok((hash, self.signer.sign(hash.as_ref())))
     }
 
-    fn sign_chunk_hash(&self, chunk_hash: &ChunkHash) -> Signature {
-        self.signer.sign(chunk_hash.as_ref())
+    fn sign_chunk_header(
+        &self,
+        header: &ShardChunkHeader,
+    ) -> Result<Signature, ValidatorSignerError> {
+        Ok(self.signer.sign(header.chunk_hash().as_ref()))
     }
 
-    fn sign_approval(&self, inner: &ApprovalInner, target_height: BlockHeight) -> Signature {
-        self.signer.sign(&Approval::get_data_for_sig(inner, target_height))
+    fn sign_chunk_state_witness(
+        &self,
+        witness_hash: &CryptoHash,
+    ) -> Result<Signature, ValidatorSignerError> {
+        Ok(self.signer.sign(&ChunkStateWitness::get_data_for_sig(witness_hash)))
     }
 
-    fn sign_challenge(&self, challenge_body: &ChallengeBody) -> (CryptoHash, Signature) {
+    fn sign_approval(
+        &self,
+        inner: &ApprovalInner,
+        target_height: BlockHeight,
+    ) -> Result<Signature, ValidatorSignerError> {
+        Ok(self.signer.sign(&Approval::get_data_for_sig(inner, target_height)))
+    }
+
+    fn sign_challenge(
+        &self,
+        challenge_body: &ChallengeBody,
+    ) -> Result<(CryptoHash, Signature), ValidatorSignerError> {
         let hash = hash(&challenge_body.try_to_vec().expect("Failed to serialize"));
         let signature = self.signer.sign(hash.as_ref());
-        (hash, signature)
+        Ok((hash, signature))
     }
 
     fn sign_account_announce(
@@ -196,16 +277,16 @@ impl ValidatorSigner for InMemoryValidatorSigner {
         account_id: &AccountId,
         peer_id: &PeerId,
         epoch_id: &EpochId,
-    ) -> Signature {
+    ) -> Result<Signature, ValidatorSignerError> {
         let hash = AnnounceAccount::build_header_hash(account_id, peer_id, epoch_id);
-        self.signer.sign(hash.as_ref())
+        Ok(self.signer.sign(hash.as_ref()))
     }
 
     fn compute_vrf_with_proof(
         &self,
         data: &[u8],
-    ) -> (near_crypto::vrf::Value, near_crypto::vrf::Proof) {
-        self.signer.compute_vrf_with_proof(data)
+    ) -> Result<(near_crypto::vrf::Value, near_crypto::vrf::Proof), ValidatorSignerError> {
+        Ok(self.signer.compute_vrf_with_proof(data))
     }
 
     fn write_to_file(&self, path: &Path) -> std::io::Result<()> {
@@ -16,7 +16,7 @@ pub struct Version {
 pub type DbVersion = u32;
 
 /// Current version of the database.
-pub const DB_VERSION: DbVersion = 31;
+pub const DB_VERSION: DbVersion = 34;
 
 /// Protocol version type.
 pub use near_primitives_core::types::ProtocolVersion;
@@ -148,6 +148,35 @@ pub enum ProtocolFeature {
     /// alpha is min stake ratio
     #[cfg(feature = "protocol_feature_fix_staking_threshold")]
     FixStakingThreshold,
+    /// Charge `regular_op_cost` with a Wasmer2 compiler middleware instead of injecting gas
+    /// metering instructions into the contract code.
+    #[cfg(feature = "protocol_feature_wasmer2_middleware_metering")]
+    Wasmer2MiddlewareMetering,
+    /// Add `ed25519_verify` host function.
+    #[cfg(feature = "protocol_feature_ed25519_verify")]
+    Ed25519Verify,
+    /// Split shards whose gas usage or state size stays above the limits set in genesis, instead
+    /// of relying on shard layouts planned in advance.
+    #[cfg(feature = "protocol_feature_dynamic_resharding")]
+    DynamicResharding,
+    /// Accept challenges for invalid state transitions and include them in blocks, so that
+    /// producers of invalid chunks get slashed.
+    #[cfg(feature = "protocol_feature_chunk_state_challenges")]
+    ChunkStateChallenges,
+    /// Chunk producers send state witnesses for their chunks, which let nodes that don't track
+    /// a shard validate its chunks.
+    #[cfg(feature = "protocol_feature_chunk_state_witness")]
+    ChunkStateWitness,
+    /// Peers tell each other in the handshake whether they accept compressed messages.
+    #[cfg(feature = "protocol_feature_peer_message_compression")]
+    PeerMessageCompression,
+    /// Peers tell each other in the handshake whether they accept QUIC connections.
+    #[cfg(feature = "protocol_feature_quic_transport")]
+    QuicTransport,
+    /// Peers tell each other in the handshake which address they see the connection at, so that
+    /// nodes behind NAT can learn and advertise their public address.
+    #[cfg(feature = "protocol_feature_observed_address")]
+    ObservedAddress,
 }
 
 /// Both, outgoing and incoming tcp connections to peers, will be rejected if `peer's`
@@ -164,7 +193,7 @@ const MAIN_NET_PROTOCOL_VERSION: ProtocolVersion = 52;
 pub const PROTOCOL_VERSION: ProtocolVersion = MAIN_NET_PROTOCOL_VERSION;
 /// Current latest nightly version of the protocol.
 #[cfg(feature = "nightly_protocol")]
-pub const PROTOCOL_VERSION: ProtocolVersion = 126;
+pub const PROTOCOL_VERSION: ProtocolVersion = 134;
 
 impl ProtocolFeature {
     pub const fn protocol_version(self) -> ProtocolVersion {
@@ -204,6 +233,22 @@ impl ProtocolFeature {
             ProtocolFeature::RoutingExchangeAlgorithm => 117,
             #[cfg(feature = "protocol_feature_fix_staking_threshold")]
             ProtocolFeature::FixStakingThreshold => 126,
+            #[cfg(feature = "protocol_feature_wasmer2_middleware_metering")]
+            ProtocolFeature::Wasmer2MiddlewareMetering => 127,
+            #[cfg(feature = "protocol_feature_ed25519_verify")]
+            ProtocolFeature::Ed25519Verify => 128,
+            #[cfg(feature = "protocol_feature_dynamic_resharding")]
+            ProtocolFeature::DynamicResharding => 129,
+            #[cfg(feature = "protocol_feature_chunk_state_challenges")]
+            ProtocolFeature::ChunkStateChallenges => 130,
+            #[cfg(feature = "protocol_feature_chunk_state_witness")]
+            ProtocolFeature::ChunkStateWitness => 131,
+            #[cfg(feature = "protocol_feature_peer_message_compression")]
+            ProtocolFeature::PeerMessageCompression => 132,
+            #[cfg(feature = "protocol_feature_quic_transport")]
+            ProtocolFeature::QuicTransport => 133,
+            #[cfg(feature = "protocol_feature_observed_address")]
+            ProtocolFeature::ObservedAddress => 134,
         }
     }
 }
@@ -1431,6 +1431,51 @@ pub struct NextEpochValidatorInfo {
     pub shards: Vec<ShardId>,
 }
 
+/// Blocks and chunks a validator was expected to produce in an epoch, and whether it did.
+#[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct ValidatorPerformanceView {
+    pub account_id: AccountId,
+    pub epoch_id: EpochId,
+    /// Epoch start block height
+    pub epoch_start_height: BlockHeight,
+    /// Height of the last block of the epoch known to the node
+    pub last_block_height: BlockHeight,
+    pub expected_blocks: Vec<BlockProductionView>,
+    /// Chunks expected in blocks of the epoch. Heights without a block expect no chunks.
+    pub expected_chunks: Vec<ChunkProductionView>,
+}
+
+#[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct BlockProductionView {
+    pub height: BlockHeight,
+    pub status: ProductionStatusView,
+}
+
+#[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct ChunkProductionView {
+    pub height: BlockHeight,
+    pub shard_id: ShardId,
+    pub status: ProductionStatusView,
+}
+
+#[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum ProductionStatusView {
+    /// Included in the canonical chain.
+    Produced,
+    /// The node has never seen it.
+    NotProduced,
+    /// The node has seen it, but it was built on a fork and is not in the canonical chain.
+    NotIncluded,
+    /// The node has seen it and it was built on the canonical chain, but the next canonical
+    /// block did not include it, e.g. because it arrived too late.
+    Late,
+}
+
 #[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
 #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, BorshDeserialize, BorshSerialize)]
 pub struct LightClientBlockView {
@@ -267,10 +267,22 @@ pub enum DBCol {
     /// - *Rows*: BlockShardId (BlockHash || ShardId) - 40 bytes
     /// - *Column type*: StateChangesForSplitStates
     ColStateChangesForSplitStates = 49,
+    /// Load of a shard in a block, used for splitting shards by load
+    /// - *Rows*: BlockShardId (BlockHash || ShardId) - 40 bytes
+    /// - *Column type*: ShardLoad
+    ColShardLoad = 50,
+    /// Shard config of an epoch chosen by dynamic resharding
+    /// - *Rows*: epoch id (CryptoHash)
+    /// - *Column type*: EpochShardConfig
+    ColEpochShardConfig = 51,
+    /// Highest blocks, chunks and approvals signed by a validator, used to refuse double signs
+    /// - *Rows*: account id (AccountId)
+    /// - *Column type*: SigningHistory
+    ColSigningHistory = 52,
 }
 
 // Do not move this line from enum DBCol
-pub const NUM_COLS: usize = 50;
+pub const NUM_COLS: usize = 53;
 
 impl std::fmt::Display for DBCol {
     fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
@@ -327,6 +339,9 @@ impl std::fmt::Display for DBCol {
             Self::ColStateChangesForSplitStates => {
                 "state changes indexed by block hash and shard id"
             }
+            Self::ColShardLoad => "shard load indexed by block hash and shard id",
+            Self::ColEpochShardConfig => "epoch shard config",
+            Self::ColSigningHistory => "signing history of validators",
         };
         write!(formatter, "{}", desc)
     }
@@ -360,6 +375,8 @@ pub static SHOULD_COL_GC: [bool; NUM_COLS] = {
     col_gc[DBCol::ColEpochValidatorInfo as usize] = false; // https://github.com/nearprotocol/nearcore/pull/2952
     col_gc[DBCol::ColEpochStart as usize] = false; // https://github.com/nearprotocol/nearcore/pull/2952
     col_gc[DBCol::ColCachedContractCode as usize] = false;
+    col_gc[DBCol::ColEpochShardConfig as usize] = false;
+    col_gc[DBCol::ColSigningHistory as usize] = false; // signing history must never be forgotten
     col_gc
 };
 
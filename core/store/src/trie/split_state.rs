@@ -1,4 +1,5 @@
 use crate::trie::iterator::TrieItem;
+use crate::trie::nibble_slice::NibbleSlice;
 use crate::{
     get, get_delayed_receipt_indices, set, ShardTries, StoreUpdate, Trie, TrieChanges, TrieUpdate,
 };
@@ -37,6 +38,33 @@ impl Trie {
         let path_end = self.find_path_for_part_boundary(state_root, part_id + 1, num_parts)?;
         self.iter(state_root)?.get_trie_items(&path_begin, &path_end)
     }
+
+    /// Returns the account of the first key past the middle of the state by memory usage. This is
+    /// where the state is split in two halves of similar size when a shard is split by load.
+    /// Returns `None` if there is no such account, e.g. if the state is empty.
+    pub fn find_median_account_id(
+        &self,
+        state_root: &StateRoot,
+    ) -> Result<Option<AccountId>, StorageError> {
+        let path = self.find_path_for_part_boundary(state_root, 1, 2)?;
+        if path.last() == Some(&16) {
+            return Ok(None);
+        }
+        let mut iterator = self.iter(state_root)?;
+        let path_encoded = NibbleSlice::encode_nibbles(&path, false);
+        iterator.seek_nibble_slice(NibbleSlice::from_encoded(&path_encoded[..]).0)?;
+        for item in iterator {
+            let (raw_key, _) = item?;
+            // Delayed receipts are the only keys without an account and they come last.
+            if let Some(account_id) = parse_account_id_from_raw_key(&raw_key).map_err(|e| {
+                let err = format!("error parsing account id from trie key {:?}: {:?}", raw_key, e);
+                StorageError::StorageInconsistentState(err)
+            })? {
+                return Ok(Some(account_id));
+            }
+        }
+        Ok(None)
+    }
 }
 
 impl ShardTries {
@@ -194,6 +222,41 @@ impl ShardTries {
         self.finalize_and_apply_trie_updates(trie_updates)
     }
 
+    /// add `values` (key-value pairs of items stored in the state of a parent shard) to the state
+    /// of `shard_uid`, which is merged from several parent shards
+    /// Like `add_values_to_split_states`, changes on DelayedReceipts or DelayedReceiptsIndices are
+    /// ignored, delayed receipts of the parent shards are added with
+    /// `apply_delayed_receipts_to_merged_state` once all values are added.
+    /// Returns `store_update` and the new state_root for the merged state
+    pub fn add_values_to_merged_state(
+        &self,
+        shard_uid: ShardUId,
+        state_root: &StateRoot,
+        values: Vec<(Vec<u8>, Option<Vec<u8>>)>,
+    ) -> Result<(StoreUpdate, StateRoot), StorageError> {
+        let state_roots: HashMap<_, _> = [(shard_uid, *state_root)].into_iter().collect();
+        let (store_update, new_state_roots) =
+            self.add_values_to_split_states(&state_roots, values, &|_| shard_uid)?;
+        Ok((store_update, new_state_roots[&shard_uid]))
+    }
+
+    /// appends `receipts` to the delayed receipts queue of the state of `shard_uid`, which is
+    /// merged from several parent shards
+    /// The merged queue holds the delayed receipts of the parent shards one after the other, so
+    /// receipts of a parent shard must be applied in order and after the receipts of the parent
+    /// shards before it.
+    pub fn apply_delayed_receipts_to_merged_state(
+        &self,
+        shard_uid: ShardUId,
+        state_root: &StateRoot,
+        receipts: &[Receipt],
+    ) -> Result<(StoreUpdate, StateRoot), StorageError> {
+        let state_roots: HashMap<_, _> = [(shard_uid, *state_root)].into_iter().collect();
+        let (store_update, new_state_roots) =
+            self.apply_delayed_receipts_to_split_states(&state_roots, receipts, &|_| shard_uid)?;
+        Ok((store_update, new_state_roots[&shard_uid]))
+    }
+
     fn finalize_and_apply_trie_updates(
         &self,
         updates: HashMap<ShardUId, TrieUpdate>,
@@ -462,6 +525,39 @@ mod tests {
         assert_eq!(expected_trie_items, combined_trie_items);
     }
 
+    #[test]
+    fn test_find_median_account_id() {
+        let tries = create_tries();
+        let trie = tries.get_trie_for_shard(ShardUId::single_shard());
+        assert_eq!(trie.find_median_account_id(&Trie::empty_root()).unwrap(), None);
+
+        let accounts: Vec<AccountId> =
+            (0..200).map(|i| format!("account{:03}", i).parse().unwrap()).collect();
+        let account = Account::new(1, 0, CryptoHash::default(), 0).try_to_vec().unwrap();
+        let changes = accounts
+            .iter()
+            .map(|account_id| {
+                (
+                    TrieKey::Account { account_id: account_id.clone() }.to_vec(),
+                    Some(account.clone()),
+                )
+            })
+            .collect();
+        let state_root =
+            test_populate_trie(&tries, &Trie::empty_root(), ShardUId::single_shard(), changes);
+
+        let median_account_id = trie.find_median_account_id(&state_root).unwrap().unwrap();
+        let num_lower =
+            accounts.iter().filter(|account_id| **account_id < median_account_id).count();
+        assert!(accounts.contains(&median_account_id));
+        assert!(
+            num_lower >= accounts.len() / 4 && num_lower <= accounts.len() * 3 / 4 + 1,
+            "{} accounts out of {} are lower than the median account",
+            num_lower,
+            accounts.len()
+        );
+    }
+
     #[test]
     fn test_add_values_to_split_states() {
         let mut rng = rand::thread_rng();
@@ -810,4 +906,92 @@ mod tests {
             test_split_and_update_state_impl(&mut rng);
         }
     }
+    #[test]
+    fn test_merge_states() {
+        let mut rng = rand::thread_rng();
+        let tries = create_tries();
+        let parent_shard_uids: Vec<_> =
+            (0..2).map(|shard_id| ShardUId { version: 0, shard_id }).collect();
+        let merged_shard_uid = ShardUId { version: 1, shard_id: 0 };
+
+        // add accounts and receipts to the parent shards
+        let account_ids = gen_unique_accounts(&mut rng, 100);
+        let parent_states: Vec<_> = parent_shard_uids
+            .iter()
+            .zip(account_ids.chunks(account_ids.len() / 2 + 1))
+            .map(|(&shard_uid, account_ids)| {
+                let mut trie_update = tries.new_trie_update(shard_uid, CryptoHash::default());
+                for account_id in account_ids {
+                    set_account(
+                        &mut trie_update,
+                        account_id.clone(),
+                        &Account::new(0, 0, CryptoHash::default(), 0),
+                    );
+                }
+                let receipts = gen_receipts(&mut rng, 50);
+                for (index, receipt) in receipts.iter().enumerate() {
+                    set(&mut trie_update, TrieKey::DelayedReceipt { index: index as u64 }, receipt);
+                }
+                set(
+                    &mut trie_update,
+                    TrieKey::DelayedReceiptIndices,
+                    &DelayedReceiptIndices {
+                        first_index: 0,
+                        next_available_index: receipts.len() as u64,
+                    },
+                );
+                trie_update.commit(StateChangeCause::Resharding);
+                let (trie_changes, _) = trie_update.finalize().unwrap();
+                let (store_update, state_root) = tries.apply_all(&trie_changes, shard_uid).unwrap();
+                store_update.commit().unwrap();
+                (shard_uid, state_root)
+            })
+            .collect();
+
+        // build the merged state from the parent states one after the other
+        let mut merged_state_root = CryptoHash::default();
+        for (shard_uid, state_root) in parent_states.iter() {
+            let trie_items = tries
+                .get_view_trie_for_shard(*shard_uid)
+                .get_trie_items_for_part(0, 1, state_root)
+                .unwrap();
+            let (store_update, new_state_root) = tries
+                .add_values_to_merged_state(
+                    merged_shard_uid,
+                    &merged_state_root,
+                    trie_items.into_iter().map(|(key, value)| (key, Some(value))).collect(),
+                )
+                .unwrap();
+            store_update.commit().unwrap();
+            merged_state_root = new_state_root;
+        }
+        for (shard_uid, state_root) in parent_states.iter() {
+            let (store_update, new_state_root) = tries
+                .apply_delayed_receipts_to_merged_state(
+                    merged_shard_uid,
+                    &merged_state_root,
+                    &get_all_delayed_receipts(&tries, shard_uid, state_root),
+                )
+                .unwrap();
+            store_update.commit().unwrap();
+            merged_state_root = new_state_root;
+        }
+
+        let mut expected_trie_items = vec![];
+        let mut expected_receipts = vec![];
+        for (shard_uid, state_root) in parent_states.iter() {
+            expected_trie_items
+                .extend(get_trie_nodes_except_delayed_receipts(&tries, shard_uid, state_root));
+            expected_receipts.extend(get_all_delayed_receipts(&tries, shard_uid, state_root));
+        }
+        expected_trie_items.sort();
+        assert_eq!(
+            get_trie_nodes_except_delayed_receipts(&tries, &merged_shard_uid, &merged_state_root),
+            expected_trie_items
+        );
+        assert_eq!(
+            get_all_delayed_receipts(&tries, &merged_shard_uid, &merged_state_root),
+            expected_receipts
+        );
+    }
 }
@@ -16,7 +16,7 @@ use near_client::Client;
 use near_crypto::{InMemorySigner, KeyType, Signer};
 use near_logger_utils::init_test_logger;
 use near_network::test_utils::MockPeerManagerAdapter;
-use near_network::types::NetworkRequests;
+use near_network::types::{NetworkRequests, PeerManagerMessageRequest};
 use near_primitives::challenge::{
     BlockDoubleSign, Challenge, ChallengeBody, ChunkProofs, MaybeEncodedShardChunk,
 };
@@ -68,7 +68,8 @@ fn test_verify_block_double_sign_challenge() {
         &signer,
         *b1.header().next_bp_hash(),
         block_merkle_tree.root(),
-    );
+    )
+    .unwrap();
     let epoch_id = b1.header().epoch_id().clone();
     let valid_challenge = Challenge::produce(
         ChallengeBody::BlockDoubleSign(BlockDoubleSign {
@@ -76,7 +77,8 @@ fn test_verify_block_double_sign_challenge() {
             right_block_header: b1.header().try_to_vec().unwrap(),
         }),
         &signer,
-    );
+    )
+    .unwrap();
     let runtime_adapter = env.clients[1].chain.runtime_adapter.clone();
     assert_eq!(
         &validate_challenge(&*runtime_adapter, &epoch_id, genesis.hash(), &valid_challenge)
@@ -90,7 +92,8 @@ fn test_verify_block_double_sign_challenge() {
             right_block_header: b1.header().try_to_vec().unwrap(),
         }),
         &signer,
-    );
+    )
+    .unwrap();
     let runtime_adapter = env.clients[1].chain.runtime_adapter.clone();
     assert!(validate_challenge(&*runtime_adapter, &epoch_id, genesis.hash(), &invalid_challenge,)
         .is_err());
@@ -101,7 +104,8 @@ fn test_verify_block_double_sign_challenge() {
             right_block_header: b3.header().try_to_vec().unwrap(),
         }),
         &signer,
-    );
+    )
+    .unwrap();
     let runtime_adapter = env.clients[1].chain.runtime_adapter.clone();
     assert!(validate_challenge(&*runtime_adapter, &epoch_id, genesis.hash(), &invalid_challenge,)
         .is_err());
@@ -241,6 +245,96 @@ fn test_verify_chunk_proofs_challenge_transaction_order() {
     assert_eq!(challenge_result.unwrap(), (*block.hash(), vec!["test0".parse().unwrap()]));
 }
 
+/// Number of chunk proofs challenges submitted in fisherman mode, summed over all tests.
+fn fisherman_chunk_proofs_challenges() -> u64 {
+    near_metrics::gather()
+        .iter()
+        .filter(|family| family.get_name() == "near_fisherman_challenges_total")
+        .flat_map(|family| family.get_metric())
+        .filter(|metric| {
+            metric
+                .get_label()
+                .iter()
+                .any(|label| label.get_name() == "kind" && label.get_value() == "chunk_proofs")
+        })
+        .map(|metric| metric.get_counter().get_value() as u64)
+        .sum()
+}
+
+/// Processes a block with a chunk with transactions in invalid order and returns the challenges
+/// sent out for it, along with the number of them recorded by the fisherman.
+fn process_chunk_with_invalid_transactions_order(fisherman: bool) -> (Vec<Challenge>, u64) {
+    let mut env = TestEnv::builder(ChainGenesis::test()).build();
+    env.clients[0].config.fisherman = fisherman;
+    env.produce_block(0, 1);
+
+    let genesis_hash = *env.clients[0].chain.genesis().hash();
+    let signer = InMemorySigner::from_seed("test0".parse().unwrap(), KeyType::ED25519, "test0");
+    let (chunk, merkle_paths, receipts, block) = create_chunk_with_transactions(
+        &mut env.clients[0],
+        vec![
+            SignedTransaction::send_money(
+                2,
+                "test0".parse().unwrap(),
+                "test1".parse().unwrap(),
+                &signer,
+                1000,
+                genesis_hash,
+            ),
+            SignedTransaction::send_money(
+                1,
+                "test0".parse().unwrap(),
+                "test1".parse().unwrap(),
+                &signer,
+                1000,
+                genesis_hash,
+            ),
+        ],
+    );
+    let client = &mut env.clients[0];
+    client
+        .shards_mgr
+        .distribute_encoded_chunk(chunk, merkle_paths, receipts, client.chain.mut_store())
+        .unwrap();
+    let recorded_before = fisherman_chunk_proofs_challenges();
+    let (_, tip) = client.process_block(block.clone().into(), Provenance::NONE);
+    assert!(tip.is_err());
+    let recorded = fisherman_chunk_proofs_challenges() - recorded_before;
+
+    let mut challenges = vec![];
+    while let Some(request) = env.network_adapters[0].pop() {
+        if let PeerManagerMessageRequest::NetworkRequests(NetworkRequests::Challenge(challenge)) =
+            request
+        {
+            challenges.push(challenge);
+        }
+    }
+    assert_eq!(challenges.len(), 1);
+    assert!(matches!(challenges[0].body, ChallengeBody::ChunkProofs(_)));
+    let runtime_adapter = env.clients[0].chain.runtime_adapter.clone();
+    assert_eq!(
+        validate_challenge(
+            &*runtime_adapter,
+            block.header().epoch_id(),
+            block.header().prev_hash(),
+            &challenges[0],
+        )
+        .unwrap(),
+        (*block.hash(), vec!["test0".parse().unwrap()])
+    );
+    (challenges, recorded)
+}
+
+/// Fisherman submits a challenge for a chunk with transactions in invalid order and records it.
+/// Only this test runs in fisherman mode, so no other test changes the count.
+#[test]
+fn test_fisherman_chunk_proofs_challenge() {
+    let (_, recorded) = process_chunk_with_invalid_transactions_order(false);
+    assert_eq!(recorded, 0);
+    let (_, recorded) = process_chunk_with_invalid_transactions_order(true);
+    assert_eq!(recorded, 1);
+}
+
 fn challenge(
     env: TestEnv,
     shard_id: usize,
@@ -255,7 +349,8 @@ fn challenge(
             merkle_proof: merkle_paths[shard_id].clone(),
         }),
         &*env.clients[0].validator_signer.as_ref().unwrap().clone(),
-    );
+    )
+    .unwrap();
     let runtime_adapter = env.clients[0].chain.runtime_adapter.clone();
     validate_challenge(
         &*runtime_adapter,
@@ -370,7 +465,8 @@ fn test_verify_chunk_invalid_state_challenge() {
         &validator_signer,
         *last_block.header().next_bp_hash(),
         block_merkle_tree.root(),
-    );
+    )
+    .unwrap();
 
     let challenge_body = {
         use near_chain::chain::{ChainUpdate, OrphanBlockPool};
@@ -420,19 +516,59 @@ fn test_verify_chunk_invalid_state_challenge() {
             ],
         );
     }
-    let challenge =
-        Challenge::produce(ChallengeBody::ChunkState(challenge_body), &validator_signer);
-    let runtime_adapter = client.chain.runtime_adapter.clone();
+    // Receipts applied with the previous chunk were sent since the chunk before it.
+    assert_eq!(challenge_body.prev_chunk_incoming_receipts.len(), 1);
+    assert_eq!(challenge_body.prev_chunk_incoming_receipts[0].0, *last_block.hash());
     assert_eq!(
-        validate_challenge(
-            &*runtime_adapter,
-            block.header().epoch_id(),
-            block.header().prev_hash(),
-            &challenge,
+        challenge_body.prev_chunk_block_headers,
+        vec![client
+            .chain
+            .get_block_header(last_block.header().prev_hash())
+            .unwrap()
+            .try_to_vec()
+            .unwrap()]
+    );
+    let runtime_adapter = client.chain.runtime_adapter.clone();
+
+    // Hiding the block with the chunk before the previous chunk makes the challenge malicious.
+    #[cfg(all(feature = "protocol_feature_chunk_state_challenges", feature = "nightly_protocol"))]
+    {
+        let mut malicious_challenge_body = challenge_body.clone();
+        malicious_challenge_body.prev_chunk_block_headers.clear();
+        let malicious_challenge = Challenge::produce(
+            ChallengeBody::ChunkStateV2(malicious_challenge_body),
+            &validator_signer,
         )
-        .unwrap(),
-        (*block.hash(), vec!["test0".parse().unwrap()])
+        .unwrap();
+        assert_eq!(
+            validate_challenge(
+                &*runtime_adapter,
+                block.header().epoch_id(),
+                block.header().prev_hash(),
+                &malicious_challenge,
+            )
+            .unwrap_err()
+            .kind(),
+            ErrorKind::MaliciousChallenge
+        );
+    }
+
+    let challenge =
+        Challenge::produce(ChallengeBody::ChunkStateV2(challenge_body), &validator_signer).unwrap();
+    let result = validate_challenge(
+        &*runtime_adapter,
+        block.header().epoch_id(),
+        block.header().prev_hash(),
+        &challenge,
     );
+    // Chunk state challenges are only valid once the protocol feature is enabled.
+    #[cfg(all(feature = "protocol_feature_chunk_state_challenges", feature = "nightly_protocol"))]
+    assert_eq!(result.unwrap(), (*block.hash(), vec!["test0".parse().unwrap()]));
+    #[cfg(not(all(
+        feature = "protocol_feature_chunk_state_challenges",
+        feature = "nightly_protocol"
+    )))]
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::InvalidChallenge);
 
     // Process the block with invalid chunk and make sure it's marked as invalid at the end.
     // And the same challenge created and sent out.
@@ -446,6 +582,14 @@ fn test_verify_chunk_invalid_state_challenge() {
     } else {
         assert!(false);
     }
+
+    // The challenge is included into the next block on top of the valid chain.
+    #[cfg(all(feature = "protocol_feature_chunk_state_challenges", feature = "nightly_protocol"))]
+    {
+        let next_block =
+            env.clients[0].produce_block(last_block.header().height() + 2).unwrap().unwrap();
+        assert_eq!(next_block.challenges(), &vec![challenge]);
+    }
 }
 
 /// Receive invalid state transition in chunk as next chunk producer.
@@ -537,7 +681,8 @@ fn test_block_challenge() {
             merkle_proof: merkle_paths[shard_id as usize].clone(),
         }),
         &*env.clients[0].validator_signer.as_ref().unwrap().clone(),
-    );
+    )
+    .unwrap();
     env.clients[0].process_challenge(challenge.clone()).unwrap();
     env.produce_block(0, 2);
     assert_eq!(env.clients[0].chain.get_block_by_height(2).unwrap().challenges(), &[challenge]);
@@ -597,11 +742,13 @@ fn test_fishermen_challenge() {
     let challenge = Challenge::produce(
         challenge_body.clone(),
         &*env.clients[1].validator_signer.as_ref().unwrap().clone(),
-    );
+    )
+    .unwrap();
     let challenge1 = Challenge::produce(
         challenge_body,
         &*env.clients[2].validator_signer.as_ref().unwrap().clone(),
-    );
+    )
+    .unwrap();
     assert!(env.clients[0].process_challenge(challenge1).is_err());
     env.clients[0].process_challenge(challenge.clone()).unwrap();
     env.produce_block(0, 12);
@@ -0,0 +1,175 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use near_chain::{ChainGenesis, Provenance, RuntimeAdapter};
+use near_chain_configs::Genesis;
+use near_client::test_utils::TestEnv;
+use near_logger_utils::init_test_logger;
+use near_primitives::block::Block;
+use near_primitives::epoch_manager::ReshardingConfig;
+use near_primitives::runtime::config_store::RuntimeConfigStore;
+use near_primitives::shard_layout::{account_id_to_shard_uid, ShardLayout};
+use near_primitives::types::{AccountId, Gas};
+use near_primitives::views::QueryRequest;
+use near_store::test_utils::create_test_store;
+use nearcore::config::GenesisExt;
+use nearcore::TrackedConfig;
+
+/// Sets up clients which track all shards, as dynamic resharding requires.
+fn setup_env(
+    mut genesis: Genesis,
+    epoch_length: u64,
+    num_validators: u64,
+    resharding_config: ReshardingConfig,
+) -> TestEnv {
+    genesis.config.epoch_length = epoch_length;
+    genesis.config.chunk_producer_kickout_threshold = 0;
+    genesis.config.dynamic_resharding = true;
+    genesis.config.resharding_config = resharding_config;
+    let chain_genesis = ChainGenesis::from(&genesis);
+    let runtimes: Vec<Arc<dyn RuntimeAdapter>> = (0..num_validators)
+        .map(|_| {
+            Arc::new(nearcore::NightshadeRuntime::test_with_runtime_config_store(
+                Path::new("."),
+                create_test_store(),
+                &genesis,
+                TrackedConfig::AllShards,
+                RuntimeConfigStore::test(),
+            )) as Arc<dyn RuntimeAdapter>
+        })
+        .collect();
+    TestEnv::builder(chain_genesis)
+        .clients_count(num_validators as usize)
+        .validator_seats(num_validators as usize)
+        .runtime_adapters(runtimes)
+        .build()
+}
+
+/// Produces blocks up to `last_height` and processes them on all clients, checking after each
+/// block the number of shards of the next block and that all accounts exist.
+fn run_blocks(
+    env: &mut TestEnv,
+    last_height: u64,
+    accounts: &[AccountId],
+    expected_num_shards: impl Fn(u64) -> u64,
+) {
+    for height in 1..=last_height {
+        let head = env.clients[0].chain.head().unwrap();
+        let epoch_id = env.clients[0]
+            .runtime_adapter
+            .get_epoch_id_from_prev_block(&head.last_block_hash)
+            .unwrap();
+        let block_producer =
+            env.clients[0].runtime_adapter.get_block_producer(&epoch_id, height).unwrap();
+        let block = env.client(&block_producer).produce_block(height).unwrap().unwrap();
+        for i in 0..env.clients.len() {
+            env.process_block_with_options(i, block.clone(), Provenance::NONE, true, true);
+        }
+        env.process_partial_encoded_chunks();
+
+        for client in env.clients.iter() {
+            let shard_layout =
+                client.runtime_adapter.get_shard_layout_from_prev_block(block.hash()).unwrap();
+            assert_eq!(shard_layout.num_shards(), expected_num_shards(height), "height {}", height);
+        }
+        for account_id in accounts.iter() {
+            check_account(env, account_id, &block);
+        }
+    }
+}
+
+/// Checks that the account exists in the state after `block` on all clients.
+fn check_account(env: &mut TestEnv, account_id: &AccountId, block: &Block) {
+    let prev_hash = block.header().prev_hash();
+    for client in env.clients.iter_mut() {
+        let shard_layout =
+            client.runtime_adapter.get_shard_layout_from_prev_block(prev_hash).unwrap();
+        let shard_uid = account_id_to_shard_uid(account_id, &shard_layout);
+        let state_root =
+            *client.chain.get_chunk_extra(block.hash(), &shard_uid).unwrap().state_root();
+        client
+            .runtime_adapter
+            .query(
+                shard_uid,
+                &state_root,
+                block.header().height(),
+                0,
+                prev_hash,
+                block.hash(),
+                block.header().epoch_id(),
+                &QueryRequest::ViewAccount { account_id: account_id.clone() },
+            )
+            .unwrap();
+    }
+}
+
+/// The state of the single shard is first recorded at the start of the second epoch and is above
+/// the limit, so the shard is split at the end of that epoch, starting from the fourth one. One of
+/// its children is split in turn two epochs later, once the state of the children is recorded,
+/// which reaches the limit on the number of shards.
+#[test]
+fn test_dynamic_resharding_splits_overloaded_shards() {
+    init_test_logger();
+    let epoch_length = 5;
+    let num_validators = 2;
+    let accounts: Vec<AccountId> = (0..num_validators)
+        .map(|i| format!("test{}", i))
+        .chain((0..50).map(|i| format!("account{:02}", i)))
+        .map(|account_id| account_id.parse().unwrap())
+        .collect();
+    // Any non-empty state is above the limit, so shards are split as soon as possible.
+    let resharding_config = ReshardingConfig {
+        max_state_size: 0,
+        num_overloaded_epochs_to_split: 1,
+        max_num_shards: 3,
+        ..ReshardingConfig::default()
+    };
+    let genesis = Genesis::test(accounts.clone(), num_validators);
+    let mut env = setup_env(genesis, epoch_length, num_validators, resharding_config);
+
+    run_blocks(&mut env, 7 * epoch_length, &accounts, |height| {
+        if height < 3 * epoch_length {
+            1
+        } else if height < 5 * epoch_length {
+            2
+        } else {
+            3
+        }
+    });
+}
+
+/// Both shards are underloaded as soon as their states are recorded at the start of the second
+/// epoch, so they are merged at the end of that epoch, starting from the fourth one, and the state
+/// of the merged shard holds the accounts of both.
+#[test]
+fn test_dynamic_resharding_merges_underloaded_shards() {
+    init_test_logger();
+    let epoch_length = 5;
+    let num_validators = 2;
+    let accounts: Vec<AccountId> = (0..num_validators)
+        .map(|i| format!("test{}", i))
+        .chain((0..50).map(|i| format!("account{:02}", i)))
+        .map(|account_id| account_id.parse().unwrap())
+        .collect();
+    let resharding_config = ReshardingConfig {
+        min_avg_gas_used_per_chunk: Gas::MAX,
+        min_state_size: u64::MAX,
+        num_underloaded_epochs_to_merge: 1,
+        ..ReshardingConfig::default()
+    };
+    let genesis = Genesis::test_with_seeds(
+        accounts.clone(),
+        num_validators,
+        vec![num_validators, num_validators],
+        ShardLayout::v1(vec![], vec!["account25".parse().unwrap()], None, 1),
+    );
+    let mut env = setup_env(genesis, epoch_length, num_validators, resharding_config);
+
+    run_blocks(&mut env, 5 * epoch_length, &accounts, |height| {
+        if height < 3 * epoch_length {
+            2
+        } else {
+            1
+        }
+    });
+}
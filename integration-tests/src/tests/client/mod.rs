@@ -1,5 +1,8 @@
 mod challenges;
 mod chunks_management;
+#[cfg(all(feature = "protocol_feature_dynamic_resharding", feature = "nightly_protocol"))]
+mod dynamic_resharding;
+mod network_simulator;
 mod process_blocks;
 mod runtimes;
 #[cfg(feature = "sandbox")]
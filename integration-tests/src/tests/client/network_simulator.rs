@@ -0,0 +1,86 @@
+use std::time::Duration;
+
+use chrono::{TimeZone, Utc};
+use near_chain::ChainGenesis;
+use near_client::network_simulator::{LinkConfig, NetworkSimulator, SimulatorStats};
+use near_client::test_utils::TestEnv;
+use near_logger_utils::init_test_logger;
+use near_primitives::hash::CryptoHash;
+
+fn setup_simulator(num_nodes: usize, seed: u64) -> NetworkSimulator {
+    let mut chain_genesis = ChainGenesis::test();
+    chain_genesis.time = Utc.ymd(2021, 1, 1).and_hms(0, 0, 0);
+    let env =
+        TestEnv::builder(chain_genesis).clients_count(num_nodes).validator_seats(num_nodes).build();
+    NetworkSimulator::new(env, seed)
+}
+
+fn heads(sim: &NetworkSimulator) -> Vec<CryptoHash> {
+    sim.env.clients.iter().map(|client| client.chain.head().unwrap().last_block_hash).collect()
+}
+
+/// Produces blocks over lossy links with jitter, and returns the resulting heads and stats.
+fn run_lossy_network(seed: u64) -> (Vec<CryptoHash>, SimulatorStats) {
+    let mut sim = setup_simulator(8, seed);
+    sim.set_default_link(LinkConfig {
+        latency: Duration::from_millis(10),
+        jitter: Duration::from_millis(200),
+        loss: 0.1,
+    });
+    for height in 1..=30 {
+        sim.produce_block(height);
+        sim.run_for(Duration::from_millis(100));
+    }
+    sim.run_until_idle();
+    // Blocks are timestamped with the virtual time.
+    let genesis_time = sim.env.clients[0].chain.genesis().timestamp();
+    for client in sim.env.clients.iter_mut() {
+        let timestamp = client.chain.head_header().unwrap().timestamp();
+        assert!(genesis_time < timestamp && timestamp <= sim.utc());
+    }
+    (heads(&sim), sim.stats().clone())
+}
+
+/// Two runs with the same seed end up in the same state.
+#[test]
+fn test_simulator_deterministic() {
+    init_test_logger();
+    let (heads1, stats1) = run_lossy_network(42);
+    let (heads2, stats2) = run_lossy_network(42);
+    assert_eq!(heads1, heads2);
+    assert_eq!(stats1, stats2);
+    assert!(stats1.lost > 0);
+}
+
+/// Nodes on both sides of a partition build their own forks, and agree on one once the
+/// partition heals.
+#[test]
+fn test_simulator_partition() {
+    init_test_logger();
+    let mut sim = setup_simulator(4, 0);
+    for height in 1..=5 {
+        sim.produce_block(height);
+        sim.run_until_idle();
+    }
+    let before = heads(&sim);
+    assert!(before.iter().all(|head| *head == before[0]));
+
+    sim.partition(&[&[0, 1], &[2, 3]]);
+    for height in 6..=15 {
+        sim.produce_block(height);
+        sim.run_until_idle();
+    }
+    let partitioned = heads(&sim);
+    assert_eq!(partitioned[0], partitioned[1]);
+    assert_eq!(partitioned[2], partitioned[3]);
+    assert_ne!(partitioned[0], partitioned[2]);
+
+    sim.heal();
+    for height in 16..=25 {
+        sim.produce_block(height);
+        sim.run_until_idle();
+    }
+    let healed = heads(&sim);
+    assert!(healed.iter().all(|head| *head == healed[0]));
+    assert_eq!(sim.env.clients[0].chain.head().unwrap().height, 25);
+}
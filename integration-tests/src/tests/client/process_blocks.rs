@@ -11,8 +11,13 @@ use near_primitives::num_rational::Rational;
 
 use near_actix_test_utils::run_actix;
 use near_chain::chain::{ApplyStatePartsRequest, NUM_EPOCHS_TO_KEEP_STORE_DATA};
+use near_chain::signing_history::{
+    get_signing_history, import_signing_history, SignedData, SigningHistory, SigningHistoryRecord,
+};
 use near_chain::types::LatestKnown;
-use near_chain::validate::validate_chunk_with_chunk_extra;
+use near_chain::validate::{
+    validate_chunk_with_chunk_extra, validate_chunk_with_chunk_extra_and_receipts_root,
+};
 use near_chain::{
     Block, ChainGenesis, ChainStore, ChainStoreAccess, ErrorKind, Provenance, RuntimeAdapter,
 };
@@ -33,6 +38,7 @@ use near_network::types::{NetworkInfo, PeerManagerMessageRequest, PeerManagerMes
 use near_network_primitives::types::{PeerChainInfoV2, PeerInfo, ReasonForBan};
 use near_primitives::block::{Approval, ApprovalInner};
 use near_primitives::block_header::BlockHeader;
+use near_primitives::challenge::PartialState;
 use near_primitives::epoch_manager::RngSeed;
 use near_primitives::errors::InvalidTxError;
 use near_primitives::errors::TxExecutionError;
@@ -43,8 +49,8 @@ use near_primitives::runtime::config::RuntimeConfig;
 use near_primitives::runtime::config_store::RuntimeConfigStore;
 use near_primitives::shard_layout::ShardUId;
 use near_primitives::sharding::{
-    EncodedShardChunk, ReedSolomonWrapper, ShardChunkHeader, ShardChunkHeaderInner,
-    ShardChunkHeaderV3,
+    ChunkStateWitness, EncodedShardChunk, ReedSolomonWrapper, ShardChunkHeader,
+    ShardChunkHeaderInner, ShardChunkHeaderV3,
 };
 use near_primitives::syncing::{get_num_state_parts, ShardStateSyncResponseHeader, StatePartKey};
 use near_primitives::transaction::{
@@ -59,7 +65,8 @@ use near_primitives::validator_signer::{InMemoryValidatorSigner, ValidatorSigner
 use near_primitives::version::ProtocolFeature;
 use near_primitives::version::PROTOCOL_VERSION;
 use near_primitives::views::{
-    BlockHeaderView, FinalExecutionStatus, QueryRequest, QueryResponseKind,
+    BlockHeaderView, BlockProductionView, FinalExecutionStatus, ProductionStatusView, QueryRequest,
+    QueryResponseKind,
 };
 use near_store::db::DBCol::ColStateParts;
 use near_store::get;
@@ -377,7 +384,8 @@ fn receive_network_block() {
                 &signer,
                 last_block.header.next_bp_hash,
                 block_merkle_tree.root(),
-            );
+            )
+            .unwrap();
             client.do_send(NetworkClientMessages::Block(block, PeerInfo::random().id, false));
             future::ready(())
         }));
@@ -453,7 +461,8 @@ fn produce_block_with_approvals() {
                 &signer1,
                 last_block.header.next_bp_hash,
                 block_merkle_tree.root(),
-            );
+            )
+            .unwrap();
             client.do_send(NetworkClientMessages::Block(
                 block.clone(),
                 PeerInfo::random().id,
@@ -474,7 +483,8 @@ fn produce_block_with_approvals() {
                     block.header().height(),
                     10, // the height at which "test1" is producing
                     &signer,
-                );
+                )
+                .unwrap();
                 client
                     .do_send(NetworkClientMessages::BlockApproval(approval, PeerInfo::random().id));
             }
@@ -642,7 +652,8 @@ fn invalid_blocks_common(is_requested: bool) {
                 &signer,
                 last_block.header.next_bp_hash,
                 block_merkle_tree.root(),
-            );
+            )
+            .unwrap();
             // Send block with invalid chunk mask
             let mut block = valid_block.clone();
             block.mut_header().get_mut().inner_rest.chunk_mask = vec![];
@@ -975,6 +986,8 @@ fn client_sync_headers() {
             received_bytes_per_sec: 0,
             known_producers: vec![],
             peer_counter: 0,
+            peer_scores: Default::default(),
+            private_peers: HashSet::new(),
         }));
         wait_or_panic(2000);
     });
@@ -1112,7 +1125,8 @@ fn test_invalid_approvals() {
                     KeyType::ED25519,
                     account_id.as_ref(),
                 )
-                .sign_approval(&ApprovalInner::Endorsement(*genesis.hash()), 1),
+                .sign_approval(&ApprovalInner::Endorsement(*genesis.hash()), 1)
+                .unwrap(),
             )
         })
         .collect();
@@ -1661,6 +1675,115 @@ fn test_tx_forwarding_no_double_forwarding() {
     assert!(env.network_adapters[0].requests.read().unwrap().is_empty());
 }
 
+/// A validator restakes with a new key while the node has it as the pending key. The node should
+/// keep producing blocks with the old key until the new one takes effect and then switch to it.
+#[test]
+fn test_validator_key_rotation() {
+    let epoch_length = 5;
+    let mut genesis = Genesis::test(vec!["test0".parse().unwrap()], 1);
+    genesis.config.epoch_length = epoch_length;
+    let mut chain_genesis = ChainGenesis::test();
+    chain_genesis.epoch_length = epoch_length;
+    let mut env = TestEnv::builder(chain_genesis)
+        .runtime_adapters(create_nightshade_runtimes(&genesis, 1))
+        .build();
+    let genesis_hash = *env.clients[0].chain.genesis().hash();
+    let old_public_key = env.clients[0].validator_signer.as_ref().unwrap().public_key();
+    let new_signer = Arc::new(InMemoryValidatorSigner::from_seed(
+        "test0".parse().unwrap(),
+        KeyType::ED25519,
+        "test0_new",
+    ));
+
+    let wrong_account_signer = Arc::new(InMemoryValidatorSigner::from_seed(
+        "test1".parse().unwrap(),
+        KeyType::ED25519,
+        "test1",
+    ));
+    assert!(env.clients[0].set_pending_validator_signer(wrong_account_signer).is_err());
+    env.clients[0].set_pending_validator_signer(new_signer.clone()).unwrap();
+
+    let signer = InMemorySigner::from_seed("test0".parse().unwrap(), KeyType::ED25519, "test0");
+    let tx = SignedTransaction::stake(
+        1,
+        "test0".parse().unwrap(),
+        &signer,
+        TESTING_INIT_STAKE,
+        new_signer.public_key(),
+        genesis_hash,
+    );
+    env.clients[0].process_tx(tx, false, false);
+
+    let mut switch_height = None;
+    for height in 1..=4 * epoch_length {
+        // Block production fails if the client signs with a key other than the one in the
+        // epoch info.
+        env.produce_block(0, height);
+        let head = env.clients[0].chain.head().unwrap();
+        let (validator_stake, _) = env.clients[0]
+            .runtime_adapter
+            .get_validator_by_account_id(
+                &head.epoch_id,
+                &head.last_block_hash,
+                &"test0".parse().unwrap(),
+            )
+            .unwrap();
+        let public_key = env.clients[0].validator_signer.as_ref().unwrap().public_key();
+        assert_eq!(public_key, validator_stake.take_public_key());
+        if public_key != old_public_key && switch_height.is_none() {
+            switch_height = Some(height);
+        }
+    }
+    assert!(switch_height.unwrap() > epoch_length);
+    assert!(env.clients[0].pending_validator_signer.is_none());
+    assert_eq!(
+        env.clients[0].validator_signer.as_ref().unwrap().public_key(),
+        new_signer.public_key()
+    );
+}
+
+/// Blocks, chunks and approvals conflicting with the signing history are refused.
+#[test]
+fn test_signing_history() {
+    let genesis = Genesis::test(vec!["test0".parse().unwrap()], 1);
+    let mut env = TestEnv::builder(ChainGenesis::test())
+        .runtime_adapters(create_nightshade_runtimes(&genesis, 1))
+        .build();
+    env.clients[0].config.signing_history = true;
+    for height in 1..=3 {
+        env.produce_block(0, height);
+    }
+    let account_id: AccountId = "test0".parse().unwrap();
+    let store = env.clients[0].chain.store().owned_store().clone();
+    let history = get_signing_history(&store, &account_id).unwrap();
+    assert_eq!(history.last_block.unwrap().height, 3);
+    assert_eq!(history.last_chunks[&0].height, 4);
+
+    // Resending an approval is fine, a different one for the same target height is not.
+    let signer = env.clients[0].validator_signer.clone().unwrap();
+    let head = env.clients[0].chain.head().unwrap();
+    let endorsement =
+        Approval::new(head.last_block_hash, head.height, head.height + 1, &*signer).unwrap();
+    env.clients[0].send_approval(&head.last_block_hash, endorsement.clone()).unwrap();
+    env.clients[0].send_approval(&head.last_block_hash, endorsement).unwrap();
+    let skip =
+        Approval::new(head.prev_block_hash, head.height - 1, head.height + 1, &*signer).unwrap();
+    assert!(env.clients[0].send_approval(&head.last_block_hash, skip).is_err());
+
+    // The validator has signed blocks up to height 10 on another machine.
+    let imported = SigningHistory {
+        last_block: Some(SignedData { height: 10, hash: hash(&[1]) }),
+        ..Default::default()
+    };
+    import_signing_history(
+        &store,
+        vec![SigningHistoryRecord { account_id: account_id.clone(), history: imported }],
+    )
+    .unwrap();
+    assert!(env.clients[0].produce_block(4).is_err());
+    assert_eq!(get_signing_history(&store, &account_id).unwrap().last_block.unwrap().height, 10);
+}
+
 #[test]
 fn test_tx_forward_around_epoch_boundary() {
     let epoch_length = 4;
@@ -2261,6 +2384,109 @@ fn test_validate_chunk_extra() {
     .is_ok());
 }
 
+/// Applying a chunk from its state witness gives the results the next chunk commits to, without
+/// the state of the shard, and witnesses missing trie nodes or receipts are rejected.
+#[test]
+fn test_apply_chunk_state_witness() {
+    let mut genesis = Genesis::test(vec!["test0".parse().unwrap(), "test1".parse().unwrap()], 1);
+    genesis.config.epoch_length = 10;
+    let mut env = TestEnv::builder(ChainGenesis::test())
+        .runtime_adapters(create_nightshade_runtimes(&genesis, 1))
+        .build();
+    let genesis_hash = *env.clients[0].chain.genesis().hash();
+    let signer = InMemorySigner::from_seed("test0".parse().unwrap(), KeyType::ED25519, "test0");
+    let tx = SignedTransaction::send_money(
+        1,
+        "test0".parse().unwrap(),
+        "test1".parse().unwrap(),
+        &signer,
+        100,
+        genesis_hash,
+    );
+    env.clients[0].process_tx(tx, false, false);
+    for i in 1..=4 {
+        env.produce_block(0, i);
+    }
+
+    let mut witnesses = vec![];
+    for height in 1..=3 {
+        let client = &mut env.clients[0];
+        let block = client.chain.get_block_by_height(height).unwrap().clone();
+        let next_block = client.chain.get_block_by_height(height + 1).unwrap().clone();
+        let witness = client.chain.create_chunk_state_witness(&block, 0).unwrap();
+        let result = client.chain.apply_chunk_state_witness(&witness).unwrap();
+        let shard_uid =
+            client.runtime_adapter.shard_id_to_uid(0, block.header().epoch_id()).unwrap();
+        assert_eq!(
+            &result.chunk_extra,
+            client.chain.get_chunk_extra(block.hash(), &shard_uid).unwrap()
+        );
+        validate_chunk_with_chunk_extra_and_receipts_root(
+            &result.chunk_extra,
+            &result.outgoing_receipts_root,
+            &next_block.chunks()[0],
+        )
+        .unwrap();
+        witnesses.push(witness);
+    }
+
+    let chain = &mut env.clients[0].chain;
+    let witness = witnesses.into_iter().find(|witness| !witness.transactions.is_empty()).unwrap();
+    let mut bad_witness = witness.clone();
+    bad_witness.partial_state = PartialState(vec![]);
+    assert_eq!(
+        chain.apply_chunk_state_witness(&bad_witness).unwrap_err().kind(),
+        ErrorKind::InvalidChunkStateWitness
+    );
+    let mut bad_witness = witness.clone();
+    bad_witness.incoming_receipts.pop();
+    assert_eq!(
+        chain.apply_chunk_state_witness(&bad_witness).unwrap_err().kind(),
+        ErrorKind::InvalidChunkStateWitness
+    );
+    let mut bad_witness = witness;
+    bad_witness.transactions.clear();
+    assert_eq!(
+        chain.apply_chunk_state_witness(&bad_witness).unwrap_err().kind(),
+        ErrorKind::InvalidChunkStateWitness
+    );
+}
+
+/// State witnesses are only kept if they are signed by the producer of their chunk.
+#[test]
+fn test_process_chunk_state_witness_signature() {
+    let genesis = Genesis::test(vec!["test0".parse().unwrap(), "test1".parse().unwrap()], 1);
+    let mut env = TestEnv::builder(ChainGenesis::test())
+        .runtime_adapters(create_nightshade_runtimes(&genesis, 1))
+        .build();
+    env.produce_block(0, 1);
+    let chain = &mut env.clients[0].chain;
+    let block = chain.get_block_by_height(1).unwrap().clone();
+    let inner = chain.create_chunk_state_witness(&block, 0).unwrap();
+    let chunk_producer =
+        InMemoryValidatorSigner::from_seed("test0".parse().unwrap(), KeyType::ED25519, "test0");
+    let other_signer =
+        InMemoryValidatorSigner::from_seed("test1".parse().unwrap(), KeyType::ED25519, "test1");
+
+    let witness = ChunkStateWitness::new(inner.clone(), &other_signer).unwrap();
+    assert_eq!(
+        chain.process_chunk_state_witness(witness).unwrap_err().kind(),
+        ErrorKind::InvalidChunkStateWitness
+    );
+    let mut witness = ChunkStateWitness::new(inner.clone(), &chunk_producer).unwrap();
+    witness.inner.incoming_receipts.clear();
+    assert_eq!(
+        chain.process_chunk_state_witness(witness).unwrap_err().kind(),
+        ErrorKind::InvalidChunkStateWitness
+    );
+    assert!(chain.store().get_chunk_state_witness(block.hash(), 0).is_none());
+
+    chain
+        .process_chunk_state_witness(ChunkStateWitness::new(inner, &chunk_producer).unwrap())
+        .unwrap();
+    assert!(chain.store().get_chunk_state_witness(block.hash(), 0).is_some());
+}
+
 /// Change protocol version back and forth and make sure that we do not produce invalid blocks
 /// TODO (#3759): re-enable the test when we have the ability to mutate `PROTOCOL_VERSION`
 #[test]
@@ -3084,6 +3310,95 @@ fn prepare_env_with_transaction() -> (TestEnv, CryptoHash) {
     (env, tx_hash)
 }
 
+#[test]
+fn test_validator_performance() {
+    let epoch_length = 10;
+    let mut genesis = Genesis::test(vec!["test0".parse().unwrap(), "test1".parse().unwrap()], 1);
+    genesis.config.epoch_length = epoch_length;
+    let mut env = TestEnv::builder(ChainGenesis::test())
+        .runtime_adapters(create_nightshade_runtimes(&genesis, 1))
+        .build();
+    for height in 1..3 {
+        env.produce_block(0, height);
+    }
+    // Block 3 only arrives after block 4 was built on its parent.
+    let late_block = env.clients[0].produce_block(3).unwrap().unwrap();
+    env.produce_block(0, 4);
+    env.process_block(0, late_block, Provenance::NONE);
+    // Nobody produces block 5.
+    env.produce_block(0, 6);
+
+    let account_id: AccountId = "test0".parse().unwrap();
+    let head = env.clients[0].chain.head().unwrap();
+    let epoch_start_height =
+        env.clients[0].runtime_adapter.get_epoch_start_height(&head.last_block_hash).unwrap();
+    let performance = env.clients[0]
+        .chain
+        .get_validator_performance(&head.epoch_id, epoch_start_height, &account_id)
+        .unwrap();
+    assert_eq!(performance.epoch_start_height, 1);
+    assert_eq!(performance.last_block_height, 6);
+    assert_eq!(
+        performance.expected_blocks,
+        vec![
+            BlockProductionView { height: 1, status: ProductionStatusView::Produced },
+            BlockProductionView { height: 2, status: ProductionStatusView::Produced },
+            BlockProductionView { height: 3, status: ProductionStatusView::Late },
+            BlockProductionView { height: 4, status: ProductionStatusView::Produced },
+            BlockProductionView { height: 5, status: ProductionStatusView::NotProduced },
+            BlockProductionView { height: 6, status: ProductionStatusView::Produced },
+        ]
+    );
+    // Chunks are only expected in the blocks of the canonical chain.
+    assert_eq!(
+        performance.expected_chunks.iter().map(|chunk| chunk.height).collect::<Vec<_>>(),
+        vec![1, 2, 4, 6]
+    );
+}
+
+/// Performance in a past epoch stops at its last block, and is unavailable once the blocks of the
+/// epoch are garbage collected.
+#[test]
+fn test_validator_performance_past_epochs() {
+    let epoch_length = 5;
+    let mut genesis = Genesis::test(vec!["test0".parse().unwrap(), "test1".parse().unwrap()], 1);
+    genesis.config.epoch_length = epoch_length;
+    let mut chain_genesis = ChainGenesis::test();
+    chain_genesis.epoch_length = epoch_length;
+    let mut env = TestEnv::builder(chain_genesis)
+        .runtime_adapters(create_nightshade_runtimes(&genesis, 1))
+        .build();
+    for height in 1..=epoch_length * (NUM_EPOCHS_TO_KEEP_STORE_DATA + 1) {
+        env.produce_block(0, height);
+    }
+
+    let account_id: AccountId = "test0".parse().unwrap();
+    let performance_at = |env: &mut TestEnv, height: BlockHeight| {
+        let header = env.clients[0].chain.get_header_by_height(height).unwrap().clone();
+        let epoch_start_height =
+            env.clients[0].runtime_adapter.get_epoch_start_height(header.hash()).unwrap();
+        env.clients[0].chain.get_validator_performance(
+            header.epoch_id(),
+            epoch_start_height,
+            &account_id,
+        )
+    };
+
+    let performance = performance_at(&mut env, 2 * epoch_length + 1).unwrap();
+    assert_eq!(performance.epoch_start_height, 2 * epoch_length + 1);
+    assert_eq!(performance.last_block_height, 3 * epoch_length);
+    assert_eq!(
+        performance.expected_blocks.iter().map(|block| block.height).collect::<Vec<_>>(),
+        (2 * epoch_length + 1..=3 * epoch_length).collect::<Vec<_>>()
+    );
+    assert!(performance
+        .expected_blocks
+        .iter()
+        .all(|block| block.status == ProductionStatusView::Produced));
+
+    assert!(performance_at(&mut env, 1).is_err());
+}
+
 #[test]
 fn test_not_broadcast_block_on_accept() {
     let epoch_length = 5;
@@ -3133,11 +3448,13 @@ fn test_header_version_downgrade() {
         match header {
             BlockHeader::BlockHeaderV1(ref mut header) => {
                 header.inner_rest.latest_protocol_version = PROTOCOL_VERSION;
-                let (hash, signature) = validator_signer.sign_block_header_parts(
-                    header.prev_hash,
-                    &header.inner_lite.try_to_vec().expect("Failed to serialize"),
-                    &header.inner_rest.try_to_vec().expect("Failed to serialize"),
-                );
+                let (hash, signature) = validator_signer
+                    .sign_block_header_parts(
+                        header.prev_hash,
+                        &header.inner_lite.try_to_vec().expect("Failed to serialize"),
+                        &header.inner_rest.try_to_vec().expect("Failed to serialize"),
+                    )
+                    .unwrap();
                 header.hash = hash;
                 header.signature = signature;
             }
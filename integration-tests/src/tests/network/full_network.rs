@@ -1,4 +1,6 @@
 pub use crate::tests::network::runner::*;
+#[cfg(feature = "protocol_feature_quic_transport")]
+use near_network_primitives::types::Transport;
 use std::cmp::min;
 
 /// Check that a node is able to connect to the network, even if the number
@@ -72,3 +74,22 @@ fn connect_on_almost_full_network() {
 fn connect_on_full_network() {
     connect_at_max_capacity(5, 2, 3, 4, 2, 1);
 }
+
+/// Check that nodes using QUIC connect with each other, and over TCP with a node which doesn't
+/// support QUIC.
+#[test]
+#[cfg(feature = "protocol_feature_quic_transport")]
+fn quic_network() {
+    let mut runner = Runner::new(3, 3)
+        .enable_outbound()
+        .use_boot_nodes(vec![0, 1, 2])
+        .set_transport(0, Transport::Quic)
+        .set_transport(1, Transport::Quic)
+        .set_transport(2, Transport::Tcp);
+
+    for node_id in 0..3 {
+        runner.push_action(check_expected_connections(node_id, Some(2), None));
+    }
+
+    start_test(runner);
+}
@@ -23,6 +23,8 @@ use near_primitives::block::GenesisId;
 use near_primitives::network::{AnnounceAccount, PeerId};
 use near_store::test_utils::create_test_store;
 
+use crate::tests::network::runner::test_transport;
+
 /// Make Peer Manager with mocked client ready to accept any announce account.
 /// Used for `test_infinite_loop`
 pub fn make_peer_manager(
@@ -33,6 +35,7 @@ pub fn make_peer_manager(
 ) -> (PeerManagerActor, PeerId, Arc<AtomicUsize>) {
     let store = create_test_store();
     let mut config = NetworkConfig::from_seed(seed, port);
+    config.transport = test_transport();
     config.boot_nodes = convert_boot_nodes(boot_nodes);
     config.max_num_peers = peer_max_count;
     let counter = Arc::new(AtomicUsize::new(0));
@@ -40,6 +40,7 @@ fn make_peer_manager(
 ) -> PeerManagerActor {
     let store = create_test_store();
     let mut config = NetworkConfig::from_seed(seed, port);
+    config.transport = test_transport();
     config.boot_nodes = convert_boot_nodes(boot_nodes);
     config.max_num_peers = peer_max_count;
     let client_addr = ClientMock::mock(Box::new(move |_msg, _ctx| {
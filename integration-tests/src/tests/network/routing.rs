@@ -229,6 +229,20 @@ fn blacklist_all() {
     start_test(runner);
 }
 
+/// Node 0 only accepts connections with its trusted node 1, and reaches node 2 through it.
+#[test]
+fn trusted_peers() {
+    let mut runner = Runner::new(3, 1)
+        .set_trusted_peers(0, vec![1])
+        .use_boot_nodes(vec![0, 1])
+        .enable_outbound();
+
+    runner.push(Action::CheckRoutingTable(0, vec![(1, vec![1]), (2, vec![1])]));
+    runner.push(Action::CheckRoutingTable(2, vec![(1, vec![1]), (0, vec![1])]));
+
+    start_test(runner);
+}
+
 /// Spawn 4 nodes with max peers required equal 2. Connect first three peers in a triangle.
 /// Try to connect peer3 to peer0 and see it fail since first three peer are at max capacity.
 #[test]
@@ -250,6 +264,23 @@ fn max_num_peers_limit() {
     start_test(runner);
 }
 
+/// Same as `max_num_peers_limit`, but peer3 is a private peer of peer0, such as a validator behind
+/// a sentry node, which doesn't count against the limit.
+#[test]
+fn max_num_peers_limit_private_peer() {
+    let mut runner =
+        Runner::new(4, 4).max_num_peers(2).set_private_peers(0, vec![3]).enable_outbound();
+
+    runner.push(Action::AddEdge(0, 1));
+    runner.push(Action::AddEdge(1, 2));
+    runner.push(Action::CheckRoutingTable(0, vec![(1, vec![1]), (2, vec![2])]));
+    runner.push(Action::AddEdge(3, 0));
+    runner.push(Action::CheckRoutingTable(0, vec![(1, vec![1]), (2, vec![2]), (3, vec![3])]));
+    runner.push(Action::CheckRoutingTable(3, vec![(0, vec![0]), (1, vec![0]), (2, vec![0])]));
+
+    start_test(runner);
+}
+
 /// Check that two archival nodes keep connected after network rebalance. Nodes 0 and 1 are archival nodes, others aren't.
 /// Initially connect 2, 3, 4 to 0. Then connect 1 to 0, this connection should persist, even after other nodes tries
 /// to connect to node 0 again.
@@ -30,13 +30,13 @@ use near_network::types::{PeerManagerMessageRequest, PeerManagerMessageResponse}
 use near_network::PeerManagerActor;
 use near_network_primitives::types::blacklist_from_iter;
 use near_network_primitives::types::{
-    NetworkConfig, OutboundTcpConnect, PeerInfo, ROUTED_MESSAGE_TTL,
+    NetworkConfig, OutboundTcpConnect, PeerInfo, Transport, ROUTED_MESSAGE_TTL,
 };
 use near_primitives::network::PeerId;
 use near_primitives::types::{AccountId, ValidatorId};
 use near_primitives::validator_signer::InMemoryValidatorSigner;
 use near_store::test_utils::create_test_store;
-use near_telemetry::{TelemetryActor, TelemetryConfig};
+use near_telemetry::TelemetryActor;
 
 pub type SharedRunningInfo = Arc<RwLock<RunningInfo>>;
 
@@ -62,7 +62,7 @@ pub fn setup_network_node(
         KeyType::ED25519,
         account_id.as_ref(),
     ));
-    let telemetry_actor = TelemetryActor::new(TelemetryConfig::default()).start();
+    let telemetry_actor = TelemetryActor::default().start();
     let mut chain_genesis = ChainGenesis::test();
     chain_genesis.time = genesis_time;
 
@@ -462,6 +462,9 @@ struct TestConfig {
     minimum_outbound_peers: Option<u32>,
     safe_set_size: Option<u32>,
     archive: bool,
+    transport: Transport,
+    trusted_peers: Vec<usize>,
+    private_peers: Vec<usize>,
 }
 
 impl TestConfig {
@@ -477,6 +480,9 @@ impl TestConfig {
             minimum_outbound_peers: None,
             safe_set_size: None,
             archive: false,
+            transport: test_transport(),
+            trusted_peers: vec![],
+            private_peers: vec![],
         }
     }
 }
@@ -531,6 +537,25 @@ impl Runner {
         self
     }
 
+    /// Set the transport of node `u`. By default it is given by `test_transport`.
+    #[cfg(feature = "protocol_feature_quic_transport")]
+    pub fn set_transport(mut self, u: usize, transport: Transport) -> Self {
+        self.test_config[u].transport = transport;
+        self
+    }
+
+    /// Make node `u` only connect to `trusted_peers`.
+    pub fn set_trusted_peers(mut self, u: usize, trusted_peers: Vec<usize>) -> Self {
+        self.test_config[u].trusted_peers = trusted_peers;
+        self
+    }
+
+    /// Make node `u` keep the addresses of `private_peers` for itself.
+    pub fn set_private_peers(mut self, u: usize, private_peers: Vec<usize>) -> Self {
+        self.test_config[u].private_peers = private_peers;
+        self
+    }
+
     /// Specify boot nodes. By default there are no boot nodes.
     pub fn use_boot_nodes(mut self, boot_nodes: Vec<usize>) -> Self {
         self.apply_all(move |test_config| {
@@ -646,6 +671,17 @@ impl Runner {
         network_config.outbound_disabled = test_config.outbound_disabled;
         network_config.boot_nodes = boot_nodes;
         network_config.archive = test_config.archive;
+        network_config.transport = test_config.transport;
+        network_config.trusted_peers = convert_boot_nodes(
+            test_config
+                .trusted_peers
+                .iter()
+                .map(|ix| (accounts_id[*ix].as_ref(), ports[*ix]))
+                .collect(),
+        );
+        network_config.private_peers = (test_config.private_peers.iter())
+            .map(|ix| peer_id_from_seed(accounts_id[*ix].as_ref()))
+            .collect();
 
         network_config.ideal_connections_lo =
             test_config.ideal_connections.map_or(network_config.ideal_connections_lo, |(lo, _)| lo);
@@ -695,6 +731,16 @@ impl Runner {
     }
 }
 
+/// Transport of the nodes in network tests. Tests run over TCP, unless the
+/// `NEAR_TEST_NETWORK_TRANSPORT` environment variable is set to `quic`, as CI does in a separate
+/// step. Builds without `protocol_feature_quic_transport` use TCP anyway.
+pub fn test_transport() -> Transport {
+    match std::env::var("NEAR_TEST_NETWORK_TRANSPORT").as_deref() {
+        Ok("quic") => Transport::Quic,
+        _ => Transport::Tcp,
+    }
+}
+
 /// Use to start running the test.
 /// It will fail if it doesn't solve all actions.
 pub fn start_test(runner: Runner) {
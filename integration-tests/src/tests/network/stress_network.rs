@@ -23,12 +23,15 @@ use near_network_primitives::types::{
 use near_primitives::network::PeerId;
 use near_store::test_utils::create_test_store;
 
+use crate::tests::network::runner::test_transport;
+
 type ClientMock = Mocker<ClientActor>;
 type ViewClientMock = Mocker<ViewClientActor>;
 
 fn make_peer_manager(seed: &str, port: u16, boot_nodes: Vec<(&str, u16)>) -> PeerManagerActor {
     let store = create_test_store();
     let mut config = NetworkConfig::from_seed(seed, port);
+    config.transport = test_transport();
     config.boot_nodes = convert_boot_nodes(boot_nodes);
     let client_addr = ClientMock::mock(Box::new(move |_msg, _ctx| {
         Box::new(Some(NetworkClientResponses::NoResponse))
@@ -1,6 +1,8 @@
+use std::collections::HashMap;
 use std::fs;
 use std::fs::File;
 use std::io::{Read, Write};
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::sync::Arc;
@@ -26,9 +28,14 @@ use near_crypto::{InMemorySigner, KeyFile, KeyType, PublicKey, Signer};
 use near_jsonrpc::RpcConfig;
 use near_network::test_utils::open_port;
 use near_network_primitives::types::blacklist_from_iter;
-use near_network_primitives::types::{NetworkConfig, ROUTED_MESSAGE_TTL};
+use near_network_primitives::types::{
+    NetworkConfig, PortMapping, RateLimit, Transport, ROUTED_MESSAGE_TTL,
+};
 use near_primitives::account::{AccessKey, Account};
 use near_primitives::hash::CryptoHash;
+use near_primitives::network::PeerId;
+#[cfg(unix)]
+use near_primitives::remote_signer::RemoteValidatorSigner;
 #[cfg(test)]
 use near_primitives::shard_layout::account_id_to_shard_id;
 use near_primitives::shard_layout::ShardLayout;
@@ -141,6 +148,7 @@ pub const CONFIG_FILENAME: &str = "config.json";
 pub const GENESIS_CONFIG_FILENAME: &str = "genesis.json";
 pub const NODE_KEY_FILE: &str = "node_key.json";
 pub const VALIDATOR_KEY_FILE: &str = "validator_key.json";
+pub const PENDING_VALIDATOR_KEY_FILE: &str = "pending_validator_key.json";
 
 pub const MAINNET_TELEMETRY_URL: &str = "https://explorer.mainnet.near.org/api/nodes";
 pub const NETWORK_TELEMETRY_URL: &str = "https://explorer.{}.near.org/api/nodes";
@@ -198,12 +206,17 @@ fn default_peer_stats_period() -> Duration {
     Duration::from_secs(5)
 }
 
+fn default_encrypted_transport() -> bool {
+    true
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Network {
     /// Address to listen for incoming connections.
     pub addr: String,
-    /// Address to advertise to peers for them to connect.
-    /// If empty, will use the same port as the addr, and will introspect on the listener.
+    /// Address to advertise to peers for them to connect, as `ip:port` or `host:port`.
+    /// If empty, will use the same port as the addr, and will learn the IP from the gateway with
+    /// `port_mapping` or from the addresses peers see this node connecting from.
     pub external_address: String,
     /// Comma separated list of nodes to connect to.
     pub boot_nodes: String,
@@ -248,6 +261,76 @@ pub struct Network {
     /// Period to check on peer status
     #[serde(default = "default_peer_stats_period")]
     pub peer_stats_period: Duration,
+    /// Offer an encrypted session on outbound connections.
+    #[serde(default = "default_encrypted_transport")]
+    pub encrypted_transport: bool,
+    /// Refuse plaintext connections. Requires `encrypted_transport`.
+    #[serde(default)]
+    pub require_encrypted_transport: bool,
+    /// Compress sent messages of at least this size (in bytes), for peers which support it.
+    #[serde(default)]
+    pub compression_threshold: Option<usize>,
+    /// Limits of messages received from and sent to each peer, by message type, e.g.
+    /// `StateRequestPart` or `BlockRequest`. Consensus messages are never limited.
+    #[serde(default)]
+    pub message_rate_limits: HashMap<String, MessageRateLimit>,
+    /// Transport of connections with peers, `tcp` or `quic`. Builds without
+    /// `protocol_feature_quic_transport` use TCP either way.
+    #[serde(default)]
+    pub transport: NetworkTransport,
+    /// Mapping of the listening port on the local gateway, `disabled`, `upnp` or
+    /// `{"nat_pmp": {"gateway": "192.168.1.1"}}`. Only builds with
+    /// `protocol_feature_observed_address` map the port.
+    #[serde(default)]
+    pub port_mapping: NetworkPortMapping,
+    /// Comma separated list of the only nodes to connect to, in the same format as `boot_nodes`.
+    /// If set, connections with all other nodes are refused. Used by validators which are only
+    /// reachable through their sentry nodes.
+    #[serde(default)]
+    pub trusted_peers: String,
+    /// Peer ids whose addresses are never shared with other peers, such as validators behind this
+    /// sentry node.
+    #[serde(default)]
+    pub private_peers: Vec<PeerId>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum NetworkTransport {
+    Tcp,
+    /// Also accepts TCP connections, and falls back to TCP for peers which don't support QUIC.
+    Quic,
+}
+
+impl Default for NetworkTransport {
+    fn default() -> Self {
+        NetworkTransport::Tcp
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum NetworkPortMapping {
+    Disabled,
+    Upnp,
+    NatPmp { gateway: IpAddr },
+}
+
+impl Default for NetworkPortMapping {
+    fn default() -> Self {
+        NetworkPortMapping::Disabled
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct MessageRateLimit {
+    /// Sustained number of messages per second.
+    pub messages_per_sec: f64,
+    /// Number of messages allowed at once.
+    pub messages_burst: u32,
+    /// Sustained number of bytes per second.
+    #[serde(default)]
+    pub bytes_per_sec: Option<u64>,
 }
 
 impl Default for Network {
@@ -270,6 +353,14 @@ impl Default for Network {
             blacklist: vec![],
             ttl_account_id_router: default_ttl_account_id_router(),
             peer_stats_period: default_peer_stats_period(),
+            encrypted_transport: default_encrypted_transport(),
+            require_encrypted_transport: false,
+            compression_threshold: None,
+            message_rate_limits: HashMap::new(),
+            transport: NetworkTransport::default(),
+            port_mapping: NetworkPortMapping::default(),
+            trusted_peers: "".to_string(),
+            private_peers: vec![],
         }
     }
 }
@@ -331,6 +422,10 @@ fn default_use_checkpoints_for_db_migration() -> bool {
     true
 }
 
+fn default_signing_history() -> bool {
+    true
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Consensus {
     /// Minimum number of peers to start syncing.
@@ -412,6 +507,19 @@ impl Default for Consensus {
     }
 }
 
+fn default_remote_signer_timeout() -> Duration {
+    Duration::from_secs(1)
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RemoteSignerConfig {
+    /// Unix socket the signer listens on, relative to the home directory.
+    pub socket_path: PathBuf,
+    /// Timeout of a single request to the signer.
+    #[serde(default = "default_remote_signer_timeout")]
+    pub timeout: Duration,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(default)]
 pub struct Config {
@@ -453,6 +561,31 @@ pub struct Config {
     /// For example, setting "use_db_migration_snapshot" to "/tmp/" will create a directory "/tmp/db_migration_snapshot" and populate it with the database files.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub db_migration_snapshot_path: Option<PathBuf>,
+    /// Fisherman mode: submit challenges for invalid chunks detected while processing blocks of
+    /// tracked shards, and report them in metrics. Requires a validator key to sign the
+    /// challenges and tracked shards or accounts.
+    #[serde(default)]
+    pub fisherman: bool,
+    /// Send a state witness for each produced chunk, i.e. the trie nodes and incoming receipts
+    /// needed to apply it, so that nodes which don't track the shard can validate it.
+    #[serde(default)]
+    pub produce_chunk_state_witnesses: bool,
+    /// Validate chunks of shards which are not tracked using the state witnesses sent by their
+    /// chunk producers, and reject blocks with chunks that don't match them.
+    #[serde(default)]
+    pub validate_chunk_state_witnesses: bool,
+    /// File with the new key of the validator during key rotation, relative to the home
+    /// directory. The node keeps using `validator_key_file` until the new key takes effect.
+    pub pending_validator_key_file: String,
+    /// Keep the history of what the validator has signed and refuse to sign anything conflicting
+    /// with it. The history can be moved to another machine with the `export_signing_history`
+    /// and `import_signing_history` state-viewer commands.
+    #[serde(default = "default_signing_history")]
+    pub signing_history: bool,
+    /// Sign with a validator key kept by a separate signer process instead of the one in
+    /// `validator_key_file`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remote_signer: Option<RemoteSignerConfig>,
 }
 
 impl Default for Config {
@@ -481,6 +614,12 @@ impl Default for Config {
             max_gas_burnt_view: None,
             db_migration_snapshot_path: None,
             use_db_migration_snapshot: true,
+            fisherman: false,
+            produce_chunk_state_witnesses: false,
+            validate_chunk_state_witnesses: false,
+            pending_validator_key_file: PENDING_VALIDATOR_KEY_FILE.to_string(),
+            signing_history: default_signing_history(),
+            remote_signer: None,
         }
     }
 }
@@ -628,6 +767,18 @@ pub struct NearConfig {
     pub telemetry_config: TelemetryConfig,
     pub genesis: Genesis,
     pub validator_signer: Option<Arc<dyn ValidatorSigner>>,
+    /// Signer process holding the validator key, with the socket path resolved against the home
+    /// directory. The node connects to it in [`NearConfig::connect_remote_signer`] on startup,
+    /// which replaces `validator_signer`.
+    pub remote_signer: Option<RemoteSignerConfig>,
+}
+
+/// Resolves the external address, which may be given with a host name, once at startup.
+fn resolve_external_address(addr: &str) -> SocketAddr {
+    addr.to_socket_addrs()
+        .ok()
+        .and_then(|mut addrs| addrs.next())
+        .unwrap_or_else(|| panic!("Failed to resolve external address {}", addr))
 }
 
 impl NearConfig {
@@ -683,6 +834,11 @@ impl NearConfig {
                 view_client_throttle_period: config.view_client_throttle_period,
                 trie_viewer_state_size_limit: config.trie_viewer_state_size_limit,
                 max_gas_burnt_view: config.max_gas_burnt_view,
+                fisherman: config.fisherman,
+                produce_chunk_state_witnesses: config.produce_chunk_state_witnesses,
+                validate_chunk_state_witnesses: config.validate_chunk_state_witnesses,
+                pending_validator_key_path: None,
+                signing_history: config.signing_history,
             },
             network_config: NetworkConfig {
                 public_key: network_key_pair.public_key,
@@ -727,6 +883,49 @@ impl NearConfig {
                 blacklist: blacklist_from_iter(config.network.blacklist),
                 outbound_disabled: false,
                 archive: config.archive,
+                encrypted_transport: config.network.encrypted_transport,
+                require_encrypted_transport: config.network.require_encrypted_transport,
+                compression_threshold: config.network.compression_threshold,
+                message_rate_limits: config
+                    .network
+                    .message_rate_limits
+                    .iter()
+                    .map(|(name, limit)| {
+                        (
+                            name.clone(),
+                            RateLimit {
+                                messages_per_sec: limit.messages_per_sec,
+                                messages_burst: limit.messages_burst,
+                                bytes_per_sec: limit.bytes_per_sec,
+                            },
+                        )
+                    })
+                    .collect(),
+                transport: match config.network.transport {
+                    NetworkTransport::Tcp => Transport::Tcp,
+                    NetworkTransport::Quic => Transport::Quic,
+                },
+                external_address: if config.network.external_address.is_empty() {
+                    None
+                } else {
+                    Some(resolve_external_address(&config.network.external_address))
+                },
+                port_mapping: match config.network.port_mapping {
+                    NetworkPortMapping::Disabled => PortMapping::Disabled,
+                    NetworkPortMapping::Upnp => PortMapping::Upnp,
+                    NetworkPortMapping::NatPmp { gateway } => PortMapping::NatPmp { gateway },
+                },
+                trusted_peers: if config.network.trusted_peers.is_empty() {
+                    vec![]
+                } else {
+                    config
+                        .network
+                        .trusted_peers
+                        .split(',')
+                        .map(|chunk| chunk.try_into().expect("Failed to parse PeerInfo"))
+                        .collect()
+                },
+                private_peers: config.network.private_peers.into_iter().collect(),
             },
             telemetry_config: config.telemetry,
             #[cfg(feature = "json_rpc")]
@@ -735,6 +934,7 @@ impl NearConfig {
             rosetta_rpc_config: config.rosetta_rpc,
             genesis,
             validator_signer,
+            remote_signer: None,
         }
     }
 
@@ -745,6 +945,48 @@ impl NearConfig {
         }
         None
     }
+
+    /// Checks that options which depend on each other are consistent, once all overrides from the
+    /// command line are applied.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.client_config.fisherman {
+            if self.validator_signer.is_none() && self.remote_signer.is_none() {
+                anyhow::bail!("Fisherman mode requires a validator key to sign challenges");
+            }
+            if self.client_config.tracked_shards.is_empty()
+                && self.client_config.tracked_accounts.is_empty()
+            {
+                anyhow::bail!("Fisherman mode requires tracked shards or tracked accounts");
+            }
+        }
+        Ok(())
+    }
+
+    /// Connects to the remote signer, if one is configured, and uses it as the validator signer.
+    #[cfg(unix)]
+    pub fn connect_remote_signer(&mut self) -> anyhow::Result<()> {
+        if let Some(remote_signer) = &self.remote_signer {
+            let signer =
+                RemoteValidatorSigner::connect(&remote_signer.socket_path, remote_signer.timeout)
+                    .with_context(|| {
+                    format!(
+                        "Failed to connect to remote signer at {}",
+                        remote_signer.socket_path.display()
+                    )
+                })?;
+            self.network_config.account_id = Some(signer.validator_id().clone());
+            self.validator_signer = Some(Arc::new(signer));
+        }
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    pub fn connect_remote_signer(&mut self) -> anyhow::Result<()> {
+        if self.remote_signer.is_some() {
+            bail!("remote_signer is only supported on unix, use validator_key_file instead");
+        }
+        Ok(())
+    }
 }
 
 impl NearConfig {
@@ -924,6 +1166,21 @@ fn test_generate_or_load_key_panic() {
     let _ = generate_or_load_key(home_dir, "key", Some("fred".parse().unwrap()), None);
 }
 
+#[test]
+fn test_resolve_external_address() {
+    assert_eq!(
+        resolve_external_address("1.2.3.4:24567"),
+        "1.2.3.4:24567".parse::<SocketAddr>().unwrap()
+    );
+    assert_eq!(resolve_external_address("localhost:24567").port(), 24567);
+}
+
+#[test]
+#[should_panic(expected = "Failed to resolve external address")]
+fn test_resolve_external_address_panic() {
+    resolve_external_address("1.2.3.4");
+}
+
 pub fn mainnet_genesis() -> Genesis {
     lazy_static_include::lazy_static_include_bytes! {
         MAINNET_GENESIS_JSON => "res/mainnet_genesis.json",
@@ -1625,7 +1882,15 @@ impl From<NodeKeyFile> for KeyFile {
 pub fn load_config(dir: &Path, genesis_validation: GenesisValidationMode) -> NearConfig {
     let config = Config::from_file(&dir.join(CONFIG_FILENAME)).unwrap();
     let genesis_file = dir.join(&config.genesis_file);
-    let validator_signer = if dir.join(&config.validator_key_file).exists() {
+    // The remote signer is only connected to when the node starts, so that commands which merely
+    // read the config don't need it running.
+    let remote_signer = config.remote_signer.as_ref().map(|remote_signer| RemoteSignerConfig {
+        socket_path: dir.join(&remote_signer.socket_path),
+        timeout: remote_signer.timeout,
+    });
+    let validator_signer = if remote_signer.is_some() {
+        None
+    } else if dir.join(&config.validator_key_file).exists() {
         let signer =
             Arc::new(InMemoryValidatorSigner::from_file(&dir.join(&config.validator_key_file)))
                 as Arc<dyn ValidatorSigner>;
@@ -1635,8 +1900,10 @@ pub fn load_config(dir: &Path, genesis_validation: GenesisValidationMode) -> Nea
     };
     let network_signer = NodeKeyFile::from_file(&dir.join(&config.node_key_file));
 
+    let pending_validator_key_path = dir.join(&config.pending_validator_key_file);
+
     let genesis_records_file = config.genesis_records_file.clone();
-    NearConfig::new(
+    let mut near_config = NearConfig::new(
         config,
         match genesis_records_file {
             Some(genesis_records_file) => Genesis::from_files(
@@ -1648,7 +1915,13 @@ pub fn load_config(dir: &Path, genesis_validation: GenesisValidationMode) -> Nea
         },
         network_signer.into(),
         validator_signer,
-    )
+    );
+    near_config.client_config.pending_validator_key_path = Some(pending_validator_key_path);
+    near_config.remote_signer = remote_signer;
+    // Like the other files of the config, the telemetry file is relative to the home dir.
+    near_config.telemetry_config.file =
+        near_config.telemetry_config.file.take().map(|file| dir.join(file));
+    near_config
 }
 
 pub fn load_test_config(seed: &str, port: u16, genesis: Genesis) -> NearConfig {
@@ -1722,3 +1995,67 @@ fn test_init_config_localnet() {
         2
     );
 }
+
+#[test]
+fn test_load_config_does_not_connect_remote_signer() {
+    let temp_dir = tempdir().unwrap();
+    init_configs(
+        &temp_dir.path(),
+        Some("localnet"),
+        None,
+        Some("seed1"),
+        1,
+        false,
+        None,
+        false,
+        None,
+        false,
+        None,
+        None,
+        None,
+    )
+    .unwrap();
+    let config_path = temp_dir.path().join(CONFIG_FILENAME);
+    let mut config = Config::from_file(&config_path).unwrap();
+    config.remote_signer = Some(RemoteSignerConfig {
+        socket_path: PathBuf::from("signer.sock"),
+        timeout: default_remote_signer_timeout(),
+    });
+    config.write_to_file(&config_path).unwrap();
+
+    // Nothing listens on the socket, which only matters once the node starts.
+    let mut near_config = load_config(temp_dir.path(), GenesisValidationMode::UnsafeFast);
+    assert!(near_config.validator_signer.is_none());
+    let socket_path = temp_dir.path().join("signer.sock");
+    assert_eq!(near_config.remote_signer.as_ref().unwrap().socket_path, socket_path);
+    #[cfg(unix)]
+    assert!(near_config.connect_remote_signer().is_err());
+}
+
+#[test]
+fn test_load_config_resolves_telemetry_file() {
+    let temp_dir = tempdir().unwrap();
+    init_configs(
+        &temp_dir.path(),
+        Some("localnet"),
+        None,
+        Some("seed1"),
+        1,
+        false,
+        None,
+        false,
+        None,
+        false,
+        None,
+        None,
+        None,
+    )
+    .unwrap();
+    let config_path = temp_dir.path().join(CONFIG_FILENAME);
+    let mut config = Config::from_file(&config_path).unwrap();
+    config.telemetry.file = Some(PathBuf::from("telemetry.jsonl"));
+    config.write_to_file(&config_path).unwrap();
+
+    let near_config = load_config(temp_dir.path(), GenesisValidationMode::UnsafeFast);
+    assert_eq!(near_config.telemetry_config.file, Some(temp_dir.path().join("telemetry.jsonl")));
+}
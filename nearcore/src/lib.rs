@@ -1,7 +1,7 @@
 pub use crate::config::{init_configs, load_config, load_test_config, NearConfig, NEAR_BASE};
 use crate::migrations::{
     migrate_12_to_13, migrate_18_to_19, migrate_19_to_20, migrate_22_to_23, migrate_23_to_24,
-    migrate_24_to_25, migrate_30_to_31,
+    migrate_24_to_25, migrate_30_to_31, migrate_33_to_34,
 };
 pub use crate::runtime::NightshadeRuntime;
 pub use crate::shard_tracker::TrackedConfig;
@@ -13,6 +13,7 @@ use near_chain::ChainGenesis;
 #[cfg(feature = "test_features")]
 use near_client::AdversarialControls;
 use near_client::{start_client, start_view_client, ClientActor, ViewClientActor};
+use near_crypto::InMemorySigner;
 use near_network::routing::start_routing_table_actor;
 use near_network::test_utils::NetworkRecipient;
 use near_network::PeerManagerActor;
@@ -324,6 +325,25 @@ pub fn apply_store_migrations(path: &Path, near_config: &NearConfig) {
         info!(target: "near", "Migrate DB from version 30 to 31");
         migrate_30_to_31(path, &near_config);
     }
+    if db_version <= 31 {
+        // version 31 => 32: add ColShardLoad and ColEpochShardConfig
+        // Does not need to do anything since open db with option `create_missing_column_families`
+        info!(target: "near", "Migrate DB from version 31 to 32");
+        let store = create_store(path);
+        set_store_version(&store, 32);
+    }
+    if db_version <= 32 {
+        // version 32 => 33: add ColSigningHistory
+        // Does not need to do anything since open db with option `create_missing_column_families`
+        info!(target: "near", "Migrate DB from version 32 to 33");
+        let store = create_store(path);
+        set_store_version(&store, 33);
+    }
+    if db_version <= 33 {
+        // version 33 => 34: add reputation to the peers in ColPeers
+        info!(target: "near", "Migrate DB from version 33 to 34");
+        migrate_33_to_34(path);
+    }
 
     #[cfg(feature = "nightly_protocol")]
     {
@@ -379,7 +399,11 @@ pub struct NearNode {
     pub rpc_servers: Vec<(&'static str, actix_web::dev::Server)>,
 }
 
-pub fn start_with_config(home_dir: &Path, config: NearConfig) -> Result<NearNode, anyhow::Error> {
+pub fn start_with_config(
+    home_dir: &Path,
+    mut config: NearConfig,
+) -> Result<NearNode, anyhow::Error> {
+    config.connect_remote_signer()?;
     let store = init_and_migrate_store(home_dir, &config);
 
     let runtime = Arc::new(NightshadeRuntime::with_config(
@@ -390,7 +414,12 @@ pub fn start_with_config(home_dir: &Path, config: NearConfig) -> Result<NearNode
         config.client_config.max_gas_burnt_view,
     ));
 
-    let telemetry = TelemetryActor::new(config.telemetry_config.clone()).start();
+    let node_signer = InMemorySigner::from_secret_key(
+        "node".parse().unwrap(),
+        config.network_config.secret_key.clone(),
+    );
+    let telemetry =
+        TelemetryActor::new(config.telemetry_config.clone(), Arc::new(node_signer)).start();
     let chain_genesis = ChainGenesis::from(&config.genesis);
 
     let node_id = PeerId::new(config.network_config.public_key.clone().into());
@@ -428,7 +457,7 @@ pub fn start_with_config(home_dir: &Path, config: NearConfig) -> Result<NearNode
     let network_config = config.network_config;
     let routing_table_addr =
         start_routing_table_actor(PeerId::new(network_config.public_key.clone()), store.clone());
-    #[cfg(all(feature = "json_rpc", feature = "test_features"))]
+    #[cfg(feature = "json_rpc")]
     let routing_table_addr2 = routing_table_addr.clone();
     let network_actor = PeerManagerActor::start_in_arbiter(&arbiter.handle(), move |_ctx| {
         PeerManagerActor::new(
@@ -448,9 +477,7 @@ pub fn start_with_config(home_dir: &Path, config: NearConfig) -> Result<NearNode
             config.genesis.config.clone(),
             client_actor.clone(),
             view_client.clone(),
-            #[cfg(feature = "test_features")]
             network_actor.clone(),
-            #[cfg(feature = "test_features")]
             routing_table_addr2,
         ));
     }
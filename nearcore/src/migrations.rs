@@ -5,6 +5,7 @@ use near_chain::migrations::check_if_block_is_first_with_chunk_of_version;
 use near_chain::types::{ApplyTransactionResult, BlockHeaderInfo};
 use near_chain::{ChainStore, ChainStoreAccess, ChainStoreUpdate, RuntimeAdapter};
 use near_epoch_manager::{EpochManager, RewardCalculator};
+use near_network_primitives::types::{KnownPeerStatus, PeerInfo, PeerReputation};
 use near_primitives::epoch_manager::{AllEpochConfig, EpochConfig};
 use near_primitives::hash::CryptoHash;
 use near_primitives::merkle::MerklePath;
@@ -514,6 +515,46 @@ pub fn migrate_30_to_31(path: &Path, near_config: &NearConfig) {
     set_store_version(&store, 31);
 }
 
+/// `KnownPeerState` as stored until version 33.
+#[derive(BorshSerialize, BorshDeserialize)]
+struct KnownPeerStateV33 {
+    peer_info: PeerInfo,
+    status: KnownPeerStatus,
+    first_seen: u64,
+    last_seen: u64,
+}
+
+/// `KnownPeerState` as stored since version 34.
+#[derive(BorshSerialize, BorshDeserialize)]
+struct KnownPeerStateV34 {
+    peer_info: PeerInfo,
+    status: KnownPeerStatus,
+    first_seen: u64,
+    last_seen: u64,
+    reputation: PeerReputation,
+}
+
+/// Adds an empty reputation to the known peers.
+pub fn migrate_33_to_34(path: &Path) {
+    let store = create_store(path);
+    let mut store_update = store.store_update();
+    for (key, value) in store.iter(DBCol::ColPeers) {
+        let KnownPeerStateV33 { peer_info, status, first_seen, last_seen } =
+            KnownPeerStateV33::try_from_slice(&value)
+                .expect("Failed to deserialize known peer state");
+        let peer_state = KnownPeerStateV34 {
+            peer_info,
+            status,
+            first_seen,
+            last_seen,
+            reputation: PeerReputation::default(),
+        };
+        store_update.set_ser(DBCol::ColPeers, &key, &peer_state).unwrap();
+    }
+    store_update.commit().unwrap();
+    set_store_version(&store, 34);
+}
+
 lazy_static_include::lazy_static_include_bytes! {
     /// File with account ids and deltas that need to be applied in order to fix storage usage
     /// difference between actual and stored usage, introduced due to bug in access key deletion,
@@ -551,8 +592,10 @@ pub fn load_migration_data(chain_id: &str) -> MigrationData {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use near_network_primitives::types::KnownPeerState;
     use near_primitives::hash::hash;
     use near_primitives::serialize::to_base;
+    use near_store::migrations::get_store_version;
 
     #[test]
     fn test_migration_data() {
@@ -566,6 +609,36 @@ mod tests {
         assert_eq!(testnet_migration_data.storage_usage_delta.len(), 0);
     }
 
+    #[test]
+    fn test_migrate_33_to_34() {
+        let dir = tempfile::Builder::new().prefix("migrate_33_to_34").tempdir().unwrap();
+        let peer_info = PeerInfo::random();
+        let key = peer_info.id.try_to_vec().unwrap();
+        {
+            let store = create_store(dir.path());
+            let peer_state = KnownPeerStateV33 {
+                peer_info: peer_info.clone(),
+                status: KnownPeerStatus::NotConnected,
+                first_seen: 1,
+                last_seen: 2,
+            };
+            let mut store_update = store.store_update();
+            store_update.set_ser(DBCol::ColPeers, &key, &peer_state).unwrap();
+            store_update.commit().unwrap();
+            set_store_version(&store, 33);
+        }
+
+        migrate_33_to_34(dir.path());
+
+        assert_eq!(get_store_version(dir.path()), 34);
+        let store = create_store(dir.path());
+        let peer_state: KnownPeerState = store.get_ser(DBCol::ColPeers, &key).unwrap().unwrap();
+        assert_eq!(peer_state.peer_info, peer_info);
+        assert_eq!(peer_state.status, KnownPeerStatus::NotConnected);
+        assert_eq!(peer_state.last_seen, 2);
+        assert_eq!(peer_state.reputation, PeerReputation::default());
+    }
+
     #[test]
     fn test_restored_receipts_data() {
         assert_eq!(
@@ -23,7 +23,7 @@ use near_primitives::challenge::ChallengesResult;
 use near_primitives::contract::ContractCode;
 use near_primitives::epoch_manager::block_info::BlockInfo;
 use near_primitives::epoch_manager::epoch_info::EpochInfo;
-use near_primitives::epoch_manager::{EpochConfig, ShardConfig};
+use near_primitives::epoch_manager::{EpochConfig, ShardConfig, ShardStateLoad};
 use near_primitives::errors::{EpochError, InvalidTxError, RuntimeError};
 use near_primitives::hash::{hash, CryptoHash};
 use near_primitives::receipt::Receipt;
@@ -186,6 +186,14 @@ impl NightshadeRuntime {
             genesis_config.shard_layout.num_shards(),
             genesis_config.num_block_producer_seats_per_shard.len() as NumShards,
         );
+        // Loads of shards are computed from their state, so all nodes must have it to agree on
+        // how shards are split.
+        #[cfg(feature = "protocol_feature_dynamic_resharding")]
+        assert!(
+            !genesis_config.dynamic_resharding
+                || matches!(tracked_config, TrackedConfig::AllShards),
+            "dynamic resharding requires tracking all shards"
+        );
         let state_roots =
             Self::initialize_genesis_state_if_needed(store.clone(), home_dir, genesis);
         let tries = ShardTries::new(
@@ -1070,6 +1078,22 @@ impl RuntimeAdapter for NightshadeRuntime {
         Ok(epoch_manager.get_epoch_config(epoch_id).map_err(Error::from)?.clone().into())
     }
 
+    fn is_resharding_enabled(&self, epoch_id: &EpochId) -> Result<bool, Error> {
+        let mut epoch_manager = self.epoch_manager.as_ref().write().expect(POISONED_LOCK_ERR);
+        Ok(epoch_manager.is_resharding_enabled(epoch_id)?)
+    }
+
+    fn get_shard_state_load(
+        &self,
+        shard_uid: &ShardUId,
+        state_root: &StateRoot,
+    ) -> Result<ShardStateLoad, Error> {
+        let trie = self.tries.get_view_trie_for_shard(*shard_uid);
+        let state_size = trie.retrieve_root_node(state_root)?.memory_usage;
+        let median_account_id = trie.find_median_account_id(state_root)?;
+        Ok(ShardStateLoad { state_size, median_account_id })
+    }
+
     fn get_prev_shard_ids(
         &self,
         prev_hash: &CryptoHash,
@@ -1699,6 +1723,51 @@ impl RuntimeAdapter for NightshadeRuntime {
         Ok(state_roots)
     }
 
+    fn build_state_for_merged_shard(
+        &self,
+        parent_states: &[(ShardUId, StateRoot)],
+        shard_uid: ShardUId,
+    ) -> Result<StateRoot, Error> {
+        let mut merged_state_root = StateRoot::default();
+        for (parent_shard_uid, state_root) in parent_states {
+            let trie = self.tries.get_view_trie_for_shard(*parent_shard_uid);
+            let state_root_node = trie.retrieve_root_node(state_root)?;
+            let num_parts = get_num_state_parts(state_root_node.memory_usage);
+            debug!(target: "runtime", "merging state of shard {:?} to {:?} in {} parts", parent_shard_uid, shard_uid, num_parts);
+            for part_id in 0..num_parts {
+                let trie_items = trie.get_trie_items_for_part(part_id, num_parts, state_root)?;
+                let (store_update, new_state_root) = self.tries.add_values_to_merged_state(
+                    shard_uid,
+                    &merged_state_root,
+                    trie_items.into_iter().map(|(key, value)| (key, Some(value))).collect(),
+                )?;
+                merged_state_root = new_state_root;
+                store_update.commit()?;
+            }
+        }
+        // Delayed receipts are appended once all values are added, so that the merged queue holds
+        // the receipts of each parent in order.
+        for (parent_shard_uid, state_root) in parent_states {
+            let parent_trie_update =
+                self.tries.new_trie_update_view(*parent_shard_uid, *state_root);
+            let mut start_index = None;
+            while let Some((next_index, receipts)) =
+                get_delayed_receipts(&parent_trie_update, start_index, STATE_PART_MEMORY_LIMIT)?
+            {
+                let (store_update, new_state_root) =
+                    self.tries.apply_delayed_receipts_to_merged_state(
+                        shard_uid,
+                        &merged_state_root,
+                        &receipts,
+                    )?;
+                merged_state_root = new_state_root;
+                start_index = Some(next_index);
+                store_update.commit()?;
+            }
+        }
+        Ok(merged_state_root)
+    }
+
     fn apply_state_part(
         &self,
         shard_id: ShardId,
@@ -1948,7 +2017,7 @@ mod test {
     use near_crypto::{InMemorySigner, KeyType, Signer};
     use near_logger_utils::init_test_logger;
     use near_primitives::block::Tip;
-    use near_primitives::challenge::SlashedValidator;
+    use near_primitives::challenge::{PartialState, SlashedValidator};
     use near_primitives::transaction::{Action, DeleteAccountAction, StakeAction};
     use near_primitives::types::{BlockHeightDelta, Nonce, ValidatorId, ValidatorKickoutReason};
     use near_primitives::validator_signer::{InMemoryValidatorSigner, ValidatorSigner};
@@ -2939,6 +3008,90 @@ mod test {
         ));
     }
 
+    /// Applying transactions only from the trie nodes recorded while applying them, as done for
+    /// chunk state witnesses, gives the same result as applying them from the stored state.
+    #[test]
+    fn test_apply_transactions_from_recorded_state() {
+        let validators = vec![AccountId::try_from("test1".to_string()).unwrap()];
+        let env = TestEnv::new(
+            "test_apply_transactions_from_recorded_state",
+            vec![validators.clone()],
+            4,
+            false,
+        );
+        let block_producer = InMemoryValidatorSigner::from_seed(
+            validators[0].clone(),
+            KeyType::ED25519,
+            validators[0].as_ref(),
+        );
+        let signer = InMemorySigner::from_seed(
+            validators[0].clone(),
+            KeyType::ED25519,
+            validators[0].as_ref(),
+        );
+        let transactions = vec![stake(1, &signer, &block_producer, TESTING_INIT_STAKE / 2)];
+        let height = env.head.height + 1;
+        let block_hash = hash(&[height as u8]);
+        let gas_price = env.runtime.genesis_config.min_gas_price;
+        let apply = |generate_storage_proof| {
+            env.runtime
+                .apply_transactions_with_optional_storage_proof(
+                    0,
+                    &env.state_roots[0],
+                    height,
+                    0,
+                    &env.head.last_block_hash,
+                    &block_hash,
+                    &[],
+                    &transactions,
+                    ValidatorStakeIter::empty(),
+                    gas_price,
+                    u64::max_value(),
+                    &ChallengesResult::default(),
+                    CryptoHash::default(),
+                    generate_storage_proof,
+                    true,
+                    false,
+                    None,
+                )
+                .unwrap()
+        };
+        let check = |partial_storage| {
+            env.runtime.check_state_transition(
+                partial_storage,
+                0,
+                &env.state_roots[0],
+                height,
+                0,
+                &env.head.last_block_hash,
+                &block_hash,
+                &[],
+                &transactions,
+                ValidatorStakeIter::empty(),
+                gas_price,
+                u64::max_value(),
+                &ChallengesResult::default(),
+                CryptoHash::default(),
+                true,
+                false,
+            )
+        };
+
+        let result = apply(false);
+        assert!(result.proof.is_none());
+        let recorded_result = apply(true);
+        let partial_storage = recorded_result.proof.unwrap();
+        let stateless_result = check(partial_storage).unwrap();
+        assert_eq!(stateless_result.new_root, result.new_root);
+        assert_eq!(stateless_result.outgoing_receipts, result.outgoing_receipts);
+        assert_eq!(stateless_result.validator_proposals, result.validator_proposals);
+        assert_eq!(stateless_result.total_gas_burnt, result.total_gas_burnt);
+        assert_eq!(stateless_result.total_balance_burnt, result.total_balance_burnt);
+
+        // State that wasn't recorded can't be read.
+        assert!(check(PartialStorage { nodes: PartialState(vec![]) }).is_err());
+    }
+
     #[test]
     fn test_challenges() {
         let mut env = TestEnv::new(
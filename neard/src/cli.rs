@@ -245,6 +245,10 @@ pub(super) struct RunCmd {
     /// Keep old blocks in the storage (default false).
     #[clap(long)]
     archive: bool,
+    /// Submit challenges for misbehavior detected in tracked shards and report them in metrics
+    /// (default false).
+    #[clap(long)]
+    fisherman: bool,
     /// Set the boot nodes to bootstrap network from.
     #[clap(long)]
     boot_nodes: Option<String>,
@@ -333,6 +337,10 @@ impl RunCmd {
         if self.max_gas_burnt_view.is_some() {
             near_config.client_config.max_gas_burnt_view = self.max_gas_burnt_view;
         }
+        if self.fisherman {
+            near_config.client_config.fisherman = true;
+        }
+        near_config.validate().expect("Invalid config");
 
         #[cfg(feature = "sandbox")]
         {
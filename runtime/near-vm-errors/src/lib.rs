@@ -225,6 +225,9 @@ pub enum HostError {
     /// Serialization error for alt_bn128 functions
     #[cfg(feature = "protocol_feature_alt_bn128")]
     AltBn128SerializationError { msg: String },
+    /// Invalid input to ed25519 signature verification
+    #[cfg(feature = "protocol_feature_ed25519_verify")]
+    Ed25519VerifyInvalidInput { msg: String },
 }
 
 #[derive(Debug, PartialEq)]
@@ -426,6 +429,8 @@ impl std::fmt::Display for HostError {
             #[cfg(feature = "protocol_feature_alt_bn128")]
             AltBn128SerializationError { msg } => write!(f, "AltBn128 serialization error: {}", msg),
             ECRecoverError { msg } => write!(f, "ECDSA recover error: {}", msg),
+            #[cfg(feature = "protocol_feature_ed25519_verify")]
+            Ed25519VerifyInvalidInput { msg } => write!(f, "ED25519 signature verification error: {}", msg),
         }
     }
 }
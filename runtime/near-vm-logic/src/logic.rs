@@ -1057,6 +1057,75 @@ impl<'a> VMLogic<'a> {
         Ok(false as u64)
     }
 
+    /// Verifies an ed25519 `signature` of `message` by `public_key`.
+    ///
+    /// Returns a bool indicating whether the signature is valid as a `u64`. Signatures and public
+    /// keys which have the right length but can't be decoded are reported as invalid.
+    ///
+    /// # Errors
+    ///
+    /// * If `signature_ptr`, `message_ptr`, or `public_key_ptr` point outside the memory or the
+    ///   registers use more memory than the limit, then returns `MemoryAccessViolation`.
+    /// * If the signature is not 64 bytes long or the public key is not 32 bytes long, then
+    ///   returns `Ed25519VerifyInvalidInput`.
+    ///
+    /// # Cost
+    ///
+    /// `base + ed25519_verify_base + ed25519_verify_byte * message_len`
+    #[cfg(feature = "protocol_feature_ed25519_verify")]
+    pub fn ed25519_verify(
+        &mut self,
+        signature_len: u64,
+        signature_ptr: u64,
+        message_len: u64,
+        message_ptr: u64,
+        public_key_len: u64,
+        public_key_ptr: u64,
+    ) -> Result<u64> {
+        use ed25519_dalek::{PublicKey, Signature, PUBLIC_KEY_LENGTH, SIGNATURE_LENGTH};
+
+        self.gas_counter.pay_base(ed25519_verify_base)?;
+
+        let signature = {
+            let vec = self.get_vec_from_memory_or_register(signature_ptr, signature_len)?;
+            if vec.len() != SIGNATURE_LENGTH {
+                return Err(VMLogicError::HostError(HostError::Ed25519VerifyInvalidInput {
+                    msg: format!(
+                        "The length of the signature: {}, is not {} bytes",
+                        vec.len(),
+                        SIGNATURE_LENGTH
+                    ),
+                }));
+            }
+            match Signature::try_from(vec.as_slice()) {
+                Ok(signature) => signature,
+                Err(_) => return Ok(false as u64),
+            }
+        };
+
+        let message = self.get_vec_from_memory_or_register(message_ptr, message_len)?;
+        self.gas_counter.pay_per(ed25519_verify_byte, message.len() as u64)?;
+
+        let public_key = {
+            let vec = self.get_vec_from_memory_or_register(public_key_ptr, public_key_len)?;
+            if vec.len() != PUBLIC_KEY_LENGTH {
+                return Err(VMLogicError::HostError(HostError::Ed25519VerifyInvalidInput {
+                    msg: format!(
+                        "The length of the public key: {}, is not {} bytes",
+                        vec.len(),
+                        PUBLIC_KEY_LENGTH
+                    ),
+                }));
+            }
+            match PublicKey::from_bytes(&vec) {
+                Ok(public_key) => public_key,
+                Err(_) => return Ok(false as u64),
+            }
+        };
+
+        Ok(public_key.verify_strict(&message, &signature).is_ok() as u64)
+    }
+
     /// Called by gas metering injected into Wasm. Counts both towards `burnt_gas` and `used_gas`.
     ///
     /// # Errors
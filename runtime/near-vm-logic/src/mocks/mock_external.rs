@@ -213,9 +213,9 @@ impl External for MockedExternal {
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Receipt {
-    receipt_indices: Vec<u64>,
-    receiver_id: AccountId,
-    actions: Vec<Action>,
+    pub receipt_indices: Vec<u64>,
+    pub receiver_id: AccountId,
+    pub actions: Vec<Action>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -239,54 +239,54 @@ pub struct DeployContractAction {
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct FunctionCallAction {
     #[serde(with = "crate::serde_with::bytes_as_str")]
-    method_name: Vec<u8>,
+    pub method_name: Vec<u8>,
     /// Most function calls still take JSON as input, so we'll keep it there as a string.
     /// Once we switch to borsh, we'll have to switch to base64 encoding.
     /// Right now, it is only used with standalone runtime when passing in Receipts or expecting
     /// receipts. The workaround for input is to use a VMContext input.
     #[serde(with = "crate::serde_with::bytes_as_str")]
-    args: Vec<u8>,
-    gas: Gas,
-    deposit: Balance,
+    pub args: Vec<u8>,
+    pub gas: Gas,
+    pub deposit: Balance,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct TransferAction {
-    deposit: Balance,
+    pub deposit: Balance,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct StakeAction {
-    stake: Balance,
+    pub stake: Balance,
     #[serde(with = "crate::serde_with::bytes_as_base58")]
-    public_key: PublicKey,
+    pub public_key: PublicKey,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct AddKeyWithFullAccessAction {
     #[serde(with = "crate::serde_with::bytes_as_base58")]
-    public_key: PublicKey,
-    nonce: u64,
+    pub public_key: PublicKey,
+    pub nonce: u64,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct AddKeyWithFunctionCallAction {
     #[serde(with = "crate::serde_with::bytes_as_base58")]
-    public_key: PublicKey,
-    nonce: u64,
-    allowance: Option<Balance>,
-    receiver_id: AccountId,
+    pub public_key: PublicKey,
+    pub nonce: u64,
+    pub allowance: Option<Balance>,
+    pub receiver_id: AccountId,
     #[serde(with = "crate::serde_with::vec_bytes_as_str")]
-    method_names: Vec<Vec<u8>>,
+    pub method_names: Vec<Vec<u8>>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct DeleteKeyAction {
     #[serde(with = "crate::serde_with::bytes_as_base58")]
-    public_key: PublicKey,
+    pub public_key: PublicKey,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct DeleteAccountAction {
-    beneficiary_id: AccountId,
+    pub beneficiary_id: AccountId,
 }
@@ -618,6 +618,62 @@ fn test_ecrecover() {
     }
 }
 
+#[cfg(feature = "protocol_feature_ed25519_verify")]
+#[test]
+fn test_ed25519_verify() {
+    use near_crypto::{KeyType, SecretKey, Signature};
+    use near_vm_errors::VMLogicError;
+
+    let secret_key = SecretKey::from_seed(KeyType::ED25519, "test");
+    let public_key = secret_key.public_key();
+    let public_key = public_key.key_data();
+    let message = b"ed25519 signed message";
+    let signature = match secret_key.sign(message) {
+        Signature::ED25519(signature) => signature.to_bytes(),
+        Signature::SECP256K1(_) => unreachable!(),
+    };
+    let mut forged_message = message.to_vec();
+    forged_message[0] ^= 1;
+
+    for (message, expected) in [(&message[..], 1), (&forged_message[..], 0)] {
+        let mut logic_builder = VMLogicBuilder::default();
+        let mut logic = logic_builder.build(get_context(vec![], false));
+        let result = logic
+            .ed25519_verify(
+                signature.len() as _,
+                signature.as_ptr() as _,
+                message.len() as _,
+                message.as_ptr() as _,
+                public_key.len() as _,
+                public_key.as_ptr() as _,
+            )
+            .unwrap();
+        assert_eq!(result, expected);
+        assert_costs(map! {
+            ExtCosts::read_memory_base: 3,
+            ExtCosts::read_memory_byte: 64 + message.len() as u64 + 32,
+            ExtCosts::ed25519_verify_base: 1,
+            ExtCosts::ed25519_verify_byte: message.len() as u64,
+        });
+    }
+
+    let mut logic_builder = VMLogicBuilder::default();
+    let mut logic = logic_builder.build(get_context(vec![], false));
+    let result = logic.ed25519_verify(
+        63,
+        signature.as_ptr() as _,
+        message.len() as _,
+        message.as_ptr() as _,
+        public_key.len() as _,
+        public_key.as_ptr() as _,
+    );
+    assert!(matches!(
+        result,
+        Err(VMLogicError::HostError(HostError::Ed25519VerifyInvalidInput { .. }))
+    ));
+    reset_costs_counter();
+}
+
 #[test]
 fn test_hash256_register() {
     let mut logic_builder = VMLogicBuilder::default();
@@ -0,0 +1,80 @@
+//! Checks a contract against the limits of the runtime before it is deployed.
+//!
+//! Exits with a non-zero code if the contract can't be called on the network, see
+//! `near_vm_runner::analysis::analyze_contract`.
+
+use clap::Clap;
+use near_primitives::runtime::config_store::RuntimeConfigStore;
+use near_primitives::version::PROTOCOL_VERSION;
+use near_vm_logic::{ProtocolVersion, VMConfig};
+use near_vm_runner::analysis::analyze_contract;
+use std::fs;
+use std::path::PathBuf;
+
+#[derive(Clap)]
+struct CliArgs {
+    /// File path that contains the Wasm code to check.
+    #[clap(long)]
+    wasm_file: PathBuf,
+    /// Specifies the Wasm config in JSON format, see `VMConfig`. Defaults to the config of the
+    /// protocol version.
+    #[clap(long)]
+    config: Option<String>,
+    /// Reads the config from the file.
+    #[clap(long)]
+    config_file: Option<PathBuf>,
+    /// Protocol version. Defaults to the latest one.
+    #[clap(long)]
+    protocol_version: Option<ProtocolVersion>,
+    /// Prints the report in JSON format.
+    #[clap(long)]
+    json: bool,
+}
+
+fn main() {
+    let cli_args = CliArgs::parse();
+
+    let protocol_version = cli_args.protocol_version.unwrap_or(PROTOCOL_VERSION);
+    let config: VMConfig = if let Some(config) = &cli_args.config {
+        serde_json::from_str(config).unwrap()
+    } else if let Some(path) = &cli_args.config_file {
+        serde_json::from_slice(&fs::read(path).unwrap()).unwrap()
+    } else {
+        RuntimeConfigStore::new(None).get_config(protocol_version).wasm_config.clone()
+    };
+
+    let code = fs::read(&cli_args.wasm_file).unwrap();
+    let analysis = analyze_contract(&code, &config, protocol_version);
+
+    if cli_args.json {
+        println!("{}", serde_json::to_string_pretty(&analysis).unwrap());
+    } else {
+        println!("{:<20} {:>12} {:>12} {:>12}", "limit", "used", "limit", "headroom");
+        for limit in &analysis.limits {
+            let or_dash = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+            println!(
+                "{:<20} {:>12} {:>12} {:>12}{}",
+                limit.name,
+                limit.used,
+                or_dash(limit.limit.map(|limit| limit.to_string())),
+                or_dash(limit.headroom().map(|headroom| headroom.to_string())),
+                if limit.exceeded() { "  EXCEEDED" } else { "" }
+            );
+        }
+        println!("\nMethods: {}", analysis.methods.join(", "));
+        println!("Imports: {}", analysis.imports.join(", "));
+        if !analysis.unknown_imports.is_empty() {
+            println!("Unknown imports: {}", analysis.unknown_imports.join(", "));
+        }
+        if let Some(err) = &analysis.prepare_error {
+            println!("Prepare error: {:?}", err);
+        }
+        if let Some(err) = &analysis.compilation_error {
+            println!("Compilation error: {}", err);
+        }
+    }
+
+    if !analysis.is_ok() {
+        std::process::exit(1);
+    }
+}
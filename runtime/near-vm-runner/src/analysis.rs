@@ -0,0 +1,220 @@
+//! Static analysis of a contract against the limits of a particular `VMConfig`.
+//!
+//! Deploying a contract succeeds even if it can't be prepared or compiled, the errors only show up
+//! once the contract is called. [`analyze_contract`] runs the same checks upfront and reports how
+//! close the contract is to each limit.
+
+use crate::prepare;
+use crate::vm_kind::VMKind;
+use near_primitives::contract::ContractCode;
+use near_vm_errors::{CompilationError, PrepareError};
+use near_vm_logic::{ProtocolVersion, VMConfig};
+use pwasm_utils::parity_wasm::elements::{self, External, Instruction, Internal};
+use serde::Serialize;
+
+/// Usage of a single limit by the contract.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LimitUsage {
+    pub name: &'static str,
+    pub used: u64,
+    /// `None` if the config doesn't limit this quantity.
+    pub limit: Option<u64>,
+}
+
+impl LimitUsage {
+    /// How much more can be used before hitting the limit, negative if it is already exceeded.
+    pub fn headroom(&self) -> Option<i128> {
+        self.limit.map(|limit| limit as i128 - self.used as i128)
+    }
+
+    pub fn exceeded(&self) -> bool {
+        self.headroom().map_or(false, |headroom| headroom < 0)
+    }
+}
+
+/// Result of [`analyze_contract`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ContractAnalysis {
+    /// Error returned by `prepare_contract`, if any.
+    pub prepare_error: Option<PrepareError>,
+    /// Error returned when compiling the contract with the VM used at the protocol version.
+    /// Only checked if the contract was prepared successfully.
+    pub compilation_error: Option<CompilationError>,
+    pub limits: Vec<LimitUsage>,
+    /// Names of the imported host functions.
+    pub imports: Vec<String>,
+    /// Imported host functions which aren't available at the protocol version. Calling any method
+    /// of such a contract fails with a link error.
+    pub unknown_imports: Vec<String>,
+    /// Exported functions, i.e. the methods which can be called.
+    pub methods: Vec<String>,
+}
+
+impl ContractAnalysis {
+    /// Whether the contract can be called on the network.
+    pub fn is_ok(&self) -> bool {
+        self.prepare_error.is_none()
+            && self.compilation_error.is_none()
+            && self.unknown_imports.is_empty()
+            && !self.limits.iter().any(LimitUsage::exceeded)
+    }
+}
+
+/// Checks the contract `code` against `config` and host functions available at
+/// `protocol_version`.
+///
+/// Reported limits are:
+///
+/// - `contract_size`: size of the code, limited by `max_contract_size`;
+/// - `functions`: number of functions including the imported ones, limited by
+///   `max_functions_number_per_contract`;
+/// - `function_locals`: the largest number of locals declared by a single function. Locals count
+///   towards the stack height, so a function with more locals than `max_stack_height` can't be
+///   called at all;
+/// - `data_memory_pages`: memory pages needed to hold the data segments, limited by
+///   `initial_memory_pages`, as the memory is replaced by the one provided by the runtime;
+/// - `table_size`: initial number of table elements, not limited;
+/// - `imports`: number of imported host functions, not limited.
+pub fn analyze_contract(
+    code: &[u8],
+    config: &VMConfig,
+    protocol_version: ProtocolVersion,
+) -> ContractAnalysis {
+    let mut analysis = ContractAnalysis {
+        prepare_error: None,
+        compilation_error: None,
+        limits: vec![LimitUsage {
+            name: "contract_size",
+            used: code.len() as u64,
+            limit: Some(config.limit_config.max_contract_size),
+        }],
+        imports: vec![],
+        unknown_imports: vec![],
+        methods: vec![],
+    };
+
+    if let Err(err) = prepare::prepare_contract(code, config) {
+        analysis.prepare_error = Some(err);
+    } else {
+        analysis.compilation_error = compile(code, config, protocol_version);
+    }
+
+    let module = match pwasm_utils::parity_wasm::deserialize_buffer::<elements::Module>(code) {
+        Ok(module) => module,
+        Err(_) => {
+            analysis.prepare_error.get_or_insert(PrepareError::Deserialization);
+            return analysis;
+        }
+    };
+
+    analysis.limits.push(LimitUsage {
+        name: "functions",
+        used: module.functions_space() as u64,
+        limit: config.limit_config.max_functions_number_per_contract,
+    });
+
+    let max_locals = module
+        .code_section()
+        .map(elements::CodeSection::bodies)
+        .unwrap_or(&[])
+        .iter()
+        .map(|body| body.locals().iter().map(|local| local.count() as u64).sum::<u64>())
+        .max()
+        .unwrap_or(0);
+    analysis.limits.push(LimitUsage {
+        name: "function_locals",
+        used: max_locals,
+        limit: Some(config.limit_config.max_stack_height as u64),
+    });
+
+    analysis.limits.push(LimitUsage {
+        name: "data_memory_pages",
+        used: data_memory_pages(&module),
+        limit: Some(config.limit_config.initial_memory_pages as u64),
+    });
+
+    let imported_table_size = module
+        .import_section()
+        .map(elements::ImportSection::entries)
+        .unwrap_or(&[])
+        .iter()
+        .filter_map(|import| match import.external() {
+            External::Table(table) => Some(table.limits().initial()),
+            _ => None,
+        });
+    let table_size = module
+        .table_section()
+        .map(elements::TableSection::entries)
+        .unwrap_or(&[])
+        .iter()
+        .map(|table| table.limits().initial())
+        .chain(imported_table_size)
+        .max()
+        .unwrap_or(0);
+    analysis.limits.push(LimitUsage { name: "table_size", used: table_size as u64, limit: None });
+
+    let available_imports = crate::imports::available_import_names(protocol_version);
+    for import in module.import_section().map(elements::ImportSection::entries).unwrap_or(&[]) {
+        if let External::Function(_) = import.external() {
+            let name = import.field().to_string();
+            if import.module() != "env" || !available_imports.contains(&name.as_str()) {
+                analysis.unknown_imports.push(format!("{}.{}", import.module(), name));
+            }
+            analysis.imports.push(name);
+        }
+    }
+    analysis.limits.push(LimitUsage {
+        name: "imports",
+        used: analysis.imports.len() as u64,
+        limit: None,
+    });
+
+    analysis.methods = module
+        .export_section()
+        .map(elements::ExportSection::entries)
+        .unwrap_or(&[])
+        .iter()
+        .filter(|export| matches!(export.internal(), Internal::Function(_)))
+        .map(|export| export.field().to_string())
+        .collect();
+
+    analysis
+}
+
+/// Number of memory pages needed to hold the data segments with constant offsets.
+fn data_memory_pages(module: &elements::Module) -> u64 {
+    const WASM_PAGE_SIZE: u64 = 64 * 1024;
+    let end = module
+        .data_section()
+        .map(elements::DataSection::entries)
+        .unwrap_or(&[])
+        .iter()
+        .filter_map(|segment| match segment.offset().as_ref()?.code() {
+            [Instruction::I32Const(offset), Instruction::End] => {
+                Some(*offset as u32 as u64 + segment.value().len() as u64)
+            }
+            _ => None,
+        })
+        .max()
+        .unwrap_or(0);
+    (end + WASM_PAGE_SIZE - 1) / WASM_PAGE_SIZE
+}
+
+fn compile(
+    code: &[u8],
+    config: &VMConfig,
+    protocol_version: ProtocolVersion,
+) -> Option<CompilationError> {
+    let vm_kind = VMKind::for_protocol_version(protocol_version);
+    if let VMKind::Wasmtime = vm_kind {
+        // Precompilation is not supported for Wasmtime.
+        return None;
+    }
+    let code = ContractCode::new(code.to_vec(), None);
+    let cache = crate::MockCompiledContractCache::default();
+    match crate::precompile_contract_vm(vm_kind, &code, config, Some(&cache)) {
+        Ok(Err(err)) => Some(err),
+        // Cache errors come from the mock cache and say nothing about the contract.
+        Ok(Ok(_)) | Err(_) => None,
+    }
+}
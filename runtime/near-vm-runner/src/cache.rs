@@ -253,6 +253,7 @@ pub mod wasmer0_cache {
 pub mod wasmer2_cache {
     use crate::wasmer2_runner::{VMArtifact, Wasmer2VM};
     use near_primitives::contract::ContractCode;
+    use near_vm_logic::GasMetering;
 
     use super::*;
 
@@ -261,9 +262,31 @@ pub mod wasmer2_cache {
         config: &VMConfig,
     ) -> Result<VMArtifact, CompilationError> {
         let _span = tracing::debug_span!(target: "vm", "compile_module_wasmer2").entered();
-        let prepared_code =
-            prepare::prepare_contract(code, config).map_err(CompilationError::PrepareError)?;
-        Wasmer2VM::new(config.clone()).compile_uncached(&prepared_code)
+        // The whole test suite can be run with the middleware by enabling the feature.
+        let gas_metering = if cfg!(feature = "force_middleware_gas_metering") {
+            GasMetering::Middleware
+        } else {
+            config.limit_config.gas_metering
+        };
+        match gas_metering {
+            GasMetering::Instrumentation => {
+                let prepared_code = prepare::prepare_contract(code, config)
+                    .map_err(CompilationError::PrepareError)?;
+                Wasmer2VM::new(config.clone()).compile_uncached(&prepared_code)
+            }
+            GasMetering::Middleware => {
+                let (prepared_code, metering_info) =
+                    prepare::prepare_contract_for_middleware_metering(code, config)
+                        .map_err(CompilationError::PrepareError)?;
+                let vm = match metering_info {
+                    Some(metering_info) => {
+                        Wasmer2VM::new_with_middleware_metering(config.clone(), metering_info)
+                    }
+                    None => Wasmer2VM::new(config.clone()),
+                };
+                vm.compile_uncached(&prepared_code)
+            }
+        }
     }
 
     pub(crate) fn compile_and_serialize_wasmer2(
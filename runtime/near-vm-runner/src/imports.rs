@@ -218,12 +218,35 @@ imports! {
     #["protocol_feature_alt_bn128", AltBn128] alt_bn128_g1_multiexp<[value_len: u64, value_ptr: u64, register_id: u64] -> []>,
     #["protocol_feature_alt_bn128", AltBn128] alt_bn128_g1_sum<[value_len: u64, value_ptr: u64, register_id: u64] -> []>,
     #["protocol_feature_alt_bn128", AltBn128] alt_bn128_pairing_check<[value_len: u64, value_ptr: u64] -> [u64]>,
+    // ##################
+    // # Ed25519 Verify #
+    // ##################
+    #["protocol_feature_ed25519_verify", Ed25519Verify] ed25519_verify<[signature_len: u64, signature_ptr: u64, message_len: u64, message_ptr: u64, public_key_len: u64, public_key_ptr: u64] -> [u64]>,
     // #############
     // #  Sandbox  #
     // #############
     ##["sandbox"] sandbox_debug_log<[len: u64, ptr: u64] -> []>,
 }
 
+/// Names of the host functions available to contracts at `protocol_version`.
+pub(crate) fn available_import_names(
+    protocol_version: near_vm_logic::ProtocolVersion,
+) -> Vec<&'static str> {
+    let mut names = vec![];
+    macro_rules! add_import {
+        (
+          $func:ident <
+            [ $( $arg_name:ident : $arg_type:ident ),* ]
+            -> [ $( $returns:ident ),* ]
+          >
+        ) => {
+            names.push(stringify!($func));
+        };
+    }
+    for_each_available_import!(protocol_version, add_import);
+    names
+}
+
 #[cfg(all(feature = "wasmer0_vm", target_arch = "x86_64"))]
 pub(crate) mod wasmer {
     use super::str_eq;
@@ -1,14 +1,18 @@
 #![doc = include_str!("../README.md")]
 
+pub mod analysis;
 mod cache;
 mod errors;
 mod imports;
 #[cfg(all(feature = "wasmer0_vm", target_arch = "x86_64"))]
 mod memory;
+#[cfg(all(feature = "wasmer2_vm", target_arch = "x86_64"))]
+mod metering;
 #[cfg(target_arch = "x86_64")]
 mod preload;
 pub mod prepare;
 mod runner;
+pub mod simulator;
 #[cfg(test)]
 mod tests;
 mod vm_kind;
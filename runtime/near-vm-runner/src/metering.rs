@@ -0,0 +1,843 @@
+//! Gas metering of the contract code with a Wasmer2 compiler middleware.
+//!
+//! With [`GasMetering::Instrumentation`](near_vm_logic::GasMetering) `pwasm-utils` splits every
+//! function into metered blocks and injects `i32.const <block cost>; call $gas` at the start of
+//! each block, and replaces `memory.grow` with a call to an injected function which charges for
+//! the new pages. The middleware here emits exactly the same charges at exactly the same places,
+//! but does so while the function is compiled rather than by rewriting the wasm code: the `gas`
+//! import is added to the module info by the middleware, and the contract is prepared without
+//! any gas instrumentation. Metered blocks are determined with the same algorithm as
+//! `pwasm-utils` uses when the contract is prepared (see [`determine_metered_blocks`]), because
+//! they can't be computed while the operators are streamed through the compiler.
+//!
+//! The contract is still prepared with the stack height limiter of `pwasm-utils`. The stack cost
+//! it assigns to a function depends on the height of the value stack, which the injected
+//! `i32.const` raises at the start of the metered blocks, so the middleware replaces the costs
+//! with the ones the instrumented code gets (see [`instrumented_stack_costs`]). The limiter code
+//! isn't metered by the instrumented path, as it is injected after the gas metering, so the
+//! middleware skips it as well. To do so the middleware relies on the exact code the limiter
+//! wraps calls in: it is checked with [`check_stack_limiter`] when the contract is prepared, and
+//! the middleware fails the compilation on any other use of the stack height global.
+
+use pwasm_utils::parity_wasm::elements::{self, BlockType, Instruction};
+use std::cmp::{max, min};
+use std::mem;
+use std::sync::Arc;
+use wasmer_compiler::wasmparser::{Operator, Type as WpType, TypeOrFuncType};
+use wasmer_compiler::{
+    FunctionMiddleware, MiddlewareError, MiddlewareReaderState, ModuleMiddleware,
+};
+use wasmer_types::entity::EntityRef;
+use wasmer_types::{
+    ExportIndex, FunctionIndex, FunctionType, GlobalInit, GlobalType, ImportIndex,
+    LocalFunctionIndex, ModuleInfo, Mutability, SignatureIndex, Type,
+};
+
+/// Number of operators the stack height limiter emits before an instrumented call:
+/// `global.get; i32.const; i32.add; global.set; global.get; i32.const; i32.gt_u; if; unreachable;
+/// end`.
+const STACK_LIMITER_PRE_CALL_LEN: usize = 10;
+/// Number of operators the stack height limiter emits after an instrumented call:
+/// `global.get; i32.const; i32.sub; global.set`.
+const STACK_LIMITER_POST_CALL_LEN: usize = 4;
+/// Number of operators in the whole stack height limiter sequence, including the call itself.
+const STACK_LIMITER_LEN: usize = STACK_LIMITER_PRE_CALL_LEN + 1 + STACK_LIMITER_POST_CALL_LEN;
+/// Stack cost of the function `pwasm-utils` injects to charge for `memory.grow`:
+/// `local.get 0; local.get 0; i32.const; i32.mul; call $gas; memory.grow`.
+const GROW_COUNTER_STACK_COST: i32 = 3;
+
+/// A sequence of instructions which is charged for at once, before its first instruction is
+/// executed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct MeteredBlock {
+    /// Index of the first instruction of the block in the original function body.
+    pub start_pos: usize,
+    /// Number of instructions in the block.
+    pub cost: u32,
+}
+
+/// Everything the middleware needs to know about the prepared contract.
+#[derive(Debug, Clone)]
+pub(crate) struct MeteringInfo {
+    /// Number of functions the contract imports. The `gas` import is added right after them, so
+    /// it takes this index and the functions defined by the contract are shifted by one.
+    pub gas_function: u32,
+    /// Index of the global the stack height limiter keeps the stack height in.
+    pub stack_height_global: u32,
+    /// Index of the global the middleware adds to keep the argument of `memory.grow` in.
+    pub grow_global: u32,
+    /// The limit the stack height limiter checks against.
+    pub max_stack_height: u32,
+    /// Cost of growing memory by one page, in the units of `regular_op_cost`.
+    pub grow_cost: u32,
+    /// Metered blocks of the functions defined by the contract itself, in the order of the code
+    /// section. Functions injected during preparation aren't metered.
+    pub functions: Vec<Vec<MeteredBlock>>,
+    /// Stack costs of the functions defined by the contract itself, as the stack height limiter
+    /// computes them for the instrumented code.
+    pub stack_costs: Vec<u32>,
+}
+
+struct ControlBlock {
+    /// The lowest control stack index corresponding to a forward jump targeted by a br, br_if, or
+    /// br_table instruction within this control block. The index must refer to a control block
+    /// that is not a loop, meaning it is a forward jump.
+    lowest_forward_br_target: usize,
+    /// The active metering block that new instructions contribute a gas cost towards.
+    active_metered_block: MeteredBlock,
+    /// Whether the control block is a loop. Loops have the distinguishing feature that branches to
+    /// them jump to the beginning of the block, not the end as with the other control blocks.
+    is_loop: bool,
+}
+
+/// Computes metered blocks of a function body.
+///
+/// Mirrors `pwasm_utils::gas` with the rule set `prepare` uses: every instruction costs one.
+/// Any divergence from it would change `gas_burnt`, so this must not be "improved" on its own.
+struct Counter {
+    /// A stack of control blocks. This stack grows when new control blocks are opened with
+    /// `block`, `loop`, and `if` and shrinks when control blocks are closed with `end`.
+    stack: Vec<ControlBlock>,
+    /// A list of metered blocks that have been finalized, meaning they will no longer change.
+    finalized_blocks: Vec<MeteredBlock>,
+}
+
+impl Counter {
+    fn new() -> Counter {
+        Counter { stack: Vec::new(), finalized_blocks: Vec::new() }
+    }
+
+    /// Open a new control block. The cursor is the position of the first instruction in the
+    /// block.
+    fn begin_control_block(&mut self, cursor: usize, is_loop: bool) {
+        let index = self.stack.len();
+        self.stack.push(ControlBlock {
+            lowest_forward_br_target: index,
+            active_metered_block: MeteredBlock { start_pos: cursor, cost: 0 },
+            is_loop,
+        })
+    }
+
+    /// Close the last control block. The cursor is the position of the final (pseudo-)instruction
+    /// in the block.
+    fn finalize_control_block(&mut self, cursor: usize) -> Result<(), ()> {
+        // This either finalizes the active metered block or merges its cost into the active
+        // metered block in the previous control block on the stack.
+        self.finalize_metered_block(cursor)?;
+
+        let closing_control_block = self.stack.pop().ok_or(())?;
+        let closing_control_index = self.stack.len();
+        if self.stack.is_empty() {
+            return Ok(());
+        }
+
+        let control_block = self.stack.last_mut().ok_or(())?;
+        control_block.lowest_forward_br_target = min(
+            control_block.lowest_forward_br_target,
+            closing_control_block.lowest_forward_br_target,
+        );
+
+        // If there may have been a branch to a lower index, then also finalize the active metered
+        // block for the previous control block. Otherwise, finalize it and begin a new one.
+        let may_br_out = closing_control_block.lowest_forward_br_target < closing_control_index;
+        if may_br_out {
+            self.finalize_metered_block(cursor)?;
+        }
+        Ok(())
+    }
+
+    /// Finalize the current active metered block.
+    ///
+    /// Finalized blocks have final cost which will not change later.
+    fn finalize_metered_block(&mut self, cursor: usize) -> Result<(), ()> {
+        let closing_metered_block = {
+            let control_block = self.stack.last_mut().ok_or(())?;
+            mem::replace(
+                &mut control_block.active_metered_block,
+                MeteredBlock { start_pos: cursor + 1, cost: 0 },
+            )
+        };
+
+        // If the block was opened with a `block`, then its start position will be set to that of
+        // the active metered block in the control block one higher on the stack. In this case,
+        // instead of finalizing the block, merge its cost into the other active metered block.
+        let last_index = self.stack.len() - 1;
+        if last_index > 0 {
+            let prev_metered_block = &mut self.stack[last_index - 1].active_metered_block;
+            if closing_metered_block.start_pos == prev_metered_block.start_pos {
+                prev_metered_block.cost =
+                    prev_metered_block.cost.checked_add(closing_metered_block.cost).ok_or(())?;
+                return Ok(());
+            }
+        }
+
+        if closing_metered_block.cost > 0 {
+            self.finalized_blocks.push(closing_metered_block);
+        }
+        Ok(())
+    }
+
+    /// Handle a branch instruction in the program. The cursor is the index of the branch
+    /// instruction in the program. The indices are the stack positions of the target control
+    /// blocks. Recall that the index is 0 for a `return` and relatively indexed from the top of
+    /// the stack by the label of `br`, `br_if`, and `br_table` instructions.
+    fn branch(&mut self, cursor: usize, indices: &[usize]) -> Result<(), ()> {
+        self.finalize_metered_block(cursor)?;
+
+        // Update the lowest_forward_br_target of the current control block.
+        for &index in indices {
+            let target_is_loop = self.stack.get(index).ok_or(())?.is_loop;
+            if target_is_loop {
+                continue;
+            }
+            let control_block = self.stack.last_mut().ok_or(())?;
+            control_block.lowest_forward_br_target =
+                min(control_block.lowest_forward_br_target, index);
+        }
+
+        Ok(())
+    }
+
+    /// Returns the stack index of the active control block. Returns None if stack is empty.
+    fn active_control_block_index(&self) -> Option<usize> {
+        self.stack.len().checked_sub(1)
+    }
+
+    /// Get a reference to the currently active metered block.
+    fn active_metered_block(&mut self) -> Result<&mut MeteredBlock, ()> {
+        let top_block = self.stack.last_mut().ok_or(())?;
+        Ok(&mut top_block.active_metered_block)
+    }
+
+    /// Increment the cost of the current block by the specified value.
+    fn increment(&mut self, val: u32) -> Result<(), ()> {
+        let top_block = self.active_metered_block()?;
+        top_block.cost = top_block.cost.checked_add(val).ok_or(())?;
+        Ok(())
+    }
+}
+
+/// Splits a function body into metered blocks, sorted by their start position.
+pub(crate) fn determine_metered_blocks(
+    instructions: &[Instruction],
+) -> Result<Vec<MeteredBlock>, ()> {
+    let mut counter = Counter::new();
+
+    // Begin an implicit function (i.e. `func...end`) block.
+    counter.begin_control_block(0, false);
+
+    for (cursor, instruction) in instructions.iter().enumerate() {
+        match instruction {
+            Instruction::Block(_) => {
+                counter.increment(1)?;
+                // Begin new block. The cost of the following opcodes until `end` or `else` will
+                // be included into this block. The start position is set to that of the previous
+                // active metered block to signal that they should be merged.
+                let top_block_start_pos = counter.active_metered_block()?.start_pos;
+                counter.begin_control_block(top_block_start_pos, false);
+            }
+            Instruction::If(_) => {
+                counter.increment(1)?;
+                counter.begin_control_block(cursor + 1, false);
+            }
+            Instruction::Loop(_) => {
+                counter.increment(1)?;
+                counter.begin_control_block(cursor + 1, true);
+            }
+            Instruction::End => {
+                counter.finalize_control_block(cursor)?;
+            }
+            Instruction::Else => {
+                counter.finalize_metered_block(cursor)?;
+            }
+            Instruction::Br(label) | Instruction::BrIf(label) => {
+                counter.increment(1)?;
+                // Label is a relative index into the control stack.
+                let active_index = counter.active_control_block_index().ok_or(())?;
+                let target_index = active_index.checked_sub(*label as usize).ok_or(())?;
+                counter.branch(cursor, &[target_index])?;
+            }
+            Instruction::BrTable(br_table_data) => {
+                counter.increment(1)?;
+                let active_index = counter.active_control_block_index().ok_or(())?;
+                let target_indices = [br_table_data.default]
+                    .iter()
+                    .chain(br_table_data.table.iter())
+                    .map(|label| active_index.checked_sub(*label as usize))
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or(())?;
+                counter.branch(cursor, &target_indices)?;
+            }
+            Instruction::Return => {
+                counter.increment(1)?;
+                counter.branch(cursor, &[0])?;
+            }
+            _ => {
+                // An ordinal non control flow instruction increments the cost of the current
+                // block.
+                counter.increment(1)?;
+            }
+        }
+    }
+
+    counter.finalized_blocks.sort_unstable_by_key(|block| block.start_pos);
+    Ok(counter.finalized_blocks)
+}
+
+/// Control frame of [`max_stack_height`].
+struct Frame {
+    /// Whether the rest of the frame is unreachable, which makes the value stack polymorphic.
+    is_polymorphic: bool,
+    /// Number of values pushed when the frame ends.
+    end_arity: u32,
+    /// Number of values popped by a branch to the frame. Zero for loops.
+    branch_arity: u32,
+    /// Height of the value stack when the frame was entered.
+    start_height: u32,
+}
+
+/// Value and control stacks of [`max_stack_height`].
+struct Stack {
+    height: u32,
+    frames: Vec<Frame>,
+}
+
+impl Stack {
+    fn frame(&self, depth: u32) -> Result<&Frame, ()> {
+        let index = self.frames.len().checked_sub(1 + depth as usize).ok_or(())?;
+        self.frames.get(index).ok_or(())
+    }
+
+    fn mark_unreachable(&mut self) -> Result<(), ()> {
+        self.frames.last_mut().ok_or(())?.is_polymorphic = true;
+        Ok(())
+    }
+
+    fn push(&mut self, count: u32) -> Result<(), ()> {
+        self.height = self.height.checked_add(count).ok_or(())?;
+        Ok(())
+    }
+
+    fn pop(&mut self, count: u32) -> Result<(), ()> {
+        if count == 0 {
+            return Ok(());
+        }
+        let frame = self.frame(0)?;
+        if self.height == frame.start_height {
+            // Popping values of the parent frame is only allowed in unreachable code.
+            return if frame.is_polymorphic { Ok(()) } else { Err(()) };
+        }
+        self.height = self.height.checked_sub(count).ok_or(())?;
+        Ok(())
+    }
+
+    fn pop_push(&mut self, pop: u32, push: u32) -> Result<(), ()> {
+        self.pop(pop)?;
+        self.push(push)
+    }
+}
+
+fn func_type(module: &elements::Module, type_index: u32) -> Result<&elements::FunctionType, ()> {
+    let types = module.type_section().map_or(&[][..], elements::TypeSection::types);
+    match types.get(type_index as usize).ok_or(())? {
+        elements::Type::Function(func_type) => Ok(func_type),
+    }
+}
+
+/// Returns the type of a function by its index in the function space of the module.
+fn func_type_by_index(
+    module: &elements::Module,
+    func_index: u32,
+) -> Result<&elements::FunctionType, ()> {
+    let imports = module.import_section().map_or(&[][..], elements::ImportSection::entries);
+    let mut imported_types = imports.iter().filter_map(|entry| match entry.external() {
+        elements::External::Function(type_index) => Some(*type_index),
+        _ => None,
+    });
+    let func_imports = module.import_count(elements::ImportCountType::Function) as u32;
+    let type_index = if func_index < func_imports {
+        imported_types.nth(func_index as usize).ok_or(())?
+    } else {
+        let functions = module.function_section().map_or(&[][..], |s| s.entries());
+        functions.get((func_index - func_imports) as usize).ok_or(())?.type_ref()
+    };
+    func_type(module, type_index)
+}
+
+/// Computes the maximal height of the value stack of a function defined by the module, the same
+/// way the stack height limiter of `pwasm-utils` does, as if `i32.const <cost>; call $gas` were
+/// injected at the start of `blocks`.
+fn max_stack_height(
+    module: &elements::Module,
+    defined_index: usize,
+    blocks: &[MeteredBlock],
+) -> Result<u32, ()> {
+    let type_index = module.function_section().ok_or(())?.entries().get(defined_index).ok_or(())?;
+    let func_arity = func_type(module, type_index.type_ref())?.results().len() as u32;
+    let body = module.code_section().ok_or(())?.bodies().get(defined_index).ok_or(())?;
+
+    let mut stack = Stack {
+        height: 0,
+        frames: vec![Frame {
+            is_polymorphic: false,
+            end_arity: func_arity,
+            branch_arity: func_arity,
+            start_height: 0,
+        }],
+    };
+    let mut max_height = 0;
+    let mut blocks = blocks.iter().peekable();
+    for (cursor, instruction) in body.code().elements().iter().enumerate() {
+        // The maximum is not raised by unreachable code.
+        if !stack.frame(0)?.is_polymorphic {
+            max_height = max(max_height, stack.height);
+            // The injected `i32.const` is on the stack when `call $gas` is reached.
+            if blocks.next_if(|block| block.start_pos == cursor).is_some() {
+                max_height = max(max_height, stack.height.checked_add(1).ok_or(())?);
+            }
+        } else {
+            blocks.next_if(|block| block.start_pos == cursor);
+        }
+
+        use Instruction::*;
+        match instruction {
+            Nop => {}
+            Block(ty) | Loop(ty) | If(ty) => {
+                if let If(_) = instruction {
+                    stack.pop(1)?;
+                }
+                let end_arity = if *ty == BlockType::NoResult { 0 } else { 1 };
+                let branch_arity = if let Loop(_) = instruction { 0 } else { end_arity };
+                let start_height = stack.height;
+                stack.frames.push(Frame {
+                    is_polymorphic: false,
+                    end_arity,
+                    branch_arity,
+                    start_height,
+                });
+            }
+            Else => {}
+            End => {
+                let frame = stack.frames.pop().ok_or(())?;
+                stack.height = frame.start_height;
+                stack.push(frame.end_arity)?;
+            }
+            Unreachable => stack.mark_unreachable()?,
+            Br(target) => {
+                let arity = stack.frame(*target)?.branch_arity;
+                stack.pop(arity)?;
+                stack.mark_unreachable()?;
+            }
+            BrIf(target) => {
+                let arity = stack.frame(*target)?.branch_arity;
+                stack.pop(arity)?;
+                stack.pop(1)?;
+                stack.push(arity)?;
+            }
+            BrTable(data) => {
+                let arity = stack.frame(data.default)?.branch_arity;
+                for target in data.table.iter() {
+                    if stack.frame(*target)?.branch_arity != arity {
+                        return Err(());
+                    }
+                }
+                stack.pop(arity)?;
+                stack.mark_unreachable()?;
+            }
+            Return => {
+                stack.pop(func_arity)?;
+                stack.mark_unreachable()?;
+            }
+            Call(index) => {
+                let ty = func_type_by_index(module, *index)?;
+                stack.pop_push(ty.params().len() as u32, ty.results().len() as u32)?;
+            }
+            CallIndirect(type_index, _) => {
+                let ty = func_type(module, *type_index)?;
+                stack.pop(1)?;
+                stack.pop_push(ty.params().len() as u32, ty.results().len() as u32)?;
+            }
+            Drop | SetLocal(_) | SetGlobal(_) => stack.pop(1)?,
+            Select => {
+                stack.pop(2)?;
+                stack.pop_push(1, 1)?;
+            }
+            GetLocal(_) | GetGlobal(_) | CurrentMemory(_) | I32Const(_) | I64Const(_)
+            | F32Const(_) | F64Const(_) => stack.push(1)?,
+            I32Store(..) | I64Store(..) | F32Store(..) | F64Store(..) | I32Store8(..)
+            | I32Store16(..) | I64Store8(..) | I64Store16(..) | I64Store32(..) => stack.pop(2)?,
+            I32Eq | I32Ne | I32LtS | I32LtU | I32GtS | I32GtU | I32LeS | I32LeU | I32GeS
+            | I32GeU | I64Eq | I64Ne | I64LtS | I64LtU | I64GtS | I64GtU | I64LeS | I64LeU
+            | I64GeS | I64GeU | F32Eq | F32Ne | F32Lt | F32Gt | F32Le | F32Ge | F64Eq | F64Ne
+            | F64Lt | F64Gt | F64Le | F64Ge | I32Add | I32Sub | I32Mul | I32DivS | I32DivU
+            | I32RemS | I32RemU | I32And | I32Or | I32Xor | I32Shl | I32ShrS | I32ShrU
+            | I32Rotl | I32Rotr | I64Add | I64Sub | I64Mul | I64DivS | I64DivU | I64RemS
+            | I64RemU | I64And | I64Or | I64Xor | I64Shl | I64ShrS | I64ShrU | I64Rotl
+            | I64Rotr | F32Add | F32Sub | F32Mul | F32Div | F32Min | F32Max | F32Copysign
+            | F64Add | F64Sub | F64Mul | F64Div | F64Min | F64Max | F64Copysign => {
+                stack.pop_push(2, 1)?
+            }
+            // Loads, `memory.grow`, `eqz`, unary and conversion operators replace one value.
+            _ => stack.pop_push(1, 1)?,
+        }
+    }
+    Ok(max_height)
+}
+
+/// Computes the stack costs the stack height limiter of `pwasm-utils` assigns to the functions
+/// defined by the module when the `gas` calls for `functions` are injected into their code: the
+/// number of locals plus the maximal height of the value stack.
+pub(crate) fn instrumented_stack_costs(
+    module: &elements::Module,
+    functions: &[Vec<MeteredBlock>],
+) -> Result<Vec<u32>, ()> {
+    let bodies = module.code_section().map_or(&[][..], elements::CodeSection::bodies);
+    bodies
+        .iter()
+        .zip(functions)
+        .enumerate()
+        .map(|(index, (body, blocks))| {
+            let locals = body
+                .locals()
+                .iter()
+                .try_fold(0u32, |count, local| count.checked_add(local.count()))
+                .ok_or(())?;
+            locals.checked_add(max_stack_height(module, index, blocks)?).ok_or(())
+        })
+        .collect()
+}
+
+/// Checks that the stack height global is only used by the code the stack height limiter of
+/// `pwasm-utils` wraps the calls in, which the middleware expects to find in the module prepared
+/// with [`MeteringInfo`] `info`:
+///
+/// ```text
+/// global.get $h; i32.const $cost; i32.add; global.set $h;
+/// global.get $h; i32.const $max_stack_height; i32.gt_u; if; unreachable; end;
+/// call $f;
+/// global.get $h; i32.const $cost; i32.sub; global.set $h
+/// ```
+pub(crate) fn check_stack_limiter(
+    module: &elements::Module,
+    info: &MeteringInfo,
+) -> Result<(), ()> {
+    use Instruction::*;
+    let global = info.stack_height_global;
+    let bodies = module.code_section().map_or(&[][..], elements::CodeSection::bodies);
+    for body in bodies {
+        let code = body.code().elements();
+        let mut position = 0;
+        while position < code.len() {
+            match code[position] {
+                GetGlobal(index) | SetGlobal(index) if index == global => {
+                    let sequence = code.get(position..position + STACK_LIMITER_LEN).ok_or(())?;
+                    let cost = match sequence[1] {
+                        I32Const(cost) => cost,
+                        _ => return Err(()),
+                    };
+                    let (pre_call, rest) = sequence.split_at(STACK_LIMITER_PRE_CALL_LEN);
+                    let (call, post_call) = rest.split_at(1);
+                    let expected_pre_call = [
+                        GetGlobal(global),
+                        I32Const(cost),
+                        I32Add,
+                        SetGlobal(global),
+                        GetGlobal(global),
+                        I32Const(info.max_stack_height as i32),
+                        I32GtU,
+                        If(BlockType::NoResult),
+                        Unreachable,
+                        End,
+                    ];
+                    let expected_post_call =
+                        [GetGlobal(global), I32Const(cost), I32Sub, SetGlobal(global)];
+                    if pre_call != expected_pre_call
+                        || !matches!(call, [Call(_)])
+                        || post_call != expected_post_call
+                    {
+                        return Err(());
+                    }
+                    position += STACK_LIMITER_LEN;
+                }
+                _ => position += 1,
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Module-level part of the gas metering middleware.
+#[derive(Debug, loupe::MemoryUsage)]
+pub(crate) struct GasMeteringMiddleware {
+    #[loupe(skip)]
+    info: Arc<MeteringInfo>,
+}
+
+impl GasMeteringMiddleware {
+    pub(crate) fn new(info: MeteringInfo) -> Self {
+        Self { info: Arc::new(info) }
+    }
+}
+
+impl ModuleMiddleware for GasMeteringMiddleware {
+    fn generate_function_middleware(
+        &self,
+        local_function_index: LocalFunctionIndex,
+    ) -> Box<dyn FunctionMiddleware> {
+        Box::new(FunctionGasMetering {
+            info: Arc::clone(&self.info),
+            function: local_function_index.index(),
+            next_block: 0,
+            position: 0,
+            stack_limiter: None,
+            stack_limiter_cost: 0,
+            instrumented_stack_cost: 0,
+        })
+    }
+
+    /// Adds the `gas` import after the other imported functions, which shifts the functions
+    /// defined by the contract, and the global `memory.grow` keeps its argument in.
+    fn transform_module_info(&self, module_info: &mut ModuleInfo) {
+        let gas_function = FunctionIndex::new(self.info.gas_function as usize);
+        debug_assert_eq!(module_info.num_imported_functions, gas_function.index());
+        let shift = |index: FunctionIndex| {
+            if index >= gas_function {
+                FunctionIndex::new(index.index() + 1)
+            } else {
+                index
+            }
+        };
+
+        let signature = module_info.signatures.push(FunctionType::new(vec![Type::I32], vec![]));
+        let mut functions: Vec<SignatureIndex> = module_info.functions.values().cloned().collect();
+        functions.insert(gas_function.index(), signature);
+        module_info.functions = functions.into_iter().collect();
+        let import_index = module_info.imports.len() as u32;
+        module_info.imports.insert(
+            ("env".to_string(), "gas".to_string(), import_index),
+            ImportIndex::Function(gas_function),
+        );
+        module_info.num_imported_functions += 1;
+
+        for export in module_info.exports.values_mut() {
+            if let ExportIndex::Function(index) = export {
+                *index = shift(*index);
+            }
+        }
+        module_info.start_function = module_info.start_function.map(shift);
+        for initializer in module_info.table_initializers.iter_mut() {
+            for index in initializer.elements.iter_mut() {
+                *index = shift(*index);
+            }
+        }
+        for elements in module_info.passive_elements.values_mut() {
+            for index in elements.iter_mut() {
+                *index = shift(*index);
+            }
+        }
+        for init in module_info.global_initializers.values_mut() {
+            if let GlobalInit::RefFunc(index) = init {
+                *index = shift(*index);
+            }
+        }
+        module_info.function_names = mem::take(&mut module_info.function_names)
+            .into_iter()
+            .map(|(index, name)| (shift(index), name))
+            .collect();
+
+        debug_assert_eq!(module_info.globals.len(), self.info.grow_global as usize);
+        module_info.globals.push(GlobalType::new(Type::I32, Mutability::Var));
+        module_info.global_initializers.push(GlobalInit::I32Const(0));
+    }
+}
+
+/// Function-level part of the gas metering middleware.
+#[derive(Debug)]
+struct FunctionGasMetering {
+    info: Arc<MeteringInfo>,
+    /// Index of the function in the code section.
+    function: usize,
+    /// Index of the next metered block to be charged for.
+    next_block: usize,
+    /// Position of the next operator in the function as it was before the stack height limiter
+    /// was injected.
+    position: usize,
+    /// Index of the next operator in the currently passed through stack limiter sequence.
+    stack_limiter: Option<usize>,
+    /// Stack cost of the callee the limiter assigned in the currently passed through sequence.
+    stack_limiter_cost: i32,
+    /// Stack cost of the callee of the currently passed through stack limiter sequence, as the
+    /// instrumented code has it.
+    instrumented_stack_cost: i32,
+}
+
+impl FunctionGasMetering {
+    fn blocks(&self) -> &[MeteredBlock] {
+        self.info.functions.get(self.function).map_or(&[], Vec::as_slice)
+    }
+
+    /// Emits the charge for the metered block starting at the current position, if any.
+    fn charge<'a>(&mut self, state: &mut MiddlewareReaderState<'a>) {
+        if let Some(block) = self.blocks().get(self.next_block) {
+            if block.start_pos == self.position {
+                let cost = block.cost as i32;
+                state.push_operator(Operator::I32Const { value: cost });
+                state.push_operator(Operator::Call { function_index: self.info.gas_function });
+                self.next_block += 1;
+            }
+        }
+    }
+
+    /// Emits the check the stack height limiter does before calling a function with `cost`.
+    fn push_stack_check<'a>(&self, cost: i32, state: &mut MiddlewareReaderState<'a>) {
+        let global_index = self.info.stack_height_global;
+        state.extend([
+            Operator::GlobalGet { global_index },
+            Operator::I32Const { value: cost },
+            Operator::I32Add,
+            Operator::GlobalSet { global_index },
+            Operator::GlobalGet { global_index },
+            Operator::I32Const { value: self.info.max_stack_height as i32 },
+            Operator::I32GtU,
+            Operator::If { ty: TypeOrFuncType::Type(WpType::EmptyBlockType) },
+            Operator::Unreachable,
+            Operator::End,
+        ]);
+    }
+
+    /// Emits the code the stack height limiter runs after calling a function with `cost`.
+    fn push_stack_release<'a>(&self, cost: i32, state: &mut MiddlewareReaderState<'a>) {
+        let global_index = self.info.stack_height_global;
+        state.extend([
+            Operator::GlobalGet { global_index },
+            Operator::I32Const { value: cost },
+            Operator::I32Sub,
+            Operator::GlobalSet { global_index },
+        ]);
+    }
+
+    /// Whether `operator` is the one the stack height limiter emits at `index` of the sequence
+    /// around a call, see [`check_stack_limiter`].
+    fn is_stack_limiter_operator(&self, index: usize, operator: &Operator) -> bool {
+        let stack_height_global = self.info.stack_height_global;
+        match (index, operator) {
+            (0 | 4 | 11, Operator::GlobalGet { global_index })
+            | (3 | 14, Operator::GlobalSet { global_index }) => {
+                *global_index == stack_height_global
+            }
+            (1, Operator::I32Const { .. }) => true,
+            (5, Operator::I32Const { value }) => *value == self.info.max_stack_height as i32,
+            (12, Operator::I32Const { value }) => *value == self.stack_limiter_cost,
+            (7, Operator::If { ty: TypeOrFuncType::Type(WpType::EmptyBlockType) }) => true,
+            (2, Operator::I32Add)
+            | (6, Operator::I32GtU)
+            | (8, Operator::Unreachable)
+            | (9, Operator::End)
+            | (10, Operator::Call { .. })
+            | (13, Operator::I32Sub) => true,
+            _ => false,
+        }
+    }
+
+    /// Passes through the stack limiter sequence around a call, with the stack cost of the
+    /// callee replaced by the one it has in the instrumented code. The check before the call is
+    /// emitted once the callee is known.
+    fn feed_stack_limiter<'a>(
+        &mut self,
+        index: usize,
+        operator: Operator<'a>,
+        state: &mut MiddlewareReaderState<'a>,
+    ) -> Result<(), MiddlewareError> {
+        if !self.is_stack_limiter_operator(index, &operator) {
+            return Err(MiddlewareError::new(
+                "gas_metering",
+                format!("unexpected operator {:?} in the stack height limiter code", operator),
+            ));
+        }
+        let next = index + 1;
+        self.stack_limiter = (next < STACK_LIMITER_LEN).then(|| next);
+        match operator {
+            Operator::I32Const { value } if index == 1 => {
+                self.stack_limiter_cost = value;
+                self.instrumented_stack_cost = value;
+            }
+            Operator::Call { function_index } => {
+                // Indices of the functions defined by the contract are already shifted.
+                let defined_index =
+                    (function_index as usize).checked_sub(self.info.gas_function as usize + 1);
+                if let Some(cost) = defined_index.and_then(|i| self.info.stack_costs.get(i)) {
+                    self.instrumented_stack_cost = *cost as i32;
+                }
+                self.push_stack_check(self.instrumented_stack_cost, state);
+                // Out of the whole sequence only the call itself is a part of the original code.
+                self.position += 1;
+                state.push_operator(operator);
+            }
+            Operator::I32Const { .. } if index == STACK_LIMITER_PRE_CALL_LEN + 2 => {
+                state.push_operator(Operator::I32Const { value: self.instrumented_stack_cost });
+            }
+            operator if index > STACK_LIMITER_PRE_CALL_LEN => state.push_operator(operator),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Emits `memory.grow` wrapped the same way as the call to the function `pwasm-utils`
+    /// replaces it with: the stack check for the function, the charge for the new pages and the
+    /// growth itself.
+    fn grow_memory<'a>(&self, operator: Operator<'a>, state: &mut MiddlewareReaderState<'a>) {
+        let grow_global = self.info.grow_global;
+        self.push_stack_check(GROW_COUNTER_STACK_COST, state);
+        state.extend([
+            Operator::GlobalSet { global_index: grow_global },
+            Operator::GlobalGet { global_index: grow_global },
+            Operator::I32Const { value: self.info.grow_cost as i32 },
+            Operator::I32Mul,
+            Operator::Call { function_index: self.info.gas_function },
+            Operator::GlobalGet { global_index: grow_global },
+            operator,
+        ]);
+        self.push_stack_release(GROW_COUNTER_STACK_COST, state);
+    }
+}
+
+impl FunctionMiddleware for FunctionGasMetering {
+    fn feed<'a>(
+        &mut self,
+        operator: Operator<'a>,
+        state: &mut MiddlewareReaderState<'a>,
+    ) -> Result<(), MiddlewareError> {
+        let operator = match operator {
+            Operator::Call { function_index } if function_index >= self.info.gas_function => {
+                Operator::Call { function_index: function_index + 1 }
+            }
+            operator => operator,
+        };
+
+        if self.stack_limiter.is_none() {
+            if let Operator::GlobalGet { global_index } | Operator::GlobalSet { global_index } =
+                operator
+            {
+                if global_index == self.info.stack_height_global {
+                    // The instrumented path injects the stack limiter after the gas metering, so
+                    // the charge for a block starting with a call precedes the stack check.
+                    self.charge(state);
+                    self.stack_limiter = Some(0);
+                }
+            }
+        }
+        if let Some(index) = self.stack_limiter {
+            return self.feed_stack_limiter(index, operator, state);
+        }
+
+        self.charge(state);
+        self.position += 1;
+        match operator {
+            Operator::MemoryGrow { .. } if self.info.grow_cost > 0 => {
+                self.grow_memory(operator, state)
+            }
+            operator => state.push_operator(operator),
+        }
+        Ok(())
+    }
+}
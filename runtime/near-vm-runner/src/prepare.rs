@@ -55,6 +55,40 @@ pub fn prepare_contract(original_code: &[u8], config: &VMConfig) -> Result<Vec<u
     }
 }
 
+/// Same as [`prepare_contract`], but leaves gas metering to the Wasmer2 compiler middleware from
+/// [`crate::metering`] and returns the information it needs.
+///
+/// Nothing is charged for in the returned code, so it must not be compiled without the
+/// middleware, unless the config is free and `None` is returned for the information.
+#[cfg(all(feature = "wasmer2_vm", target_arch = "x86_64"))]
+pub(crate) fn prepare_contract_for_middleware_metering(
+    original_code: &[u8],
+    config: &VMConfig,
+) -> Result<(Vec<u8>, Option<crate::metering::MeteringInfo>), PrepareError> {
+    wasmparser::Validator::new()
+        .wasm_features(WASM_FEATURES)
+        .validate_all(original_code)
+        .map_err(|_| PrepareError::Deserialization)?;
+
+    // The middleware only exists for protocol versions which use the fixed stack limiter, and
+    // it relies on the exact code this version emits.
+    if config.limit_config.stack_limiter_version != near_vm_logic::StackLimiterVersion::V1 {
+        return Err(PrepareError::StackHeightInstrumentation);
+    }
+    let module = ContractModule::init(original_code, config)?
+        .validate_functions_number()?
+        .standardize_mem()
+        .ensure_no_internal_memory()?;
+    let metering_info = module.middleware_metering_info()?;
+    let module = module.inject_stack_height_metering()?;
+    if let Some(info) = &metering_info {
+        crate::metering::check_stack_limiter(&module.module, info)
+            .map_err(|_| PrepareError::StackHeightInstrumentation)?;
+    }
+    let code = module.scan_imports()?.into_wasm_code()?;
+    Ok((code, metering_info))
+}
+
 struct ContractModule<'a> {
     module: elements::Module,
     config: &'a VMConfig,
@@ -115,6 +149,44 @@ impl<'a> ContractModule<'a> {
         Ok(Self { module, config })
     }
 
+    /// Computes what [`crate::metering`] needs to charge for the functions of the module the
+    /// same way [`Self::inject_gas_metering`] does, without changing the module. Must be called
+    /// right before [`Self::inject_stack_height_metering`].
+    #[cfg(all(feature = "wasmer2_vm", target_arch = "x86_64"))]
+    fn middleware_metering_info(
+        &self,
+    ) -> Result<Option<crate::metering::MeteringInfo>, PrepareError> {
+        let Self { module, config } = self;
+        // Free config, no need for gas metering.
+        if config.regular_op_cost == 0 {
+            return Ok(None);
+        }
+        let functions = module
+            .code_section()
+            .map(elements::CodeSection::bodies)
+            .unwrap_or(&[])
+            .iter()
+            .map(|body| crate::metering::determine_metered_blocks(body.code().elements()))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| PrepareError::GasInstrumentation)?;
+        let stack_costs = crate::metering::instrumented_stack_costs(module, &functions)
+            .map_err(|_| PrepareError::StackHeightInstrumentation)?;
+        // The stack height limiter appends its global after all the imported and defined ones,
+        // and the middleware appends one more.
+        let stack_height_global = (module.import_count(elements::ImportCountType::Global)
+            + module.global_section().map_or(0, |section| section.entries().len()))
+            as u32;
+        Ok(Some(crate::metering::MeteringInfo {
+            gas_function: module.import_count(elements::ImportCountType::Function) as u32,
+            stack_height_global,
+            grow_global: stack_height_global + 1,
+            max_stack_height: config.limit_config.max_stack_height,
+            grow_cost: config.grow_mem_cost,
+            functions,
+            stack_costs,
+        }))
+    }
+
     fn inject_stack_height_metering(self) -> Result<Self, PrepareError> {
         let Self { module, config } = self;
         let module =
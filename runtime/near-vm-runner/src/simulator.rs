@@ -0,0 +1,535 @@
+//! In-process simulation of cross-contract calls.
+//!
+//! [`MockedExternal`] records the receipts created by a contract but never executes them.
+//! [`Simulator`] keeps a set of mocked accounts, executes function calls on them with [`run`]
+//! and routes the created receipts to the other mocked accounts, resolving promise results and
+//! callbacks on the way.  This makes it possible to write integration tests for contracts doing
+//! promise chains without spinning up a node.
+//!
+//! Only the gas burnt by the VM and the gas attached to promises is accounted for, action fees
+//! from [`RuntimeFeesConfig`] are not charged.  Access keys and staking are not simulated, the
+//! corresponding actions are no-ops.
+
+use std::collections::{HashMap, VecDeque};
+
+use near_primitives::contract::ContractCode;
+use near_primitives::hash::hash;
+use near_primitives::runtime::config_store::RuntimeConfigStore;
+use near_primitives::runtime::fees::RuntimeFeesConfig;
+use near_primitives::types::{AccountId, Balance, BlockHeight, Gas, StorageUsage};
+use near_primitives::version::{ProtocolVersion, PROTOCOL_VERSION};
+use near_vm_errors::VMError;
+use near_vm_logic::mocks::mock_external::{
+    Action, FunctionCallAction, MockedExternal, Receipt, TransferAction,
+};
+use near_vm_logic::types::{PromiseResult, ReturnData};
+use near_vm_logic::{VMConfig, VMContext};
+
+use crate::run;
+
+/// Gas price used to buy and refund gas, in yoctoNEAR.
+const DEFAULT_GAS_PRICE: Balance = 100_000_000;
+/// Interval between the simulated blocks, in nanoseconds.
+const BLOCK_INTERVAL_NS: u64 = 1_000_000_000;
+/// Predecessor of the refund receipts.
+const SYSTEM_ACCOUNT_ID: &str = "system";
+
+/// State of an account known to the [`Simulator`].
+#[derive(Default, Clone)]
+pub struct MockedAccount {
+    pub balance: Balance,
+    pub storage_usage: StorageUsage,
+    pub code: Option<ContractCode>,
+    pub storage: HashMap<Vec<u8>, Vec<u8>>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ExecutionError {
+    AccountDoesNotExist(AccountId),
+    AccountAlreadyExists(AccountId),
+    ContractNotDeployed(AccountId),
+    NotEnoughBalance {
+        account_id: AccountId,
+        balance: Balance,
+        cost: Balance,
+    },
+    VMError(VMError),
+    /// Receipts which wait for data that was never produced, so they could not be executed.
+    ReceiptsNeverExecuted(Vec<u64>),
+}
+
+/// Outcome of a single receipt executed by the [`Simulator`].
+#[derive(Debug)]
+pub struct ReceiptOutcome {
+    pub receipt_id: u64,
+    pub predecessor_id: AccountId,
+    pub receiver_id: AccountId,
+    pub block_index: BlockHeight,
+    pub logs: Vec<String>,
+    pub gas_burnt: Gas,
+    /// Gas returned to the signer of the original call.
+    pub gas_refund: Gas,
+    pub error: Option<ExecutionError>,
+}
+
+#[derive(Debug)]
+pub struct CallResult {
+    /// Result of the call, following the promises returned by the called methods.
+    pub result: PromiseResult,
+    /// Outcomes of all receipts in the order of execution.
+    pub outcomes: Vec<ReceiptOutcome>,
+}
+
+impl CallResult {
+    pub fn is_success(&self) -> bool {
+        matches!(self.result, PromiseResult::Successful(_))
+    }
+
+    pub fn total_gas_burnt(&self) -> Gas {
+        self.outcomes.iter().map(|outcome| outcome.gas_burnt).sum()
+    }
+
+    pub fn logs(&self) -> Vec<&str> {
+        self.outcomes.iter().flat_map(|outcome| outcome.logs.iter().map(String::as_str)).collect()
+    }
+}
+
+struct PendingReceipt {
+    id: u64,
+    signer_id: AccountId,
+    predecessor_id: AccountId,
+    receiver_id: AccountId,
+    block_index: BlockHeight,
+    actions: Vec<Action>,
+    /// Data this receipt waits for, in the order of the promise results.
+    input_data_ids: Vec<u64>,
+    /// Data produced by this receipt and the accounts receiving it.
+    output_data_receivers: Vec<(u64, AccountId)>,
+}
+
+/// Executes contracts of several mocked accounts calling each other.
+///
+/// # Example
+/// ```ignore
+/// let mut simulator = Simulator::new();
+/// simulator.add_account("alice".parse().unwrap(), 10u128.pow(25));
+/// simulator.deploy(&"alice".parse().unwrap(), &code);
+/// let result = simulator
+///     .call(&"bob".parse().unwrap(), &"alice".parse().unwrap(), "foo", vec![], 10u64.pow(14), 0)
+///     .unwrap();
+/// assert_eq!(result.result, PromiseResult::Successful(b"42".to_vec()));
+/// ```
+pub struct Simulator {
+    accounts: HashMap<AccountId, MockedAccount>,
+    wasm_config: VMConfig,
+    fees_config: RuntimeFeesConfig,
+    protocol_version: ProtocolVersion,
+    gas_price: Balance,
+    block_index: BlockHeight,
+    block_timestamp: u64,
+    next_id: u64,
+    pending: VecDeque<PendingReceipt>,
+    data: HashMap<u64, PromiseResult>,
+    outcomes: Vec<ReceiptOutcome>,
+}
+
+impl Simulator {
+    /// Creates a simulator with the runtime config of the latest protocol version.
+    pub fn new() -> Self {
+        let config_store = RuntimeConfigStore::new(None);
+        let runtime_config = config_store.get_config(PROTOCOL_VERSION);
+        Self::with_config(
+            runtime_config.wasm_config.clone(),
+            runtime_config.transaction_costs.clone(),
+            PROTOCOL_VERSION,
+        )
+    }
+
+    pub fn with_config(
+        wasm_config: VMConfig,
+        fees_config: RuntimeFeesConfig,
+        protocol_version: ProtocolVersion,
+    ) -> Self {
+        Self {
+            accounts: HashMap::new(),
+            wasm_config,
+            fees_config,
+            protocol_version,
+            gas_price: DEFAULT_GAS_PRICE,
+            block_index: 1,
+            block_timestamp: 1586796191203000000,
+            next_id: 0,
+            pending: VecDeque::new(),
+            data: HashMap::new(),
+            outcomes: vec![],
+        }
+    }
+
+    pub fn gas_price(&mut self, gas_price: Balance) -> &mut Self {
+        self.gas_price = gas_price;
+        self
+    }
+
+    pub fn add_account(&mut self, account_id: AccountId, balance: Balance) -> &mut MockedAccount {
+        self.accounts.insert(account_id.clone(), MockedAccount { balance, ..Default::default() });
+        self.accounts.get_mut(&account_id).unwrap()
+    }
+
+    /// Deploys `code` to an existing account.
+    pub fn deploy(&mut self, account_id: &AccountId, code: &[u8]) {
+        let account = self
+            .accounts
+            .get_mut(account_id)
+            .unwrap_or_else(|| panic!("account {} does not exist", account_id));
+        account.code = Some(ContractCode::new(code.to_vec(), None));
+    }
+
+    pub fn account(&self, account_id: &AccountId) -> Option<&MockedAccount> {
+        self.accounts.get(account_id)
+    }
+
+    pub fn account_mut(&mut self, account_id: &AccountId) -> Option<&mut MockedAccount> {
+        self.accounts.get_mut(account_id)
+    }
+
+    /// Height at which the next call starts.
+    pub fn block_index(&self) -> BlockHeight {
+        self.block_index
+    }
+
+    /// Calls `method_name` on `receiver_id` on behalf of `signer_id` and executes all the
+    /// receipts created as a result, until none are left.
+    ///
+    /// The signer pays for the attached deposit and the prepaid gas upfront, unused gas is
+    /// refunded after every receipt, and deposits of failed receipts are returned to their
+    /// predecessor.
+    pub fn call(
+        &mut self,
+        signer_id: &AccountId,
+        receiver_id: &AccountId,
+        method_name: &str,
+        args: Vec<u8>,
+        gas: Gas,
+        deposit: Balance,
+    ) -> Result<CallResult, ExecutionError> {
+        let gas_price = self.gas_price;
+        let signer = self
+            .accounts
+            .get_mut(signer_id)
+            .ok_or_else(|| ExecutionError::AccountDoesNotExist(signer_id.clone()))?;
+        let cost = Balance::from(gas) * gas_price + deposit;
+        if signer.balance < cost {
+            return Err(ExecutionError::NotEnoughBalance {
+                account_id: signer_id.clone(),
+                balance: signer.balance,
+                cost,
+            });
+        }
+        signer.balance -= cost;
+
+        let result_data_id = self.new_id();
+        let receipt = PendingReceipt {
+            id: self.new_id(),
+            signer_id: signer_id.clone(),
+            predecessor_id: signer_id.clone(),
+            receiver_id: receiver_id.clone(),
+            block_index: self.block_index,
+            actions: vec![Action::FunctionCall(FunctionCallAction {
+                method_name: method_name.as_bytes().to_vec(),
+                args,
+                gas,
+                deposit,
+            })],
+            input_data_ids: vec![],
+            output_data_receivers: vec![(result_data_id, signer_id.clone())],
+        };
+        self.pending.push_back(receipt);
+
+        let mut last_block_index = self.block_index;
+        while let Some(position) = self.pending.iter().position(|receipt| {
+            receipt.input_data_ids.iter().all(|data_id| self.data.contains_key(data_id))
+        }) {
+            let receipt = self.pending.remove(position).unwrap();
+            last_block_index = last_block_index.max(receipt.block_index);
+            self.execute_receipt(receipt);
+        }
+        self.block_index = last_block_index + 1;
+        let stuck: Vec<u64> = self.pending.drain(..).map(|receipt| receipt.id).collect();
+        let result = self.data.remove(&result_data_id).unwrap_or(PromiseResult::NotReady);
+        self.data.clear();
+        if !stuck.is_empty() {
+            self.outcomes.clear();
+            return Err(ExecutionError::ReceiptsNeverExecuted(stuck));
+        }
+        Ok(CallResult { result, outcomes: std::mem::take(&mut self.outcomes) })
+    }
+
+    fn new_id(&mut self) -> u64 {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    fn execute_receipt(&mut self, receipt: PendingReceipt) {
+        let promise_results: Vec<PromiseResult> = receipt
+            .input_data_ids
+            .iter()
+            .map(|data_id| self.data.remove(data_id).unwrap())
+            .collect();
+        let snapshot = self.accounts.get(&receipt.receiver_id).cloned();
+        let mut outcome = ReceiptOutcome {
+            receipt_id: receipt.id,
+            predecessor_id: receipt.predecessor_id.clone(),
+            receiver_id: receipt.receiver_id.clone(),
+            block_index: receipt.block_index,
+            logs: vec![],
+            gas_burnt: 0,
+            gas_refund: 0,
+            error: None,
+        };
+
+        let mut new_receipts = vec![];
+        let mut return_data = ReturnData::None;
+        let mut prepaid_gas = 0;
+        let mut used_gas = 0;
+        for action in &receipt.actions {
+            if let Action::FunctionCall(function_call) = action {
+                prepaid_gas += function_call.gas;
+            }
+            // The result of a receipt is the result of its last action.
+            return_data = ReturnData::None;
+            if let Err(err) = self.apply_action(
+                &receipt,
+                action,
+                &promise_results,
+                &mut outcome,
+                &mut used_gas,
+                &mut new_receipts,
+                &mut return_data,
+            ) {
+                outcome.error = Some(err);
+                break;
+            }
+        }
+
+        let deposit = total_deposit(&receipt.actions);
+        if outcome.error.is_some() {
+            // Revert the state changes and return the attached deposit to the predecessor.
+            match snapshot {
+                Some(account) => self.accounts.insert(receipt.receiver_id.clone(), account),
+                None => self.accounts.remove(&receipt.receiver_id),
+            };
+            if deposit > 0 && !is_system(&receipt.predecessor_id) {
+                self.push_refund(&receipt.predecessor_id, deposit);
+            }
+            for (data_id, _) in &receipt.output_data_receivers {
+                self.data.insert(*data_id, PromiseResult::Failed);
+            }
+            used_gas = outcome.gas_burnt;
+        } else {
+            let return_receipt_index = match return_data {
+                ReturnData::ReceiptIndex(index) => Some(index),
+                ReturnData::Value(value) => {
+                    for (data_id, _) in &receipt.output_data_receivers {
+                        self.data.insert(*data_id, PromiseResult::Successful(value.clone()));
+                    }
+                    None
+                }
+                ReturnData::None => {
+                    for (data_id, _) in &receipt.output_data_receivers {
+                        self.data.insert(*data_id, PromiseResult::Successful(vec![]));
+                    }
+                    None
+                }
+            };
+            self.schedule_receipts(&receipt, new_receipts, return_receipt_index);
+        }
+
+        outcome.gas_refund = prepaid_gas.saturating_sub(used_gas);
+        if outcome.gas_refund > 0 {
+            let refund = Balance::from(outcome.gas_refund) * self.gas_price;
+            self.push_refund(&receipt.signer_id, refund);
+        }
+        self.outcomes.push(outcome);
+    }
+
+    fn apply_action(
+        &mut self,
+        receipt: &PendingReceipt,
+        action: &Action,
+        promise_results: &[PromiseResult],
+        outcome: &mut ReceiptOutcome,
+        used_gas: &mut Gas,
+        new_receipts: &mut Vec<Receipt>,
+        return_data: &mut ReturnData,
+    ) -> Result<(), ExecutionError> {
+        let receiver_id = &receipt.receiver_id;
+        if let Action::CreateAccount = action {
+            if self.accounts.contains_key(receiver_id) {
+                return Err(ExecutionError::AccountAlreadyExists(receiver_id.clone()));
+            }
+            self.accounts.insert(receiver_id.clone(), MockedAccount::default());
+            return Ok(());
+        }
+        let account = self
+            .accounts
+            .get_mut(receiver_id)
+            .ok_or_else(|| ExecutionError::AccountDoesNotExist(receiver_id.clone()))?;
+        match action {
+            Action::CreateAccount => unreachable!(),
+            Action::DeployContract(deploy) => {
+                account.code = Some(ContractCode::new(deploy.code.clone(), None));
+            }
+            Action::Transfer(transfer) => account.balance += transfer.deposit,
+            Action::FunctionCall(function_call) => {
+                account.balance += function_call.deposit;
+                let code = account
+                    .code
+                    .clone()
+                    .ok_or_else(|| ExecutionError::ContractNotDeployed(receiver_id.clone()))?;
+                let mut external = MockedExternal::new();
+                external.fake_trie = account.storage.clone();
+                let context = VMContext {
+                    current_account_id: receiver_id.clone(),
+                    signer_account_id: receipt.signer_id.clone(),
+                    signer_account_pk: vec![],
+                    predecessor_account_id: receipt.predecessor_id.clone(),
+                    input: function_call.args.clone(),
+                    block_index: receipt.block_index,
+                    block_timestamp: self.block_timestamp + receipt.block_index * BLOCK_INTERVAL_NS,
+                    epoch_height: 1,
+                    account_balance: account.balance,
+                    account_locked_balance: 0,
+                    storage_usage: account.storage_usage,
+                    attached_deposit: function_call.deposit,
+                    prepaid_gas: function_call.gas,
+                    random_seed: hash(&receipt.id.to_le_bytes()).as_ref().to_vec(),
+                    view_config: None,
+                    output_data_receivers: receipt
+                        .output_data_receivers
+                        .iter()
+                        .map(|(_, account_id)| account_id.clone())
+                        .collect(),
+                };
+                let method_name = String::from_utf8_lossy(&function_call.method_name);
+                let (vm_outcome, err) = run(
+                    &code,
+                    &method_name,
+                    &mut external,
+                    context,
+                    &self.wasm_config,
+                    &self.fees_config,
+                    promise_results,
+                    self.protocol_version,
+                    None,
+                );
+                if let Some(vm_outcome) = &vm_outcome {
+                    outcome.logs.extend(vm_outcome.logs.iter().cloned());
+                    outcome.gas_burnt += vm_outcome.burnt_gas;
+                }
+                if let Some(err) = err {
+                    return Err(ExecutionError::VMError(err));
+                }
+                let vm_outcome = vm_outcome.expect("outcome is present when there is no error");
+                *used_gas += vm_outcome.used_gas;
+                account.balance = vm_outcome.balance;
+                account.storage_usage = vm_outcome.storage_usage;
+                account.storage = external.fake_trie.clone();
+                // Receipt indices of a function call are local to it, shift them so that they
+                // index into the receipts created by the whole receipt.
+                let offset = new_receipts.len() as u64;
+                for mut new_receipt in external.get_receipt_create_calls().iter().cloned() {
+                    new_receipt.receipt_indices.iter_mut().for_each(|index| *index += offset);
+                    new_receipts.push(new_receipt);
+                }
+                *return_data = match vm_outcome.return_data {
+                    ReturnData::ReceiptIndex(index) => ReturnData::ReceiptIndex(index + offset),
+                    return_data => return_data,
+                };
+            }
+            Action::DeleteAccount(delete_account) => {
+                let balance = account.balance;
+                self.accounts.remove(receiver_id);
+                if balance > 0 {
+                    self.push_refund(&delete_account.beneficiary_id, balance);
+                }
+            }
+            Action::Stake(_)
+            | Action::AddKeyWithFullAccess(_)
+            | Action::AddKeyWithFunctionCall(_)
+            | Action::DeleteKey(_) => {}
+        }
+        Ok(())
+    }
+
+    /// Turns receipts created by `parent` into pending receipts, wiring the promise results
+    /// between them.
+    fn schedule_receipts(
+        &mut self,
+        parent: &PendingReceipt,
+        new_receipts: Vec<Receipt>,
+        return_receipt_index: Option<u64>,
+    ) {
+        let first_id = self.pending.len();
+        for new_receipt in &new_receipts {
+            let mut input_data_ids = vec![];
+            for dependency_index in &new_receipt.receipt_indices {
+                let data_id = self.new_id();
+                input_data_ids.push(data_id);
+                let dependency = &mut self.pending[first_id + *dependency_index as usize];
+                dependency.output_data_receivers.push((data_id, new_receipt.receiver_id.clone()));
+            }
+            let receipt = PendingReceipt {
+                id: self.new_id(),
+                signer_id: parent.signer_id.clone(),
+                predecessor_id: parent.receiver_id.clone(),
+                receiver_id: new_receipt.receiver_id.clone(),
+                block_index: parent.block_index + 1,
+                actions: new_receipt.actions.clone(),
+                input_data_ids,
+                output_data_receivers: vec![],
+            };
+            self.pending.push_back(receipt);
+        }
+        if let Some(index) = return_receipt_index {
+            let returned = &mut self.pending[first_id + index as usize];
+            returned.output_data_receivers.extend(parent.output_data_receivers.iter().cloned());
+        }
+    }
+
+    /// Refunds are sent on behalf of the system account and are never refunded back.
+    fn push_refund(&mut self, receiver_id: &AccountId, amount: Balance) {
+        let system_id: AccountId = SYSTEM_ACCOUNT_ID.parse().unwrap();
+        let receipt = PendingReceipt {
+            id: self.new_id(),
+            signer_id: system_id.clone(),
+            predecessor_id: system_id,
+            receiver_id: receiver_id.clone(),
+            block_index: self.block_index,
+            actions: vec![Action::Transfer(TransferAction { deposit: amount })],
+            input_data_ids: vec![],
+            output_data_receivers: vec![],
+        };
+        self.pending.push_back(receipt);
+    }
+}
+
+impl Default for Simulator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn total_deposit(actions: &[Action]) -> Balance {
+    actions
+        .iter()
+        .map(|action| match action {
+            Action::FunctionCall(function_call) => function_call.deposit,
+            Action::Transfer(transfer) => transfer.deposit,
+            _ => 0,
+        })
+        .sum()
+}
+
+fn is_system(account_id: &AccountId) -> bool {
+    account_id.as_ref() == SYSTEM_ACCOUNT_ID
+}
@@ -1,8 +1,12 @@
+mod analysis;
 mod cache;
 mod compile_errors;
 mod contract_preload;
+#[cfg(all(feature = "wasmer2_vm", target_arch = "x86_64"))]
+mod gas_metering;
 mod rs_contract;
 mod runtime_errors;
+mod simulator;
 mod ts_contract;
 mod wasm_validation;
 
@@ -0,0 +1,57 @@
+use crate::analysis::analyze_contract;
+use crate::tests::LATEST_PROTOCOL_VERSION;
+use near_vm_errors::PrepareError;
+use near_vm_logic::VMConfig;
+
+#[test]
+fn test_analyze_rs_contract() {
+    let analysis = analyze_contract(
+        near_test_contracts::rs_contract(),
+        &VMConfig::test(),
+        LATEST_PROTOCOL_VERSION,
+    );
+    assert!(analysis.is_ok(), "{:?}", analysis);
+    assert!(analysis.methods.iter().any(|method| method == "log_something"));
+    assert!(analysis.imports.iter().any(|import| import == "value_return"));
+    let contract_size = analysis.limits.iter().find(|limit| limit.name == "contract_size").unwrap();
+    assert_eq!(contract_size.used, near_test_contracts::rs_contract().len() as u64);
+    assert!(contract_size.headroom().unwrap() > 0);
+}
+
+#[test]
+fn test_analyze_too_many_functions() {
+    let mut config = VMConfig::test();
+    config.limit_config.max_functions_number_per_contract = Some(10);
+    let analysis = analyze_contract(
+        &near_test_contracts::many_functions_contract(11),
+        &config,
+        LATEST_PROTOCOL_VERSION,
+    );
+    assert!(!analysis.is_ok());
+    assert_eq!(analysis.prepare_error, Some(PrepareError::TooManyFunctions));
+    let functions = analysis.limits.iter().find(|limit| limit.name == "functions").unwrap();
+    assert_eq!((functions.used, functions.headroom()), (11, Some(-1)));
+}
+
+#[test]
+fn test_analyze_unknown_import_and_locals() {
+    let code = wat::parse_str(
+        r#"
+            (module
+              (import "env" "no_such_function" (func (param i64)))
+              (func (export "main") (local i64 i64 i64))
+              (memory 1)
+              (data (i32.const 65536) "x")
+            )"#,
+    )
+    .unwrap();
+    let mut config = VMConfig::test();
+    config.limit_config.max_stack_height = 2;
+    let analysis = analyze_contract(&code, &config, LATEST_PROTOCOL_VERSION);
+    assert_eq!(analysis.prepare_error, None);
+    assert_eq!(analysis.unknown_imports, vec!["env.no_such_function".to_string()]);
+    let limit = |name| analysis.limits.iter().find(|limit| limit.name == name).unwrap().clone();
+    assert_eq!((limit("function_locals").used, limit("function_locals").exceeded()), (3, true));
+    assert_eq!(limit("data_memory_pages").used, 2);
+    assert!(!analysis.is_ok());
+}
@@ -0,0 +1,340 @@
+//! Checks that `GasMetering::Middleware` charges exactly the same gas as
+//! `GasMetering::Instrumentation`.
+
+use crate::metering::{
+    check_stack_limiter, determine_metered_blocks, instrumented_stack_costs, MeteredBlock,
+};
+use crate::tests::{create_context, LATEST_PROTOCOL_VERSION};
+use crate::vm_kind::VMKind;
+use near_primitives::contract::ContractCode;
+use near_primitives::runtime::fees::RuntimeFeesConfig;
+use near_vm_errors::VMError;
+use near_vm_logic::mocks::mock_external::MockedExternal;
+use near_vm_logic::{GasMetering, VMConfig, VMOutcome};
+use pwasm_utils::parity_wasm::elements::{self, Instruction};
+
+fn loops_and_branches_contract() -> Vec<u8> {
+    wat::parse_str(
+        r#"
+            (module
+              (func $sum (param i32) (result i32) (local i32)
+                (block
+                  (loop
+                    (br_if 1 (i32.eqz (local.get 0)))
+                    (local.set 1 (i32.add (local.get 1) (local.get 0)))
+                    (local.set 0 (i32.sub (local.get 0) (i32.const 1)))
+                    (br 0)))
+                (local.get 1))
+              (func $classify (param i32) (result i32)
+                (block (block (block
+                  (br_table 0 1 2 (local.get 0)))
+                  (return (i32.const 10)))
+                  (return (i32.const 20)))
+                (if (result i32) (i32.gt_u (local.get 0) (i32.const 5))
+                  (then (i32.const 30))
+                  (else (i32.const 40))))
+              (func (export "hello")
+                (drop (call $sum (i32.const 1000)))
+                (drop (call $classify (i32.const 0)))
+                (drop (call $classify (i32.const 1)))
+                (drop (call $classify (i32.const 7)))
+                (drop (call $classify (i32.const 2))))
+            )"#,
+    )
+    .unwrap()
+}
+
+fn infinite_loop_contract() -> Vec<u8> {
+    wat::parse_str(
+        r#"
+            (module
+              (func $f (param i32) (result i32)
+                (i32.add (local.get 0) (i32.const 1)))
+              (func (export "hello") (local i32)
+                (loop
+                  (local.set 0 (call $f (local.get 0)))
+                  (br 0)))
+            )"#,
+    )
+    .unwrap()
+}
+
+fn stack_overflow_contract() -> Vec<u8> {
+    wat::parse_str(
+        r#"
+            (module
+              (func $f (export "hello") (local i32 i32)
+                (block
+                  (call $f)))
+            )"#,
+    )
+    .unwrap()
+}
+
+/// The metered block of the loop starts with the value stack at its maximal height, so the
+/// injected `i32.const` raises the stack cost of the function.
+fn stack_cost_contract() -> Vec<u8> {
+    wat::parse_str(
+        r#"
+            (module
+              (func $f (export "hello")
+                (i32.const 7)
+                (loop (nop))
+                (drop)
+                (call $f))
+            )"#,
+    )
+    .unwrap()
+}
+
+fn memory_grow_contract() -> Vec<u8> {
+    wat::parse_str(
+        r#"
+            (module
+              (func (export "hello")
+                (loop
+                  (memory.grow (i32.const 1))
+                  drop
+                  br 0))
+              (memory 17 32)
+            )"#,
+    )
+    .unwrap()
+}
+
+fn indirect_call_contract() -> Vec<u8> {
+    wat::parse_str(
+        r#"
+            (module
+              (type $t (func (param i32) (result i32)))
+              (func $double (type $t) (i32.mul (local.get 0) (i32.const 2)))
+              (func $inc (type $t) (i32.add (local.get 0) (i32.const 1)))
+              (table 2 funcref)
+              (elem (i32.const 0) $double $inc)
+              (func (export "hello")
+                (drop (call_indirect (type $t) (i32.const 3) (i32.const 0)))
+                (drop (call_indirect (type $t) (i32.const 3) (i32.const 1))))
+            )"#,
+    )
+    .unwrap()
+}
+
+fn run(
+    code: &[u8],
+    method_name: &str,
+    input: &[u8],
+    gas_metering: GasMetering,
+) -> (Option<VMOutcome>, Option<VMError>) {
+    let mut fake_external = MockedExternal::new();
+    let context = create_context(input.to_vec());
+    let mut config = VMConfig::test();
+    config.limit_config.gas_metering = gas_metering;
+    let fees = RuntimeFeesConfig::test();
+    let code = ContractCode::new(code.to_vec(), None);
+    let runtime = VMKind::Wasmer2.runtime(config).expect("runtime has not been compiled");
+    runtime.run(
+        &code,
+        method_name,
+        &mut fake_external,
+        context,
+        &fees,
+        &[],
+        LATEST_PROTOCOL_VERSION,
+        None,
+    )
+}
+
+#[track_caller]
+fn assert_same_gas(code: &[u8], method_name: &str, input: &[u8]) {
+    let (expected_outcome, expected_err) =
+        run(code, method_name, input, GasMetering::Instrumentation);
+    let (outcome, err) = run(code, method_name, input, GasMetering::Middleware);
+    assert_eq!(err, expected_err, "{}", method_name);
+    match (outcome, expected_outcome) {
+        (Some(outcome), Some(expected_outcome)) => {
+            assert_eq!(outcome.burnt_gas, expected_outcome.burnt_gas, "{}", method_name);
+            assert_eq!(outcome.used_gas, expected_outcome.used_gas, "{}", method_name);
+            assert_eq!(outcome.return_data, expected_outcome.return_data, "{}", method_name);
+        }
+        (None, None) => {}
+        (outcome, expected_outcome) => {
+            panic!("{}: {:?} != {:?}", method_name, outcome, expected_outcome)
+        }
+    }
+}
+
+#[test]
+fn test_middleware_metering_wat_contracts() {
+    assert_same_gas(&loops_and_branches_contract(), "hello", &[]);
+    assert_same_gas(&infinite_loop_contract(), "hello", &[]);
+    assert_same_gas(&stack_overflow_contract(), "hello", &[]);
+    assert_same_gas(&stack_cost_contract(), "hello", &[]);
+    assert_same_gas(&memory_grow_contract(), "hello", &[]);
+    assert_same_gas(&indirect_call_contract(), "hello", &[]);
+}
+
+#[test]
+fn test_middleware_metering_rs_contract() {
+    let code = near_test_contracts::rs_contract();
+    for method_name in [
+        "ext_account_id",
+        "ext_signer_id",
+        "ext_predecessor_account_id",
+        "ext_signer_pk",
+        "ext_random_seed",
+        "ext_prepaid_gas",
+        "ext_block_index",
+        "ext_block_timestamp",
+        "ext_storage_usage",
+        "ext_used_gas",
+        "ext_sha256",
+        "ext_account_balance",
+        "ext_attached_deposit",
+    ] {
+        assert_same_gas(code, method_name, b"tesdsst");
+    }
+    assert_same_gas(code, "write_key_value", &[10, 0, 0, 0, 0, 0, 0, 0, 20, 0, 0, 0, 0, 0, 0, 0]);
+    assert_same_gas(code, "sum_n", &1000u64.to_le_bytes());
+    assert_same_gas(code, "fibonacci", &[16]);
+    assert_same_gas(code, "recurse", &100u64.to_le_bytes());
+    // Runs out of stack.
+    assert_same_gas(code, "recurse", &1_000_000u64.to_le_bytes());
+    // Runs out of gas.
+    assert_same_gas(code, "loop_forever", &[]);
+}
+
+#[test]
+fn test_middleware_metering_ts_contract() {
+    let code = near_test_contracts::ts_contract();
+    assert_same_gas(code, "try_panic", &[]);
+    assert_same_gas(code, "try_storage_write", b"foo bar");
+    assert_same_gas(code, "try_storage_read", b"foo");
+}
+
+/// Returns the metered blocks `pwasm-utils` injects into the functions of `module`, as positions
+/// in the original function bodies.
+fn pwasm_metered_blocks(module: elements::Module) -> Vec<Vec<MeteredBlock>> {
+    let gas_function = module.import_count(elements::ImportCountType::Function) as u32;
+    let rules = pwasm_utils::rules::Set::new(1, Default::default());
+    let module = pwasm_utils::inject_gas_counter(module, &rules, "env").unwrap();
+    let bodies = module.code_section().map_or(&[][..], |section| section.bodies());
+    bodies
+        .iter()
+        .map(|body| {
+            let mut blocks = vec![];
+            let mut position = 0;
+            let mut instructions = body.code().elements().iter().peekable();
+            while let Some(instruction) = instructions.next() {
+                match (instruction, instructions.peek()) {
+                    (Instruction::I32Const(cost), Some(Instruction::Call(f)))
+                        if *f == gas_function =>
+                    {
+                        blocks.push(MeteredBlock { start_pos: position, cost: *cost as u32 });
+                        instructions.next();
+                    }
+                    _ => position += 1,
+                }
+            }
+            blocks
+        })
+        .collect()
+}
+
+#[test]
+fn test_metered_blocks_match_pwasm() {
+    for code in [
+        near_test_contracts::rs_contract().to_vec(),
+        near_test_contracts::ts_contract().to_vec(),
+        near_test_contracts::fuzzing_contract().to_vec(),
+        loops_and_branches_contract(),
+        infinite_loop_contract(),
+        indirect_call_contract(),
+    ] {
+        let module = pwasm_utils::parity_wasm::deserialize_buffer(&code).unwrap();
+        let blocks = module
+            .code_section()
+            .map_or(&[][..], |section| section.bodies())
+            .iter()
+            .map(|body| determine_metered_blocks(body.code().elements()).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(blocks, pwasm_metered_blocks(module));
+    }
+}
+
+/// Returns the stack costs `pwasm-utils` puts into the stack limiter before the calls to the
+/// functions defined by `module`, when the gas metering is injected first, by defined index.
+fn pwasm_stack_costs(module: elements::Module) -> Vec<(usize, u32)> {
+    let gas_function = module.import_count(elements::ImportCountType::Function) as u32;
+    let num_defined = module.code_section().map_or(0, |section| section.bodies().len());
+    let rules = pwasm_utils::rules::Set::new(1, Default::default()).with_grow_cost(1);
+    let module = pwasm_utils::inject_gas_counter(module, &rules, "env").unwrap();
+    let module = pwasm_utils::stack_height::inject_limiter(module, 1024).unwrap();
+    let bodies = module.code_section().map_or(&[][..], |section| section.bodies());
+    let mut costs = vec![];
+    for body in bodies {
+        for window in body.code().elements().windows(11) {
+            if let (Instruction::I32Const(cost), Instruction::I32Add, Instruction::Call(f)) =
+                (&window[1], &window[2], &window[10])
+            {
+                // Skip the functions injected during the instrumentation.
+                let defined = (*f as usize).checked_sub(gas_function as usize + 1);
+                if let Some(defined) = defined.filter(|defined| *defined < num_defined) {
+                    costs.push((defined, *cost as u32));
+                }
+            }
+        }
+    }
+    costs
+}
+
+#[test]
+fn test_stack_costs_match_pwasm() {
+    for code in [
+        near_test_contracts::rs_contract().to_vec(),
+        near_test_contracts::ts_contract().to_vec(),
+        near_test_contracts::fuzzing_contract().to_vec(),
+        loops_and_branches_contract(),
+        stack_overflow_contract(),
+        stack_cost_contract(),
+        memory_grow_contract(),
+        indirect_call_contract(),
+    ] {
+        let module: elements::Module = pwasm_utils::parity_wasm::deserialize_buffer(&code).unwrap();
+        let blocks = module
+            .code_section()
+            .map_or(&[][..], |section| section.bodies())
+            .iter()
+            .map(|body| determine_metered_blocks(body.code().elements()).unwrap())
+            .collect::<Vec<_>>();
+        let costs = instrumented_stack_costs(&module, &blocks).unwrap();
+        for (defined, cost) in pwasm_stack_costs(module) {
+            assert_eq!(costs[defined], cost, "function {}", defined);
+        }
+    }
+}
+
+#[test]
+fn test_check_stack_limiter_rejects_unexpected_code() {
+    let config = VMConfig::test();
+    let (code, info) = crate::prepare::prepare_contract_for_middleware_metering(
+        &infinite_loop_contract(),
+        &config,
+    )
+    .unwrap();
+    let info = info.unwrap();
+    let mut module: elements::Module = pwasm_utils::parity_wasm::deserialize_buffer(&code).unwrap();
+    assert_eq!(check_stack_limiter(&module, &info), Ok(()));
+
+    // Release the stack height by a different amount than it was raised by before the call.
+    let body = module
+        .code_section_mut()
+        .unwrap()
+        .bodies_mut()
+        .iter_mut()
+        .find(|body| body.code().elements().contains(&Instruction::I32Sub))
+        .unwrap();
+    let position =
+        body.code().elements().iter().position(|i| *i == Instruction::I32Sub).unwrap() - 1;
+    body.code_mut().elements_mut()[position] = Instruction::I32Const(1_000);
+    assert_eq!(check_stack_limiter(&module, &info), Err(()));
+}
@@ -0,0 +1,158 @@
+use crate::simulator::Simulator;
+use crate::tests::LATEST_PROTOCOL_VERSION;
+use near_primitives::runtime::fees::RuntimeFeesConfig;
+use near_primitives::types::{AccountId, Balance};
+use near_vm_logic::types::PromiseResult;
+use near_vm_logic::VMConfig;
+
+const INITIAL_BALANCE: Balance = 10u128.pow(25);
+const PREPAID_GAS: u64 = 100_000_000_000_000;
+
+/// Calls `bob` and returns the result of the call through a callback, or `failed` if the call
+/// has failed.
+fn caller_contract() -> Vec<u8> {
+    wat::parse_str(
+        r#"
+            (module
+              (import "env" "promise_create" (func $promise_create (param i64 i64 i64 i64 i64 i64 i64 i64) (result i64)))
+              (import "env" "promise_then" (func $promise_then (param i64 i64 i64 i64 i64 i64 i64 i64 i64) (result i64)))
+              (import "env" "promise_return" (func $promise_return (param i64)))
+              (import "env" "promise_result" (func $promise_result (param i64 i64) (result i64)))
+              (import "env" "read_register" (func $read_register (param i64 i64)))
+              (import "env" "register_len" (func $register_len (param i64) (result i64)))
+              (import "env" "value_return" (func $value_return (param i64 i64)))
+              (memory 1)
+              (data (i32.const 0) "bob")
+              (data (i32.const 8) "answer")
+              (data (i32.const 16) "alice")
+              (data (i32.const 24) "callback")
+              (data (i32.const 48) "fail")
+              (data (i32.const 56) "failed")
+              (func $call_bob (param $method_len i64) (param $method_ptr i64)
+                (call $promise_return
+                  (call $promise_then
+                    (call $promise_create
+                      (i64.const 3) (i64.const 0)
+                      (local.get $method_len) (local.get $method_ptr)
+                      (i64.const 0) (i64.const 0)
+                      (i64.const 32) (i64.const 20000000000000))
+                    (i64.const 5) (i64.const 16)
+                    (i64.const 8) (i64.const 24)
+                    (i64.const 0) (i64.const 0)
+                    (i64.const 32) (i64.const 20000000000000))))
+              (func (export "call_answer") (call $call_bob (i64.const 6) (i64.const 8)))
+              (func (export "call_fail") (call $call_bob (i64.const 4) (i64.const 48)))
+              (func (export "callback")
+                (if (i64.eq (call $promise_result (i64.const 0) (i64.const 0)) (i64.const 1))
+                  (then
+                    (call $read_register (i64.const 0) (i64.const 64))
+                    (call $value_return (call $register_len (i64.const 0)) (i64.const 64)))
+                  (else
+                    (call $value_return (i64.const 6) (i64.const 56)))))
+            )"#,
+    )
+    .unwrap()
+}
+
+fn callee_contract() -> Vec<u8> {
+    wat::parse_str(
+        r#"
+            (module
+              (import "env" "value_return" (func $value_return (param i64 i64)))
+              (memory 1)
+              (data (i32.const 0) "42")
+              (func (export "answer") (call $value_return (i64.const 2) (i64.const 0)))
+              (func (export "fail") unreachable)
+            )"#,
+    )
+    .unwrap()
+}
+
+fn setup() -> (Simulator, AccountId, AccountId) {
+    let mut simulator = Simulator::with_config(
+        VMConfig::test(),
+        RuntimeFeesConfig::test(),
+        LATEST_PROTOCOL_VERSION,
+    );
+    let alice: AccountId = "alice".parse().unwrap();
+    let bob: AccountId = "bob".parse().unwrap();
+    let carol: AccountId = "carol".parse().unwrap();
+    simulator.add_account(alice.clone(), INITIAL_BALANCE);
+    simulator.add_account(bob.clone(), INITIAL_BALANCE);
+    simulator.add_account(carol.clone(), INITIAL_BALANCE);
+    simulator.deploy(&alice, &caller_contract());
+    simulator.deploy(&bob, &callee_contract());
+    (simulator, alice, carol)
+}
+
+#[test]
+fn test_simulator_callback_receives_promise_result() {
+    let (mut simulator, alice, carol) = setup();
+    let result = simulator.call(&carol, &alice, "call_answer", vec![], PREPAID_GAS, 0).unwrap();
+    assert_eq!(result.result, PromiseResult::Successful(b"42".to_vec()));
+    let receivers: Vec<&str> = result
+        .outcomes
+        .iter()
+        .filter(|outcome| outcome.gas_burnt > 0)
+        .map(|outcome| outcome.receiver_id.as_ref())
+        .collect();
+    assert_eq!(receivers, vec!["alice", "bob", "alice"]);
+}
+
+#[test]
+fn test_simulator_callback_receives_failure() {
+    let (mut simulator, alice, carol) = setup();
+    let result = simulator.call(&carol, &alice, "call_fail", vec![], PREPAID_GAS, 0).unwrap();
+    assert_eq!(result.result, PromiseResult::Successful(b"failed".to_vec()));
+    assert_eq!(result.outcomes.iter().filter(|outcome| outcome.error.is_some()).count(), 1);
+}
+
+#[test]
+fn test_simulator_refunds_unused_gas() {
+    let (mut simulator, alice, carol) = setup();
+    let gas_price = 100;
+    simulator.gas_price(gas_price);
+    let result = simulator.call(&carol, &alice, "call_answer", vec![], PREPAID_GAS, 0).unwrap();
+    let burnt = Balance::from(result.total_gas_burnt()) * gas_price;
+    assert_eq!(simulator.account(&carol).unwrap().balance, INITIAL_BALANCE - burnt);
+}
+
+#[test]
+fn test_simulator_refunds_deposit_of_failed_call() {
+    let (mut simulator, _, carol) = setup();
+    simulator.gas_price(0);
+    let bob: AccountId = "bob".parse().unwrap();
+    let deposit = 1000;
+    let result = simulator.call(&carol, &bob, "fail", vec![], PREPAID_GAS, deposit).unwrap();
+    assert_eq!(result.result, PromiseResult::Failed);
+    assert_eq!(simulator.account(&carol).unwrap().balance, INITIAL_BALANCE);
+    assert_eq!(simulator.account(&bob).unwrap().balance, INITIAL_BALANCE);
+}
+
+#[test]
+fn test_simulator_random_seed_has_runtime_length() {
+    let (mut simulator, _, carol) = setup();
+    let dave: AccountId = "dave".parse().unwrap();
+    simulator.add_account(dave.clone(), INITIAL_BALANCE);
+    let code = wat::parse_str(
+        r#"
+            (module
+              (import "env" "random_seed" (func $random_seed (param i64)))
+              (import "env" "read_register" (func $read_register (param i64 i64)))
+              (import "env" "register_len" (func $register_len (param i64) (result i64)))
+              (import "env" "value_return" (func $value_return (param i64 i64)))
+              (memory 1)
+              (func (export "seed")
+                (call $random_seed (i64.const 0))
+                (call $read_register (i64.const 0) (i64.const 0))
+                (call $value_return (call $register_len (i64.const 0)) (i64.const 0)))
+            )"#,
+    )
+    .unwrap();
+    simulator.deploy(&dave, &code);
+    let result = simulator.call(&carol, &dave, "seed", vec![], PREPAID_GAS, 0).unwrap();
+    match result.result {
+        PromiseResult::Successful(seed) => assert_eq!(seed.len(), 32),
+        other => panic!("unexpected result {:?}", other),
+    }
+}
@@ -1,5 +1,6 @@
 use crate::cache::into_vm_result;
 use crate::imports::wasmer2::Wasmer2Imports;
+use crate::metering::{GasMeteringMiddleware, MeteringInfo};
 use crate::prepare::WASM_FEATURES;
 use crate::{cache, imports};
 use memoffset::offset_of;
@@ -14,6 +15,7 @@ use near_vm_logic::{External, MemoryLike, VMConfig, VMContext, VMLogic, VMOutcom
 use std::hash::{Hash, Hasher};
 use std::mem::size_of;
 use std::sync::Arc;
+use wasmer_compiler::CompilerConfig;
 use wasmer_compiler_singlepass::Singlepass;
 use wasmer_engine::{DeserializeError, Engine};
 use wasmer_engine_universal::{Universal, UniversalEngine};
@@ -240,6 +242,25 @@ pub(crate) fn wasmer2_vm_hash() -> u64 {
     WASMER2_CONFIG.config_hash()
 }
 
+fn host_target() -> wasmer_compiler::Target {
+    use wasmer_compiler::{CpuFeature, Target, Triple};
+    let target_features = if cfg!(feature = "no_cpu_compatibility_checks") {
+        let mut fs = CpuFeature::set();
+        // These features should be sufficient to run the single pass compiler.
+        fs.insert(CpuFeature::SSE2);
+        fs.insert(CpuFeature::SSE3);
+        fs.insert(CpuFeature::SSSE3);
+        fs.insert(CpuFeature::SSE41);
+        fs.insert(CpuFeature::SSE42);
+        fs.insert(CpuFeature::POPCNT);
+        fs.insert(CpuFeature::AVX);
+        fs
+    } else {
+        CpuFeature::for_host()
+    };
+    Target::new(Triple::host(), target_features)
+}
+
 #[derive(Clone)]
 pub(crate) struct VMArtifact {
     artifact: Arc<dyn wasmer_engine::Artifact>,
@@ -269,9 +290,20 @@ pub(crate) struct Wasmer2VM {
 
 impl Wasmer2VM {
     pub(crate) fn new_for_target(config: VMConfig, target: wasmer_compiler::Target) -> Self {
+        Self::build(config, target, None)
+    }
+
+    fn build(
+        config: VMConfig,
+        target: wasmer_compiler::Target,
+        metering_info: Option<MeteringInfo>,
+    ) -> Self {
         // We only support singlepass compiler at the moment.
         assert_eq!(WASMER2_CONFIG.compiler, WasmerCompiler::Singlepass);
-        let compiler = Singlepass::new();
+        let mut compiler = Singlepass::new();
+        if let Some(metering_info) = metering_info {
+            compiler.push_middleware(Arc::new(GasMeteringMiddleware::new(metering_info)));
+        }
         // We only support universal engine at the moment.
         assert_eq!(WASMER2_CONFIG.engine, WasmerEngine::Universal);
         Self {
@@ -281,22 +313,18 @@ impl Wasmer2VM {
     }
 
     pub(crate) fn new(config: VMConfig) -> Self {
-        use wasmer_compiler::{CpuFeature, Target, Triple};
-        let target_features = if cfg!(feature = "no_cpu_compatibility_checks") {
-            let mut fs = CpuFeature::set();
-            // These features should be sufficient to run the single pass compiler.
-            fs.insert(CpuFeature::SSE2);
-            fs.insert(CpuFeature::SSE3);
-            fs.insert(CpuFeature::SSSE3);
-            fs.insert(CpuFeature::SSE41);
-            fs.insert(CpuFeature::SSE42);
-            fs.insert(CpuFeature::POPCNT);
-            fs.insert(CpuFeature::AVX);
-            fs
-        } else {
-            CpuFeature::for_host()
-        };
-        Self::new_for_target(config, Target::new(Triple::host(), target_features))
+        Self::new_for_target(config, host_target())
+    }
+
+    /// Creates a VM which charges `regular_op_cost` while compiling the contract, see
+    /// [`crate::metering`]. The code must be prepared with
+    /// [`crate::prepare::prepare_contract_for_middleware_metering`] which provides
+    /// `metering_info`.
+    pub(crate) fn new_with_middleware_metering(
+        config: VMConfig,
+        metering_info: MeteringInfo,
+    ) -> Self {
+        Self::build(config, host_target(), Some(metering_info))
     }
 
     pub(crate) fn compile_uncached(&self, code: &[u8]) -> Result<VMArtifact, CompilationError> {
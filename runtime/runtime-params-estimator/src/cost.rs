@@ -212,6 +212,9 @@ pub enum Cost {
     AltBn128G1SumBase,
     AltBn128G1SumByte,
 
+    Ed25519VerifyBase,
+    Ed25519VerifyByte,
+
     // Costs used only in estimator
     //
     /// Costs associated with applying an empty block. This overhead is not
@@ -159,6 +159,10 @@ fn ext_costs_config(cost_table: &CostTable) -> anyhow::Result<ExtCostsConfig> {
         alt_bn128_pairing_check_base: get(Cost::AltBn128PairingCheckBase)?,
         #[cfg(feature = "protocol_feature_alt_bn128")]
         alt_bn128_pairing_check_byte: get(Cost::AltBn128PairingCheckByte)?,
+        #[cfg(feature = "protocol_feature_ed25519_verify")]
+        ed25519_verify_base: get(Cost::Ed25519VerifyBase)?,
+        #[cfg(feature = "protocol_feature_ed25519_verify")]
+        ed25519_verify_byte: get(Cost::Ed25519VerifyByte)?,
     };
 
     Ok(res)
@@ -164,6 +164,8 @@ static ALL_COSTS: &[(Cost, fn(&mut EstimatorContext) -> GasCost)] = &[
     (Cost::AltBn128G1SumByte, alt_bn128g1_sum_byte),
     (Cost::AltBn128PairingCheckBase, alt_bn128_pairing_check_base),
     (Cost::AltBn128PairingCheckByte, alt_bn128_pairing_check_byte),
+    (Cost::Ed25519VerifyBase, ed25519_verify_base),
+    (Cost::Ed25519VerifyByte, ed25519_verify_byte),
     (Cost::StorageHasKeyBase, storage_has_key_base),
     (Cost::StorageHasKeyByte, storage_has_key_byte),
     (Cost::StorageReadBase, storage_read_base),
@@ -930,6 +932,24 @@ fn alt_bn128_pairing_check_byte(ctx: &mut EstimatorContext) -> GasCost {
     return GasCost::zero(ctx.config.metric);
 }
 
+fn ed25519_verify_base(ctx: &mut EstimatorContext) -> GasCost {
+    #[cfg(feature = "protocol_feature_ed25519_verify")]
+    return fn_cost(ctx, "ed25519_verify_32b_1k", ExtCosts::ed25519_verify_base, 1000);
+    #[cfg(not(feature = "protocol_feature_ed25519_verify"))]
+    return GasCost::zero(ctx.config.metric);
+}
+fn ed25519_verify_byte(ctx: &mut EstimatorContext) -> GasCost {
+    #[cfg(feature = "protocol_feature_ed25519_verify")]
+    return fn_cost(
+        ctx,
+        "ed25519_verify_16kib_1k",
+        ExtCosts::ed25519_verify_byte,
+        16 * 1024 * 1000,
+    );
+    #[cfg(not(feature = "protocol_feature_ed25519_verify"))]
+    return GasCost::zero(ctx.config.metric);
+}
+
 fn storage_has_key_base(ctx: &mut EstimatorContext) -> GasCost {
     fn_cost_with_setup(
         ctx,
@@ -57,6 +57,15 @@ extern "C" {
         malleability_flag: u64,
         register_id: u64,
     ) -> u64;
+    #[cfg(feature = "protocol_feature_ed25519_verify")]
+    fn ed25519_verify(
+        signature_len: u64,
+        signature_ptr: u64,
+        message_len: u64,
+        message_ptr: u64,
+        public_key_len: u64,
+        public_key_ptr: u64,
+    ) -> u64;
     // #####################
     // # Miscellaneous API #
     // #####################
@@ -481,6 +490,65 @@ pub unsafe fn ecrecover_10k() {
     }
 }
 
+#[cfg(feature = "protocol_feature_ed25519_verify")]
+const ED25519_PUBLIC_KEY: [u8; 32] = [
+    0x03, 0xa1, 0x07, 0xbf, 0xf3, 0xce, 0x10, 0xbe, 0x1d, 0x70, 0xdd, 0x18, 0xe7, 0x4b, 0xc0, 0x99,
+    0x67, 0xe4, 0xd6, 0x30, 0x9b, 0xa5, 0x0d, 0x5f, 0x1d, 0xdc, 0x86, 0x64, 0x12, 0x55, 0x31, 0xb8,
+];
+
+// Function to measure `ed25519_verify_base`. Also measures `base` and `ed25519_verify_byte` for
+// the 32 byte message, which is negligible compared to the signature verification.
+// Verify the signature of a 32 byte message 1k times.
+#[cfg(feature = "protocol_feature_ed25519_verify")]
+#[no_mangle]
+pub unsafe fn ed25519_verify_32b_1k() {
+    let message = [0u8; 32];
+    let signature: [u8; 64] = [
+        0xf7, 0x26, 0xa1, 0x14, 0x7f, 0x71, 0xf0, 0xde, 0xd8, 0xfb, 0x57, 0x6d, 0xdb, 0x36, 0x9a,
+        0xce, 0xbf, 0x5b, 0xec, 0xc3, 0xfc, 0x93, 0xa3, 0x1c, 0x82, 0x7f, 0xb1, 0x8e, 0xef, 0x8d,
+        0xe7, 0xb1, 0x68, 0xd4, 0xd5, 0x72, 0x05, 0x87, 0x4a, 0xda, 0x00, 0xde, 0xbe, 0x2e, 0x85,
+        0x50, 0x23, 0xe0, 0xec, 0xfc, 0x86, 0x58, 0x6e, 0x86, 0xbd, 0x7d, 0x4b, 0xd5, 0xb4, 0xfe,
+        0xb6, 0x40, 0xda, 0x0b,
+    ];
+    for _ in 0..1_000 {
+        ed25519_verify(
+            signature.len() as _,
+            signature.as_ptr() as _,
+            message.len() as _,
+            message.as_ptr() as _,
+            ED25519_PUBLIC_KEY.len() as _,
+            ED25519_PUBLIC_KEY.as_ptr() as _,
+        );
+    }
+}
+
+// Function to measure `ed25519_verify_byte`. Also measures `base` and `ed25519_verify_base`.
+// However hashing 16KiB message is more expensive than the rest of the verification so we are
+// okay overcharging it.
+// Verify the signature of a 16KiB message 1k times.
+#[cfg(feature = "protocol_feature_ed25519_verify")]
+#[no_mangle]
+pub unsafe fn ed25519_verify_16kib_1k() {
+    let message = [0u8; 16 * 1024];
+    let signature: [u8; 64] = [
+        0x8a, 0xc8, 0xf4, 0x0e, 0x51, 0x76, 0x09, 0xc0, 0x5c, 0x42, 0x8b, 0xc5, 0x11, 0x61, 0x36,
+        0xa9, 0x21, 0x15, 0x88, 0x5e, 0x2a, 0x81, 0x39, 0x63, 0xd4, 0xaf, 0x90, 0xdb, 0x35, 0xed,
+        0xf2, 0xdf, 0xe9, 0xdf, 0x77, 0xc1, 0x12, 0x55, 0xbf, 0x3c, 0x63, 0xf0, 0x4d, 0xf6, 0x8a,
+        0xd9, 0x7c, 0xa1, 0x3b, 0xbe, 0x36, 0xe3, 0x34, 0x87, 0x46, 0xe5, 0x50, 0x0a, 0xc2, 0xb8,
+        0xa4, 0xef, 0x71, 0x0d,
+    ];
+    for _ in 0..1_000 {
+        ed25519_verify(
+            signature.len() as _,
+            signature.as_ptr() as _,
+            message.len() as _,
+            message.as_ptr() as _,
+            ED25519_PUBLIC_KEY.len() as _,
+            ED25519_PUBLIC_KEY.as_ptr() as _,
+        );
+    }
+}
+
 // Function to measure `alt_bn128_g1_multiexp_base` and `alt_bn128_g1_multiexp_sublinear`. Also measures `base`, `write_register_base`,
 // and `write_register_byte`. However `g1_multiexp` computation is more expensive than register writing
 // so we are okay overcharging it.
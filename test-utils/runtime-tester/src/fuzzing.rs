@@ -47,7 +47,13 @@ impl Arbitrary<'_> for Scenario {
         while blocks.len() < MAX_BLOCKS && u.len() > BlockConfig::size_hint(0).0 {
             blocks.push(BlockConfig::arbitrary(u, &mut scope)?);
         }
-        Ok(Scenario { network_config, runtime_config, blocks, use_in_memory_store: true })
+        Ok(Scenario {
+            network_config,
+            runtime_config,
+            blocks,
+            use_in_memory_store: true,
+            initial_state_records: vec![],
+        })
     }
 
     fn size_hint(_depth: usize) -> (usize, Option<usize>) {
@@ -4,11 +4,12 @@ use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use near_chain::{Block, ChainGenesis, Provenance};
-use near_chain_configs::Genesis;
+use near_chain_configs::{get_initial_supply, Genesis};
 use near_client::test_utils::TestEnv;
 use near_client_primitives::types::Error;
 use near_crypto::InMemorySigner;
 use near_primitives::hash::CryptoHash;
+use near_primitives::state_record::StateRecord;
 use near_primitives::transaction::{Action, SignedTransaction};
 use near_primitives::types::{AccountId, BlockHeight, BlockHeightDelta, Gas, Nonce};
 use near_store::create_store;
@@ -43,6 +44,10 @@ impl Scenario {
             self.runtime_config.max_total_prepaid_gas;
         genesis.config.epoch_length = self.runtime_config.epoch_length;
         genesis.config.gas_limit = self.runtime_config.gas_limit;
+        if !self.initial_state_records.is_empty() {
+            genesis.records.0.extend(self.initial_state_records.iter().cloned());
+            genesis.config.total_supply = get_initial_supply(&genesis.records.0);
+        }
         let runtime_config_store = RuntimeConfigStore::with_one_config(runtime_config);
 
         let (tempdir, store) = if self.use_in_memory_store {
@@ -106,6 +111,11 @@ pub struct Scenario {
     pub runtime_config: RuntimeConfig,
     pub blocks: Vec<BlockConfig>,
     pub use_in_memory_store: bool,
+    /// Records added to the genesis on top of the accounts created for
+    /// `network_config.seeds`.  Used by scenarios recorded from a real chain
+    /// (see `neard view_state dump_scenario`).
+    #[serde(default)]
+    pub initial_state_records: Vec<StateRecord>,
 }
 
 #[derive(Serialize, Deserialize)]
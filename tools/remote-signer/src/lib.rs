@@ -0,0 +1,266 @@
+//! Reference implementation of the signer process used by `RemoteValidatorSigner`.
+//!
+//! The signer holds the validator key and answers signing requests from the node over a unix
+//! socket. Block headers, chunk headers and approvals are checked against the slashing protection
+//! state first, so the key never signs two conflicting blocks, chunks or approvals. The signer
+//! computes the signed hashes itself and prefixes everything else, so that a request can't be
+//! used to get a signature of a block, chunk or approval past the slashing protection.
+use std::io;
+use std::os::unix::net::{UnixListener, UnixStream};
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use tracing::{info, warn};
+
+use near_crypto::{InMemorySigner, Signer};
+use near_primitives::block::{Approval, BlockHeader};
+use near_primitives::block_header::BlockHeaderInnerLite;
+use near_primitives::hash::hash;
+use near_primitives::network::AnnounceAccount;
+use near_primitives::remote_signer::{
+    read_message, write_message, SignerRequest, SignerResponse, REMOTE_SIGNER_PROTOCOL_VERSION,
+};
+use near_primitives::sharding::ChunkStateWitness;
+use near_primitives::telemetry::TelemetryInfo;
+
+pub use crate::slashing_protection::{
+    SignedData, SlashingProtection, SlashingProtectionError, SlashingProtectionState,
+};
+
+mod slashing_protection;
+
+pub struct RemoteSigner {
+    signer: InMemorySigner,
+    slashing_protection: SlashingProtection,
+}
+
+impl RemoteSigner {
+    pub fn new(signer: InMemorySigner, slashing_protection: SlashingProtection) -> Self {
+        Self { signer, slashing_protection }
+    }
+
+    pub fn handle(&mut self, request: SignerRequest) -> SignerResponse {
+        match request {
+            SignerRequest::GetValidatorKey { protocol_version } => {
+                if protocol_version != REMOTE_SIGNER_PROTOCOL_VERSION {
+                    return SignerResponse::Refused(format!(
+                        "Unsupported protocol version {}, expected {}",
+                        protocol_version, REMOTE_SIGNER_PROTOCOL_VERSION
+                    ));
+                }
+                SignerResponse::ValidatorKey {
+                    account_id: self.signer.account_id.clone(),
+                    public_key: self.signer.public_key(),
+                }
+            }
+            SignerRequest::SignBlockHeader { prev_hash, inner_lite, inner_rest } => {
+                let height = match BlockHeaderInnerLite::try_from_slice(&inner_lite) {
+                    Ok(inner_lite) => inner_lite.height,
+                    Err(err) => {
+                        return SignerResponse::Refused(format!("Invalid block header: {}", err))
+                    }
+                };
+                let hash = BlockHeader::compute_hash(prev_hash, &inner_lite, &inner_rest);
+                if let Err(err) = self.slashing_protection.check_and_record_block(height, hash) {
+                    warn!(target: "remote_signer", "Refused to sign block {}: {}", hash, err);
+                    return SignerResponse::Refused(err.to_string());
+                }
+                SignerResponse::Signature(self.signer.sign(hash.as_ref()))
+            }
+            SignerRequest::SignApproval { inner, target_height } => {
+                let data = Approval::get_data_for_sig(&inner, target_height);
+                if let Err(err) =
+                    self.slashing_protection.check_and_record_approval(target_height, hash(&data))
+                {
+                    warn!(target: "remote_signer", "Refused to sign approval {:?}: {}", inner, err);
+                    return SignerResponse::Refused(err.to_string());
+                }
+                SignerResponse::Signature(self.signer.sign(&data))
+            }
+            SignerRequest::SignChunkHeader { header } => {
+                // The hash is recomputed from the header when it's deserialized.
+                let chunk_hash = header.chunk_hash();
+                if let Err(err) = self.slashing_protection.check_and_record_chunk(
+                    header.shard_id(),
+                    header.height_created(),
+                    chunk_hash.0,
+                ) {
+                    warn!(target: "remote_signer", "Refused to sign chunk {:?}: {}", chunk_hash, err);
+                    return SignerResponse::Refused(err.to_string());
+                }
+                SignerResponse::Signature(self.signer.sign(chunk_hash.as_ref()))
+            }
+            SignerRequest::SignChunkStateWitness { witness_hash } => SignerResponse::Signature(
+                self.signer.sign(&ChunkStateWitness::get_data_for_sig(&witness_hash)),
+            ),
+            SignerRequest::SignChallenge { challenge_body } => {
+                let hash = hash(&challenge_body.try_to_vec().expect("Failed to serialize"));
+                SignerResponse::Signature(self.signer.sign(hash.as_ref()))
+            }
+            SignerRequest::SignAccountAnnounce { account_id, peer_id, epoch_id } => {
+                let hash = AnnounceAccount::build_header_hash(&account_id, &peer_id, &epoch_id);
+                SignerResponse::Signature(self.signer.sign(hash.as_ref()))
+            }
+            SignerRequest::SignTelemetry { content } => {
+                // Only sign JSON objects, so that this can't be used to sign arbitrary data.
+                if serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(&content)
+                    .is_err()
+                {
+                    return SignerResponse::Refused("Telemetry must be a JSON object".to_string());
+                }
+                SignerResponse::Signature(
+                    self.signer.sign(&TelemetryInfo::get_data_for_sig(&content)),
+                )
+            }
+            SignerRequest::ComputeVrfWithProof { data } => {
+                let (value, proof) = self.signer.compute_vrf_with_proof(&data);
+                SignerResponse::VrfWithProof { value, proof }
+            }
+        }
+    }
+
+    /// Serves connections one at a time, so requests from several nodes using the same key
+    /// can't race each other.
+    pub fn serve(&mut self, listener: &UnixListener) -> io::Result<()> {
+        for stream in listener.incoming() {
+            let mut stream = stream?;
+            info!(target: "remote_signer", "Node connected");
+            match self.serve_connection(&mut stream) {
+                Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                    info!(target: "remote_signer", "Node disconnected")
+                }
+                Err(err) => warn!(target: "remote_signer", "Connection failed: {}", err),
+                Ok(()) => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn serve_connection(&mut self, stream: &mut UnixStream) -> io::Result<()> {
+        loop {
+            let request = read_message(stream)?;
+            let response = self.handle(request);
+            write_message(stream, &response)?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use near_crypto::KeyType;
+    use near_primitives::block::Block;
+    use near_primitives::block_header::ApprovalInner;
+    use near_primitives::hash::CryptoHash;
+    use near_primitives::remote_signer::RemoteValidatorSigner;
+    use near_primitives::sharding::ShardChunkHeaderV3;
+    use near_primitives::validator_signer::{
+        InMemoryValidatorSigner, ValidatorSigner, ValidatorSignerError,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_remote_signer() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let socket_path = tmp_dir.path().join("signer.sock");
+        let listener = UnixListener::bind(&socket_path).unwrap();
+        let slashing_protection =
+            SlashingProtection::open(&tmp_dir.path().join("state.json")).unwrap();
+        let key = InMemorySigner::from_seed("test0".parse().unwrap(), KeyType::ED25519, "test0");
+        let mut remote_signer = RemoteSigner::new(key, slashing_protection);
+        std::thread::spawn(move || remote_signer.serve(&listener));
+
+        let local_signer: Arc<dyn ValidatorSigner> = Arc::new(InMemoryValidatorSigner::from_seed(
+            "test0".parse().unwrap(),
+            KeyType::ED25519,
+            "test0",
+        ));
+        let signer: Arc<dyn ValidatorSigner> =
+            Arc::new(RemoteValidatorSigner::connect(&socket_path, Duration::from_secs(5)).unwrap());
+        assert_eq!(signer.validator_id(), local_signer.validator_id());
+        assert_eq!(signer.public_key(), local_signer.public_key());
+
+        // Signatures are deterministic, so they must match the ones of a local signer. Signing
+        // the same approval twice is fine.
+        let inner = ApprovalInner::Endorsement(CryptoHash::default());
+        let approval = local_signer.sign_approval(&inner, 2).unwrap();
+        assert_eq!(signer.sign_approval(&inner, 2).unwrap(), approval);
+        assert_eq!(signer.sign_approval(&inner, 2).unwrap(), approval);
+        assert_eq!(
+            signer.compute_vrf_with_proof(&[1, 2, 3]).unwrap().0,
+            local_signer.compute_vrf_with_proof(&[1, 2, 3]).unwrap().0
+        );
+
+        // A different approval for the same height, or one for a lower height, is refused.
+        let skip = ApprovalInner::Skip(0);
+        assert!(matches!(signer.sign_approval(&skip, 2), Err(ValidatorSignerError::Refused(_))));
+        assert!(matches!(signer.sign_approval(&inner, 1), Err(ValidatorSignerError::Refused(_))));
+
+        let genesis = Block::genesis(
+            near_primitives::version::PROTOCOL_VERSION,
+            vec![],
+            near_primitives::time::Clock::utc(),
+            0,
+            100,
+            1_000_000_000,
+            CryptoHash::default(),
+        );
+        let block = Block::empty_with_height(&genesis, 2, &*signer);
+        assert!(block.header().verify_block_producer(&signer.public_key()));
+        // Signing a block below the highest signed height is refused.
+        let old_header = Block::empty(&genesis, &*local_signer).header().clone();
+        assert!(matches!(
+            signer.sign_block_header_parts(
+                *old_header.prev_hash(),
+                &old_header.inner_lite_bytes(),
+                &old_header.inner_rest_bytes(),
+            ),
+            Err(ValidatorSignerError::Refused(_))
+        ));
+
+        // Chunk headers are signed by the hash the signer computes, and a different chunk of the
+        // same shard at the same height is refused.
+        let chunk_header = |gas_used, signer: &dyn ValidatorSigner| {
+            ShardChunkHeaderV3::new(
+                *genesis.hash(),
+                CryptoHash::default(),
+                CryptoHash::default(),
+                CryptoHash::default(),
+                0,
+                3,
+                0,
+                gas_used,
+                0,
+                0,
+                CryptoHash::default(),
+                CryptoHash::default(),
+                vec![],
+                signer,
+            )
+        };
+        assert_eq!(
+            chunk_header(0, &*signer).unwrap().signature,
+            chunk_header(0, &*local_signer).unwrap().signature
+        );
+        assert!(matches!(chunk_header(1, &*signer), Err(ValidatorSignerError::Refused(_))));
+
+        // Telemetry is signed with the same prefix as locally, and only if it's a JSON object.
+        let key = InMemorySigner::from_seed("test0".parse().unwrap(), KeyType::ED25519, "test0");
+        let mut remote_signer = RemoteSigner::new(
+            key.clone(),
+            SlashingProtection::open(&tmp_dir.path().join("state2.json")).unwrap(),
+        );
+        let content = serde_json::json!({ "chain": { "node_id": "node" } }).to_string();
+        assert_eq!(
+            remote_signer.handle(SignerRequest::SignTelemetry { content: content.clone() }),
+            SignerResponse::Signature(key.sign(&TelemetryInfo::get_data_for_sig(&content)))
+        );
+        assert!(matches!(
+            remote_signer
+                .handle(SignerRequest::SignTelemetry { content: "\"not an object\"".to_string() }),
+            SignerResponse::Refused(_)
+        ));
+    }
+}
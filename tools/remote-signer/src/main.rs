@@ -0,0 +1,62 @@
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::UnixListener;
+use std::path::Path;
+
+use clap::{App, Arg};
+use tracing::info;
+use tracing::metadata::LevelFilter;
+use tracing_subscriber::EnvFilter;
+
+use near_crypto::{InMemorySigner, KeyFile};
+use remote_signer::{RemoteSigner, SlashingProtection};
+
+fn main() {
+    tracing_subscriber::fmt::Subscriber::builder()
+        .with_env_filter(EnvFilter::default().add_directive(LevelFilter::INFO.into()))
+        .with_writer(std::io::stderr)
+        .init();
+
+    let matches = App::new("Remote signer")
+        .about("Holds a validator key and signs blocks, approvals and chunks for a node")
+        .arg(
+            Arg::with_name("key-file")
+                .long("key-file")
+                .required(true)
+                .help("Validator key file, in the same format as validator_key.json")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("socket")
+                .long("socket")
+                .required(true)
+                .help("Path of the unix socket to listen on")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("state-file")
+                .long("state-file")
+                .required(true)
+                .help("File keeping the highest signed heights, to prevent double signing")
+                .takes_value(true),
+        )
+        .get_matches();
+
+    let key_file = matches.value_of("key-file").map(Path::new).unwrap();
+    let socket_path = matches.value_of("socket").map(Path::new).unwrap();
+    let state_file = matches.value_of("state-file").map(Path::new).unwrap();
+
+    let signer: InMemorySigner = KeyFile::from_file(key_file).into();
+    let slashing_protection =
+        SlashingProtection::open(state_file).expect("Failed to open slashing protection state");
+
+    // Left over from a previous run.
+    if socket_path.exists() {
+        std::fs::remove_file(socket_path).expect("Failed to remove old socket");
+    }
+    let listener = UnixListener::bind(socket_path).expect("Failed to bind socket");
+    std::fs::set_permissions(socket_path, std::fs::Permissions::from_mode(0o600))
+        .expect("Failed to restrict socket permissions");
+
+    info!(target: "remote_signer", "Signing for {} with {} on {}", signer.account_id, signer.public_key, socket_path.display());
+    RemoteSigner::new(signer, slashing_protection).serve(&listener).expect("Signer failed");
+}
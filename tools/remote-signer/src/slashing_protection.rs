@@ -0,0 +1,186 @@
+//! Keeps track of what the signer has signed so that it never signs two different blocks,
+//! approvals or chunks of a shard at the same height, nor anything below the highest height signed
+//! so far.
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use near_primitives::hash::CryptoHash;
+use near_primitives::types::{BlockHeight, ShardId};
+
+#[derive(thiserror::Error, Debug)]
+pub enum SlashingProtectionError {
+    #[error("{kind} at height {height} is below the highest signed height {highest}")]
+    BelowHighestHeight { kind: &'static str, height: BlockHeight, highest: BlockHeight },
+    #[error("{kind} at height {height} differs from the one already signed at this height")]
+    ConflictingData { kind: &'static str, height: BlockHeight },
+    #[error("Failed to persist slashing protection state: {0}")]
+    Io(#[from] io::Error),
+}
+
+/// Height and hash of the data signed last.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SignedData {
+    pub height: BlockHeight,
+    pub hash: CryptoHash,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct SlashingProtectionState {
+    /// Highest block header signed, identified by the block hash.
+    pub last_block: Option<SignedData>,
+    /// Highest approval signed, by its target height and the hash of the signed data.
+    pub last_approval: Option<SignedData>,
+    /// Highest chunk header signed for each shard, identified by the chunk hash.
+    #[serde(default)]
+    pub last_chunks: BTreeMap<ShardId, SignedData>,
+}
+
+/// Slashing protection state backed by a JSON file. The state is written to disk before a
+/// signature is handed out, so a restart of the signer can't lead to a double sign.
+pub struct SlashingProtection {
+    path: PathBuf,
+    state: SlashingProtectionState,
+}
+
+impl SlashingProtection {
+    /// Opens the state file, starting from an empty state if it doesn't exist yet.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let state = if path.exists() {
+            serde_json::from_str(&std::fs::read_to_string(path)?)?
+        } else {
+            SlashingProtectionState::default()
+        };
+        Ok(Self { path: path.to_path_buf(), state })
+    }
+
+    pub fn state(&self) -> &SlashingProtectionState {
+        &self.state
+    }
+
+    pub fn check_and_record_block(
+        &mut self,
+        height: BlockHeight,
+        hash: CryptoHash,
+    ) -> Result<(), SlashingProtectionError> {
+        if Self::check(&self.state.last_block, "Block", height, &hash)? {
+            self.state.last_block = Some(SignedData { height, hash });
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    pub fn check_and_record_approval(
+        &mut self,
+        target_height: BlockHeight,
+        hash: CryptoHash,
+    ) -> Result<(), SlashingProtectionError> {
+        if Self::check(&self.state.last_approval, "Approval", target_height, &hash)? {
+            self.state.last_approval = Some(SignedData { height: target_height, hash });
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    pub fn check_and_record_chunk(
+        &mut self,
+        shard_id: ShardId,
+        height_created: BlockHeight,
+        hash: CryptoHash,
+    ) -> Result<(), SlashingProtectionError> {
+        let last = self.state.last_chunks.get(&shard_id).cloned();
+        if Self::check(&last, "Chunk", height_created, &hash)? {
+            self.state.last_chunks.insert(shard_id, SignedData { height: height_created, hash });
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    /// Returns whether the data is new and has to be recorded. Signing the same data again is
+    /// allowed, e.g. when the node retries a request.
+    fn check(
+        last: &Option<SignedData>,
+        kind: &'static str,
+        height: BlockHeight,
+        hash: &CryptoHash,
+    ) -> Result<bool, SlashingProtectionError> {
+        match last {
+            None => Ok(true),
+            Some(last) if height < last.height => {
+                Err(SlashingProtectionError::BelowHighestHeight {
+                    kind,
+                    height,
+                    highest: last.height,
+                })
+            }
+            Some(last) if height == last.height => {
+                if &last.hash == hash {
+                    Ok(false)
+                } else {
+                    Err(SlashingProtectionError::ConflictingData { kind, height })
+                }
+            }
+            Some(_) => Ok(true),
+        }
+    }
+
+    /// Atomically replaces the state file.
+    fn save(&self) -> io::Result<()> {
+        let tmp_path = self.path.with_extension("tmp");
+        let mut file = File::create(&tmp_path)?;
+        file.write_all(serde_json::to_string_pretty(&self.state)?.as_bytes())?;
+        file.sync_all()?;
+        std::fs::rename(&tmp_path, &self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_primitives::hash::hash;
+
+    use super::*;
+
+    #[test]
+    fn test_slashing_protection() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let path = tmp_dir.path().join("state.json");
+        let mut protection = SlashingProtection::open(&path).unwrap();
+        protection.check_and_record_block(10, hash(&[1])).unwrap();
+        // Same block again is fine, a different one at the same or lower height is not.
+        protection.check_and_record_block(10, hash(&[1])).unwrap();
+        assert!(matches!(
+            protection.check_and_record_block(10, hash(&[2])),
+            Err(SlashingProtectionError::ConflictingData { height: 10, .. })
+        ));
+        assert!(matches!(
+            protection.check_and_record_block(9, hash(&[3])),
+            Err(SlashingProtectionError::BelowHighestHeight { height: 9, highest: 10, .. })
+        ));
+        protection.check_and_record_block(11, hash(&[4])).unwrap();
+
+        // Approvals are tracked separately from blocks.
+        protection.check_and_record_approval(5, hash(&[5])).unwrap();
+        assert!(protection.check_and_record_approval(5, hash(&[6])).is_err());
+
+        // Chunks are tracked per shard.
+        protection.check_and_record_chunk(0, 7, hash(&[7])).unwrap();
+        protection.check_and_record_chunk(1, 7, hash(&[8])).unwrap();
+        assert!(matches!(
+            protection.check_and_record_chunk(0, 7, hash(&[9])),
+            Err(SlashingProtectionError::ConflictingData { height: 7, .. })
+        ));
+
+        // The state survives a restart.
+        let mut protection = SlashingProtection::open(&path).unwrap();
+        assert_eq!(
+            protection.state().last_block,
+            Some(SignedData { height: 11, hash: hash(&[4]) })
+        );
+        assert!(protection.check_and_record_block(11, hash(&[1])).is_err());
+        assert!(protection.check_and_record_approval(4, hash(&[5])).is_err());
+        assert!(protection.check_and_record_chunk(1, 6, hash(&[8])).is_err());
+    }
+}
@@ -3,6 +3,8 @@ use crate::epoch_info;
 use crate::rocksdb_stats::get_rocksdb_stats;
 use clap::{AppSettings, Clap};
 use near_chain_configs::GenesisValidationMode;
+use near_crypto::PublicKey;
+use near_epoch_manager::ProposalOverride;
 use near_logger_utils::init_integration_logger;
 use near_primitives::account::id::AccountId;
 use near_primitives::hash::CryptoHash;
@@ -104,6 +106,25 @@ pub enum StateViewerSubCommand {
     Chunks(ChunksCmd),
     #[clap(name = "partial_chunks")]
     PartialChunks(PartialChunksCmd),
+    /// Extract a block range for a set of accounts into a runtime-tester `Scenario` JSON file.
+    #[cfg(feature = "scenario_dump")]
+    #[clap(name = "dump_scenario")]
+    DumpScenario(DumpScenarioCmd),
+    /// Run validator selection as if the epoch ended at the given block, with hypothetical
+    /// proposals, and print the resulting seats, kickouts and rewards.
+    #[clap(name = "simulate_epoch")]
+    SimulateEpoch(SimulateEpochCmd),
+    /// Write the signing history of validators to a JSON file, to move a validator to another
+    /// machine.
+    #[clap(name = "export_signing_history")]
+    ExportSigningHistory(ExportSigningHistoryCmd),
+    /// Merge the signing history from a JSON file written by `export_signing_history`.
+    #[clap(name = "import_signing_history")]
+    ImportSigningHistory(ImportSigningHistoryCmd),
+    /// Export the network components stored in the DB, i.e. edges between peers which became
+    /// unreachable and were pruned from the routing table, as a Graphviz or JSON graph.
+    #[clap(name = "network_graph")]
+    NetworkGraph(NetworkGraphCmd),
 }
 
 impl StateViewerSubCommand {
@@ -127,6 +148,12 @@ impl StateViewerSubCommand {
             StateViewerSubCommand::Receipts(cmd) => cmd.run(near_config, store),
             StateViewerSubCommand::Chunks(cmd) => cmd.run(near_config, store),
             StateViewerSubCommand::PartialChunks(cmd) => cmd.run(near_config, store),
+            #[cfg(feature = "scenario_dump")]
+            StateViewerSubCommand::DumpScenario(cmd) => cmd.run(home_dir, near_config, store),
+            StateViewerSubCommand::SimulateEpoch(cmd) => cmd.run(near_config, store),
+            StateViewerSubCommand::ExportSigningHistory(cmd) => cmd.run(store),
+            StateViewerSubCommand::ImportSigningHistory(cmd) => cmd.run(store),
+            StateViewerSubCommand::NetworkGraph(cmd) => cmd.run(store),
         }
     }
 }
@@ -358,3 +385,105 @@ impl PartialChunksCmd {
         get_partial_chunk(partial_chunk_hash, near_config, store)
     }
 }
+
+#[cfg(feature = "scenario_dump")]
+#[derive(Clap)]
+pub struct DumpScenarioCmd {
+    #[clap(long)]
+    start_index: BlockHeight,
+    #[clap(long)]
+    end_index: BlockHeight,
+    /// Comma-separated list of accounts whose state and transactions are recorded.
+    #[clap(long)]
+    account_ids: String,
+    #[clap(long, parse(from_os_str))]
+    output: PathBuf,
+}
+
+#[cfg(feature = "scenario_dump")]
+impl DumpScenarioCmd {
+    pub fn run(self, home_dir: &Path, near_config: NearConfig, store: Store) {
+        let account_ids = self
+            .account_ids
+            .split(',')
+            .map(|s| AccountId::from_str(s.trim()).unwrap())
+            .collect::<Vec<_>>();
+        dump_scenario(
+            self.start_index,
+            self.end_index,
+            &account_ids,
+            &self.output,
+            home_dir,
+            near_config,
+            store,
+        );
+    }
+}
+
+#[derive(Clap)]
+pub struct SimulateEpochCmd {
+    /// Height of the block the epoch is assumed to end with. Defaults to the head.
+    #[clap(long)]
+    height: Option<BlockHeight>,
+    /// Hypothetical proposal `account_id:stake[:public_key]`, with stake in yoctoNEAR. Zero stake
+    /// unstakes. Can be given multiple times.
+    #[clap(long)]
+    stake: Vec<String>,
+}
+
+impl SimulateEpochCmd {
+    pub fn run(self, near_config: NearConfig, store: Store) {
+        let overrides = self
+            .stake
+            .iter()
+            .map(|proposal| {
+                let mut parts = proposal.splitn(3, ':');
+                let account_id = AccountId::from_str(parts.next().unwrap()).unwrap();
+                let stake = parts.next().expect("stake is missing").parse().unwrap();
+                let public_key = parts.next().map(|key| PublicKey::from_str(key).unwrap());
+                ProposalOverride { account_id, stake, public_key }
+            })
+            .collect();
+        simulate_epoch(self.height, overrides, near_config, store);
+    }
+}
+
+#[derive(Clap)]
+pub struct ExportSigningHistoryCmd {
+    #[clap(long, parse(from_os_str))]
+    output: PathBuf,
+}
+
+impl ExportSigningHistoryCmd {
+    pub fn run(self, store: Store) {
+        export_signing_history(&self.output, store);
+    }
+}
+
+#[derive(Clap)]
+pub struct ImportSigningHistoryCmd {
+    #[clap(long, parse(from_os_str))]
+    input: PathBuf,
+}
+
+impl ImportSigningHistoryCmd {
+    pub fn run(self, store: Store) {
+        import_signing_history(&self.input, store);
+    }
+}
+
+#[derive(Clap)]
+pub struct NetworkGraphCmd {
+    /// Output format: `dot` for Graphviz or `json`.
+    #[clap(long, default_value = "dot", possible_values = &["dot", "json"])]
+    format: String,
+    /// Write the graph into this file instead of stdout.
+    #[clap(long, parse(from_os_str))]
+    output: Option<PathBuf>,
+}
+
+impl NetworkGraphCmd {
+    pub fn run(self, store: Store) {
+        network_graph(&self.format, self.output.as_deref(), store);
+    }
+}
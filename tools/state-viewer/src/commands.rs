@@ -1,13 +1,17 @@
 use crate::apply_chain_range::apply_chain_range;
 use crate::epoch_info;
+#[cfg(feature = "scenario_dump")]
+use crate::scenario_dump::scenario_dump;
 use crate::state_dump::state_dump;
 use ansi_term::Color::Red;
 use near_chain::chain::collect_receipts_from_response;
 use near_chain::migrations::check_if_block_is_first_with_chunk_of_version;
+use near_chain::signing_history;
 use near_chain::types::{ApplyTransactionResult, BlockHeaderInfo};
 use near_chain::{ChainStore, ChainStoreAccess, ChainStoreUpdate, RuntimeAdapter};
-use near_epoch_manager::EpochManager;
-use near_network::iter_peers_from_store;
+use near_epoch_manager::{EpochManager, ProposalOverride};
+use near_network::{components_from_store, iter_peers_from_store};
+use near_network_primitives::types::EdgeState;
 use near_primitives::account::id::AccountId;
 use near_primitives::block::BlockHeader;
 use near_primitives::hash::CryptoHash;
@@ -550,6 +554,95 @@ pub(crate) fn print_epoch_info(
     );
 }
 
+pub(crate) fn simulate_epoch(
+    height: Option<BlockHeight>,
+    overrides: Vec<ProposalOverride>,
+    near_config: NearConfig,
+    store: Store,
+) {
+    let mut chain_store = ChainStore::new(store.clone(), near_config.genesis.config.genesis_height);
+    let mut epoch_manager =
+        EpochManager::new_from_genesis_config(store, &near_config.genesis.config)
+            .expect("Failed to start Epoch Manager");
+    let block_hash = match height {
+        Some(height) => chain_store.get_block_hash_by_height(height).unwrap(),
+        None => chain_store.head().unwrap().last_block_hash,
+    };
+    epoch_info::simulate_epoch(block_hash, overrides, &mut chain_store, &mut epoch_manager);
+}
+
+pub(crate) fn export_signing_history(output: &Path, store: Store) {
+    let records = signing_history::export_signing_history(&store).unwrap();
+    let file = File::create(output).unwrap();
+    serde_json::to_writer_pretty(file, &records).unwrap();
+    println!("Exported signing history of {} validators into {}", records.len(), output.display());
+}
+
+pub(crate) fn import_signing_history(input: &Path, store: Store) {
+    let records: Vec<signing_history::SigningHistoryRecord> =
+        serde_json::from_reader(File::open(input).unwrap()).unwrap();
+    let num_records = records.len();
+    signing_history::import_signing_history(&store, records).unwrap();
+    println!("Imported signing history of {} validators from {}", num_records, input.display());
+}
+
+pub(crate) fn network_graph(format: &str, output: Option<&Path>, store: Store) {
+    let components = components_from_store(&store);
+    let graph = if format == "json" {
+        let components: Vec<_> = components
+            .iter()
+            .map(|(nonce, (edges, peers))| {
+                let edges: Vec<_> = edges
+                    .iter()
+                    .map(|edge| {
+                        let (peer0, peer1) = edge.key();
+                        serde_json::json!({
+                            "peer0": peer0,
+                            "peer1": peer1,
+                            "nonce": edge.nonce(),
+                            "removed": edge.edge_type() == EdgeState::Removed,
+                        })
+                    })
+                    .collect();
+                serde_json::json!({ "nonce": nonce, "peers": peers, "edges": edges })
+            })
+            .collect();
+        serde_json::to_string_pretty(&components).unwrap()
+    } else {
+        // Every component is drawn as a cluster, removed edges are dashed.
+        let mut graph = String::from("graph network {\n");
+        for (nonce, (edges, peers)) in components.iter() {
+            graph.push_str(&format!("  subgraph cluster_{} {{\n", nonce));
+            graph.push_str(&format!("    label=\"component {}\";\n", nonce));
+            for peer_id in peers {
+                graph.push_str(&format!("    \"{}\";\n", peer_id));
+            }
+            for edge in edges {
+                let (peer0, peer1) = edge.key();
+                let style =
+                    if edge.edge_type() == EdgeState::Removed { ", style=dashed" } else { "" };
+                graph.push_str(&format!(
+                    "    \"{}\" -- \"{}\" [label=\"{}\"{}];\n",
+                    peer0,
+                    peer1,
+                    edge.nonce(),
+                    style
+                ));
+            }
+            graph.push_str("  }\n");
+        }
+        graph.push_str("}\n");
+        graph
+    };
+    match output {
+        Some(output) => {
+            fs::write(output, graph).unwrap();
+            println!("Exported {} network components into {}", components.len(), output.display());
+        }
+        None => println!("{}", graph),
+    }
+}
+
 pub(crate) fn get_receipt(receipt_id: CryptoHash, near_config: NearConfig, store: Store) {
     let mut chain_store = ChainStore::new(store.clone(), near_config.genesis.config.genesis_height);
     let receipt = chain_store.get_receipt(&receipt_id);
@@ -572,6 +665,42 @@ pub(crate) fn get_partial_chunk(
     println!("Partial chunk: {:#?}", partial_chunk);
 }
 
+#[cfg(feature = "scenario_dump")]
+pub(crate) fn dump_scenario(
+    start_height: BlockHeight,
+    end_height: BlockHeight,
+    account_ids: &[AccountId],
+    output: &Path,
+    home_dir: &Path,
+    near_config: NearConfig,
+    store: Store,
+) {
+    let mut chain_store = ChainStore::new(store.clone(), near_config.genesis.config.genesis_height);
+    let runtime = NightshadeRuntime::with_config(
+        home_dir,
+        store,
+        &near_config,
+        None,
+        near_config.client_config.max_gas_burnt_view,
+    );
+    let scenario = scenario_dump(
+        &runtime,
+        &mut chain_store,
+        &near_config.genesis.config,
+        start_height,
+        end_height,
+        account_ids,
+    );
+    fs::write(output, serde_json::to_vec_pretty(&scenario).unwrap()).unwrap();
+    println!(
+        "Dump scenario for heights {}..={} of accounts {:?} into file {}",
+        start_height,
+        end_height,
+        account_ids,
+        output.display()
+    );
+}
+
 #[allow(unused)]
 enum LoadTrieMode {
     /// Load latest state
@@ -2,7 +2,7 @@ use borsh::BorshDeserialize;
 use clap::{ArgEnum, Clap};
 use core::ops::Range;
 use near_chain::{ChainStore, ChainStoreAccess, RuntimeAdapter};
-use near_epoch_manager::EpochManager;
+use near_epoch_manager::{EpochManager, ProposalOverride};
 use near_primitives::account::id::AccountId;
 use near_primitives::epoch_manager::epoch_info::EpochInfo;
 use near_primitives::epoch_manager::AGGREGATOR_KEY;
@@ -250,3 +250,76 @@ fn display_validator_info(
         );
     }
 }
+
+// Simulates the end of the epoch of the given block with the given hypothetical proposals and
+// prints the outcome of the validator selection.
+pub(crate) fn simulate_epoch(
+    block_hash: CryptoHash,
+    overrides: Vec<ProposalOverride>,
+    chain_store: &mut ChainStore,
+    epoch_manager: &mut EpochManager,
+) {
+    let rng_seed = chain_store.get_block_header(&block_hash).unwrap().random_value().0;
+    let simulation = match epoch_manager.simulate_epoch_end(&block_hash, overrides, rng_seed) {
+        Ok(simulation) => simulation,
+        Err(err) => {
+            println!("Validator selection failed: {}", err);
+            return;
+        }
+    };
+    let epoch_info = &simulation.epoch_info;
+    println!(
+        "Epoch {:?} ending with block #{} {}",
+        simulation.epoch_id, simulation.last_block_height, block_hash
+    );
+    println!("Proposals:");
+    for proposal in &simulation.proposals {
+        println!("  {} {}", proposal.account_id(), proposal.stake());
+    }
+    println!(
+        "Epoch #{} (protocol version {}):",
+        epoch_info.epoch_height(),
+        epoch_info.protocol_version()
+    );
+    println!("  Seat price: {}", epoch_info.seat_price());
+    println!("  Validators:");
+    for (validator_id, validator) in epoch_info.validators_iter().enumerate() {
+        let validator_id = validator_id as u64;
+        let block_producer_seats = epoch_info
+            .block_producers_settlement()
+            .iter()
+            .filter(|&&id| id == validator_id)
+            .count();
+        let chunk_producer_shards: Vec<ShardId> = epoch_info
+            .chunk_producers_settlement()
+            .iter()
+            .enumerate()
+            .filter(|(_, chunk_producers)| chunk_producers.contains(&validator_id))
+            .map(|(shard_id, _)| shard_id as ShardId)
+            .collect();
+        println!(
+            "    {} stake: {}, block producer seats: {}, chunk producer for shards: {:?}",
+            validator.account_id(),
+            validator.stake(),
+            block_producer_seats,
+            chunk_producer_shards
+        );
+    }
+    println!("  Fishermen:");
+    for fisherman in epoch_info.fishermen_iter() {
+        println!("    {} stake: {}", fisherman.account_id(), fisherman.stake());
+    }
+    println!("  Kickouts:");
+    let mut kickouts: Vec<_> = epoch_info.validator_kickout().iter().collect();
+    kickouts.sort_by_key(|(account_id, _)| account_id.clone());
+    for (account_id, reason) in kickouts {
+        println!("    {}: {:?}", account_id, reason);
+    }
+    println!("Rewards for epoch {:?}:", simulation.epoch_id);
+    let mut rewards: Vec<_> = epoch_info.validator_reward().iter().collect();
+    rewards.sort_by_key(|(account_id, _)| account_id.clone());
+    for (account_id, reward) in rewards {
+        println!("  {}: {}", account_id, reward);
+    }
+    println!("  Minted: {}", epoch_info.minted_amount());
+}
@@ -5,6 +5,8 @@ pub mod cli;
 mod commands;
 mod epoch_info;
 mod rocksdb_stats;
+#[cfg(feature = "scenario_dump")]
+mod scenario_dump;
 mod state_dump;
 
 pub use cli::StateViewerSubCommand;
@@ -0,0 +1,337 @@
+use near_chain::{ChainStore, ChainStoreAccess, RuntimeAdapter};
+use near_chain_configs::GenesisConfig;
+use near_crypto::{InMemorySigner, KeyType};
+use near_primitives::account::AccessKey;
+use near_primitives::hash::CryptoHash;
+use near_primitives::runtime::config_store::RuntimeConfigStore;
+use near_primitives::state_record::StateRecord;
+use near_primitives::transaction::SignedTransaction;
+use near_primitives::trie_key::{trie_key_parsers, TrieKey};
+use near_primitives::types::{AccountId, BlockHeight, Nonce, StateRoot};
+use near_store::{Trie, TrieIterator};
+use nearcore::NightshadeRuntime;
+use runtime_tester::{BlockConfig, NetworkConfig, Scenario, TransactionConfig};
+use std::collections::{BTreeSet, HashMap, VecDeque};
+
+/// Seed of the account which produces blocks when the scenario is replayed.
+/// It must not collide with any of the recorded accounts.
+const SCENARIO_VALIDATOR_SEED: &str = "test0";
+
+/// Extracts blocks in `start_height..=end_height` into a `runtime-tester` `Scenario`.
+///
+/// All the transactions signed by or sent to `accounts` within the range are recorded in the
+/// order they were included in the chain.  The initial state of every account these
+/// transactions touch, i.e. their signers, their receivers and the receivers of all receipts
+/// they produced, is taken from the state right before `start_height` and stored as genesis
+/// records of the scenario.  Receipts are followed through the execution outcomes in the store,
+/// so the dump must be taken on a node which tracked the shards of these accounts.
+///
+/// Access keys of the recorded accounts are kept as they are.  Since private keys are not
+/// available, signers of the recorded transactions additionally get a full access key derived
+/// from the account id, the same way `runtime-tester` derives keys for its own accounts, and
+/// transactions are re-signed with it on replay.  Nonces are renumbered per signer for the same
+/// reason.
+pub fn scenario_dump(
+    runtime: &NightshadeRuntime,
+    chain_store: &mut ChainStore,
+    genesis_config: &GenesisConfig,
+    start_height: BlockHeight,
+    end_height: BlockHeight,
+    accounts: &[AccountId],
+) -> Scenario {
+    assert!(start_height <= end_height, "start_height must not exceed end_height");
+    let accounts: BTreeSet<AccountId> = accounts.iter().cloned().collect();
+
+    let first_height = (start_height..=end_height)
+        .find(|height| chain_store.get_block_hash_by_height(*height).is_ok())
+        .unwrap_or_else(|| panic!("No blocks in range {}..={}", start_height, end_height));
+    let first_block_hash = chain_store.get_block_hash_by_height(first_height).unwrap();
+    let first_block = chain_store.get_block(&first_block_hash).unwrap().clone();
+    let epoch_id = first_block.header().epoch_id().clone();
+    let protocol_version = runtime.get_epoch_protocol_version(&epoch_id).unwrap();
+
+    let mut recorded_accounts = accounts.clone();
+    let mut signers = BTreeSet::new();
+    let mut recorded_blocks: Vec<(BlockHeight, Vec<SignedTransaction>)> = vec![];
+    for height in first_height..=end_height {
+        let block_hash = match chain_store.get_block_hash_by_height(height) {
+            Ok(block_hash) => block_hash,
+            Err(_) => continue,
+        };
+        let block = chain_store.get_block(&block_hash).unwrap().clone();
+        let mut transactions = vec![];
+        for chunk_header in block.chunks().iter() {
+            if chunk_header.height_included() != height {
+                continue;
+            }
+            let chunk = chain_store.get_chunk(&chunk_header.chunk_hash()).unwrap().clone();
+            for tx in chunk.transactions() {
+                let signer_id = &tx.transaction.signer_id;
+                let receiver_id = &tx.transaction.receiver_id;
+                if !accounts.contains(signer_id) && !accounts.contains(receiver_id) {
+                    continue;
+                }
+                recorded_accounts.insert(signer_id.clone());
+                recorded_accounts.insert(receiver_id.clone());
+                signers.insert(signer_id.clone());
+                recorded_accounts.extend(receipt_receivers(chain_store, tx.get_hash()));
+                transactions.push(tx.clone());
+            }
+        }
+        // Keep empty blocks as well, so that receipts are executed at the same relative heights.
+        recorded_blocks.push((height, transactions));
+    }
+    assert!(
+        !recorded_accounts.iter().any(|account_id| account_id.as_ref() == SCENARIO_VALIDATOR_SEED),
+        "account {} is reserved for the scenario validator",
+        SCENARIO_VALIDATOR_SEED
+    );
+
+    let mut initial_state_records = vec![];
+    let mut nonces: HashMap<AccountId, Nonce> = HashMap::new();
+    for account_id in &recorded_accounts {
+        let shard_id = runtime.account_id_to_shard_id(account_id, &epoch_id).unwrap();
+        let state_root = first_block.chunks()[shard_id as usize].prev_state_root();
+        let trie = runtime.get_trie_for_shard(shard_id, first_block.header().prev_hash()).unwrap();
+
+        let account_key = TrieKey::Account { account_id: account_id.clone() }.to_vec();
+        let account_value = match trie.get(&state_root, &account_key).unwrap() {
+            Some(value) => value,
+            None => {
+                println!(
+                    "Account {} does not exist at height {}, skipping",
+                    account_id, first_height
+                );
+                continue;
+            }
+        };
+        initial_state_records
+            .push(StateRecord::from_raw_key_value(account_key, account_value).unwrap());
+
+        // Transactions of signers are re-signed with the derived key, whose nonce continues from
+        // the one in the state if the account already has this key.
+        let signer =
+            InMemorySigner::from_seed(account_id.clone(), KeyType::ED25519, account_id.as_ref());
+        let access_keys_prefix = trie_key_parsers::get_raw_prefix_for_access_keys(account_id);
+        let mut has_signer_key = false;
+        for record in records_with_prefix(&trie, &state_root, &access_keys_prefix) {
+            if let StateRecord::AccessKey { public_key, access_key, .. } = &record {
+                if public_key == &signer.public_key {
+                    has_signer_key = true;
+                    nonces.insert(account_id.clone(), access_key.nonce);
+                }
+            }
+            initial_state_records.push(record);
+        }
+        if signers.contains(account_id) && !has_signer_key {
+            initial_state_records.push(StateRecord::AccessKey {
+                account_id: account_id.clone(),
+                public_key: signer.public_key,
+                access_key: AccessKey::full_access(),
+            });
+        }
+
+        let code_key = TrieKey::ContractCode { account_id: account_id.clone() }.to_vec();
+        if let Some(code) = trie.get(&state_root, &code_key).unwrap() {
+            initial_state_records.push(StateRecord::from_raw_key_value(code_key, code).unwrap());
+        }
+
+        let data_prefix = trie_key_parsers::get_raw_prefix_for_contract_data(account_id, &[]);
+        initial_state_records.extend(records_with_prefix(&trie, &state_root, &data_prefix));
+    }
+
+    let blocks = recorded_blocks
+        .into_iter()
+        .map(|(height, transactions)| BlockConfig {
+            height: height - first_height + 1,
+            transactions: transactions
+                .into_iter()
+                .map(|tx| {
+                    let signer_id = tx.transaction.signer_id;
+                    let nonce = nonces.entry(signer_id.clone()).or_insert(0);
+                    *nonce += 1;
+                    let signer = InMemorySigner::from_seed(
+                        signer_id.clone(),
+                        KeyType::ED25519,
+                        signer_id.as_ref(),
+                    );
+                    TransactionConfig {
+                        nonce: *nonce,
+                        signer_id,
+                        receiver_id: tx.transaction.receiver_id,
+                        signer,
+                        actions: tx.transaction.actions,
+                    }
+                })
+                .collect(),
+        })
+        .collect();
+
+    let runtime_config = RuntimeConfigStore::new(None).get_config(protocol_version).clone();
+    Scenario {
+        network_config: NetworkConfig { seeds: vec![SCENARIO_VALIDATOR_SEED.to_string()] },
+        runtime_config: runtime_tester::RuntimeConfig {
+            max_total_prepaid_gas: runtime_config.wasm_config.limit_config.max_total_prepaid_gas,
+            gas_limit: genesis_config.gas_limit,
+            epoch_length: genesis_config.epoch_length,
+        },
+        blocks,
+        use_in_memory_store: true,
+        initial_state_records,
+    }
+}
+
+/// Returns the receivers of all receipts produced, directly or transitively, by the transaction
+/// with the given hash.
+///
+/// Panics if the execution outcome of the transaction or one of its receipts is not in the
+/// store, because the scenario would then miss accounts and fail on replay.  Receipts which
+/// have not been executed yet have no outcome and are not followed further.
+fn receipt_receivers(chain_store: &mut ChainStore, tx_hash: CryptoHash) -> BTreeSet<AccountId> {
+    let mut receivers = BTreeSet::new();
+    let mut queue = VecDeque::from(vec![tx_hash]);
+    let mut is_transaction = true;
+    while let Some(id) = queue.pop_front() {
+        let outcomes = chain_store.get_outcomes_by_id(&id).unwrap();
+        if outcomes.is_empty() {
+            assert!(
+                !is_transaction,
+                "Execution outcome of transaction {} is not in the store, so the accounts its \
+                 receipts touch are unknown. Dump the scenario on a node which tracks all shards \
+                 of the recorded accounts",
+                id
+            );
+            continue;
+        }
+        is_transaction = false;
+        // On forks the same transaction or receipt may have several outcomes, one per block.
+        let receipt_ids: BTreeSet<CryptoHash> = outcomes
+            .into_iter()
+            .flat_map(|outcome| outcome.outcome_with_id.outcome.receipt_ids)
+            .collect();
+        for receipt_id in receipt_ids {
+            let receipt = chain_store.get_receipt(&receipt_id).unwrap().unwrap_or_else(|| {
+                panic!(
+                    "Receipt {} produced by {} is not in the store, so the accounts it touches \
+                     are unknown",
+                    receipt_id, id
+                )
+            });
+            receivers.insert(receipt.receiver_id.clone());
+            queue.push_back(receipt_id);
+        }
+    }
+    receivers
+}
+
+/// Returns state records of all the trie entries whose key starts with `prefix`.
+fn records_with_prefix(trie: &Trie, state_root: &StateRoot, prefix: &[u8]) -> Vec<StateRecord> {
+    let mut records = vec![];
+    let mut iter = TrieIterator::new(trie, state_root).unwrap();
+    iter.seek(prefix).unwrap();
+    for item in iter {
+        let (key, value) = item.unwrap();
+        if !key.starts_with(prefix) {
+            break;
+        }
+        if let Some(record) = StateRecord::from_raw_key_value(key, value) {
+            records.push(record);
+        }
+    }
+    records
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+    use std::sync::Arc;
+
+    use near_chain::{ChainGenesis, ChainStore};
+    use near_chain_configs::Genesis;
+    use near_client::test_utils::TestEnv;
+    use near_crypto::{InMemorySigner, KeyType};
+    use near_primitives::state_record::StateRecord;
+    use near_primitives::transaction::SignedTransaction;
+    use near_store::test_utils::create_test_store;
+    use nearcore::config::GenesisExt;
+    use nearcore::NightshadeRuntime;
+
+    use crate::scenario_dump::scenario_dump;
+
+    /// Test that a recorded scenario contains the transactions of the given accounts, both
+    /// outgoing and incoming, and can be replayed by `runtime-tester`.
+    #[test]
+    fn test_dump_scenario_and_replay() {
+        let epoch_length = 10;
+        let mut genesis = Genesis::test(
+            vec!["test0".parse().unwrap(), "test1".parse().unwrap(), "test2".parse().unwrap()],
+            1,
+        );
+        genesis.config.epoch_length = epoch_length;
+        let store = create_test_store();
+        let nightshade_runtime = NightshadeRuntime::test(Path::new("."), store.clone(), &genesis);
+        let mut chain_genesis = ChainGenesis::test();
+        chain_genesis.epoch_length = epoch_length;
+        chain_genesis.gas_limit = genesis.config.gas_limit;
+        let mut env = TestEnv::builder(chain_genesis)
+            .runtime_adapters(vec![Arc::new(nightshade_runtime)])
+            .build();
+
+        let genesis_hash = *env.clients[0].chain.genesis().hash();
+        let signer = InMemorySigner::from_seed("test1".parse().unwrap(), KeyType::ED25519, "test1");
+        let tx = SignedTransaction::send_money(
+            1,
+            "test1".parse().unwrap(),
+            "test2".parse().unwrap(),
+            &signer,
+            100,
+            genesis_hash,
+        );
+        env.clients[0].process_tx(tx, false, false);
+        for i in 1..=5 {
+            env.produce_block(0, i);
+        }
+
+        let runtime = NightshadeRuntime::test(Path::new("."), store.clone(), &genesis);
+        let mut chain_store = ChainStore::new(store, genesis.config.genesis_height);
+        let scenario = scenario_dump(
+            &runtime,
+            &mut chain_store,
+            &genesis.config,
+            1,
+            5,
+            &["test1".parse().unwrap(), "test2".parse().unwrap()],
+        );
+        assert_eq!(scenario.blocks.len(), 5);
+        assert_eq!(scenario.blocks.iter().map(|block| block.transactions.len()).sum::<usize>(), 1);
+        // The genesis key of `test1` is the one derived from its id, so it is kept as it is.
+        assert_eq!(
+            scenario
+                .initial_state_records
+                .iter()
+                .filter(|record| matches!(
+                    record,
+                    StateRecord::AccessKey { account_id, .. } if account_id.as_ref() == "test1"
+                ))
+                .count(),
+            1
+        );
+        scenario.run().result.unwrap();
+
+        // Transactions sent to the dumped accounts are recorded together with their signers.
+        let scenario = scenario_dump(
+            &runtime,
+            &mut chain_store,
+            &genesis.config,
+            1,
+            5,
+            &["test2".parse().unwrap()],
+        );
+        assert_eq!(scenario.blocks.iter().map(|block| block.transactions.len()).sum::<usize>(), 1);
+        assert!(scenario.initial_state_records.iter().any(|record| matches!(
+            record,
+            StateRecord::Account { account_id, .. } if account_id.as_ref() == "test1"
+        )));
+        scenario.run().result.unwrap();
+    }
+}
@@ -0,0 +1,78 @@
+use std::time::Instant;
+
+/// Token bucket holding up to `capacity` tokens, refilled at `rate` tokens per second.
+///
+/// Taking tokens succeeds as long as the bucket isn't empty, even if more tokens are taken than
+/// the bucket holds. The bucket then goes into debt, which has to be paid off before anything else
+/// is let through. This way a single request larger than the capacity can still pass, while the
+/// long term rate is kept.
+#[derive(Clone, Debug)]
+pub struct TokenBucket {
+    capacity: f64,
+    rate: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    /// Creates a full bucket.
+    pub fn new(capacity: f64, rate: f64, now: Instant) -> Self {
+        Self { capacity, rate, tokens: capacity, last_refill: now }
+    }
+
+    /// Takes `amount` tokens if the bucket isn't empty. Returns whether they were taken.
+    pub fn try_take(&mut self, amount: f64, now: Instant) -> bool {
+        self.refill(now);
+        if self.tokens <= 0.0 {
+            return false;
+        }
+        self.tokens -= amount;
+        true
+    }
+
+    /// Number of tokens currently available, negative while the bucket is in debt.
+    pub fn tokens(&mut self, now: Instant) -> f64 {
+        self.refill(now);
+        self.tokens
+    }
+
+    fn refill(&mut self, now: Instant) {
+        if now <= self.last_refill {
+            return;
+        }
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.last_refill = now;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TokenBucket;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_token_bucket() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(2.0, 1.0, start);
+        assert!(bucket.try_take(1.0, start));
+        assert!(bucket.try_take(1.0, start));
+        assert!(!bucket.try_take(1.0, start));
+
+        // Refilled at one token per second, up to the capacity.
+        assert!(bucket.try_take(1.0, start + Duration::from_secs(1)));
+        assert!(!bucket.try_take(1.0, start + Duration::from_secs(1)));
+        assert_eq!(bucket.tokens(start + Duration::from_secs(10)), 2.0);
+    }
+
+    #[test]
+    fn test_token_bucket_debt() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(2.0, 1.0, start);
+        // Larger than the capacity, still let through.
+        assert!(bucket.try_take(5.0, start));
+        assert_eq!(bucket.tokens(start), -3.0);
+        assert!(!bucket.try_take(1.0, start + Duration::from_secs(3)));
+        assert!(bucket.try_take(1.0, start + Duration::from_secs(4)));
+    }
+}